@@ -0,0 +1,203 @@
+// Arena files: a custom arena's obstacles, movers, platforms and hazards, read
+// from TOML and passed with `--arena`. Without one, matches use the default
+// arena.
+//
+//     [[obstacle]]
+//     x = 180
+//     y = 150
+//     width = 40
+//     height = 100
+//
+//     [[mover]]
+//     kind = "door"
+//     x = 100
+//     y = 0
+//     width = 20
+//     height = 80
+//     open = [100, -70]
+//     period = 300
+//
+//     [[platform]]
+//     x = 250
+//     y = 250
+//     width = 100
+//     height = 100
+//
+//     [[ramp]]
+//     x = 230
+//     y = 290
+//     width = 30
+//     height = 20
+//
+//     [[hazard]]
+//     kind = "meteor"
+//     every = 400
+//     warning = 60
+//     damage = 3
+//     radius = 40
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::elevation::{Elevation, Rect};
+use crate::hazards::Hazard;
+use crate::movers::Mover;
+use crate::raycast::Obstacle;
+use crate::setup::default_obstacles;
+
+/// An arena file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Arena {
+    /// The arena's obstacles; without any the arena is empty.
+    #[serde(default, rename = "obstacle")]
+    pub obstacles: Vec<Obstacle>,
+    /// Sliding doors and rotating barriers (see `movers`).
+    #[serde(default, rename = "mover")]
+    pub movers: Vec<Mover>,
+    /// Raised platforms (see `elevation`).
+    #[serde(default, rename = "platform")]
+    pub platforms: Vec<Rect>,
+    /// Ramps up to the platforms.
+    #[serde(default, rename = "ramp")]
+    pub ramps: Vec<Rect>,
+    /// Hazards that go off during a match (see `hazards`).
+    #[serde(default, rename = "hazard")]
+    pub hazards: Vec<Hazard>,
+}
+
+impl Default for Arena {
+    /// The default arena: a few obstacles, standing still, on flat ground and
+    /// no hazards.
+    fn default() -> Self {
+        Arena {
+            obstacles: default_obstacles(),
+            movers: Vec::new(),
+            platforms: Vec::new(),
+            ramps: Vec::new(),
+            hazards: Vec::new(),
+        }
+    }
+}
+
+impl Arena {
+    /// Load an arena from a TOML file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The arena's platforms and ramps.
+    pub fn elevation(&self) -> Elevation {
+        Elevation {
+            platforms: self.platforms.clone(),
+            ramps: self.ramps.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hazards::Axis;
+
+    #[test]
+    fn test_arena_from_toml() {
+        let arena: Arena = toml::from_str(
+            r#"
+            [[obstacle]]
+            x = 10
+            y = 20
+            width = 30
+            height = 40
+
+            [[obstacle]]
+            x = 60
+            y = 20
+            width = 10
+            height = 40
+            health = 12
+
+            [[mover]]
+            kind = "barrier"
+            pivot = [200, 200]
+            length = 80
+            thickness = 8
+            speed = 0.5
+
+            [[platform]]
+            x = 100
+            y = 100
+            width = 50
+            height = 50
+
+            [[ramp]]
+            x = 80
+            y = 120
+            width = 25
+            height = 10
+
+            [[hazard]]
+            kind = "laser_grid"
+            every = 300
+            warning = 40
+            damage = 2
+            axis = "horizontal"
+            spacing = 100
+            width = 12
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            arena.obstacles,
+            vec![
+                Obstacle::new(10.0, 20.0, 30.0, 40.0),
+                Obstacle::new(60.0, 20.0, 10.0, 40.0).with_health(12),
+            ]
+        );
+        assert_eq!(
+            arena.movers,
+            vec![Mover::Barrier {
+                pivot: (200.0, 200.0),
+                length: 80.0,
+                thickness: 8.0,
+                speed: 0.5,
+                angle: 0.0,
+            }]
+        );
+        let elevation = arena.elevation();
+        assert_eq!(
+            elevation.platforms,
+            vec![Rect::new(100.0, 100.0, 50.0, 50.0)]
+        );
+        assert_eq!(elevation.ramps, vec![Rect::new(80.0, 120.0, 25.0, 10.0)]);
+        assert_eq!(
+            arena.hazards,
+            vec![Hazard::LaserGrid {
+                every: 300,
+                warning: 40,
+                damage: 2,
+                axis: Axis::Horizontal,
+                spacing: 100.0,
+                width: 12.0,
+                drift: 0.0,
+            }]
+        );
+        assert!(toml::from_str::<Arena>("[[hazard]]\nkind = \"volcano\"").is_err());
+    }
+
+    #[test]
+    fn test_shipped_arenas_keep_the_default_obstacles() {
+        let arena = Arena::load(Path::new("arenas/hazards.toml")).unwrap();
+        assert_eq!(arena.obstacles, Arena::default().obstacles);
+        assert_eq!(arena.hazards.len(), 2);
+        let arena = Arena::load(Path::new("arenas/gates.toml")).unwrap();
+        assert_eq!(arena.obstacles, Arena::default().obstacles);
+        assert_eq!(arena.movers.len(), 2);
+        let arena = Arena::load(Path::new("arenas/heights.toml")).unwrap();
+        assert_eq!(arena.obstacles, Arena::default().obstacles);
+        assert!(!arena.elevation().is_flat());
+    }
+}
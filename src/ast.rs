@@ -1,17 +1,362 @@
 // AST type definitions for the robot-battle DSL.
 // This module defines the core structures for representing parsed robot scripts.
 
+use crate::commands;
+use crate::config::GameConfig;
+use crate::loadout::Loadout;
+use crate::math::MathMode;
+use crate::registers::{Reg, Registers};
+use crate::rng::Rng;
+use crate::series::{Memory, Tendencies};
+
+/// Health a robot starts a match with.
+pub const STARTING_HEALTH: i32 = 10;
+/// Energy a robot starts a match with.
+pub const STARTING_ENERGY: i32 = 100;
+/// Ammunition a robot starts a match with.
+pub const STARTING_AMMO: i32 = 50;
+/// Number of scan contacts each robot remembers.
+pub const SCAN_HISTORY_LEN: usize = 8;
+
+/// Registers the simulation refreshes at the start of every tick.
+pub const SENSOR_REGISTERS: &[&str] = &[
+    "x",
+    "y",
+    "heading",
+    "health",
+    "energy",
+    "enemy_count",
+    "nearest_wall_dist",
+    "in_zone",
+    "tick",
+    "pushed",
+    "detected",
+    "cloaked",
+    "point_defense",
+    "hooked",
+    "layer",
+    "hazard_warning",
+    "hazard_bearing",
+];
+
+/// Registers written as a side effect of instructions (`scan`, `scan_all`,
+/// `follow path`).
+pub const RESULT_REGISTERS: &[&str] = &[
+    "scan",
+    "scan_bearing",
+    "scan_debris",
+    "scan_salvage",
+    "scan_layer",
+    "scan_count",
+    "enemies_alive",
+    "nearest_dist",
+    "nearest_bearing",
+    "target_vx",
+    "target_vy",
+    "waypoint",
+];
+
+/// Registers describing a robot's opponents in earlier rounds of a series, set
+/// once at the start of each round.
+pub const SERIES_REGISTERS: &[&str] = &[
+    "opp_distance",
+    "opp_fire_rate",
+    "opp_direction",
+    "opp_rounds",
+];
+
+/// Whether scripts may only read the register; assigning to it is a compile error.
+pub fn is_read_only_register(name: &str) -> bool {
+    SENSOR_REGISTERS.contains(&name)
+        || RESULT_REGISTERS.contains(&name)
+        || SERIES_REGISTERS.contains(&name)
+}
+
+/// Wrap an angle in degrees into [0, 360).
+pub fn normalize_degrees(angle: f32) -> f32 {
+    let wrapped = angle.rem_euclid(360.0);
+    // rem_euclid can round up to exactly 360 for tiny negative angles
+    if wrapped >= 360.0 { 0.0 } else { wrapped }
+}
+
+/// Ticks a robot's latest `say` stays on screen, about two seconds at normal speed.
+pub const SPEECH_TICKS: u64 = 120;
+/// Ticks at the end of `SPEECH_TICKS` over which a speech bubble fades out.
+pub const SPEECH_FADE_TICKS: u64 = 40;
+
+/// A grappling hook a robot has out, and how long its cable is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hook {
+    pub anchor: HookAnchor,
+    pub length: f32,
+}
+
+/// What a grappling hook caught.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookAnchor {
+    /// A point on an obstacle, wreck or wall; the cable reels the robot in.
+    Point((f32, f32)),
+    /// An enemy robot, by id; the cable tows it in.
+    Robot(usize),
+}
+
+/// What a robot last said with `say`, and when.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Speech {
+    pub text: String,
+    pub tick: u64,
+}
+
+impl Speech {
+    /// How visible the speech is at `tick`: 1 at first, fading to 0 over the
+    /// last `SPEECH_FADE_TICKS` ticks it is on screen.
+    pub fn opacity(&self, tick: u64) -> f32 {
+        let left = (self.tick + SPEECH_TICKS).saturating_sub(tick);
+        (left as f32 / SPEECH_FADE_TICKS as f32).min(1.0)
+    }
+}
+
+/// Input from the player driving a human robot, set by the frontend before
+/// each tick. Axes run from -1 to 1, so analog input can drive them too.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Controls {
+    /// Forward (1) or reverse (-1), as a share of the robot's speed.
+    pub drive: f32,
+    /// Body turn: right (clockwise) is positive, as a share of the turn rate.
+    pub turn: f32,
+    /// Turret turn, like `turn`.
+    pub turret: f32,
+    /// Fire one shot on the next tick; cleared once fired.
+    pub fire: bool,
+}
+
+/// A robot seen by a scan, as remembered in the scanning robot's history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanRecord {
+    pub tick: u64,            // Tick of the scan
+    pub id: usize,            // Id of the robot that was seen
+    pub position: (f32, f32), // Where it was
+}
+
 /// State for a robot in the simulation.
+/// Headings are in degrees, normalized to [0, 360); 0 points along +x and angles
+/// grow clockwise on screen (y points down).
 #[derive(Debug, Clone)]
 pub struct Robot {
-    pub id: usize, // Unique identifier for the robot
+    pub id: usize,    // Unique identifier for the robot
+    pub name: String, // Display name, usually the script name
+    pub team: usize,  // Robots on the same team share a score; defaults to the id
     pub position: (f32, f32),
     pub heading: f32,
+    pub turret_heading: f32,  // Relative to the body heading
+    pub scanner_heading: f32, // Relative to the body heading
     pub health: i32,
+    pub max_health: i32,
+    pub energy: i32,
+    pub ammo: i32,
+    pub heat: i32,
+    pub kills: u32,
+    pub shots_fired: u32,
+    pub damage_dealt: u32,          // Health taken from robots of other teams
+    pub path: Vec<(i32, i32)>,      // Waypoints for `follow path`
+    pub waypoint: usize,            // Index of the waypoint `follow path` drives to next
+    pub fuse: Option<u32>,          // Ticks until an armed self-destruct detonates
+    pub knockback: (f32, f32),      // Slide velocity from hits and blasts, in units per tick
+    pub detected_by: Option<usize>, // Id of the last enemy whose scan covered the robot this tick
+    pub cloaked: bool,              // Hidden from enemy scans; drains energy
+    pub point_defense: bool,        // Shooting down enemy projectiles that come within reach
+    pub stunned: u32,               // Ticks left before an EMP hit lets the program run again
+    pub emp_ready_at: u64,          // Tick from which the robot may fire its next EMP charge
+    pub hook: Option<Hook>,         // Grappling hook the robot has out
+    pub speech: Option<Speech>,     // What the robot last said with `say`
+    pub loadout: Loadout,           // Stat levels from the script's `build` header
+    pub speed_factor: f32,          // Movement speed multiplier from the match's starting stats
+    pub memory: Memory,             // Values kept across the rounds of a series
+    pub rng: Rng,                   // Stream `random` draws from, from the match seed and id
+    pub tendencies: Tendencies,     // How the robot has played this round
+    pub scan_history: std::collections::VecDeque<ScanRecord>, // Latest contact first
+    pub ticks_spent: Vec<u64>, // Ticks per instruction, then idle ticks; empty unless profiling
+    pub controls: Option<Controls>, // Player input for a human robot, which runs no program
+    pub suspended: Option<(Vec<Instruction>, usize)>, // Program and ip put aside while injected commands run
     // Assembly-like execution state:
     pub instruction_queue: Vec<Instruction>, // Instructions to execute
     pub ip: usize,                           // Instruction pointer
-    pub registers: std::collections::HashMap<String, i32>, // Counter registers
+    pub registers: Registers,                // Counter registers
+}
+
+impl Robot {
+    /// Create a robot with starting stats and a compiled program.
+    pub fn new(
+        id: usize,
+        name: &str,
+        position: (f32, f32),
+        instruction_queue: Vec<Instruction>,
+    ) -> Self {
+        let mut robot = Robot {
+            id,
+            name: name.to_string(),
+            team: id,
+            position,
+            heading: 0.0,
+            turret_heading: 0.0,
+            scanner_heading: 0.0,
+            health: STARTING_HEALTH,
+            max_health: STARTING_HEALTH,
+            energy: STARTING_ENERGY,
+            ammo: STARTING_AMMO,
+            heat: 0,
+            kills: 0,
+            damage_dealt: 0,
+            shots_fired: 0,
+            path: Vec::new(),
+            waypoint: 0,
+            fuse: None,
+            knockback: (0.0, 0.0),
+            detected_by: None,
+            cloaked: false,
+            point_defense: false,
+            stunned: 0,
+            emp_ready_at: 0,
+            hook: None,
+            speech: None,
+            loadout: Loadout::default(),
+            speed_factor: 1.0,
+            memory: Memory::new(),
+            rng: Rng::stream(0, id),
+            tendencies: Tendencies::default(),
+            scan_history: std::collections::VecDeque::new(),
+            ticks_spent: Vec::new(),
+            controls: None,
+            suspended: None,
+            instruction_queue,
+            ip: 0,
+            registers: Registers::default(),
+        };
+        robot.sync_heading_register();
+        robot
+    }
+
+    /// What the robot said with `say`, if it is still on screen at `tick`.
+    pub fn speech_at(&self, tick: u64) -> Option<&str> {
+        self.speech
+            .as_ref()
+            .filter(|s| tick < s.tick + SPEECH_TICKS)
+            .map(|s| s.text.as_str())
+    }
+
+    /// Give the robot the match's starting stats for its name, adjusted by the
+    /// stat levels of `loadout`, at full health.
+    pub fn equip(&mut self, loadout: Loadout, config: &GameConfig) {
+        let start = config.start.for_bot(&self.name);
+        self.loadout = loadout;
+        self.max_health = loadout.max_health(start.health, config);
+        self.health = self.max_health;
+        self.energy = start.energy;
+        self.ammo = start.ammo;
+        self.speed_factor = start.speed;
+    }
+
+    /// Distance the robot moves per step, from its speed level and the
+    /// match's speed multiplier.
+    pub fn move_speed(&self, config: &GameConfig) -> f32 {
+        self.loadout.move_speed(config) * self.speed_factor
+    }
+
+    /// Absolute direction the turret points in, in degrees.
+    pub fn turret_direction(&self) -> f32 {
+        normalize_degrees(self.heading + self.turret_heading)
+    }
+
+    /// Absolute direction the scanner points in, in degrees.
+    pub fn scanner_direction(&self) -> f32 {
+        normalize_degrees(self.heading + self.scanner_heading)
+    }
+
+    /// Turn a section by `degrees` (positive is clockwise on screen).
+    pub fn turn(&mut self, section: Section, degrees: f32) {
+        match section {
+            Section::Body => {
+                self.heading = normalize_degrees(self.heading + degrees);
+                self.sync_heading_register();
+            }
+            Section::Turret => {
+                self.turret_heading = normalize_degrees(self.turret_heading + degrees);
+            }
+            Section::Scanner => {
+                self.scanner_heading = normalize_degrees(self.scanner_heading + degrees);
+            }
+        }
+    }
+
+    /// Set the next waypoint and expose it to scripts as the `waypoint` register.
+    pub fn set_waypoint(&mut self, index: usize) {
+        self.waypoint = index;
+        self.registers.set(Reg::WAYPOINT, index as i32);
+    }
+
+    /// Remember a scan contact, forgetting the oldest once `SCAN_HISTORY_LEN`
+    /// are stored. The number stored is exposed as the `scan_count` register and
+    /// the contact's estimated velocity as `target_vx`/`target_vy`.
+    pub fn record_scan(&mut self, record: ScanRecord) {
+        self.scan_history.push_front(record);
+        self.scan_history.truncate(SCAN_HISTORY_LEN);
+        self.registers
+            .set(Reg::SCAN_COUNT, self.scan_history.len() as i32);
+
+        // Thousandths of a unit per tick, since registers hold integers
+        let (vx, vy) = self.target_velocity().unwrap_or((0.0, 0.0));
+        self.registers
+            .set(Reg::TARGET_VX, (vx * 1000.0).round() as i32);
+        self.registers
+            .set(Reg::TARGET_VY, (vy * 1000.0).round() as i32);
+    }
+
+    /// Velocity of the latest contact in units per tick, estimated from the two
+    /// most recent scans. `None` unless both saw the same robot.
+    pub fn target_velocity(&self) -> Option<(f32, f32)> {
+        let latest = self.scan_history.front()?;
+        let previous = self.scan_history.get(1)?;
+        if latest.id != previous.id || latest.tick <= previous.tick {
+            return None;
+        }
+        let dt = (latest.tick - previous.tick) as f32;
+        Some((
+            (latest.position.0 - previous.position.0) / dt,
+            (latest.position.1 - previous.position.1) / dt,
+        ))
+    }
+
+    /// Expose the body heading to scripts as the `heading` register, in whole degrees.
+    fn sync_heading_register(&mut self) {
+        let degrees = (self.heading.round() as i32).rem_euclid(360);
+        self.registers.set(Reg::HEADING, degrees);
+    }
+
+    /// The instruction the robot will execute next, if any.
+    pub fn current_instruction(&self) -> Option<&Instruction> {
+        self.instruction_queue.get(self.ip)
+    }
+
+    /// Run `program` before the rest of the robot's own program, which picks up
+    /// where it left off once `program` has run to its end. Injecting again
+    /// before then replaces the earlier injected program.
+    pub fn inject(&mut self, program: Vec<Instruction>) {
+        let injected = std::mem::replace(&mut self.instruction_queue, program);
+        if self.suspended.is_none() {
+            self.suspended = Some((injected, self.ip));
+        }
+        self.ip = 0;
+    }
+
+    /// Go back to the robot's own program if an injected one has run to its end.
+    pub fn resume(&mut self) {
+        if self.ip >= self.instruction_queue.len()
+            && let Some((program, ip)) = self.suspended.take()
+        {
+            self.instruction_queue = program;
+            self.ip = ip;
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -21,22 +366,365 @@ pub enum Section {
     Scanner,
 }
 
+impl std::fmt::Display for Section {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Section::Body => write!(f, "body"),
+            Section::Turret => write!(f, "turret"),
+            Section::Scanner => write!(f, "scanner"),
+        }
+    }
+}
+
+/// Predefined faces a robot can show with `emote`, as a short `say`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emote {
+    Happy,
+    Sad,
+    Angry,
+    Surprised,
+    Confused,
+    Alert,
+}
+
+impl Emote {
+    pub const ALL: [Emote; 6] = [
+        Emote::Happy,
+        Emote::Sad,
+        Emote::Angry,
+        Emote::Surprised,
+        Emote::Confused,
+        Emote::Alert,
+    ];
+
+    /// The emote called `name` in scripts.
+    pub fn from_name(name: &str) -> Option<Emote> {
+        Emote::ALL.into_iter().find(|e| e.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Emote::Happy => "happy",
+            Emote::Sad => "sad",
+            Emote::Angry => "angry",
+            Emote::Surprised => "surprised",
+            Emote::Confused => "confused",
+            Emote::Alert => "alert",
+        }
+    }
+
+    /// The text the robot says for the emote.
+    pub fn face(self) -> &'static str {
+        match self {
+            Emote::Happy => ":)",
+            Emote::Sad => ":(",
+            Emote::Angry => ">:(",
+            Emote::Surprised => ":O",
+            Emote::Confused => "?",
+            Emote::Alert => "!",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
     /// Move the robot in a direction by a certain distance.
     Move { direction: String, distance: i32 },
     /// Rotate a section (treads, turret, scanner) by an angle.
     Rotate { section: Section, angle: i32 },
+    /// Turn toward and drive to an arena coordinate.
+    GoTo { x: i32, y: i32 },
+    /// Declare the waypoints `follow path` cycles through.
+    Path { waypoints: Vec<(i32, i32)> },
+    /// Drive to the current waypoint, then advance to the next one.
+    FollowPath,
     /// Scan for enemies.
     Scan,
+    /// Sense every enemy at once, coarsely.
+    ScanAll,
+    /// Scan, then turn the scanner `step` degrees and scan again, until it has
+    /// turned through `arc` degrees (counterclockwise if positive, like `Rotate`).
+    Sweep { arc: i32, step: i32 },
+    /// Turn the turret toward the most recent scan contact.
+    AimAtScanned,
+    /// Aim ahead of the latest contact along its estimated velocity, then fire.
+    FireLead,
     /// Fire weapon.
     Fire,
+    /// Fire a shot that bounces off walls and obstacles.
+    FireRicochet,
+    /// Fire the laser.
+    FireLaser,
+    /// Fire an EMP charge.
+    FireEmp,
+    /// Arm the self-destruct.
+    SelfDestruct,
+    /// Drop a smoke cloud.
+    Smoke,
+    /// Repair the robot itself, or the nearest damaged teammate, for `ticks` ticks.
+    Repair { ally: bool, ticks: i32 },
+    /// Header choosing the robot's stat levels; only allowed as the first command.
+    Build(Loadout),
+    /// Turn on the cloak.
+    Cloak,
+    /// Turn off the cloak.
+    Uncloak,
+    /// Turn the point-defense turret on or off.
+    PointDefense { on: bool },
+    /// Throw the grappling hook.
+    Hook,
+    /// Let go of the grappling hook.
+    Unhook,
+    /// Say something, for debugging.
+    Say { text: String },
+    /// Show a predefined face, like saying it.
+    Emote(Emote),
+    /// Save a register to the robot's series memory.
+    PersistStore { name: String },
+    /// Restore a register from the robot's series memory.
+    PersistLoad { name: String },
     /// Infinite loop: executes the block repeatedly.
     Loop { block: Block },
-    // Future extensions:
-    // If { condition: Expr, block: Block, else_block: Option<Block> },
-    // Assignment { name: String, expr: Expr },
-    // Let { name: String, expr: Expr },
+    /// Run the block if the condition is not zero, else the else block, if any.
+    If {
+        condition: Expr,
+        block: Block,
+        else_block: Option<Block>,
+    },
+    /// Evaluate an expression and store it in a register.
+    Let { name: String, expr: Expr },
+    /// A command added by a plugin (see `plugin`), with one expression per
+    /// argument.
+    Plugin { name: String, args: Vec<Expr> },
+}
+
+impl Command {
+    /// The name of the command's entry in the command table
+    /// (`commands::COMMANDS`), or the name a plugin gave it.
+    pub fn name(&self) -> &str {
+        match self {
+            Command::Move { .. } => "move",
+            Command::Rotate { .. } => "rotate",
+            Command::GoTo { .. } => "goto",
+            Command::Path { .. } => "path",
+            Command::FollowPath => "follow path",
+            Command::Scan => "scan",
+            Command::ScanAll => "scan_all",
+            Command::Sweep { .. } => "sweep",
+            Command::AimAtScanned => "aim at scanned",
+            Command::FireLead => "fire lead",
+            Command::Fire => "fire",
+            Command::FireRicochet => "fire ricochet",
+            Command::FireLaser => "fire laser",
+            Command::FireEmp => "fire emp",
+            Command::SelfDestruct => "selfdestruct",
+            Command::Smoke => "smoke",
+            Command::Repair { .. } => "repair",
+            Command::Build(_) => "build",
+            Command::Cloak => "cloak",
+            Command::Uncloak => "uncloak",
+            Command::PointDefense { on: true } => "defense on",
+            Command::PointDefense { on: false } => "defense off",
+            Command::Hook => "hook",
+            Command::Unhook => "unhook",
+            Command::Say { .. } => "say",
+            Command::Emote(_) => "emote",
+            Command::PersistStore { .. } => "persist store",
+            Command::PersistLoad { .. } => "persist load",
+            Command::Loop { .. } => "loop",
+            Command::If { .. } => "if",
+            Command::Let { .. } => "let",
+            Command::Plugin { name, .. } => name,
+        }
+    }
+}
+
+/// An integer expression over registers and constants.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i32),
+    /// Value of a register; registers that were never set read as 0.
+    Register(String),
+    /// Operator or builtin call; `args.len()` matches `op.arity()`.
+    Op {
+        op: MathOp,
+        args: Vec<Expr>,
+    },
+    /// A field of the scan history entry at `index` (0 is the latest contact).
+    History {
+        field: HistoryField,
+        index: Box<Expr>,
+    },
+}
+
+/// Fields of a scan history entry, read with `contact_x(i)` and friends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryField {
+    X,
+    Y,
+    Tick,
+    Id,
+}
+
+impl HistoryField {
+    /// The history accessor with this name, if any.
+    pub fn builtin(name: &str) -> Option<HistoryField> {
+        match name {
+            "contact_x" => Some(HistoryField::X),
+            "contact_y" => Some(HistoryField::Y),
+            "contact_tick" => Some(HistoryField::Tick),
+            "contact_id" => Some(HistoryField::Id),
+            _ => None,
+        }
+    }
+
+    /// Name used in scripts and instruction listings.
+    pub fn name(self) -> &'static str {
+        match self {
+            HistoryField::X => "contact_x",
+            HistoryField::Y => "contact_y",
+            HistoryField::Tick => "contact_tick",
+            HistoryField::Id => "contact_id",
+        }
+    }
+
+    /// Value of this field in a history entry.
+    pub fn read(self, record: &ScanRecord) -> i32 {
+        match self {
+            HistoryField::X => record.position.0.round() as i32,
+            HistoryField::Y => record.position.1.round() as i32,
+            HistoryField::Tick => record.tick as i32,
+            HistoryField::Id => record.id as i32,
+        }
+    }
+}
+
+/// Arithmetic operators, comparisons and math builtins. Angles are in degrees,
+/// and `sin`/`cos` return their result scaled by 1000 since registers hold
+/// integers. Comparisons give 1 if they hold and 0 if not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MathOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Neg,
+    Sin,
+    Cos,
+    Atan2,
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    /// `random(lo, hi)`: drawn by the VM from the robot's own stream, so
+    /// `apply` alone gives `lo`.
+    Random,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl MathOp {
+    /// The builtin function with this name, if any.
+    pub fn builtin(name: &str) -> Option<MathOp> {
+        match name {
+            "sin" => Some(MathOp::Sin),
+            "cos" => Some(MathOp::Cos),
+            "atan2" => Some(MathOp::Atan2),
+            "sqrt" => Some(MathOp::Sqrt),
+            "abs" => Some(MathOp::Abs),
+            "min" => Some(MathOp::Min),
+            "max" => Some(MathOp::Max),
+            "random" => Some(MathOp::Random),
+            _ => None,
+        }
+    }
+
+    /// Number of arguments the operation takes.
+    pub fn arity(self) -> usize {
+        match self {
+            MathOp::Neg | MathOp::Sin | MathOp::Cos | MathOp::Sqrt | MathOp::Abs => 1,
+            _ => 2,
+        }
+    }
+
+    /// Name used in instruction listings.
+    pub fn name(self) -> &'static str {
+        match self {
+            MathOp::Add => "add",
+            MathOp::Sub => "sub",
+            MathOp::Mul => "mul",
+            MathOp::Div => "div",
+            MathOp::Mod => "mod",
+            MathOp::Neg => "neg",
+            MathOp::Sin => "sin",
+            MathOp::Cos => "cos",
+            MathOp::Atan2 => "atan2",
+            MathOp::Sqrt => "sqrt",
+            MathOp::Abs => "abs",
+            MathOp::Min => "min",
+            MathOp::Max => "max",
+            MathOp::Random => "random",
+            MathOp::Lt => "lt",
+            MathOp::Le => "le",
+            MathOp::Gt => "gt",
+            MathOp::Ge => "ge",
+            MathOp::Eq => "eq",
+            MathOp::Ne => "ne",
+        }
+    }
+
+    /// Evaluate the operation, with trigonometry from `math`. Integer overflow
+    /// wraps and division by zero gives 0, so a script can never crash the VM.
+    pub fn apply(self, args: &[i32], math: MathMode) -> i32 {
+        let a = args.first().copied().unwrap_or(0);
+        let b = args.get(1).copied().unwrap_or(0);
+        match self {
+            MathOp::Add => a.wrapping_add(b),
+            MathOp::Sub => a.wrapping_sub(b),
+            MathOp::Mul => a.wrapping_mul(b),
+            MathOp::Div => a.checked_div(b).unwrap_or(0),
+            MathOp::Mod => a.checked_rem(b).unwrap_or(0),
+            MathOp::Neg => a.wrapping_neg(),
+            MathOp::Sin => (math.sin_cos((a as f32).to_radians()).0 * 1000.0).round() as i32,
+            MathOp::Cos => (math.sin_cos((a as f32).to_radians()).1 * 1000.0).round() as i32,
+            MathOp::Atan2 => {
+                let degrees = math.atan2(a as f32, b as f32).to_degrees();
+                (normalize_degrees(degrees).round() as i32).rem_euclid(360)
+            }
+            MathOp::Sqrt => (a.max(0) as f64).sqrt() as i32,
+            MathOp::Abs => a.wrapping_abs(),
+            MathOp::Min => a.min(b),
+            MathOp::Max => a.max(b),
+            MathOp::Random => a.min(b),
+            MathOp::Lt => i32::from(a < b),
+            MathOp::Le => i32::from(a <= b),
+            MathOp::Gt => i32::from(a > b),
+            MathOp::Ge => i32::from(a >= b),
+            MathOp::Eq => i32::from(a == b),
+            MathOp::Ne => i32::from(a != b),
+        }
+    }
+}
+
+/// Instruction operand: a constant or a register.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Const(i32),
+    Reg(Reg),
+}
+
+impl std::fmt::Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operand::Const(n) => write!(f, "{}", n),
+            Operand::Reg(reg) => write!(f, "{}", reg),
+        }
+    }
 }
 
 /// Low-level assembly-like instructions for robot execution.
@@ -46,19 +734,176 @@ pub enum Instruction {
     TurnLeft,
     /// Turn right by 1 unit.
     TurnRight,
+    /// Turn the turret left by 1 unit.
+    TurnTurretLeft,
+    /// Turn the turret right by 1 unit.
+    TurnTurretRight,
+    /// Turn the scanner left by 1 unit.
+    TurnScannerLeft,
+    /// Turn the scanner right by 1 unit.
+    TurnScannerRight,
     /// Move forward by 1 unit.
     MoveForward,
-    /// Fire weapon.
+    /// Turn toward the target, then drive to it; repeats every tick until the
+    /// robot has arrived or is blocked.
+    GoTo { x: i32, y: i32 },
+    /// Turn a section toward an absolute bearing in degrees, one turn step per
+    /// tick. A register target that has never been set is skipped.
+    TurnTo { section: Section, target: Operand },
+    /// Replace the robot's path and restart it at the first waypoint.
+    SetPath { waypoints: Vec<(i32, i32)> },
+    /// Drive to the current waypoint like `GoTo`; on arrival, advance the
+    /// waypoint index (wrapping around) and continue.
+    FollowPath,
+    /// Turn the turret toward where the latest contact will be when a projectile
+    /// reaches it, one turn step per tick. Skipped if nothing has been scanned.
+    AimLead,
+    /// Fire weapon along the turret direction.
     Fire,
+    /// Fire a ricochet shot along the turret direction; it bounces off walls and
+    /// obstacles, losing damage with every bounce.
+    FireRicochet,
+    /// Fire the laser along the turret direction: it hits the first robot in
+    /// its path at once, unless an obstacle is in the way. Costs energy.
+    FireLaser,
+    /// Fire an EMP charge along the turret direction. An enemy it hits stops
+    /// running its program for a while and has a register scrambled. Costs
+    /// energy, and the robot must wait out a cooldown before the next one.
+    FireEmp,
+    /// Arm the self-destruct fuse; the robot keeps running its program until it
+    /// detonates.
+    SelfDestruct,
+    /// Drop a smoke cloud at the robot's position; scanners cannot see through
+    /// it. Costs energy.
+    Smoke,
+    /// Convert energy into health while standing still.
+    Repair,
+    /// Spend energy to repair the nearest damaged teammate in range while
+    /// standing still.
+    RepairAlly,
+    /// Turn on the cloak, hiding the robot from enemy scans while it drains
+    /// energy every tick. Skipped without energy.
+    Cloak,
+    /// Turn off the cloak.
+    Uncloak,
+    /// Turn the point-defense turret on or off. While on, it shoots down the
+    /// nearest enemy projectile within reach each tick, for energy.
+    PointDefense { on: bool },
+    /// Throw the grappling hook along the turret direction. It catches the
+    /// first enemy, obstacle, wreck or wall in reach, and its cable then reels
+    /// the robot to the point or tows the enemy to the robot.
+    Hook,
+    /// Let go of the grappling hook.
+    Unhook,
+    /// Show `text` above the robot and log it as an event.
+    Say { text: String },
+    /// Copy a register into the robot's memory. A new key is dropped once the
+    /// memory holds `max_memory_entries` values.
+    PersistStore { reg: Reg },
+    /// Copy a value from the robot's memory into a register; the register keeps
+    /// its value if nothing was stored under its name.
+    PersistLoad { reg: Reg },
+    /// Scan along the scanner direction; stores the distance to the nearest
+    /// visible robot in the `scan` register (0 if none).
+    Scan,
+    /// Sense enemies in every direction, ignoring the scanner cone and
+    /// obstacles; stores their number in `enemies_alive` and the rounded
+    /// distance and bearing of the nearest in `nearest_dist` and `nearest_bearing`.
+    ScanAll,
     /// Load counter register with value.
-    LoadCounter { reg: String, value: i32 },
+    LoadCounter { reg: Reg, value: i32 },
     /// Decrement register.
-    Dec { reg: String },
+    Dec { reg: Reg },
     /// Jump to label if register is not zero.
-    Jnz { reg: String, label: String },
+    Jnz { reg: Reg, label: String },
     /// Label definition.
     Label(String),
-    // Future: Add more instructions as needed.
+    /// Copy an operand into a register.
+    Set { dst: Reg, src: Operand },
+    /// Apply a math operation to the operands and store the result in a register.
+    Math {
+        op: MathOp,
+        dst: Reg,
+        args: Vec<Operand>,
+    },
+    /// Read a field of the scan history entry at `index` into a register; 0 when
+    /// there is no such entry.
+    ReadHistory {
+        field: HistoryField,
+        dst: Reg,
+        index: Operand,
+    },
+    /// Run a plugin's command with the operands' values, every tick until the
+    /// plugin says it is done.
+    Plugin { name: String, args: Vec<Operand> },
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::TurnLeft => write!(f, "turn_left"),
+            Instruction::TurnRight => write!(f, "turn_right"),
+            Instruction::TurnTurretLeft => write!(f, "turret_left"),
+            Instruction::TurnTurretRight => write!(f, "turret_right"),
+            Instruction::TurnScannerLeft => write!(f, "scanner_left"),
+            Instruction::TurnScannerRight => write!(f, "scanner_right"),
+            Instruction::MoveForward => write!(f, "move_forward"),
+            Instruction::GoTo { x, y } => write!(f, "goto {} {}", x, y),
+            Instruction::TurnTo { section, target } => {
+                write!(f, "turn_to {} {}", section, target)
+            }
+            Instruction::SetPath { waypoints } => {
+                write!(f, "path")?;
+                for (x, y) in waypoints {
+                    write!(f, " {},{}", x, y)?;
+                }
+                Ok(())
+            }
+            Instruction::FollowPath => write!(f, "follow_path"),
+            Instruction::AimLead => write!(f, "aim_lead"),
+            Instruction::Fire => write!(f, "fire"),
+            Instruction::FireRicochet => write!(f, "fire_ricochet"),
+            Instruction::FireLaser => write!(f, "fire_laser"),
+            Instruction::FireEmp => write!(f, "fire_emp"),
+            Instruction::SelfDestruct => write!(f, "selfdestruct"),
+            Instruction::Smoke => write!(f, "smoke"),
+            Instruction::Repair => write!(f, "repair"),
+            Instruction::RepairAlly => write!(f, "repair_ally"),
+            Instruction::Cloak => write!(f, "cloak"),
+            Instruction::Uncloak => write!(f, "uncloak"),
+            Instruction::PointDefense { on: true } => write!(f, "defense_on"),
+            Instruction::PointDefense { on: false } => write!(f, "defense_off"),
+            Instruction::Hook => write!(f, "hook"),
+            Instruction::Unhook => write!(f, "unhook"),
+            Instruction::Say { text } => write!(f, "say \"{}\"", text),
+            Instruction::PersistStore { reg } => write!(f, "persist_store {}", reg),
+            Instruction::PersistLoad { reg } => write!(f, "persist_load {}", reg),
+            Instruction::Scan => write!(f, "scan"),
+            Instruction::ScanAll => write!(f, "scan_all"),
+            Instruction::LoadCounter { reg, value } => write!(f, "load {} {}", reg, value),
+            Instruction::Dec { reg } => write!(f, "dec {}", reg),
+            Instruction::Jnz { reg, label } => write!(f, "jnz {} {}", reg, label),
+            Instruction::Label(label) => write!(f, "{}:", label),
+            Instruction::Set { dst, src } => write!(f, "set {} {}", dst, src),
+            Instruction::Math { op, dst, args } => {
+                write!(f, "{} {}", op.name(), dst)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                Ok(())
+            }
+            Instruction::ReadHistory { field, dst, index } => {
+                write!(f, "{} {} {}", field.name(), dst, index)
+            }
+            Instruction::Plugin { name, args } => {
+                write!(f, "{}", name)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 /// A block is a sequence of commands.
@@ -67,82 +912,154 @@ pub type Block = Vec<Command>;
 /// Translate a high-level Command AST into a sequence of low-level Instructions.
 /// For repeated actions (e.g., turn left 90), generates a loop using labels and jumps.
 pub fn translate_commands_to_instructions(commands: &[Command]) -> Vec<Instruction> {
+    translate_with_origins(commands).0
+}
+
+/// Translate commands like `translate_commands_to_instructions`, also returning
+/// which command each instruction came from. Commands are numbered in source
+/// order, a loop before the commands in its block, as `parse_script_with_spans`
+/// lists their spans.
+pub fn translate_with_origins(commands: &[Command]) -> (Vec<Instruction>, Vec<usize>) {
     let mut instructions = Vec::new();
-    let mut label_count = 0;
+    let mut origins = Vec::new();
+    translate_block(commands, &mut instructions, &mut origins, &mut 0, &mut 0);
+    (instructions, origins)
+}
 
+/// Translate `commands` into `instructions`. Labels and counter registers are
+/// numbered from `label_count`, which is shared with nested blocks so every label
+/// in the program is unique. `origins` gets the number of the command each
+/// instruction came from; commands are numbered from `command_count`. Each
+/// command is compiled by its entry in the command table.
+fn translate_block(
+    commands: &[Command],
+    instructions: &mut Vec<Instruction>,
+    origins: &mut Vec<usize>,
+    label_count: &mut usize,
+    command_count: &mut usize,
+) {
     for cmd in commands {
-        match cmd {
-            Command::Move {
-                direction,
-                distance,
-            } => {
-                // Only support "forward" for now; can be extended
-                if direction == "forward" {
-                    // Use a loop for repeated moves
-                    let reg = format!("mv{}", label_count);
-                    let label = format!("move_loop{}", label_count);
-                    instructions.push(Instruction::LoadCounter {
-                        reg: reg.clone(),
-                        value: *distance,
-                    });
-                    instructions.push(Instruction::Label(label.clone()));
-                    instructions.push(Instruction::MoveForward);
-                    instructions.push(Instruction::Dec { reg: reg.clone() });
-                    instructions.push(Instruction::Jnz {
-                        reg: reg.clone(),
-                        label: label.clone(),
-                    });
-                    label_count += 1;
-                }
-                // Extend for other directions if needed
-            }
-            Command::Rotate { section, angle } => {
-                // Only support "left" and "right" for now; section can be ignored
-                let reg = format!("rot{}", label_count);
-                let label = format!("turn_loop{}", label_count);
-                let turns = angle.abs();
-                let counter_clockwise = *angle >= 0;
-                let turn_instr = match (section, counter_clockwise) {
-                    (Section::Body, true) => Instruction::TurnLeft,
-                    (Section::Body, false) => Instruction::TurnRight,
-                    _ => panic!("Unsupported section for rotation"),
-                };
-
-                instructions.push(Instruction::LoadCounter {
-                    reg: reg.clone(),
-                    value: turns,
-                });
-                instructions.push(Instruction::Label(label.clone()));
-                instructions.push(turn_instr);
-                instructions.push(Instruction::Dec { reg: reg.clone() });
-                instructions.push(Instruction::Jnz {
-                    reg: reg.clone(),
-                    label: label.clone(),
-                });
-                label_count += 1;
-            }
-            Command::Scan => {
-                // No atomic scan instruction yet; could add if needed
-                // For now, ignore or extend as needed
-            }
-            Command::Fire => {
-                instructions.push(Instruction::Fire);
-            }
-            Command::Loop { block } => {
-                // Infinite loop: label at start, jump to start at end
-                let label = format!("loop{}", label_count);
-                instructions.push(Instruction::Label(label.clone()));
-                let inner = translate_commands_to_instructions(block);
-                instructions.extend(inner);
-                instructions.push(Instruction::Jnz {
-                    reg: "always".to_string(),
-                    label: label.clone(),
-                });
-                label_count += 1;
-            }
+        let origin = *command_count;
+        *command_count += 1;
+        let mut out = Emitter {
+            instructions,
+            origins,
+            label_count,
+            command_count,
+            origin,
+        };
+        commands::compile(cmd, &mut out);
+        origins.resize(instructions.len(), origin);
+    }
+}
+
+/// Where a command's instructions go, as the compile functions of the command
+/// table see it.
+pub(crate) struct Emitter<'a> {
+    instructions: &'a mut Vec<Instruction>,
+    origins: &'a mut Vec<usize>,
+    label_count: &'a mut usize,
+    command_count: &'a mut usize,
+    /// Number of the command being compiled.
+    origin: usize,
+}
+
+impl Emitter<'_> {
+    pub(crate) fn push(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+    }
+
+    /// A number for the labels and counter registers of one command, unique
+    /// in the program.
+    pub(crate) fn label(&mut self) -> usize {
+        *self.label_count += 1;
+        *self.label_count - 1
+    }
+
+    /// Run `step` `count` times, counting down in register `reg` and jumping
+    /// back to `label`.
+    pub(crate) fn repeat(&mut self, reg: &str, label: String, count: i32, step: Instruction) {
+        let reg = Reg::named(reg);
+        self.push(Instruction::LoadCounter { reg, value: count });
+        self.push(Instruction::Label(label.clone()));
+        self.push(step);
+        self.push(Instruction::Dec { reg });
+        self.push(Instruction::Jnz { reg, label });
+    }
+
+    /// Compile the commands of a nested block. The instructions emitted so far
+    /// belong to the command holding it.
+    pub(crate) fn block(&mut self, commands: &[Command]) {
+        self.origins.resize(self.instructions.len(), self.origin);
+        translate_block(
+            commands,
+            self.instructions,
+            self.origins,
+            self.label_count,
+            self.command_count,
+        );
+    }
+
+    /// Evaluate `expr` into register `dst`.
+    pub(crate) fn expr(&mut self, expr: &Expr, dst: Reg) {
+        compile_expr_into(expr, dst, self.instructions, &mut 0);
+    }
+
+    /// Operands for `exprs`, evaluating into temporary registers those that
+    /// are not a number or a register.
+    pub(crate) fn operands(&mut self, exprs: &[Expr]) -> Vec<Operand> {
+        let mut temps = 0;
+        exprs
+            .iter()
+            .map(|expr| compile_operand(expr, self.instructions, &mut temps))
+            .collect()
+    }
+}
+
+/// Emit instructions that evaluate `expr` into register `dst`.
+/// Intermediate results go to temporary registers `_t0`, `_t1`, ...; they are
+/// only live within one statement, so numbering restarts for each.
+fn compile_expr_into(
+    expr: &Expr,
+    dst: Reg,
+    instructions: &mut Vec<Instruction>,
+    temps: &mut usize,
+) {
+    match expr {
+        Expr::Op { op, args } => {
+            let args = args
+                .iter()
+                .map(|arg| compile_operand(arg, instructions, temps))
+                .collect();
+            instructions.push(Instruction::Math { op: *op, dst, args });
+        }
+        Expr::History { field, index } => {
+            let index = compile_operand(index, instructions, temps);
+            instructions.push(Instruction::ReadHistory {
+                field: *field,
+                dst,
+                index,
+            });
+        }
+        _ => {
+            let src = compile_operand(expr, instructions, temps);
+            instructions.push(Instruction::Set { dst, src });
+        }
+    }
+}
+
+/// Compile `expr` to an operand, evaluating it into a temporary register if needed.
+fn compile_operand(expr: &Expr, instructions: &mut Vec<Instruction>, temps: &mut usize) -> Operand {
+    match expr {
+        Expr::Number(n) => Operand::Const(*n),
+        Expr::Register(reg) => Operand::Reg(Reg::named(reg)),
+        Expr::Op { .. } | Expr::History { .. } => {
+            let temp = Reg::named(&format!("_t{}", *temps));
+            *temps += 1;
+            compile_expr_into(expr, temp, instructions, temps);
+            Operand::Reg(temp)
         }
     }
-    instructions
 }
 
 #[cfg(test)]
@@ -179,6 +1096,131 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_instruction_display() {
+        let instr = Instruction::Jnz {
+            reg: Reg::named("mv0"),
+            label: "move_loop0".to_string(),
+        };
+        assert_eq!(instr.to_string(), "jnz mv0 move_loop0");
+        assert_eq!(
+            Instruction::Label("loop0".to_string()).to_string(),
+            "loop0:"
+        );
+    }
+
+    #[test]
+    fn test_translate_turret_and_scan() {
+        let instructions = translate_commands_to_instructions(&[
+            Command::Rotate {
+                section: Section::Turret,
+                angle: -3,
+            },
+            Command::Scan,
+        ]);
+        assert!(instructions.contains(&Instruction::TurnTurretRight));
+        assert_eq!(instructions.last(), Some(&Instruction::Scan));
+    }
+
+    #[test]
+    fn test_normalize_degrees() {
+        assert_eq!(normalize_degrees(370.0), 10.0);
+        assert_eq!(normalize_degrees(-90.0), 270.0);
+        assert_eq!(normalize_degrees(360.0), 0.0);
+        assert!((0.0..360.0).contains(&normalize_degrees(-1e-6)));
+    }
+
+    #[test]
+    fn test_turn_wraps_and_updates_heading_register() {
+        let mut robot = Robot::new(1, "test", (0.0, 0.0), vec![]);
+        assert_eq!(robot.registers.get("heading"), Some(0));
+        robot.turn(Section::Body, -1.0);
+        assert_eq!(robot.heading, 359.0);
+        assert_eq!(robot.registers.get("heading"), Some(359));
+        robot.turn(Section::Turret, 5.0);
+        assert_eq!(robot.turret_direction(), 4.0);
+        assert_eq!(robot.registers.get("heading"), Some(359));
+    }
+
+    #[test]
+    fn test_math_builtins() {
+        assert_eq!(MathOp::Sin.apply(&[90], MathMode::Native), 1000);
+        assert_eq!(MathOp::Cos.apply(&[60], MathMode::Native), 500);
+        assert_eq!(MathOp::Atan2.apply(&[10, 10], MathMode::Native), 45);
+        assert_eq!(MathOp::Atan2.apply(&[-10, 0], MathMode::Native), 270);
+        assert_eq!(MathOp::Sqrt.apply(&[17], MathMode::Native), 4);
+        assert_eq!(MathOp::Sqrt.apply(&[-4], MathMode::Native), 0);
+        assert_eq!(MathOp::Abs.apply(&[-3], MathMode::Native), 3);
+        assert_eq!(MathOp::Min.apply(&[3, -2], MathMode::Native), -2);
+        assert_eq!(MathOp::Max.apply(&[3, -2], MathMode::Native), 3);
+        assert_eq!(MathOp::Div.apply(&[7, 0], MathMode::Native), 0);
+        for op in [MathOp::Sin, MathOp::Cos, MathOp::Atan2] {
+            for a in (-360..=360).step_by(15) {
+                let args = [a, 40];
+                assert_eq!(
+                    op.apply(&args, MathMode::Portable),
+                    op.apply(&args, MathMode::Native),
+                    "{} {}",
+                    op.name(),
+                    a
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_translate_let_uses_temporaries() {
+        // let d = sqrt(dx * dx + 4)
+        let square = Expr::Op {
+            op: MathOp::Mul,
+            args: vec![Expr::Register("dx".into()), Expr::Register("dx".into())],
+        };
+        let sum = Expr::Op {
+            op: MathOp::Add,
+            args: vec![square, Expr::Number(4)],
+        };
+        let expr = Expr::Op {
+            op: MathOp::Sqrt,
+            args: vec![sum],
+        };
+        let instructions = translate_commands_to_instructions(&[Command::Let {
+            name: "d".to_string(),
+            expr,
+        }]);
+        let listing: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            listing,
+            vec!["mul _t1 dx dx", "add _t0 _t1 4", "sqrt d _t0"]
+        );
+    }
+
+    #[test]
+    fn test_record_scan_keeps_latest_contacts() {
+        let mut robot = Robot::new(1, "test", (0.0, 0.0), vec![]);
+        for tick in 0..SCAN_HISTORY_LEN as u64 + 3 {
+            robot.record_scan(ScanRecord {
+                tick,
+                id: 2,
+                position: (tick as f32, 0.0),
+            });
+        }
+        assert_eq!(robot.scan_history.len(), SCAN_HISTORY_LEN);
+        assert_eq!(robot.scan_history[0].tick, SCAN_HISTORY_LEN as u64 + 2);
+        assert_eq!(robot.registers["scan_count"], SCAN_HISTORY_LEN as i32);
+        // One unit along x per tick
+        assert_eq!(robot.target_velocity(), Some((1.0, 0.0)));
+        assert_eq!(robot.registers["target_vx"], 1000);
+
+        // A different robot seen last: no estimate
+        robot.record_scan(ScanRecord {
+            tick: 20,
+            id: 3,
+            position: (0.0, 0.0),
+        });
+        assert_eq!(robot.target_velocity(), None);
+        assert_eq!(robot.registers["target_vx"], 0);
+    }
+
     #[test]
     fn test_loop_command() {
         let block = vec![Command::Scan, Command::Fire];
@@ -0,0 +1,872 @@
+// Key bindings for the human robots, loaded from TOML files with `--controls`
+// and, for a second player on the same keyboard, `--controls2`, and for the
+// window itself (the pause menu, stepping, speed, view and console) with
+// `--viewer-keys`.
+// Every action takes a list of key names, any of which triggers it. A human
+// robot's bindings also name the gamepad axes and buttons that drive it, read
+// with gilrs since macroquad 0.4 has no gamepad input; the keys and the
+// gamepad work together.
+
+use std::fs;
+use std::path::Path;
+
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use macroquad::input::{KeyCode, is_key_pressed};
+use serde::{Deserialize, Serialize};
+
+use crate::ast::Controls;
+
+/// Keys by the names used in a bindings file, besides the letters and digits.
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("space", KeyCode::Space),
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("left_shift", KeyCode::LeftShift),
+    ("right_shift", KeyCode::RightShift),
+    ("left_control", KeyCode::LeftControl),
+    ("right_control", KeyCode::RightControl),
+    ("left_alt", KeyCode::LeftAlt),
+    ("right_alt", KeyCode::RightAlt),
+    ("comma", KeyCode::Comma),
+    ("period", KeyCode::Period),
+    ("slash", KeyCode::Slash),
+    ("semicolon", KeyCode::Semicolon),
+    ("escape", KeyCode::Escape),
+    ("grave", KeyCode::GraveAccent),
+    ("minus", KeyCode::Minus),
+    ("equal", KeyCode::Equal),
+    ("apostrophe", KeyCode::Apostrophe),
+    ("backslash", KeyCode::Backslash),
+    ("left_bracket", KeyCode::LeftBracket),
+    ("right_bracket", KeyCode::RightBracket),
+    ("insert", KeyCode::Insert),
+    ("delete", KeyCode::Delete),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("page_up", KeyCode::PageUp),
+    ("page_down", KeyCode::PageDown),
+    ("f1", KeyCode::F1),
+    ("f2", KeyCode::F2),
+    ("f3", KeyCode::F3),
+    ("f4", KeyCode::F4),
+    ("f5", KeyCode::F5),
+    ("f6", KeyCode::F6),
+    ("f7", KeyCode::F7),
+    ("f8", KeyCode::F8),
+    ("f9", KeyCode::F9),
+    ("f10", KeyCode::F10),
+    ("f11", KeyCode::F11),
+    ("f12", KeyCode::F12),
+];
+
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+];
+
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// Gamepad axes by the names used in a bindings file. Sticks read positive
+/// when pushed up or right.
+const AXES: &[(&str, Axis)] = &[
+    ("left_stick_x", Axis::LeftStickX),
+    ("left_stick_y", Axis::LeftStickY),
+    ("right_stick_x", Axis::RightStickX),
+    ("right_stick_y", Axis::RightStickY),
+    ("left_z", Axis::LeftZ),
+    ("right_z", Axis::RightZ),
+    ("dpad_x", Axis::DPadX),
+    ("dpad_y", Axis::DPadY),
+];
+
+/// Gamepad buttons by the names used in a bindings file. The face buttons
+/// are named by where they sit, as their labels differ between makers.
+const BUTTONS: &[(&str, Button)] = &[
+    ("south", Button::South),
+    ("east", Button::East),
+    ("north", Button::North),
+    ("west", Button::West),
+    ("left_bumper", Button::LeftTrigger),
+    ("right_bumper", Button::RightTrigger),
+    ("left_trigger", Button::LeftTrigger2),
+    ("right_trigger", Button::RightTrigger2),
+    ("select", Button::Select),
+    ("start", Button::Start),
+    ("left_thumb", Button::LeftThumb),
+    ("right_thumb", Button::RightThumb),
+    ("dpad_up", Button::DPadUp),
+    ("dpad_down", Button::DPadDown),
+    ("dpad_left", Button::DPadLeft),
+    ("dpad_right", Button::DPadRight),
+];
+
+/// The gamepad axis called `name` in a bindings file.
+pub fn axis_code(name: &str) -> Option<Axis> {
+    AXES.iter().find(|(n, _)| *n == name).map(|(_, axis)| *axis)
+}
+
+/// The gamepad button called `name` in a bindings file.
+pub fn button_code(name: &str) -> Option<Button> {
+    BUTTONS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, button)| *button)
+}
+
+/// Reject gamepad axis or button names that are not in `table`.
+fn check_pad_names<'a, T>(
+    kind: &str,
+    table: &[(&str, T)],
+    mut names: impl Iterator<Item = &'a String>,
+) -> Result<(), String> {
+    match names.find(|n| !table.iter().any(|(name, _)| name == n)) {
+        Some(unknown) => Err(format!(
+            "unknown gamepad {} `{}` (expected one of {})",
+            kind,
+            unknown,
+            table
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The key called `name` in a bindings file: a letter, a digit or one of
+/// `NAMED_KEYS`, in lower case.
+pub fn key_code(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c @ 'a'..='z'), None) => Some(LETTERS[c as usize - 'a' as usize]),
+        (Some(c @ '0'..='9'), None) => Some(DIGITS[c as usize - '0' as usize]),
+        _ => NAMED_KEYS
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, code)| *code),
+    }
+}
+
+/// Reject key names that do not exist.
+fn check_names<'a>(mut keys: impl Iterator<Item = &'a String>) -> Result<(), String> {
+    match keys.find(|k| key_code(k).is_none()) {
+        Some(unknown) => Err(format!(
+            "unknown key `{}` (expected a letter, a digit or one of {})",
+            unknown,
+            NAMED_KEYS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Whether any of `keys` was pressed this frame.
+pub fn pressed(keys: &[String]) -> bool {
+    keys.iter().filter_map(|k| key_code(k)).any(is_key_pressed)
+}
+
+/// `keys` as shown in hints, e.g. `escape/q`.
+pub fn key_label(keys: &[String]) -> String {
+    if keys.is_empty() {
+        "-".to_string()
+    } else {
+        keys.join("/")
+    }
+}
+
+/// The name `key` has in a bindings file, if it can be bound.
+pub fn key_name(key: KeyCode) -> Option<String> {
+    if let Some(i) = LETTERS.iter().position(|k| *k == key) {
+        return Some(char::from(b'a' + i as u8).to_string());
+    }
+    if let Some(i) = DIGITS.iter().position(|k| *k == key) {
+        return Some(i.to_string());
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|(_, code)| *code == key)
+        .map(|(name, _)| name.to_string())
+}
+
+/// The actions of a bindings file, in the order `Bindings::keys` numbers them.
+pub const ACTIONS: [&str; 7] = [
+    "forward",
+    "back",
+    "turn_left",
+    "turn_right",
+    "turret_left",
+    "turret_right",
+    "fire",
+];
+
+/// Which keys, and which axes and buttons of a gamepad, drive a human robot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bindings {
+    pub forward: Vec<String>,
+    pub back: Vec<String>,
+    pub turn_left: Vec<String>,
+    pub turn_right: Vec<String>,
+    pub turret_left: Vec<String>,
+    pub turret_right: Vec<String>,
+    /// Each press fires one shot.
+    pub fire: Vec<String>,
+    /// Which connected gamepad drives the robot, counting from 0 in the order
+    /// they were connected; none if left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gamepad: Option<usize>,
+    /// Axes that drive forward when pushed up and back when pulled down. Of
+    /// several axes, the one pushed furthest counts.
+    pub drive_axes: Vec<String>,
+    /// Axes that turn the robot right when pushed right.
+    pub turn_axes: Vec<String>,
+    /// Axes that turn the turret right when pushed right.
+    pub turret_axes: Vec<String>,
+    /// Gamepad buttons, each press of which fires one shot.
+    pub fire_buttons: Vec<String>,
+    /// Share of an axis' travel around its center that is ignored, so a
+    /// stick at rest does not creep; the rest of the travel is stretched to
+    /// the full range.
+    pub deadzone: f32,
+}
+
+/// Default `Bindings::deadzone`.
+const DEADZONE: f32 = 0.2;
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        Bindings {
+            forward: keys(&["w", "up"]),
+            back: keys(&["s", "down"]),
+            turn_left: keys(&["a", "left"]),
+            turn_right: keys(&["d", "right"]),
+            turret_left: keys(&["q"]),
+            turret_right: keys(&["e"]),
+            fire: keys(&["space"]),
+            gamepad: Some(0),
+            drive_axes: keys(&["left_stick_y"]),
+            turn_axes: keys(&["left_stick_x"]),
+            turret_axes: keys(&["right_stick_x"]),
+            fire_buttons: keys(&["right_trigger", "south"]),
+            deadzone: DEADZONE,
+        }
+    }
+}
+
+impl Bindings {
+    /// The second player's default keys, on the right of the keyboard: the
+    /// arrows drive, comma and period turn the turret and Enter fires. The
+    /// second gamepad drives too.
+    pub fn second_player() -> Self {
+        let keys = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        Bindings {
+            forward: keys(&["up"]),
+            back: keys(&["down"]),
+            turn_left: keys(&["left"]),
+            turn_right: keys(&["right"]),
+            turret_left: keys(&["comma"]),
+            turret_right: keys(&["period"]),
+            fire: keys(&["enter", "right_control"]),
+            gamepad: Some(1),
+            ..Bindings::default()
+        }
+    }
+
+    /// These bindings without the keys and the gamepad `other` uses, so two
+    /// players sharing the machine never drive each other's robot.
+    pub fn without(&self, other: &Bindings) -> Self {
+        let taken: Vec<&String> = other.actions().into_iter().flatten().collect();
+        let keep = |keys: &Vec<String>| {
+            keys.iter()
+                .filter(|k| !taken.contains(k))
+                .cloned()
+                .collect()
+        };
+        Bindings {
+            forward: keep(&self.forward),
+            back: keep(&self.back),
+            turn_left: keep(&self.turn_left),
+            turn_right: keep(&self.turn_right),
+            turret_left: keep(&self.turret_left),
+            turret_right: keep(&self.turret_right),
+            fire: keep(&self.fire),
+            gamepad: self.gamepad.filter(|pad| other.gamepad != Some(*pad)),
+            ..self.clone()
+        }
+    }
+
+    /// Load bindings from a TOML file; actions it leaves out keep their
+    /// default keys.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Bindings::load_over(path, Bindings::default())
+    }
+
+    /// Load bindings from a TOML file; actions it leaves out keep their keys
+    /// in `defaults`.
+    pub fn load_over(path: &Path, defaults: Bindings) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Bindings::parse_over(&text, defaults).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parse bindings, rejecting key, axis and button names that do not exist.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        Bindings::parse_over(text, Bindings::default())
+    }
+
+    /// Parse bindings over `defaults`, rejecting key, axis and button names
+    /// that do not exist and a deadzone outside [0, 1).
+    pub fn parse_over(text: &str, defaults: Bindings) -> Result<Self, String> {
+        let given: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut table = toml::Table::try_from(defaults).map_err(|e| e.to_string())?;
+        table.extend(given);
+        let bindings: Bindings = table
+            .try_into()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+        check_names(bindings.actions().into_iter().flatten())?;
+        let axes = [
+            &bindings.drive_axes,
+            &bindings.turn_axes,
+            &bindings.turret_axes,
+        ];
+        check_pad_names("axis", AXES, axes.into_iter().flatten())?;
+        check_pad_names("button", BUTTONS, bindings.fire_buttons.iter())?;
+        if !(0.0..1.0).contains(&bindings.deadzone) {
+            return Err(format!(
+                "deadzone must be at least 0 and below 1, not {}",
+                bindings.deadzone
+            ));
+        }
+        Ok(bindings)
+    }
+
+    /// Write a bindings file, creating its directory if needed.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The keys of action number `action` in `ACTIONS`.
+    pub fn keys(&self, action: usize) -> &[String] {
+        self.actions()[action]
+    }
+
+    /// Bind action number `action` in `ACTIONS` to `keys` alone.
+    pub fn bind(&mut self, action: usize, keys: Vec<String>) {
+        let slot = match action {
+            0 => &mut self.forward,
+            1 => &mut self.back,
+            2 => &mut self.turn_left,
+            3 => &mut self.turn_right,
+            4 => &mut self.turret_left,
+            5 => &mut self.turret_right,
+            _ => &mut self.fire,
+        };
+        *slot = keys;
+    }
+
+    fn actions(&self) -> [&Vec<String>; 7] {
+        [
+            &self.forward,
+            &self.back,
+            &self.turn_left,
+            &self.turn_right,
+            &self.turret_left,
+            &self.turret_right,
+            &self.fire,
+        ]
+    }
+
+    /// The controls given by the keys `held` down this frame and the keys
+    /// `pressed` this frame.
+    pub fn controls(
+        &self,
+        held: impl Fn(KeyCode) -> bool,
+        pressed: impl Fn(KeyCode) -> bool,
+    ) -> Controls {
+        let any = |keys: &[String], down: &dyn Fn(KeyCode) -> bool| {
+            keys.iter().filter_map(|k| key_code(k)).any(down)
+        };
+        let axis = |negative: &[String], positive: &[String]| {
+            f32::from(u8::from(any(positive, &held))) - f32::from(u8::from(any(negative, &held)))
+        };
+        Controls {
+            drive: axis(&self.back, &self.forward),
+            turn: axis(&self.turn_left, &self.turn_right),
+            turret: axis(&self.turret_left, &self.turret_right),
+            fire: any(&self.fire, &pressed),
+        }
+    }
+
+    /// The controls given by a gamepad whose axes read `axis` this frame and
+    /// whose buttons were `pressed` this frame.
+    pub fn gamepad_controls(
+        &self,
+        axis: impl Fn(Axis) -> f32,
+        pressed: impl Fn(Button) -> bool,
+    ) -> Controls {
+        let read = |names: &[String]| {
+            let furthest = names
+                .iter()
+                .filter_map(|n| axis_code(n))
+                .map(&axis)
+                .fold(0.0_f32, |a, b| if b.abs() > a.abs() { b } else { a });
+            let travel = furthest.abs().min(1.0);
+            if travel <= self.deadzone {
+                0.0
+            } else {
+                furthest.signum() * (travel - self.deadzone) / (1.0 - self.deadzone)
+            }
+        };
+        Controls {
+            drive: read(&self.drive_axes),
+            turn: read(&self.turn_axes),
+            turret: read(&self.turret_axes),
+            fire: self
+                .fire_buttons
+                .iter()
+                .filter_map(|b| button_code(b))
+                .any(pressed),
+        }
+    }
+}
+
+/// The controls from the keys and a gamepad together: each axis follows the
+/// keys while one is held and the gamepad otherwise, and either fires.
+pub fn combine(keys: Controls, pad: Controls) -> Controls {
+    let axis = |key: f32, pad: f32| if key != 0.0 { key } else { pad };
+    Controls {
+        drive: axis(keys.drive, pad.drive),
+        turn: axis(keys.turn, pad.turn),
+        turret: axis(keys.turret, pad.turret),
+        fire: keys.fire || pad.fire,
+    }
+}
+
+/// The gamepads connected to the machine, read once a frame.
+pub struct Gamepads {
+    /// None where gamepads cannot be read, such as without udev.
+    gilrs: Option<Gilrs>,
+    /// Buttons pressed since the last `update`, with their gamepad.
+    pressed: Vec<(GamepadId, Button)>,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Gamepads {
+            gilrs: Gilrs::new().ok(),
+            pressed: Vec::new(),
+        }
+    }
+
+    /// Catch up with what the gamepads did since the last frame.
+    pub fn update(&mut self) {
+        self.pressed.clear();
+        let Some(gilrs) = &mut self.gilrs else {
+            return;
+        };
+        while let Some(event) = gilrs.next_event() {
+            if let EventType::ButtonPressed(button, _) = event.event {
+                self.pressed.push((event.id, button));
+            }
+        }
+    }
+
+    /// The controls the gamepad of `bindings` gives this frame, if it is
+    /// connected.
+    pub fn controls(&self, bindings: &Bindings) -> Option<Controls> {
+        let (id, pad) = self.gilrs.as_ref()?.gamepads().nth(bindings.gamepad?)?;
+        Some(bindings.gamepad_controls(
+            |axis| pad.value(axis),
+            |button| self.pressed.contains(&(id, button)),
+        ))
+    }
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Gamepads::new()
+    }
+}
+
+/// Keys that work the window rather than a robot. Each action takes a list
+/// of keys, any of which triggers it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ViewerKeys {
+    /// Opens and closes the pause menu, and closes the console and the
+    /// settings screen.
+    pub menu: Vec<String>,
+    /// Opens and closes the script console.
+    pub console: Vec<String>,
+    /// Steps the paused match one tick back.
+    pub step_back: Vec<String>,
+    /// Steps the paused match one tick forward.
+    pub step_forward: Vec<String>,
+    pub resume: Vec<String>,
+    pub restart: Vec<String>,
+    pub reload: Vec<String>,
+    /// Cycles the speed, in the pause menu and the tournament grid.
+    pub speed: Vec<String>,
+    pub debug: Vec<String>,
+    pub split: Vec<String>,
+    pub observer: Vec<String>,
+    pub performance: Vec<String>,
+    pub settings: Vec<String>,
+    pub quit: Vec<String>,
+    /// Pauses the tournament grid.
+    pub pause: Vec<String>,
+    /// Skips to the next batch of the tournament grid.
+    pub next: Vec<String>,
+}
+
+/// Keys the pause menu is moved through with, which cannot be rebound.
+const MENU_NAVIGATION: [&str; 3] = ["up", "down", "enter"];
+
+impl Default for ViewerKeys {
+    fn default() -> Self {
+        let keys = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        ViewerKeys {
+            menu: keys(&["escape"]),
+            console: keys(&["grave"]),
+            step_back: keys(&["left"]),
+            step_forward: keys(&["right"]),
+            resume: keys(&["space"]),
+            restart: keys(&["r"]),
+            reload: keys(&["l"]),
+            speed: keys(&["s"]),
+            debug: keys(&["d"]),
+            split: keys(&["v"]),
+            observer: keys(&["o"]),
+            performance: keys(&["f"]),
+            settings: keys(&["p"]),
+            quit: keys(&["q"]),
+            pause: keys(&["space"]),
+            next: keys(&["n"]),
+        }
+    }
+}
+
+impl ViewerKeys {
+    /// Load viewer keys from a TOML file; actions it leaves out keep their
+    /// default keys.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        ViewerKeys::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parse viewer keys, rejecting key names that do not exist and keys
+    /// given to two actions that are read at the same time.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let keys: ViewerKeys = toml::from_str(text).map_err(|e| e.to_string())?;
+        check_names(keys.all().into_iter().flat_map(|(_, keys)| keys))?;
+        for context in keys.contexts() {
+            check_conflicts(&context)?;
+        }
+        Ok(keys)
+    }
+
+    /// Reject keys that both work the window and drive the robot of one of
+    /// `players`, as both are read while the match runs.
+    pub fn check_players(&self, players: &[Bindings]) -> Result<(), String> {
+        for (n, bindings) in players.iter().enumerate() {
+            let mut context = self.playing();
+            context.extend(ACTIONS.iter().enumerate().map(|(i, action)| {
+                let label = format!("player {}'s `{}`", n + 1, action);
+                (label, names(bindings.keys(i)))
+            }));
+            check_conflicts(&context)?;
+        }
+        Ok(())
+    }
+
+    /// The action that already takes `key` while the match runs, if any.
+    pub fn used_while_playing(&self, key: &str) -> Option<String> {
+        self.playing()
+            .into_iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| action)
+    }
+
+    fn all(&self) -> [(&'static str, &Vec<String>); 16] {
+        [
+            ("menu", &self.menu),
+            ("console", &self.console),
+            ("step_back", &self.step_back),
+            ("step_forward", &self.step_forward),
+            ("resume", &self.resume),
+            ("restart", &self.restart),
+            ("reload", &self.reload),
+            ("speed", &self.speed),
+            ("debug", &self.debug),
+            ("split", &self.split),
+            ("observer", &self.observer),
+            ("performance", &self.performance),
+            ("settings", &self.settings),
+            ("quit", &self.quit),
+            ("pause", &self.pause),
+            ("next", &self.next),
+        ]
+    }
+
+    /// The actions in `actions` with their keys, labelled for error messages.
+    fn context(&self, actions: &[&str]) -> Vec<(String, Vec<&str>)> {
+        self.all()
+            .into_iter()
+            .filter(|(action, _)| actions.contains(action))
+            .map(|(action, keys)| (format!("`{}`", action), names(keys)))
+            .collect()
+    }
+
+    /// The actions read while the match runs.
+    fn playing(&self) -> Vec<(String, Vec<&str>)> {
+        self.context(&["menu", "console"])
+    }
+
+    /// The actions read at the same time as each other: while the match
+    /// runs, in the pause menu and in the tournament grid.
+    fn contexts(&self) -> [Vec<(String, Vec<&str>)>; 3] {
+        let mut menu = self.context(&[
+            "menu",
+            "step_back",
+            "step_forward",
+            "resume",
+            "restart",
+            "reload",
+            "speed",
+            "debug",
+            "split",
+            "observer",
+            "performance",
+            "settings",
+            "quit",
+        ]);
+        menu.push((
+            "moving through the menu".to_string(),
+            MENU_NAVIGATION.to_vec(),
+        ));
+        [
+            self.playing(),
+            menu,
+            self.context(&["pause", "speed", "next"]),
+        ]
+    }
+}
+
+fn names(keys: &[String]) -> Vec<&str> {
+    keys.iter().map(String::as_str).collect()
+}
+
+/// Reject a key that two of the actions in `context` take.
+fn check_conflicts(context: &[(String, Vec<&str>)]) -> Result<(), String> {
+    for (i, (first, keys)) in context.iter().enumerate() {
+        for (second, other) in &context[i + 1..] {
+            if let Some(key) = keys.iter().find(|k| other.contains(k)) {
+                return Err(format!(
+                    "`{}` is bound to both {} and {}",
+                    key, first, second
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(key_code("w"), Some(KeyCode::W));
+        assert_eq!(key_code("7"), Some(KeyCode::Key7));
+        assert_eq!(key_code("left_shift"), Some(KeyCode::LeftShift));
+        assert_eq!(key_code("W"), None);
+        assert_eq!(key_code("f13"), None);
+        assert_eq!(key_code("grave"), Some(KeyCode::GraveAccent));
+        for name in ["q", "4", "right_control"] {
+            assert_eq!(key_name(key_code(name).unwrap()).as_deref(), Some(name));
+        }
+        assert_eq!(key_name(KeyCode::Menu), None);
+    }
+
+    #[test]
+    fn test_bindings_file() {
+        let bindings =
+            Bindings::parse("fire = [\"left_control\", \"f\"]\nforward = [\"i\"]").unwrap();
+        assert_eq!(bindings.fire, vec!["left_control", "f"]);
+        assert_eq!(bindings.back, Bindings::default().back);
+
+        let held = |key| key == KeyCode::I || key == KeyCode::A;
+        let controls = bindings.controls(held, |key| key == KeyCode::F);
+        let second = Bindings::parse_over("fire = [\"slash\"]", Bindings::second_player());
+        assert_eq!(second.unwrap().forward, vec!["up"]);
+        assert_eq!(
+            controls,
+            Controls {
+                drive: 1.0,
+                turn: -1.0,
+                turret: 0.0,
+                fire: true,
+            }
+        );
+
+        let error = Bindings::parse("fire = [\"trigger\"]").unwrap_err();
+        assert!(error.starts_with("unknown key `trigger`"), "{}", error);
+        assert!(Bindings::parse("jump = [\"space\"]").is_err());
+    }
+
+    #[test]
+    fn test_gamepad_bindings() {
+        let bindings = Bindings::parse(
+            "turret_axes = [\"right_stick_x\", \"dpad_x\"]\nfire_buttons = [\"east\"]\ndeadzone = 0.5",
+        )
+        .unwrap();
+        assert_eq!(bindings.drive_axes, vec!["left_stick_y"]);
+        assert_eq!(bindings.gamepad, Some(0));
+
+        let axis = |axis| match axis {
+            Axis::LeftStickY => 0.75,
+            Axis::LeftStickX => -0.4,
+            Axis::RightStickX => 0.3,
+            Axis::DPadX => -1.0,
+            _ => 0.0,
+        };
+        let controls = bindings.gamepad_controls(axis, |button| button == Button::East);
+        assert_eq!(
+            controls,
+            Controls {
+                drive: 0.5,
+                turn: 0.0,
+                turret: -1.0,
+                fire: true,
+            }
+        );
+        let defaults = Bindings::default();
+        let controls = defaults.gamepad_controls(axis, |button| button == Button::East);
+        assert!((controls.turn + 0.25).abs() < 1e-6, "{:?}", controls);
+        assert!(!controls.fire);
+
+        // The keys win while held; the gamepad fills in the rest
+        let keys = Controls {
+            drive: -1.0,
+            ..Controls::default()
+        };
+        assert_eq!(combine(keys, controls).drive, -1.0);
+        assert_eq!(combine(keys, controls).turn, controls.turn);
+
+        let error = Bindings::parse("drive_axes = [\"left_stick\"]").unwrap_err();
+        assert!(
+            error.starts_with("unknown gamepad axis `left_stick`"),
+            "{}",
+            error
+        );
+        let error = Bindings::parse("fire_buttons = [\"a\"]").unwrap_err();
+        assert!(error.starts_with("unknown gamepad button `a`"), "{}", error);
+        assert!(Bindings::parse("deadzone = 1.0").is_err());
+
+        // A gamepad player two takes is no longer player one's
+        let second = Bindings::parse_over("gamepad = 0", Bindings::second_player()).unwrap();
+        assert_eq!(Bindings::default().without(&second).gamepad, None);
+        let first = Bindings::default().without(&Bindings::second_player());
+        assert_eq!(first.gamepad, Some(0));
+        assert_eq!(first.fire_buttons, Bindings::default().fire_buttons);
+    }
+
+    #[test]
+    fn test_players_share_the_keyboard() {
+        let first = Bindings::default().without(&Bindings::second_player());
+        assert_eq!(first.forward, vec!["w"]);
+        assert_eq!(first.turn_right, vec!["d"]);
+        assert_eq!(first.fire, vec!["space"]);
+        let held = |key| key == KeyCode::Up;
+        assert_eq!(first.controls(held, |_| false).drive, 0.0);
+        assert_eq!(
+            Bindings::second_player().controls(held, |_| false).drive,
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_shipped_bindings_are_the_defaults() {
+        let shipped = Bindings::load(Path::new("config/controls.toml")).unwrap();
+        assert_eq!(shipped, Bindings::default());
+        let second = Bindings::second_player();
+        let shipped = Bindings::load_over(Path::new("config/controls2.toml"), second.clone());
+        assert_eq!(shipped.unwrap(), second);
+        let shipped = ViewerKeys::load(Path::new("config/viewer_keys.toml")).unwrap();
+        assert_eq!(shipped, ViewerKeys::default());
+    }
+
+    #[test]
+    fn test_viewer_key_conflicts() {
+        let keys = ViewerKeys::parse("console = [\"f1\"]\nstep_back = [\"comma\"]").unwrap();
+        assert_eq!(keys.console, vec!["f1"]);
+        assert_eq!(keys.used_while_playing("f1").as_deref(), Some("`console`"));
+        assert_eq!(keys.used_while_playing("w"), None);
+
+        // The grid's pause and the menu's resume are never read together
+        assert!(ViewerKeys::parse("pause = [\"r\"]").is_ok());
+        let error = ViewerKeys::parse("debug = [\"s\"]").unwrap_err();
+        assert_eq!(error, "`s` is bound to both `speed` and `debug`");
+        let error = ViewerKeys::parse("quit = [\"enter\"]").unwrap_err();
+        assert!(error.ends_with("moving through the menu"), "{}", error);
+        assert!(ViewerKeys::parse("menu = [\"esc\"]").is_err());
+
+        let players = [Bindings::default(), Bindings::second_player()];
+        assert!(ViewerKeys::default().check_players(&players).is_ok());
+        let keys = ViewerKeys::parse("console = [\"e\"]").unwrap();
+        let error = keys.check_players(&players).unwrap_err();
+        assert_eq!(
+            error,
+            "`e` is bound to both `console` and player 1's `turret_right`"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+// Built-in reference opponents.
+// Selected on the command line as `builtin:<name>`, so there is always a known
+// opponent to test a script against.
+
+/// Built-in bots by name, with their scripts.
+pub const BUILTINS: &[(&str, &str)] = &[
+    // Idle target: never moves or shoots
+    ("sitting-duck", ""),
+    // Drives in a circle without fighting back
+    (
+        "circler",
+        "loop {
+            move forward 2
+            rotate body 6
+        }",
+    ),
+    // Patrols along the walls of the default arena, shooting at what it sees
+    (
+        "wall-follower",
+        "path {
+            30 30
+            370 30
+            370 370
+            30 370
+        }
+        loop {
+            follow path
+            rotate scanner 45
+            scan
+            aim at scanned
+            fire
+        }",
+    ),
+    // Stands still, spinning its turret and firing in every direction
+    (
+        "spinner",
+        "loop {
+            rotate turret 10
+            fire
+        }",
+    ),
+    // Sweeps its scanner, aims at contacts and keeps firing while roaming in
+    // wide arcs, so obstacles cannot hide a target for long
+    (
+        "tracker",
+        "loop {
+            rotate scanner 12
+            scan
+            aim at scanned
+            fire
+            move forward 8
+            rotate body 4
+        }",
+    ),
+];
+
+/// Script for the built-in bot called `name`.
+pub fn script(name: &str) -> Option<&'static str> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, script)| *script)
+}
+
+/// Names of all built-in bots, for help and error messages.
+pub fn names() -> Vec<&'static str> {
+    BUILTINS.iter().map(|(name, _)| *name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_script;
+
+    #[test]
+    fn test_builtin_scripts_compile() {
+        for (name, script) in BUILTINS {
+            assert!(parse_script(script).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_lookup_by_name() {
+        assert!(script("tracker").is_some());
+        assert_eq!(script("sitting-duck"), Some(""));
+        assert_eq!(script("terminator"), None);
+    }
+}
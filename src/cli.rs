@@ -0,0 +1,1501 @@
+// Command-line option parsing for the robot-battle binary.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use robot_battle::commands::DocsFormat;
+use robot_battle::dirs::Dirs;
+use robot_battle::graph::GraphFormat;
+use robot_battle::modes::GameMode;
+#[cfg(feature = "net")]
+use robot_battle::server::DEFAULT_ADDR;
+use robot_battle::setup::Entrant;
+use robot_battle::tournament::Format;
+use robot_battle::tutorial;
+
+/// Script used for both robots when no `fight` entrants are given.
+pub const DEFAULT_SCRIPT: &str = "robot-scripts/circler.robo";
+
+/// Which frontend draws the battle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderMode {
+    /// macroquad window (default in builds with the `render` feature).
+    Window,
+    /// Plain-ANSI terminal renderer.
+    Tui,
+    /// No rendering; run the match to completion and report the result.
+    Headless,
+}
+
+impl Default for RenderMode {
+    fn default() -> Self {
+        if cfg!(feature = "render") {
+            RenderMode::Window
+        } else {
+            RenderMode::Headless
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub render: RenderMode,
+    /// Write the match to this GIF file.
+    pub record: Option<PathBuf>,
+    /// TOML file with game constants.
+    pub config: Option<PathBuf>,
+    /// `key=value` overrides applied on top of the config, in order.
+    pub overrides: Vec<String>,
+    pub mode: GameMode,
+    /// Robots in the match, in spawn order.
+    pub entrants: Vec<Entrant>,
+    /// Write the match result as JSON to this file.
+    pub result_json: Option<PathBuf>,
+    /// Stop the match after this many ticks.
+    pub max_ticks: Option<u64>,
+    /// Seed of the match's random numbers.
+    pub seed: u64,
+    /// Threads the robots' instructions are executed on.
+    pub threads: usize,
+    /// Stop the match after this much wall-clock time.
+    pub timeout: Option<Duration>,
+    /// Write a replay of the match to this file.
+    pub save_replay: Option<PathBuf>,
+    /// Re-simulate this replay and check it against its events instead of
+    /// playing a new match.
+    pub verify_replay: Option<PathBuf>,
+    /// Play this replay back in the window or terminal instead of playing a
+    /// new match.
+    pub watch_replay: Option<PathBuf>,
+    /// Play a best-of-N series instead of a single match.
+    pub series: Option<u32>,
+    /// POST the result to this webhook URL.
+    pub webhook: Option<String>,
+    /// URL the saved replay is published under, for the webhook's replay link.
+    pub replay_url: Option<String>,
+    /// Record the match (every round of a series) in this history database.
+    pub history: Option<PathBuf>,
+    /// Write a report of where each robot's script spent its ticks to this file.
+    pub profile: Option<PathBuf>,
+    /// Write each robot's script, annotated with the commands that ran, to this file.
+    pub coverage: Option<PathBuf>,
+    /// TOML file with the keys that drive a human robot.
+    pub controls: Option<PathBuf>,
+    /// TOML file with the keys that drive the second human robot.
+    pub controls2: Option<PathBuf>,
+    /// Split the window in two, each half following one robot with only what
+    /// it can see; always on when two humans play.
+    pub split_screen: bool,
+    /// TOML file with the window's palette, HUD scale and contrast.
+    pub settings: Option<PathBuf>,
+    /// TOML file with the keys that work the window: the pause menu,
+    /// stepping, speed, views and console.
+    pub viewer_keys: Option<PathBuf>,
+    /// Arena file with the obstacles and hazards to fight among, instead of
+    /// the default arena.
+    pub arena: Option<PathBuf>,
+    /// Play this scenario file, with the first entrant as the player.
+    pub scenario: Option<PathBuf>,
+    /// Play this tutorial lesson, with the first entrant as the player.
+    pub lesson: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            render: RenderMode::default(),
+            record: None,
+            config: None,
+            overrides: Vec::new(),
+            mode: GameMode::default(),
+            entrants: vec![Entrant::Script(PathBuf::from(DEFAULT_SCRIPT)); 2],
+            result_json: None,
+            max_ticks: None,
+            seed: 0,
+            threads: 1,
+            timeout: None,
+            save_replay: None,
+            verify_replay: None,
+            watch_replay: None,
+            series: None,
+            webhook: None,
+            replay_url: None,
+            history: None,
+            profile: None,
+            coverage: None,
+            controls: None,
+            controls2: None,
+            split_screen: false,
+            settings: None,
+            viewer_keys: None,
+            arena: None,
+            scenario: None,
+            lesson: None,
+        }
+    }
+}
+
+/// What the binary was asked to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Invocation {
+    /// Play a match. Boxed, as its options dwarf every other invocation's.
+    Match(Box<Options>),
+    /// `verify <bot.robo>`: check a bot for tournament submission.
+    Verify(VerifyOptions),
+    /// `fetch <source>`: download bots from a repository.
+    #[cfg(feature = "net")]
+    Fetch(FetchOptions),
+    /// `tournament <entrant>...`: play a tournament between many bots.
+    Tournament(TournamentOptions),
+    /// `serve`: run matches on request over HTTP.
+    #[cfg(feature = "net")]
+    Serve(ServeOptions),
+    /// `history`: query the match history database.
+    History(HistoryOptions),
+    /// `graph <bot>`: draw a script's command tree and control flow.
+    Graph(GraphOptions),
+    /// `test <bot>`: run the tests written in a bot's script.
+    Test(TestOptions),
+    /// `docs`: print the command reference.
+    Docs(DocsOptions),
+    /// `tutorial` without a lesson: list the lessons and the progress made.
+    Tutorial,
+}
+
+/// Ticks a tournament or server match may last unless `--ticks` says otherwise.
+pub const DEFAULT_MATCH_TICKS: u64 = 20_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TournamentOptions {
+    /// Bots in the field, in entry order.
+    pub entrants: Vec<Entrant>,
+    pub format: Format,
+    /// TOML ladder the bots are seeded from; updated with the results.
+    pub ratings: Option<PathBuf>,
+    /// Write the bracket as JSON to this file.
+    pub bracket: Option<PathBuf>,
+    /// Save the replay of every match in this directory.
+    pub replays: Option<PathBuf>,
+    /// POST every match result and the final standings to this webhook URL.
+    pub webhook: Option<String>,
+    /// URL the replays directory is published under, for the webhook's replay links.
+    pub replay_url: Option<String>,
+    /// Record every match in this history database.
+    pub history: Option<PathBuf>,
+    /// Write each bot's coverage over all its matches to this directory.
+    pub coverage: Option<PathBuf>,
+    /// Once the tournament is played, watch its rounds in a window, with the
+    /// matches of each round side by side.
+    pub watch: bool,
+    /// Stop each match after this many ticks.
+    pub max_ticks: u64,
+    /// TOML file with game constants.
+    pub config: Option<PathBuf>,
+    /// `key=value` overrides applied on top of the config, in order.
+    pub overrides: Vec<String>,
+}
+
+/// Matches `history` lists unless `--limit` says otherwise.
+pub const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryOptions {
+    /// History database to read.
+    pub db: PathBuf,
+    /// Only list matches of this bot, and show its record.
+    pub bot: Option<String>,
+    /// Only list matches of `bot` against this opponent.
+    pub opponent: Option<String>,
+    /// List at most this many of the latest matches.
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphOptions {
+    /// The bot whose script is drawn.
+    pub bot: Entrant,
+    pub format: GraphFormat,
+    /// Write the graph to this file instead of stdout.
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestOptions {
+    /// The bot whose tests are run.
+    pub bot: Entrant,
+    /// TOML file with game constants.
+    pub config: Option<PathBuf>,
+    /// `key=value` overrides applied on top of the config, in order.
+    pub overrides: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DocsOptions {
+    pub format: DocsFormat,
+    /// Write the reference to this file instead of stdout.
+    pub output: Option<PathBuf>,
+}
+
+#[cfg(feature = "net")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServeOptions {
+    /// Address to listen on, as `host:port`.
+    pub addr: String,
+    /// Longest a match may run; requests can only ask for fewer ticks.
+    pub max_ticks: u64,
+    /// TOML file with game constants.
+    pub config: Option<PathBuf>,
+    /// `key=value` overrides applied on top of the config, in order.
+    pub overrides: Vec<String>,
+}
+
+#[cfg(feature = "net")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchOptions {
+    /// Git repository URL, or URL or path of an index file.
+    pub source: String,
+    /// Directory the bots are saved in.
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VerifyOptions {
+    pub script: PathBuf,
+    /// Name for the manifest; defaults to the file name.
+    pub name: Option<String>,
+    pub author: Option<String>,
+    /// TOML file with the tournament's feature bans.
+    pub rules: Option<PathBuf>,
+    /// Write the manifest to this file instead of stdout.
+    pub manifest: Option<PathBuf>,
+    /// TOML file with game constants, including the sandbox limits.
+    pub config: Option<PathBuf>,
+    /// `key=value` overrides applied on top of the config, in order.
+    pub overrides: Vec<String>,
+}
+
+/// Parse command-line arguments (without the program name), dispatching on the
+/// subcommand.
+pub fn parse_invocation(args: Vec<String>) -> Result<Invocation, String> {
+    match args.split_first() {
+        Some((first, rest)) if first == "verify" => parse_verify_args(rest).map(Invocation::Verify),
+        #[cfg(feature = "net")]
+        Some((first, rest)) if first == "fetch" => parse_fetch_args(rest).map(Invocation::Fetch),
+        Some((first, rest)) if first == "tournament" => {
+            parse_tournament_args(rest).map(Invocation::Tournament)
+        }
+        #[cfg(feature = "net")]
+        Some((first, rest)) if first == "serve" => parse_serve_args(rest).map(Invocation::Serve),
+        #[cfg(not(feature = "net"))]
+        Some((first, _)) if first == "fetch" || first == "serve" => {
+            Err(format!("{} needs a build with the `net` feature", first))
+        }
+        Some((first, rest)) if first == "history" => {
+            parse_history_args(rest).map(Invocation::History)
+        }
+        Some((first, rest)) if first == "graph" => parse_graph_args(rest).map(Invocation::Graph),
+        Some((first, rest)) if first == "test" => parse_test_args(rest).map(Invocation::Test),
+        Some((first, rest)) if first == "docs" => parse_docs_args(rest).map(Invocation::Docs),
+        Some((first, rest)) if first == "scenario" => {
+            parse_scenario_args(rest).map(|o| Invocation::Match(Box::new(o)))
+        }
+        Some((first, rest)) if first == "tutorial" => parse_tutorial_args(rest),
+        _ => parse_args(args).map(|o| Invocation::Match(Box::new(o))),
+    }
+}
+
+/// Parse an entrant, looking for a script that is not in the working directory
+/// in the bots directory.
+fn entrant(arg: &str) -> Result<Entrant, String> {
+    Ok(match Entrant::parse(arg)? {
+        Entrant::Script(path) => Entrant::Script(Dirs::locate().bot_script(&path)),
+        other => other,
+    })
+}
+
+/// Parse the arguments after `verify`.
+fn parse_verify_args(args: &[String]) -> Result<VerifyOptions, String> {
+    let mut args = args.iter();
+    let script = args
+        .next()
+        .filter(|a| !a.starts_with("--"))
+        .ok_or("verify expects a robot script")?;
+    let mut options = VerifyOptions {
+        script: PathBuf::from(script),
+        ..VerifyOptions::default()
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |expects: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} expects {}", arg, expects))
+        };
+        match arg.as_str() {
+            "--name" => options.name = Some(value("a bot name")?),
+            "--author" => options.author = Some(value("an author name")?),
+            "--rules" => options.rules = Some(PathBuf::from(value("a TOML file path")?)),
+            "--manifest" => options.manifest = Some(PathBuf::from(value("an output path")?)),
+            "--config" => options.config = Some(PathBuf::from(value("a TOML file path")?)),
+            "--set" => {
+                let assignment = value("key=value")?;
+                if !assignment.contains('=') {
+                    return Err(format!("--set expects key=value, got: {}", assignment));
+                }
+                options.overrides.push(assignment);
+            }
+            other => return Err(format!("unknown argument for verify: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+/// Parse the arguments after `fetch`.
+#[cfg(feature = "net")]
+fn parse_fetch_args(args: &[String]) -> Result<FetchOptions, String> {
+    let mut args = args.iter();
+    let source = args
+        .next()
+        .filter(|a| !a.starts_with("--"))
+        .ok_or("fetch expects a git URL or index file")?;
+    let mut options = FetchOptions {
+        source: source.clone(),
+        dir: Dirs::locate().bots(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => {
+                let value = args.next().ok_or("--dir expects a directory")?;
+                options.dir = PathBuf::from(value);
+            }
+            other => return Err(format!("unknown argument for fetch: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+/// Parse the arguments after `tournament`.
+fn parse_tournament_args(args: &[String]) -> Result<TournamentOptions, String> {
+    let mut args = args.iter().peekable();
+    let mut entrants = Vec::new();
+    while let Some(arg) = args.next_if(|a| !a.starts_with("--")) {
+        entrants.push(entrant(arg)?);
+    }
+    if entrants.contains(&Entrant::Human) {
+        return Err(
+            "a tournament is played headless, so it cannot have a human entrant".to_string(),
+        );
+    }
+    if entrants.len() < 2 {
+        return Err("tournament expects at least two robot scripts or builtin:<name>".to_string());
+    }
+    let mut options = TournamentOptions {
+        entrants,
+        format: Format::SingleElimination,
+        ratings: None,
+        bracket: None,
+        replays: None,
+        webhook: None,
+        replay_url: None,
+        history: None,
+        coverage: None,
+        watch: false,
+        max_ticks: DEFAULT_MATCH_TICKS,
+        config: None,
+        overrides: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |expects: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} expects {}", arg, expects))
+        };
+        match arg.as_str() {
+            "--format" => {
+                let name = value("round-robin|single|double")?;
+                options.format = Format::from_name(&name)
+                    .ok_or_else(|| format!("unknown tournament format: {}", name))?;
+            }
+            "--ratings" => options.ratings = Some(PathBuf::from(value("a TOML file path")?)),
+            "--bracket" => options.bracket = Some(PathBuf::from(value("an output path")?)),
+            "--replays" => options.replays = Some(PathBuf::from(value("a directory")?)),
+            "--webhook" => options.webhook = Some(value("a URL")?),
+            "--replay-url" => options.replay_url = Some(value("a URL")?),
+            "--history" => options.history = Some(PathBuf::from(value("a database path")?)),
+            "--coverage" => options.coverage = Some(PathBuf::from(value("a directory")?)),
+            "--watch" => options.watch = true,
+            "--ticks" => {
+                let ticks = value("a number of ticks")?;
+                options.max_ticks =
+                    ticks.parse().ok().filter(|t| *t > 0).ok_or_else(|| {
+                        format!("--ticks expects a number of ticks, got: {}", ticks)
+                    })?;
+            }
+            "--config" => options.config = Some(PathBuf::from(value("a TOML file path")?)),
+            "--set" => {
+                let assignment = value("key=value")?;
+                if !assignment.contains('=') {
+                    return Err(format!("--set expects key=value, got: {}", assignment));
+                }
+                options.overrides.push(assignment);
+            }
+            other => return Err(format!("unknown argument for tournament: {}", other)),
+        }
+    }
+    if options.replay_url.is_some() && (options.webhook.is_none() || options.replays.is_none()) {
+        return Err("--replay-url needs --webhook and --replays".to_string());
+    }
+    if let Some(flag) = missing_feature(options.watch, options.webhook.is_some()) {
+        return Err(flag);
+    }
+    Ok(options)
+}
+
+/// Parse the arguments after `history`.
+fn parse_history_args(args: &[String]) -> Result<HistoryOptions, String> {
+    let mut args = args.iter();
+    let mut options = HistoryOptions {
+        db: Dirs::locate().history_db(),
+        bot: None,
+        opponent: None,
+        limit: DEFAULT_HISTORY_LIMIT,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |expects: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} expects {}", arg, expects))
+        };
+        match arg.as_str() {
+            "--db" => options.db = PathBuf::from(value("a database path")?),
+            "--bot" => options.bot = Some(value("a bot name")?),
+            "--vs" => options.opponent = Some(value("a bot name")?),
+            "--limit" => {
+                let limit = value("a number of matches")?;
+                options.limit = limit.parse().ok().filter(|n| *n > 0).ok_or_else(|| {
+                    format!("--limit expects a number of matches, got: {}", limit)
+                })?;
+            }
+            other => return Err(format!("unknown argument for history: {}", other)),
+        }
+    }
+    if options.opponent.is_some() && options.bot.is_none() {
+        return Err("--vs needs --bot".to_string());
+    }
+    Ok(options)
+}
+
+/// Parse the arguments after `graph`.
+fn parse_graph_args(args: &[String]) -> Result<GraphOptions, String> {
+    let mut args = args.iter();
+    let bot = args
+        .next()
+        .filter(|a| !a.starts_with("--"))
+        .ok_or("graph expects a robot script or builtin:<name>")?;
+    let bot = entrant(bot)?;
+    if bot == Entrant::Human {
+        return Err("a human robot has no script to graph".to_string());
+    }
+    let mut options = GraphOptions {
+        bot,
+        format: GraphFormat::default(),
+        output: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |expects: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} expects {}", arg, expects))
+        };
+        match arg.as_str() {
+            "--format" => {
+                let name = value("dot|mermaid")?;
+                options.format = GraphFormat::from_name(&name)
+                    .ok_or_else(|| format!("unknown graph format: {}", name))?;
+            }
+            "--output" => options.output = Some(PathBuf::from(value("an output path")?)),
+            other => return Err(format!("unknown argument for graph: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+/// Parse the arguments after `test`.
+fn parse_test_args(args: &[String]) -> Result<TestOptions, String> {
+    let mut args = args.iter();
+    let bot = args
+        .next()
+        .filter(|a| !a.starts_with("--"))
+        .ok_or("test expects a robot script or builtin:<name>")?;
+    let bot = entrant(bot)?;
+    if bot == Entrant::Human {
+        return Err("a human robot has no script to test".to_string());
+    }
+    let mut options = TestOptions {
+        bot,
+        config: None,
+        overrides: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |expects: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} expects {}", arg, expects))
+        };
+        match arg.as_str() {
+            "--config" => options.config = Some(PathBuf::from(value("a TOML file path")?)),
+            "--set" => {
+                let assignment = value("key=value")?;
+                if !assignment.contains('=') {
+                    return Err(format!("--set expects key=value, got: {}", assignment));
+                }
+                options.overrides.push(assignment);
+            }
+            other => return Err(format!("unknown argument for test: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+/// Parse the arguments after `docs`.
+fn parse_docs_args(args: &[String]) -> Result<DocsOptions, String> {
+    let mut args = args.iter();
+    let mut options = DocsOptions::default();
+    while let Some(arg) = args.next() {
+        let mut value = |expects: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} expects {}", arg, expects))
+        };
+        match arg.as_str() {
+            "--format" => {
+                let name = value("markdown|json")?;
+                options.format = DocsFormat::from_name(&name)
+                    .ok_or_else(|| format!("unknown docs format: {}", name))?;
+            }
+            "--output" => options.output = Some(PathBuf::from(value("an output path")?)),
+            other => return Err(format!("unknown argument for docs: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+/// Parse the arguments after `scenario`: the scenario file and the player's
+/// bot, then the options of a match.
+fn parse_scenario_args(args: &[String]) -> Result<Options, String> {
+    let positional = |i: usize| args.get(i).filter(|a| !a.starts_with("--"));
+    let (Some(scenario), Some(bot)) = (positional(0), positional(1)) else {
+        return Err("scenario expects a scenario file and a robot script".to_string());
+    };
+    let mut options = parse_scenario_match("scenario", bot, &args[2..])?;
+    options.scenario = Some(PathBuf::from(scenario));
+    Ok(options)
+}
+
+/// Parse the arguments after `tutorial`: nothing, to list the lessons, or a
+/// lesson and the player's bot, then the options of a match.
+fn parse_tutorial_args(args: &[String]) -> Result<Invocation, String> {
+    let Some(name) = args.first() else {
+        return Ok(Invocation::Tutorial);
+    };
+    let lesson = tutorial::lesson(name).ok_or_else(|| {
+        let names: Vec<&str> = tutorial::LESSONS.iter().map(|l| l.name).collect();
+        format!("unknown lesson: {} (available: {})", name, names.join(", "))
+    })?;
+    let bot = args
+        .get(1)
+        .filter(|a| !a.starts_with("--"))
+        .ok_or("tutorial expects a lesson and a robot script")?;
+    let mut options = parse_scenario_match("tutorial", bot, &args[2..])?;
+    options.lesson = Some(lesson.name.to_string());
+    Ok(Invocation::Match(Box::new(options)))
+}
+
+/// Parse the options of a `command` match played by `bot` in a scenario.
+fn parse_scenario_match(command: &str, bot: &str, args: &[String]) -> Result<Options, String> {
+    let fight = ["fight".to_string(), bot.to_string()];
+    let options = parse_args(fight.into_iter().chain(args.iter().cloned()))?;
+    // Replays and series set up a plain match, without the scenario's
+    // placements; the scenario brings its own arena
+    let unsupported = [
+        ("--arena", options.arena.is_some()),
+        ("--mode", options.mode != GameMode::default()),
+        ("--series", options.series.is_some()),
+        ("--save-replay", options.save_replay.is_some()),
+        ("--verify-replay", options.verify_replay.is_some()),
+        ("--watch-replay", options.watch_replay.is_some()),
+    ];
+    if let Some((flag, _)) = unsupported.iter().find(|(_, set)| *set) {
+        return Err(format!("{} cannot be used with {}", flag, command));
+    }
+    Ok(options)
+}
+
+/// Parse the arguments after `serve`.
+#[cfg(feature = "net")]
+fn parse_serve_args(args: &[String]) -> Result<ServeOptions, String> {
+    let mut args = args.iter();
+    let mut options = ServeOptions {
+        addr: DEFAULT_ADDR.to_string(),
+        max_ticks: DEFAULT_MATCH_TICKS,
+        config: None,
+        overrides: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |expects: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} expects {}", arg, expects))
+        };
+        match arg.as_str() {
+            "--addr" => options.addr = value("host:port")?,
+            "--ticks" => {
+                let ticks = value("a number of ticks")?;
+                options.max_ticks =
+                    ticks.parse().ok().filter(|t| *t > 0).ok_or_else(|| {
+                        format!("--ticks expects a number of ticks, got: {}", ticks)
+                    })?;
+            }
+            "--config" => options.config = Some(PathBuf::from(value("a TOML file path")?)),
+            "--set" => {
+                let assignment = value("key=value")?;
+                if !assignment.contains('=') {
+                    return Err(format!("--set expects key=value, got: {}", assignment));
+                }
+                options.overrides.push(assignment);
+            }
+            other => return Err(format!("unknown argument for serve: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+/// Parse command-line arguments (without the program name) into `Options`.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter().peekable();
+
+    // `fight <entrant>...` replaces the default entrants
+    if args.peek().map(String::as_str) == Some("fight") {
+        args.next();
+        let mut entrants = Vec::new();
+        while let Some(arg) = args.next_if(|a| !a.starts_with("--")) {
+            entrants.push(entrant(&arg)?);
+        }
+        if entrants.is_empty() {
+            return Err("fight expects at least one robot script or builtin:<name>".to_string());
+        }
+        options.entrants = entrants;
+    }
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--render" => {
+                let value = args
+                    .next()
+                    .ok_or("--render expects a value (window|tui|none)")?;
+                options.render = match value.as_str() {
+                    "window" | "gui" => RenderMode::Window,
+                    "tui" => RenderMode::Tui,
+                    "none" => RenderMode::Headless,
+                    other => return Err(format!("unknown render mode: {}", other)),
+                };
+            }
+            "--headless" => options.render = RenderMode::Headless,
+            "--record" => {
+                let value = args.next().ok_or("--record expects an output path")?;
+                if !value.to_lowercase().ends_with(".gif") {
+                    return Err(format!("--record only supports .gif output: {}", value));
+                }
+                options.record = Some(PathBuf::from(value));
+            }
+            "--mode" => {
+                let value = args
+                    .next()
+                    .ok_or("--mode expects deathmatch|koth|waves|practice")?;
+                options.mode = GameMode::from_name(&value)
+                    .ok_or_else(|| format!("unknown game mode: {}", value))?;
+            }
+            "--config" => {
+                let value = args.next().ok_or("--config expects a TOML file path")?;
+                options.config = Some(PathBuf::from(value));
+            }
+            "--set" => {
+                let value = args.next().ok_or("--set expects key=value")?;
+                if !value.contains('=') {
+                    return Err(format!("--set expects key=value, got: {}", value));
+                }
+                options.overrides.push(value);
+            }
+            "--result-json" => {
+                let value = args.next().ok_or("--result-json expects an output path")?;
+                options.result_json = Some(PathBuf::from(value));
+            }
+            "--ticks" => {
+                let value = args.next().ok_or("--ticks expects a number of ticks")?;
+                let ticks = value
+                    .parse()
+                    .map_err(|_| format!("--ticks expects a number of ticks, got: {}", value))?;
+                options.max_ticks = Some(ticks);
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed expects a number")?;
+                options.seed = value
+                    .parse()
+                    .map_err(|_| format!("--seed expects a number, got: {}", value))?;
+            }
+            "--threads" => {
+                let value = args.next().ok_or("--threads expects a number of threads")?;
+                options.threads = value.parse().ok().filter(|n| *n > 0).ok_or_else(|| {
+                    format!("--threads expects a number of threads, got: {}", value)
+                })?;
+            }
+            "--timeout" => {
+                let value = args.next().ok_or("--timeout expects a number of seconds")?;
+                let seconds: f64 = value
+                    .parse()
+                    .ok()
+                    .filter(|s: &f64| s.is_finite() && *s > 0.0)
+                    .ok_or_else(|| {
+                        format!("--timeout expects a number of seconds, got: {}", value)
+                    })?;
+                options.timeout = Some(Duration::from_secs_f64(seconds));
+            }
+            "--save-replay" => {
+                let value = args.next().ok_or("--save-replay expects an output path")?;
+                options.save_replay = Some(PathBuf::from(value));
+            }
+            "--verify-replay" => {
+                let value = args.next().ok_or("--verify-replay expects a replay file")?;
+                options.verify_replay = Some(Dirs::locate().replay(Path::new(&value)));
+            }
+            "--watch-replay" => {
+                let value = args.next().ok_or("--watch-replay expects a replay file")?;
+                options.watch_replay = Some(Dirs::locate().replay(Path::new(&value)));
+            }
+            "--series" => {
+                let value = args.next().ok_or("--series expects a number of rounds")?;
+                let rounds = value.parse().ok().filter(|n| *n > 0).ok_or_else(|| {
+                    format!("--series expects a number of rounds, got: {}", value)
+                })?;
+                options.series = Some(rounds);
+            }
+            "--webhook" => {
+                let value = args.next().ok_or("--webhook expects a URL")?;
+                options.webhook = Some(value);
+            }
+            "--replay-url" => {
+                let value = args.next().ok_or("--replay-url expects a URL")?;
+                options.replay_url = Some(value);
+            }
+            "--history" => {
+                let value = args.next().ok_or("--history expects a database path")?;
+                options.history = Some(PathBuf::from(value));
+            }
+            "--profile" => {
+                let value = args.next().ok_or("--profile expects an output path")?;
+                options.profile = Some(PathBuf::from(value));
+            }
+            "--coverage" => {
+                let value = args.next().ok_or("--coverage expects an output path")?;
+                options.coverage = Some(PathBuf::from(value));
+            }
+            "--controls" => {
+                let value = args.next().ok_or("--controls expects a TOML file path")?;
+                options.controls = Some(PathBuf::from(value));
+            }
+            "--controls2" => {
+                let value = args.next().ok_or("--controls2 expects a TOML file path")?;
+                options.controls2 = Some(PathBuf::from(value));
+            }
+            "--split-screen" => options.split_screen = true,
+            "--settings" => {
+                let value = args.next().ok_or("--settings expects a TOML file path")?;
+                options.settings = Some(PathBuf::from(value));
+            }
+            "--viewer-keys" => {
+                let value = args
+                    .next()
+                    .ok_or("--viewer-keys expects a TOML file path")?;
+                options.viewer_keys = Some(PathBuf::from(value));
+            }
+            "--arena" => {
+                let value = args.next().ok_or("--arena expects a TOML file path")?;
+                options.arena = Some(PathBuf::from(value));
+            }
+            other => return Err(format!("unknown argument: {}", other)),
+        }
+    }
+
+    // The window keeps running after the match, so it has no single result
+    if options.render == RenderMode::Window && options.verify_replay.is_none() {
+        let unattended = [
+            ("--result-json", options.result_json.is_some()),
+            ("--save-replay", options.save_replay.is_some()),
+            ("--ticks", options.max_ticks.is_some()),
+            ("--timeout", options.timeout.is_some()),
+            ("--series", options.series.is_some()),
+            ("--webhook", options.webhook.is_some()),
+            ("--history", options.history.is_some()),
+            ("--profile", options.profile.is_some()),
+            ("--coverage", options.coverage.is_some()),
+        ];
+        if let Some((flag, _)) = unattended.iter().find(|(_, set)| *set) {
+            return Err(format!("{} needs --headless or --render tui", flag));
+        }
+    }
+
+    // The player steers from the window
+    if options.entrants.contains(&Entrant::Human) && options.render != RenderMode::Window {
+        return Err("a human robot needs the window".to_string());
+    }
+    let humans = options
+        .entrants
+        .iter()
+        .filter(|e| **e == Entrant::Human)
+        .count();
+    if humans > 2 {
+        return Err("at most two human robots can play, sharing the keyboard".to_string());
+    }
+    if options.controls.is_some() && humans == 0 {
+        return Err("--controls needs a human entrant".to_string());
+    }
+    if options.controls2.is_some() && humans < 2 {
+        return Err("--controls2 needs a second human entrant".to_string());
+    }
+    if options.split_screen && options.render != RenderMode::Window {
+        return Err("--split-screen needs the window".to_string());
+    }
+    if options.settings.is_some() && options.render != RenderMode::Window {
+        return Err("--settings needs the window".to_string());
+    }
+    if options.viewer_keys.is_some() && options.render != RenderMode::Window {
+        return Err("--viewer-keys needs the window".to_string());
+    }
+
+    if options.watch_replay.is_some() && options.render == RenderMode::Headless {
+        return Err("--watch-replay needs the window or --render tui".to_string());
+    }
+
+    // Outputs for a single match have no place in a series
+    if options.series.is_some() {
+        let single = [
+            ("--record", options.record.is_some()),
+            ("--result-json", options.result_json.is_some()),
+            ("--save-replay", options.save_replay.is_some()),
+            ("--webhook", options.webhook.is_some()),
+            ("--profile", options.profile.is_some()),
+            ("--coverage", options.coverage.is_some()),
+        ];
+        if let Some((flag, _)) = single.iter().find(|(_, set)| *set) {
+            return Err(format!("{} cannot be used with --series", flag));
+        }
+    }
+
+    if options.replay_url.is_some() && (options.webhook.is_none() || options.save_replay.is_none())
+    {
+        return Err("--replay-url needs --webhook and --save-replay".to_string());
+    }
+    let window = options.render == RenderMode::Window && options.verify_replay.is_none();
+    if let Some(flag) = missing_feature(window, options.webhook.is_some()) {
+        return Err(flag);
+    }
+
+    Ok(options)
+}
+
+/// Why a window or a webhook cannot be used in this build, if it was left out
+/// by its cargo feature.
+fn missing_feature(window: bool, webhook: bool) -> Option<String> {
+    if window && !cfg!(feature = "render") {
+        return Some("the window needs a build with the `render` feature".to_string());
+    }
+    if webhook && !cfg!(feature = "net") {
+        return Some("--webhook needs a build with the `net` feature".to_string());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_default_render_is_window() {
+        let options = parse_args(args(&[])).unwrap();
+        assert_eq!(options.render, RenderMode::Window);
+    }
+
+    #[cfg(not(feature = "render"))]
+    #[test]
+    fn test_window_needs_render_feature() {
+        let options = parse_args(args(&[])).unwrap();
+        assert_eq!(options.render, RenderMode::Headless);
+        assert!(parse_args(args(&["--render", "window"])).is_err());
+        assert!(parse_args(args(&["fight", "human", "builtin:spinner"])).is_err());
+    }
+
+    #[test]
+    fn test_render_tui() {
+        let options = parse_args(args(&["--render", "tui"])).unwrap();
+        assert_eq!(options.render, RenderMode::Tui);
+    }
+
+    #[test]
+    fn test_render_rejects_unknown_mode() {
+        assert!(parse_args(args(&["--render", "vr"])).is_err());
+        assert!(parse_args(args(&["--render"])).is_err());
+    }
+
+    #[test]
+    fn test_headless() {
+        let options = parse_args(args(&["--headless"])).unwrap();
+        assert_eq!(options.render, RenderMode::Headless);
+        let options = parse_args(args(&["--render", "none"])).unwrap();
+        assert_eq!(options.render, RenderMode::Headless);
+    }
+
+    #[test]
+    fn test_record_path() {
+        let options = parse_args(args(&["--record", "out.gif", "--render", "tui"])).unwrap();
+        assert_eq!(options.record, Some(PathBuf::from("out.gif")));
+        assert_eq!(options.render, RenderMode::Tui);
+        assert!(parse_args(args(&["--record", "out.mp4"])).is_err());
+    }
+
+    #[test]
+    fn test_fight_entrants() {
+        let options = parse_args(args(&[
+            "fight",
+            "mybot.robo",
+            "builtin:tracker",
+            "--headless",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.entrants,
+            vec![
+                Entrant::Script(PathBuf::from("mybot.robo")),
+                Entrant::Builtin("tracker".to_string()),
+            ]
+        );
+        assert_eq!(options.render, RenderMode::Headless);
+        assert!(parse_args(args(&["fight", "builtin:terminator"])).is_err());
+        assert!(parse_args(args(&["fight", "--headless"])).is_err());
+        assert_eq!(parse_args(args(&[])).unwrap().entrants.len(), 2);
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_human_entrant() {
+        let options = parse_args(args(&["fight", "human", "mybot.robo"])).unwrap();
+        assert_eq!(options.entrants[0], Entrant::Human);
+        assert!(parse_args(args(&["fight", "human", "mybot.robo", "--headless"])).is_err());
+        assert!(parse_args(args(&["fight", "human", "human", "human"])).is_err());
+        let options = parse_args(args(&[
+            "fight",
+            "human",
+            "a.robo",
+            "--controls",
+            "pad.toml",
+        ]))
+        .unwrap();
+        assert_eq!(options.controls, Some(PathBuf::from("pad.toml")));
+        assert!(parse_args(args(&["fight", "a.robo", "--controls", "pad.toml"])).is_err());
+        assert!(parse_invocation(args(&["tournament", "human", "a.robo"])).is_err());
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_two_humans_split_the_screen() {
+        let options = parse_args(args(&[
+            "fight",
+            "human",
+            "human",
+            "--controls2",
+            "pad.toml",
+        ]))
+        .unwrap();
+        assert_eq!(options.entrants, vec![Entrant::Human; 2]);
+        assert_eq!(options.controls2, Some(PathBuf::from("pad.toml")));
+        assert!(
+            parse_args(args(&[
+                "fight",
+                "human",
+                "a.robo",
+                "--controls2",
+                "pad.toml"
+            ]))
+            .is_err()
+        );
+        assert!(
+            parse_args(args(&["fight", "a.robo", "b.robo", "--split-screen"]))
+                .unwrap()
+                .split_screen
+        );
+        assert!(parse_args(args(&["--split-screen", "--headless"])).is_err());
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_settings_file() {
+        let options = parse_args(args(&["--settings", "big.toml"])).unwrap();
+        assert_eq!(options.settings, Some(PathBuf::from("big.toml")));
+        assert!(parse_args(args(&["--settings", "big.toml", "--headless"])).is_err());
+        let options = parse_args(args(&["--viewer-keys", "azerty.toml"])).unwrap();
+        assert_eq!(options.viewer_keys, Some(PathBuf::from("azerty.toml")));
+        assert!(parse_args(args(&["--viewer-keys", "azerty.toml", "--render", "tui"])).is_err());
+    }
+
+    #[test]
+    fn test_mode() {
+        assert_eq!(parse_args(args(&[])).unwrap().mode, GameMode::Deathmatch);
+        let options = parse_args(args(&["--mode", "koth"])).unwrap();
+        assert_eq!(options.mode, GameMode::KingOfTheHill);
+        assert!(parse_args(args(&["--mode", "tag"])).is_err());
+    }
+
+    #[test]
+    fn test_config_and_overrides() {
+        let options = parse_args(args(&[
+            "--config",
+            "game.toml",
+            "--set",
+            "move_speed=0.5",
+            "--set",
+            "scan_range=100",
+        ]))
+        .unwrap();
+        assert_eq!(options.config, Some(PathBuf::from("game.toml")));
+        assert_eq!(options.overrides, vec!["move_speed=0.5", "scan_range=100"]);
+        assert!(parse_args(args(&["--set", "move_speed"])).is_err());
+        assert!(parse_args(args(&["--config"])).is_err());
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_result_json() {
+        let options = parse_args(args(&["--headless", "--result-json", "out.json"])).unwrap();
+        assert_eq!(options.result_json, Some(PathBuf::from("out.json")));
+        assert!(parse_args(args(&["--result-json", "out.json"])).is_err());
+        assert!(parse_args(args(&["--headless", "--result-json"])).is_err());
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_replays() {
+        let options = parse_args(args(&["--headless", "--save-replay", "match.json"])).unwrap();
+        assert_eq!(options.save_replay, Some(PathBuf::from("match.json")));
+        assert!(parse_args(args(&["--save-replay", "match.json"])).is_err());
+        // Verifying a replay plays no match, so it needs no renderer flag
+        let options = parse_args(args(&["--verify-replay", "match.json"])).unwrap();
+        assert_eq!(options.verify_replay, Some(PathBuf::from("match.json")));
+        assert!(parse_args(args(&["--verify-replay"])).is_err());
+        let options = parse_args(args(&["--watch-replay", "match.json"])).unwrap();
+        assert_eq!(options.watch_replay, Some(PathBuf::from("match.json")));
+        assert!(parse_args(args(&["--render", "tui", "--watch-replay", "match.json"])).is_ok());
+        assert!(parse_args(args(&["--headless", "--watch-replay", "match.json"])).is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let invocation = parse_invocation(args(&[
+            "verify",
+            "mybot.robo",
+            "--author",
+            "ada",
+            "--rules",
+            "cup.toml",
+            "--set",
+            "max_variables=8",
+        ]))
+        .unwrap();
+        assert_eq!(
+            invocation,
+            Invocation::Verify(VerifyOptions {
+                script: PathBuf::from("mybot.robo"),
+                author: Some("ada".to_string()),
+                rules: Some(PathBuf::from("cup.toml")),
+                overrides: vec!["max_variables=8".to_string()],
+                ..VerifyOptions::default()
+            })
+        );
+        assert!(parse_invocation(args(&["verify"])).is_err());
+        assert!(parse_invocation(args(&["verify", "mybot.robo", "--headless"])).is_err());
+        assert!(parse_invocation(args(&["verify", "mybot.robo", "--author"])).is_err());
+        assert!(matches!(
+            parse_invocation(args(&["--headless"])),
+            Ok(Invocation::Match(_))
+        ));
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_ticks_and_timeout() {
+        let options =
+            parse_args(args(&["--ticks", "5000", "--timeout", "2.5", "--headless"])).unwrap();
+        assert_eq!(options.max_ticks, Some(5000));
+        assert_eq!(options.timeout, Some(Duration::from_millis(2500)));
+        assert!(parse_args(args(&["--ticks", "5000"])).is_err());
+        assert!(parse_args(args(&["--headless", "--ticks", "-1"])).is_err());
+        assert!(parse_args(args(&["--headless", "--timeout", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_seed() {
+        assert_eq!(parse_args(args(&[])).unwrap().seed, 0);
+        assert_eq!(parse_args(args(&["--seed", "42"])).unwrap().seed, 42);
+        assert!(parse_args(args(&["--seed", "-1"])).is_err());
+    }
+
+    #[test]
+    fn test_threads() {
+        assert_eq!(parse_args(args(&[])).unwrap().threads, 1);
+        assert_eq!(parse_args(args(&["--threads", "4"])).unwrap().threads, 4);
+        assert!(parse_args(args(&["--threads", "0"])).is_err());
+        assert!(parse_args(args(&["--threads"])).is_err());
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_fetch() {
+        let invocation =
+            parse_invocation(args(&["fetch", "https://example.com/bots.git"])).unwrap();
+        assert_eq!(
+            invocation,
+            Invocation::Fetch(FetchOptions {
+                source: "https://example.com/bots.git".to_string(),
+                dir: Dirs::locate().bots(),
+            })
+        );
+        let invocation =
+            parse_invocation(args(&["fetch", "index.toml", "--dir", "opponents"])).unwrap();
+        assert!(matches!(invocation, Invocation::Fetch(f) if f.dir == Path::new("opponents")));
+        assert!(parse_invocation(args(&["fetch"])).is_err());
+        assert!(parse_invocation(args(&["fetch", "index.toml", "--dir"])).is_err());
+    }
+
+    #[cfg(all(feature = "render", feature = "net"))]
+    #[test]
+    fn test_tournament() {
+        let invocation = parse_invocation(args(&[
+            "tournament",
+            "a.robo",
+            "builtin:tracker",
+            "b.robo",
+            "--format",
+            "double",
+            "--ratings",
+            "ladder.toml",
+        ]))
+        .unwrap();
+        let Invocation::Tournament(options) = invocation else {
+            panic!("expected a tournament, got {:?}", invocation);
+        };
+        assert_eq!(options.entrants.len(), 3);
+        assert_eq!(options.format, Format::DoubleElimination);
+        assert_eq!(options.ratings, Some(PathBuf::from("ladder.toml")));
+        assert_eq!(options.max_ticks, DEFAULT_MATCH_TICKS);
+        assert!(!options.watch);
+        let invocation =
+            parse_invocation(args(&["tournament", "a.robo", "b.robo", "--watch"])).unwrap();
+        assert!(matches!(
+            invocation,
+            Invocation::Tournament(TournamentOptions { watch: true, .. })
+        ));
+        assert!(parse_invocation(args(&["tournament", "a.robo"])).is_err());
+        assert!(
+            parse_invocation(args(&[
+                "tournament",
+                "a.robo",
+                "b.robo",
+                "--webhook",
+                "https://example.com/hook",
+                "--replay-url",
+                "https://example.com/replays"
+            ]))
+            .is_err()
+        );
+        assert!(
+            parse_invocation(args(&[
+                "tournament",
+                "a.robo",
+                "b.robo",
+                "--format",
+                "swiss"
+            ]))
+            .is_err()
+        );
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn test_serve() {
+        let invocation = parse_invocation(args(&["serve"])).unwrap();
+        assert!(matches!(
+            invocation,
+            Invocation::Serve(ServeOptions { ref addr, max_ticks: DEFAULT_MATCH_TICKS, .. })
+                if addr == DEFAULT_ADDR
+        ));
+        let invocation = parse_invocation(args(&[
+            "serve",
+            "--addr",
+            "0.0.0.0:9000",
+            "--ticks",
+            "5000",
+        ]))
+        .unwrap();
+        assert!(matches!(
+            invocation,
+            Invocation::Serve(ServeOptions { ref addr, max_ticks: 5000, .. }) if addr == "0.0.0.0:9000"
+        ));
+        assert!(parse_invocation(args(&["serve", "--ticks", "0"])).is_err());
+        assert!(parse_invocation(args(&["serve", "bot.robo"])).is_err());
+    }
+
+    #[cfg(all(feature = "render", feature = "net"))]
+    #[test]
+    fn test_webhook() {
+        let options = parse_args(args(&[
+            "--headless",
+            "--webhook",
+            "https://example.com/hook",
+            "--save-replay",
+            "match.json",
+            "--replay-url",
+            "https://example.com/replays",
+        ]))
+        .unwrap();
+        assert_eq!(options.webhook.as_deref(), Some("https://example.com/hook"));
+        assert!(parse_args(args(&["--webhook", "https://example.com/hook"])).is_err());
+        assert!(
+            parse_args(args(&[
+                "--headless",
+                "--replay-url",
+                "https://example.com/replays"
+            ]))
+            .is_err()
+        );
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_history() {
+        let invocation = parse_invocation(args(&["history"])).unwrap();
+        let Invocation::History(options) = invocation else {
+            panic!("expected history, got {:?}", invocation);
+        };
+        assert_eq!(options.db, Dirs::locate().history_db());
+        assert_eq!(options.limit, DEFAULT_HISTORY_LIMIT);
+        let invocation =
+            parse_invocation(args(&["history", "--bot", "hunter", "--vs", "patrol"])).unwrap();
+        assert!(matches!(
+            invocation,
+            Invocation::History(HistoryOptions { opponent: Some(ref o), .. }) if o == "patrol"
+        ));
+        assert!(parse_invocation(args(&["history", "--vs", "patrol"])).is_err());
+        assert!(parse_invocation(args(&["history", "--limit", "0"])).is_err());
+        let options = parse_args(args(&["--headless", "--history", "league.db"])).unwrap();
+        assert_eq!(options.history, Some(PathBuf::from("league.db")));
+        assert!(parse_args(args(&["--history", "league.db"])).is_err());
+    }
+
+    #[test]
+    fn test_graph() {
+        let invocation = parse_invocation(args(&["graph", "bot.robo"])).unwrap();
+        assert_eq!(
+            invocation,
+            Invocation::Graph(GraphOptions {
+                bot: Entrant::Script(PathBuf::from("bot.robo")),
+                format: GraphFormat::Dot,
+                output: None,
+            })
+        );
+        let invocation = parse_invocation(args(&[
+            "graph",
+            "builtin:tracker",
+            "--format",
+            "mermaid",
+            "--output",
+            "tracker.mmd",
+        ]))
+        .unwrap();
+        let Invocation::Graph(options) = invocation else {
+            panic!("expected graph, got {:?}", invocation);
+        };
+        assert_eq!(options.format, GraphFormat::Mermaid);
+        assert_eq!(options.output, Some(PathBuf::from("tracker.mmd")));
+        assert!(parse_invocation(args(&["graph"])).is_err());
+        assert!(parse_invocation(args(&["graph", "human"])).is_err());
+        assert!(parse_invocation(args(&["graph", "bot.robo", "--format", "svg"])).is_err());
+    }
+
+    #[test]
+    fn test_test() {
+        let invocation = parse_invocation(args(&["test", "bot.robo"])).unwrap();
+        assert_eq!(
+            invocation,
+            Invocation::Test(TestOptions {
+                bot: Entrant::Script(PathBuf::from("bot.robo")),
+                config: None,
+                overrides: Vec::new(),
+            })
+        );
+        let invocation = parse_invocation(args(&[
+            "test",
+            "bot.robo",
+            "--config",
+            "game.toml",
+            "--set",
+            "move_speed=2",
+        ]))
+        .unwrap();
+        let Invocation::Test(options) = invocation else {
+            panic!("expected test, got {:?}", invocation);
+        };
+        assert_eq!(options.config, Some(PathBuf::from("game.toml")));
+        assert_eq!(options.overrides, vec!["move_speed=2".to_string()]);
+        assert!(parse_invocation(args(&["test"])).is_err());
+        assert!(parse_invocation(args(&["test", "human"])).is_err());
+        assert!(parse_invocation(args(&["test", "bot.robo", "--set", "fast"])).is_err());
+    }
+
+    #[test]
+    fn test_docs() {
+        let invocation = parse_invocation(args(&["docs"])).unwrap();
+        assert_eq!(invocation, Invocation::Docs(DocsOptions::default()));
+        let invocation = parse_invocation(args(&[
+            "docs",
+            "--format",
+            "json",
+            "--output",
+            "commands.json",
+        ]))
+        .unwrap();
+        assert_eq!(
+            invocation,
+            Invocation::Docs(DocsOptions {
+                format: DocsFormat::Json,
+                output: Some(PathBuf::from("commands.json")),
+            })
+        );
+        assert!(parse_invocation(args(&["docs", "--format", "html"])).is_err());
+        assert!(parse_invocation(args(&["docs", "bot.robo"])).is_err());
+    }
+
+    #[test]
+    fn test_tutorial() {
+        assert_eq!(
+            parse_invocation(args(&["tutorial"])).unwrap(),
+            Invocation::Tutorial
+        );
+        let invocation =
+            parse_invocation(args(&["tutorial", "1", "bot.robo", "--render", "tui"])).unwrap();
+        let Invocation::Match(options) = invocation else {
+            panic!("a lesson plays a match");
+        };
+        assert_eq!(options.lesson.as_deref(), Some("waypoint"));
+        assert!(parse_invocation(args(&["tutorial", "boss", "bot.robo"])).is_err());
+        assert!(parse_invocation(args(&["tutorial", "patrol"])).is_err());
+    }
+
+    #[test]
+    fn test_arena() {
+        assert_eq!(parse_args(args(&[])).unwrap().arena, None);
+        let options = parse_args(args(&["fight", "a.robo", "--arena", "pit.toml"])).unwrap();
+        assert_eq!(options.arena, Some(PathBuf::from("pit.toml")));
+        assert!(parse_args(args(&["fight", "a.robo", "--arena"])).is_err());
+        let scenario = ["scenario", "escape.toml", "bot.robo", "--arena", "pit.toml"];
+        assert!(parse_invocation(args(&scenario)).is_err());
+    }
+
+    #[test]
+    fn test_scenario() {
+        let invocation =
+            parse_invocation(args(&["scenario", "escape.toml", "bot.robo", "--headless"])).unwrap();
+        let Invocation::Match(options) = invocation else {
+            panic!("scenario plays a match");
+        };
+        assert_eq!(options.scenario, Some(PathBuf::from("escape.toml")));
+        assert_eq!(
+            options.entrants,
+            vec![Entrant::Script(PathBuf::from("bot.robo"))]
+        );
+        assert_eq!(options.render, RenderMode::Headless);
+        assert!(parse_invocation(args(&["scenario", "escape.toml"])).is_err());
+        assert!(
+            parse_invocation(args(&[
+                "scenario",
+                "escape.toml",
+                "bot.robo",
+                "--headless",
+                "--series",
+                "3"
+            ]))
+            .is_err()
+        );
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_profile() {
+        let options = parse_args(args(&["--render", "tui", "--profile", "profile.txt"])).unwrap();
+        assert_eq!(options.profile, Some(PathBuf::from("profile.txt")));
+        assert!(parse_args(args(&["--profile", "profile.txt"])).is_err());
+        assert!(
+            parse_args(args(&[
+                "--headless",
+                "--series",
+                "3",
+                "--profile",
+                "profile.txt"
+            ]))
+            .is_err()
+        );
+        assert!(parse_args(args(&["--headless", "--profile"])).is_err());
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_coverage() {
+        let options = parse_args(args(&["--headless", "--coverage", "coverage.txt"])).unwrap();
+        assert_eq!(options.coverage, Some(PathBuf::from("coverage.txt")));
+        assert!(parse_args(args(&["--coverage", "coverage.txt"])).is_err());
+        let invocation = parse_invocation(args(&[
+            "tournament",
+            "a.robo",
+            "b.robo",
+            "--coverage",
+            "coverage",
+        ]))
+        .unwrap();
+        assert!(matches!(
+            invocation,
+            Invocation::Tournament(TournamentOptions { coverage: Some(ref dir), .. })
+                if dir == Path::new("coverage")
+        ));
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_series() {
+        let options = parse_args(args(&["--series", "5", "--headless"])).unwrap();
+        assert_eq!(options.series, Some(5));
+        assert!(parse_args(args(&["--series", "5"])).is_err());
+        assert!(parse_args(args(&["--headless", "--series", "0"])).is_err());
+        assert!(
+            parse_args(args(&[
+                "--headless",
+                "--series",
+                "3",
+                "--result-json",
+                "out.json"
+            ]))
+            .is_err()
+        );
+    }
+}
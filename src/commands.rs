@@ -0,0 +1,1146 @@
+// The command table: every command of the DSL, with its arguments, what it
+// costs to run, how it is parsed and what it compiles to. The tokenizer takes
+// its keywords from it, the parser looks commands up in it by name and the
+// translator compiles each command with its entry, so a new command is a
+// `Command` variant and an entry here. `robot-battle docs` prints the table as
+// the language reference. The tests run each command's example through the
+// parser and translator and check the tick costs given here against the ticks
+// the VM actually spends.
+
+use serde::Serialize;
+
+use crate::ast::{
+    Command, Emitter, Emote, Instruction, MathOp, Operand, Section, is_read_only_register,
+};
+use crate::error::{CompileError, ParseError};
+use crate::loadout::Loadout;
+use crate::parser::{Cursor, Fault, is_register_name};
+use crate::registers::Reg;
+use crate::tokenizer::Token;
+
+/// What an argument of a command is written as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgKind {
+    /// A whole number.
+    Number,
+    /// `body`, `turret` or `scanner`.
+    Section,
+    /// One of a fixed set of words.
+    Choice(&'static [&'static str]),
+    /// A register name.
+    Register,
+    /// A quoted string.
+    Text,
+    /// The name of an emote.
+    Emote,
+    /// An integer expression over registers and constants.
+    Expr,
+    /// A `{ ... }` or `do ... end` block, or a single command.
+    Body,
+    /// `{ <x> <y> ... }`: waypoints, one pair of numbers each.
+    Waypoints,
+    /// `<stat> <level>` pairs, for stats out of `armor`, `speed`, `weapon`
+    /// and `scanner`.
+    Stats,
+}
+
+impl ArgKind {
+    /// The kind as shown in the reference.
+    pub fn describe(self) -> String {
+        match self {
+            ArgKind::Number => "number".to_string(),
+            ArgKind::Section => "`body`, `turret` or `scanner`".to_string(),
+            ArgKind::Choice(words) => words
+                .iter()
+                .map(|w| format!("`{}`", w))
+                .collect::<Vec<_>>()
+                .join(" or "),
+            ArgKind::Register => "register".to_string(),
+            ArgKind::Text => "quoted string".to_string(),
+            ArgKind::Emote => Emote::ALL
+                .iter()
+                .map(|e| format!("`{}`", e.name()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            ArgKind::Expr => "expression".to_string(),
+            ArgKind::Body => "block or single command".to_string(),
+            ArgKind::Waypoints => "`{ x y ... }`".to_string(),
+            ArgKind::Stats => "`<stat> <level>` pairs".to_string(),
+        }
+    }
+}
+
+/// An argument of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    /// Whether the argument can be left out.
+    pub optional: bool,
+}
+
+const fn arg(name: &'static str, kind: ArgKind) -> ArgSpec {
+    ArgSpec {
+        name,
+        kind,
+        optional: false,
+    }
+}
+
+const fn optional(name: &'static str, kind: ArgKind) -> ArgSpec {
+    ArgSpec {
+        name,
+        kind,
+        optional: true,
+    }
+}
+
+/// How many ticks a command takes. The VM runs one instruction per tick, so
+/// this is how many instructions it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ticks {
+    /// Always this many.
+    Fixed(u32),
+    /// `base + per × <arg>`, for the absolute value of the argument; none at
+    /// all when the argument is 0.
+    PerUnit {
+        base: u32,
+        per: u32,
+        arg: &'static str,
+    },
+    /// Depends on the world or on the commands inside.
+    Varies(&'static str),
+}
+
+impl Ticks {
+    /// The cost as shown in the reference.
+    pub fn describe(self) -> String {
+        match self {
+            Ticks::Fixed(n) => n.to_string(),
+            Ticks::PerUnit { base, per, arg } => format!("{} + {} × {}", base, per, arg),
+            Ticks::Varies(text) => text.to_string(),
+        }
+    }
+}
+
+/// Reads a command's arguments, which follow its name.
+pub(crate) type ParseFn = fn(&mut Cursor) -> Result<Command, Fault>;
+
+/// Emits the instructions a command compiles to.
+pub(crate) type CompileFn = fn(&Command, &mut Emitter);
+
+/// One command of the DSL.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CommandSpec {
+    /// The words the command is written with, like `fire lead`; the first is
+    /// its keyword.
+    pub name: &'static str,
+    pub args: &'static [ArgSpec],
+    pub summary: &'static str,
+    pub ticks: Ticks,
+    /// A use of the command that parses.
+    pub example: &'static str,
+    #[serde(skip)]
+    pub(crate) parse: ParseFn,
+    /// Called with commands this entry parsed.
+    #[serde(skip)]
+    pub(crate) compile: CompileFn,
+}
+
+impl CommandSpec {
+    /// The keyword the command starts with.
+    pub fn keyword(&self) -> &'static str {
+        self.name.split(' ').next().unwrap_or(self.name)
+    }
+
+    /// Number of words in the name.
+    pub fn words(&self) -> usize {
+        self.name.split(' ').count()
+    }
+
+    /// Fewest and most arguments the command takes.
+    pub fn arity(&self) -> (usize, usize) {
+        let required = self.args.iter().filter(|a| !a.optional).count();
+        (required, self.args.len())
+    }
+
+    /// How the command is written: `rotate <section> <degrees>`, with optional
+    /// arguments in brackets.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for arg in self.args {
+            let text = match arg.kind {
+                ArgKind::Waypoints => "{ <x> <y> ... }".to_string(),
+                ArgKind::Stats => "<stat> <level> ...".to_string(),
+                // An optional block is the one `else` runs
+                ArgKind::Body if arg.optional => format!("else <{}>", arg.name),
+                _ => format!("<{}>", arg.name),
+            };
+            if arg.optional {
+                usage += &format!(" [{}]", text);
+            } else {
+                usage += &format!(" {}", text);
+            }
+        }
+        if self.name == "let" {
+            // The only command with punctuation between its arguments
+            usage = usage.replacen("> <", "> = <", 1);
+        }
+        usage
+    }
+}
+
+/// Every command, in the order the reference lists them.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "move",
+        args: &[
+            arg("direction", ArgKind::Choice(&["forward"])),
+            arg("distance", ArgKind::Number),
+        ],
+        summary: "Drive forward one step per distance unit; zero or less does nothing.",
+        ticks: Ticks::PerUnit {
+            base: 1,
+            per: 4,
+            arg: "distance",
+        },
+        example: "move forward 3",
+        parse: parse_move,
+        compile: compile_move,
+    },
+    CommandSpec {
+        name: "rotate",
+        args: &[
+            arg("section", ArgKind::Section),
+            arg("degrees", ArgKind::Number),
+        ],
+        summary: "Turn the body, turret or scanner, counterclockwise if positive.",
+        ticks: Ticks::PerUnit {
+            base: 1,
+            per: 4,
+            arg: "degrees",
+        },
+        example: "rotate turret -5",
+        parse: parse_rotate,
+        compile: compile_rotate,
+    },
+    CommandSpec {
+        name: "goto",
+        args: &[arg("x", ArgKind::Number), arg("y", ArgKind::Number)],
+        summary: "Turn toward a point of the arena and drive there.",
+        ticks: Ticks::Varies("until the robot arrives or is blocked"),
+        example: "goto 200 150",
+        parse: parse_goto,
+        compile: compile_goto,
+    },
+    CommandSpec {
+        name: "path",
+        args: &[arg("waypoints", ArgKind::Waypoints)],
+        summary: "Set the waypoints `follow path` drives through.",
+        ticks: Ticks::Fixed(1),
+        example: "path { 60 60 340 60 }",
+        parse: parse_path,
+        compile: compile_path,
+    },
+    CommandSpec {
+        name: "follow path",
+        args: &[],
+        summary: "Drive to the current waypoint, then make the next one current.",
+        ticks: Ticks::Varies("until the robot reaches the waypoint; 1 without a path"),
+        example: "follow path",
+        parse: |_| Ok(Command::FollowPath),
+        compile: |_, out| out.push(Instruction::FollowPath),
+    },
+    CommandSpec {
+        name: "scan",
+        args: &[],
+        summary: "Look along the scanner for the nearest robot or wreck.",
+        ticks: Ticks::Fixed(1),
+        example: "scan",
+        parse: |_| Ok(Command::Scan),
+        compile: |_, out| out.push(Instruction::Scan),
+    },
+    CommandSpec {
+        name: "scan_all",
+        args: &[],
+        summary: "Count the living enemies and coarsely locate the nearest.",
+        ticks: Ticks::Fixed(1),
+        example: "scan_all",
+        parse: |_| Ok(Command::ScanAll),
+        compile: |_, out| out.push(Instruction::ScanAll),
+    },
+    CommandSpec {
+        name: "sweep",
+        args: &[arg("arc", ArgKind::Number), arg("step", ArgKind::Number)],
+        summary: "Scan, then turn the scanner by step and scan again through arc degrees.",
+        ticks: Ticks::Varies("2 + (5 + 4 × step) × (arc / step); 1 if step is wider than arc"),
+        example: "sweep 90 15",
+        parse: parse_sweep,
+        compile: compile_sweep,
+    },
+    CommandSpec {
+        name: "aim at scanned",
+        args: &[],
+        summary: "Turn the turret toward the latest scan contact.",
+        ticks: Ticks::Varies("until the turret points at the contact; 1 without one"),
+        example: "aim at scanned",
+        parse: |_| Ok(Command::AimAtScanned),
+        compile: compile_aim_at_scanned,
+    },
+    CommandSpec {
+        name: "fire",
+        args: &[],
+        summary: "Fire a projectile along the turret.",
+        ticks: Ticks::Fixed(1),
+        example: "fire",
+        parse: |_| Ok(Command::Fire),
+        compile: |_, out| out.push(Instruction::Fire),
+    },
+    CommandSpec {
+        name: "fire lead",
+        args: &[],
+        summary: "Aim ahead of the latest contact along its estimated velocity, then fire.",
+        ticks: Ticks::Varies("until the turret leads the contact, then 1"),
+        example: "fire lead",
+        parse: |_| Ok(Command::FireLead),
+        compile: compile_fire_lead,
+    },
+    CommandSpec {
+        name: "fire ricochet",
+        args: &[],
+        summary: "Fire a shot that bounces off walls and obstacles.",
+        ticks: Ticks::Fixed(1),
+        example: "fire ricochet",
+        parse: |_| Ok(Command::FireRicochet),
+        compile: |_, out| out.push(Instruction::FireRicochet),
+    },
+    CommandSpec {
+        name: "fire laser",
+        args: &[],
+        summary: "Fire the laser, which hits at once and costs energy.",
+        ticks: Ticks::Fixed(1),
+        example: "fire laser",
+        parse: |_| Ok(Command::FireLaser),
+        compile: |_, out| out.push(Instruction::FireLaser),
+    },
+    CommandSpec {
+        name: "fire emp",
+        args: &[],
+        summary: "Fire an EMP charge, which stops the program of the enemy it hits.",
+        ticks: Ticks::Fixed(1),
+        example: "fire emp",
+        parse: |_| Ok(Command::FireEmp),
+        compile: |_, out| out.push(Instruction::FireEmp),
+    },
+    CommandSpec {
+        name: "selfdestruct",
+        args: &[],
+        summary: "Arm the self-destruct fuse.",
+        ticks: Ticks::Fixed(1),
+        example: "selfdestruct",
+        parse: |_| Ok(Command::SelfDestruct),
+        compile: |_, out| out.push(Instruction::SelfDestruct),
+    },
+    CommandSpec {
+        name: "smoke",
+        args: &[],
+        summary: "Drop a smoke cloud that blocks scans.",
+        ticks: Ticks::Fixed(1),
+        example: "smoke",
+        parse: |_| Ok(Command::Smoke),
+        compile: |_, out| out.push(Instruction::Smoke),
+    },
+    CommandSpec {
+        name: "repair",
+        args: &[
+            optional("ally", ArgKind::Choice(&["ally"])),
+            optional("ticks", ArgKind::Number),
+        ],
+        summary: "Repair the robot, or with `ally` its nearest damaged teammate, for 1 or ticks ticks.",
+        ticks: Ticks::PerUnit {
+            base: 1,
+            per: 4,
+            arg: "ticks",
+        },
+        example: "repair ally 2",
+        parse: parse_repair,
+        compile: compile_repair,
+    },
+    CommandSpec {
+        name: "build",
+        args: &[arg("stats", ArgKind::Stats)],
+        summary: "Choose the robot's stat levels; only allowed as the first command.",
+        ticks: Ticks::Fixed(0),
+        example: "build armor 3 speed 2",
+        parse: parse_build,
+        // Applied to the robot when it is set up, not executed
+        compile: |_, _| {},
+    },
+    CommandSpec {
+        name: "cloak",
+        args: &[],
+        summary: "Turn on the cloak, hiding the robot from scans.",
+        ticks: Ticks::Fixed(1),
+        example: "cloak",
+        parse: |_| Ok(Command::Cloak),
+        compile: |_, out| out.push(Instruction::Cloak),
+    },
+    CommandSpec {
+        name: "uncloak",
+        args: &[],
+        summary: "Turn off the cloak.",
+        ticks: Ticks::Fixed(1),
+        example: "uncloak",
+        parse: |_| Ok(Command::Uncloak),
+        compile: |_, out| out.push(Instruction::Uncloak),
+    },
+    CommandSpec {
+        name: "defense on",
+        args: &[],
+        summary: "Turn on point defense, shooting down enemy projectiles that come close.",
+        ticks: Ticks::Fixed(1),
+        example: "defense on",
+        parse: |_| Ok(Command::PointDefense { on: true }),
+        compile: |_, out| out.push(Instruction::PointDefense { on: true }),
+    },
+    CommandSpec {
+        name: "defense off",
+        args: &[],
+        summary: "Turn off point defense.",
+        ticks: Ticks::Fixed(1),
+        example: "defense off",
+        parse: |_| Ok(Command::PointDefense { on: false }),
+        compile: |_, out| out.push(Instruction::PointDefense { on: false }),
+    },
+    CommandSpec {
+        name: "hook",
+        args: &[],
+        summary: "Throw the grappling hook; its cable reels the robot to a wall or tows an enemy in.",
+        ticks: Ticks::Fixed(1),
+        example: "hook",
+        parse: |_| Ok(Command::Hook),
+        compile: |_, out| out.push(Instruction::Hook),
+    },
+    CommandSpec {
+        name: "unhook",
+        args: &[],
+        summary: "Let go of the grappling hook.",
+        ticks: Ticks::Fixed(1),
+        example: "unhook",
+        parse: |_| Ok(Command::Unhook),
+        compile: |_, out| out.push(Instruction::Unhook),
+    },
+    CommandSpec {
+        name: "say",
+        args: &[arg("text", ArgKind::Text)],
+        summary: "Show text over the robot and log it, for debugging.",
+        ticks: Ticks::Fixed(1),
+        example: "say \"reloading\"",
+        parse: parse_say,
+        compile: compile_say,
+    },
+    CommandSpec {
+        name: "emote",
+        args: &[arg("emote", ArgKind::Emote)],
+        summary: "Show a predefined face over the robot.",
+        ticks: Ticks::Fixed(1),
+        example: "emote happy",
+        parse: parse_emote,
+        compile: compile_emote,
+    },
+    CommandSpec {
+        name: "persist store",
+        args: &[arg("register", ArgKind::Register)],
+        summary: "Save a register to the robot's memory for the rest of the series.",
+        ticks: Ticks::Fixed(1),
+        example: "persist store hits",
+        parse: parse_persist_store,
+        compile: compile_persist_store,
+    },
+    CommandSpec {
+        name: "persist load",
+        args: &[arg("register", ArgKind::Register)],
+        summary: "Restore a register from the robot's series memory.",
+        ticks: Ticks::Fixed(1),
+        example: "persist load hits",
+        parse: parse_persist_load,
+        compile: compile_persist_load,
+    },
+    CommandSpec {
+        name: "let",
+        args: &[
+            arg("register", ArgKind::Register),
+            arg("value", ArgKind::Expr),
+        ],
+        summary: "Evaluate an expression into a register.",
+        ticks: Ticks::Varies("1 per operator, function or history read, at least 1"),
+        example: "let range = scan * 2",
+        parse: parse_let,
+        compile: compile_let,
+    },
+    CommandSpec {
+        name: "if",
+        args: &[
+            arg("condition", ArgKind::Expr),
+            arg("then", ArgKind::Body),
+            optional("else", ArgKind::Body),
+        ],
+        summary: "Run the block if the condition is not zero, else the `else` block.",
+        ticks: Ticks::Varies(
+            "the condition, 2 to test it and 1 to leave (2 with an else), besides the block run",
+        ),
+        example: "if scan > 0 { fire } else { rotate scanner 10 }",
+        parse: parse_if,
+        compile: compile_if,
+    },
+    CommandSpec {
+        name: "loop",
+        args: &[arg("body", ArgKind::Body)],
+        summary: "Run the block forever.",
+        ticks: Ticks::Varies("2 per pass, besides the block"),
+        example: "loop { scan; fire }",
+        parse: parse_loop,
+        compile: compile_loop,
+    },
+];
+
+/// The entry called `name`.
+///
+/// # Panics
+///
+/// If there is none; every `Command` has an entry under `Command::name`.
+pub fn spec(name: &str) -> &'static CommandSpec {
+    COMMANDS
+        .iter()
+        .find(|c| c.name == name)
+        .unwrap_or_else(|| panic!("no command table entry for `{}`", name))
+}
+
+/// The command whose name `tokens` start with, the longest if several do
+/// (`fire lead` rather than `fire`). If none does, the error is how many of
+/// the tokens fit the name of a command that starts with the same keyword,
+/// which is 0 if the first is not a command keyword.
+pub(crate) fn lookup(tokens: &[Token]) -> Result<&'static CommandSpec, usize> {
+    let Some(Token::Keyword(keyword)) = tokens.first() else {
+        return Err(0);
+    };
+    let mut found: Option<&'static CommandSpec> = None;
+    let mut matched = 0;
+    for spec in COMMANDS.iter().filter(|c| c.keyword() == keyword) {
+        let fits = spec
+            .name
+            .split(' ')
+            .zip(tokens)
+            .take_while(|(word, token)| {
+                matches!(token, Token::Keyword(w) | Token::Identifier(w) if w == word)
+            })
+            .count();
+        if fits == spec.words() && found.is_none_or(|f| f.words() < fits) {
+            found = Some(spec);
+        }
+        matched = matched.max(fits);
+    }
+    found.ok_or(matched)
+}
+
+/// Compile `command` with its entry, or into the instruction that runs a
+/// plugin's command.
+pub(crate) fn compile(command: &Command, out: &mut Emitter) {
+    match command {
+        Command::Plugin { name, args } => {
+            let args = out.operands(args);
+            out.push(Instruction::Plugin {
+                name: name.clone(),
+                args,
+            });
+        }
+        _ => (spec(command.name()).compile)(command, out),
+    }
+}
+
+/// Words the tokenizer reads as keywords besides the command keywords.
+pub const RESERVED: &[&str] = &["else", "while", "body", "turret", "scanner"];
+
+/// Whether `word` is tokenized as a keyword.
+pub fn is_keyword(word: &str) -> bool {
+    RESERVED.contains(&word) || COMMANDS.iter().any(|c| c.keyword() == word)
+}
+
+/// Output format of the command reference.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocsFormat {
+    #[default]
+    Markdown,
+    Json,
+}
+
+impl DocsFormat {
+    /// Parse a `--format` value.
+    pub fn from_name(name: &str) -> Option<DocsFormat> {
+        match name {
+            "markdown" => Some(DocsFormat::Markdown),
+            "json" => Some(DocsFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The command reference in `format`.
+pub fn reference(format: DocsFormat) -> String {
+    match format {
+        DocsFormat::Markdown => markdown(),
+        DocsFormat::Json => {
+            let mut json = serde_json::to_string_pretty(COMMANDS).expect("commands serialize");
+            json.push('\n');
+            json
+        }
+    }
+}
+
+/// The command reference as Markdown: a summary table, then each command with
+/// its arguments.
+fn markdown() -> String {
+    let mut out = "# Command reference\n\n".to_string();
+    out += "Generated by `robot-battle docs` from the command table the parser \
+             and translator use. Ticks count VM instructions, one per tick.\n\n";
+    out += "| Command | Ticks | Description |\n|---|---|---|\n";
+    for command in COMMANDS {
+        out += &format!(
+            "| `{}` | {} | {} |\n",
+            command.usage(),
+            command.ticks.describe(),
+            command.summary
+        );
+    }
+    for command in COMMANDS {
+        out += &format!("\n## `{}`\n\n{}\n\n", command.name, command.summary);
+        out += &format!("```text\n{}\n```\n\n", command.example);
+        for arg in command.args {
+            let optional = if arg.optional { ", optional" } else { "" };
+            out += &format!("- `{}`: {}{}\n", arg.name, arg.kind.describe(), optional);
+        }
+        out += &format!("- Ticks: {}\n", command.ticks.describe());
+    }
+    out
+}
+
+fn unexpected(token: &Token) -> Fault {
+    ParseError::UnexpectedToken(token.clone()).into()
+}
+
+fn parse_move(input: &mut Cursor) -> Result<Command, Fault> {
+    let direction = match input.next()? {
+        Token::Identifier(dir) => dir.clone(),
+        tok => return Err(unexpected(tok)),
+    };
+    let distance = input.number()?;
+    Ok(Command::Move {
+        direction,
+        distance,
+    })
+}
+
+fn compile_move(command: &Command, out: &mut Emitter) {
+    let Command::Move {
+        direction,
+        distance,
+    } = command
+    else {
+        unreachable!()
+    };
+    // Only "forward" moves for now; a zero or negative distance is a no-op
+    if direction == "forward" && *distance > 0 {
+        let n = out.label();
+        let step = Instruction::MoveForward;
+        out.repeat(
+            &format!("mv{}", n),
+            format!("move_loop{}", n),
+            *distance,
+            step,
+        );
+    }
+}
+
+fn parse_rotate(input: &mut Cursor) -> Result<Command, Fault> {
+    let section = match input.next()? {
+        Token::Keyword(k) if k == "body" => Section::Body,
+        Token::Keyword(k) if k == "turret" => Section::Turret,
+        Token::Keyword(k) if k == "scanner" => Section::Scanner,
+        tok => return Err(unexpected(tok)),
+    };
+    let angle = input.number()?;
+    Ok(Command::Rotate { section, angle })
+}
+
+fn compile_rotate(command: &Command, out: &mut Emitter) {
+    let Command::Rotate { section, angle } = command else {
+        unreachable!()
+    };
+    if *angle == 0 {
+        return;
+    }
+    let step = match (section, *angle >= 0) {
+        (Section::Body, true) => Instruction::TurnLeft,
+        (Section::Body, false) => Instruction::TurnRight,
+        (Section::Turret, true) => Instruction::TurnTurretLeft,
+        (Section::Turret, false) => Instruction::TurnTurretRight,
+        (Section::Scanner, true) => Instruction::TurnScannerLeft,
+        (Section::Scanner, false) => Instruction::TurnScannerRight,
+    };
+    let n = out.label();
+    out.repeat(
+        &format!("rot{}", n),
+        format!("turn_loop{}", n),
+        angle.abs(),
+        step,
+    );
+}
+
+fn parse_goto(input: &mut Cursor) -> Result<Command, Fault> {
+    let x = input.number()?;
+    let y = input.number()?;
+    Ok(Command::GoTo { x, y })
+}
+
+fn compile_goto(command: &Command, out: &mut Emitter) {
+    let Command::GoTo { x, y } = command else {
+        unreachable!()
+    };
+    out.push(Instruction::GoTo { x: *x, y: *y });
+}
+
+fn parse_path(input: &mut Cursor) -> Result<Command, Fault> {
+    let Some(close) = input.open_block() else {
+        return Err(unexpected(input.next()?));
+    };
+    let mut waypoints = Vec::new();
+    loop {
+        match input.peek() {
+            Some(token) if *token == close => break,
+            None => return Err(ParseError::UnexpectedEOF.into()),
+            Some(_) => {}
+        }
+        let x = input.number()?;
+        let y = input.number()?;
+        waypoints.push((x, y));
+    }
+    input.advance();
+    Ok(Command::Path { waypoints })
+}
+
+fn compile_path(command: &Command, out: &mut Emitter) {
+    let Command::Path { waypoints } = command else {
+        unreachable!()
+    };
+    out.push(Instruction::SetPath {
+        waypoints: waypoints.clone(),
+    });
+}
+
+fn parse_sweep(input: &mut Cursor) -> Result<Command, Fault> {
+    let arc = input.number()?;
+    let step = input.number()?;
+    if step <= 0 {
+        return Err(unexpected(&Token::Number(step)));
+    }
+    Ok(Command::Sweep { arc, step })
+}
+
+fn compile_sweep(command: &Command, out: &mut Emitter) {
+    let Command::Sweep { arc, step } = command else {
+        unreachable!()
+    };
+    // Scan at the start, then after each whole step of the arc
+    out.push(Instruction::Scan);
+    let steps = if *step > 0 { arc.abs() / step } else { 0 };
+    if steps == 0 {
+        return;
+    }
+    let n = out.label();
+    let (reg, label) = (Reg::named(&format!("sw{}", n)), format!("sweep_loop{}", n));
+    let turn = if *arc > 0 {
+        Instruction::TurnScannerLeft
+    } else {
+        Instruction::TurnScannerRight
+    };
+    out.push(Instruction::LoadCounter { reg, value: steps });
+    out.push(Instruction::Label(label.clone()));
+    out.repeat(
+        &format!("swt{}", n),
+        format!("sweep_turn{}", n),
+        *step,
+        turn,
+    );
+    out.push(Instruction::Scan);
+    out.push(Instruction::Dec { reg });
+    out.push(Instruction::Jnz { reg, label });
+}
+
+fn compile_aim_at_scanned(_: &Command, out: &mut Emitter) {
+    out.push(Instruction::TurnTo {
+        section: Section::Turret,
+        target: Operand::Reg(Reg::SCAN_BEARING),
+    });
+}
+
+fn compile_fire_lead(_: &Command, out: &mut Emitter) {
+    out.push(Instruction::AimLead);
+    out.push(Instruction::Fire);
+}
+
+fn parse_repair(input: &mut Cursor) -> Result<Command, Fault> {
+    let ally = matches!(input.peek(), Some(Token::Identifier(w)) if w == "ally");
+    if ally {
+        input.advance();
+    }
+    let ticks = match input.peek() {
+        Some(Token::Number(_)) => input.number()?,
+        _ => 1,
+    };
+    Ok(Command::Repair { ally, ticks })
+}
+
+fn compile_repair(command: &Command, out: &mut Emitter) {
+    let Command::Repair { ally, ticks } = command else {
+        unreachable!()
+    };
+    if *ticks <= 0 {
+        return;
+    }
+    let step = if *ally {
+        Instruction::RepairAlly
+    } else {
+        Instruction::Repair
+    };
+    let n = out.label();
+    out.repeat(
+        &format!("rep{}", n),
+        format!("repair_loop{}", n),
+        *ticks,
+        step,
+    );
+}
+
+fn parse_build(input: &mut Cursor) -> Result<Command, Fault> {
+    // Stats left out keep their base level
+    let mut loadout = Loadout::default();
+    while let Some(Token::Identifier(name) | Token::Keyword(name)) = input.peek()
+        && let Some(level) = loadout.stat_mut(name)
+    {
+        input.advance();
+        let value = input.number()?;
+        *level = u32::try_from(value).map_err(|_| unexpected(&Token::Number(value)))?;
+    }
+    // Anything else on the line would be read as the next command
+    if let Some(Token::Identifier(name)) = input.peek() {
+        input.advance();
+        return Err(ParseError::UnknownStat(name.clone()).into());
+    }
+    Ok(Command::Build(loadout))
+}
+
+fn parse_say(input: &mut Cursor) -> Result<Command, Fault> {
+    match input.next()? {
+        Token::Str(text) => Ok(Command::Say { text: text.clone() }),
+        tok => Err(unexpected(tok)),
+    }
+}
+
+fn compile_say(command: &Command, out: &mut Emitter) {
+    let Command::Say { text } = command else {
+        unreachable!()
+    };
+    out.push(Instruction::Say { text: text.clone() });
+}
+
+fn parse_emote(input: &mut Cursor) -> Result<Command, Fault> {
+    match input.next()? {
+        Token::Identifier(name) => Emote::from_name(name)
+            .map(Command::Emote)
+            .ok_or_else(|| ParseError::UnknownEmote(name.clone()).into()),
+        tok => Err(unexpected(tok)),
+    }
+}
+
+fn compile_emote(command: &Command, out: &mut Emitter) {
+    let Command::Emote(emote) = command else {
+        unreachable!()
+    };
+    out.push(Instruction::Say {
+        text: emote.face().to_string(),
+    });
+}
+
+fn parse_persist_store(input: &mut Cursor) -> Result<Command, Fault> {
+    match input.next()? {
+        Token::Identifier(name) if is_register_name(name) => {
+            Ok(Command::PersistStore { name: name.clone() })
+        }
+        tok => Err(unexpected(tok)),
+    }
+}
+
+fn compile_persist_store(command: &Command, out: &mut Emitter) {
+    let Command::PersistStore { name } = command else {
+        unreachable!()
+    };
+    out.push(Instruction::PersistStore {
+        reg: Reg::named(name),
+    });
+}
+
+fn parse_persist_load(input: &mut Cursor) -> Result<Command, Fault> {
+    match input.next()? {
+        Token::Identifier(name) if is_read_only_register(name) => {
+            Err(CompileError::ReadOnlyRegister(name.clone()).into())
+        }
+        Token::Identifier(name) if is_register_name(name) => {
+            Ok(Command::PersistLoad { name: name.clone() })
+        }
+        tok => Err(unexpected(tok)),
+    }
+}
+
+fn compile_persist_load(command: &Command, out: &mut Emitter) {
+    let Command::PersistLoad { name } = command else {
+        unreachable!()
+    };
+    out.push(Instruction::PersistLoad {
+        reg: Reg::named(name),
+    });
+}
+
+fn parse_let(input: &mut Cursor) -> Result<Command, Fault> {
+    let name = match input.next()? {
+        Token::Identifier(name) if is_read_only_register(name) => {
+            return Err(CompileError::ReadOnlyRegister(name.clone()).into());
+        }
+        Token::Identifier(name) if is_register_name(name) => name.clone(),
+        Token::Keyword(k) if k == "scan" => {
+            return Err(CompileError::ReadOnlyRegister(k.clone()).into());
+        }
+        tok => return Err(unexpected(tok)),
+    };
+    match input.next()? {
+        Token::Identifier(eq) if eq == "=" => {}
+        tok => return Err(unexpected(tok)),
+    }
+    let expr = input.expr()?;
+    Ok(Command::Let { name, expr })
+}
+
+fn compile_let(command: &Command, out: &mut Emitter) {
+    let Command::Let { name, expr } = command else {
+        unreachable!()
+    };
+    out.expr(expr, Reg::named(name));
+}
+
+fn parse_if(input: &mut Cursor) -> Result<Command, Fault> {
+    // if <expr> <body> [else <body>], or if <expr> do ... [else ...] end
+    let condition = input.expr()?;
+    let else_keyword = Token::Keyword("else".to_string());
+    let (block, closer) = match input.open_block() {
+        // `else` can end a do block, and the else block runs to its `end`
+        Some(close) if close != Token::Symbol('}') => {
+            input.block(&[close, else_keyword.clone()])?
+        }
+        Some(close) => input.block(&[close])?,
+        None => (input.single_command()?, Token::Symbol(';')),
+    };
+    let else_block = if closer == else_keyword {
+        let end = Token::Identifier("end".to_string());
+        Some(input.block(&[end])?.0)
+    } else if input.peek() == Some(&else_keyword) {
+        input.advance();
+        Some(input.body()?)
+    } else {
+        None
+    };
+    Ok(Command::If {
+        condition,
+        block,
+        else_block,
+    })
+}
+
+fn compile_if(command: &Command, out: &mut Emitter) {
+    let Command::If {
+        condition,
+        block,
+        else_block,
+    } = command
+    else {
+        unreachable!()
+    };
+    // Jump past the block unless the condition holds
+    let n = out.label();
+    let reg = Reg::named(&format!("cond{}", n));
+    let else_label = format!("if_else{}", n);
+    let end_label = format!("if_end{}", n);
+    out.expr(condition, reg);
+    out.push(Instruction::Math {
+        op: MathOp::Eq,
+        dst: reg,
+        args: vec![Operand::Reg(reg), Operand::Const(0)],
+    });
+    let skip_to = if else_block.is_some() {
+        &else_label
+    } else {
+        &end_label
+    };
+    out.push(Instruction::Jnz {
+        reg,
+        label: skip_to.clone(),
+    });
+    out.block(block);
+    if let Some(else_block) = else_block {
+        out.push(Instruction::Jnz {
+            reg: Reg::ALWAYS,
+            label: end_label.clone(),
+        });
+        out.push(Instruction::Label(else_label));
+        out.block(else_block);
+    }
+    out.push(Instruction::Label(end_label));
+}
+
+fn parse_loop(input: &mut Cursor) -> Result<Command, Fault> {
+    let block = input.body()?;
+    Ok(Command::Loop { block })
+}
+
+fn compile_loop(command: &Command, out: &mut Emitter) {
+    let Command::Loop { block } = command else {
+        unreachable!()
+    };
+    // Infinite loop: label at start, jump to start at end
+    let label = format!("loop{}", out.label());
+    out.push(Instruction::Label(label.clone()));
+    out.block(block);
+    out.push(Instruction::Jnz {
+        reg: Reg::ALWAYS,
+        label,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Robot, translate_commands_to_instructions};
+    use crate::config::GameConfig;
+    use crate::parser::parse_script;
+    use crate::simulation::Simulation;
+
+    /// Ticks the robot spends running `script` to its end.
+    fn ticks_to_run(script: &str) -> u32 {
+        let commands = parse_script(script).unwrap_or_else(|e| panic!("`{}`: {}", script, e));
+        let program = translate_commands_to_instructions(&commands);
+        let end = program.len();
+        let robots = vec![
+            Robot::new(1, "runner", (100.0, 100.0), program),
+            Robot::new(2, "target", (300.0, 300.0), Vec::new()),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, Vec::new());
+        let mut ticks = 0;
+        while sim.robots[0].ip < end {
+            sim.step();
+            ticks += 1;
+            assert!(ticks < 10_000, "`{}` never finished", script);
+        }
+        ticks
+    }
+
+    #[test]
+    fn test_examples_parse_to_their_command() {
+        for spec in COMMANDS {
+            let commands =
+                parse_script(spec.example).unwrap_or_else(|e| panic!("`{}`: {}", spec.example, e));
+            assert_eq!(commands.len(), 1, "`{}`", spec.example);
+            assert_eq!(commands[0].name(), spec.name);
+            assert!(spec.example.starts_with(spec.name));
+        }
+        let mut names: Vec<&str> = COMMANDS.iter().map(|c| c.name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), COMMANDS.len());
+    }
+
+    #[test]
+    fn test_tick_costs_match_the_vm() {
+        for spec in COMMANDS {
+            let expected = match spec.ticks {
+                Ticks::Fixed(n) => n,
+                Ticks::PerUnit { base, per, arg } => {
+                    // The argument's value is the example's word in its place
+                    let position = spec.args.iter().position(|a| a.name == arg).unwrap();
+                    let words: Vec<&str> =
+                        spec.example[spec.name.len()..].split_whitespace().collect();
+                    let value: i32 = words[position].parse().unwrap();
+                    base + per * value.unsigned_abs()
+                }
+                Ticks::Varies(_) => continue,
+            };
+            assert_eq!(ticks_to_run(spec.example), expected, "`{}`", spec.example);
+        }
+        // Spelled out for the commands whose cost is described in words
+        assert_eq!(ticks_to_run("sweep 30 10"), 2 + (5 + 4 * 10) * 3);
+        assert_eq!(ticks_to_run("sweep 10 30"), 1);
+        assert_eq!(ticks_to_run("let n = 1"), 1);
+        assert_eq!(ticks_to_run("let n = max(scan, 2) + 1"), 2);
+        assert_eq!(ticks_to_run("if 1 > 0 { fire }"), 1 + 2 + 1 + 1);
+    }
+
+    #[test]
+    fn test_lookup_prefers_the_longest_name() {
+        let tokens = |script: &str| crate::tokenizer::tokenize_script(script);
+        let name = |script: &str| lookup(&tokens(script)).map(|spec| spec.name);
+        assert_eq!(name("fire"), Ok("fire"));
+        assert_eq!(name("fire lead"), Ok("fire lead"));
+        assert_eq!(name("fire scan"), Ok("fire"));
+        assert_eq!(name("follow path"), Ok("follow path"));
+        assert_eq!(name("aim at scanned"), Ok("aim at scanned"));
+        // Two words of `aim at scanned` fit
+        assert_eq!(name("aim at nothing"), Err(2));
+        assert_eq!(name("persist forget x"), Err(1));
+        assert_eq!(name("else"), Err(0));
+        assert_eq!(name("forward"), Err(0));
+        assert_eq!(name(""), Err(0));
+
+        assert_eq!(spec("rotate").arity(), (2, 2));
+        assert_eq!(spec("repair").arity(), (0, 2));
+        assert_eq!(spec("if").arity(), (2, 3));
+        assert_eq!(spec("fire laser").words(), 2);
+    }
+
+    #[test]
+    fn test_reference() {
+        assert!(is_keyword("scan_all") && is_keyword("persist") && is_keyword("turret"));
+        assert!(!is_keyword("lead") && !is_keyword("forward"));
+        let repair = COMMANDS.iter().find(|c| c.name == "repair").unwrap();
+        assert_eq!(repair.usage(), "repair [<ally>] [<ticks>]");
+        let let_spec = COMMANDS.iter().find(|c| c.name == "let").unwrap();
+        assert_eq!(let_spec.usage(), "let <register> = <value>");
+
+        let markdown = reference(DocsFormat::Markdown);
+        assert!(markdown.contains("| `rotate <section> <degrees>` | 1 + 4 × degrees | "));
+        assert!(markdown.contains("\n## `aim at scanned`\n"));
+        let json: serde_json::Value = serde_json::from_str(&reference(DocsFormat::Json)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), COMMANDS.len());
+        assert_eq!(json[1]["args"][0]["kind"], "section");
+        assert_eq!(json[0]["ticks"]["per_unit"]["per"], 4);
+        assert_eq!(DocsFormat::from_name("html"), None);
+    }
+
+    #[test]
+    fn test_shipped_reference_is_up_to_date() {
+        let shipped = std::fs::read_to_string("docs/commands.md").unwrap();
+        assert!(
+            shipped == reference(DocsFormat::Markdown),
+            "docs/commands.md is out of date; regenerate it with \
+             `cargo run -- docs --output docs/commands.md`"
+        );
+    }
+}
@@ -0,0 +1,675 @@
+// Game constants shared by the simulation and the renderers.
+// Defaults can be overridden from a TOML file (`--config`) and individual
+// `--set key=value` command-line options. Combat numbers live in their own
+// `[combat]` table, so a tournament can swap in a ruleset that only rebalances
+// damage and armor; its keys are set as `combat.<key>=value`. What robots start
+// a match with lives in the `[start]` table, which can also hand single bots a
+// handicap. How a match that runs out of time is decided on points lives in
+// the `[judging]` table.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{STARTING_AMMO, STARTING_ENERGY, STARTING_HEALTH};
+use crate::math::MathMode;
+
+/// Physics and combat constants for a match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    /// Size of the arena (in logical units).
+    pub arena_width: f32,
+    pub arena_height: f32,
+    /// Whether the arena has walls, or wraps around at its edges.
+    pub topology: Topology,
+    /// Size of each robot (in logical units).
+    pub robot_width: f32,
+    pub robot_height: f32,
+    /// Distance a robot moves per `move` step.
+    pub move_speed: f32,
+    /// Degrees a robot, turret or scanner turns per `rotate` step.
+    pub turn_rate: f32,
+    /// How close a robot must get to a `goto` target to count as arrived.
+    pub arrival_tolerance: f32,
+    /// Distance a projectile travels per tick.
+    pub projectile_speed: f32,
+    /// Ticks a projectile flies before it fizzles out.
+    pub projectile_lifetime: u32,
+    /// Speed at which a projectile hit knocks a robot along the shot's path.
+    pub projectile_knockback: f32,
+    /// Times a ricochet shot bounces off walls and obstacles before breaking.
+    pub ricochet_bounces: u32,
+    /// How close projectiles of different teams must pass to destroy each
+    /// other (0 = they pass through each other).
+    pub projectile_collision_radius: f32,
+    /// Reach of the laser beam.
+    pub laser_range: f32,
+    /// Energy used by each laser shot.
+    pub laser_energy_cost: i32,
+    /// Ticks a laser beam stays visible while it fades.
+    pub laser_beam_ticks: u32,
+    /// Radius of a smoke cloud.
+    pub smoke_radius: f32,
+    /// Ticks a smoke cloud lasts.
+    pub smoke_ticks: u32,
+    /// Energy used to drop a smoke cloud.
+    pub smoke_energy_cost: i32,
+    /// Energy a cloaked robot loses each tick.
+    pub cloak_energy_drain: i32,
+    /// Reach of the point-defense turret.
+    pub point_defense_radius: f32,
+    /// Energy used by each projectile the point-defense turret shoots down.
+    pub point_defense_energy_cost: i32,
+    /// Reach of the grappling hook.
+    pub hook_range: f32,
+    /// How much a grappling hook's cable shortens each tick.
+    pub hook_reel_speed: f32,
+    /// Energy used by each EMP charge.
+    pub emp_energy_cost: i32,
+    /// Ticks after firing an EMP charge before the robot can fire another.
+    pub emp_cooldown: u32,
+    /// Ticks an EMP hit stops the target's program.
+    pub emp_stun_ticks: u32,
+    /// Health restored by each tick of `repair` or `repair ally`.
+    pub repair_rate: i32,
+    /// Energy used by each tick of repairs.
+    pub repair_energy_cost: i32,
+    /// How close a teammate must be for `repair ally` to reach it.
+    pub repair_ally_range: f32,
+    /// Ticks between arming a self-destruct and the blast.
+    pub self_destruct_fuse: u32,
+    /// Reach of the self-destruct blast.
+    pub self_destruct_radius: f32,
+    /// Speed at which the blast knocks robots away from its center.
+    pub self_destruct_knockback: f32,
+    /// Fraction of its speed a knocked-back robot loses each tick.
+    pub knockback_friction: f32,
+    /// Radius of the king-of-the-hill control zone.
+    pub koth_zone_radius: f32,
+    /// Points a team needs to win king of the hill.
+    pub koth_target_score: u32,
+    /// Drones in the first survival wave; each later wave adds one more.
+    pub wave_base_drones: u32,
+    /// Health each survival drone and practice dummy spawns with.
+    pub drone_health: i32,
+    /// Ticks the wreck of a destroyed robot blocks the arena before it decays;
+    /// 0 leaves no wrecks.
+    pub wreck_lifetime: u32,
+    /// Ammunition and energy a wreck holds for the first robot to reach it.
+    pub salvage_ammo: i32,
+    pub salvage_energy: i32,
+    /// How close a robot's center must come to a wreck's to collect its salvage.
+    pub salvage_reach: f32,
+    /// Tick at which a match is stopped as a draw, whatever `--ticks` says;
+    /// 0 for no cap.
+    pub max_ticks: u64,
+    /// Ticks in which no robot takes damage before a deathmatch is a
+    /// stalemate; 0 never calls one.
+    pub stalemate_ticks: u64,
+    /// What a stalemate leads to.
+    pub stalemate: StalemateRule,
+    /// Ticks between the points of damage every robot takes in sudden death.
+    pub sudden_death_interval: u32,
+    /// Maximum distance the scanner can see.
+    pub scan_range: f32,
+    /// Half-width of the scanner cone, in degrees.
+    pub scan_half_angle: f32,
+    /// `scan_all` reports distances rounded up to a multiple of this.
+    pub scan_all_distance_step: f32,
+    /// `scan_all` reports bearings rounded to a multiple of this many degrees.
+    pub scan_all_bearing_step: f32,
+    /// Points a script's `build` header may spend on stat levels.
+    pub loadout_budget: u32,
+    /// Most values a robot may keep in its series memory with `persist store`.
+    pub max_memory_entries: usize,
+    /// Most instructions a script may compile to.
+    pub max_program_size: usize,
+    /// Most distinct variables a script may assign with `let`.
+    pub max_variables: usize,
+    /// How deeply blocks such as `loop` may nest.
+    pub max_nesting_depth: usize,
+    /// Trigonometry used by the simulation; `portable` makes replays agree
+    /// bit for bit across platforms.
+    pub math: MathMode,
+    /// Damage and armor numbers.
+    pub combat: CombatTable,
+    /// What robots start a match with.
+    pub start: StartingStats,
+    /// How a match stopped at its tick limit is decided.
+    pub judging: JudgingTable,
+}
+
+/// How the arena's edges behave.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+    /// Walls all around.
+    #[default]
+    Bounded,
+    /// No walls: whatever leaves the arena at one edge comes back in at the
+    /// opposite edge.
+    Torus,
+}
+
+/// How a stalemate ends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StalemateRule {
+    /// Every robot starts losing health, until one is left.
+    #[default]
+    SuddenDeath,
+    /// The match stops with no winner.
+    Draw,
+}
+
+/// Damage dealt by every source, and how armor changes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CombatTable {
+    /// Damage dealt by a projectile hit at weapon level 2.
+    pub projectile_damage: i32,
+    /// Projectile damage each weapon level adds or removes.
+    pub weapon_damage_per_level: i32,
+    /// Fraction of its damage a ricochet shot keeps after each bounce.
+    pub ricochet_damage_factor: f32,
+    /// Damage dealt by a laser hit.
+    pub laser_damage: i32,
+    /// Damage dealt to every robot caught in a self-destruct blast.
+    pub self_destruct_damage: i32,
+    /// Damage two enemy robots deal each other for every tick their hulls overlap.
+    pub ram_damage: i32,
+    /// Damage a knocked-back robot takes when it slams into a wall or obstacle.
+    pub wall_damage: i32,
+    /// Starting health each armor level adds or removes.
+    pub armor_health_per_level: i32,
+    /// Fraction of incoming damage each armor level above 2 absorbs; levels
+    /// below 2 take that much more. Every hit still deals at least 1.
+    pub armor_damage_reduction: f32,
+}
+
+/// Points a robot scores when a deathmatch or king of the hill is stopped at
+/// its tick limit with more than one team standing. The team with the most
+/// points wins; a tie is a draw.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JudgingTable {
+    /// Whether such a match is judged at all; if not, it is a draw.
+    pub enabled: bool,
+    /// Points per point of damage dealt to enemies.
+    pub damage: f32,
+    /// Points per enemy destroyed.
+    pub kills: f32,
+    /// Points for still standing at the end.
+    pub survival: f32,
+    /// Points per point of health left.
+    pub health: f32,
+    /// Points per point of the team's score in king of the hill.
+    pub objective: f32,
+}
+
+impl Default for JudgingTable {
+    fn default() -> Self {
+        JudgingTable {
+            enabled: true,
+            damage: 1.0,
+            kills: 5.0,
+            survival: 5.0,
+            health: 1.0,
+            objective: 0.1,
+        }
+    }
+}
+
+/// Health, energy, ammunition and speed a robot starts a match with, before
+/// its `build` header adjusts them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartingStats {
+    /// Starting and maximum health at armor level 2.
+    pub health: i32,
+    pub energy: i32,
+    pub ammo: i32,
+    /// Multiplier on the movement speed of the robot's speed level.
+    pub speed: f32,
+    /// Overrides for single bots, by name, such as `[start.bots.tracker]`.
+    /// Keys an override leaves out keep the values above.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub bots: BTreeMap<String, StatOverrides>,
+}
+
+/// Starting stats that differ for one bot.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub energy: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ammo: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+}
+
+impl Default for StartingStats {
+    fn default() -> Self {
+        StartingStats {
+            health: STARTING_HEALTH,
+            energy: STARTING_ENERGY,
+            ammo: STARTING_AMMO,
+            speed: 1.0,
+            bots: BTreeMap::new(),
+        }
+    }
+}
+
+impl StartingStats {
+    /// The stats the bot called `name` starts with, its overrides applied.
+    pub fn for_bot(&self, name: &str) -> StartingStats {
+        let overrides = self.bots.get(name).cloned().unwrap_or_default();
+        StartingStats {
+            health: overrides.health.unwrap_or(self.health),
+            energy: overrides.energy.unwrap_or(self.energy),
+            ammo: overrides.ammo.unwrap_or(self.ammo),
+            speed: overrides.speed.unwrap_or(self.speed),
+            bots: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for CombatTable {
+    fn default() -> Self {
+        CombatTable {
+            projectile_damage: 2,
+            weapon_damage_per_level: 1,
+            ricochet_damage_factor: 0.5,
+            laser_damage: 1,
+            self_destruct_damage: 6,
+            ram_damage: 0,
+            wall_damage: 0,
+            armor_health_per_level: 3,
+            armor_damage_reduction: 0.0,
+        }
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            arena_width: 400.0,
+            arena_height: 400.0,
+            topology: Topology::Bounded,
+            robot_width: 10.0,
+            robot_height: 10.0,
+            move_speed: 0.2,
+            turn_rate: 1.0,
+            arrival_tolerance: 1.0,
+            projectile_speed: 4.0,
+            projectile_lifetime: 150,
+            projectile_knockback: 1.0,
+            ricochet_bounces: 3,
+            projectile_collision_radius: 2.0,
+            laser_range: 250.0,
+            laser_energy_cost: 5,
+            laser_beam_ticks: 8,
+            smoke_radius: 30.0,
+            smoke_ticks: 300,
+            smoke_energy_cost: 20,
+            cloak_energy_drain: 1,
+            point_defense_radius: 40.0,
+            point_defense_energy_cost: 5,
+            hook_range: 150.0,
+            hook_reel_speed: 1.0,
+            emp_energy_cost: 40,
+            emp_cooldown: 300,
+            emp_stun_ticks: 30,
+            repair_rate: 1,
+            repair_energy_cost: 10,
+            repair_ally_range: 40.0,
+            self_destruct_fuse: 30,
+            self_destruct_radius: 50.0,
+            self_destruct_knockback: 3.0,
+            knockback_friction: 0.2,
+            koth_zone_radius: 40.0,
+            koth_target_score: 500,
+            wave_base_drones: 2,
+            drone_health: 4,
+            wreck_lifetime: 600,
+            salvage_ammo: 10,
+            salvage_energy: 30,
+            salvage_reach: 15.0,
+            max_ticks: 0,
+            stalemate_ticks: 0,
+            stalemate: StalemateRule::SuddenDeath,
+            sudden_death_interval: 60,
+            scan_range: 300.0,
+            scan_half_angle: 10.0,
+            scan_all_distance_step: 50.0,
+            scan_all_bearing_step: 15.0,
+            loadout_budget: 8,
+            max_memory_entries: 16,
+            max_program_size: 10_000,
+            max_variables: 64,
+            max_nesting_depth: 32,
+            math: MathMode::Native,
+            combat: CombatTable::default(),
+            start: StartingStats::default(),
+            judging: JudgingTable::default(),
+        }
+    }
+}
+
+impl GameConfig {
+    /// `position` brought back into a torus arena across its edges; unchanged
+    /// in a bounded arena.
+    pub fn wrap(&self, position: (f32, f32)) -> (f32, f32) {
+        if self.topology == Topology::Bounded {
+            return position;
+        }
+        let wrap = |value: f32, size: f32| {
+            let wrapped = value.rem_euclid(size);
+            // rem_euclid can round up to exactly `size` for tiny negative values
+            if wrapped >= size { 0.0 } else { wrapped }
+        };
+        (
+            wrap(position.0, self.arena_width),
+            wrap(position.1, self.arena_height),
+        )
+    }
+
+    /// The shortest vector from `from` to `to`. In a torus it may cross an
+    /// edge, so it is at most half the arena long on each axis.
+    pub fn displacement(&self, from: (f32, f32), to: (f32, f32)) -> (f32, f32) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        if self.topology == Topology::Bounded {
+            return (dx, dy);
+        }
+        (
+            dx - self.arena_width * (dx / self.arena_width).round(),
+            dy - self.arena_height * (dy / self.arena_height).round(),
+        )
+    }
+
+    /// Offsets of the copies of the arena a torus is tiled with: the arena
+    /// itself and its eight neighbors. A bounded arena has only itself.
+    pub fn tile_offsets(&self) -> impl Iterator<Item = (f32, f32)> + Clone + use<> {
+        let (w, h) = (self.arena_width, self.arena_height);
+        let tiles = match self.topology {
+            Topology::Bounded => 1,
+            Topology::Torus => 9,
+        };
+        [
+            (0.0, 0.0),
+            (-w, -h),
+            (-w, 0.0),
+            (-w, h),
+            (0.0, -h),
+            (0.0, h),
+            (w, -h),
+            (w, 0.0),
+            (w, h),
+        ]
+        .into_iter()
+        .take(tiles)
+    }
+
+    /// Load a config from a TOML file. Keys that are not present keep their
+    /// defaults; the result is checked with `validate`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let config: GameConfig =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        config
+            .validate()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    /// Check that the arena and robot sizes, speeds and ranges make sense:
+    /// sizes, speeds and reaches must be positive, the other distances must
+    /// not be negative, `scan_all` steps must be at least 1 and
+    /// `knockback_friction` a fraction. The error names the first key that is
+    /// wrong.
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("arena_width", self.arena_width),
+            ("arena_height", self.arena_height),
+            ("robot_width", self.robot_width),
+            ("robot_height", self.robot_height),
+            ("move_speed", self.move_speed),
+            ("turn_rate", self.turn_rate),
+            ("projectile_speed", self.projectile_speed),
+            ("laser_range", self.laser_range),
+            ("smoke_radius", self.smoke_radius),
+            ("hook_range", self.hook_range),
+            ("repair_ally_range", self.repair_ally_range),
+            ("self_destruct_radius", self.self_destruct_radius),
+            ("koth_zone_radius", self.koth_zone_radius),
+            ("scan_range", self.scan_range),
+            ("start.speed", self.start.speed),
+        ];
+        for (key, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("`{}` must be positive, got {}", key, value));
+            }
+        }
+        for (name, overrides) in &self.start.bots {
+            if let Some(speed) = overrides.speed
+                && !(speed.is_finite() && speed > 0.0)
+            {
+                return Err(format!(
+                    "`start.bots.{}.speed` must be positive, got {}",
+                    name, speed
+                ));
+            }
+        }
+        // `scan_all` rounds to whole units and degrees at the finest
+        let steps = [
+            ("scan_all_distance_step", self.scan_all_distance_step),
+            ("scan_all_bearing_step", self.scan_all_bearing_step),
+        ];
+        for (key, value) in steps {
+            if !(value.is_finite() && value >= 1.0) {
+                return Err(format!("`{}` must be at least 1, got {}", key, value));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.knockback_friction) {
+            return Err(format!(
+                "`knockback_friction` must be from 0 to 1, got {}",
+                self.knockback_friction
+            ));
+        }
+        let non_negative = [
+            ("arrival_tolerance", self.arrival_tolerance),
+            ("projectile_knockback", self.projectile_knockback),
+            (
+                "projectile_collision_radius",
+                self.projectile_collision_radius,
+            ),
+            ("point_defense_radius", self.point_defense_radius),
+            ("hook_reel_speed", self.hook_reel_speed),
+            ("self_destruct_knockback", self.self_destruct_knockback),
+            ("salvage_reach", self.salvage_reach),
+            ("scan_half_angle", self.scan_half_angle),
+        ];
+        for (key, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("`{}` must not be negative, got {}", key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a `key=value` override, where the value is written as in the TOML
+    /// file. Keys of a table are written `table.key`. An override that fails
+    /// `validate` is refused and leaves the config as it was.
+    pub fn apply_override(&mut self, assignment: &str) -> Result<(), String> {
+        let (key, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value, got `{}`", assignment))?;
+        let (key, value) = (key.trim(), value.trim());
+
+        let mut table = toml::Table::try_from(&*self).map_err(|e| e.to_string())?;
+        let unknown = || format!("unknown config key `{}`", key);
+        let (section, field) = match key.split_once('.') {
+            Some((section, field)) => {
+                let section = table.get_mut(section).and_then(|v| v.as_table_mut());
+                (section.ok_or_else(unknown)?, field)
+            }
+            None => (&mut table, key),
+        };
+        if section.get(field).is_none_or(toml::Value::is_table) {
+            return Err(unknown());
+        }
+        let parsed: toml::Table = toml::from_str(&format!("value = {}", value))
+            .map_err(|_| format!("invalid value for `{}`: {}", key, value))?;
+        section.insert(field.to_string(), parsed["value"].clone());
+
+        let config: GameConfig = table.try_into().map_err(|e: toml::de::Error| {
+            format!("invalid value for `{}`: {}", key, e.message())
+        })?;
+        config.validate()?;
+        *self = config;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_toml_keeps_defaults() {
+        let config: GameConfig = toml::from_str("move_speed = 0.5\narena_width = 800.0").unwrap();
+        assert_eq!(config.move_speed, 0.5);
+        assert_eq!(config.arena_width, 800.0);
+        assert_eq!(config.arena_height, GameConfig::default().arena_height);
+    }
+
+    #[test]
+    fn test_combat_table_from_toml() {
+        let config: GameConfig =
+            toml::from_str("move_speed = 0.5\n[combat]\nram_damage = 2\n").unwrap();
+        assert_eq!(config.combat.ram_damage, 2);
+        assert_eq!(
+            config.combat.laser_damage,
+            CombatTable::default().laser_damage
+        );
+        assert!(toml::from_str::<GameConfig>("[combat]\nwarp_damage = 1").is_err());
+    }
+
+    #[test]
+    fn test_unknown_key_rejected() {
+        assert!(toml::from_str::<GameConfig>("warp_speed = 9").is_err());
+    }
+
+    #[test]
+    fn test_torus_wraps_positions_and_distances() {
+        let mut config = GameConfig::default();
+        assert_eq!(config.wrap((-5.0, 410.0)), (-5.0, 410.0));
+        assert_eq!(
+            config.displacement((390.0, 10.0), (20.0, 390.0)),
+            (-370.0, 380.0)
+        );
+        assert_eq!(config.tile_offsets().collect::<Vec<_>>(), vec![(0.0, 0.0)]);
+
+        config.apply_override("topology=\"torus\"").unwrap();
+        assert_eq!(config.topology, Topology::Torus);
+        assert_eq!(config.wrap((-5.0, 410.0)), (395.0, 10.0));
+        assert_eq!(config.wrap((400.0, 0.0)), (0.0, 0.0));
+        // The short way round crosses both edges
+        assert_eq!(
+            config.displacement((390.0, 10.0), (20.0, 390.0)),
+            (30.0, -20.0)
+        );
+        assert_eq!(
+            config.displacement((100.0, 100.0), (150.0, 50.0)),
+            (50.0, -50.0)
+        );
+        let offsets: Vec<_> = config.tile_offsets().collect();
+        assert_eq!(offsets.len(), 9);
+        assert_eq!(offsets[0], (0.0, 0.0));
+        assert!(offsets.contains(&(-400.0, 400.0)));
+    }
+
+    #[test]
+    fn test_apply_override() {
+        let mut config = GameConfig::default();
+        config.apply_override("combat.projectile_damage=5").unwrap();
+        config.apply_override("scan_range = 120.5").unwrap();
+        assert_eq!(config.combat.projectile_damage, 5);
+        assert_eq!(config.scan_range, 120.5);
+        config.apply_override("arena_width=800").unwrap();
+        assert_eq!(config.arena_width, 800.0);
+
+        assert!(config.apply_override("combat.projectile_damage").is_err());
+        assert!(config.apply_override("warp_speed=9").is_err());
+        assert!(config.apply_override("projectile_damage=5").is_err());
+        assert!(config.apply_override("combat=5").is_err());
+        assert!(config.apply_override("combat.warp_damage=5").is_err());
+        assert!(
+            config
+                .apply_override("combat.projectile_damage=lots")
+                .is_err()
+        );
+        assert_eq!(config.combat.projectile_damage, 5);
+    }
+
+    #[test]
+    fn test_invalid_values_rejected() {
+        assert_eq!(GameConfig::default().validate(), Ok(()));
+        let mut config = GameConfig::default();
+        let error = config.apply_override("arena_width=0").unwrap_err();
+        assert!(
+            error.contains("`arena_width` must be positive"),
+            "{}",
+            error
+        );
+        assert_eq!(config.arena_width, GameConfig::default().arena_width);
+        assert!(config.apply_override("move_speed=-1.5").is_err());
+        assert!(config.apply_override("scan_range=nan").is_err());
+        assert!(config.apply_override("salvage_reach=-1").is_err());
+        // Zero turns projectile collisions off, so it is allowed
+        config
+            .apply_override("projectile_collision_radius=0")
+            .unwrap();
+        assert!(config.apply_override("knockback_friction=1.5").is_err());
+        assert!(config.apply_override("knockback_friction=-0.1").is_err());
+        config.apply_override("knockback_friction=1").unwrap();
+        assert!(config.apply_override("scan_all_distance_step=0").is_err());
+        assert!(config.apply_override("scan_all_bearing_step=0.5").is_err());
+        assert!(config.apply_override("start.speed=0").is_err());
+        assert!(config.apply_override("start.speed=-1").is_err());
+        let error = config.apply_override("start.speed=nan").unwrap_err();
+        assert!(error.contains("`start.speed`"), "{}", error);
+        assert_eq!(config.start.speed, GameConfig::default().start.speed);
+
+        let stopped: GameConfig = toml::from_str("[start.bots.tracker]\nspeed = 0.0").unwrap();
+        assert_eq!(
+            stopped.validate(),
+            Err("`start.bots.tracker.speed` must be positive, got 0".to_string())
+        );
+        let negative: GameConfig = toml::from_str("arena_height = -400.0").unwrap();
+        assert_eq!(
+            negative.validate(),
+            Err("`arena_height` must be positive, got -400".to_string())
+        );
+    }
+
+    #[test]
+    fn test_starting_stats_with_handicap() {
+        let mut config: GameConfig =
+            toml::from_str("[start]\nhealth = 20\n[start.bots.tracker]\nhealth = 8\nspeed = 0.5")
+                .unwrap();
+        config.apply_override("start.ammo=5").unwrap();
+        let tracker = config.start.for_bot("tracker");
+        assert_eq!((tracker.health, tracker.ammo, tracker.speed), (8, 5, 0.5));
+        let other = config.start.for_bot("spinner");
+        assert_eq!((other.health, other.ammo, other.speed), (20, 5, 1.0));
+        assert_eq!(other.energy, STARTING_ENERGY);
+        assert!(config.apply_override("start.bots=1").is_err());
+    }
+}
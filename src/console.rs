@@ -0,0 +1,288 @@
+//! Drop-down script console for the window frontend.
+//!
+//! Opened and closed with the ~ key. Commands typed into it are compiled like
+//! a script and injected into the selected robot, which runs them before going
+//! back to its own program, so a maneuver can be tried out without editing and
+//! reloading the script. Tab picks the next robot, Up and Down go through the
+//! commands entered before, and errors are shown in the console.
+
+use macroquad::prelude::*;
+
+use crate::ast::Instruction;
+use crate::bindings::{ViewerKeys, key_label, pressed};
+use crate::locale;
+use crate::parser::parse_script;
+use crate::registers::Reg;
+use crate::sandbox;
+use crate::simulation::Simulation;
+
+/// Lines of output kept in the console.
+const LOG_LINES: usize = 8;
+const LINE_HEIGHT: f32 = 20.0;
+
+/// One line of console output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub text: String,
+    pub error: bool,
+}
+
+/// State of the script console.
+#[derive(Debug, Clone, Default)]
+pub struct Console {
+    pub open: bool,
+    /// The command being typed.
+    pub input: String,
+    /// Commands entered so far, oldest first.
+    history: Vec<String>,
+    /// Index into `history` while going through it with Up and Down.
+    browsing: Option<usize>,
+    pub log: Vec<LogLine>,
+    /// Index of the robot commands are injected into.
+    pub target: usize,
+}
+
+impl Console {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Select the next robot that is still in the match.
+    pub fn next_target(&mut self, sim: &Simulation) {
+        let count = sim.robots.len();
+        for step in 1..=count {
+            let i = (self.target + step) % count;
+            if sim.robots[i].health > 0 {
+                self.target = i;
+                return;
+            }
+        }
+    }
+
+    /// Show the previous command entered, going further back each time.
+    pub fn history_back(&mut self) {
+        let back = match self.browsing {
+            Some(i) => i.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+        self.browsing = Some(back);
+        self.input = self.history[back].clone();
+    }
+
+    /// Show the next command entered, or an empty line after the latest.
+    pub fn history_forward(&mut self) {
+        match self.browsing {
+            Some(i) if i + 1 < self.history.len() => {
+                self.browsing = Some(i + 1);
+                self.input = self.history[i + 1].clone();
+            }
+            _ => {
+                self.browsing = None;
+                self.input.clear();
+            }
+        }
+    }
+
+    /// Compile the typed command and inject it into the selected robot, logging
+    /// the outcome.
+    pub fn submit(&mut self, sim: &mut Simulation) {
+        let command = std::mem::take(&mut self.input);
+        self.browsing = None;
+        if command.trim().is_empty() {
+            return;
+        }
+        if self.history.last() != Some(&command) {
+            self.history.push(command.clone());
+        }
+        self.print(format!("> {}", command), false);
+
+        let Some(robot) = sim.robots.get(self.target) else {
+            self.print(locale::text("console.no_robot").to_string(), true);
+            return;
+        };
+        if robot.controls.is_some() {
+            let text = locale::format("console.driven_by_player", &[("name", &robot.name)]);
+            self.print(text, true);
+            return;
+        }
+        let compiled = parse_script(&command)
+            .and_then(|commands| Ok(sandbox::compile(&commands, &sim.config)?));
+        match compiled {
+            Ok(program) => {
+                let robot = &mut sim.robots[self.target];
+                let text = locale::format(
+                    "console.runs",
+                    &[
+                        ("name", &robot.name),
+                        ("id", &robot.id),
+                        ("count", &program.len()),
+                    ],
+                );
+                robot.inject(isolate_counters(program));
+                self.print(text, false);
+            }
+            Err(e) => self.print(e.to_string(), true),
+        }
+    }
+
+    fn print(&mut self, text: String, error: bool) {
+        self.log.push(LogLine { text, error });
+        if self.log.len() > LOG_LINES {
+            self.log.remove(0);
+        }
+    }
+
+    /// Read keyboard input for this frame: typing, Enter to submit, Tab to pick
+    /// the next robot, Up and Down for history. The console and menu keys (~
+    /// and Esc) close the console.
+    pub fn handle_input(&mut self, sim: &mut Simulation, keys: &ViewerKeys) {
+        while let Some(c) = get_char_pressed() {
+            if !c.is_control() && c != '`' && c != '~' {
+                self.input.push(c);
+            }
+        }
+        if pressed(&keys.console) || pressed(&keys.menu) {
+            self.open = false;
+        } else if is_key_pressed(KeyCode::Backspace) {
+            self.input.pop();
+        } else if is_key_pressed(KeyCode::Enter) {
+            self.submit(sim);
+        } else if is_key_pressed(KeyCode::Tab) {
+            self.next_target(sim);
+        } else if is_key_pressed(KeyCode::Up) {
+            self.history_back();
+        } else if is_key_pressed(KeyCode::Down) {
+            self.history_forward();
+        }
+    }
+
+    /// Draw the console across the top of the screen.
+    pub fn draw(&self, sim: &Simulation, keys: &ViewerKeys) {
+        let width = screen_width();
+        let height = LINE_HEIGHT * (LOG_LINES as f32 + 2.0);
+        draw_rectangle(0.0, 0.0, width, height, Color::new(0.05, 0.05, 0.05, 0.9));
+        draw_line(0.0, height, width, height, 2.0, LIGHTGRAY);
+
+        let mut y = LINE_HEIGHT;
+        for line in &self.log {
+            let color = if line.error { RED } else { LIGHTGRAY };
+            draw_text(&line.text, 10.0, y, 20.0, color);
+            y += LINE_HEIGHT;
+        }
+        let prompt = match sim.robots.get(self.target) {
+            Some(robot) => format!("{} #{}> {}_", robot.name, robot.id, self.input),
+            None => format!("> {}_", self.input),
+        };
+        draw_text(&prompt, 10.0, height - LINE_HEIGHT * 0.5, 22.0, YELLOW);
+        let help = locale::format("console.help", &[("console", &key_label(&keys.console))]);
+        let help_w = measure_text(&help, None, 18, 1.0).width;
+        draw_text(
+            &help,
+            width - help_w - 10.0,
+            height - LINE_HEIGHT * 0.5,
+            18.0,
+            GRAY,
+        );
+    }
+}
+
+/// Rename the loop counters of an injected program, so they cannot clobber
+/// the counters of a loop the robot's own program is in the middle of.
+fn isolate_counters(mut program: Vec<Instruction>) -> Vec<Instruction> {
+    let counters: Vec<Reg> = program
+        .iter()
+        .filter_map(|i| match i {
+            Instruction::LoadCounter { reg, .. } => Some(*reg),
+            _ => None,
+        })
+        .collect();
+    for instruction in &mut program {
+        if let Instruction::LoadCounter { reg, .. }
+        | Instruction::Dec { reg }
+        | Instruction::Jnz { reg, .. } = instruction
+            && counters.contains(reg)
+        {
+            *reg = Reg::named(&format!("console_{}", reg));
+        }
+    }
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Robot;
+    use crate::config::GameConfig;
+    use crate::parser::parse_script;
+
+    fn sim() -> Simulation {
+        let program =
+            crate::ast::translate_commands_to_instructions(&parse_script("loop { scan }").unwrap());
+        let robots = vec![
+            Robot::new(1, "scanner", (100.0, 100.0), program),
+            Robot::new(2, "target", (300.0, 300.0), Vec::new()),
+        ];
+        Simulation::new(GameConfig::default(), robots, Vec::new())
+    }
+
+    #[test]
+    fn test_injected_commands_run_before_the_script_resumes() {
+        let mut sim = sim();
+        sim.step();
+        let own = sim.robots[0].instruction_queue.clone();
+        let ip = sim.robots[0].ip;
+
+        let mut console = Console {
+            input: "rotate body 3".to_string(),
+            ..Console::default()
+        };
+        console.submit(&mut sim);
+        assert_eq!(console.log.len(), 2);
+        assert!(!console.log[1].error, "{:?}", console.log);
+        assert!(console.log[1].text.starts_with("scanner #1 runs"));
+        assert!(matches!(
+            sim.robots[0].instruction_queue[0],
+            Instruction::LoadCounter { reg, .. } if reg.name().starts_with("console_")
+        ));
+
+        for _ in 0..40 {
+            sim.step();
+            if sim.robots[0].suspended.is_none() {
+                break;
+            }
+        }
+        // Positive rotations turn counter-clockwise
+        assert_eq!(sim.robots[0].heading, 357.0);
+        assert_eq!(sim.robots[0].instruction_queue, own);
+        assert!(sim.robots[0].ip >= ip);
+    }
+
+    #[test]
+    fn test_errors_are_logged_and_history_is_kept() {
+        let mut sim = sim();
+        let mut console = Console {
+            input: "fly away".to_string(),
+            ..Console::default()
+        };
+        console.submit(&mut sim);
+        assert!(console.log[1].error);
+        assert!(sim.robots[0].suspended.is_none());
+
+        console.input = "scan".to_string();
+        console.submit(&mut sim);
+        console.history_back();
+        assert_eq!(console.input, "scan");
+        console.history_back();
+        assert_eq!(console.input, "fly away");
+        console.history_forward();
+        console.history_forward();
+        assert_eq!(console.input, "");
+
+        console.next_target(&sim);
+        assert_eq!(console.target, 1);
+        sim.robots[0].health = 0;
+        console.next_target(&sim);
+        assert_eq!(console.target, 1);
+    }
+}
@@ -0,0 +1,201 @@
+// Script coverage: which commands of a bot ever ran.
+// Built on the same per-instruction tick counts as profiles (see
+// `Simulation::with_profiling`), summed over every match a bot played, so a
+// tournament shows which parts of a bot were never reached by any opponent.
+// The listing annotates each source line the way gcov does: ticks spent on
+// the line, `#####` for commands that never ran, `-` for lines without code.
+
+use std::path::Path;
+
+use crate::ast::{Instruction, Robot, translate_with_origins};
+use crate::error::{SimError, Span};
+use crate::parser::parse_script_with_spans;
+use crate::setup::BotScript;
+
+/// Marker for lines with a command that never ran.
+const NEVER_RAN: &str = "#####";
+
+/// Which commands of one bot's script ran, over one or more matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    pub name: String,
+    script: String,
+    /// Where each command starts, in source order.
+    spans: Vec<Span>,
+    program: Vec<Instruction>,
+    /// Command each instruction of the compiled program came from.
+    origins: Vec<usize>,
+    /// Ticks spent on each command over every match added.
+    ticks: Vec<u64>,
+    /// Whether each command compiles to any instructions; `move forward 0` or
+    /// a `build` header do not, so they cannot run.
+    has_code: Vec<bool>,
+    /// Matches added so far.
+    pub matches: u32,
+}
+
+impl Coverage {
+    /// Empty coverage of `bot`'s script; `None` if it does not parse.
+    pub fn new(bot: &BotScript) -> Option<Coverage> {
+        let (commands, spans) = parse_script_with_spans(&bot.script).ok()?;
+        let (program, origins) = translate_with_origins(&commands);
+        let mut has_code = vec![false; spans.len()];
+        for origin in &origins {
+            has_code[*origin] = true;
+        }
+        Some(Coverage {
+            name: bot.name.clone(),
+            script: bot.script.clone(),
+            ticks: vec![0; spans.len()],
+            spans,
+            program,
+            origins,
+            has_code,
+            matches: 0,
+        })
+    }
+
+    /// Add the ticks `robot` spent in a profiled match. Returns false, adding
+    /// nothing, if the robot was not profiled or runs a different program.
+    pub fn add(&mut self, robot: &Robot) -> bool {
+        if robot.instruction_queue != self.program
+            || robot.ticks_spent.len() != self.program.len() + 1
+        {
+            return false;
+        }
+        for (origin, ticks) in self.origins.iter().zip(&robot.ticks_spent) {
+            self.ticks[*origin] += ticks;
+        }
+        self.matches += 1;
+        true
+    }
+
+    /// Commands that ran at least once, and commands that could have.
+    pub fn covered(&self) -> (usize, usize) {
+        let runnable = self.has_code.iter().filter(|c| **c).count();
+        let ran = self.ticks.iter().filter(|t| **t > 0).count();
+        (ran, runnable)
+    }
+
+    /// Source lines whose commands include one that never ran.
+    pub fn dead_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = self
+            .spans
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.has_code[*i] && self.ticks[*i] == 0)
+            .map(|(_, span)| span.line)
+            .collect();
+        lines.dedup();
+        lines
+    }
+
+    /// The script with every line annotated: ticks spent on the commands that
+    /// start on it, `#####` if one of them never ran, or `-` without code.
+    pub fn listing(&self) -> String {
+        let (ran, runnable) = self.covered();
+        let percent = if runnable == 0 {
+            100.0
+        } else {
+            ran as f64 * 100.0 / runnable as f64
+        };
+        let mut listing = format!(
+            "{}: {} of {} commands ran in {} match(es) ({:.1}%)\n",
+            self.name, ran, runnable, self.matches, percent
+        );
+        let dead = self.dead_lines();
+        for (number, text) in self.script.lines().enumerate() {
+            let line = number + 1;
+            let commands = || {
+                self.spans
+                    .iter()
+                    .enumerate()
+                    .filter(move |(i, span)| span.line == line && self.has_code[*i])
+                    .map(|(i, _)| i)
+            };
+            let mark = if dead.contains(&line) {
+                NEVER_RAN.to_string()
+            } else if commands().next().is_none() {
+                "-".to_string()
+            } else {
+                commands().map(|i| self.ticks[i]).sum::<u64>().to_string()
+            };
+            listing += &format!("{:>9} | {:>4} | {}\n", mark, line, text);
+        }
+        listing
+    }
+
+    /// Write the listing of each of `coverages` to `path`, one after another.
+    pub fn write(coverages: &[Coverage], path: &Path) -> Result<(), SimError> {
+        let listings: Vec<String> = coverages.iter().map(Coverage::listing).collect();
+        std::fs::write(path, listings.join("\n")).map_err(|source| SimError::WriteCoverage {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::setup::compile_robots;
+    use crate::simulation::Simulation;
+
+    const SCRIPT: &str =
+        "build armor 3 speed 1\nmove forward 0\nscan\nloop {\n  fire\n}\nselfdestruct";
+
+    fn bot(name: &str, script: &str) -> BotScript {
+        BotScript {
+            entrant: name.to_string(),
+            name: name.to_string(),
+            script: script.to_string(),
+        }
+    }
+
+    fn play(bots: &[BotScript], ticks: u64) -> Simulation {
+        let robots = compile_robots(bots, &GameConfig::default()).unwrap();
+        let mut sim = Simulation::new(GameConfig::default(), robots, Vec::new()).with_profiling();
+        for _ in 0..ticks {
+            sim.step();
+        }
+        sim
+    }
+
+    #[test]
+    fn test_unreached_commands_are_marked() {
+        let shooter = bot("shooter", SCRIPT);
+        let sim = play(&[shooter.clone(), bot("idle", "scan")], 10);
+        let mut coverage = Coverage::new(&shooter).unwrap();
+        assert!(coverage.add(&sim.robots[0]));
+        assert!(!coverage.add(&sim.robots[1]));
+
+        // The build header and the empty move have no code to run
+        assert_eq!(coverage.covered(), (3, 4));
+        assert_eq!(coverage.dead_lines(), vec![7]);
+        assert_eq!(
+            coverage.listing(),
+            "shooter: 3 of 4 commands ran in 1 match(es) (75.0%)\n\
+             \x20       - |    1 | build armor 3 speed 1\n\
+             \x20       - |    2 | move forward 0\n\
+             \x20       1 |    3 | scan\n\
+             \x20       6 |    4 | loop {\n\
+             \x20       3 |    5 |   fire\n\
+             \x20       - |    6 | }\n\
+             \x20   ##### |    7 | selfdestruct\n"
+        );
+    }
+
+    #[test]
+    fn test_matches_add_up() {
+        let looper = bot("looper", "loop {\n  scan\n}");
+        let mut coverage = Coverage::new(&looper).unwrap();
+        for ticks in [10, 20] {
+            let sim = play(&[looper.clone(), bot("idle", "scan")], ticks);
+            coverage.add(&sim.robots[0]);
+        }
+        assert_eq!(coverage.matches, 2);
+        assert_eq!(coverage.covered(), (2, 2));
+        assert!(coverage.listing().contains("       10 |    2 |   scan\n"));
+    }
+}
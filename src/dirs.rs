@@ -0,0 +1,152 @@
+// Where robot-battle keeps its files between runs, so they do not depend on the
+// working directory. The config directory holds the default `game.toml`,
+// `controls.toml`, `viewer_keys.toml` and `settings.toml`; the data directory holds fetched bots, the match history,
+// saved replays and tutorial progress. Each platform has its own convention (XDG directories on
+// Linux, `Application Support` on macOS, `AppData` on Windows). Paths given on
+// the command line always win, and a relative path that exists in the working
+// directory is used as is.
+
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+
+/// Directory under the data directory that `fetch` saves bots in.
+pub const BOTS_DIR: &str = "bots";
+/// File under the data directory that holds the match history.
+pub const HISTORY_DB: &str = "history.db";
+/// Directory under the data directory that saved replays are looked up in.
+pub const REPLAYS_DIR: &str = "replays";
+/// File under the data directory that records the tutorial lessons passed.
+pub const TUTORIAL_PROGRESS: &str = "tutorial.toml";
+/// Game config read from the config directory when `--config` is not given.
+pub const GAME_CONFIG: &str = "game.toml";
+/// Key bindings read from the config directory when `--controls` is not given.
+pub const CONTROLS: &str = "controls.toml";
+/// Window settings read from the config directory when `--settings` is not given.
+pub const SETTINGS: &str = "settings.toml";
+/// Window keys read from the config directory when `--viewer-keys` is not given.
+pub const VIEWER_KEYS: &str = "viewer_keys.toml";
+
+/// The config and data directories of this user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dirs {
+    pub config: PathBuf,
+    pub data: PathBuf,
+}
+
+impl Dirs {
+    /// The platform's directories for robot-battle. Without a home directory
+    /// to put them in, both are the working directory.
+    pub fn locate() -> Dirs {
+        match ProjectDirs::from("", "", "robot-battle") {
+            Some(dirs) => Dirs {
+                config: dirs.config_dir().to_path_buf(),
+                data: dirs.data_dir().to_path_buf(),
+            },
+            None => Dirs {
+                config: PathBuf::new(),
+                data: PathBuf::new(),
+            },
+        }
+    }
+
+    /// The default game config, if the user has written one.
+    pub fn game_config(&self) -> Option<PathBuf> {
+        Some(self.config.join(GAME_CONFIG)).filter(|path| path.is_file())
+    }
+
+    /// The default key bindings, if the user has written them.
+    pub fn controls(&self) -> Option<PathBuf> {
+        Some(self.config.join(CONTROLS)).filter(|path| path.is_file())
+    }
+
+    /// The window settings, if the user has written them.
+    pub fn settings(&self) -> Option<PathBuf> {
+        Some(self.config.join(SETTINGS)).filter(|path| path.is_file())
+    }
+
+    /// The keys that work the window, if the user has written them.
+    pub fn viewer_keys(&self) -> Option<PathBuf> {
+        Some(self.config.join(VIEWER_KEYS)).filter(|path| path.is_file())
+    }
+
+    /// Where fetched bots are saved.
+    pub fn bots(&self) -> PathBuf {
+        self.data.join(BOTS_DIR)
+    }
+
+    /// The match history database.
+    pub fn history_db(&self) -> PathBuf {
+        self.data.join(HISTORY_DB)
+    }
+
+    /// Where saved replays are looked up.
+    pub fn replays(&self) -> PathBuf {
+        self.data.join(REPLAYS_DIR)
+    }
+
+    /// The tutorial lessons passed so far.
+    pub fn tutorial_progress(&self) -> PathBuf {
+        self.data.join(TUTORIAL_PROGRESS)
+    }
+
+    /// A bot script named on the command line: as given if it exists there,
+    /// otherwise from the bots directory if it is there.
+    pub fn bot_script(&self, path: &Path) -> PathBuf {
+        resolve(path, &self.bots())
+    }
+
+    /// A replay named on the command line: as given if it exists there,
+    /// otherwise from the replays directory if it is there.
+    pub fn replay(&self, path: &Path) -> PathBuf {
+        resolve(path, &self.replays())
+    }
+}
+
+/// `path` if it exists or is absolute, otherwise `path` under `dir` if that
+/// exists. A path found in neither place is returned unchanged, so errors
+/// name it as the user wrote it.
+fn resolve(path: &Path, dir: &Path) -> PathBuf {
+    if path.is_absolute() || path.exists() {
+        return path.to_path_buf();
+    }
+    let fallback = dir.join(path);
+    if fallback.exists() {
+        fallback
+    } else {
+        path.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_bot_scripts_fall_back_to_the_bots_directory() {
+        let root = std::env::temp_dir().join(format!("robot-battle-dirs-{}", std::process::id()));
+        let dirs = Dirs {
+            config: root.join("config"),
+            data: root.join("data"),
+        };
+        fs::create_dir_all(dirs.bots()).unwrap();
+        fs::write(dirs.bots().join("tracker.robo"), "scan\n").unwrap();
+
+        assert_eq!(
+            dirs.bot_script(Path::new("tracker.robo")),
+            dirs.bots().join("tracker.robo")
+        );
+        // The working directory comes first, and missing files keep their name
+        assert_eq!(
+            dirs.bot_script(Path::new("robot-scripts/circler.robo")),
+            Path::new("robot-scripts/circler.robo")
+        );
+        assert_eq!(
+            dirs.bot_script(Path::new("missing.robo")),
+            Path::new("missing.robo")
+        );
+        assert_eq!(dirs.game_config(), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
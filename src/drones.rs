@@ -0,0 +1,121 @@
+// AI drones for the wave-survival mode.
+// Drones are ordinary robots on a shared team running one of a few built-in
+// scripts, so they obey the same rules as scripted robots.
+
+use std::f32::consts::TAU;
+
+use crate::ast::{Robot, translate_commands_to_instructions};
+use crate::parser::parse_script;
+use crate::simulation::Simulation;
+
+/// Team shared by all drones.
+pub const DRONE_TEAM: usize = usize::MAX;
+
+/// Built-in drone behaviors, assigned round-robin within a wave.
+const DRONE_SCRIPTS: &[&str] = &[
+    // Turret: stays put, sweeps its scanner and shoots at contacts
+    "loop {
+        rotate scanner 15
+        scan
+        aim at scanned
+        fire
+    }",
+    // Wanderer: zig-zags across the arena, shooting at whatever it sees
+    "loop {
+        move forward 40
+        rotate body 70
+        scan
+        aim at scanned
+        fire
+    }",
+    // Rammer: charges toward the middle of the default arena, then circles
+    "goto 200 200
+    loop {
+        move forward 20
+        rotate body -30
+        rotate scanner 30
+        scan
+        aim at scanned
+        fire
+    }",
+];
+
+/// Number of drones in a wave (waves count from 1).
+pub fn wave_size(sim: &Simulation, wave: u32) -> u32 {
+    sim.config.wave_base_drones + wave.saturating_sub(1)
+}
+
+/// Add the drones for `wave`, spread around a ring near the arena edge.
+/// Spawn points inside obstacles are skipped in favor of the next free one.
+pub fn spawn_wave(sim: &mut Simulation, wave: u32) {
+    let count = wave_size(sim, wave) as usize;
+    let (w, h) = (sim.config.arena_width, sim.config.arena_height);
+    let center = (w / 2.0, h / 2.0);
+    let ring = w.min(h) * 0.42;
+    let half = sim.config.robot_width.max(sim.config.robot_height) / 2.0;
+
+    // Twice as many candidate slots as drones, offset each wave
+    let slots = count * 2;
+    let offset = wave as f32 * 0.7;
+    let mut next_id = sim.next_robot_id();
+    let mut spawned = 0;
+    for slot in 0..slots {
+        if spawned == count {
+            break;
+        }
+        let angle = offset + slot as f32 / slots as f32 * TAU;
+        let (sin, cos) = sim.config.math.sin_cos(angle);
+        let position = (center.0 + ring * cos, center.1 + ring * sin);
+        if sim
+            .obstacles
+            .iter()
+            .any(|o| o.overlaps_square(position, half))
+        {
+            continue;
+        }
+
+        let script = DRONE_SCRIPTS[spawned % DRONE_SCRIPTS.len()];
+        let commands = parse_script(script).expect("built-in drone script");
+        let mut drone = Robot::new(
+            next_id,
+            "drone",
+            position,
+            translate_commands_to_instructions(&commands),
+        );
+        drone.team = DRONE_TEAM;
+        drone.health = sim.config.drone_health;
+        drone.max_health = sim.config.drone_health;
+        drone.rng = sim.rng_for(next_id);
+        sim.robots.push(drone);
+        next_id += 1;
+        spawned += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+
+    #[test]
+    fn test_drone_scripts_compile() {
+        for script in DRONE_SCRIPTS {
+            assert!(parse_script(script).is_ok(), "{}", script);
+        }
+    }
+
+    #[test]
+    fn test_spawn_wave_adds_drones_with_fresh_ids() {
+        let player = Robot::new(1, "player", (200.0, 200.0), vec![]);
+        let mut sim = Simulation::new(GameConfig::default(), vec![player], vec![]);
+        spawn_wave(&mut sim, 3);
+
+        let drones: Vec<&Robot> = sim.robots.iter().filter(|r| r.team == DRONE_TEAM).collect();
+        assert_eq!(drones.len() as u32, wave_size(&sim, 3));
+        let mut ids: Vec<usize> = sim.robots.iter().map(|r| r.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), sim.robots.len());
+        assert!(drones.iter().all(|d| d.health == sim.config.drone_health));
+    }
+}
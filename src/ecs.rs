@@ -0,0 +1,320 @@
+//! Hand-rolled entity-component storage for world objects.
+//!
+//! Projectiles and other non-robot objects are entities: plain ids with optional
+//! components attached. Behaviour lives in systems (see `systems.rs`) that iterate
+//! over the entities carrying the components they care about, so a new kind of
+//! object is a new combination of components rather than a change to the main loop.
+
+use crate::elevation::Level;
+use crate::movers::Course;
+use crate::raycast::Obstacle;
+
+/// Handle to an entity. Ids of despawned entities are reused.
+pub type Entity = usize;
+
+/// Position and facing of an entity; the heading is in degrees, like robot headings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub position: (f32, f32),
+    pub heading: f32,
+}
+
+/// Movement along the entity's heading, in units per tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Velocity {
+    pub speed: f32,
+}
+
+/// Damage dealt to the first robot the entity touches; the entity is consumed on hit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Damage {
+    pub owner: usize, // Id of the robot responsible, never damaged by this entity
+    pub amount: i32,
+}
+
+/// Stops the program of the robot the entity hits, instead of damaging it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Emp {
+    pub stun_ticks: u32,
+}
+
+/// Remaining ticks before the entity despawns on its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lifetime {
+    pub ticks: u32,
+}
+
+/// A straight beam drawn from the entity's position along its heading. It fades
+/// out over `duration` ticks, the lifetime it was spawned with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Beam {
+    pub length: f32,
+    pub duration: u32,
+    pub kind: BeamKind,
+}
+
+/// What a beam is, so renderers can tell them apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BeamKind {
+    Laser,
+    Repair,
+    /// A point-defense shot at a projectile.
+    Intercept,
+}
+
+/// A cloud around the entity's position that scanners cannot see through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Smoke {
+    pub radius: f32,
+}
+
+/// Bounces off walls and obstacles instead of breaking, up to `remaining` more
+/// times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounce {
+    pub remaining: u32,
+}
+
+/// A box centered on the entity that blocks robots and projectiles like an obstacle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collider {
+    pub half_width: f32,
+    pub half_height: f32,
+}
+
+/// Moves the entity along a fixed course each tick, as a piece of an arena
+/// mover (see `movers`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Kinematic {
+    pub course: Course,
+}
+
+/// Remains of a destroyed robot; scans report it as debris.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Debris {
+    pub robot: usize, // Id of the robot that was destroyed
+}
+
+/// Ammunition and energy left in a wreck, for the first robot that drives up
+/// to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Salvage {
+    pub ammo: i32,
+    pub energy: i32,
+}
+
+/// How renderers should draw an entity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sprite {
+    Projectile,
+    Ricochet,
+    Emp,
+    Wreck,
+    /// A wreck that still holds salvage.
+    SalvageWreck,
+    /// An injected supply of ammo and energy (see `inject`).
+    Supply,
+}
+
+/// Sparse storage for one component type, indexed by entity id.
+#[derive(Debug, Clone)]
+pub struct Storage<T> {
+    items: Vec<Option<T>>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Storage { items: Vec::new() }
+    }
+}
+
+impl<T> Storage<T> {
+    pub fn insert(&mut self, entity: Entity, value: T) {
+        if entity >= self.items.len() {
+            self.items.resize_with(entity + 1, || None);
+        }
+        self.items[entity] = Some(value);
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.items.get_mut(entity).and_then(Option::take)
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.items.get(entity).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.items.get_mut(entity).and_then(Option::as_mut)
+    }
+
+    /// Iterate over the entities that have this component.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(e, item)| item.as_ref().map(|c| (e, c)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.items
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(e, item)| item.as_mut().map(|c| (e, c)))
+    }
+}
+
+/// All non-robot entities and their components.
+#[derive(Debug, Clone, Default)]
+pub struct World {
+    alive: Vec<bool>,
+    free: Vec<Entity>,
+    pub transforms: Storage<Transform>,
+    pub velocities: Storage<Velocity>,
+    pub damages: Storage<Damage>,
+    pub emps: Storage<Emp>,
+    /// The level a projectile was fired from; it only hits robots it meets.
+    pub levels: Storage<Level>,
+    pub lifetimes: Storage<Lifetime>,
+    pub bounces: Storage<Bounce>,
+    pub beams: Storage<Beam>,
+    pub smoke: Storage<Smoke>,
+    pub colliders: Storage<Collider>,
+    pub kinematics: Storage<Kinematic>,
+    pub debris: Storage<Debris>,
+    pub salvage: Storage<Salvage>,
+    pub sprites: Storage<Sprite>,
+}
+
+impl World {
+    /// Create a new entity with no components.
+    pub fn spawn(&mut self) -> Entity {
+        match self.free.pop() {
+            Some(entity) => {
+                self.alive[entity] = true;
+                entity
+            }
+            None => {
+                self.alive.push(true);
+                self.alive.len() - 1
+            }
+        }
+    }
+
+    /// Remove an entity and all of its components.
+    pub fn despawn(&mut self, entity: Entity) {
+        if !self.is_alive(entity) {
+            return;
+        }
+        self.alive[entity] = false;
+        self.free.push(entity);
+        self.transforms.remove(entity);
+        self.velocities.remove(entity);
+        self.damages.remove(entity);
+        self.emps.remove(entity);
+        self.levels.remove(entity);
+        self.lifetimes.remove(entity);
+        self.bounces.remove(entity);
+        self.beams.remove(entity);
+        self.smoke.remove(entity);
+        self.colliders.remove(entity);
+        self.kinematics.remove(entity);
+        self.debris.remove(entity);
+        self.salvage.remove(entity);
+        self.sprites.remove(entity);
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.alive.get(entity).copied().unwrap_or(false)
+    }
+
+    /// Number of live entities.
+    pub fn entity_count(&self) -> usize {
+        self.alive.iter().filter(|a| **a).count()
+    }
+
+    /// Live entities that have a sprite, with their transform, for rendering.
+    pub fn renderables(&self) -> impl Iterator<Item = (Sprite, &Transform)> {
+        self.sprites
+            .iter()
+            .filter_map(|(e, sprite)| self.transforms.get(e).map(|t| (*sprite, t)))
+    }
+
+    /// Beams with their transform and brightness, from 1 when fired down to 0 as
+    /// they fade, for rendering.
+    pub fn visible_beams(&self) -> impl Iterator<Item = (&Transform, &Beam, f32)> {
+        self.beams.iter().filter_map(|(e, beam)| {
+            let transform = self.transforms.get(e)?;
+            let left = self.lifetimes.get(e).map_or(0, |l| l.ticks);
+            Some((transform, beam, left as f32 / beam.duration.max(1) as f32))
+        })
+    }
+
+    /// Smoke clouds as (center, radius).
+    pub fn smoke_clouds(&self) -> impl Iterator<Item = ((f32, f32), f32)> {
+        self.smoke
+            .iter()
+            .filter_map(|(e, smoke)| self.transforms.get(e).map(|t| (t.position, smoke.radius)))
+    }
+
+    /// Boxes of the entities that have a collider, as obstacles.
+    pub fn solid_boxes(&self) -> impl Iterator<Item = Obstacle> {
+        self.colliders
+            .iter()
+            .filter_map(|(e, collider)| self.solid_box(e, collider))
+    }
+
+    /// Boxes of the pieces of arena movers, as obstacles.
+    pub fn moving_boxes(&self) -> impl Iterator<Item = Obstacle> {
+        self.colliders
+            .iter()
+            .filter(|(e, _)| self.kinematics.get(*e).is_some())
+            .filter_map(|(e, collider)| self.solid_box(e, collider))
+    }
+
+    fn solid_box(&self, entity: Entity, collider: &Collider) -> Option<Obstacle> {
+        self.transforms.get(entity).map(|t| {
+            Obstacle::new(
+                t.position.0 - collider.half_width,
+                t.position.1 - collider.half_height,
+                collider.half_width * 2.0,
+                collider.half_height * 2.0,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_and_despawn_reuses_ids() {
+        let mut world = World::default();
+        let a = world.spawn();
+        let b = world.spawn();
+        world.velocities.insert(a, Velocity { speed: 1.0 });
+        assert_eq!(world.entity_count(), 2);
+
+        world.despawn(a);
+        assert!(!world.is_alive(a));
+        assert_eq!(world.velocities.get(a), None);
+        assert_eq!(world.entity_count(), 1);
+
+        let c = world.spawn();
+        assert_eq!(c, a);
+        assert_ne!(c, b);
+        assert_eq!(world.velocities.get(c), None);
+    }
+
+    #[test]
+    fn test_storage_iterates_only_present_components() {
+        let mut storage = Storage::default();
+        storage.insert(3, 'c');
+        storage.insert(1, 'a');
+        let items: Vec<_> = storage.iter().collect();
+        assert_eq!(items, vec![(1, &'a'), (3, &'c')]);
+        assert_eq!(storage.remove(3), Some('c'));
+        assert_eq!(storage.get(3), None);
+    }
+}
@@ -0,0 +1,116 @@
+// Elevation: raised platforms in an arena and the ramps leading up to them.
+// A robot is on the ground, on a ramp or up on a platform, by where its center
+// is. It can only get between the ground and a platform by way of a ramp; the
+// other edges of a platform are cliffs. Shots stay on the level they were fired
+// from and pass over or under robots on the other one, while robots on a ramp
+// can be hit from both.
+
+use serde::{Deserialize, Serialize};
+
+/// A rectangle of the arena, by its top-left corner and size.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Whether `point` lies within the rectangle, edges included.
+    pub fn contains(&self, point: (f32, f32)) -> bool {
+        point.0 >= self.x
+            && point.0 <= self.x + self.width
+            && point.1 >= self.y
+            && point.1 <= self.y + self.height
+    }
+}
+
+/// How high up a point of the arena is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Ground,
+    Ramp,
+    Platform,
+}
+
+impl Level {
+    /// Whether robots and shots on these two levels can reach each other:
+    /// they are on the same one, or either is on a ramp.
+    pub fn meets(self, other: Level) -> bool {
+        self == other || self == Level::Ramp || other == Level::Ramp
+    }
+
+    /// The level as the `layer` and `scan_layer` registers read it.
+    pub fn value(self) -> i32 {
+        match self {
+            Level::Ground => 0,
+            Level::Ramp => 1,
+            Level::Platform => 2,
+        }
+    }
+}
+
+/// An arena's platforms and ramps. Without any, the arena is flat.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Elevation {
+    #[serde(default)]
+    pub platforms: Vec<Rect>,
+    #[serde(default)]
+    pub ramps: Vec<Rect>,
+}
+
+impl Elevation {
+    pub fn is_flat(&self) -> bool {
+        self.platforms.is_empty() && self.ramps.is_empty()
+    }
+
+    /// The level at `point`. A ramp overlapping a platform takes precedence.
+    pub fn level_at(&self, point: (f32, f32)) -> Level {
+        if self.ramps.iter().any(|r| r.contains(point)) {
+            Level::Ramp
+        } else if self.platforms.iter().any(|p| p.contains(point)) {
+            Level::Platform
+        } else {
+            Level::Ground
+        }
+    }
+
+    /// Whether a robot at `from` would go over a cliff moving to `to`.
+    pub fn blocks(&self, from: (f32, f32), to: (f32, f32)) -> bool {
+        !self.is_flat() && !self.level_at(from).meets(self.level_at(to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ramps_are_the_only_way_up() {
+        let elevation = Elevation {
+            platforms: vec![Rect::new(100.0, 100.0, 100.0, 100.0)],
+            ramps: vec![Rect::new(80.0, 140.0, 30.0, 20.0)],
+        };
+        assert_eq!(elevation.level_at((50.0, 50.0)), Level::Ground);
+        assert_eq!(elevation.level_at((150.0, 150.0)), Level::Platform);
+        assert_eq!(elevation.level_at((105.0, 150.0)), Level::Ramp);
+        // Up the ramp and onto the platform, but not off its edge
+        assert!(!elevation.blocks((75.0, 150.0), (85.0, 150.0)));
+        assert!(!elevation.blocks((105.0, 150.0), (115.0, 150.0)));
+        assert!(elevation.blocks((150.0, 195.0), (150.0, 205.0)));
+        assert!(elevation.blocks((95.0, 120.0), (105.0, 120.0)));
+        assert!(Level::Ramp.meets(Level::Platform));
+        assert!(!Level::Ground.meets(Level::Platform));
+    }
+}
@@ -0,0 +1,228 @@
+// Error types for scripts and matches.
+// `ScriptError` covers everything that can be wrong with a robot script, located
+// by line and column; `SimError` covers failures setting up or running a match,
+// `VerifyError` why a bot was turned away from a tournament, `FetchError`
+// why a bot could not be downloaded, `NotifyError` why a webhook failed,
+// `HistoryError` why the match history database could not be used, and
+// `PluginError` why a plugin could not be registered.
+
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::tokenizer::Token;
+
+/// Position of a token in a script; both numbers start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Text that cannot be split into tokens.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum TokenizeError {
+    #[error("number `{0}` does not fit in a register")]
+    NumberOutOfRange(String),
+    #[error("string is missing its closing quote")]
+    UnterminatedString,
+    #[error("unexpected character `{0}`")]
+    UnexpectedCharacter(char),
+}
+
+/// Tokens that do not form valid commands.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ParseError {
+    #[error("unexpected end of script")]
+    UnexpectedEOF,
+    #[error("unexpected token {0}")]
+    UnexpectedToken(Token),
+    #[error("invalid command")]
+    InvalidCommand,
+    #[error("unknown function `{0}`")]
+    UnknownFunction(String),
+    #[error("`{function}` takes {expected} argument(s)")]
+    ArgumentCount { function: String, expected: usize },
+    #[error("`build` must come before every other command")]
+    MisplacedBuild,
+    #[error("unknown stat `{0}` (expected armor, speed, weapon or scanner)")]
+    UnknownStat(String),
+    #[error("unknown emote `{0}` (expected happy, sad, angry, surprised, confused or alert)")]
+    UnknownEmote(String),
+    #[error("unexpected {0} in a test (expected `run`, `assert` or `opponent`)")]
+    UnknownTestStep(Token),
+    #[error("a test can only have one opponent")]
+    SecondOpponent,
+}
+
+/// Commands that parse but cannot be compiled into a program.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum CompileError {
+    #[error("register `{0}` is read-only")]
+    ReadOnlyRegister(String),
+}
+
+/// Scripts that compile but use more than the sandbox allows (see `sandbox`).
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum LimitError {
+    #[error("program compiles to {size} instructions; the limit is {max}")]
+    ProgramTooLarge { size: usize, max: usize },
+    #[error("script assigns {count} variables; the limit is {max}")]
+    TooManyVariables { count: usize, max: usize },
+    #[error("script names {count} new registers, but only {room} more fit in the {max} allowed")]
+    OutOfRegisterNames {
+        count: usize,
+        room: usize,
+        max: usize,
+    },
+    #[error("blocks are nested {depth} deep; the limit is {max}")]
+    NestingTooDeep { depth: usize, max: usize },
+    #[error("build costs {cost} points; the budget is {budget}")]
+    OverBudget { cost: u32, budget: u32 },
+    #[error("{stat} level {level} is above the maximum of {max}")]
+    LevelTooHigh {
+        stat: &'static str,
+        level: u32,
+        max: u32,
+    },
+}
+
+/// Why a robot script was rejected, and where.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ScriptError {
+    #[error("{span}: {source}")]
+    Tokenize { span: Span, source: TokenizeError },
+    #[error("{span}: {source}")]
+    Parse { span: Span, source: ParseError },
+    #[error("{span}: {source}")]
+    Compile { span: Span, source: CompileError },
+    /// The whole program is over a sandbox limit, so there is no single position.
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+}
+
+impl ScriptError {
+    /// Where in the script the error was found, if it has a position.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ScriptError::Tokenize { span, .. }
+            | ScriptError::Parse { span, .. }
+            | ScriptError::Compile { span, .. } => Some(*span),
+            ScriptError::Limit(_) => None,
+        }
+    }
+}
+
+/// Why a submitted bot failed tournament verification.
+#[derive(Debug, Error)]
+pub enum VerifyError {
+    #[error("{}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("{}: {source}", path.display())]
+    Script { path: PathBuf, source: ScriptError },
+    #[error("{}: uses features the tournament rules ban: {}", path.display(), features.join(", "))]
+    Banned {
+        path: PathBuf,
+        features: Vec<&'static str>,
+    },
+}
+
+/// A plugin that could not be registered (see `Plugins::register`).
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PluginError {
+    #[error("plugin `{plugin}` was written for plugin API {found}; this is API {expected}")]
+    Version {
+        plugin: String,
+        expected: u32,
+        found: u32,
+    },
+    #[error(
+        "plugin `{plugin}` cannot add command `{command}`: the name is taken or is not a single word"
+    )]
+    CommandName { plugin: String, command: String },
+}
+
+/// The match history database failed.
+#[derive(Debug, Error)]
+#[error("match history: {0}")]
+pub struct HistoryError(#[from] rusqlite::Error);
+
+/// A webhook that could not be notified.
+#[derive(Debug, Error)]
+#[error("webhook failed: {message}")]
+pub struct NotifyError {
+    pub url: String,
+    pub message: String,
+}
+
+/// Why bots could not be fetched from a repository.
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("{location}: {message}")]
+    Download { location: String, message: String },
+    #[error("git clone {url} failed: {message}")]
+    Git { url: String, message: String },
+    #[error("{location}: invalid index: {message}")]
+    Index { location: String, message: String },
+    #[error("{name}: invalid bot name (use letters, digits, `-` and `_`)")]
+    InvalidName { name: String },
+    #[error("{name}: checksum mismatch (expected {expected}, got {actual})")]
+    Checksum {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Failed to write {}: {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+}
+
+/// Why a match could not be set up or run.
+#[derive(Debug, Error)]
+pub enum SimError {
+    #[error("Invalid config: {0}")]
+    Config(String),
+    #[error("Invalid replay: {0}")]
+    Replay(String),
+    #[error("Too many robots: at most {max} can take part")]
+    TooManyRobots { max: usize },
+    #[error("Robot {robot} (builtin:{name}): unknown built-in bot")]
+    UnknownBuiltin { robot: usize, name: String },
+    #[error("Robot {robot} ({}): {source}", path.display())]
+    ReadScript {
+        robot: usize,
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[error("Robot {robot} ({entrant}): {source}")]
+    Script {
+        robot: usize,
+        entrant: String,
+        source: ScriptError,
+    },
+    #[error(
+        "{}Match not started: fix the script errors above.",
+        .0.iter().map(|e| format!("{e}\n")).collect::<String>()
+    )]
+    ScriptsRejected(Vec<SimError>),
+    #[error("Failed to create recording {}: {source}", path.display())]
+    Recording { path: PathBuf, source: io::Error },
+    #[error("Failed to write result {}: {source}", path.display())]
+    WriteResult { path: PathBuf, source: io::Error },
+    #[error("Failed to write profile {}: {source}", path.display())]
+    WriteProfile { path: PathBuf, source: io::Error },
+    #[error("Failed to write coverage {}: {source}", path.display())]
+    WriteCoverage { path: PathBuf, source: io::Error },
+    #[error("Terminal output error: {0}")]
+    Terminal(#[from] io::Error),
+    #[error("{0}")]
+    History(#[from] HistoryError),
+    #[error("Failed to serve on {addr}: {source}")]
+    Serve { addr: String, source: io::Error },
+}
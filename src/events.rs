@@ -0,0 +1,212 @@
+// Match event log.
+// The simulation records notable moments here so frontends can show them and
+// reports can summarize a match.
+
+use serde::{Deserialize, Serialize};
+
+use crate::locale::Catalog;
+
+/// Something notable that happened during a match. Robots are referred to by id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// A robot armed its self-destruct; it detonates after `fuse` ticks.
+    SelfDestructArmed { robot: usize, fuse: u32 },
+    /// A robot detonated, damaging the robots in `hit`.
+    Detonated { robot: usize, hit: Vec<usize> },
+    /// A robot was destroyed, by `by` if another robot was responsible.
+    Destroyed { robot: usize, by: Option<usize> },
+    /// A robot was knocked back by a hit or blast caused by `by`.
+    Pushed { robot: usize, by: usize },
+    /// A survival wave of `drones` drones arrived.
+    WaveStarted { wave: u32, drones: u32 },
+    /// A robot said something with `say`.
+    Said { robot: usize, text: String },
+    /// A robot collected the salvage in the wreck of robot `wreck`.
+    Salvaged {
+        robot: usize,
+        wreck: usize,
+        ammo: i32,
+        energy: i32,
+    },
+    /// A robot collected an injected supply (see `inject`).
+    Supplied {
+        robot: usize,
+        ammo: i32,
+        energy: i32,
+    },
+    /// A robot was hit by an EMP charge from `by`, which stopped its program
+    /// and scrambled the register `scrambled`, if it had one to scramble.
+    Stunned {
+        robot: usize,
+        by: usize,
+        scrambled: Option<String>,
+    },
+    /// A robot's point defense shot down a projectile fired by robot `owner`.
+    Intercepted { robot: usize, owner: usize },
+    /// No robot had taken damage for a while, so every robot now loses health.
+    SuddenDeath,
+    /// An arena hazard landed, damaging the robots in `hit`.
+    HazardStruck { hazard: String, hit: Vec<usize> },
+    /// A destructible obstacle centered at `at` was shot to pieces, by robot
+    /// `by` if a robot did it.
+    ObstacleDestroyed { at: (f32, f32), by: Option<usize> },
+}
+
+/// An event and the tick it happened on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub tick: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+impl Event {
+    /// The event as a line of the log, in the language of `catalog`.
+    pub fn describe(&self, catalog: &Catalog) -> String {
+        let robots = |ids: &[usize]| {
+            let names: Vec<String> = ids
+                .iter()
+                .map(|id| catalog.format("event.robot", &[("id", id)]))
+                .collect();
+            names.join(", ")
+        };
+        let text = match &self.kind {
+            EventKind::SelfDestructArmed { robot, fuse } => catalog.format(
+                "event.self_destruct_armed",
+                &[("robot", robot), ("fuse", fuse)],
+            ),
+            EventKind::Detonated { robot, hit } if hit.is_empty() => {
+                catalog.format("event.detonated_nothing", &[("robot", robot)])
+            }
+            EventKind::Detonated { robot, hit } => catalog.format(
+                "event.detonated",
+                &[("robot", robot), ("hit", &robots(hit))],
+            ),
+            EventKind::Destroyed {
+                robot,
+                by: Some(by),
+            } => catalog.format("event.destroyed_by", &[("robot", robot), ("by", by)]),
+            EventKind::Destroyed { robot, by: None } => {
+                catalog.format("event.destroyed", &[("robot", robot)])
+            }
+            EventKind::Pushed { robot, by } => {
+                catalog.format("event.pushed", &[("robot", robot), ("by", by)])
+            }
+            EventKind::WaveStarted { wave, drones } => {
+                catalog.format("event.wave_started", &[("wave", wave), ("drones", drones)])
+            }
+            EventKind::Said { robot, text } => {
+                catalog.format("event.said", &[("robot", robot), ("text", text)])
+            }
+            EventKind::Salvaged {
+                robot,
+                wreck,
+                ammo,
+                energy,
+            } => catalog.format(
+                "event.salvaged",
+                &[
+                    ("robot", robot),
+                    ("ammo", ammo),
+                    ("energy", energy),
+                    ("wreck", wreck),
+                ],
+            ),
+            EventKind::Supplied {
+                robot,
+                ammo,
+                energy,
+            } => catalog.format(
+                "event.supplied",
+                &[("robot", robot), ("ammo", ammo), ("energy", energy)],
+            ),
+            EventKind::Stunned {
+                robot,
+                by,
+                scrambled: Some(reg),
+            } => catalog.format(
+                "event.stunned_scrambled",
+                &[("robot", robot), ("by", by), ("register", reg)],
+            ),
+            EventKind::Stunned {
+                robot,
+                by,
+                scrambled: None,
+            } => catalog.format("event.stunned", &[("robot", robot), ("by", by)]),
+            EventKind::Intercepted { robot, owner } => {
+                catalog.format("event.intercepted", &[("robot", robot), ("owner", owner)])
+            }
+            EventKind::SuddenDeath => catalog.text("event.sudden_death").to_string(),
+            EventKind::ObstacleDestroyed { at, by } => {
+                let (x, y) = (format!("{:.0}", at.0), format!("{:.0}", at.1));
+                match by {
+                    Some(by) => catalog.format(
+                        "event.obstacle_destroyed_by",
+                        &[("by", by), ("x", &x), ("y", &y)],
+                    ),
+                    None => catalog.format("event.obstacle_destroyed", &[("x", &x), ("y", &y)]),
+                }
+            }
+            EventKind::HazardStruck { hazard, hit } => {
+                // Hazards are logged by their English name, kept when untranslated
+                let key = format!("hazard.{}", hazard.replace(' ', "_"));
+                let hazard = match catalog.text(&key) {
+                    text if text == key => hazard.as_str(),
+                    text => text,
+                };
+                if hit.is_empty() {
+                    catalog.format("event.hazard_missed", &[("hazard", &hazard)])
+                } else {
+                    catalog.format(
+                        "event.hazard_struck",
+                        &[("hazard", &hazard), ("hit", &robots(hit))],
+                    )
+                }
+            }
+        };
+        format!("[{:>5}] {}", self.tick, text)
+    }
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe(Catalog::english()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_display() {
+        let event = Event {
+            tick: 42,
+            kind: EventKind::Detonated {
+                robot: 1,
+                hit: vec![2, 3],
+            },
+        };
+        assert_eq!(
+            event.to_string(),
+            "[   42] Robot 1 detonated, hitting Robot 2, Robot 3"
+        );
+        let event = Event {
+            tick: 7,
+            kind: EventKind::Destroyed {
+                robot: 2,
+                by: Some(1),
+            },
+        };
+        assert_eq!(event.to_string(), "[    7] Robot 2 destroyed by Robot 1");
+        let event = Event {
+            tick: 9,
+            kind: EventKind::Said {
+                robot: 3,
+                text: "reloading".to_string(),
+            },
+        };
+        assert_eq!(event.to_string(), "[    9] Robot 3: \"reloading\"");
+    }
+}
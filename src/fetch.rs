@@ -0,0 +1,283 @@
+// Downloads community bots into a local bots directory.
+// A bot repository is an index file that lists each bot's name, the location of
+// its script and the script's SHA-256. The index is read from a URL or a local
+// path, or from `index.toml` at the root of a git repository. Every script is
+// checked against its hash before it is written, so a tampered or truncated
+// download never lands in the bots directory.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::error::FetchError;
+use crate::verify::sha256_hex;
+
+/// Index file looked up at the root of a git repository.
+pub const GIT_INDEX: &str = "index.toml";
+/// Largest file read from a URL; anything longer is cut off and fails its checksum.
+const MAX_DOWNLOAD: u64 = 1 << 20;
+
+/// Bots offered by a repository.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Index {
+    #[serde(rename = "bot", default)]
+    pub bots: Vec<IndexEntry>,
+}
+
+/// One bot in an index.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexEntry {
+    /// Name the script is saved under, as `<name>.robo`.
+    pub name: String,
+    /// URL or path of the script; relative paths are resolved against the index.
+    pub path: String,
+    /// SHA-256 of the script, in hex.
+    pub sha256: String,
+}
+
+/// Where bots are fetched from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// A git repository with an `index.toml` at its root.
+    Git(String),
+    /// An index file, by URL or local path.
+    Index(String),
+}
+
+impl Source {
+    /// Tell git repositories (`git@…`, `git://…` or ending in `.git`) from index files.
+    pub fn parse(location: &str) -> Source {
+        let git = location.starts_with("git@")
+            || location.starts_with("git://")
+            || location.trim_end_matches('/').ends_with(".git");
+        if git {
+            Source::Git(location.to_string())
+        } else {
+            Source::Index(location.to_string())
+        }
+    }
+}
+
+/// What happened to one bot listed in the index.
+#[derive(Debug)]
+pub struct Fetched {
+    pub name: String,
+    /// Where the script was saved, or why it was not.
+    pub result: Result<PathBuf, FetchError>,
+}
+
+/// Fetch every bot in `source` into `dir`, creating the directory if needed.
+/// Fails only if the index cannot be read; bots that fail on their own are
+/// reported in the returned list and the rest are still fetched.
+pub fn fetch(source: &Source, dir: &Path) -> Result<Vec<Fetched>, FetchError> {
+    match source {
+        Source::Index(location) => fetch_index(location, dir),
+        Source::Git(url) => {
+            let checkout = clone(url)?;
+            let index = checkout.join(GIT_INDEX);
+            let fetched = fetch_index(&index.to_string_lossy(), dir);
+            let _ = fs::remove_dir_all(&checkout);
+            fetched
+        }
+    }
+}
+
+/// Fetch the bots listed in the index at `location` into `dir`.
+pub fn fetch_index(location: &str, dir: &Path) -> Result<Vec<Fetched>, FetchError> {
+    let text = read_location(location)?;
+    let index: Index =
+        toml::from_str(&String::from_utf8_lossy(&text)).map_err(|e| FetchError::Index {
+            location: location.to_string(),
+            message: e.message().to_string(),
+        })?;
+    fs::create_dir_all(dir).map_err(|source| FetchError::Write {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    Ok(index
+        .bots
+        .iter()
+        .map(|entry| Fetched {
+            name: entry.name.clone(),
+            result: fetch_bot(entry, location, dir),
+        })
+        .collect())
+}
+
+/// Download one bot, check its hash and save it.
+fn fetch_bot(entry: &IndexEntry, index: &str, dir: &Path) -> Result<PathBuf, FetchError> {
+    let valid = !entry.name.is_empty()
+        && entry
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(FetchError::InvalidName {
+            name: entry.name.clone(),
+        });
+    }
+    let bytes = read_location(&resolve(index, &entry.path))?;
+    let actual = sha256_hex(&bytes);
+    if !actual.eq_ignore_ascii_case(entry.sha256.trim()) {
+        return Err(FetchError::Checksum {
+            name: entry.name.clone(),
+            expected: entry.sha256.trim().to_string(),
+            actual,
+        });
+    }
+    let path = dir.join(format!("{}.robo", entry.name));
+    fs::write(&path, bytes).map_err(|source| FetchError::Write {
+        path: path.clone(),
+        source,
+    })?;
+    Ok(path)
+}
+
+/// Shallow-clone a git repository into a fresh temporary directory.
+fn clone(url: &str) -> Result<PathBuf, FetchError> {
+    let checkout = std::env::temp_dir().join(format!("robot-battle-fetch-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&checkout);
+    let git_error = |message: String| FetchError::Git {
+        url: url.to_string(),
+        message,
+    };
+    let output = Command::new("git")
+        .args(["clone", "--depth", "1", "--quiet", "--", url])
+        .arg(&checkout)
+        .output()
+        .map_err(|e| git_error(e.to_string()))?;
+    if !output.status.success() {
+        let _ = fs::remove_dir_all(&checkout);
+        return Err(git_error(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(checkout)
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Read a URL or local file.
+fn read_location(location: &str) -> Result<Vec<u8>, FetchError> {
+    let download_error = |message: String| FetchError::Download {
+        location: location.to_string(),
+        message,
+    };
+    if !is_url(location) {
+        return fs::read(location).map_err(|e| download_error(e.to_string()));
+    }
+    let response = ureq::get(location)
+        .call()
+        .map_err(|e| download_error(e.to_string()))?;
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD)
+        .read_to_end(&mut bytes)
+        .map_err(|e| download_error(e.to_string()))?;
+    Ok(bytes)
+}
+
+/// Location of `path` as listed in the index at `base`: URLs are used as they
+/// are, and relative paths are taken relative to the index's directory.
+pub fn resolve(base: &str, path: &str) -> String {
+    if is_url(path) || Path::new(path).is_absolute() {
+        return path.to_string();
+    }
+    if is_url(base) {
+        return match base.rfind('/') {
+            Some(end) => format!("{}/{}", &base[..end], path),
+            None => path.to_string(),
+        };
+    }
+    let dir = Path::new(base).parent().unwrap_or(Path::new(""));
+    dir.join(path).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_kinds() {
+        for git in [
+            "https://example.com/bots.git",
+            "git@example.com:team/bots.git",
+            "git://example.com/bots",
+        ] {
+            assert_eq!(Source::parse(git), Source::Git(git.to_string()));
+        }
+        assert_eq!(
+            Source::parse("https://example.com/index.toml"),
+            Source::Index("https://example.com/index.toml".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve_relative_to_index() {
+        assert_eq!(
+            resolve("https://example.com/bots/index.toml", "tracker.robo"),
+            "https://example.com/bots/tracker.robo"
+        );
+        assert_eq!(
+            resolve(
+                "https://example.com/index.toml",
+                "https://cdn.example.com/a.robo"
+            ),
+            "https://cdn.example.com/a.robo"
+        );
+        assert_eq!(
+            resolve("repo/index.toml", "bots/a.robo"),
+            Path::new("repo").join("bots/a.robo").to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn test_fetch_checks_hashes_and_names() {
+        let root =
+            std::env::temp_dir().join(format!("robot-battle-fetch-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("scripts")).unwrap();
+        let script = "loop {\n scan\n fire\n}\n";
+        fs::write(root.join("scripts/gunner.robo"), script).unwrap();
+        let index = format!(
+            "[[bot]]\nname = \"gunner\"\npath = \"scripts/gunner.robo\"\nsha256 = \"{hash}\"\n\n\
+             [[bot]]\nname = \"tampered\"\npath = \"scripts/gunner.robo\"\nsha256 = \"{bad}\"\n\n\
+             [[bot]]\nname = \"../escape\"\npath = \"scripts/gunner.robo\"\nsha256 = \"{hash}\"\n",
+            hash = sha256_hex(script.as_bytes()),
+            bad = "0".repeat(64),
+        );
+        fs::write(root.join("index.toml"), index).unwrap();
+
+        let dir = root.join("bots");
+        let source = Source::parse(&root.join("index.toml").to_string_lossy());
+        let fetched = fetch(&source, &dir).unwrap();
+        assert_eq!(fetched.len(), 3);
+        let saved = fetched[0].result.as_ref().unwrap();
+        assert_eq!(saved, &dir.join("gunner.robo"));
+        assert_eq!(fs::read_to_string(saved).unwrap(), script);
+        assert!(matches!(
+            fetched[1].result,
+            Err(FetchError::Checksum { .. })
+        ));
+        assert!(!dir.join("tampered.robo").exists());
+        assert!(matches!(
+            fetched[2].result,
+            Err(FetchError::InvalidName { .. })
+        ));
+
+        fs::write(root.join("index.toml"), "[[bot]]\nname = \"x\"\n").unwrap();
+        assert!(matches!(
+            fetch(&source, &dir),
+            Err(FetchError::Index { .. })
+        ));
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,403 @@
+// Script structure as a graph, for `robot-battle graph`.
+// Two views of one script: the tree of commands as parsed, with the commands
+// of each block under the loop or if that holds it, and the control-flow graph
+// of the compiled program, whose nodes are basic blocks of VM instructions and
+// whose edges are the jumps between them. Both render as Graphviz DOT or as
+// Mermaid, so they can be turned into pictures or pasted into Markdown.
+
+use crate::ast::{Command, Instruction, translate_commands_to_instructions};
+use crate::error::ScriptError;
+use crate::parser::parse_script_with_spans;
+use crate::profile::command_text;
+use crate::registers::Reg;
+
+/// Output format of a graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT, for `dot -Tsvg`.
+    #[default]
+    Dot,
+    /// Mermaid flowchart, for Markdown that renders it.
+    Mermaid,
+}
+
+impl GraphFormat {
+    /// Parse a `--format` value.
+    pub fn from_name(name: &str) -> Option<GraphFormat> {
+        match name {
+            "dot" => Some(GraphFormat::Dot),
+            "mermaid" => Some(GraphFormat::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+/// A node of the command tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeNode {
+    /// The command as written, or `else` for the else block of an if.
+    pub label: String,
+    /// The loop, if or else holding the command; `None` at the top level.
+    pub parent: Option<usize>,
+}
+
+/// A run of instructions that is only entered at its first and left after
+/// its last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    /// Index of the first instruction in the program.
+    pub start: usize,
+    pub instructions: Vec<Instruction>,
+}
+
+/// Where control goes from the end of a basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Block(usize),
+    /// The program has run to its end and the robot halts.
+    End,
+}
+
+/// An edge of the control-flow graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: Target,
+    /// The condition the edge is taken on, if it is not always taken.
+    pub label: Option<String>,
+}
+
+/// Command tree and control-flow graph of one script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptGraph {
+    pub name: String,
+    pub tree: Vec<TreeNode>,
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+impl ScriptGraph {
+    /// Parse and compile `script`, named `name` in the output.
+    pub fn new(name: &str, script: &str) -> Result<ScriptGraph, ScriptError> {
+        let (commands, spans) = parse_script_with_spans(script)?;
+        let lines: Vec<&str> = script.lines().collect();
+        let mut labels = (0..spans.len()).map(|i| {
+            let text = command_text(&lines, spans[i], spans.get(i + 1));
+            text.trim_end_matches('{').trim_end().to_string()
+        });
+        let mut tree = Vec::new();
+        add_commands(&commands, None, &mut labels, &mut tree);
+
+        let program = translate_commands_to_instructions(&commands);
+        let (blocks, edges) = control_flow(&program);
+        Ok(ScriptGraph {
+            name: name.to_string(),
+            tree,
+            blocks,
+            edges,
+        })
+    }
+
+    /// The graph in `format`.
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.dot(),
+            GraphFormat::Mermaid => self.mermaid(),
+        }
+    }
+
+    /// The graph in Graphviz DOT, with the command tree and the control-flow
+    /// graph side by side.
+    pub fn dot(&self) -> String {
+        let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut out = format!("digraph {} {{\n", quote(&self.name));
+        out += "  subgraph cluster_commands {\n    label=\"commands\";\n";
+        out += &format!(
+            "    script [label={} shape=plaintext];\n",
+            quote(&self.name)
+        );
+        for (i, node) in self.tree.iter().enumerate() {
+            out += &format!("    c{} [label={} shape=box];\n", i, quote(&node.label));
+        }
+        for (i, node) in self.tree.iter().enumerate() {
+            let parent = node
+                .parent
+                .map_or("script".to_string(), |p| format!("c{}", p));
+            out += &format!("    {} -> c{};\n", parent, i);
+        }
+        out += "  }\n";
+
+        out += "  subgraph cluster_instructions {\n    label=\"instructions\";\n";
+        out += "    node [shape=box fontname=monospace];\n";
+        for (i, block) in self.blocks.iter().enumerate() {
+            // `\l` ends a left-aligned line
+            let text: String = block_lines(block)
+                .iter()
+                .map(|line| format!("{}\\l", line.replace('\\', "\\\\").replace('"', "\\\"")))
+                .collect();
+            out += &format!("    b{} [label=\"{}\"];\n", i, text);
+        }
+        if self.edges.iter().any(|e| e.to == Target::End) {
+            out += "    end [label=\"end\" shape=oval];\n";
+        }
+        for edge in &self.edges {
+            let label = edge
+                .label
+                .as_ref()
+                .map_or(String::new(), |l| format!(" [label={}]", quote(l)));
+            out += &format!("    b{} -> {}{};\n", edge.from, dot_target(edge.to), label);
+        }
+        out += "  }\n}\n";
+        out
+    }
+
+    /// The graph as a Mermaid flowchart, with a subgraph for the command tree
+    /// and one for the control-flow graph.
+    pub fn mermaid(&self) -> String {
+        // Mermaid has no escapes inside quoted labels, only HTML entities
+        let quote = |text: &str| format!("\"{}\"", text.replace('"', "#quot;"));
+        let mut out = "flowchart TD\n".to_string();
+        out += "  subgraph commands\n";
+        out += &format!("    script([{}])\n", quote(&self.name));
+        for (i, node) in self.tree.iter().enumerate() {
+            out += &format!("    c{}[{}]\n", i, quote(&node.label));
+        }
+        for (i, node) in self.tree.iter().enumerate() {
+            let parent = node
+                .parent
+                .map_or("script".to_string(), |p| format!("c{}", p));
+            out += &format!("    {} --> c{}\n", parent, i);
+        }
+        out += "  end\n";
+
+        out += "  subgraph instructions\n";
+        for (i, block) in self.blocks.iter().enumerate() {
+            out += &format!("    b{}[{}]\n", i, quote(&block_lines(block).join("<br/>")));
+        }
+        if self.edges.iter().any(|e| e.to == Target::End) {
+            out += "    halt([end])\n";
+        }
+        for edge in &self.edges {
+            let to = match edge.to {
+                Target::Block(b) => format!("b{}", b),
+                Target::End => "halt".to_string(),
+            };
+            match &edge.label {
+                Some(label) => out += &format!("    b{} -->|{}| {}\n", edge.from, quote(label), to),
+                None => out += &format!("    b{} --> {}\n", edge.from, to),
+            }
+        }
+        out += "  end\n";
+        out
+    }
+}
+
+fn dot_target(target: Target) -> String {
+    match target {
+        Target::Block(b) => format!("b{}", b),
+        Target::End => "end".to_string(),
+    }
+}
+
+/// The instructions of `block`, one per line, numbered by their place in the
+/// program.
+fn block_lines(block: &BasicBlock) -> Vec<String> {
+    block
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(i, instruction)| format!("{:>3}  {}", block.start + i, instruction))
+        .collect()
+}
+
+/// Add `block` to `tree` under `parent`, taking each command's label from
+/// `labels` in source order.
+fn add_commands(
+    block: &[Command],
+    parent: Option<usize>,
+    labels: &mut impl Iterator<Item = String>,
+    tree: &mut Vec<TreeNode>,
+) {
+    for command in block {
+        let index = tree.len();
+        tree.push(TreeNode {
+            label: labels.next().unwrap_or_default(),
+            parent,
+        });
+        match command {
+            Command::Loop { block } => add_commands(block, Some(index), labels, tree),
+            Command::If {
+                block, else_block, ..
+            } => {
+                add_commands(block, Some(index), labels, tree);
+                if let Some(else_block) = else_block {
+                    let else_index = tree.len();
+                    tree.push(TreeNode {
+                        label: "else".to_string(),
+                        parent: Some(index),
+                    });
+                    add_commands(else_block, Some(else_index), labels, tree);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Split `program` into basic blocks and find the edges between them. A block
+/// starts at the first instruction, at every label and after every jump.
+fn control_flow(program: &[Instruction]) -> (Vec<BasicBlock>, Vec<Edge>) {
+    let mut starts: Vec<usize> = vec![0];
+    for (i, instruction) in program.iter().enumerate() {
+        match instruction {
+            Instruction::Label(_) if i > 0 => starts.push(i),
+            Instruction::Jnz { .. } if i + 1 < program.len() => starts.push(i + 1),
+            _ => {}
+        }
+    }
+    starts.dedup();
+    if program.is_empty() {
+        starts.clear();
+    }
+
+    let blocks: Vec<BasicBlock> = starts
+        .iter()
+        .enumerate()
+        .map(|(b, &start)| {
+            let end = starts.get(b + 1).copied().unwrap_or(program.len());
+            BasicBlock {
+                start,
+                instructions: program[start..end].to_vec(),
+            }
+        })
+        .collect();
+    let block_of = |label: &str| {
+        blocks.iter().position(
+            |block| matches!(block.instructions.first(), Some(Instruction::Label(l)) if l == label),
+        )
+    };
+    let next = |b: usize| {
+        if b + 1 < blocks.len() {
+            Target::Block(b + 1)
+        } else {
+            Target::End
+        }
+    };
+
+    let mut edges = Vec::new();
+    for (b, block) in blocks.iter().enumerate() {
+        match block.instructions.last() {
+            Some(Instruction::Jnz { reg, label }) => {
+                // A jump to a label that does not exist falls through
+                let target = block_of(label).map_or(next(b), Target::Block);
+                if *reg == Reg::ALWAYS {
+                    edges.push(Edge {
+                        from: b,
+                        to: target,
+                        label: None,
+                    });
+                } else {
+                    edges.push(Edge {
+                        from: b,
+                        to: target,
+                        label: Some(format!("{} != 0", reg)),
+                    });
+                    edges.push(Edge {
+                        from: b,
+                        to: next(b),
+                        label: Some(format!("{} == 0", reg)),
+                    });
+                }
+            }
+            _ => edges.push(Edge {
+                from: b,
+                to: next(b),
+                label: None,
+            }),
+        }
+    }
+    (blocks, edges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str =
+        "scan\nloop {\n  if scan > 0 {\n    fire\n  } else {\n    rotate scanner 10\n  }\n}";
+
+    #[test]
+    fn test_command_tree() {
+        let graph = ScriptGraph::new("hunter", SCRIPT).unwrap();
+        let nodes: Vec<(&str, Option<usize>)> = graph
+            .tree
+            .iter()
+            .map(|n| (n.label.as_str(), n.parent))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("scan", None),
+                ("loop", None),
+                ("if scan > 0", Some(1)),
+                ("fire", Some(2)),
+                ("else", Some(2)),
+                ("rotate scanner 10", Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let graph = ScriptGraph::new("hunter", SCRIPT).unwrap();
+        let program: Vec<Instruction> = graph
+            .blocks
+            .iter()
+            .flat_map(|b| b.instructions.clone())
+            .collect();
+        assert_eq!(program, {
+            let (commands, _) = parse_script_with_spans(SCRIPT).unwrap();
+            translate_commands_to_instructions(&commands)
+        });
+        // Every block but the first starts at a label or right after a jump
+        for block in &graph.blocks[1..] {
+            let before = &program[block.start - 1];
+            assert!(
+                matches!(block.instructions[0], Instruction::Label(_))
+                    || matches!(before, Instruction::Jnz { .. })
+            );
+        }
+        // The loop never ends, so nothing reaches the end of the program
+        assert!(graph.edges.iter().all(|e| e.to != Target::End));
+        let conditional = graph.edges.iter().filter(|e| e.label.is_some()).count();
+        // The if's test and the scanner turn's counter
+        assert_eq!(conditional, 4);
+
+        let straight = ScriptGraph::new("once", "scan\nfire").unwrap();
+        assert_eq!(straight.blocks.len(), 1);
+        assert_eq!(
+            straight.edges,
+            vec![Edge {
+                from: 0,
+                to: Target::End,
+                label: None
+            }]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let graph = ScriptGraph::new("say \"hi\"", "say \"hi\"\nfire").unwrap();
+        let dot = graph.render(GraphFormat::Dot);
+        assert!(dot.starts_with("digraph \"say \\\"hi\\\"\" {\n"));
+        assert!(dot.contains("    c0 [label=\"say \\\"hi\\\"\" shape=box];\n"));
+        assert!(dot.contains("    script -> c1;\n"));
+        assert!(dot.contains("    b0 -> end;\n"));
+
+        let mermaid = graph.render(GraphFormat::Mermaid);
+        assert!(mermaid.starts_with("flowchart TD\n  subgraph commands\n"));
+        assert!(mermaid.contains("    c0[\"say #quot;hi#quot;\"]\n"));
+        assert!(mermaid.contains("    b0 --> halt\n"));
+        assert_eq!(GraphFormat::from_name("svg"), None);
+    }
+}
@@ -0,0 +1,268 @@
+// Arena hazards: meteor strikes and laser grids an arena file sets off on a
+// timer. Every strike is telegraphed `warning` ticks before it lands, so a
+// robot watching its `hazard_warning` register has time to get out of the way;
+// robots still in the struck area when it lands take the hazard's damage.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::rng::Rng;
+
+/// A hazard that strikes every `every` ticks, `warning` ticks after it is
+/// telegraphed, dealing `damage` to each robot whose center is in the area.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Hazard {
+    /// A meteor landing at a random spot, hitting every robot within `radius`.
+    Meteor {
+        every: u64,
+        warning: u64,
+        damage: i32,
+        radius: f32,
+    },
+    /// Parallel laser lines `spacing` apart across the whole arena, each
+    /// `width` wide. Each strike the lines have moved `drift` further across.
+    LaserGrid {
+        every: u64,
+        warning: u64,
+        damage: i32,
+        axis: Axis,
+        spacing: f32,
+        width: f32,
+        #[serde(default)]
+        drift: f32,
+    },
+}
+
+/// Which way laser grid lines run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Axis {
+    /// Lines of constant y.
+    Horizontal,
+    /// Lines of constant x.
+    Vertical,
+}
+
+/// A telegraphed strike of hazard number `hazard`: where it lands, and when.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strike {
+    pub hazard: usize,
+    pub area: Area,
+    pub lands_at: u64,
+}
+
+/// The part of the arena a strike hits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    Circle {
+        center: (f32, f32),
+        radius: f32,
+    },
+    /// Lines along `axis` at `offset`, `offset + spacing` and so on.
+    Lines {
+        axis: Axis,
+        offset: f32,
+        spacing: f32,
+        width: f32,
+    },
+}
+
+impl Hazard {
+    /// Name used in the event log.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hazard::Meteor { .. } => "meteor strike",
+            Hazard::LaserGrid { .. } => "laser grid",
+        }
+    }
+
+    pub fn damage(&self) -> i32 {
+        match self {
+            Hazard::Meteor { damage, .. } | Hazard::LaserGrid { damage, .. } => *damage,
+        }
+    }
+
+    /// The strike this hazard, number `index` in its arena, telegraphs at
+    /// `tick`, if it is due one. Meteors land where `rng` says.
+    pub fn telegraph(
+        &self,
+        index: usize,
+        tick: u64,
+        rng: &mut Rng,
+        config: &GameConfig,
+    ) -> Option<Strike> {
+        let (every, warning) = match self {
+            Hazard::Meteor { every, warning, .. } | Hazard::LaserGrid { every, warning, .. } => {
+                ((*every).max(1), *warning)
+            }
+        };
+        if tick == 0 || !tick.is_multiple_of(every) {
+            return None;
+        }
+        let area = match *self {
+            Hazard::Meteor { radius, .. } => Area::Circle {
+                center: (
+                    rng.range(0, config.arena_width as i32) as f32,
+                    rng.range(0, config.arena_height as i32) as f32,
+                ),
+                radius,
+            },
+            Hazard::LaserGrid {
+                axis,
+                spacing,
+                width,
+                drift,
+                ..
+            } => {
+                let spacing = spacing.max(1.0);
+                Area::Lines {
+                    axis,
+                    offset: ((tick / every) as f32 * drift).rem_euclid(spacing),
+                    spacing,
+                    width,
+                }
+            }
+        };
+        Some(Strike {
+            hazard: index,
+            area,
+            lands_at: tick + warning,
+        })
+    }
+}
+
+impl Area {
+    /// Whether `position` is inside the area.
+    pub fn contains(&self, position: (f32, f32), config: &GameConfig) -> bool {
+        match *self {
+            Area::Circle { center, radius } => {
+                let (dx, dy) = config.displacement(center, position);
+                dx * dx + dy * dy <= radius * radius
+            }
+            Area::Lines { width, spacing, .. } => {
+                let across = self.across_line(position);
+                across.min(spacing - across) <= width / 2.0
+            }
+        }
+    }
+
+    /// Bearing, in degrees, of the quickest way out of the area from
+    /// `position`: away from a meteor's center, or across a laser line.
+    pub fn escape_bearing(&self, position: (f32, f32), config: &GameConfig) -> i32 {
+        let bearing = match *self {
+            Area::Circle { center, .. } => {
+                let (dx, dy) = config.displacement(center, position);
+                config.math.atan2(dy, dx).to_degrees()
+            }
+            Area::Lines { axis, spacing, .. } => {
+                // Past the middle of the line: carry on the same way
+                let forward = self.across_line(position) <= spacing / 2.0;
+                match (axis, forward) {
+                    (Axis::Horizontal, true) => 90.0,
+                    (Axis::Horizontal, false) => 270.0,
+                    (Axis::Vertical, true) => 0.0,
+                    (Axis::Vertical, false) => 180.0,
+                }
+            }
+        };
+        (bearing.round() as i32).rem_euclid(360)
+    }
+
+    /// Where the middle of each of the area's lines crosses the arena, for
+    /// drawing: y for horizontal lines, x for vertical ones. None for a circle.
+    pub fn line_offsets(&self, config: &GameConfig) -> Vec<f32> {
+        let Area::Lines {
+            axis,
+            offset,
+            spacing,
+            ..
+        } = *self
+        else {
+            return Vec::new();
+        };
+        let extent = match axis {
+            Axis::Horizontal => config.arena_height,
+            Axis::Vertical => config.arena_width,
+        };
+        // Start one line early, in case its edge reaches into the arena
+        let mut offsets = Vec::new();
+        let mut at = offset - spacing;
+        while at <= extent + spacing {
+            offsets.push(at);
+            at += spacing;
+        }
+        offsets
+    }
+
+    /// How far `position` is past the nearest line before it, for lines.
+    fn across_line(&self, position: (f32, f32)) -> f32 {
+        let Area::Lines {
+            axis,
+            offset,
+            spacing,
+            ..
+        } = *self
+        else {
+            return 0.0;
+        };
+        let coordinate = match axis {
+            Axis::Horizontal => position.1,
+            Axis::Vertical => position.0,
+        };
+        (coordinate - offset).rem_euclid(spacing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_laser_grid_lines_drift_each_strike() {
+        let grid = Hazard::LaserGrid {
+            every: 100,
+            warning: 20,
+            damage: 2,
+            axis: Axis::Vertical,
+            spacing: 80.0,
+            width: 10.0,
+            drift: 30.0,
+        };
+        let config = GameConfig::default();
+        let mut rng = Rng::stream(0, 0);
+        assert_eq!(grid.telegraph(0, 0, &mut rng, &config), None);
+        assert_eq!(grid.telegraph(0, 150, &mut rng, &config), None);
+
+        let strike = grid.telegraph(3, 200, &mut rng, &config).unwrap();
+        assert_eq!((strike.hazard, strike.lands_at), (3, 220));
+        // Two strikes in, the lines sit 60 units over
+        let area = strike.area;
+        assert!(area.contains((60.0, 10.0), &config));
+        assert!(area.contains((144.0, 300.0), &config));
+        assert!(!area.contains((100.0, 10.0), &config));
+        assert_eq!(area.escape_bearing((62.0, 10.0), &config), 0);
+        assert_eq!(area.escape_bearing((138.0, 10.0), &config), 180);
+    }
+
+    #[test]
+    fn test_meteor_lands_in_the_arena() {
+        let meteor = Hazard::Meteor {
+            every: 50,
+            warning: 25,
+            damage: 3,
+            radius: 30.0,
+        };
+        let config = GameConfig::default();
+        let mut rng = Rng::stream(7, usize::MAX);
+        let strike = meteor.telegraph(0, 50, &mut rng, &config).unwrap();
+        let Area::Circle { center, radius } = strike.area else {
+            panic!("a meteor strikes a circle");
+        };
+        assert_eq!(radius, 30.0);
+        assert!((0.0..=config.arena_width).contains(&center.0));
+        assert!(strike.area.contains(center, &config));
+        let east = (center.0 + 10.0, center.1);
+        assert_eq!(strike.area.escape_bearing(east, &config), 0);
+    }
+}
@@ -0,0 +1,320 @@
+// Match history kept in an SQLite database, for leagues that play for weeks.
+// Every recorded match stores its mode, outcome, length and replay path, with
+// one row per participant holding that robot's final stats. Queries list past
+// matches and sum up a bot's record, overall or against one opponent.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, params};
+
+use crate::error::HistoryError;
+use crate::results::MatchResult;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS matches (
+    id INTEGER PRIMARY KEY,
+    played_at INTEGER NOT NULL,
+    mode TEXT NOT NULL,
+    end_condition TEXT NOT NULL,
+    winner TEXT,
+    seed INTEGER,
+    ticks INTEGER NOT NULL,
+    duration_secs REAL NOT NULL,
+    wave INTEGER,
+    replay TEXT
+);
+CREATE TABLE IF NOT EXISTS participants (
+    match_id INTEGER NOT NULL REFERENCES matches(id),
+    robot_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    team INTEGER NOT NULL,
+    alive INTEGER NOT NULL,
+    health INTEGER NOT NULL,
+    kills INTEGER NOT NULL,
+    shots_fired INTEGER NOT NULL,
+    score INTEGER NOT NULL,
+    PRIMARY KEY (match_id, robot_id)
+);
+CREATE INDEX IF NOT EXISTS participants_by_name ON participants(name);
+";
+
+/// Matches with `?1` among the participants, and `?2` too unless it is null.
+const MATCHES_OF: &str = "
+    (?1 IS NULL OR EXISTS (SELECT 1 FROM participants p WHERE p.match_id = m.id AND p.name = ?1))
+    AND (?2 IS NULL OR EXISTS (SELECT 1 FROM participants p WHERE p.match_id = m.id AND p.name = ?2))
+";
+
+/// One past match, as listed by `history`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchRow {
+    pub id: i64,
+    /// When the match was recorded, as UTC `YYYY-MM-DD HH:MM:SS`.
+    pub played_at: String,
+    pub mode: String,
+    /// Name of the winning robot; `None` for a draw.
+    pub winner: Option<String>,
+    pub ticks: u64,
+    /// Robot names in spawn order.
+    pub participants: Vec<String>,
+    pub replay: Option<String>,
+}
+
+/// Wins, draws and losses of a bot over some matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Record {
+    pub played: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Record {
+    /// Share of the matches won, in percent; 0 before the first match.
+    pub fn win_rate(&self) -> f64 {
+        if self.played == 0 {
+            return 0.0;
+        }
+        f64::from(self.wins) * 100.0 / f64::from(self.played)
+    }
+
+    /// Count one more match, won by `winner` (`None` for a draw), for `bot`.
+    pub fn add(&mut self, bot: &str, winner: Option<&str>) {
+        self.played += 1;
+        match winner {
+            Some(w) if w == bot => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+    }
+}
+
+/// An open match history database.
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    /// Open the database at `path`, creating it and its tables if needed.
+    pub fn open(path: &Path) -> Result<Self, HistoryError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(History { conn })
+    }
+
+    /// Store a finished match and its participants, with the replay saved at
+    /// `replay`, if any. Returns the id of the match in the history.
+    pub fn record(&self, result: &MatchResult, replay: Option<&Path>) -> Result<i64, HistoryError> {
+        let played_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let winner = result
+            .winner
+            .and_then(|id| result.robots.iter().find(|r| r.id == id))
+            .map(|r| r.name.as_str());
+        let end_condition = serde_json::to_value(result.end_condition)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default();
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO matches (played_at, mode, end_condition, winner, seed, ticks, \
+             duration_secs, wave, replay) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                played_at,
+                result.mode,
+                end_condition,
+                winner,
+                result.seed.map(|s| s as i64),
+                result.ticks as i64,
+                result.duration_secs,
+                result.wave,
+                replay.map(|p| p.to_string_lossy().into_owned()),
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        for robot in &result.robots {
+            tx.execute(
+                "INSERT INTO participants (match_id, robot_id, name, team, alive, health, \
+                 kills, shots_fired, score) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    id,
+                    robot.id as i64,
+                    robot.name,
+                    robot.team as i64,
+                    robot.alive,
+                    robot.health,
+                    robot.kills,
+                    robot.shots_fired,
+                    robot.score,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// The last `limit` matches `bot` played (against `opponent`, if given),
+    /// oldest first; every match if `bot` is `None`.
+    pub fn matches(
+        &self,
+        bot: Option<&str>,
+        opponent: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<MatchRow>, HistoryError> {
+        let sql = format!(
+            "SELECT m.id, datetime(m.played_at, 'unixepoch'), m.mode, m.winner, m.ticks, m.replay, \
+             (SELECT group_concat(name, char(31)) FROM \
+              (SELECT name FROM participants p WHERE p.match_id = m.id ORDER BY p.robot_id)) \
+             FROM matches m WHERE {} ORDER BY m.id DESC LIMIT ?3",
+            MATCHES_OF
+        );
+        let mut statement = self.conn.prepare(&sql)?;
+        let rows = statement.query_map(params![bot, opponent, limit as i64], |row| {
+            let participants: Option<String> = row.get(6)?;
+            Ok(MatchRow {
+                id: row.get(0)?,
+                played_at: row.get(1)?,
+                mode: row.get(2)?,
+                winner: row.get(3)?,
+                ticks: row.get::<_, i64>(4)? as u64,
+                replay: row.get(5)?,
+                participants: participants
+                    .map(|names| names.split('\u{1f}').map(str::to_string).collect())
+                    .unwrap_or_default(),
+            })
+        })?;
+        let mut matches = rows.collect::<Result<Vec<_>, _>>()?;
+        matches.reverse();
+        Ok(matches)
+    }
+
+    /// Record of `bot` over every match it played against other robots, or
+    /// only those against `opponent`.
+    pub fn record_of(&self, bot: &str, opponent: Option<&str>) -> Result<Record, HistoryError> {
+        let sql = format!(
+            "SELECT m.winner FROM matches m WHERE {} \
+             AND (SELECT COUNT(*) FROM participants p WHERE p.match_id = m.id) > 1",
+            MATCHES_OF
+        );
+        let mut statement = self.conn.prepare(&sql)?;
+        let winners = statement.query_map(params![bot, opponent], |row| {
+            row.get::<_, Option<String>>(0)
+        })?;
+        let mut record = Record::default();
+        for winner in winners {
+            record.add(bot, winner?.as_deref());
+        }
+        Ok(record)
+    }
+
+    /// Number of matches recorded.
+    pub fn count(&self) -> Result<u64, HistoryError> {
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM matches", [], |row| row.get(0))?;
+        Ok(count as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{EndCondition, RobotResult};
+
+    fn result(names: &[&str], winner: Option<usize>) -> MatchResult {
+        MatchResult {
+            mode: "deathmatch".to_string(),
+            end_condition: match winner {
+                Some(_) => EndCondition::LastStanding,
+                None => EndCondition::TickLimit,
+            },
+            winner,
+            seed: None,
+            ticks: 600,
+            duration_secs: 0.2,
+            wave: None,
+            sudden_death: None,
+            robots: names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| RobotResult {
+                    id: i + 1,
+                    name: name.to_string(),
+                    team: i + 1,
+                    alive: winner.is_none_or(|w| w == i + 1),
+                    health: 5,
+                    kills: 0,
+                    shots_fired: 3,
+                    damage_dealt: 0,
+                    score: 0,
+                    judging: None,
+                })
+                .collect(),
+            drill: None,
+            objective: None,
+        }
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let history = History::open(Path::new(":memory:")).unwrap();
+        history
+            .record(&result(&["hunter", "patrol"], Some(1)), None)
+            .unwrap();
+        history
+            .record(&result(&["patrol", "hunter"], Some(1)), None)
+            .unwrap();
+        history
+            .record(&result(&["hunter", "patrol"], None), None)
+            .unwrap();
+        let id = history
+            .record(
+                &result(&["hunter", "tracker"], Some(1)),
+                Some(Path::new("replays/4.json")),
+            )
+            .unwrap();
+        assert_eq!(id, 4);
+        assert_eq!(history.count().unwrap(), 4);
+
+        let against_patrol = history.record_of("hunter", Some("patrol")).unwrap();
+        assert_eq!(
+            against_patrol,
+            Record {
+                played: 3,
+                wins: 1,
+                draws: 1,
+                losses: 1
+            }
+        );
+        let overall = history.record_of("hunter", None).unwrap();
+        assert_eq!(overall.wins, 2);
+        assert_eq!(overall.win_rate(), 50.0);
+
+        let meetings = history.matches(Some("hunter"), Some("patrol"), 2).unwrap();
+        assert_eq!(meetings.len(), 2);
+        // The latest two, oldest first
+        assert_eq!(meetings[0].winner.as_deref(), Some("patrol"));
+        assert_eq!(meetings[0].participants, vec!["patrol", "hunter"]);
+        assert_eq!(meetings[1].winner, None);
+
+        let all = history.matches(None, None, 10).unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[3].replay.as_deref(), Some("replays/4.json"));
+        assert!(
+            history
+                .matches(Some("nobody"), None, 10)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_solo_matches_are_not_counted_in_records() {
+        let history = History::open(Path::new(":memory:")).unwrap();
+        history.record(&result(&["survivor"], None), None).unwrap();
+        assert_eq!(history.record_of("survivor", None).unwrap().played, 0);
+        assert_eq!(history.matches(Some("survivor"), None, 5).unwrap().len(), 1);
+    }
+}
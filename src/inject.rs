@@ -0,0 +1,266 @@
+// Synthetic events: things made to happen to a match at a set tick, rather
+// than by the robots. Tests and scenario files use them to put a robot in a
+// situation on cue, such as a hit at tick 50 or a supply drop next to it, so
+// how its script reacts can be checked deterministically. Injections are kept
+// in the simulation and recorded in replays, so a replay plays them again.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ecs::{Salvage, Sprite, Transform};
+use crate::simulation::Simulation;
+
+/// Something made to happen to a match. Robots are referred to by id; an
+/// injection for a robot that is not in the match, or is destroyed, does
+/// nothing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Injection {
+    /// Hit a robot for `damage`, less its armor, as a shot from robot `by`
+    /// would, or from nowhere without `by`.
+    Hit {
+        robot: usize,
+        damage: i32,
+        #[serde(default)]
+        by: Option<usize>,
+    },
+    /// Knock a robot back by `impulse`, in units per tick, as a blast from
+    /// robot `by` would.
+    Push {
+        robot: usize,
+        impulse: (f32, f32),
+        #[serde(default)]
+        by: usize,
+    },
+    /// Stun a robot for `ticks`, as an EMP charge from robot `by` would.
+    Stun {
+        robot: usize,
+        ticks: u32,
+        #[serde(default)]
+        by: usize,
+    },
+    /// Drop a supply of ammo and energy at `at`, collected like salvage by
+    /// the first robot to drive up to it.
+    Supply {
+        at: (f32, f32),
+        #[serde(default)]
+        ammo: i32,
+        #[serde(default)]
+        energy: i32,
+    },
+}
+
+/// An injection and the tick it happens on, before the robots act.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scheduled {
+    pub tick: u64,
+    #[serde(flatten)]
+    pub injection: Injection,
+}
+
+impl Simulation {
+    /// Make `injection` happen at the start of `tick`, before the robots act;
+    /// a tick that has passed means the next one. Injections for the same
+    /// tick happen in the order they were made.
+    pub fn inject_event(&mut self, tick: u64, injection: Injection) {
+        self.injections.push(Scheduled {
+            tick: tick.max(self.tick),
+            injection,
+        });
+    }
+
+    /// Carry out `injection` now.
+    fn apply_injection(&mut self, injection: &Injection) {
+        let index = |sim: &Simulation, id: usize| {
+            sim.robots.iter().position(|r| r.id == id && r.health > 0)
+        };
+        match *injection {
+            Injection::Hit { robot, damage, by } => {
+                if let Some(i) = index(self, robot) {
+                    self.damage(i, damage, by);
+                }
+            }
+            Injection::Push { robot, impulse, by } => {
+                if let Some(i) = index(self, robot) {
+                    self.push(i, impulse, by);
+                }
+            }
+            Injection::Stun { robot, ticks, by } => {
+                if let Some(i) = index(self, robot) {
+                    self.stun(i, ticks, by);
+                }
+            }
+            Injection::Supply { at, ammo, energy } => {
+                let supply = self.world.spawn();
+                self.world.transforms.insert(
+                    supply,
+                    Transform {
+                        position: at,
+                        heading: 0.0,
+                    },
+                );
+                self.world.salvage.insert(
+                    supply,
+                    Salvage {
+                        ammo: ammo.max(0),
+                        energy: energy.max(0),
+                    },
+                );
+                self.world.sprites.insert(supply, Sprite::Supply);
+            }
+        }
+    }
+}
+
+/// Carry out the injections scheduled for this tick.
+pub fn injection_system(sim: &mut Simulation) {
+    let due: Vec<Injection> = sim
+        .injections
+        .iter()
+        .filter(|s| s.tick == sim.tick)
+        .map(|s| s.injection.clone())
+        .collect();
+    for injection in &due {
+        sim.apply_injection(injection);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Robot;
+    use crate::config::GameConfig;
+    use crate::events::EventKind;
+
+    fn match_of_two() -> Simulation {
+        let robots = vec![
+            Robot::new(1, "one", (100.0, 100.0), vec![]),
+            Robot::new(2, "two", (300.0, 300.0), vec![]),
+        ];
+        Simulation::new(GameConfig::default(), robots, vec![])
+    }
+
+    #[test]
+    fn test_hit_lands_on_its_tick() {
+        let mut sim = match_of_two();
+        sim.inject_event(
+            5,
+            Injection::Hit {
+                robot: 2,
+                damage: 3,
+                by: Some(1),
+            },
+        );
+        for _ in 0..5 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[1].health, 10);
+        sim.step();
+        assert_eq!(sim.robots[1].health, 7);
+        assert_eq!(sim.robots[0].damage_dealt, 3);
+        assert_eq!(sim.last_damage_tick, 5);
+    }
+
+    #[test]
+    fn test_past_ticks_mean_the_next_one() {
+        let mut sim = match_of_two();
+        for _ in 0..3 {
+            sim.step();
+        }
+        sim.inject_event(
+            0,
+            Injection::Stun {
+                robot: 1,
+                ticks: 4,
+                by: 2,
+            },
+        );
+        sim.step();
+        assert!(sim.robots[0].stunned > 0);
+        assert!(matches!(
+            sim.events.last().map(|e| &e.kind),
+            Some(EventKind::Stunned {
+                robot: 1,
+                by: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_and_destroyed_robots_are_left_alone() {
+        let mut sim = match_of_two();
+        sim.inject_event(
+            0,
+            Injection::Hit {
+                robot: 9,
+                damage: 3,
+                by: None,
+            },
+        );
+        sim.inject_event(
+            0,
+            Injection::Hit {
+                robot: 2,
+                damage: 50,
+                by: None,
+            },
+        );
+        sim.inject_event(
+            0,
+            Injection::Push {
+                robot: 2,
+                impulse: (1.0, 0.0),
+                by: 1,
+            },
+        );
+        sim.step();
+        assert_eq!(sim.robots[0].health, 10);
+        assert!(sim.robots[1].health <= 0);
+        assert_eq!(sim.robots[1].knockback, (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_supply_is_collected() {
+        let mut sim = match_of_two();
+        let ammo = sim.robots[0].ammo;
+        sim.inject_event(
+            2,
+            Injection::Supply {
+                at: (101.0, 100.0),
+                ammo: 5,
+                energy: 0,
+            },
+        );
+        for _ in 0..4 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].ammo, ammo + 5);
+        assert!(sim.events.iter().any(|e| e.kind
+            == EventKind::Supplied {
+                robot: 1,
+                ammo: 5,
+                energy: 0
+            }));
+        // The supply is gone once collected
+        assert_eq!(sim.world.salvage.iter().count(), 0);
+        assert_eq!(sim.world.sprites.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_scheduled_reads_like_an_event() {
+        let scheduled: Scheduled =
+            toml::from_str("tick = 50\nkind = \"hit\"\nrobot = 2\ndamage = 3").unwrap();
+        assert_eq!(
+            scheduled,
+            Scheduled {
+                tick: 50,
+                injection: Injection::Hit {
+                    robot: 2,
+                    damage: 3,
+                    by: None
+                },
+            }
+        );
+        assert!(toml::from_str::<Scheduled>("tick = 1\nkind = \"hit\"\nrobot = 2").is_err());
+    }
+}
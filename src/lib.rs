@@ -0,0 +1,109 @@
+//! Robot battle simulator: robots run scripts written in a small DSL and fight
+//! in a shared arena.
+//!
+//! The binary in `main.rs` is a thin command-line frontend over this library;
+//! integration tests drive the same modules to run matches headlessly.
+//!
+//! A script is parsed into [`Command`]s, compiled into VM [`Instruction`]s and
+//! loaded into a [`Robot`]. A [`Simulation`] then advances every robot one
+//! instruction per tick:
+//!
+//! ```
+//! use robot_battle::{GameConfig, Robot, Simulation, parse_script, translate_commands_to_instructions};
+//!
+//! let commands = parse_script("loop {\n move forward 20\n rotate body 90\n}")?;
+//! let program = translate_commands_to_instructions(&commands);
+//!
+//! let square = Robot::new(1, "square", (100.0, 100.0), program);
+//! let target = Robot::new(2, "target", (300.0, 300.0), Vec::new());
+//! let mut sim = Simulation::new(GameConfig::default(), vec![square, target], Vec::new());
+//! for _ in 0..50 {
+//!     sim.step();
+//! }
+//!
+//! assert_eq!(sim.tick, 50);
+//! assert!(sim.robots[0].position.0 > 100.0);
+//! assert!(!sim.is_over());
+//! # Ok::<(), robot_battle::ScriptError>(())
+//! ```
+//!
+//! [`Simulation::add_robot`] adds a robot to a match under a fresh id and
+//! returns a [`RobotHandle`] to find it again with [`Simulation::robot`].
+//!
+//! Matches between script files and built-in bots are set up with
+//! [`setup::load_robots`], and [`results::MatchResult`] summarizes a finished match.
+
+pub mod arena;
+pub mod ast;
+#[cfg(feature = "render")]
+pub mod bindings;
+pub mod builtins;
+pub mod commands;
+pub mod config;
+#[cfg(feature = "render")]
+mod console;
+pub mod coverage;
+pub mod dirs;
+mod drones;
+pub mod ecs;
+pub mod elevation;
+pub mod error;
+pub mod events;
+#[cfg(feature = "net")]
+pub mod fetch;
+pub mod graph;
+pub mod hazards;
+pub mod history;
+pub mod inject;
+pub mod loadout;
+pub mod locale;
+pub mod math;
+#[cfg(feature = "render")]
+mod menu;
+pub mod modes;
+pub mod movers;
+#[cfg(feature = "net")]
+pub mod notify;
+pub mod observer;
+pub mod parser;
+pub mod perf;
+pub mod plugin;
+pub mod practice;
+pub mod profile;
+pub mod raycast;
+pub mod record;
+pub mod registers;
+pub mod replay;
+pub mod results;
+pub mod rng;
+pub mod sandbox;
+pub mod scenario;
+pub mod script_tests;
+pub mod series;
+#[cfg(feature = "net")]
+pub mod server;
+pub mod settings;
+#[cfg(feature = "render")]
+pub mod settings_screen;
+pub mod setup;
+pub mod simulation;
+pub mod snapshots;
+mod systems;
+pub mod tokenizer;
+pub mod tournament;
+pub mod tui;
+pub mod tutorial;
+pub mod verify;
+#[cfg(feature = "render")]
+pub mod visualize;
+mod workers;
+
+pub use ast::{Command, Instruction, Robot, translate_commands_to_instructions};
+pub use config::GameConfig;
+pub use error::{
+    FetchError, HistoryError, LimitError, NotifyError, ParseError, ScriptError, SimError,
+    VerifyError,
+};
+pub use modes::GameMode;
+pub use parser::{parse_script, parse_script_with};
+pub use simulation::{RobotHandle, Simulation};
@@ -0,0 +1,184 @@
+// Robot builds bought from a points budget.
+// A script can start with a `build` header that spends points on armor, speed,
+// weapon and scanner levels; the sandbox rejects builds over the budget, so
+// every bot in a tournament is equally strong on paper.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast::Command;
+use crate::config::GameConfig;
+
+/// Highest level a single stat can be bought up to.
+pub const MAX_LEVEL: u32 = 4;
+/// Level every stat has without a `build` header; it matches the base config.
+pub const BASE_LEVEL: u32 = 2;
+
+/// Stat levels of a robot, from 0 to `MAX_LEVEL`; each level costs one point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Loadout {
+    pub armor: u32,
+    pub speed: u32,
+    pub weapon: u32,
+    pub scanner: u32,
+}
+
+impl Default for Loadout {
+    fn default() -> Self {
+        Loadout {
+            armor: BASE_LEVEL,
+            speed: BASE_LEVEL,
+            weapon: BASE_LEVEL,
+            scanner: BASE_LEVEL,
+        }
+    }
+}
+
+impl Loadout {
+    /// The build chosen by a script's header, or the default without one.
+    pub fn of(commands: &[Command]) -> Loadout {
+        match commands.first() {
+            Some(Command::Build(loadout)) => *loadout,
+            _ => Loadout::default(),
+        }
+    }
+
+    /// Names of the stats, as written in `build` headers, with their levels.
+    pub fn stats(&self) -> [(&'static str, u32); 4] {
+        [
+            ("armor", self.armor),
+            ("speed", self.speed),
+            ("weapon", self.weapon),
+            ("scanner", self.scanner),
+        ]
+    }
+
+    /// The stat called `name` in `build` headers, if there is one.
+    pub fn stat_mut(&mut self, name: &str) -> Option<&mut u32> {
+        match name {
+            "armor" => Some(&mut self.armor),
+            "speed" => Some(&mut self.speed),
+            "weapon" => Some(&mut self.weapon),
+            "scanner" => Some(&mut self.scanner),
+            _ => None,
+        }
+    }
+
+    /// Points the build costs.
+    pub fn cost(&self) -> u32 {
+        self.stats().iter().map(|(_, level)| level).sum()
+    }
+
+    /// Starting and maximum health, from `base` health at armor level 2.
+    pub fn max_health(&self, base: i32, config: &GameConfig) -> i32 {
+        base + config.combat.armor_health_per_level * self.levels_above_base(self.armor)
+    }
+
+    /// Damage actually taken from a hit of `amount`, after armor; always at
+    /// least 1.
+    pub fn damage_taken(&self, amount: i32, config: &GameConfig) -> i32 {
+        let reduction =
+            config.combat.armor_damage_reduction * self.levels_above_base(self.armor) as f32;
+        ((amount as f32 * (1.0 - reduction).max(0.0)).round() as i32).max(1)
+    }
+
+    /// Distance moved per step.
+    pub fn move_speed(&self, config: &GameConfig) -> f32 {
+        config.move_speed * scale(self.speed)
+    }
+
+    /// Damage dealt by a projectile; always at least 1.
+    pub fn projectile_damage(&self, config: &GameConfig) -> i32 {
+        let combat = &config.combat;
+        (combat.projectile_damage
+            + combat.weapon_damage_per_level * self.levels_above_base(self.weapon))
+        .max(1)
+    }
+
+    /// How far the scanner can see.
+    pub fn scan_range(&self, config: &GameConfig) -> f32 {
+        config.scan_range * scale(self.scanner)
+    }
+
+    /// How far `level` is above `BASE_LEVEL`; negative below it.
+    fn levels_above_base(&self, level: u32) -> i32 {
+        level as i32 - BASE_LEVEL as i32
+    }
+}
+
+/// Multiplier for a level: half the base value at 0, 1.5 times at `MAX_LEVEL`.
+fn scale(level: u32) -> f32 {
+    0.5 + 0.25 * level as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::STARTING_HEALTH;
+
+    #[test]
+    fn test_default_build_matches_base_config() {
+        let config = GameConfig::default();
+        let build = Loadout::default();
+        assert_eq!(build.cost(), 8);
+        assert_eq!(build.max_health(STARTING_HEALTH, &config), STARTING_HEALTH);
+        assert_eq!(build.move_speed(&config), config.move_speed);
+        assert_eq!(
+            build.projectile_damage(&config),
+            config.combat.projectile_damage
+        );
+        assert_eq!(build.damage_taken(3, &config), 3);
+        assert_eq!(build.scan_range(&config), config.scan_range);
+    }
+
+    #[test]
+    fn test_levels_scale_stats() {
+        let config = GameConfig::default();
+        let tank = Loadout {
+            armor: 4,
+            speed: 0,
+            weapon: 4,
+            scanner: 0,
+        };
+        assert_eq!(
+            tank.max_health(STARTING_HEALTH, &config),
+            STARTING_HEALTH + 6
+        );
+        assert_eq!(tank.move_speed(&config), config.move_speed * 0.5);
+        assert_eq!(
+            tank.projectile_damage(&config),
+            config.combat.projectile_damage + 2
+        );
+        assert_eq!(tank.scan_range(&config), config.scan_range * 0.5);
+        let glass = Loadout { weapon: 0, ..tank };
+        assert_eq!(glass.projectile_damage(&config), 1);
+    }
+
+    #[test]
+    fn test_combat_table_drives_armor_and_weapons() {
+        let mut config = GameConfig::default();
+        config.combat.armor_health_per_level = 5;
+        config.combat.armor_damage_reduction = 0.25;
+        config.combat.weapon_damage_per_level = 2;
+        let tank = Loadout {
+            armor: 4,
+            weapon: 3,
+            ..Loadout::default()
+        };
+        assert_eq!(
+            tank.max_health(STARTING_HEALTH, &config),
+            STARTING_HEALTH + 10
+        );
+        assert_eq!(
+            tank.projectile_damage(&config),
+            config.combat.projectile_damage + 2
+        );
+        // Two levels above base absorb half of every hit, but never all of it
+        assert_eq!(tank.damage_taken(6, &config), 3);
+        assert_eq!(tank.damage_taken(1, &config), 1);
+        let flimsy = Loadout {
+            armor: 0,
+            ..Loadout::default()
+        };
+        assert_eq!(flimsy.damage_taken(4, &config), 6);
+    }
+}
@@ -0,0 +1,200 @@
+// Message catalogs for the text the window shows: its menu, HUD, event log,
+// console and error screen. Each locale is a TOML file in `locales/`, compiled
+// in, with its messages in tables by where they appear; `{name}` in a message
+// is filled in when it is shown. A translation may leave messages out, which
+// then show in English, so adding a locale takes only a new file listed in
+// `CATALOGS`.
+//
+// The locale is picked when the window opens, from `locale` in the window
+// settings or else the `LANG` environment variable, and again whenever the
+// settings screen changes it. Output on the command line stays in English.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{OnceLock, RwLock};
+
+/// The compiled-in catalogs by locale, English first.
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.toml")),
+    ("de", include_str!("../locales/de.toml")),
+];
+
+/// The names of the locales there are catalogs for.
+pub fn locales() -> impl Iterator<Item = &'static str> {
+    CATALOGS.iter().map(|(name, _)| *name)
+}
+
+/// The messages of one locale, by `table.key`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catalog {
+    pub locale: &'static str,
+    messages: HashMap<String, String>,
+}
+
+static PARSED: OnceLock<Vec<Catalog>> = OnceLock::new();
+static CURRENT: RwLock<Option<&'static Catalog>> = RwLock::new(None);
+
+impl Catalog {
+    /// Parse a catalog file into messages keyed `table.key`.
+    pub fn parse(locale: &'static str, text: &str) -> Result<Catalog, String> {
+        let table: toml::Table = toml::from_str(text).map_err(|e| format!("{}: {}", locale, e))?;
+        let mut messages = HashMap::new();
+        for (section, entries) in table {
+            let toml::Value::Table(entries) = entries else {
+                return Err(format!("{}: `{}` is not a table", locale, section));
+            };
+            for (key, message) in entries {
+                let toml::Value::String(message) = message else {
+                    return Err(format!("{}: `{}.{}` is not a string", locale, section, key));
+                };
+                messages.insert(format!("{}.{}", section, key), message);
+            }
+        }
+        Ok(Catalog { locale, messages })
+    }
+
+    /// The catalog for `locale`, if there is one.
+    pub fn named(locale: &str) -> Option<&'static Catalog> {
+        let catalogs = PARSED.get_or_init(|| {
+            CATALOGS
+                .iter()
+                .map(|(name, text)| Catalog::parse(name, text).expect("shipped catalogs parse"))
+                .collect()
+        });
+        catalogs.iter().find(|c| c.locale == locale)
+    }
+
+    pub fn english() -> &'static Catalog {
+        Catalog::named("en").expect("English is always compiled in")
+    }
+
+    /// The message `key`, in English if this catalog leaves it out, or the
+    /// key itself if no catalog has it.
+    pub fn text<'a>(&'a self, key: &'a str) -> &'a str {
+        self.messages
+            .get(key)
+            .or_else(|| Catalog::english().messages.get(key))
+            .map_or(key, String::as_str)
+    }
+
+    /// The message `key` with each `{name}` in it replaced by its value in
+    /// `args`.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        // One pass, so values that themselves contain braces are left alone
+        let mut text = String::new();
+        let mut rest = self.text(key);
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let value = after.split_once('}').and_then(|(name, tail)| {
+                let (_, value) = args.iter().find(|(n, _)| *n == name)?;
+                Some((value, tail))
+            });
+            match value {
+                Some((value, tail)) => {
+                    text.push_str(&value.to_string());
+                    rest = tail;
+                }
+                None => {
+                    text.push('{');
+                    rest = after;
+                }
+            }
+        }
+        text.push_str(rest);
+        text
+    }
+
+    /// The keys this catalog has messages for.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.messages.keys().map(String::as_str)
+    }
+}
+
+/// Pick the catalog the window shows its text from: `locale` if given,
+/// otherwise the language of `LANG`, falling back to English.
+pub fn select(locale: Option<&str>) {
+    let from_env = || {
+        let lang = std::env::var("LANG").ok()?;
+        let name = lang.split(['_', '.']).next()?.to_string();
+        Catalog::named(&name)
+    };
+    let catalog = locale
+        .and_then(Catalog::named)
+        .or_else(from_env)
+        .unwrap_or_else(Catalog::english);
+    *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = Some(catalog);
+}
+
+/// The catalog picked with `select`, English until then.
+pub fn current() -> &'static Catalog {
+    let current = *CURRENT.read().unwrap_or_else(|e| e.into_inner());
+    current.unwrap_or_else(Catalog::english)
+}
+
+/// The message `key` in the current locale.
+pub fn text(key: &str) -> &str {
+    current().text(key)
+}
+
+/// The message `key` in the current locale, filled in with `args`.
+pub fn format(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    current().format(key, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `{name}`s a message fills in, sorted.
+    fn placeholders(message: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = message
+            .split('{')
+            .skip(1)
+            .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_translations_match_the_english_messages() {
+        let english = Catalog::english();
+        for locale in locales() {
+            let catalog = Catalog::named(locale).unwrap();
+            for key in catalog.keys() {
+                assert!(
+                    english.keys().any(|k| k == key),
+                    "{}: unknown {}",
+                    locale,
+                    key
+                );
+                assert_eq!(
+                    placeholders(catalog.text(key)),
+                    placeholders(english.text(key)),
+                    "{}: {}",
+                    locale,
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_messages_fall_back_to_english() {
+        let partial = Catalog::parse("xx", "[menu]\nquit = \"Sortir\"").unwrap();
+        assert_eq!(partial.text("menu.quit"), "Sortir");
+        assert_eq!(partial.text("menu.resume"), "Resume");
+        assert_eq!(partial.text("no.such.key"), "no.such.key");
+        let german = Catalog::named("de").unwrap();
+        assert_eq!(
+            german.format("hud.wave", &[("wave", &3), ("score", &12)]),
+            "Welle 3  Punkte 12"
+        );
+        assert_eq!(
+            german.format("event.said", &[("robot", &1), ("text", &"{robot}")]),
+            "Roboter 1: \"{robot}\""
+        );
+        assert!(Catalog::parse("xx", "quit = \"Sortir\"").is_err());
+    }
+}
@@ -1,156 +1,1016 @@
-use std::f32::consts::PI;
+mod cli;
 
-mod ast;
-mod parser;
-mod tokenizer;
-mod visualize;
+use std::time::Instant;
 
-const ROBOT_TURN_SPEED: f32 = PI / 180.0;
-const ROBOT_MOVE_SPEED: f32 = 0.2;
+use std::path::{Path, PathBuf};
 
-#[macroquad::main("Robot Battle")]
-async fn main() {
-    use ast::Robot;
+use cli::{Invocation, RenderMode};
+#[cfg(feature = "net")]
+use robot_battle::NotifyError;
+use robot_battle::SimError;
+use robot_battle::arena::Arena;
+#[cfg(feature = "render")]
+use robot_battle::bindings::{Bindings, ViewerKeys};
+use robot_battle::commands;
+use robot_battle::config::GameConfig;
+use robot_battle::coverage::Coverage;
+use robot_battle::dirs::Dirs;
+#[cfg(feature = "render")]
+use robot_battle::dirs::{CONTROLS, SETTINGS};
+#[cfg(feature = "net")]
+use robot_battle::fetch::{Source, fetch};
+use robot_battle::graph::ScriptGraph;
+use robot_battle::history::{History, Record};
+#[cfg(feature = "render")]
+use robot_battle::locale;
+use robot_battle::modes::GameMode;
+#[cfg(feature = "net")]
+use robot_battle::notify::Notifier;
+#[cfg(feature = "render")]
+use robot_battle::perf::CountingAllocator;
+use robot_battle::profile::{Profile, SystemTimes};
+use robot_battle::record::Recorder;
+use robot_battle::replay::{Replay, ReplayWriter};
+use robot_battle::results::{self, EndCondition, MatchResult, RunLimits};
+use robot_battle::scenario::Scenario;
+use robot_battle::script_tests::run_tests;
+use robot_battle::series::Series;
+#[cfg(feature = "net")]
+use robot_battle::server::{MatchServer, serve};
+#[cfg(feature = "render")]
+use robot_battle::settings::Settings;
+#[cfg(feature = "render")]
+use robot_battle::settings_screen::Preferences;
+use robot_battle::setup::{BotScript, compile_robots, read_entrants, read_scripts};
+#[cfg(feature = "render")]
+use robot_battle::setup::{default_obstacles, load_robots};
+use robot_battle::simulation::Simulation;
+use robot_battle::tournament::Ladder;
+#[cfg(feature = "render")]
+use robot_battle::tournament::Round;
+use robot_battle::tournament::Tournament;
+use robot_battle::tui;
+use robot_battle::tutorial::{self, Progress};
+use robot_battle::verify::{TournamentRules, verify};
+#[cfg(feature = "render")]
+use robot_battle::visualize;
 
-    // Load script from file for both robots
-    use std::fs;
+// Counted for the window's performance HUD, once a window is open
+#[cfg(feature = "render")]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
-    let script_path = "robot-scripts/circler.robo";
-    let script1 = &fs::read_to_string(script_path).expect("Failed to read robot script file");
-    let script2 = script1;
+fn main() {
+    let invocation = match cli::parse_invocation(std::env::args().skip(1).collect()) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "usage: robot-battle [fight <bot.robo|builtin:name>...] [--render window|tui|none] [--headless] [--record out.gif] \
+                 [--mode deathmatch|koth|waves|practice] [--config game.toml] [--set key=value]... [--result-json out.json] [--ticks N] [--seed N] [--threads N] [--timeout SECS] [--save-replay match.json] [--verify-replay match.json] [--watch-replay match.json] [--series N] [--webhook URL [--replay-url URL]] [--history league.db] [--profile out.txt] [--coverage out.txt] [--controls keys.toml] [--controls2 keys.toml] [--split-screen] [--settings settings.toml] [--viewer-keys viewer_keys.toml] [--arena arena.toml]\n\
+                 \x20      robot-battle verify <bot.robo> [--name NAME] [--author NAME] [--rules rules.toml] [--manifest out.json] \
+                 [--config game.toml] [--set key=value]...\n\
+                 \x20      robot-battle fetch <repo.git|index.toml> [--dir bots]\n\
+                 \x20      robot-battle tournament <bot.robo|builtin:name>... [--format round-robin|single|double] \
+                 [--ratings ladder.toml] [--bracket out.json] [--replays DIR] [--webhook URL [--replay-url URL]] [--history league.db] [--coverage DIR] [--watch] [--ticks N] [--config game.toml] [--set key=value]...\n\
+                 \x20      robot-battle serve [--addr host:port] [--ticks N] [--config game.toml] [--set key=value]...\n\
+                 \x20      robot-battle history [--db history.db] [--bot NAME [--vs NAME]] [--limit N]\n\
+                 \x20      robot-battle graph <bot.robo|builtin:name> [--format dot|mermaid] [--output FILE]\n\
+                 \x20      robot-battle test <bot.robo|builtin:name> [--config game.toml] [--set key=value]...\n\
+                 \x20      robot-battle docs [--format markdown|json] [--output FILE]\n\
+                 \x20      robot-battle scenario <scenario.toml> <bot.robo|builtin:name|human> [match options]\n\
+                 \x20      robot-battle tutorial [<lesson> <bot.robo|human> [match options]]"
+            );
+            std::process::exit(2);
+        }
+    };
+    let outcome = match invocation {
+        Invocation::Match(options) => run(*options),
+        Invocation::Verify(options) => run_verify(options),
+        #[cfg(feature = "net")]
+        Invocation::Fetch(options) => Ok(run_fetch(options)),
+        Invocation::Tournament(options) => run_tournament(options),
+        #[cfg(feature = "net")]
+        Invocation::Serve(options) => run_serve(options),
+        Invocation::History(options) => run_history(options),
+        Invocation::Graph(options) => run_graph(options),
+        Invocation::Test(options) => run_test(options),
+        Invocation::Docs(options) => run_docs(options),
+        Invocation::Tutorial => run_tutorial(),
+    };
+    match outcome {
+        Ok(status) => std::process::exit(status),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
 
-    // Tokenize and parse scripts
-    let tokens1 = tokenizer::tokenize_script(script1);
-    let ast1 = parser::parse_tokens(&tokens1).unwrap_or_else(|e| {
-        println!("Parse error for robot 1: {:?}", e);
-        vec![]
-    });
+/// Set up and play the match, returning the process exit status (see
+/// `MatchResult::exit_code`). Errors that stop the match from running exit with 2.
+fn run(options: cli::Options) -> Result<i32, SimError> {
+    if let Some(path) = &options.verify_replay {
+        return verify_replay(path);
+    }
+    if let Some(path) = &options.watch_replay {
+        return watch_replay(path, &options);
+    }
+    let lesson = options.lesson.as_deref().and_then(tutorial::lesson);
+    let scenario = match (&options.scenario, lesson) {
+        (Some(path), _) => Some(Scenario::load(path).map_err(SimError::Config)?),
+        (None, Some(lesson)) => Some(lesson.scenario()),
+        (None, None) => None,
+    };
+    let arena = match &options.arena {
+        Some(path) => Arena::load(path).map_err(SimError::Config)?,
+        None => Arena::default(),
+    };
+    // The scenario's own overrides come first, so the command line can change them
+    let overrides: Vec<String> = scenario
+        .iter()
+        .flat_map(|s| s.set.iter().cloned())
+        .chain(options.overrides.iter().cloned())
+        .collect();
+    let config = load_config(options.config.as_deref(), &overrides).map_err(SimError::Config)?;
+    let entrants = match &scenario {
+        Some(scenario) => scenario
+            .entrants(&options.entrants[0])
+            .map_err(SimError::Config)?,
+        None => options.entrants.clone(),
+    };
 
-    let tokens2 = tokenizer::tokenize_script(script2);
-    let ast2 = parser::parse_tokens(&tokens2).unwrap_or_else(|e| {
-        println!("Parse error for robot 2: {:?}", e);
-        vec![]
+    let recorder = match options.record.as_deref() {
+        Some(path) => Some(
+            Recorder::create(path).map_err(|source| SimError::Recording {
+                path: path.to_path_buf(),
+                source,
+            })?,
+        ),
+        None => None,
+    };
+
+    let robots = read_scripts(&entrants).and_then(|mut bots| {
+        if let Some(max) = options.mode.max_entrants() {
+            bots.truncate(max);
+        }
+        compile_robots(&bots, &config).map(|robots| (bots, robots))
     });
+    let (bots, robots) = match robots {
+        Ok(loaded) => loaded,
+        #[cfg(feature = "render")]
+        Err(errors) if options.render == RenderMode::Window => {
+            // Keep the window open so the errors are visible
+            macroquad::Window::new("Robot Battle", visualize::show_errors(errors));
+            return Ok(0);
+        }
+        Err(errors) => return Err(SimError::ScriptsRejected(errors)),
+    };
+    if let Some(rounds) = options.series {
+        return run_series(rounds, &options, &config, &arena, &bots);
+    }
+    let mut sim = match &scenario {
+        Some(scenario) => {
+            println!("{}", scenario.briefing());
+            scenario.start(config, robots)
+        }
+        None => Simulation::new(config, robots, arena.obstacles.clone())
+            .with_mode(options.mode)
+            .with_movers(arena.movers.clone())
+            .with_elevation(arena.elevation())
+            .with_hazards(arena.hazards.clone()),
+    }
+    .with_seed(options.seed)
+    .with_threads(options.threads);
+    if options.profile.is_some() || options.coverage.is_some() {
+        sim = sim.with_profiling();
+    }
+    let started = Instant::now();
+    let limits = RunLimits {
+        max_ticks: options.max_ticks,
+        timeout: options.timeout,
+    };
+    // Streamed as the match runs, so an interrupted match still leaves a replay
+    let mut replay = match &options.save_replay {
+        Some(path) => Some(ReplayWriter::create(
+            path,
+            &sim,
+            bots.clone(),
+            arena.obstacles.clone(),
+        )?),
+        None => None,
+    };
 
-    // Initialize robots with translated instructions, registers, and instruction pointer
-    let mut robots = vec![
-        Robot {
-            id: 1,
-            position: (100.0, 50.0),
-            heading: 0.0,
-            health: 10,
-            instruction_queue: ast::translate_commands_to_instructions(&ast1),
-            ip: 0,
-            registers: std::collections::HashMap::new(),
-        },
-        Robot {
-            id: 2,
-            position: (200.0, 200.0),
-            heading: 0.0,
-            health: 10,
-            instruction_queue: ast::translate_commands_to_instructions(&ast2),
-            ip: 0,
-            registers: std::collections::HashMap::new(),
-        },
-    ];
-
-    // Simulation loop
-    loop {
-        let mut damage_events = Vec::new();
-
-        let robots_len = robots.len(); // Avoid multiple mutable borrows
-
-        for i in 0..robots_len {
-            let robot = &mut robots[i];
-
-            if robot.health <= 0 {
-                continue;
+    #[cfg(feature = "render")]
+    if options.render == RenderMode::Window {
+        let config = sim.config.clone();
+        let reload = Box::new(move || {
+            let mut robots = load_robots(&entrants, &config)?;
+            if let Some(scenario) = &scenario {
+                scenario.place(&mut robots);
             }
-
-            // Execute one instruction per tick
-            execute_robot_instruction(robot);
-
-            // Interaction: If last instruction was Fire
-            if robot.ip > 0
-                && robot.instruction_queue.get(robot.ip - 1) == Some(&ast::Instruction::Fire)
-            {
-                let robot_id = robot.id;
-                let robot_pos = robot.position;
-
-                // Search for targets **without borrowing robots again mutably**
-                for (j, other) in robots.iter().enumerate() {
-                    if i != j && other.health > 0 && other.position == robot_pos {
-                        damage_events.push((robot_id, other.id, j, 2));
-                    }
+            Ok(robots)
+        });
+        let prefs = load_preferences(&options)?;
+        let mut players = vec![prefs.controls.clone()];
+        let humans = sim.robots.iter().filter(|r| r.controls.is_some()).count();
+        // Two players share the keyboard, so the first gives up the second's keys
+        if humans == 2 {
+            let second = match &options.controls2 {
+                Some(path) => Bindings::load_over(path, Bindings::second_player())
+                    .map_err(SimError::Config)?,
+                None => Bindings::second_player(),
+            };
+            players = vec![players[0].without(&second), second];
+        }
+        prefs
+            .keys
+            .check_players(&players[..humans.min(players.len())])
+            .map_err(SimError::Config)?;
+        let split_screen = options.split_screen || humans == 2;
+        // A lesson passed in the window counts as soon as it is decided
+        let on_over: Option<visualize::MatchOver> = lesson.map(|lesson| {
+            Box::new(move |sim: &Simulation| {
+                let passed = sim.objective.as_ref().and_then(|o| o.outcome.as_ref());
+                if passed.is_some_and(|outcome| outcome.passed)
+                    && let Err(e) = pass_lesson(lesson.name)
+                {
+                    eprintln!("{}", e);
                 }
-            }
+            }) as visualize::MatchOver
+        });
+        macroquad::Window::new(
+            "Robot Battle",
+            visualize::run(sim, reload, recorder, players, split_screen, prefs, on_over),
+        );
+        return Ok(0);
+    }
+    // Without the `render` feature the command line never asks for the window
+    let (mut finished, stopped) = match options.render {
+        RenderMode::Tui => tui::run(sim, recorder, replay.as_mut(), limits)?,
+        _ => run_headless(sim, recorder, replay.as_mut(), limits),
+    };
+
+    let result = MatchResult::from_sim(&finished, stopped, started.elapsed());
+    if let Some(drill) = &finished.drill {
+        // The robot practicing joined first; the dummies were added after it
+        let shots = finished.robots.first().map_or(0, |r| r.shots_fired);
+        println!("{}", drill.report(shots));
+    }
+    if let Some(path) = &options.result_json {
+        result.write(path)?;
+    }
+    if let Some(lesson) = lesson
+        && result.end_condition == EndCondition::GoalMet
+    {
+        pass_lesson(lesson.name)?;
+    }
+    if let Some(writer) = replay {
+        writer.finish(&mut finished)?;
+    }
+    if let Some(path) = &options.profile {
+        write_profile(path, &bots, &finished)?;
+    }
+    if let Some(path) = &options.coverage {
+        let coverages: Vec<Coverage> = bots
+            .iter()
+            .zip(&finished.robots)
+            .filter_map(|(bot, robot)| {
+                let mut coverage = Coverage::new(bot)?;
+                coverage.add(robot).then_some(coverage)
+            })
+            .collect();
+        Coverage::write(&coverages, path)?;
+    }
+    if let Some(path) = &options.history {
+        History::open(path)?.record(&result, options.save_replay.as_deref())?;
+    }
+    #[cfg(feature = "net")]
+    if let Some(notifier) = notifier(options.webhook.as_deref(), options.replay_url.as_deref()) {
+        let payload = notifier.match_payload(&result, options.save_replay.as_deref());
+        warn_on_failure(notifier.send(&payload));
+    }
+    Ok(result.exit_code())
+}
+
+/// Write the profile of every robot that ran one of `bots` to `path`, then
+/// the time each system took.
+fn write_profile(path: &Path, bots: &[BotScript], sim: &Simulation) -> Result<(), SimError> {
+    let mut reports: Vec<String> = bots
+        .iter()
+        .zip(&sim.robots)
+        .filter_map(|(bot, robot)| Profile::new(bot, robot))
+        .map(|profile| profile.report())
+        .collect();
+    reports.extend(sim.system_times.as_ref().map(SystemTimes::report));
+    std::fs::write(path, reports.join("\n")).map_err(|source| SimError::WriteProfile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Play a best-of-`rounds` series, carrying each robot's memory over from one
+/// round to the next, and print the outcome. Returns 0 if a robot won the
+/// series and 1 if none won a majority.
+fn run_series(
+    rounds: u32,
+    options: &cli::Options,
+    config: &GameConfig,
+    arena: &Arena,
+    bots: &[BotScript],
+) -> Result<i32, SimError> {
+    let limits = RunLimits {
+        max_ticks: options.max_ticks,
+        timeout: options.timeout,
+    };
+    let history = options.history.as_deref().map(History::open).transpose()?;
+    let mut series = Series::new(rounds);
+    while !series.is_decided() {
+        let mut robots = compile_robots(bots, config).map_err(SimError::ScriptsRejected)?;
+        series.start_round(&mut robots, config.math);
+        // Every round draws different numbers, all following from the one seed
+        let seed = options.seed.wrapping_add(u64::from(series.played));
+        let sim = Simulation::new(config.clone(), robots, arena.obstacles.clone())
+            .with_mode(options.mode)
+            .with_movers(arena.movers.clone())
+            .with_elevation(arena.elevation())
+            .with_hazards(arena.hazards.clone())
+            .with_seed(seed)
+            .with_threads(options.threads);
+        println!("Round {} of {}", series.played + 1, rounds);
+        let started = Instant::now();
+        let (finished, stopped) = match options.render {
+            RenderMode::Tui => tui::run(sim, None, None, limits)?,
+            _ => run_headless(sim, None, None, limits),
+        };
+        let result = MatchResult::from_sim(&finished, stopped, started.elapsed());
+        if let Some(history) = &history {
+            history.record(&result, None)?;
         }
+        series.finish_round(&finished, result.winner);
+    }
 
-        // Apply damage after borrow ends
-        // for (firing_id, target_id, idx, dmg) in damage_events {
-        //     if let Some(robot) = robots.get_mut(idx) {
-        //         robot.health -= dmg;
-        //     }
-        // }
+    let tally: Vec<String> = series.wins.iter().map(u32::to_string).collect();
+    match series.winner() {
+        Some(index) => {
+            println!(
+                "Series over after {} rounds: {} #{} wins {}",
+                series.played,
+                bots[index].name,
+                index + 1,
+                tally.join("-")
+            );
+            Ok(0)
+        }
+        None => {
+            println!(
+                "Series over after {} rounds: no winner ({})",
+                series.played,
+                tally.join("-")
+            );
+            Ok(1)
+        }
+    }
+}
 
-        visualize::visualize_robots(&robots).await;
+/// Re-simulate a saved replay and report whether it reproduces the recorded
+/// events. Returns 0 if it does and 1 if the runs diverge.
+fn verify_replay(path: &Path) -> Result<i32, SimError> {
+    let replay = Replay::load(path).map_err(SimError::Replay)?;
+    match replay.check()? {
+        None => {
+            let partial = if replay.partial {
+                " (partial: the match was cut short)"
+            } else {
+                ""
+            };
+            println!(
+                "Replay verified: {} events over {} ticks match{}",
+                replay.events.len(),
+                replay.ticks,
+                partial
+            );
+            Ok(0)
+        }
+        Some(divergence) => {
+            println!("Replay diverged: {}", divergence);
+            Ok(1)
+        }
     }
 }
 
-/// Execute the instruction at the current instruction pointer for a robot.
-/// Advances the instruction pointer and updates robot state as needed.
-fn execute_robot_instruction(robot: &mut ast::Robot) {
-    use ast::Instruction;
-    if robot.ip < robot.instruction_queue.len() {
-        let instr = &robot.instruction_queue[robot.ip];
-        match instr {
-            Instruction::MoveForward => {
-                robot.position.0 += ROBOT_MOVE_SPEED * robot.heading.cos();
-                robot.position.1 += ROBOT_MOVE_SPEED * robot.heading.sin();
-                robot.ip += 1;
-            }
-            Instruction::TurnLeft => {
-                robot.heading -= ROBOT_TURN_SPEED;
-                robot.ip += 1;
-            }
-            Instruction::TurnRight => {
-                robot.heading += ROBOT_TURN_SPEED;
-                robot.ip += 1;
-            }
-            Instruction::Fire => {
-                robot.ip += 1;
+/// Play a saved replay back in the window or the terminal. Matches are
+/// deterministic, so the replay is re-simulated from its starting state.
+fn watch_replay(path: &Path, options: &cli::Options) -> Result<i32, SimError> {
+    let replay = Replay::load(path).map_err(SimError::Replay)?;
+    let sim = replay.start()?;
+    match options.render {
+        #[cfg(feature = "render")]
+        RenderMode::Window => {
+            // "Reload scripts" has nothing new to load, so it restarts the replay
+            let (bots, config) = (replay.bots, replay.config);
+            let reload = Box::new(move || compile_robots(&bots, &config));
+            macroquad::Window::new(
+                "Robot Battle",
+                visualize::run(
+                    sim,
+                    reload,
+                    None,
+                    Vec::new(),
+                    options.split_screen,
+                    load_preferences(options)?,
+                    None,
+                ),
+            );
+        }
+        _ => {
+            let limits = RunLimits {
+                max_ticks: Some(replay.ticks),
+                timeout: None,
+            };
+            tui::run(sim, None, None, limits)?;
+        }
+    }
+    Ok(0)
+}
+
+/// Check a bot for tournament submission and print or write its manifest.
+/// Returns 0 if the bot passed and 1 if it was rejected.
+fn run_verify(options: cli::VerifyOptions) -> Result<i32, SimError> {
+    let config =
+        load_config(options.config.as_deref(), &options.overrides).map_err(SimError::Config)?;
+    let rules = match &options.rules {
+        Some(path) => TournamentRules::load(path).map_err(SimError::Config)?,
+        None => TournamentRules::default(),
+    };
+    let manifest = match verify(
+        &options.script,
+        options.name.as_deref(),
+        options.author.as_deref(),
+        &config,
+        &rules,
+    ) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Ok(1);
+        }
+    };
+
+    let json = serde_json::to_string_pretty(&manifest).expect("manifest serializes");
+    match &options.manifest {
+        Some(path) => {
+            std::fs::write(path, json + "\n").map_err(|source| SimError::WriteResult {
+                path: PathBuf::from(path),
+                source,
+            })?
+        }
+        None => println!("{}", json),
+    }
+    Ok(0)
+}
+
+/// Print or write the command tree and control-flow graph of a bot's script.
+/// A script that does not parse is reported and exits with 1.
+fn run_graph(options: cli::GraphOptions) -> Result<i32, SimError> {
+    let bots =
+        read_entrants(std::slice::from_ref(&options.bot)).map_err(SimError::ScriptsRejected)?;
+    let bot = &bots[0];
+    let graph = match ScriptGraph::new(&bot.name, &bot.script) {
+        Ok(graph) => graph,
+        Err(e) => {
+            eprintln!("{}: {}", bot.entrant, e);
+            return Ok(1);
+        }
+    };
+    let text = graph.render(options.format);
+    match &options.output {
+        Some(path) => std::fs::write(path, text).map_err(|source| SimError::WriteResult {
+            path: path.clone(),
+            source,
+        })?,
+        None => print!("{}", text),
+    }
+    Ok(0)
+}
+
+/// Run the tests written in a bot's script, printing how each went. Exits with
+/// 1 if a test failed or the script does not compile.
+fn run_test(options: cli::TestOptions) -> Result<i32, SimError> {
+    let config =
+        load_config(options.config.as_deref(), &options.overrides).map_err(SimError::Config)?;
+    let bots =
+        read_entrants(std::slice::from_ref(&options.bot)).map_err(SimError::ScriptsRejected)?;
+    let bot = &bots[0];
+    let results = match run_tests(&bot.name, &bot.script, &config) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}: {}", bot.entrant, e);
+            return Ok(1);
+        }
+    };
+    if results.is_empty() {
+        println!("{}: no tests", bot.entrant);
+        return Ok(0);
+    }
+    for result in &results {
+        match &result.failure {
+            None => println!("test {} ... ok", result.name),
+            Some(failure) => println!("test {} ... FAILED: {}", result.name, failure),
+        }
+    }
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    println!(
+        "\n{}: {} passed, {} failed",
+        bot.entrant,
+        results.len() - failed,
+        failed
+    );
+    Ok(i32::from(failed > 0))
+}
+
+/// List the tutorial lessons, marking those passed, and say how to play the
+/// next one.
+fn run_tutorial() -> Result<i32, SimError> {
+    let progress = Progress::load(&Dirs::locate().tutorial_progress()).map_err(SimError::Config)?;
+    for (i, lesson) in tutorial::LESSONS.iter().enumerate() {
+        let mark = if progress.passed.contains(lesson.name) {
+            "x"
+        } else {
+            " "
+        };
+        let title = lesson.scenario().name;
+        println!("[{}] {}. {:<10} {}", mark, i + 1, lesson.name, title);
+    }
+    match progress.next() {
+        Some((i, lesson)) => {
+            println!("\nNext: lesson {}, {}", i + 1, lesson.scenario().name);
+            println!("{}", lesson.scenario().description.trim());
+            println!(
+                "\nPlay it with: robot-battle tutorial {} <bot.robo>",
+                lesson.name
+            );
+        }
+        None => println!("\nEvery lesson passed. Try `fight` against the built-in bots next."),
+    }
+    Ok(0)
+}
+
+/// Remember that the lesson `name` was passed and point to the next one.
+fn pass_lesson(name: &str) -> Result<(), SimError> {
+    let path = Dirs::locate().tutorial_progress();
+    let mut progress = Progress::load(&path).map_err(SimError::Config)?;
+    progress.passed.insert(name.to_string());
+    progress.write(&path).map_err(SimError::Config)?;
+    match progress.next() {
+        Some((i, next)) => println!(
+            "Lesson passed! Next: robot-battle tutorial {} <bot.robo> (lesson {})",
+            next.name,
+            i + 1
+        ),
+        None => println!("Lesson passed! That was the last lesson."),
+    }
+    Ok(())
+}
+
+/// Print or write the command reference.
+fn run_docs(options: cli::DocsOptions) -> Result<i32, SimError> {
+    let text = commands::reference(options.format);
+    match &options.output {
+        Some(path) => std::fs::write(path, text).map_err(|source| SimError::WriteResult {
+            path: path.clone(),
+            source,
+        })?,
+        None => print!("{}", text),
+    }
+    Ok(0)
+}
+
+/// Play a tournament, printing each round as it finishes, and update the
+/// ladder and write the bracket if asked to.
+fn run_tournament(options: cli::TournamentOptions) -> Result<i32, SimError> {
+    let config =
+        load_config(options.config.as_deref(), &options.overrides).map_err(SimError::Config)?;
+    let bots = read_entrants(&options.entrants).map_err(SimError::ScriptsRejected)?;
+    // A ladder file that does not exist yet is created with the results
+    let mut ladder = match &options.ratings {
+        Some(path) if path.exists() => Ladder::load(path).map_err(SimError::Config)?,
+        _ => Ladder::default(),
+    };
+
+    // The matches are replayed for `--watch`, which the tournament cannot do once run
+    #[cfg(feature = "render")]
+    let (entrants, watched_config) = (bots.clone(), config.clone());
+    let mut tournament = Tournament::new(options.format, bots, &ladder, config, options.max_ticks)?;
+    if let Some(dir) = &options.replays {
+        tournament = tournament.with_replays(dir);
+    }
+    if let Some(path) = &options.history {
+        tournament = tournament.with_history(History::open(path)?);
+    }
+    if let Some(dir) = &options.coverage {
+        tournament = tournament.with_coverage(dir);
+    }
+    #[cfg(feature = "net")]
+    let notifier = notifier(options.webhook.as_deref(), options.replay_url.as_deref());
+    let bracket = tournament.run(&mut ladder, |round| {
+        println!("{}", round.render());
+        #[cfg(feature = "net")]
+        if let Some(notifier) = &notifier {
+            for pairing in round.matches.iter().filter(|p| p.b.is_some()) {
+                warn_on_failure(notifier.send(&notifier.pairing_payload(round, pairing)));
             }
-            Instruction::LoadCounter { reg, value } => {
-                robot.registers.insert(reg.clone(), *value);
-                robot.ip += 1;
+        }
+    })?;
+
+    for (place, standing) in bracket.standings.iter().enumerate() {
+        println!(
+            "{:>2}. {:<20} {:>3} pts ({}-{}-{})",
+            place + 1,
+            standing.name,
+            standing.points,
+            standing.wins,
+            standing.draws,
+            standing.losses
+        );
+    }
+    if let Some(champion) = &bracket.champion {
+        println!("Champion: {}", champion);
+    }
+    if let Some(path) = &options.ratings {
+        ladder.write(path).map_err(SimError::Config)?;
+    }
+    if let Some(path) = &options.bracket {
+        bracket.write(path)?;
+    }
+    #[cfg(feature = "net")]
+    if let Some(notifier) = &notifier {
+        warn_on_failure(notifier.send(&notifier.tournament_payload(&bracket)));
+    }
+    #[cfg(feature = "render")]
+    if options.watch {
+        let batches = round_batches(&bracket.rounds, &entrants, &watched_config)?;
+        macroquad::Window::new(
+            "Robot Battle",
+            visualize::run_grid(
+                batches,
+                options.max_ticks,
+                load_settings(None)?,
+                load_viewer_keys(None)?,
+            ),
+        );
+    }
+    Ok(0)
+}
+
+/// The matches of every round set up again from the start, since matches are
+/// deterministic, in batches of at most `GRID_MAX_MATCHES` for the grid view.
+/// Byes are left out.
+#[cfg(feature = "render")]
+fn round_batches(
+    rounds: &[Round],
+    bots: &[BotScript],
+    config: &GameConfig,
+) -> Result<Vec<(String, Vec<Simulation>)>, SimError> {
+    let bot = |name: &str| bots.iter().find(|b| b.name == name).cloned();
+    let mut batches = Vec::new();
+    for round in rounds {
+        let mut sims = Vec::new();
+        for pairing in &round.matches {
+            let Some(b) = &pairing.b else { continue };
+            let pair: Vec<BotScript> = [bot(&pairing.a), bot(b)].into_iter().flatten().collect();
+            let robots = compile_robots(&pair, config).map_err(SimError::ScriptsRejected)?;
+            sims.push(Simulation::new(config.clone(), robots, default_obstacles()));
+        }
+        let parts = sims.len().div_ceil(visualize::GRID_MAX_MATCHES);
+        for (part, chunk) in sims.chunks(visualize::GRID_MAX_MATCHES).enumerate() {
+            let title = match parts {
+                1 => round.title(),
+                _ => format!("{} ({}/{})", round.title(), part + 1, parts),
+            };
+            batches.push((title, chunk.to_vec()));
+        }
+    }
+    Ok(batches)
+}
+
+/// The webhook notifier for `--webhook`, linking replays under `--replay-url`.
+#[cfg(feature = "net")]
+fn notifier(webhook: Option<&str>, replay_url: Option<&str>) -> Option<Notifier> {
+    let notifier = Notifier::new(webhook?);
+    Some(match replay_url {
+        Some(base) => notifier.with_replay_base(base),
+        None => notifier,
+    })
+}
+
+/// A webhook that cannot be reached should not fail the match or tournament
+/// it reports on, so its errors are only printed.
+#[cfg(feature = "net")]
+fn warn_on_failure(sent: Result<(), NotifyError>) {
+    if let Err(e) = sent {
+        eprintln!("Warning: {}", e);
+    }
+}
+
+/// List past matches from the history database, with the record of `--bot`
+/// (against `--vs`) and its win rate after each of the listed matches.
+fn run_history(options: cli::HistoryOptions) -> Result<i32, SimError> {
+    if !options.db.exists() {
+        return Err(SimError::Config(format!(
+            "no match history at {}",
+            options.db.display()
+        )));
+    }
+    let history = History::open(&options.db)?;
+    let (bot, opponent) = (options.bot.as_deref(), options.opponent.as_deref());
+    let matches = history.matches(bot, opponent, options.limit)?;
+    if matches.is_empty() {
+        println!("No matches recorded");
+        return Ok(0);
+    }
+
+    // Running record over the listed matches, so the win rate reads as a trend
+    let mut running = Record::default();
+    for row in &matches {
+        let outcome = match &row.winner {
+            Some(winner) => format!("{} won", winner),
+            None => "no winner".to_string(),
+        };
+        let mut line = format!(
+            "#{:<5} {}  {:<10} {:<40} {} in {} ticks",
+            row.id,
+            row.played_at,
+            row.mode,
+            row.participants.join(" vs "),
+            outcome,
+            row.ticks
+        );
+        if let Some(bot) = bot
+            && row.participants.len() > 1
+        {
+            running.add(bot, row.winner.as_deref());
+            line += &format!(" ({:.0}% won so far)", running.win_rate());
+        }
+        if let Some(replay) = &row.replay {
+            line += &format!(" [{}]", replay);
+        }
+        println!("{}", line);
+    }
+
+    if let Some(bot) = bot {
+        let record = history.record_of(bot, opponent)?;
+        let against = opponent.map_or(String::new(), |o| format!(" against {}", o));
+        println!(
+            "{}{}: {} played, {} won, {} drawn, {} lost ({:.0}% won)",
+            bot,
+            against,
+            record.played,
+            record.wins,
+            record.draws,
+            record.losses,
+            record.win_rate()
+        );
+    }
+    Ok(0)
+}
+
+/// Run the match server until the process is stopped.
+#[cfg(feature = "net")]
+fn run_serve(options: cli::ServeOptions) -> Result<i32, SimError> {
+    let config =
+        load_config(options.config.as_deref(), &options.overrides).map_err(SimError::Config)?;
+    let server = MatchServer::new(config, options.max_ticks);
+    println!("Serving matches on http://{}", options.addr);
+    serve(&options.addr, &server).map_err(|source| SimError::Serve {
+        addr: options.addr.clone(),
+        source,
+    })?;
+    Ok(0)
+}
+
+/// Download the bots of a repository and report each one. Returns 0 if every
+/// bot was fetched, 1 if some were rejected and 2 if the index could not be read.
+#[cfg(feature = "net")]
+fn run_fetch(options: cli::FetchOptions) -> i32 {
+    let fetched = match fetch(&Source::parse(&options.source), &options.dir) {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let mut rejected = 0;
+    for bot in &fetched {
+        match &bot.result {
+            Ok(path) => println!("Fetched {} -> {}", bot.name, path.display()),
+            Err(e) => {
+                eprintln!("Rejected {}", e);
+                rejected += 1;
             }
-            Instruction::Dec { reg } => {
-                if let Some(val) = robot.registers.get_mut(reg) {
-                    *val -= 1;
+        }
+    }
+    println!(
+        "{} of {} bots fetched into {}",
+        fetched.len() - rejected,
+        fetched.len(),
+        options.dir.display()
+    );
+    i32::from(rejected > 0)
+}
+
+/// Game constants from `--config` (or `game.toml` in the config directory, or
+/// the defaults) with `--set` overrides applied.
+fn load_config(path: Option<&Path>, overrides: &[String]) -> Result<GameConfig, String> {
+    let default = Dirs::locate().game_config();
+    let mut config = match path.or(default.as_deref()) {
+        Some(path) => GameConfig::load(path)?,
+        None => GameConfig::default(),
+    };
+    for assignment in overrides {
+        config.apply_override(assignment)?;
+    }
+    Ok(config)
+}
+
+/// Window settings from `--settings` (or `settings.toml` in the config
+/// directory, or the defaults), and the language the window shows its text in.
+#[cfg(feature = "render")]
+fn load_settings(path: Option<PathBuf>) -> Result<Settings, SimError> {
+    let settings = match path.or_else(|| Dirs::locate().settings()) {
+        Some(path) => Settings::load(&path).map_err(SimError::Config)?,
+        None => Settings::default(),
+    };
+    locale::select(settings.locale.as_deref());
+    Ok(settings)
+}
+
+/// Window keys from `--viewer-keys` (or `viewer_keys.toml` in the config
+/// directory, or the defaults).
+#[cfg(feature = "render")]
+fn load_viewer_keys(path: Option<PathBuf>) -> Result<ViewerKeys, SimError> {
+    match path.or_else(|| Dirs::locate().viewer_keys()) {
+        Some(path) => ViewerKeys::load(&path).map_err(SimError::Config),
+        None => Ok(ViewerKeys::default()),
+    }
+}
+
+/// The window settings and the first player's keys, with the files the
+/// settings screen saves them to: those given on the command line, or else
+/// the ones in the config directory, written there if need be.
+#[cfg(feature = "render")]
+fn load_preferences(options: &cli::Options) -> Result<Preferences, SimError> {
+    let dirs = Dirs::locate();
+    let controls = match options.controls.clone().or_else(|| dirs.controls()) {
+        Some(path) => Bindings::load(&path).map_err(SimError::Config)?,
+        None => Bindings::default(),
+    };
+    Ok(Preferences {
+        settings: load_settings(options.settings.clone())?,
+        settings_path: options
+            .settings
+            .clone()
+            .unwrap_or_else(|| dirs.config.join(SETTINGS)),
+        controls,
+        controls_path: options
+            .controls
+            .clone()
+            .unwrap_or_else(|| dirs.config.join(CONTROLS)),
+        keys: load_viewer_keys(options.viewer_keys.clone())?,
+    })
+}
+
+/// Run the match without rendering until it ends or hits one of `limits`, and
+/// print the outcome. Returns the final simulation and the limit it hit, if any.
+fn run_headless(
+    mut sim: Simulation,
+    mut recorder: Option<Recorder>,
+    mut replay: Option<&mut ReplayWriter>,
+    limits: RunLimits,
+) -> (Simulation, Option<EndCondition>) {
+    // Handicaps can start robots unequal, so show what each one starts with
+    for robot in &sim.robots {
+        println!(
+            "Robot {} {}: {} health, {} energy, {} ammo, speed {:.2}",
+            robot.id,
+            robot.name,
+            robot.health,
+            robot.energy,
+            robot.ammo,
+            robot.move_speed(&sim.config)
+        );
+    }
+    let started = Instant::now();
+    let mut stopped = None;
+    while !sim.is_over() {
+        stopped = limits.reached(&sim, started);
+        if stopped.is_some() {
+            break;
+        }
+        sim.step();
+        if let Some(rec) = recorder.as_mut()
+            && let Err(e) = rec.capture(&sim)
+        {
+            eprintln!("Recording stopped: {}", e);
+            recorder = None;
+        }
+        if let Some(writer) = replay.as_deref_mut()
+            && let Err(e) = writer.capture(&mut sim)
+        {
+            eprintln!("Replay autosave stopped: {}", e);
+            replay = None;
+        }
+    }
+    // A match stopped by a limit is not over, so the recording is still open
+    if let Some(rec) = recorder.as_mut()
+        && let Err(e) = rec.finish()
+    {
+        eprintln!("Failed to finish recording: {}", e);
+    }
+
+    if let Some(limit) = stopped.or_else(|| sim.stopped_by_rules()) {
+        let reason = match limit {
+            EndCondition::Timeout => "timed out",
+            EndCondition::Stalemate => "stalemate",
+            _ => "tick limit reached",
+        };
+        let judgement = (limit == EndCondition::TickLimit)
+            .then(|| results::judge(&sim))
+            .flatten();
+        let verdict = match &judgement {
+            Some(judgement) => {
+                for (robot, card) in sim.robots.iter().zip(&judgement.scorecards) {
+                    println!(
+                        "  {} #{}: {:.1} points ({:.1} damage, {:.1} kills, {:.1} survival, {:.1} health, {:.1} objective)",
+                        robot.name,
+                        robot.id,
+                        card.total,
+                        card.damage,
+                        card.kills,
+                        card.survival,
+                        card.health,
+                        card.objective
+                    );
                 }
-                robot.ip += 1;
-            }
-            Instruction::Jnz { reg, label } => {
-                let jump = match robot.registers.get(reg) {
-                    Some(val) => *val != 0,
-                    None => reg == "always",
-                };
-                if jump {
-                    if let Some(target) = robot.instruction_queue.iter().position(|i| match i {
-                        Instruction::Label(l) => l == label,
-                        _ => false,
-                    }) {
-                        robot.ip = target;
-                    } else {
-                        robot.ip += 1;
-                    }
-                } else {
-                    robot.ip += 1;
+                match judgement
+                    .winner
+                    .and_then(|team| sim.robots.iter().find(|r| r.team == team))
+                {
+                    Some(winner) => format!("{} #{} wins on points", winner.name, winner.id),
+                    None => "tied on points".to_string(),
                 }
             }
-            Instruction::Label(_) => {
-                robot.ip += 1;
-            }
+            None => "no winner".to_string(),
+        };
+        println!(
+            "Match stopped after {} ticks: {}, {}",
+            sim.tick, reason, verdict
+        );
+        return (sim, stopped);
+    }
+
+    if sim.mode == GameMode::WaveSurvival {
+        let score = sim.scores.values().sum::<u32>();
+        println!(
+            "Match over after {} ticks: destroyed in wave {} with {} points",
+            sim.tick, sim.wave, score
+        );
+        return (sim, None);
+    }
+
+    if sim.mode == GameMode::Practice {
+        let outcome = match &sim.drill {
+            Some(drill) if drill.is_complete() => "drill complete",
+            _ => "robot destroyed",
+        };
+        println!("Practice over after {} ticks: {}", sim.tick, outcome);
+        return (sim, None);
+    }
+
+    if let Some(outcome) = sim.objective.as_ref().and_then(|o| o.outcome.as_ref()) {
+        let verdict = if outcome.passed { "passed" } else { "failed" };
+        println!(
+            "Scenario over after {} ticks: {} ({})",
+            sim.tick, verdict, outcome.reason
+        );
+        return (sim, None);
+    }
+
+    let winner = sim
+        .winning_team()
+        .and_then(|team| sim.robots.iter().find(|r| r.team == team));
+    match winner {
+        Some(winner) => {
+            let score = match sim.scores.get(&winner.team) {
+                Some(points) => format!(" with {} points", points),
+                None => String::new(),
+            };
+            println!(
+                "Match over after {} ticks: {} #{} wins{}",
+                sim.tick, winner.name, winner.id, score
+            )
         }
+        None => println!("Match over after {} ticks: no survivors", sim.tick),
     }
+    (sim, None)
 }
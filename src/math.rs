@@ -0,0 +1,156 @@
+// Trigonometry for the simulation, native or portable.
+// Basic float arithmetic and `sqrt` are exactly rounded under IEEE 754, so they
+// give the same bits on every platform; `sin`, `cos` and `atan2` come from the
+// platform's math library and can differ in the last bit between Linux,
+// Windows and WASM. A few ulps are enough to send a replay down another path,
+// so `math = "portable"` in the config replaces them with versions written in
+// plain f64 arithmetic, which agree everywhere.
+
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use serde::{Deserialize, Serialize};
+
+/// Where the simulation's trigonometry comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MathMode {
+    /// The platform's math library: fast, but replays may diverge between
+    /// platforms.
+    #[default]
+    Native,
+    /// Built from IEEE basic operations only, so every platform computes the
+    /// same bits.
+    Portable,
+}
+
+impl MathMode {
+    /// Sine and cosine of an angle in radians.
+    pub fn sin_cos(self, radians: f32) -> (f32, f32) {
+        match self {
+            MathMode::Native => (radians.sin(), radians.cos()),
+            MathMode::Portable => {
+                let (sin, cos) = sin_cos(f64::from(radians));
+                (sin as f32, cos as f32)
+            }
+        }
+    }
+
+    /// Angle of the point (x, y) in radians, in (-π, π], like `f32::atan2`.
+    pub fn atan2(self, y: f32, x: f32) -> f32 {
+        match self {
+            MathMode::Native => y.atan2(x),
+            MathMode::Portable => atan2(f64::from(y), f64::from(x)) as f32,
+        }
+    }
+
+    /// Like `atan2`, in double precision.
+    pub fn atan2_f64(self, y: f64, x: f64) -> f64 {
+        match self {
+            MathMode::Native => y.atan2(x),
+            MathMode::Portable => atan2(y, x),
+        }
+    }
+}
+
+/// Sine and cosine from their Taylor series, after reducing the angle to
+/// [-π/4, π/4]. Accurate to about 1e-16 for the angles a match produces.
+fn sin_cos(x: f64) -> (f64, f64) {
+    let quadrant = (x / FRAC_PI_2).round();
+    let r = x - quadrant * FRAC_PI_2;
+    let r2 = r * r;
+    // Horner form: sin r = r (1 - r²/(2·3) (1 - r²/(4·5) (1 - ...)))
+    let mut sin = 1.0;
+    let mut cos = 1.0;
+    for n in (1..=8).rev() {
+        let n = f64::from(n);
+        sin = 1.0 - r2 / ((2.0 * n) * (2.0 * n + 1.0)) * sin;
+        cos = 1.0 - r2 / ((2.0 * n - 1.0) * (2.0 * n)) * cos;
+    }
+    let sin = r * sin;
+    match (quadrant as i64).rem_euclid(4) {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+/// Arctangent from its Taylor series, after reducing the argument to
+/// |x| <= tan(π/16).
+fn atan(x: f64) -> f64 {
+    if x.is_nan() {
+        return x;
+    }
+    let (x, sign) = if x < 0.0 { (-x, -1.0) } else { (x, 1.0) };
+    let (x, inverted) = if x > 1.0 { (1.0 / x, true) } else { (x, false) };
+    // atan x = 2 atan(x / (1 + √(1 + x²))), applied twice
+    let mut x = x;
+    for _ in 0..2 {
+        x /= 1.0 + (1.0 + x * x).sqrt();
+    }
+    let x2 = x * x;
+    let mut series = 0.0;
+    for n in (0..=12).rev() {
+        series = 1.0 / f64::from(2 * n + 1) - x2 * series;
+    }
+    let angle = 4.0 * x * series;
+    sign * if inverted { FRAC_PI_2 - angle } else { angle }
+}
+
+/// Angle of the point (x, y), in (-π, π].
+fn atan2(y: f64, x: f64) -> f64 {
+    if x > 0.0 {
+        atan(y / x)
+    } else if x < 0.0 && y >= 0.0 {
+        atan(y / x) + PI
+    } else if x < 0.0 {
+        atan(y / x) - PI
+    } else if y > 0.0 {
+        FRAC_PI_2
+    } else if y < 0.0 {
+        -FRAC_PI_2
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_portable_trig_matches_native() {
+        for step in -720..=720 {
+            let radians = (step as f64 * 0.5).to_radians();
+            let (sin, cos) = sin_cos(radians);
+            assert!((sin - radians.sin()).abs() < 1e-15, "sin {}", radians);
+            assert!((cos - radians.cos()).abs() < 1e-15, "cos {}", radians);
+        }
+        for y in -20..=20 {
+            for x in -20..=20 {
+                let (y, x) = (f64::from(y) * 0.7, f64::from(x) * 1.3);
+                assert!(
+                    (atan2(y, x) - y.atan2(x)).abs() < 1e-15,
+                    "atan2 {} {}",
+                    y,
+                    x
+                );
+            }
+        }
+        assert_eq!(atan2(0.0, -1.0), PI);
+        assert_eq!(atan2(0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_modes_agree_to_f32_precision() {
+        let portable = MathMode::Portable;
+        for degrees in [0.0f32, 30.0, 45.0, 90.0, 135.0, 271.5, 359.0, -45.0] {
+            let (sin, cos) = portable.sin_cos(degrees.to_radians());
+            let (native_sin, native_cos) = MathMode::Native.sin_cos(degrees.to_radians());
+            assert!((sin - native_sin).abs() <= f32::EPSILON);
+            assert!((cos - native_cos).abs() <= f32::EPSILON);
+        }
+        assert_eq!(portable.atan2(1.0, 1.0).to_degrees().round(), 45.0);
+        assert_eq!(portable.atan2(-3.0, 0.0), -std::f32::consts::FRAC_PI_2);
+    }
+}
@@ -0,0 +1,247 @@
+//! In-game pause menu for the window frontend.
+//!
+//! Opened with Esc. Navigation is with the Up/Down keys and Enter; each entry also
+//! has a shortcut shown next to its label. Left and Right step the paused match
+//! one tick back or forward (handled by the window frontend). All but the
+//! navigation keys can be changed in the viewer keys file.
+
+use macroquad::prelude::*;
+
+use crate::bindings::{ViewerKeys, key_label, pressed};
+use crate::locale;
+use crate::settings::{SPEEDS, Settings};
+
+/// Something the user asked for from the menu.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    Resume,
+    Restart,
+    ReloadScripts,
+    CycleSpeed,
+    ToggleDebug,
+    ToggleSplit,
+    ToggleObserver,
+    TogglePerformance,
+    OpenSettings,
+    Quit,
+}
+
+/// Menu entries in display order, with the catalog keys of their labels.
+const ENTRIES: [(MenuAction, &str); 10] = [
+    (MenuAction::Resume, "menu.resume"),
+    (MenuAction::Restart, "menu.restart"),
+    (MenuAction::ReloadScripts, "menu.reload"),
+    (MenuAction::CycleSpeed, "menu.speed"),
+    (MenuAction::ToggleDebug, "menu.debug"),
+    (MenuAction::ToggleSplit, "menu.split"),
+    (MenuAction::ToggleObserver, "menu.observer"),
+    (MenuAction::TogglePerformance, "menu.performance"),
+    (MenuAction::OpenSettings, "menu.settings"),
+    (MenuAction::Quit, "menu.quit"),
+];
+
+/// The shortcut keys of `action`.
+fn shortcut(action: MenuAction, keys: &ViewerKeys) -> &[String] {
+    match action {
+        MenuAction::Resume => &keys.resume,
+        MenuAction::Restart => &keys.restart,
+        MenuAction::ReloadScripts => &keys.reload,
+        MenuAction::CycleSpeed => &keys.speed,
+        MenuAction::ToggleDebug => &keys.debug,
+        MenuAction::ToggleSplit => &keys.split,
+        MenuAction::ToggleObserver => &keys.observer,
+        MenuAction::TogglePerformance => &keys.performance,
+        MenuAction::OpenSettings => &keys.settings,
+        MenuAction::Quit => &keys.quit,
+    }
+}
+
+/// Viewer settings the menu can change.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ViewSettings {
+    /// Index into `SPEEDS`.
+    pub speed: usize,
+    pub debug_overlay: bool,
+    /// Split the window between two robots, each with its own view.
+    pub split_screen: bool,
+    /// Show spectators where shots are headed, what scanners cover and
+    /// where robots are steering.
+    pub observer_overlay: bool,
+    /// Show frame and tick times, entity counts and allocations.
+    pub performance_overlay: bool,
+}
+
+impl ViewSettings {
+    /// The view the window opens with, as the settings file has it.
+    pub fn starting(style: &Settings, split_screen: bool) -> Self {
+        ViewSettings {
+            speed: speed_index(style.speed),
+            debug_overlay: style.debug_overlay,
+            split_screen,
+            observer_overlay: style.observer_overlay,
+            performance_overlay: style.performance_overlay,
+        }
+    }
+
+    pub fn ticks_per_frame(&self) -> u32 {
+        SPEEDS[self.speed]
+    }
+
+    pub fn cycle_speed(&mut self) {
+        self.speed = (self.speed + 1) % SPEEDS.len();
+    }
+}
+
+/// The index in `SPEEDS` of `ticks_per_frame`, or of the slowest speed.
+pub fn speed_index(ticks_per_frame: u32) -> usize {
+    SPEEDS
+        .iter()
+        .position(|s| *s == ticks_per_frame)
+        .unwrap_or(0)
+}
+
+/// State of the pause menu.
+#[derive(Debug, Clone, Default)]
+pub struct PauseMenu {
+    pub open: bool,
+    selected: usize,
+    /// Feedback from the last action, e.g. reload errors.
+    pub status: Vec<String>,
+}
+
+impl PauseMenu {
+    pub fn open(&mut self) {
+        self.open = true;
+        self.selected = 0;
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+        self.status.clear();
+    }
+
+    /// Move the selection by `delta` entries, wrapping around.
+    pub fn move_selection(&mut self, delta: isize) {
+        let len = ENTRIES.len() as isize;
+        self.selected = (self.selected as isize + delta).rem_euclid(len) as usize;
+    }
+
+    pub fn selected_action(&self) -> MenuAction {
+        ENTRIES[self.selected].0
+    }
+
+    /// Read keyboard input for this frame and return the chosen action, if any.
+    pub fn handle_input(&mut self, keys: &ViewerKeys) -> Option<MenuAction> {
+        if is_key_pressed(KeyCode::Up) {
+            self.move_selection(-1);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.move_selection(1);
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            return Some(self.selected_action());
+        }
+        ENTRIES
+            .iter()
+            .find(|(action, _)| pressed(shortcut(*action, keys)))
+            .map(|(action, _)| *action)
+    }
+
+    /// Draw the menu centered over the current frame, paused at `tick`.
+    pub fn draw(&self, settings: &ViewSettings, tick: u64, keys: &ViewerKeys) {
+        let (screen_w, screen_h) = (screen_width(), screen_height());
+        draw_rectangle(0.0, 0.0, screen_w, screen_h, Color::new(0.0, 0.0, 0.0, 0.6));
+
+        let menu_w = 320.0;
+        let line_h = 34.0;
+        let menu_h = line_h * (ENTRIES.len() as f32 + 2.0) + 28.0 * self.status.len() as f32;
+        let x = (screen_w - menu_w) / 2.0;
+        let y = (screen_h - menu_h) / 2.0;
+        draw_rectangle(x, y, menu_w, menu_h, Color::new(0.1, 0.1, 0.1, 0.95));
+        draw_rectangle_lines(x, y, menu_w, menu_h, 2.0, LIGHTGRAY);
+        let title = locale::text("menu.paused");
+        draw_text(title, x + 20.0, y + line_h, 36.0, WHITE);
+        let title_w = measure_text(title, None, 36, 1.0).width;
+        draw_text(
+            &locale::format(
+                "menu.step_hint",
+                &[
+                    ("tick", &tick),
+                    ("back", &key_label(&keys.step_back)),
+                    ("forward", &key_label(&keys.step_forward)),
+                ],
+            ),
+            x + title_w + 30.0,
+            y + line_h,
+            18.0,
+            GRAY,
+        );
+
+        let toggle = |label: &str, on: bool| {
+            let key = if on { "menu.on" } else { "menu.off" };
+            locale::format(key, &[("label", &label)])
+        };
+        for (i, (action, label)) in ENTRIES.iter().enumerate() {
+            let label = locale::text(label);
+            let text = match action {
+                MenuAction::CycleSpeed => locale::format(
+                    "menu.speed_value",
+                    &[("label", &label), ("speed", &settings.ticks_per_frame())],
+                ),
+                MenuAction::ToggleDebug => toggle(label, settings.debug_overlay),
+                MenuAction::ToggleSplit => toggle(label, settings.split_screen),
+                MenuAction::ToggleObserver => toggle(label, settings.observer_overlay),
+                MenuAction::TogglePerformance => toggle(label, settings.performance_overlay),
+                _ => label.to_string(),
+            };
+            let color = if i == self.selected {
+                YELLOW
+            } else {
+                LIGHTGRAY
+            };
+            let line_y = y + line_h * (i as f32 + 2.0);
+            draw_text(&text, x + 20.0, line_y, 28.0, color);
+            draw_text(
+                &format!("[{}]", key_label(shortcut(*action, keys))),
+                x + menu_w - 90.0,
+                line_y,
+                22.0,
+                GRAY,
+            );
+        }
+
+        let mut status_y = y + line_h * (ENTRIES.len() as f32 + 2.0);
+        for line in &self.status {
+            draw_text(line, x + 20.0, status_y, 20.0, RED);
+            status_y += 28.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_wraps() {
+        let mut menu = PauseMenu::default();
+        menu.open();
+        assert_eq!(menu.selected_action(), MenuAction::Resume);
+        menu.move_selection(-1);
+        assert_eq!(menu.selected_action(), MenuAction::Quit);
+        menu.move_selection(2);
+        assert_eq!(menu.selected_action(), MenuAction::Restart);
+    }
+
+    #[test]
+    fn test_speed_cycles() {
+        let mut settings = ViewSettings::default();
+        assert_eq!(settings.ticks_per_frame(), 1);
+        for _ in 0..SPEEDS.len() - 1 {
+            settings.cycle_speed();
+        }
+        assert_eq!(settings.ticks_per_frame(), 16);
+        settings.cycle_speed();
+        assert_eq!(settings.ticks_per_frame(), 1);
+    }
+}
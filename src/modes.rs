@@ -0,0 +1,107 @@
+// Game modes: what a match is about and how it is won.
+
+/// Rules a match is played under.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GameMode {
+    /// Last robot standing wins.
+    #[default]
+    Deathmatch,
+    /// Robots score a point per tick while their team is alone in the control
+    /// zone at the center of the arena; the first team to `koth_target_score` wins.
+    KingOfTheHill,
+    /// A single robot fights ever larger waves of AI drones until it is
+    /// destroyed, scoring a point per drone it destroys.
+    WaveSurvival,
+    /// A single robot practices on target dummies that never shoot back and
+    /// drives to a checkpoint; the drill is reported once it is over.
+    Practice,
+    /// A scenario file's setup, over once its goal is met or missed. Only the
+    /// `scenario` subcommand plays it, so it has no `--mode` name.
+    Scenario,
+}
+
+impl GameMode {
+    /// Look up a mode by its command-line name.
+    pub fn from_name(name: &str) -> Option<GameMode> {
+        match name {
+            "deathmatch" => Some(GameMode::Deathmatch),
+            "koth" | "king-of-the-hill" => Some(GameMode::KingOfTheHill),
+            "waves" | "survival" => Some(GameMode::WaveSurvival),
+            "practice" | "training" => Some(GameMode::Practice),
+            _ => None,
+        }
+    }
+
+    /// Command-line name of the mode (the first one `from_name` accepts, or
+    /// the subcommand for a scenario).
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Deathmatch => "deathmatch",
+            GameMode::KingOfTheHill => "koth",
+            GameMode::WaveSurvival => "waves",
+            GameMode::Practice => "practice",
+            GameMode::Scenario => "scenario",
+        }
+    }
+}
+
+impl std::fmt::Display for GameMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameMode::Deathmatch => write!(f, "deathmatch"),
+            GameMode::KingOfTheHill => write!(f, "king of the hill"),
+            GameMode::WaveSurvival => write!(f, "wave survival"),
+            GameMode::Practice => write!(f, "practice"),
+            GameMode::Scenario => write!(f, "scenario"),
+        }
+    }
+}
+
+impl GameMode {
+    /// How many scripted robots take part, if the mode limits it.
+    pub fn max_entrants(self) -> Option<usize> {
+        match self {
+            GameMode::WaveSurvival | GameMode::Practice => Some(1),
+            _ => None,
+        }
+    }
+}
+
+/// A circular control zone, in logical arena units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Zone {
+    pub center: (f32, f32),
+    pub radius: f32,
+}
+
+impl Zone {
+    pub fn contains(&self, point: (f32, f32)) -> bool {
+        let (dx, dy) = (point.0 - self.center.0, point.1 - self.center.1);
+        dx * dx + dy * dy <= self.radius * self.radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_names() {
+        assert_eq!(GameMode::from_name("koth"), Some(GameMode::KingOfTheHill));
+        assert_eq!(
+            GameMode::from_name("deathmatch"),
+            Some(GameMode::Deathmatch)
+        );
+        assert_eq!(GameMode::from_name("ctf"), None);
+    }
+
+    #[test]
+    fn test_zone_contains() {
+        let zone = Zone {
+            center: (100.0, 100.0),
+            radius: 10.0,
+        };
+        assert!(zone.contains((105.0, 105.0)));
+        assert!(!zone.contains((108.0, 108.0)));
+    }
+}
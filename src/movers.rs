@@ -0,0 +1,229 @@
+// Arena movers: sliding doors and rotating barriers that an arena file sets in
+// motion. A mover is made of solid boxes, world entities with a collider, that
+// `kinematic_system` moves along a fixed course every tick. They block robots,
+// projectiles, the laser and scans like obstacles, and push robots out of their
+// way. Where a mover is depends on the tick alone, so replays and rewinds see
+// the same arena.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ecs::{Collider, Kinematic, Transform, World};
+use crate::math::MathMode;
+
+/// A moving arena element.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Mover {
+    /// A rectangle at `x`, `y` while closed, that slides to put its top-left
+    /// corner at `open` and back. It starts closed and spends `period` ticks
+    /// opening, then `period` ticks closing, sliding `speed` units per tick.
+    Door {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        open: (f32, f32),
+        period: u64,
+        #[serde(default = "default_door_speed")]
+        speed: f32,
+    },
+    /// A bar `length` long and `thickness` wide, turning about its middle at
+    /// `pivot` by `speed` degrees per tick, clockwise on screen if positive.
+    /// It starts at `angle` degrees.
+    Barrier {
+        pivot: (f32, f32),
+        length: f32,
+        thickness: f32,
+        speed: f32,
+        #[serde(default)]
+        angle: f32,
+    },
+}
+
+fn default_door_speed() -> f32 {
+    1.0
+}
+
+/// The course a piece of a mover follows, by the tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Course {
+    /// Between the centers of a door's closed and open positions.
+    Slide {
+        closed: (f32, f32),
+        open: (f32, f32),
+        period: u64,
+        speed: f32,
+    },
+    /// Round `pivot` at `radius`, which is negative on the far side of it.
+    Orbit {
+        pivot: (f32, f32),
+        radius: f32,
+        angle: f32,
+        speed: f32,
+    },
+}
+
+impl Course {
+    /// Where the piece's center is at `tick`.
+    pub fn position(&self, tick: u64, math: MathMode) -> (f32, f32) {
+        match *self {
+            Course::Slide {
+                closed,
+                open,
+                period,
+                speed,
+            } => {
+                let period = period.max(1);
+                let (dx, dy) = (open.0 - closed.0, open.1 - closed.1);
+                let distance = (dx * dx + dy * dy).sqrt();
+                // How far through this phase's slide the door is; a door too
+                // slow to finish within the period jumps home at its end
+                let since = (tick % period) as f32;
+                let slid = if speed > 0.0 && distance > 0.0 {
+                    (since * speed / distance).min(1.0)
+                } else {
+                    1.0
+                };
+                let opening = (tick / period) % 2 == 1;
+                let fraction = match (opening, tick < period) {
+                    (true, _) => slid,
+                    (false, true) => 0.0,
+                    (false, false) => 1.0 - slid,
+                };
+                (closed.0 + fraction * dx, closed.1 + fraction * dy)
+            }
+            Course::Orbit {
+                pivot,
+                radius,
+                angle,
+                speed,
+            } => {
+                let turned = (f64::from(speed) * tick as f64).rem_euclid(360.0) as f32;
+                let (sin, cos) = math.sin_cos((angle + turned).to_radians());
+                (pivot.0 + radius * cos, pivot.1 + radius * sin)
+            }
+        }
+    }
+}
+
+impl Mover {
+    /// Add the mover's solid boxes to `world`, where they are at `tick`.
+    pub fn spawn(&self, world: &mut World, tick: u64, math: MathMode) {
+        match *self {
+            Mover::Door {
+                x,
+                y,
+                width,
+                height,
+                open,
+                period,
+                speed,
+            } => {
+                let (half_width, half_height) = (width / 2.0, height / 2.0);
+                let course = Course::Slide {
+                    closed: (x + half_width, y + half_height),
+                    open: (open.0 + half_width, open.1 + half_height),
+                    period,
+                    speed,
+                };
+                spawn_piece(world, course, half_width, half_height, tick, math);
+            }
+            Mover::Barrier {
+                pivot,
+                length,
+                thickness,
+                speed,
+                angle,
+            } => {
+                // The bar is a row of square pieces, each circling the pivot
+                let thickness = thickness.max(1.0);
+                let pieces = (length / thickness).ceil().max(1.0) as usize;
+                let step = length / pieces as f32;
+                for k in 0..pieces {
+                    let course = Course::Orbit {
+                        pivot,
+                        radius: -length / 2.0 + step * (k as f32 + 0.5),
+                        angle,
+                        speed,
+                    };
+                    let half = thickness / 2.0;
+                    spawn_piece(world, course, half, half, tick, math);
+                }
+            }
+        }
+    }
+}
+
+fn spawn_piece(
+    world: &mut World,
+    course: Course,
+    half_width: f32,
+    half_height: f32,
+    tick: u64,
+    math: MathMode,
+) {
+    let piece = world.spawn();
+    world.transforms.insert(
+        piece,
+        Transform {
+            position: course.position(tick, math),
+            heading: 0.0,
+        },
+    );
+    world.colliders.insert(
+        piece,
+        Collider {
+            half_width,
+            half_height,
+        },
+    );
+    world.kinematics.insert(piece, Kinematic { course });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_door_opens_and_closes_on_its_timer() {
+        let door = Course::Slide {
+            closed: (10.0, 10.0),
+            open: (10.0, 50.0),
+            period: 100,
+            speed: 2.0,
+        };
+        let at = |tick| door.position(tick, MathMode::Native);
+        assert_eq!(at(0), (10.0, 10.0));
+        assert_eq!(at(99), (10.0, 10.0));
+        assert_eq!(at(110), (10.0, 30.0));
+        assert_eq!(at(150), (10.0, 50.0));
+        assert_eq!(at(210), (10.0, 30.0));
+        assert_eq!(at(299), (10.0, 10.0));
+    }
+
+    #[test]
+    fn test_barrier_is_a_turning_row_of_pieces() {
+        let barrier = Mover::Barrier {
+            pivot: (100.0, 100.0),
+            length: 40.0,
+            thickness: 10.0,
+            speed: 1.0,
+            angle: 0.0,
+        };
+        let mut world = World::default();
+        barrier.spawn(&mut world, 90, MathMode::Native);
+        let mut centers: Vec<(f32, f32)> = world
+            .kinematics
+            .iter()
+            .map(|(e, _)| world.transforms.get(e).unwrap().position)
+            .map(|(x, y)| (x.round(), y.round()))
+            .collect();
+        centers.sort_by(|a, b| a.1.total_cmp(&b.1));
+        // A quarter turn in, the bar stands upright
+        assert_eq!(
+            centers,
+            vec![(100.0, 85.0), (100.0, 95.0), (100.0, 105.0), (100.0, 115.0)]
+        );
+        assert_eq!(world.solid_boxes().count(), 4);
+    }
+}
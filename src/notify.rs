@@ -0,0 +1,249 @@
+// Webhook notifications for leagues that run matches and tournaments unattended.
+// Results are POSTed as JSON to a configured URL once a match, a tournament match
+// or a whole tournament is over. The `content` field is a short text summary, so
+// the payload can go straight to a Discord webhook; the other fields carry the
+// full result for anything else listening.
+
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::error::NotifyError;
+use crate::results::MatchResult;
+use crate::tournament::{Bracket, Pairing, Round};
+
+/// Where results are posted, and how replays are linked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notifier {
+    pub url: String,
+    /// URL the saved replays are published under; replay file names are
+    /// appended to it. Without one, links are the local paths.
+    pub replay_base: Option<String>,
+}
+
+impl Notifier {
+    pub fn new(url: &str) -> Self {
+        Notifier {
+            url: url.to_string(),
+            replay_base: None,
+        }
+    }
+
+    /// Link replays as `<base>/<file name>`.
+    pub fn with_replay_base(mut self, base: &str) -> Self {
+        self.replay_base = Some(base.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Link to a replay saved at `path`.
+    pub fn replay_link(&self, path: &Path) -> String {
+        match (&self.replay_base, path.file_name()) {
+            (Some(base), Some(file)) => format!("{}/{}", base, file.to_string_lossy()),
+            _ => path.display().to_string(),
+        }
+    }
+
+    /// Payload for a finished match, with the replay saved at `replay`, if any.
+    pub fn match_payload(&self, result: &MatchResult, replay: Option<&Path>) -> Value {
+        let name = |id: usize| {
+            let robot = result.robots.iter().find(|r| r.id == id);
+            robot.map_or(String::new(), |r| format!("{} #{}", r.name, r.id))
+        };
+        let headline = match (result.winner, result.wave) {
+            (Some(id), _) => format!(
+                "{} wins {} after {} ticks",
+                name(id),
+                result.mode,
+                result.ticks
+            ),
+            (None, Some(wave)) => format!(
+                "{} reached wave {} after {} ticks",
+                result.robots.first().map_or(String::new(), |r| name(r.id)),
+                wave,
+                result.ticks
+            ),
+            (None, None) => format!("No winner in {} after {} ticks", result.mode, result.ticks),
+        };
+        let mut content = headline;
+        for robot in &result.robots {
+            content += &format!(
+                "\n{} #{}: health {}, score {}",
+                robot.name, robot.id, robot.health, robot.score
+            );
+        }
+        let replay = replay.map(|path| self.replay_link(path));
+        if let Some(link) = &replay {
+            content += &format!("\nReplay: {}", link);
+        }
+        json!({
+            "event": "match",
+            "content": content,
+            "result": result,
+            "replay": replay,
+        })
+    }
+
+    /// Payload for one match of a tournament round.
+    pub fn pairing_payload(&self, round: &Round, pairing: &Pairing) -> Value {
+        let mut content = format!("{}: {}", round.title(), pairing.describe());
+        let replay = pairing.replay.as_deref().map(|path| self.replay_link(path));
+        if let Some(link) = &replay {
+            content += &format!("\nReplay: {}", link);
+        }
+        json!({
+            "event": "tournament_match",
+            "content": content,
+            "stage": round.stage,
+            "round": round.number,
+            "match": pairing,
+            "replay": replay,
+        })
+    }
+
+    /// Payload for a finished tournament: the champion and, for a round
+    /// robin, the final table.
+    pub fn tournament_payload(&self, bracket: &Bracket) -> Value {
+        let mut content = format!(
+            "Tournament over ({} bots): {} is the champion",
+            bracket.seeds.len(),
+            bracket.champion.as_deref().unwrap_or("nobody")
+        );
+        for (place, standing) in bracket.standings.iter().enumerate() {
+            content += &format!(
+                "\n{}. {} {} pts ({}-{}-{})",
+                place + 1,
+                standing.name,
+                standing.points,
+                standing.wins,
+                standing.draws,
+                standing.losses
+            );
+        }
+        json!({
+            "event": "tournament",
+            "content": content,
+            "format": bracket.format,
+            "champion": bracket.champion,
+            "standings": bracket.standings,
+        })
+    }
+
+    /// POST `payload` to the webhook.
+    pub fn send(&self, payload: &Value) -> Result<(), NotifyError> {
+        ureq::post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&payload.to_string())
+            .map(|_| ())
+            .map_err(|e| NotifyError {
+                url: self.url.clone(),
+                message: e.to_string(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::{EndCondition, RobotResult};
+    use crate::tournament::Stage;
+    use std::path::PathBuf;
+
+    fn robot(id: usize, name: &str, health: i32) -> RobotResult {
+        RobotResult {
+            id,
+            name: name.to_string(),
+            team: id,
+            alive: health > 0,
+            health,
+            kills: 0,
+            shots_fired: 0,
+            damage_dealt: 0,
+            score: 0,
+            judging: None,
+        }
+    }
+
+    fn result(winner: Option<usize>) -> MatchResult {
+        MatchResult {
+            mode: "deathmatch".to_string(),
+            end_condition: EndCondition::LastStanding,
+            winner,
+            seed: None,
+            ticks: 812,
+            duration_secs: 0.1,
+            wave: None,
+            sudden_death: None,
+            robots: vec![robot(1, "hunter", 6), robot(2, "patrol", 0)],
+            drill: None,
+            objective: None,
+        }
+    }
+
+    #[test]
+    fn test_match_payload() {
+        let notifier = Notifier::new("http://example.com/hook")
+            .with_replay_base("https://example.com/replays/");
+        let payload = notifier.match_payload(&result(Some(1)), Some(Path::new("out/m1.json")));
+        assert_eq!(
+            payload["content"],
+            "hunter #1 wins deathmatch after 812 ticks\n\
+             hunter #1: health 6, score 0\n\
+             patrol #2: health 0, score 0\n\
+             Replay: https://example.com/replays/m1.json"
+        );
+        assert_eq!(payload["result"]["winner"], 1);
+
+        let local = Notifier::new("http://example.com/hook");
+        let payload = local.match_payload(&result(None), None);
+        let content = payload["content"].as_str().unwrap();
+        assert!(content.starts_with("No winner in deathmatch after 812 ticks"));
+        assert!(payload["replay"].is_null());
+    }
+
+    #[test]
+    fn test_pairing_payload_links_replay() {
+        let notifier = Notifier::new("http://example.com/hook");
+        let pairing = Pairing {
+            a: "hunter".to_string(),
+            b: Some("patrol".to_string()),
+            winner: Some("patrol".to_string()),
+            ticks: 500,
+            tiebreak: true,
+            replay: Some(PathBuf::from("replays/001-hunter-vs-patrol.json")),
+        };
+        let round = Round {
+            stage: Stage::Winners,
+            number: 2,
+            matches: vec![pairing.clone()],
+        };
+        let payload = notifier.pairing_payload(&round, &pairing);
+        assert_eq!(
+            payload["content"],
+            "Winners round 2: patrol beat hunter (500 ticks, on tiebreak)\n\
+             Replay: replays/001-hunter-vs-patrol.json"
+        );
+        assert_eq!(payload["match"]["winner"], "patrol");
+    }
+
+    #[test]
+    fn test_send_posts_json() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr());
+        let receiver = std::thread::spawn(move || {
+            let mut request = server.recv().unwrap();
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let method = request.method().to_string();
+            request.respond(tiny_http::Response::empty(204)).unwrap();
+            (method, body)
+        });
+        let notifier = Notifier::new(&url);
+        notifier.send(&json!({ "content": "hello" })).unwrap();
+        let (method, body) = receiver.join().unwrap();
+        assert_eq!(method, "POST");
+        assert_eq!(body, r#"{"content":"hello"}"#);
+
+        let closed = Notifier::new("http://127.0.0.1:9/hook");
+        assert!(closed.send(&json!({})).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+// The observer overlay: what a spectator sees on top of a match to follow it
+// and explain it. Every shot in flight is projected along the path it will
+// take if nothing moves, bouncing as a ricochet shot does, to the robot it will
+// hit or wherever it ends; robots show where their current instruction is
+// steering them. Players never see it, as it gives away more than their
+// scanners could.
+
+use crate::ast::{Instruction, Operand, Section};
+use crate::config::Topology;
+use crate::ecs::Entity;
+use crate::raycast::{self, Obstacle};
+use crate::simulation::Simulation;
+use crate::systems::arena_walls;
+
+/// Ticks a shot without a lifetime is projected for.
+const MAX_PROJECTION_TICKS: u32 = 600;
+/// How far past a face a bouncing projection restarts, as `movement_system`
+/// moves a bouncing shot off the surface it hit.
+const BOUNCE_OFFSET: f32 = 0.01;
+
+/// The path a shot in flight will take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory {
+    /// Where the shot is, every point it bounces at, and where it ends.
+    pub points: Vec<(f32, f32)>,
+    /// The robot it ends in, by index, if it hits one.
+    pub hits: Option<usize>,
+}
+
+/// The projected path of every shot in flight.
+pub fn trajectories(sim: &Simulation) -> Vec<Trajectory> {
+    sim.world
+        .damages
+        .iter()
+        .filter_map(|(entity, _)| project(sim, entity))
+        .collect()
+}
+
+/// Project the shot `entity` along its path, supposing robots and the arena
+/// stay where they are.
+pub fn project(sim: &Simulation, entity: Entity) -> Option<Trajectory> {
+    let transform = sim.world.transforms.get(entity)?;
+    let speed = sim.world.velocities.get(entity)?.speed;
+    let owner = sim.world.damages.get(entity)?.owner;
+    let ticks = sim
+        .world
+        .lifetimes
+        .get(entity)
+        .map_or(MAX_PROJECTION_TICKS, |l| l.ticks);
+    let mut bounces = sim.world.bounces.get(entity).map_or(0, |b| b.remaining);
+    let math = sim.config.math;
+
+    let walls = arena_walls(sim.config.arena_width, sim.config.arena_height);
+    let mut surfaces = sim.blockers();
+    if sim.config.topology == Topology::Bounded {
+        surfaces.extend_from_slice(&walls);
+    }
+    // The hulls of the robots the shot can hit, as contact damage finds them
+    let owner_team = sim.robots.iter().find(|r| r.id == owner).map(|r| r.team);
+    let level = sim.world.levels.get(entity).copied();
+    let (width, height) = (sim.config.robot_width, sim.config.robot_height);
+    let targets: Vec<(usize, Obstacle)> = sim
+        .robots
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| {
+            r.id != owner
+                && Some(r.team) != owner_team
+                && r.health > 0
+                && level.is_none_or(|l| l.meets(sim.elevation.level_at(r.position)))
+        })
+        .map(|(i, r)| {
+            let corner = (r.position.0 - width / 2.0, r.position.1 - height / 2.0);
+            (i, Obstacle::new(corner.0, corner.1, width, height))
+        })
+        .collect();
+    let hulls: Vec<Obstacle> = targets.iter().map(|(_, hull)| hull.clone()).collect();
+
+    let mut position = transform.position;
+    let mut heading = transform.heading;
+    let mut travel = speed * ticks as f32;
+    let mut points = vec![position];
+    loop {
+        let angle = heading.to_radians();
+        let (sin, cos) = math.sin_cos(angle);
+        let along = |distance: f32| (position.0 + distance * cos, position.1 + distance * sin);
+        let mut hit = raycast::cast_ray_hit(position, angle, travel, &surfaces, math);
+        // A torus has no walls to stop the shot, but the projection ends at its edge
+        if sim.config.topology == Topology::Torus
+            && let Some(edge) = raycast::cast_ray(position, angle, travel, &walls, math)
+            && hit.is_none_or(|hit| edge < hit.distance)
+        {
+            travel = edge;
+            hit = None;
+        }
+        let reach = hit.map_or(travel, |hit| hit.distance);
+        if let Some((k, distance)) = raycast::cast_ray_index(position, angle, reach, &hulls, math) {
+            points.push(along(distance));
+            return Some(Trajectory {
+                points,
+                hits: Some(targets[k].0),
+            });
+        }
+        let Some(hit) = hit else {
+            points.push(along(travel));
+            break;
+        };
+        points.push(along(hit.distance));
+        if bounces == 0 || hit.normal == (0.0, 0.0) {
+            break;
+        }
+        let bounced = along(hit.distance);
+        position = (
+            bounced.0 + hit.normal.0 * BOUNCE_OFFSET,
+            bounced.1 + hit.normal.1 * BOUNCE_OFFSET,
+        );
+        heading = raycast::reflect_heading(heading, hit.normal, math);
+        travel -= hit.distance;
+        bounces -= 1;
+    }
+    Some(Trajectory { points, hits: None })
+}
+
+/// The heading, in degrees, robot `i`'s current instruction is turning or
+/// driving it toward, if it is steering anywhere. Robots driven by a player
+/// have no instruction to go by.
+pub fn intended_heading(sim: &Simulation, i: usize) -> Option<f32> {
+    let robot = &sim.robots[i];
+    if robot.health <= 0 || robot.controls.is_some() {
+        return None;
+    }
+    let toward = |target: (f32, f32)| {
+        let (dx, dy) = sim.config.displacement(robot.position, target);
+        sim.config.math.atan2(dy, dx).to_degrees()
+    };
+    match robot.current_instruction()? {
+        Instruction::MoveForward => Some(robot.heading),
+        Instruction::TurnLeft => Some(robot.heading - sim.config.turn_rate),
+        Instruction::TurnRight => Some(robot.heading + sim.config.turn_rate),
+        Instruction::GoTo { x, y } => Some(toward((*x as f32, *y as f32))),
+        Instruction::FollowPath => {
+            let &(x, y) = robot.path.get(robot.waypoint)?;
+            Some(toward((x as f32, y as f32)))
+        }
+        Instruction::TurnTo {
+            section: Section::Body,
+            target,
+        } => match target {
+            Operand::Reg(reg) => robot.registers.get(*reg).map(|t| t as f32),
+            Operand::Const(n) => Some(*n as f32),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Robot;
+    use crate::config::GameConfig;
+
+    #[test]
+    fn test_ricochet_projection_bounces_into_its_target() {
+        let robots = vec![
+            Robot::new(1, "shooter", (50.0, 50.0), vec![Instruction::FireRicochet]),
+            Robot::new(2, "target", (20.0, 50.0), vec![]),
+        ];
+        let wall = Obstacle::new(90.0, 0.0, 10.0, 100.0);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        sim.step();
+        let paths = trajectories(&sim);
+        assert_eq!(paths.len(), 1);
+        // Off the wall, past the shooter and into the robot behind it
+        let path = &paths[0];
+        assert_eq!(path.points.len(), 3);
+        assert!((path.points[1].0 - 90.0).abs() < 0.1);
+        assert_eq!(path.hits, Some(1));
+    }
+
+    #[test]
+    fn test_intended_heading_follows_the_current_instruction() {
+        let program = vec![Instruction::GoTo { x: 50, y: 150 }];
+        let sim = Simulation::new(
+            GameConfig::default(),
+            vec![Robot::new(1, "driver", (50.0, 50.0), program)],
+            vec![],
+        );
+        let heading = intended_heading(&sim, 0).unwrap();
+        assert!((heading - 90.0).abs() < 0.01);
+    }
+}
@@ -1,159 +1,671 @@
 // Parser for the robot-battle DSL.
 // Converts a stream of tokens into an AST (Vec<Command>).
 //
-// Supports: move, rotate, scan, fire, loop { ... }
+// The commands and how each reads its arguments are in the command table
+// (`commands::COMMANDS`); this module finds the command each keyword starts
+// and parses blocks and expressions for them.
+//
+// Line breaks carry no meaning: commands may share a line, and `;` may separate
+// them anywhere a command could end, so `loop { scan; fire }` is one line.
+// The body of a `loop`, `if` or `else` is a block or a single command, and a
+// file that starts with `# syntax: do-end` may write blocks as `do ... end`.
+// Top-level `test "name" { ... }` blocks are parsed too, but are not part of
+// the program (see `script_tests`).
+
+use crate::ast::{Block, Command, Expr, HistoryField, MathOp};
+use crate::commands;
+use crate::error::{CompileError, ParseError, ScriptError, Span};
+use crate::plugin::Plugins;
+use crate::script_tests::{Opponent, ScriptTest, TestStep};
+use crate::tokenizer::{Token, has_pragma, tokenize_with_spans};
 
-use crate::ast::{Command, Section};
-use crate::tokenizer::Token;
+/// Comment that switches a file to `do ... end` blocks, before its first command.
+const DO_END_PRAGMA: &str = "# syntax: do-end";
 
+/// How blocks are written in a script.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlockSyntax {
+    /// `{ ... }` only.
+    #[default]
+    Braces,
+    /// `do ... end` as well as braces; `do` and `end` are reserved.
+    DoEnd,
+}
+
+impl BlockSyntax {
+    /// The syntax `source` asks for: `DoEnd` if `DO_END_PRAGMA` comes before
+    /// any code.
+    pub fn of(source: &str) -> BlockSyntax {
+        if has_pragma(source, DO_END_PRAGMA) {
+            BlockSyntax::DoEnd
+        } else {
+            BlockSyntax::Braces
+        }
+    }
+}
+
+/// A parse or compile error, before it is located in the script.
 #[derive(Debug)]
-pub enum ParseError {
-    UnexpectedEOF,
-    #[allow(dead_code)]
-    UnexpectedToken(Token),
-    InvalidCommand,
+pub(crate) enum Fault {
+    Parse(ParseError),
+    Compile(CompileError),
+}
+
+impl From<ParseError> for Fault {
+    fn from(error: ParseError) -> Self {
+        Fault::Parse(error)
+    }
+}
+
+impl From<CompileError> for Fault {
+    fn from(error: CompileError) -> Self {
+        Fault::Compile(error)
+    }
+}
+
+/// Tokenize and parse a script's source text.
+///
+/// ```
+/// use robot_battle::{Command, parse_script};
+///
+/// let commands = parse_script("scan\nfire").unwrap();
+/// assert_eq!(commands, vec![Command::Scan, Command::Fire]);
+///
+/// let err = parse_script("rotate sideways 90").unwrap_err();
+/// assert_eq!(err.to_string(), "line 1, column 8: unexpected token `sideways`");
+/// ```
+pub fn parse_script(source: &str) -> Result<Vec<Command>, ScriptError> {
+    parse_script_with_spans(source).map(|(commands, _)| commands)
+}
+
+/// Like `parse_script`, also accepting the commands of `plugins`.
+pub fn parse_script_with(source: &str, plugins: &Plugins) -> Result<Vec<Command>, ScriptError> {
+    let (tokens, spans) = tokenize_with_spans(source)?;
+    parse_program(&tokens, &spans, BlockSyntax::of(source), plugins).map(|program| program.commands)
+}
+
+/// Tokenize and parse a script, also returning where each command starts.
+/// Spans are in the order the commands appear in the source, so a loop comes
+/// before the commands in its block.
+pub fn parse_script_with_spans(source: &str) -> Result<(Vec<Command>, Vec<Span>), ScriptError> {
+    let (tokens, spans) = tokenize_with_spans(source)?;
+    parse_tokens_with_spans(&tokens, &spans, BlockSyntax::of(source))
 }
 
 /// Parse a script (token stream) into a sequence of commands (AST).
-pub fn parse_tokens(tokens: &[Token]) -> Result<Vec<Command>, ParseError> {
+/// `spans` holds the position of each token; errors are reported at the last
+/// token read, which for an unexpected end of script is the final token.
+/// Blocks are written with braces.
+pub fn parse_tokens(tokens: &[Token], spans: &[Span]) -> Result<Vec<Command>, ScriptError> {
+    parse_tokens_with_spans(tokens, spans, BlockSyntax::Braces).map(|(commands, _)| commands)
+}
+
+/// Like `parse_tokens`, with blocks written in `syntax`, also returning where
+/// each command starts, as for `parse_script_with_spans`.
+pub fn parse_tokens_with_spans(
+    tokens: &[Token],
+    spans: &[Span],
+    syntax: BlockSyntax,
+) -> Result<(Vec<Command>, Vec<Span>), ScriptError> {
+    parse_program(tokens, spans, syntax, &Plugins::default())
+        .map(|program| (program.commands, program.starts))
+}
+
+/// Tokenize and parse the `test` blocks of a script. The rest of the script
+/// must parse too.
+pub fn parse_tests(source: &str) -> Result<Vec<ScriptTest>, ScriptError> {
+    let (tokens, spans) = tokenize_with_spans(source)?;
+    parse_program(
+        &tokens,
+        &spans,
+        BlockSyntax::of(source),
+        &Plugins::default(),
+    )
+    .map(|program| program.tests)
+}
+
+/// A whole script, as parsed.
+struct Program {
+    commands: Vec<Command>,
+    /// Where each command starts, as for `parse_script_with_spans`.
+    starts: Vec<Span>,
+    tests: Vec<ScriptTest>,
+}
+
+/// Parse a whole script: its commands, where each starts, and its tests.
+fn parse_program(
+    tokens: &[Token],
+    spans: &[Span],
+    syntax: BlockSyntax,
+    plugins: &Plugins,
+) -> Result<Program, ScriptError> {
     let mut idx = 0;
     let mut commands = Vec::new();
+    let mut starts = Vec::new();
+    let mut tests = Vec::new();
+    // Errors are reported at the last token read
+    let locate = |fault: Fault, idx: usize| {
+        let span = spans
+            .get(idx.saturating_sub(1))
+            .copied()
+            .unwrap_or_default();
+        match fault {
+            Fault::Parse(source) => ScriptError::Parse { span, source },
+            Fault::Compile(source) => ScriptError::Compile { span, source },
+        }
+    };
 
     while idx < tokens.len() {
         match &tokens[idx] {
-            Token::Keyword(k) if k == "move" => {
-                // move <direction> <distance>
-                idx += 1;
-                let direction = match tokens.get(idx) {
-                    Some(Token::Identifier(dir)) => dir.clone(),
-                    Some(tok) => return Err(ParseError::UnexpectedToken(tok.clone())),
-                    None => return Err(ParseError::UnexpectedEOF),
-                };
-                idx += 1;
-                let distance = match tokens.get(idx) {
-                    Some(Token::Number(n)) => *n,
-                    Some(tok) => return Err(ParseError::UnexpectedToken(tok.clone())),
-                    None => return Err(ParseError::UnexpectedEOF),
-                };
-                idx += 1;
-                commands.push(Command::Move {
-                    direction,
-                    distance,
-                });
-            }
-            Token::Keyword(k) if k == "rotate" => {
-                // rotate <section> <angle>
-                idx += 1;
-                let section = match tokens.get(idx) {
-                    Some(Token::Keyword(k)) if k == "body" => Section::Body,
-                    Some(Token::Keyword(k)) if k == "turret" => Section::Turret,
-                    Some(Token::Keyword(k)) if k == "scanner" => Section::Scanner,
-                    Some(tok) => return Err(ParseError::UnexpectedToken(tok.clone())),
-                    None => return Err(ParseError::UnexpectedEOF),
-                };
-                idx += 1;
-                let angle = match tokens.get(idx) {
-                    Some(Token::Number(n)) => *n,
-                    Some(tok) => return Err(ParseError::UnexpectedToken(tok.clone())),
-                    None => return Err(ParseError::UnexpectedEOF),
-                };
+            Token::Symbol('}') | Token::Symbol('{') | Token::Symbol(';') => {
+                // Separators and stray block delimiters are skipped
                 idx += 1;
-                commands.push(Command::Rotate { section, angle });
             }
-            Token::Keyword(k) if k == "scan" => {
-                idx += 1;
-                commands.push(Command::Scan);
-            }
-            Token::Keyword(k) if k == "fire" => {
-                idx += 1;
-                commands.push(Command::Fire);
-            }
-            Token::Keyword(k) if k == "loop" => {
-                idx += 1;
-                // Expect '{'
-                match tokens.get(idx) {
-                    Some(Token::Symbol('{')) => idx += 1,
-                    Some(tok) => return Err(ParseError::UnexpectedToken(tok.clone())),
-                    None => return Err(ParseError::UnexpectedEOF),
+            Token::Identifier(word)
+                if word == TEST && matches!(tokens.get(idx + 1), Some(Token::Str(_))) =>
+            {
+                match parse_test(tokens, spans, &mut idx, syntax) {
+                    Ok(test) => tests.push(test),
+                    Err(fault) => return Err(locate(fault, idx)),
                 }
-                // Parse block until matching '}'
-                let mut block = Vec::new();
-                while idx < tokens.len() {
-                    match &tokens[idx] {
-                        Token::Symbol('}') => {
-                            idx += 1;
-                            break;
-                        }
-                        _ => {
-                            // Recursively parse commands inside the block
-                            let start = idx;
-                            // Parse one command
-                            match parse_tokens(&tokens[start..]) {
-                                Ok(mut inner_cmds) if !inner_cmds.is_empty() => {
-                                    // Only take the first command parsed
-                                    block.push(inner_cmds.remove(0));
-                                    // Advance idx by the number of tokens consumed for that command
-                                    idx += tokens_consumed_for_command(&tokens[start..]);
-                                }
-                                Ok(_) => break,
-                                Err(e) => return Err(e),
-                            }
-                        }
-                    }
-                }
-                commands.push(Command::Loop { block });
             }
-            Token::Symbol('}') | Token::Symbol('{') => {
-                // Block delimiters are handled in loop parsing, skip them here
-                idx += 1;
+            _ => match parse_command(tokens, spans, &mut idx, &mut starts, syntax, plugins) {
+                Ok(Command::Build(_)) if !commands.is_empty() => {
+                    return Err(locate(ParseError::MisplacedBuild.into(), idx));
+                }
+                Ok(command) => commands.push(command),
+                Err(fault) => return Err(locate(fault, idx)),
+            },
+        }
+    }
+
+    Ok(Program {
+        commands,
+        starts,
+        tests,
+    })
+}
+
+/// Word that starts a test block, followed by the test's name as a string.
+const TEST: &str = "test";
+
+/// Parse the test block starting at `tokens[*idx]`: its name, then a block of
+/// `run <ticks>` and `assert <expr>` steps, and at most one `opponent`.
+fn parse_test(
+    tokens: &[Token],
+    spans: &[Span],
+    idx: &mut usize,
+    syntax: BlockSyntax,
+) -> Result<ScriptTest, Fault> {
+    let line = spans.get(*idx).map_or(0, |span| span.line);
+    *idx += 1;
+    let name = match next(tokens, idx)? {
+        Token::Str(name) => name.clone(),
+        tok => return Err(ParseError::UnexpectedToken(tok.clone()).into()),
+    };
+    let Some(close) = open_block(tokens, idx, syntax) else {
+        return Err(ParseError::UnexpectedToken(next(tokens, idx)?.clone()).into());
+    };
+    let mut steps = Vec::new();
+    let mut opponent = None;
+    loop {
+        let step_line = spans.get(*idx).map_or(0, |span| span.line);
+        match next(tokens, idx)? {
+            Token::Symbol(';') => {}
+            tok if *tok == close => break,
+            Token::Identifier(word) if word == "run" => {
+                let ticks = expect_number(tokens, idx)?;
+                let ticks = u32::try_from(ticks)
+                    .map_err(|_| ParseError::UnexpectedToken(Token::Number(ticks)))?;
+                steps.push(TestStep::Run(ticks));
             }
-            Token::Keyword(_) => {
-                return Err(ParseError::InvalidCommand);
+            Token::Identifier(word) if word == "assert" => steps.push(TestStep::Assert {
+                condition: parse_expr(tokens, idx)?,
+                line: step_line,
+            }),
+            Token::Identifier(word) if word == "opponent" => {
+                if opponent.is_some() {
+                    return Err(ParseError::SecondOpponent.into());
+                }
+                opponent = Some(parse_opponent(tokens, idx, syntax)?);
             }
-            _ => {
-                return Err(ParseError::UnexpectedToken(tokens[idx].clone()));
+            tok => return Err(ParseError::UnknownTestStep(tok.clone()).into()),
+        }
+    }
+    Ok(ScriptTest {
+        name,
+        line,
+        opponent,
+        steps,
+    })
+}
+
+/// Parse a test's opponent after `opponent`: `at <x> <y>`, then optionally
+/// `path { <x> <y> ... }` and `fire { <tick> ... }`.
+fn parse_opponent(
+    tokens: &[Token],
+    idx: &mut usize,
+    syntax: BlockSyntax,
+) -> Result<Opponent, Fault> {
+    if !is_word(tokens.get(*idx), "at") {
+        return Err(ParseError::UnexpectedToken(next(tokens, idx)?.clone()).into());
+    }
+    *idx += 1;
+    let start = (expect_number(tokens, idx)?, expect_number(tokens, idx)?);
+    let mut opponent = Opponent {
+        start,
+        path: Vec::new(),
+        fire: Vec::new(),
+    };
+    loop {
+        let token = tokens.get(*idx);
+        let path = is_word(token, "path");
+        if !path && !is_word(token, "fire") {
+            return Ok(opponent);
+        }
+        *idx += 1;
+        let Some(close) = open_block(tokens, idx, syntax) else {
+            return Err(ParseError::UnexpectedToken(next(tokens, idx)?.clone()).into());
+        };
+        while peek(tokens, *idx)? != &close {
+            if path {
+                let waypoint = (expect_number(tokens, idx)?, expect_number(tokens, idx)?);
+                opponent.path.push(waypoint);
+            } else {
+                let tick = expect_number(tokens, idx)?;
+                let tick = u64::try_from(tick)
+                    .map_err(|_| ParseError::UnexpectedToken(Token::Number(tick)))?;
+                opponent.fire.push(tick);
             }
         }
+        *idx += 1;
+    }
+}
+
+/// Whether `token` is `word`, as a keyword or an identifier.
+fn is_word(token: Option<&Token>, word: &str) -> bool {
+    matches!(token, Some(Token::Keyword(w) | Token::Identifier(w)) if w == word)
+}
+
+/// Parse the command starting at `tokens[*idx]`, advancing `idx` past it.
+/// The span of its first token, then those of any commands in its block, are
+/// pushed onto `starts`. The command is looked up by its name in the command
+/// table, whose parse function reads its arguments.
+fn parse_command(
+    tokens: &[Token],
+    spans: &[Span],
+    idx: &mut usize,
+    starts: &mut Vec<Span>,
+    syntax: BlockSyntax,
+    plugins: &Plugins,
+) -> Result<Command, Fault> {
+    starts.push(spans.get(*idx).copied().unwrap_or_default());
+    let spec = match commands::lookup(&tokens[(*idx).min(tokens.len())..]) {
+        Ok(spec) => spec,
+        Err(0) => {
+            return match next(tokens, idx)? {
+                Token::Keyword(_) => Err(ParseError::InvalidCommand.into()),
+                Token::Identifier(name) => match plugins.command(name) {
+                    // One expression per argument
+                    Some(command) => Ok(Command::Plugin {
+                        name: command.name.clone(),
+                        args: (0..command.args.len())
+                            .map(|_| parse_expr(tokens, idx))
+                            .collect::<Result<_, _>>()?,
+                    }),
+                    None => {
+                        Err(ParseError::UnexpectedToken(Token::Identifier(name.clone())).into())
+                    }
+                },
+                tok => Err(ParseError::UnexpectedToken(tok.clone()).into()),
+            };
+        }
+        Err(matched) => {
+            // The words of the name that matched are fine; the next one is not
+            *idx += matched;
+            return Err(ParseError::UnexpectedToken(next(tokens, idx)?.clone()).into());
+        }
+    };
+    *idx += spec.words();
+    (spec.parse)(&mut Cursor {
+        tokens,
+        spans,
+        idx,
+        starts,
+        syntax,
+        plugins,
+    })
+}
+
+/// The parser's place in a script, as the parse functions of the command
+/// table see it: just past the command's name.
+pub(crate) struct Cursor<'a> {
+    tokens: &'a [Token],
+    spans: &'a [Span],
+    idx: &'a mut usize,
+    starts: &'a mut Vec<Span>,
+    syntax: BlockSyntax,
+    plugins: &'a Plugins,
+}
+
+impl<'a> Cursor<'a> {
+    /// The next token, without consuming it.
+    pub(crate) fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(*self.idx)
+    }
+
+    /// Consume the next token, which must exist.
+    pub(crate) fn next(&mut self) -> Result<&'a Token, Fault> {
+        next(self.tokens, self.idx)
+    }
+
+    /// Skip the token `peek` returned.
+    pub(crate) fn advance(&mut self) {
+        *self.idx += 1;
+    }
+
+    pub(crate) fn number(&mut self) -> Result<i32, Fault> {
+        expect_number(self.tokens, self.idx)
+    }
+
+    pub(crate) fn expr(&mut self) -> Result<Expr, Fault> {
+        parse_expr(self.tokens, self.idx)
+    }
+
+    /// Open a block if one starts here, returning the token that closes it.
+    pub(crate) fn open_block(&mut self) -> Option<Token> {
+        open_block(self.tokens, self.idx, self.syntax)
+    }
+
+    /// The commands of an opened block up to the first of `closers`, and the
+    /// closer found.
+    pub(crate) fn block(&mut self, closers: &[Token]) -> Result<(Block, Token), Fault> {
+        parse_block(
+            self.tokens,
+            self.spans,
+            self.idx,
+            self.starts,
+            self.syntax,
+            self.plugins,
+            closers,
+        )
+    }
+
+    /// A single command, as a block.
+    pub(crate) fn single_command(&mut self) -> Result<Block, Fault> {
+        single_command(
+            self.tokens,
+            self.spans,
+            self.idx,
+            self.starts,
+            self.syntax,
+            self.plugins,
+        )
+    }
+
+    /// The body of a `loop`, `if` or `else`: a block, or a single command.
+    pub(crate) fn body(&mut self) -> Result<Block, Fault> {
+        parse_body(
+            self.tokens,
+            self.spans,
+            self.idx,
+            self.starts,
+            self.syntax,
+            self.plugins,
+        )
+    }
+}
+
+/// Parse an expression. Operators need no spaces around them, except that a
+/// `-` after a space and before a digit is the sign of a number:
+///
+/// ```text
+/// expr   := term (("+" | "-") term)*
+/// term   := unary (("*" | "/" | "%") unary)*
+/// unary  := "-" unary | atom
+/// atom   := number | register | builtin "(" expr ("," expr)* ")" | "(" expr ")"
+/// ```
+///
+/// The expression ends at the first token that cannot continue it, which is
+/// normally the start of the next command.
+/// Parse the body of a `loop`, `if` or `else`: a block, or a single command.
+fn parse_body(
+    tokens: &[Token],
+    spans: &[Span],
+    idx: &mut usize,
+    starts: &mut Vec<Span>,
+    syntax: BlockSyntax,
+    plugins: &Plugins,
+) -> Result<Block, Fault> {
+    match open_block(tokens, idx, syntax) {
+        Some(close) => Ok(parse_block(tokens, spans, idx, starts, syntax, plugins, &[close])?.0),
+        None => single_command(tokens, spans, idx, starts, syntax, plugins),
+    }
+}
+
+/// Parse a body that is a single command, as a block.
+fn single_command(
+    tokens: &[Token],
+    spans: &[Span],
+    idx: &mut usize,
+    starts: &mut Vec<Span>,
+    syntax: BlockSyntax,
+    plugins: &Plugins,
+) -> Result<Block, Fault> {
+    match parse_command(tokens, spans, idx, starts, syntax, plugins)? {
+        Command::Build(_) => Err(ParseError::MisplacedBuild.into()),
+        command => Ok(vec![command]),
+    }
+}
+
+/// Parse the commands of an opened block up to the first of `closers`,
+/// returning them and the closer, which is consumed.
+fn parse_block(
+    tokens: &[Token],
+    spans: &[Span],
+    idx: &mut usize,
+    starts: &mut Vec<Span>,
+    syntax: BlockSyntax,
+    plugins: &Plugins,
+    closers: &[Token],
+) -> Result<(Block, Token), Fault> {
+    let mut block = Vec::new();
+    loop {
+        while peek(tokens, *idx)? == &Token::Symbol(';') {
+            *idx += 1;
+        }
+        let token = peek(tokens, *idx)?;
+        if closers.contains(token) {
+            *idx += 1;
+            return Ok((block, token.clone()));
+        }
+        match parse_command(tokens, spans, idx, starts, syntax, plugins)? {
+            Command::Build(_) => return Err(ParseError::MisplacedBuild.into()),
+            command => block.push(command),
+        }
     }
+}
+
+/// If a block opens at `tokens[*idx]`, with `{` or, in `do ... end` syntax,
+/// `do`, consume the opener and return the token that closes the block.
+fn open_block(tokens: &[Token], idx: &mut usize, syntax: BlockSyntax) -> Option<Token> {
+    let close = match tokens.get(*idx)? {
+        Token::Symbol('{') => Token::Symbol('}'),
+        Token::Identifier(word) if word == "do" && syntax == BlockSyntax::DoEnd => {
+            Token::Identifier("end".to_string())
+        }
+        _ => return None,
+    };
+    *idx += 1;
+    Some(close)
+}
+
+/// Parse an expression: a sum, or a comparison of two sums.
+fn parse_expr(tokens: &[Token], idx: &mut usize) -> Result<Expr, Fault> {
+    let lhs = parse_sum(tokens, idx)?;
+    let Some(op) = binary_op(tokens.get(*idx), &["<", "<=", ">", ">=", "==", "!="]) else {
+        return Ok(lhs);
+    };
+    *idx += 1;
+    let rhs = parse_sum(tokens, idx)?;
+    Ok(Expr::Op {
+        op,
+        args: vec![lhs, rhs],
+    })
+}
+
+fn parse_sum(tokens: &[Token], idx: &mut usize) -> Result<Expr, Fault> {
+    let mut lhs = parse_term(tokens, idx)?;
+    while let Some(op) = binary_op(tokens.get(*idx), &["+", "-"]) {
+        *idx += 1;
+        let rhs = parse_term(tokens, idx)?;
+        lhs = Expr::Op {
+            op,
+            args: vec![lhs, rhs],
+        };
+    }
+    Ok(lhs)
+}
 
-    Ok(commands)
+fn parse_term(tokens: &[Token], idx: &mut usize) -> Result<Expr, Fault> {
+    let mut lhs = parse_unary(tokens, idx)?;
+    while let Some(op) = binary_op(tokens.get(*idx), &["*", "/", "%"]) {
+        *idx += 1;
+        let rhs = parse_unary(tokens, idx)?;
+        lhs = Expr::Op {
+            op,
+            args: vec![lhs, rhs],
+        };
+    }
+    Ok(lhs)
 }
 
-/// Helper function: returns the number of tokens consumed for a single command.
-/// Used to advance the index when parsing blocks.
-fn tokens_consumed_for_command(tokens: &[Token]) -> usize {
-    if tokens.is_empty() {
-        return 0;
+fn parse_unary(tokens: &[Token], idx: &mut usize) -> Result<Expr, Fault> {
+    if binary_op(tokens.get(*idx), &["-"]).is_some() {
+        *idx += 1;
+        let operand = parse_unary(tokens, idx)?;
+        return Ok(Expr::Op {
+            op: MathOp::Neg,
+            args: vec![operand],
+        });
     }
-    match &tokens[0] {
-        Token::Keyword(k) if k == "move" || k == "rotate" => 3,
-        Token::Keyword(k) if k == "scan" || k == "fire" => 1,
-        Token::Keyword(k) if k == "loop" => {
-            // Find matching '{' and '}'
-            let mut count = 1; // "loop"
-            if tokens.get(count) == Some(&Token::Symbol('{')) {
-                count += 1;
+    parse_atom(tokens, idx)
+}
+
+fn parse_atom(tokens: &[Token], idx: &mut usize) -> Result<Expr, Fault> {
+    match next(tokens, idx)? {
+        Token::Number(n) => Ok(Expr::Number(*n)),
+        Token::Symbol('(') => {
+            let expr = parse_expr(tokens, idx)?;
+            expect_symbol(tokens, idx, ')')?;
+            Ok(expr)
+        }
+        // `scan` is both a command and the register it writes
+        Token::Keyword(k) if k == "scan" => Ok(Expr::Register(k.clone())),
+        Token::Identifier(name) if tokens.get(*idx) == Some(&Token::Symbol('(')) => {
+            if let Some(field) = HistoryField::builtin(name) {
+                *idx += 1;
+                let index = parse_expr(tokens, idx)?;
+                if next(tokens, idx)? != &Token::Symbol(')') {
+                    return Err(ParseError::ArgumentCount {
+                        function: name.clone(),
+                        expected: 1,
+                    }
+                    .into());
+                }
+                return Ok(Expr::History {
+                    field,
+                    index: Box::new(index),
+                });
             }
-            let mut depth = 1;
-            while count < tokens.len() && depth > 0 {
-                match &tokens[count] {
-                    Token::Symbol('{') => depth += 1,
-                    Token::Symbol('}') => depth -= 1,
-                    _ => {}
+            let op =
+                MathOp::builtin(name).ok_or_else(|| ParseError::UnknownFunction(name.clone()))?;
+            *idx += 1;
+            let mut args = vec![parse_expr(tokens, idx)?];
+            while tokens.get(*idx) == Some(&Token::Symbol(',')) {
+                *idx += 1;
+                args.push(parse_expr(tokens, idx)?);
+            }
+            expect_symbol(tokens, idx, ')')?;
+            if args.len() != op.arity() {
+                return Err(ParseError::ArgumentCount {
+                    function: name.clone(),
+                    expected: op.arity(),
                 }
-                count += 1;
+                .into());
             }
-            count
+            Ok(Expr::Op { op, args })
         }
-        _ => 1,
+        Token::Identifier(name) if is_register_name(name) => Ok(Expr::Register(name.clone())),
+        tok => Err(ParseError::UnexpectedToken(tok.clone()).into()),
+    }
+}
+
+/// The binary operator at `token`, if it is one of `allowed`.
+fn binary_op(token: Option<&Token>, allowed: &[&str]) -> Option<MathOp> {
+    let Some(Token::Identifier(sym)) = token else {
+        return None;
+    };
+    if !allowed.contains(&sym.as_str()) {
+        return None;
+    }
+    match sym.as_str() {
+        "+" => Some(MathOp::Add),
+        "-" => Some(MathOp::Sub),
+        "*" => Some(MathOp::Mul),
+        "/" => Some(MathOp::Div),
+        "%" => Some(MathOp::Mod),
+        "<" => Some(MathOp::Lt),
+        "<=" => Some(MathOp::Le),
+        ">" => Some(MathOp::Gt),
+        ">=" => Some(MathOp::Ge),
+        "==" => Some(MathOp::Eq),
+        "!=" => Some(MathOp::Ne),
+        _ => None,
+    }
+}
+
+/// Register names start with a letter or underscore and contain only
+/// letters, digits and underscores.
+pub(crate) fn is_register_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn peek(tokens: &[Token], idx: usize) -> Result<&Token, Fault> {
+    tokens
+        .get(idx)
+        .ok_or(Fault::Parse(ParseError::UnexpectedEOF))
+}
+
+fn next<'a>(tokens: &'a [Token], idx: &mut usize) -> Result<&'a Token, Fault> {
+    let token = peek(tokens, *idx)?;
+    *idx += 1;
+    Ok(token)
+}
+
+fn expect_number(tokens: &[Token], idx: &mut usize) -> Result<i32, Fault> {
+    match next(tokens, idx)? {
+        Token::Number(n) => Ok(*n),
+        tok => Err(ParseError::UnexpectedToken(tok.clone()).into()),
+    }
+}
+
+fn expect_symbol(tokens: &[Token], idx: &mut usize, symbol: char) -> Result<(), Fault> {
+    match next(tokens, idx)? {
+        Token::Symbol(c) if *c == symbol => Ok(()),
+        tok => Err(ParseError::UnexpectedToken(tok.clone()).into()),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ast::Command;
-    use crate::tokenizer::tokenize_script;
+    use crate::ast::{Command, Emote, Section};
+    use crate::loadout::Loadout;
 
     #[test]
     fn test_parse_simple_commands() {
@@ -163,8 +675,7 @@ mod tests {
             scan
             fire
         "#;
-        let tokens = tokenize_script(script);
-        let ast = parse_tokens(&tokens).unwrap();
+        let ast = parse_script(script).unwrap();
         assert_eq!(
             ast,
             vec![
@@ -182,6 +693,157 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_say() {
+        let ast = parse_script("scan\nsay \"found you\"").unwrap();
+        assert_eq!(
+            ast[1],
+            Command::Say {
+                text: "found you".to_string()
+            }
+        );
+        let err = parse_script("say 5").unwrap_err();
+        assert_eq!(err.to_string(), "line 1, column 5: unexpected token `5`");
+        assert!(parse_script("say").is_err());
+
+        assert_eq!(
+            parse_script("emote angry").unwrap(),
+            vec![Command::Emote(Emote::Angry)]
+        );
+        let err = parse_script("emote smug").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 1, column 7: unknown emote `smug` (expected happy, sad, angry, surprised, confused or alert)"
+        );
+    }
+
+    #[test]
+    fn test_brace_free_bodies() {
+        let ast = parse_script("if scan > 0 fire").unwrap();
+        assert_eq!(
+            ast,
+            vec![Command::If {
+                condition: Expr::Op {
+                    op: MathOp::Gt,
+                    args: vec![Expr::Register("scan".to_string()), Expr::Number(0)],
+                },
+                block: vec![Command::Fire],
+                else_block: None,
+            }]
+        );
+        assert_eq!(
+            parse_script("if scan > 0 { fire }").unwrap(),
+            ast,
+            "a one-command block is the same as a bare command"
+        );
+        let ast = parse_script("loop scan").unwrap();
+        assert_eq!(
+            ast,
+            vec![Command::Loop {
+                block: vec![Command::Scan]
+            }]
+        );
+        let ast = parse_script("if health <= 3 { smoke; cloak } else fire\nscan").unwrap();
+        assert_eq!(ast.len(), 2);
+        let Command::If {
+            block, else_block, ..
+        } = &ast[0]
+        else {
+            panic!("expected an if");
+        };
+        assert_eq!(block, &vec![Command::Smoke, Command::Cloak]);
+        assert_eq!(else_block, &Some(vec![Command::Fire]));
+        // Comparisons do not chain
+        assert!(parse_script("if 1 < 2 < 3 fire").is_err());
+        assert!(parse_script("if scan > 0 build armor 3").is_err());
+    }
+
+    #[test]
+    fn test_inline_blocks() {
+        // Keywords inside a one-line block are commands, not register names
+        let ast = parse_script("let n = 1\nif n > 0 { fire }").unwrap();
+        let Command::If { block, .. } = &ast[1] else {
+            panic!("expected an if");
+        };
+        assert_eq!(block, &vec![Command::Fire]);
+        assert_eq!(
+            parse_script("loop { if scan > 0 { fire } else { scan }; rotate body 10 }").unwrap(),
+            parse_script(
+                "loop {\n  if scan > 0 {\n    fire\n  } else {\n    scan\n  }\n  rotate body 10\n}"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            parse_script("# keywords: any-case\nLoop { If Scan > 0 { Fire } }").unwrap(),
+            parse_script("loop { if scan > 0 { fire } }").unwrap()
+        );
+        assert!(parse_script("Fire").is_err());
+    }
+
+    #[test]
+    fn test_do_end_blocks_are_opt_in() {
+        let braces = parse_script(
+            "path { 60 60 340 60 }\nloop {\n  follow path\n  if scan > 0 {\n    fire\n  } else {\n    scan\n  }\n}",
+        )
+        .unwrap();
+        let do_end = "# syntax: do-end\n\
+                      path do 60 60 340 60 end\n\
+                      loop do\n\
+                      \x20 follow path\n\
+                      \x20 if scan > 0 do\n\
+                      \x20   fire\n\
+                      \x20 else\n\
+                      \x20   scan\n\
+                      \x20 end\n\
+                      end";
+        assert_eq!(BlockSyntax::of(do_end), BlockSyntax::DoEnd);
+        assert_eq!(parse_script(do_end).unwrap(), braces);
+        // A do block can also end before the else
+        assert_eq!(
+            parse_script("# syntax: do-end\nif scan > 0 do fire end else do scan end").unwrap(),
+            parse_script("if scan > 0 { fire } else { scan }").unwrap()
+        );
+        // Braces still work in a do/end file
+        assert_eq!(
+            parse_script("# syntax: do-end\nloop { scan }").unwrap(),
+            parse_script("loop { scan }").unwrap()
+        );
+
+        // Without the pragma, or with it after the first command, `do` is no keyword
+        let without = do_end.replace("# syntax: do-end\n", "");
+        assert_eq!(BlockSyntax::of(&without), BlockSyntax::Braces);
+        assert!(parse_script(&without).is_err());
+        assert_eq!(
+            BlockSyntax::of("scan\n# syntax: do-end\nloop do scan end"),
+            BlockSyntax::Braces
+        );
+        assert_eq!(
+            BlockSyntax::of("# A bot\n\n  # syntax: do-end\nscan"),
+            BlockSyntax::DoEnd
+        );
+    }
+
+    #[test]
+    fn test_semicolons_and_line_breaks_are_interchangeable() {
+        let multi_line =
+            parse_script("build armor 3\nlet a = 1 + 2\nloop {\n  scan\n  fire\n}\nrepair\nsmoke")
+                .unwrap();
+        let one_line =
+            parse_script("build armor 3; let a = 1 + 2; loop { scan; fire; }; repair; smoke")
+                .unwrap();
+        assert_eq!(one_line, multi_line);
+        // Separators are optional, repeatable, and blocks may open on the next line
+        let spread =
+            parse_script(";; build armor 3 let a = 1\n+ 2 loop\n{ ; scan fire }\n repair ; smoke;")
+                .unwrap();
+        assert_eq!(spread, multi_line);
+
+        let err = parse_script("move forward; fire").unwrap_err();
+        assert_eq!(err.to_string(), "line 1, column 13: unexpected token `;`");
+        let (_, spans) = parse_script_with_spans("scan;fire").unwrap();
+        assert_eq!(spans[1], Span { line: 1, column: 6 });
+    }
+
     #[test]
     fn test_parse_loop_block() {
         let script = r#"
@@ -191,8 +853,7 @@ mod tests {
                 fire
             }
         "#;
-        let tokens = tokenize_script(script);
-        let ast = parse_tokens(&tokens).unwrap();
+        let ast = parse_script(script).unwrap();
         assert_eq!(
             ast,
             vec![Command::Loop {
@@ -208,6 +869,256 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_goto() {
+        let ast = parse_script("loop {\n goto 50 300\n goto 350 300\n}").unwrap();
+        assert_eq!(
+            ast,
+            vec![Command::Loop {
+                block: vec![
+                    Command::GoTo { x: 50, y: 300 },
+                    Command::GoTo { x: 350, y: 300 },
+                ]
+            }]
+        );
+        assert!(parse_script("goto 50").is_err());
+    }
+
+    #[test]
+    fn test_parse_path_and_follow() {
+        let script = r#"
+            path {
+                50 50
+                350 50
+            }
+            loop {
+                follow path
+                scan
+            }
+        "#;
+        let ast = parse_script(script).unwrap();
+        assert_eq!(
+            ast,
+            vec![
+                Command::Path {
+                    waypoints: vec![(50, 50), (350, 50)]
+                },
+                Command::Loop {
+                    block: vec![Command::FollowPath, Command::Scan]
+                },
+            ]
+        );
+        // Waypoints come in pairs
+        assert!(parse_script("path { 50 50 350 }").is_err());
+        assert!(parse_script("follow me").is_err());
+    }
+
+    #[test]
+    fn test_parse_let_expression() {
+        let ast = parse_script("let bearing = atan2(dy, dx) + - offset * 2\nfire").unwrap();
+        let reg = |name: &str| Expr::Register(name.to_string());
+        assert_eq!(
+            ast,
+            vec![
+                Command::Let {
+                    name: "bearing".to_string(),
+                    expr: Expr::Op {
+                        op: MathOp::Add,
+                        args: vec![
+                            Expr::Op {
+                                op: MathOp::Atan2,
+                                args: vec![reg("dy"), reg("dx")],
+                            },
+                            Expr::Op {
+                                op: MathOp::Mul,
+                                args: vec![
+                                    Expr::Op {
+                                        op: MathOp::Neg,
+                                        args: vec![reg("offset")],
+                                    },
+                                    Expr::Number(2),
+                                ],
+                            },
+                        ],
+                    },
+                },
+                Command::Fire,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operators_need_no_spaces() {
+        assert_eq!(
+            parse_script("let d=x*2-y%3;if d>=0{fire}").unwrap(),
+            parse_script("let d = x * 2 - y % 3\nif d >= 0 {\n  fire\n}").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_expression_errors() {
+        let err = |script: &str| parse_script(script).unwrap_err().to_string();
+        assert_eq!(
+            err("let v = tan(1)"),
+            "line 1, column 9: unknown function `tan`"
+        );
+        assert_eq!(
+            err("let v = min(1)"),
+            "line 1, column 14: `min` takes 2 argument(s)"
+        );
+        assert_eq!(
+            err("scan\nlet v = (1 + 2"),
+            "line 2, column 14: unexpected end of script"
+        );
+        assert_eq!(err("let 5 = 1"), "line 1, column 5: unexpected token `5`");
+        assert_eq!(
+            err("let v = contact_x(1, 2)"),
+            "line 1, column 20: `contact_x` takes 1 argument(s)"
+        );
+    }
+
+    #[test]
+    fn test_parse_persist() {
+        let ast = parse_script("persist load seen\npersist store health").unwrap();
+        assert_eq!(
+            ast,
+            vec![
+                Command::PersistLoad {
+                    name: "seen".to_string()
+                },
+                Command::PersistStore {
+                    name: "health".to_string()
+                },
+            ]
+        );
+        assert!(parse_script("persist forget seen").is_err());
+        assert!(parse_script("persist store").is_err());
+    }
+
+    #[test]
+    fn test_read_only_register_is_compile_error() {
+        for (script, name) in [
+            ("let health = 99", "health"),
+            ("fire\n  let scan = 0", "scan"),
+            ("persist load energy", "energy"),
+            ("let opp_distance = 0", "opp_distance"),
+        ] {
+            match parse_script(script).unwrap_err() {
+                ScriptError::Compile {
+                    source: CompileError::ReadOnlyRegister(register),
+                    ..
+                } => assert_eq!(register, name),
+                err => panic!("expected a compile error, got {:?}", err),
+            }
+        }
+        assert_eq!(
+            parse_script("fire\n  let scan = 0")
+                .unwrap_err()
+                .to_string(),
+            "line 2, column 7: register `scan` is read-only"
+        );
+    }
+
+    #[test]
+    fn test_parse_history_access() {
+        let ast = parse_script("let dx = contact_x(0) - contact_x(i + 1)").unwrap();
+        let history = |index| Expr::History {
+            field: HistoryField::X,
+            index: Box::new(index),
+        };
+        let index = Expr::Op {
+            op: MathOp::Add,
+            args: vec![Expr::Register("i".to_string()), Expr::Number(1)],
+        };
+        assert_eq!(
+            ast,
+            vec![Command::Let {
+                name: "dx".to_string(),
+                expr: Expr::Op {
+                    op: MathOp::Sub,
+                    args: vec![history(Expr::Number(0)), history(index)],
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_aim_at_scanned() {
+        let ast = parse_script("scan\naim at scanned\nfire").unwrap();
+        assert_eq!(
+            ast,
+            vec![Command::Scan, Command::AimAtScanned, Command::Fire]
+        );
+        assert!(parse_script("aim at me").is_err());
+        let ast = parse_script("scan_all\nlet d = nearest_dist").unwrap();
+        assert_eq!(ast[0], Command::ScanAll);
+        assert!(parse_script("let nearest_bearing = 0").is_err());
+        let ast = parse_script("sweep -90 15").unwrap();
+        assert_eq!(ast, vec![Command::Sweep { arc: -90, step: 15 }]);
+        assert!(parse_script("sweep 90 0").is_err());
+        assert!(parse_script("sweep 90").is_err());
+        let ast = parse_script("fire lead\nfire").unwrap();
+        assert_eq!(ast, vec![Command::FireLead, Command::Fire]);
+        let ast = parse_script("fire ricochet\nfire laser").unwrap();
+        assert_eq!(ast, vec![Command::FireRicochet, Command::FireLaser]);
+        let ast = parse_script("selfdestruct\nsmoke\ncloak\nuncloak").unwrap();
+        assert_eq!(
+            ast,
+            vec![
+                Command::SelfDestruct,
+                Command::Smoke,
+                Command::Cloak,
+                Command::Uncloak
+            ]
+        );
+        let ast = parse_script("build armor 4 scanner 0\nscan").unwrap();
+        assert_eq!(
+            ast[0],
+            Command::Build(Loadout {
+                armor: 4,
+                scanner: 0,
+                ..Loadout::default()
+            })
+        );
+        assert_eq!(ast[1], Command::Scan);
+        let err = parse_script("scan\nbuild armor 3").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 13: `build` must come before every other command"
+        );
+        assert!(parse_script("loop {\n build speed 3\n}").is_err());
+        let err = parse_script("build armor 3 luck 9").unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("unknown stat `luck` (expected armor, speed, weapon or scanner)")
+        );
+        let ast = parse_script("repair\nrepair 20\nrepair ally 5").unwrap();
+        assert_eq!(
+            ast,
+            vec![
+                Command::Repair {
+                    ally: false,
+                    ticks: 1
+                },
+                Command::Repair {
+                    ally: false,
+                    ticks: 20
+                },
+                Command::Repair {
+                    ally: true,
+                    ticks: 5
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_error_message() {
+        let err = parse_script("rotate main 6").unwrap_err();
+        assert_eq!(err.to_string(), "line 1, column 8: unexpected token `main`");
+        assert_eq!(err.span(), Some(Span { line: 1, column: 8 }));
+    }
+
     #[test]
     fn test_parse_nested_loops() {
         let script = r#"
@@ -218,8 +1129,7 @@ mod tests {
                 fire
             }
         "#;
-        let tokens = tokenize_script(script);
-        let ast = parse_tokens(&tokens).unwrap();
+        let ast = parse_script(script).unwrap();
         assert_eq!(
             ast,
             vec![Command::Loop {
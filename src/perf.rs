@@ -0,0 +1,196 @@
+// Numbers for the window's performance HUD.
+// `CountingAllocator` wraps the system allocator and counts every allocation;
+// the binary installs it as the global allocator when built with the window, so
+// the HUD can show how much each frame allocates. It only counts once the
+// window has called `count_allocations`, so headless runs, `serve` and
+// tournaments pay one relaxed load per allocation rather than two shared
+// read-modify-writes. Without it the counts stay at zero. `FrameStats` averages
+// frame and tick times over the last second or so of frames, so the HUD's
+// numbers are readable rather than flickering.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicU64, Ordering};
+
+/// Frames the HUD averages over.
+pub const FRAME_WINDOW: usize = 60;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+// Signed, as memory allocated before counting started may be freed after
+static BYTES_IN_USE: AtomicIsize = AtomicIsize::new(0);
+
+/// Start counting allocations, for the rest of the process.
+pub fn count_allocations() {
+    COUNTING.store(true, Ordering::Relaxed);
+}
+
+/// The system allocator, counting allocations and the bytes in use once
+/// `count_allocations` has been called.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() && COUNTING.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            BYTES_IN_USE.fetch_add(layout.size() as isize, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        if COUNTING.load(Ordering::Relaxed) {
+            BYTES_IN_USE.fetch_sub(layout.size() as isize, Ordering::Relaxed);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { System.realloc(ptr, layout, new_size) };
+        // A failed reallocation leaves the old block as it was
+        if !new.is_null() && COUNTING.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            let grown = new_size as isize - layout.size() as isize;
+            BYTES_IN_USE.fetch_add(grown, Ordering::Relaxed);
+        }
+        new
+    }
+}
+
+/// What `CountingAllocator` has counted so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// Allocations and reallocations since the program started.
+    pub allocations: u64,
+    pub bytes_in_use: usize,
+}
+
+/// The allocation counts now; all zero unless `CountingAllocator` is the
+/// global allocator and counting has started.
+pub fn alloc_stats() -> AllocStats {
+    AllocStats {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes_in_use: BYTES_IN_USE.load(Ordering::Relaxed).max(0) as usize,
+    }
+}
+
+/// One frame as the HUD counts it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Frame {
+    seconds: f32,
+    /// Part of the frame spent stepping the match.
+    stepping: f32,
+    ticks: u32,
+    allocations: u64,
+}
+
+/// The last `FRAME_WINDOW` frames of the window.
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    frames: VecDeque<Frame>,
+}
+
+impl FrameStats {
+    /// Count a frame that took `seconds` and stepped the match `ticks` times
+    /// in `stepping` of them, allocating `allocations` times.
+    pub fn record(&mut self, seconds: f32, stepping: f32, ticks: u32, allocations: u64) {
+        if self.frames.len() == FRAME_WINDOW {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(Frame {
+            seconds,
+            stepping,
+            ticks,
+            allocations,
+        });
+    }
+
+    fn average(&self, value: impl Fn(&Frame) -> f64) -> f64 {
+        if self.frames.is_empty() {
+            return 0.0;
+        }
+        self.frames.iter().map(value).sum::<f64>() / self.frames.len() as f64
+    }
+
+    /// Average milliseconds per frame.
+    pub fn ms_per_frame(&self) -> f64 {
+        self.average(|f| f.seconds as f64 * 1000.0)
+    }
+
+    /// Milliseconds the slowest frame took.
+    pub fn worst_ms(&self) -> f64 {
+        self.frames
+            .iter()
+            .map(|f| f.seconds as f64 * 1000.0)
+            .fold(0.0, f64::max)
+    }
+
+    /// Average ticks stepped per frame.
+    pub fn ticks_per_frame(&self) -> f64 {
+        self.average(|f| f.ticks as f64)
+    }
+
+    /// Average milliseconds a tick took to step, over the ticks stepped.
+    pub fn ms_per_tick(&self) -> f64 {
+        let ticks: u32 = self.frames.iter().map(|f| f.ticks).sum();
+        let stepping: f64 = self.frames.iter().map(|f| f.stepping as f64).sum();
+        if ticks == 0 {
+            0.0
+        } else {
+            stepping * 1000.0 / ticks as f64
+        }
+    }
+
+    /// Average allocations per frame.
+    pub fn allocations_per_frame(&self) -> f64 {
+        self.average(|f| f.allocations as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_stats_average_recent_frames() {
+        let mut stats = FrameStats::default();
+        assert_eq!(stats.ms_per_frame(), 0.0);
+        assert_eq!(stats.ms_per_tick(), 0.0);
+        stats.record(0.010, 0.002, 1, 4);
+        stats.record(0.030, 0.006, 3, 0);
+        assert!((stats.ms_per_frame() - 20.0).abs() < 1e-3);
+        assert!((stats.worst_ms() - 30.0).abs() < 1e-3);
+        assert_eq!(stats.ticks_per_frame(), 2.0);
+        assert_eq!(stats.allocations_per_frame(), 2.0);
+        assert!((stats.ms_per_tick() - 2.0).abs() < 1e-3);
+
+        // The slow frame drops out once a window of frames has passed
+        for _ in 0..FRAME_WINDOW {
+            stats.record(0.016, 0.004, 4, 0);
+        }
+        assert!(stats.worst_ms() < 17.0);
+        assert_eq!(stats.ticks_per_frame(), 4.0);
+    }
+
+    #[test]
+    fn test_counting_allocator_counts() {
+        count_allocations();
+        let before = alloc_stats();
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        unsafe {
+            let ptr = CountingAllocator.alloc(layout);
+            assert!(!ptr.is_null());
+            // Too large to succeed: counted as neither an allocation nor bytes
+            let huge = isize::MAX as usize / 2;
+            assert!(CountingAllocator.realloc(ptr, layout, huge).is_null());
+            assert_eq!(alloc_stats().bytes_in_use - before.bytes_in_use, 64);
+            let ptr = CountingAllocator.realloc(ptr, layout, 128);
+            assert_eq!(alloc_stats().bytes_in_use - before.bytes_in_use, 128);
+            CountingAllocator.dealloc(ptr, Layout::from_size_align(128, 8).unwrap());
+        }
+        let after = alloc_stats();
+        assert_eq!(after.allocations - before.allocations, 2);
+        assert_eq!(after.bytes_in_use, before.bytes_in_use);
+    }
+}
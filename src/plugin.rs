@@ -0,0 +1,335 @@
+// Plugins: DSL commands and world behaviors added by other crates.
+// Plugins are registered in a `Plugins` registry, which is handed to the parser
+// (`parse_script_with`) and to the match (`Simulation::with_plugins`). Scripts
+// parsed with it may use the plugins' commands like built-in ones: the
+// command's name followed by one expression per argument. Each compiles to a
+// single `Instruction::Plugin`, which the robot runs by calling the plugin with
+// the argument values, tick after tick until the plugin says it is done. Once
+// per tick, after the robots have run, every plugin of the match also gets the
+// whole simulation, to move, damage or spawn things as its commands asked.
+//
+// The interface is versioned by `API_VERSION`. A plugin states the version it
+// was written for, and registering it fails if that is not the version of this
+// crate, rather than letting it misbehave.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::ast::Robot;
+use crate::commands;
+use crate::error::PluginError;
+use crate::parser::is_register_name;
+use crate::simulation::Simulation;
+
+/// Version of the plugin interface. Bumped whenever `Plugin`, `PluginCommand`
+/// or what the simulation does with them changes.
+pub const API_VERSION: u32 = 1;
+
+/// A command a plugin adds to the DSL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginCommand {
+    /// The word the command is written with; not a keyword of the DSL.
+    pub name: String,
+    /// Names of the arguments, each an expression.
+    pub args: Vec<String>,
+    pub summary: String,
+}
+
+/// Extends the DSL and the simulation. Everything but `name` and
+/// `api_version` has a default that does nothing.
+pub trait Plugin: Send + Sync {
+    /// Name of the plugin, for errors.
+    fn name(&self) -> &str;
+
+    /// The `API_VERSION` the plugin was written against.
+    fn api_version(&self) -> u32;
+
+    /// Commands the plugin adds to the DSL.
+    fn commands(&self) -> Vec<PluginCommand> {
+        Vec::new()
+    }
+
+    /// Run one tick of `command` for `robot`, with its arguments evaluated.
+    /// Returns true once the command is done; until then it runs again every
+    /// tick, like `goto`.
+    fn execute(&self, command: &str, robot: &mut Robot, args: &[i32]) -> bool {
+        let _ = (command, robot, args);
+        true
+    }
+
+    /// Advance the plugin's world behaviors by a tick. Runs after the robots'
+    /// instructions and before movement.
+    fn tick(&self, sim: &mut Simulation) {
+        let _ = sim;
+    }
+}
+
+#[derive(Clone)]
+struct Registered {
+    plugin: Arc<dyn Plugin>,
+    commands: Vec<PluginCommand>,
+}
+
+/// A set of registered plugins. Cloning one is cheap, and clones share the
+/// plugins registered so far but not those registered afterwards.
+#[derive(Clone, Default)]
+pub struct Plugins {
+    registered: Arc<Vec<Registered>>,
+}
+
+impl Plugins {
+    /// A registry without any plugins.
+    pub fn new() -> Self {
+        Plugins::default()
+    }
+
+    /// Register `plugin`, for the scripts parsed and the matches run with
+    /// this registry afterwards.
+    pub fn register(&mut self, plugin: impl Plugin + 'static) -> Result<(), PluginError> {
+        if plugin.api_version() != API_VERSION {
+            return Err(PluginError::Version {
+                plugin: plugin.name().to_string(),
+                expected: API_VERSION,
+                found: plugin.api_version(),
+            });
+        }
+        let commands = plugin.commands();
+        for (i, command) in commands.iter().enumerate() {
+            let taken = commands::is_keyword(&command.name)
+                || commands[..i].iter().any(|c| c.name == command.name)
+                || self.command(&command.name).is_some();
+            if taken || !is_register_name(&command.name) {
+                return Err(PluginError::CommandName {
+                    plugin: plugin.name().to_string(),
+                    command: command.name.clone(),
+                });
+            }
+        }
+        Arc::make_mut(&mut self.registered).push(Registered {
+            plugin: Arc::new(plugin),
+            commands,
+        });
+        Ok(())
+    }
+
+    /// The registered command called `name`.
+    pub fn command(&self, name: &str) -> Option<&PluginCommand> {
+        self.registered
+            .iter()
+            .flat_map(|r| &r.commands)
+            .find(|c| c.name == name)
+    }
+
+    /// The plugin that added the command called `name`.
+    pub(crate) fn provider(&self, name: &str) -> Option<&dyn Plugin> {
+        self.registered
+            .iter()
+            .find(|r| r.commands.iter().any(|c| c.name == name))
+            .map(|r| &*r.plugin)
+    }
+
+    /// Every registered plugin, in the order registered.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Plugin> {
+        self.registered.iter().map(|r| &*r.plugin)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registered.is_empty()
+    }
+}
+
+impl fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|p| p.name()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::translate_commands_to_instructions;
+    use crate::config::GameConfig;
+    use crate::parser::{parse_script, parse_script_with};
+    use crate::replay::Replay;
+    use crate::setup::BotScript;
+
+    /// `grapple <x> <y>` fires a hook at a point, which pulls the robot there
+    /// a few units per tick.
+    struct GrapplingHook;
+
+    impl Plugin for GrapplingHook {
+        fn name(&self) -> &str {
+            "grappling hook"
+        }
+
+        fn api_version(&self) -> u32 {
+            API_VERSION
+        }
+
+        fn commands(&self) -> Vec<PluginCommand> {
+            vec![PluginCommand {
+                name: "grapple".to_string(),
+                args: vec!["x".to_string(), "y".to_string()],
+                summary: "Pull the robot to a point.".to_string(),
+            }]
+        }
+
+        fn execute(&self, _: &str, robot: &mut Robot, args: &[i32]) -> bool {
+            robot.registers.set("hook_x", args[0]);
+            robot.registers.set("hook_y", args[1]);
+            true
+        }
+
+        fn tick(&self, sim: &mut Simulation) {
+            for robot in &mut sim.robots {
+                let (Some(x), Some(y)) =
+                    (robot.registers.get("hook_x"), robot.registers.get("hook_y"))
+                else {
+                    continue;
+                };
+                let (dx, dy) = (x as f32 - robot.position.0, y as f32 - robot.position.1);
+                let distance = dx.hypot(dy);
+                let pull = distance.min(5.0);
+                if distance > 0.0 {
+                    robot.position.0 += dx / distance * pull;
+                    robot.position.1 += dy / distance * pull;
+                }
+            }
+        }
+    }
+
+    struct Outdated;
+
+    impl Plugin for Outdated {
+        fn name(&self) -> &str {
+            "outdated"
+        }
+
+        fn api_version(&self) -> u32 {
+            0
+        }
+    }
+
+    struct Clashing(&'static str);
+
+    impl Plugin for Clashing {
+        fn name(&self) -> &str {
+            "clashing"
+        }
+
+        fn api_version(&self) -> u32 {
+            API_VERSION
+        }
+
+        fn commands(&self) -> Vec<PluginCommand> {
+            vec![PluginCommand {
+                name: self.0.to_string(),
+                args: Vec::new(),
+                summary: String::new(),
+            }]
+        }
+    }
+
+    #[test]
+    fn test_plugin_command_runs_in_a_match() {
+        let mut plugins = Plugins::new();
+        plugins.register(GrapplingHook).unwrap();
+        let source = "let d = 20\ngrapple 100 + d * 2 100\nscan";
+        let commands = parse_script_with(source, &plugins).unwrap();
+        let program = translate_commands_to_instructions(&commands);
+        assert!(
+            program
+                .iter()
+                .any(|i| i.to_string().starts_with("grapple "))
+        );
+
+        let robots = vec![
+            Robot::new(1, "climber", (100.0, 100.0), program),
+            Robot::new(2, "target", (300.0, 300.0), Vec::new()),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, Vec::new())
+            .with_plugins(plugins.clone());
+        for _ in 0..20 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].registers.get("hook_x"), Some(140));
+        assert_eq!(sim.robots[0].position, (140.0, 100.0));
+        assert_eq!(sim.robots[1].position, (300.0, 300.0));
+
+        // Wrong argument count
+        assert!(parse_script_with("grapple 100", &plugins).is_err());
+        assert_eq!(plugins.command("grapple").unwrap().args.len(), 2);
+        assert!(matches!(
+            plugins.register(Clashing("grapple")),
+            Err(PluginError::CommandName { .. })
+        ));
+    }
+
+    #[test]
+    fn test_registries_are_separate() {
+        let mut plugins = Plugins::new();
+        plugins.register(GrapplingHook).unwrap();
+        let before = plugins.clone();
+        plugins.register(Clashing("winch")).unwrap();
+        assert!(before.command("winch").is_none());
+        assert_eq!(
+            format!("{:?}", plugins),
+            r#"["grappling hook", "clashing"]"#
+        );
+
+        // Without the plugin, its commands are errors and do nothing
+        assert!(parse_script("grapple 1 2").is_err());
+        assert!(Plugins::new().command("grapple").is_none());
+        let program = translate_commands_to_instructions(
+            &parse_script_with("grapple 140 100", &plugins).unwrap(),
+        );
+        let robots = vec![Robot::new(1, "climber", (100.0, 100.0), program)];
+        let mut sim = Simulation::new(GameConfig::default(), robots, Vec::new());
+        sim.step();
+        assert_eq!(sim.robots[0].registers.get("hook_x"), None);
+    }
+
+    #[test]
+    fn test_replays_check_with_plugins() {
+        let mut plugins = Plugins::new();
+        plugins.register(GrapplingHook).unwrap();
+        let bot = |name: &str, script: &str| BotScript {
+            entrant: name.to_string(),
+            name: name.to_string(),
+            script: script.to_string(),
+        };
+        let bots = vec![
+            bot("climber", "grapple 200 100\nloop { scan; fire }"),
+            bot("target", "loop { scan; fire }"),
+        ];
+        let config = GameConfig::default();
+        let robots = crate::setup::compile_robots_with(&bots, &config, &plugins).unwrap();
+        let mut sim = Simulation::new(config, robots, Vec::new()).with_plugins(plugins.clone());
+        for _ in 0..50 {
+            sim.step();
+        }
+        let replay = Replay::record(&sim, bots, Vec::new());
+        assert!(replay.check().is_err());
+        assert_eq!(replay.check_with(&plugins).unwrap(), None);
+    }
+
+    #[test]
+    fn test_registration_is_checked() {
+        let mut plugins = Plugins::new();
+        assert_eq!(
+            plugins.register(Outdated),
+            Err(PluginError::Version {
+                plugin: "outdated".to_string(),
+                expected: API_VERSION,
+                found: 0,
+            })
+        );
+        for name in ["fire", "else", "two words", "9lives"] {
+            assert!(plugins.register(Clashing(name)).is_err(), "{}", name);
+        }
+        assert!(plugins.command("fire").is_none());
+        assert!(plugins.is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+// Practice drills for tuning a bot without an opponent that fights back.
+// A single robot shares the arena with target dummies, some standing still and
+// one patrolling, and a checkpoint to drive to. The drill records when the
+// robot first hit a dummy, how many hits it landed, when each dummy fell
+// and when the robot reached the checkpoint, and reports it once it is over.
+
+use serde::Serialize;
+
+use crate::ast::{Robot, translate_commands_to_instructions};
+use crate::modes::Zone;
+use crate::parser::parse_script;
+use crate::simulation::Simulation;
+
+/// Team shared by all dummies.
+pub const DUMMY_TEAM: usize = usize::MAX - 1;
+
+/// Dummies in a drill: where they start, as fractions of the arena size, and
+/// the script they run. Dummies never shoot.
+const DUMMIES: &[((f32, f32), &str)] = &[
+    ((0.8, 0.25), ""),
+    ((0.2, 0.85), ""),
+    (
+        (0.35, 0.62),
+        "loop {\n move forward 120\n rotate body 180\n}",
+    ),
+];
+
+/// Center of the checkpoint, as fractions of the arena size.
+const CHECKPOINT: (f32, f32) = (0.85, 0.85);
+
+/// One dummy and what happened to it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Target {
+    pub id: usize,
+    pub moving: bool,
+    /// Tick it was first hit.
+    pub first_hit: Option<u64>,
+    /// Tick it was destroyed.
+    pub destroyed: Option<u64>,
+}
+
+/// Progress of a practice drill.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Drill {
+    pub targets: Vec<Target>,
+    /// Hits the robot landed on dummies.
+    pub hits: u32,
+    /// Tick the robot first hit any dummy.
+    pub first_hit: Option<u64>,
+    /// Tick the robot first entered the checkpoint.
+    pub checkpoint_reached: Option<u64>,
+}
+
+impl Drill {
+    /// Record a hit on the dummy with id `target` at `tick`.
+    pub fn record_hit(&mut self, target: usize, tick: u64, destroyed: bool) {
+        let Some(dummy) = self.targets.iter_mut().find(|t| t.id == target) else {
+            return;
+        };
+        self.hits += 1;
+        self.first_hit.get_or_insert(tick);
+        dummy.first_hit.get_or_insert(tick);
+        if destroyed {
+            dummy.destroyed = Some(tick);
+        }
+    }
+
+    /// Whether every dummy is destroyed and the checkpoint reached.
+    pub fn is_complete(&self) -> bool {
+        self.checkpoint_reached.is_some() && self.targets.iter().all(|t| t.destroyed.is_some())
+    }
+
+    /// One line of progress for the HUD.
+    pub fn status(&self) -> String {
+        let down = self
+            .targets
+            .iter()
+            .filter(|t| t.destroyed.is_some())
+            .count();
+        let checkpoint = match self.checkpoint_reached {
+            Some(_) => "reached",
+            None => "not reached",
+        };
+        format!(
+            "Practice | {} of {} dummies down | {} hits | checkpoint {}",
+            down,
+            self.targets.len(),
+            self.hits,
+            checkpoint
+        )
+    }
+
+    /// The drill report, for a robot that fired `shots_fired` shots.
+    pub fn report(&self, shots_fired: u32) -> String {
+        let tick = |t: Option<u64>| t.map_or("never".to_string(), |t| format!("tick {}", t));
+        let accuracy = match shots_fired {
+            0 => "no shots fired".to_string(),
+            n => format!(
+                "{} of {} shots hit ({:.0}%)",
+                self.hits,
+                n,
+                self.hits as f32 * 100.0 / n as f32
+            ),
+        };
+        let mut lines = vec![
+            format!("First hit: {}", tick(self.first_hit)),
+            format!("Accuracy: {}", accuracy),
+            format!("Checkpoint reached: {}", tick(self.checkpoint_reached)),
+        ];
+        for target in &self.targets {
+            lines.push(format!(
+                "Dummy {} ({}): first hit {}, destroyed {}",
+                target.id,
+                if target.moving {
+                    "moving"
+                } else {
+                    "stationary"
+                },
+                tick(target.first_hit),
+                tick(target.destroyed)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// The checkpoint the robot has to reach.
+pub fn checkpoint(sim: &Simulation) -> Zone {
+    Zone {
+        center: (
+            sim.config.arena_width * CHECKPOINT.0,
+            sim.config.arena_height * CHECKPOINT.1,
+        ),
+        radius: sim.config.koth_zone_radius,
+    }
+}
+
+/// Add the dummies and start the drill.
+pub fn start_drill(sim: &mut Simulation) {
+    let mut targets = Vec::new();
+    for (id, ((fx, fy), script)) in (sim.next_robot_id()..).zip(DUMMIES) {
+        let commands = parse_script(script).expect("built-in dummy script");
+        let position = (sim.config.arena_width * fx, sim.config.arena_height * fy);
+        let mut dummy = Robot::new(
+            id,
+            "dummy",
+            position,
+            translate_commands_to_instructions(&commands),
+        );
+        dummy.team = DUMMY_TEAM;
+        dummy.health = sim.config.drone_health;
+        dummy.max_health = sim.config.drone_health;
+        dummy.rng = sim.rng_for(id);
+        sim.robots.push(dummy);
+        targets.push(Target {
+            id,
+            moving: !script.is_empty(),
+            first_hit: None,
+            destroyed: None,
+        });
+    }
+    sim.drill = Some(Drill {
+        targets,
+        hits: 0,
+        first_hit: None,
+        checkpoint_reached: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::modes::GameMode;
+
+    #[test]
+    fn test_drill_records_hits_and_checkpoint() {
+        let player = Robot::new(1, "player", (100.0, 50.0), vec![]);
+        let mut sim = Simulation::new(GameConfig::default(), vec![player], vec![])
+            .with_mode(GameMode::Practice);
+        sim.step();
+        let drill = sim.drill.as_ref().unwrap();
+        assert_eq!(drill.targets.len(), DUMMIES.len());
+        assert!(sim.robots[1..].iter().all(|r| r.team == DUMMY_TEAM));
+
+        for i in 1..sim.robots.len() {
+            sim.damage(i, sim.config.drone_health, Some(1));
+        }
+        assert!(!sim.is_over());
+        sim.robots[0].position = checkpoint(&sim).center;
+        sim.step();
+        let drill = sim.drill.as_ref().unwrap();
+        assert_eq!(drill.hits, DUMMIES.len() as u32);
+        assert_eq!(drill.first_hit, Some(1));
+        assert_eq!(drill.checkpoint_reached, Some(1));
+        assert!(sim.is_over());
+        assert!(drill.report(6).contains("3 of 6 shots hit (50%)"));
+    }
+}
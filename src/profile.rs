@@ -0,0 +1,353 @@
+// Script profiles: where a robot's ticks went during a match.
+// The VM runs one instruction per robot per tick, so the ticks counted per
+// instruction (see `Simulation::with_profiling`) are traced back to the script
+// commands that compiled to them. The report lists the commands by ticks spent,
+// then every loop with the ticks spent inside it, nested commands included.
+// A profiled match also times each of the simulation's systems, for finding
+// where the engine itself spends its time.
+
+use std::time::Duration;
+
+use crate::ast::{Command, Robot, translate_with_origins};
+use crate::error::Span;
+use crate::parser::parse_script_with_spans;
+use crate::setup::BotScript;
+use crate::systems::SYSTEMS;
+
+/// Ticks spent on one command of a script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandTicks {
+    pub span: Span,
+    /// The command as written in the script.
+    pub text: String,
+    /// Number of blocks (loops, ifs and elses) the command sits in.
+    pub depth: usize,
+    pub is_loop: bool,
+    /// Whether the command sits in a loop.
+    pub in_loop: bool,
+    /// Ticks spent on the command's own instructions; for a loop, on jumping
+    /// back to its start, and for an `if`, on testing its condition.
+    pub own: u64,
+    /// Ticks spent on the command, including the commands in its block.
+    pub total: u64,
+}
+
+/// Where one robot spent its ticks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// The robot, as `name #id`.
+    pub robot: String,
+    /// Every command of the script, in source order.
+    pub commands: Vec<CommandTicks>,
+    /// Ticks spent alive after the script ran to its end.
+    pub idle: u64,
+}
+
+impl Profile {
+    /// Profile of `robot`, which ran the script of `bot` in a profiled match.
+    /// `None` if the robot was not profiled or does not run that script.
+    pub fn new(bot: &BotScript, robot: &Robot) -> Option<Profile> {
+        let (commands, spans) = parse_script_with_spans(&bot.script).ok()?;
+        let (program, origins) = translate_with_origins(&commands);
+        if program != robot.instruction_queue || robot.ticks_spent.len() != program.len() + 1 {
+            return None;
+        }
+        let mut own = vec![0; spans.len()];
+        for (origin, ticks) in origins.iter().zip(&robot.ticks_spent) {
+            own[*origin] += ticks;
+        }
+
+        let lines: Vec<&str> = bot.script.lines().collect();
+        let mut profile = Profile {
+            robot: format!("{} #{}", robot.name, robot.id),
+            commands: Vec::with_capacity(spans.len()),
+            idle: robot.ticks_spent[program.len()],
+        };
+        profile.add_block(&commands, 0, false, &spans, &lines, &own);
+        Some(profile)
+    }
+
+    /// Add `block`, whose first command is `commands.len()`-th in source
+    /// order, returning the ticks spent in it.
+    fn add_block(
+        &mut self,
+        block: &[Command],
+        depth: usize,
+        in_loop: bool,
+        spans: &[Span],
+        lines: &[&str],
+        own: &[u64],
+    ) -> u64 {
+        let mut block_total = 0;
+        for command in block {
+            let index = self.commands.len();
+            let span = spans[index];
+            self.commands.push(CommandTicks {
+                span,
+                text: command_text(lines, span, spans.get(index + 1)),
+                depth,
+                is_loop: matches!(command, Command::Loop { .. }),
+                in_loop,
+                own: own[index],
+                total: own[index],
+            });
+            let nested = match command {
+                Command::Loop { block } => {
+                    self.add_block(block, depth + 1, true, spans, lines, own)
+                }
+                Command::If {
+                    block, else_block, ..
+                } => {
+                    self.add_block(block, depth + 1, in_loop, spans, lines, own)
+                        + else_block.as_deref().map_or(0, |block| {
+                            self.add_block(block, depth + 1, in_loop, spans, lines, own)
+                        })
+                }
+                _ => 0,
+            };
+            self.commands[index].total += nested;
+            block_total += self.commands[index].total;
+        }
+        block_total
+    }
+
+    /// Ticks the robot ran for while profiled.
+    pub fn ticks(&self) -> u64 {
+        self.idle
+            + self
+                .commands
+                .iter()
+                .filter(|c| c.depth == 0)
+                .map(|c| c.total)
+                .sum::<u64>()
+    }
+
+    /// The report printed by `--profile`: a flat profile of the commands the
+    /// robot spent ticks on, busiest first, then the ticks spent in each loop.
+    pub fn report(&self) -> String {
+        let ticks = self.ticks();
+        let share = |n: u64| {
+            if ticks == 0 {
+                0.0
+            } else {
+                n as f64 * 100.0 / ticks as f64
+            }
+        };
+        let row = |n: u64, line: String, text: &str| {
+            format!("  {:>8} {:>6.1}%  {:>5}  {}\n", n, share(n), line, text)
+        };
+        let header = format!(
+            "  {:>8} {:>7}  {:>5}  {}\n",
+            "ticks", "%", "line", "command"
+        );
+
+        let mut report = format!("{}: {} ticks\n", self.robot, ticks);
+        report += "Commands, busiest first:\n";
+        report += &header;
+        let mut flat: Vec<&CommandTicks> = self.commands.iter().filter(|c| c.own > 0).collect();
+        flat.sort_by_key(|c| std::cmp::Reverse(c.own));
+        for command in flat {
+            report += &row(command.own, command.span.line.to_string(), &command.text);
+        }
+        if self.idle > 0 {
+            report += &row(self.idle, "-".to_string(), "(script finished)");
+        }
+
+        if self.commands.iter().any(|c| c.is_loop) {
+            report += "Loops, with the commands inside them:\n";
+            report += &header;
+            for command in self.commands.iter().filter(|c| c.is_loop || c.in_loop) {
+                let text = format!("{}{}", "  ".repeat(command.depth), command.text);
+                report += &row(command.total, command.span.line.to_string(), &text);
+            }
+        }
+        report
+    }
+}
+
+/// Wall-clock time a profiled match spent in each system.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemTimes {
+    /// Ticks timed.
+    pub ticks: u64,
+    /// Time spent in each of `SYSTEMS`, in the order they run.
+    pub spent: Vec<Duration>,
+}
+
+impl Default for SystemTimes {
+    fn default() -> Self {
+        SystemTimes {
+            ticks: 0,
+            spent: vec![Duration::ZERO; SYSTEMS.len()],
+        }
+    }
+}
+
+impl SystemTimes {
+    /// The systems report `--profile` ends with: the time each system took,
+    /// slowest first, as a total, a share and an average per tick.
+    pub fn report(&self) -> String {
+        let total: Duration = self.spent.iter().sum();
+        let mut report = format!(
+            "Systems over {} ticks: {:.3} ms\n  {:>10} {:>7} {:>9}  {}\n",
+            self.ticks,
+            total.as_secs_f64() * 1000.0,
+            "ms",
+            "%",
+            "us/tick",
+            "system"
+        );
+        let mut rows: Vec<(&str, Duration)> = SYSTEMS
+            .iter()
+            .map(|(name, _)| *name)
+            .zip(self.spent.iter().copied())
+            .collect();
+        rows.sort_by_key(|(_, spent)| std::cmp::Reverse(*spent));
+        for (name, spent) in rows {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                spent.as_secs_f64() * 100.0 / total.as_secs_f64()
+            };
+            let per_tick = spent.as_secs_f64() * 1e6 / self.ticks.max(1) as f64;
+            report += &format!(
+                "  {:>10.3} {:>6.1}% {:>9.2}  {}\n",
+                spent.as_secs_f64() * 1000.0,
+                share,
+                per_tick,
+                name
+            );
+        }
+        report
+    }
+}
+
+/// The text of the command at `span`: the rest of its line, up to the next
+/// command if that starts on the same line, without comments, separators or
+/// closing braces.
+pub(crate) fn command_text(lines: &[&str], span: Span, next: Option<&Span>) -> String {
+    let line = lines.get(span.line.wrapping_sub(1)).copied().unwrap_or("");
+    let rest = line.chars().skip(span.column.saturating_sub(1));
+    let text: String = match next {
+        Some(next) if next.line == span.line => rest.take(next.column - span.column).collect(),
+        _ => rest.collect(),
+    };
+    let code = text.split('#').next().unwrap_or_default();
+    code.trim_end_matches(|c: char| c == '}' || c == ';' || c.is_whitespace())
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::simulation::Simulation;
+
+    fn profiled(script: &str, ticks: u64) -> Profile {
+        let bot = BotScript {
+            entrant: "test".to_string(),
+            name: "spinner".to_string(),
+            script: script.to_string(),
+        };
+        let commands = crate::parser::parse_script(script).unwrap();
+        let program = crate::ast::translate_commands_to_instructions(&commands);
+        let robots = vec![
+            Robot::new(1, "spinner", (100.0, 100.0), program),
+            Robot::new(2, "target", (300.0, 300.0), Vec::new()),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, Vec::new()).with_profiling();
+        for _ in 0..ticks {
+            sim.step();
+        }
+        Profile::new(&bot, &sim.robots[0]).unwrap()
+    }
+
+    #[test]
+    fn test_ticks_are_traced_to_commands() {
+        let profile = profiled(
+            "scan\nloop {\n  rotate body 10 # slowly\n  loop { fire }\n}",
+            200,
+        );
+        assert_eq!(profile.ticks(), 200);
+        assert_eq!(profile.idle, 0);
+        let texts: Vec<&str> = profile.commands.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["scan", "loop {", "rotate body 10", "loop {", "fire"]
+        );
+        let depths: Vec<usize> = profile.commands.iter().map(|c| c.depth).collect();
+        assert_eq!(depths, vec![0, 0, 1, 1, 2]);
+
+        let [scan, outer, rotate, inner, fire] = &profile.commands[..] else {
+            panic!("expected five commands");
+        };
+        assert_eq!(scan.own, 1);
+        assert_eq!(outer.total, 199);
+        assert_eq!(outer.total, outer.own + rotate.total + inner.total);
+        assert_eq!(inner.total, inner.own + fire.total);
+        // Loading the counter, then four instructions per degree; the inner
+        // loop never ends, and spends two of every three ticks on jumping back
+        assert_eq!(rotate.total, 41);
+        assert_eq!(fire.own, 52);
+        assert_eq!(inner.own, 105);
+        assert_eq!(inner.span, Span { line: 4, column: 3 });
+    }
+
+    #[test]
+    fn test_report() {
+        let profile = profiled("scan\nfire", 10);
+        assert_eq!(profile.idle, 8);
+        let report = profile.report();
+        assert!(report.starts_with("spinner #1: 10 ticks\nCommands, busiest first:\n"));
+        assert!(report.contains("         1   10.0%      1  scan\n"));
+        assert!(report.contains("         8   80.0%      -  (script finished)\n"));
+        assert!(!report.contains("Loops"));
+
+        let one_line = profiled("scan; fire", 10);
+        let texts: Vec<&str> = one_line.commands.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, vec!["scan", "fire"]);
+
+        // Commands in an if are counted once, and listed with loops only in one
+        let branching = profiled("if 1 > 0 { scan }\nloop { fire }", 20);
+        assert_eq!(branching.ticks(), 20);
+        assert_eq!(branching.commands[0].total, branching.commands[0].own + 1);
+        let report = branching.report();
+        let loops = &report[report.find("Loops").unwrap()..];
+        assert!(loops.contains("  fire\n") && !loops.contains("scan"));
+
+        let looping = profiled("loop {\n  scan\n}", 10).report();
+        assert!(looping.contains("        10  100.0%      1  loop {\n"));
+        assert!(looping.contains("  scan\n"));
+    }
+
+    #[test]
+    fn test_systems_are_timed() {
+        let robots = vec![Robot::new(1, "idle", (100.0, 100.0), Vec::new())];
+        let mut sim = Simulation::new(GameConfig::default(), robots, Vec::new());
+        sim.step();
+        assert_eq!(sim.system_times, None);
+
+        let mut sim = sim.with_profiling();
+        for _ in 0..5 {
+            sim.step();
+        }
+        let times = sim.system_times.as_ref().unwrap();
+        assert_eq!(times.ticks, 5);
+        assert!(times.spent.iter().any(|spent| !spent.is_zero()));
+        let report = times.report();
+        assert!(report.starts_with("Systems over 5 ticks: "));
+        assert_eq!(report.lines().count(), SYSTEMS.len() + 2);
+        assert!(report.contains("  sensor\n"));
+    }
+
+    #[test]
+    fn test_unprofiled_robot_has_no_profile() {
+        let bot = BotScript {
+            entrant: "test".to_string(),
+            name: "idle".to_string(),
+            script: "scan".to_string(),
+        };
+        let robot = Robot::new(1, "idle", (0.0, 0.0), Vec::new());
+        assert_eq!(Profile::new(&bot, &robot), None);
+    }
+}
@@ -0,0 +1,338 @@
+// Ray casting against arena obstacles.
+// Used by the scanner for line-of-sight checks and by projectiles for obstacle collisions.
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::MathMode;
+
+/// An axis-aligned rectangular obstacle, in logical arena units.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Hit points left, for an obstacle that shots and lasers can destroy;
+    /// `None` for one that stands forever.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<i32>,
+}
+
+impl Obstacle {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Obstacle {
+            x,
+            y,
+            width,
+            height,
+            health: None,
+        }
+    }
+
+    /// The same obstacle, destroyed once it has taken `health` damage.
+    pub fn with_health(mut self, health: i32) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Whether the obstacle has been destroyed.
+    pub fn is_destroyed(&self) -> bool {
+        self.health.is_some_and(|h| h <= 0)
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    /// Whether a point lies inside the obstacle (edges included).
+    pub fn contains(&self, point: (f32, f32)) -> bool {
+        point.0 >= self.x
+            && point.0 <= self.x + self.width
+            && point.1 >= self.y
+            && point.1 <= self.y + self.height
+    }
+
+    /// Whether a square of half-size `half` centered at `center` overlaps the obstacle.
+    pub fn overlaps_square(&self, center: (f32, f32), half: f32) -> bool {
+        center.0 + half > self.x
+            && center.0 - half < self.x + self.width
+            && center.1 + half > self.y
+            && center.1 - half < self.y + self.height
+    }
+
+    /// Distance from a point to the nearest point of the obstacle; 0 inside it.
+    pub fn distance_to(&self, point: (f32, f32)) -> f32 {
+        let dx = (self.x - point.0)
+            .max(point.0 - (self.x + self.width))
+            .max(0.0);
+        let dy = (self.y - point.1)
+            .max(point.1 - (self.y + self.height))
+            .max(0.0);
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /// Distance along a ray to the first point on the obstacle, if the ray hits it.
+    /// Uses the slab method; `dir` must be a unit vector. A ray starting inside hits at 0.
+    pub fn ray_hit(&self, origin: (f32, f32), dir: (f32, f32)) -> Option<f32> {
+        self.ray_hit_face(origin, dir).map(|hit| hit.distance)
+    }
+
+    /// Like `ray_hit`, but also reports the face the ray enters through.
+    /// A ray starting inside the obstacle enters through no face; its normal is (0, 0).
+    pub fn ray_hit_face(&self, origin: (f32, f32), dir: (f32, f32)) -> Option<RayHit> {
+        let mut t_min = 0.0_f32;
+        let mut t_max = f32::INFINITY;
+        let mut normal = (0.0, 0.0);
+
+        for (axis, o, d, lo, hi) in [
+            (0, origin.0, dir.0, self.x, self.x + self.width),
+            (1, origin.1, dir.1, self.y, self.y + self.height),
+        ] {
+            if d.abs() < f32::EPSILON {
+                // Parallel to this slab: must already be between its planes
+                if o < lo || o > hi {
+                    return None;
+                }
+            } else {
+                let t1 = (lo - o) / d;
+                let t2 = (hi - o) / d;
+                if t1.min(t2) > t_min {
+                    t_min = t1.min(t2);
+                    // The entry face points back against the ray
+                    let facing = -d.signum();
+                    normal = if axis == 0 {
+                        (facing, 0.0)
+                    } else {
+                        (0.0, facing)
+                    };
+                }
+                t_max = t_max.min(t1.max(t2));
+                if t_min > t_max {
+                    return None;
+                }
+            }
+        }
+        Some(RayHit {
+            distance: t_min,
+            normal,
+        })
+    }
+}
+
+/// Where a ray meets an obstacle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    /// Distance along the ray.
+    pub distance: f32,
+    /// Unit normal of the face that was hit, pointing out of the obstacle.
+    pub normal: (f32, f32),
+}
+
+/// Cast a ray from `origin` along `angle` (radians) and return the distance to the
+/// nearest obstacle within `max_dist`, if any.
+pub fn cast_ray(
+    origin: (f32, f32),
+    angle: f32,
+    max_dist: f32,
+    obstacles: &[Obstacle],
+    math: MathMode,
+) -> Option<f32> {
+    cast_ray_index(origin, angle, max_dist, obstacles, math).map(|(_, t)| t)
+}
+
+/// Like `cast_ray`, but also reports which of `obstacles` was hit, by index.
+pub fn cast_ray_index(
+    origin: (f32, f32),
+    angle: f32,
+    max_dist: f32,
+    obstacles: &[Obstacle],
+    math: MathMode,
+) -> Option<(usize, f32)> {
+    let (sin, cos) = math.sin_cos(angle);
+    nearest_hit(origin, (cos, sin), max_dist, obstacles)
+}
+
+/// Index of and distance along a unit direction to the nearest obstacle within
+/// `max_dist`.
+fn nearest_hit(
+    origin: (f32, f32),
+    dir: (f32, f32),
+    max_dist: f32,
+    obstacles: &[Obstacle],
+) -> Option<(usize, f32)> {
+    obstacles
+        .iter()
+        .enumerate()
+        .filter_map(|(k, o)| o.ray_hit(origin, dir).map(|t| (k, t)))
+        .filter(|(_, t)| *t <= max_dist)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Like `cast_ray`, but also reports the face of the nearest obstacle that was hit.
+pub fn cast_ray_hit(
+    origin: (f32, f32),
+    angle: f32,
+    max_dist: f32,
+    obstacles: &[Obstacle],
+    math: MathMode,
+) -> Option<RayHit> {
+    let (sin, cos) = math.sin_cos(angle);
+    let dir = (cos, sin);
+    obstacles
+        .iter()
+        .filter_map(|o| o.ray_hit_face(origin, dir))
+        .filter(|hit| hit.distance <= max_dist)
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+/// Reflect a heading (degrees) off a surface with the given unit normal.
+pub fn reflect_heading(heading: f32, normal: (f32, f32), math: MathMode) -> f32 {
+    let (dy, dx) = math.sin_cos(heading.to_radians());
+    let dot = dx * normal.0 + dy * normal.1;
+    let (rx, ry) = (dx - 2.0 * dot * normal.0, dy - 2.0 * dot * normal.1);
+    math.atan2(ry, rx).to_degrees().rem_euclid(360.0)
+}
+
+/// Whether the straight segment between two points passes through a circle.
+pub fn segment_hits_circle(
+    from: (f32, f32),
+    to: (f32, f32),
+    center: (f32, f32),
+    radius: f32,
+) -> bool {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let len_sq = dx * dx + dy * dy;
+    // Closest point of the segment to the center
+    let t = if len_sq < f32::EPSILON {
+        0.0
+    } else {
+        (((center.0 - from.0) * dx + (center.1 - from.1) * dy) / len_sq).clamp(0.0, 1.0)
+    };
+    let (px, py) = (from.0 + t * dx - center.0, from.1 + t * dy - center.1);
+    px * px + py * py <= radius * radius
+}
+
+/// Whether the straight segment between two points is free of obstacles.
+pub fn line_of_sight(from: (f32, f32), to: (f32, f32), obstacles: &[Obstacle]) -> bool {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let dist = (dx * dx + dy * dy).sqrt();
+    if dist < f32::EPSILON {
+        return true;
+    }
+    // Normalizing the segment avoids trigonometry, so this is exact everywhere
+    nearest_hit(from, (dx / dist, dy / dist), dist, obstacles).is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_ray_hits_obstacle_in_front() {
+        let wall = Obstacle::new(10.0, -5.0, 2.0, 10.0);
+        let hit = cast_ray(
+            (0.0, 0.0),
+            0.0,
+            100.0,
+            std::slice::from_ref(&wall),
+            MathMode::Native,
+        );
+        assert!((hit.unwrap() - 10.0).abs() < 1e-4);
+        // Pointing away misses
+        assert_eq!(
+            cast_ray((0.0, 0.0), PI, 100.0, &[wall], MathMode::Native),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ray_respects_max_distance_and_nearest() {
+        let near = Obstacle::new(5.0, -1.0, 1.0, 2.0);
+        let far = Obstacle::new(20.0, -1.0, 1.0, 2.0);
+        let obstacles = [far, near];
+        let hit = cast_ray((0.0, 0.0), 0.0, 100.0, &obstacles, MathMode::Native).unwrap();
+        assert!((hit - 5.0).abs() < 1e-4);
+        let (index, _) =
+            cast_ray_index((0.0, 0.0), 0.0, 100.0, &obstacles, MathMode::Native).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(
+            cast_ray((0.0, 0.0), 0.0, 4.0, &obstacles, MathMode::Native),
+            None
+        );
+    }
+
+    #[test]
+    fn test_distance_to_obstacle() {
+        let wall = Obstacle::new(10.0, 10.0, 10.0, 10.0);
+        assert_eq!(wall.distance_to((15.0, 15.0)), 0.0);
+        assert_eq!(wall.distance_to((5.0, 15.0)), 5.0);
+        assert_eq!(wall.distance_to((23.0, 24.0)), 5.0);
+    }
+
+    #[test]
+    fn test_ray_hit_reports_face_normal() {
+        let wall = Obstacle::new(10.0, -5.0, 2.0, 10.0);
+        let hit = cast_ray_hit(
+            (0.0, 0.0),
+            0.0,
+            100.0,
+            std::slice::from_ref(&wall),
+            MathMode::Native,
+        )
+        .unwrap();
+        assert_eq!(hit.normal, (-1.0, 0.0));
+        // From below, the ray enters through the top face (y grows downward)
+        let hit = cast_ray_hit((11.0, -20.0), PI / 2.0, 100.0, &[wall], MathMode::Native).unwrap();
+        assert!((hit.distance - 15.0).abs() < 1e-4);
+        assert_eq!(hit.normal, (0.0, -1.0));
+    }
+
+    #[test]
+    fn test_reflect_heading() {
+        for math in [MathMode::Native, MathMode::Portable] {
+            assert!((reflect_heading(30.0, (-1.0, 0.0), math) - 150.0).abs() < 1e-3);
+            assert!((reflect_heading(30.0, (0.0, -1.0), math) - 330.0).abs() < 1e-3);
+            assert!((reflect_heading(0.0, (-1.0, 0.0), math) - 180.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_segment_hits_circle() {
+        assert!(segment_hits_circle(
+            (0.0, 0.0),
+            (100.0, 0.0),
+            (50.0, 5.0),
+            10.0
+        ));
+        assert!(!segment_hits_circle(
+            (0.0, 0.0),
+            (100.0, 0.0),
+            (50.0, 15.0),
+            10.0
+        ));
+        // Beyond the end of the segment
+        assert!(!segment_hits_circle(
+            (0.0, 0.0),
+            (100.0, 0.0),
+            (120.0, 0.0),
+            10.0
+        ));
+        // Starting inside the circle
+        assert!(segment_hits_circle(
+            (0.0, 0.0),
+            (100.0, 0.0),
+            (0.0, 0.0),
+            1.0
+        ));
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let wall = [Obstacle::new(45.0, 0.0, 10.0, 100.0)];
+        assert!(!line_of_sight((10.0, 50.0), (90.0, 50.0), &wall));
+        assert!(line_of_sight((10.0, 150.0), (90.0, 150.0), &wall));
+        // Target in front of the wall is visible
+        assert!(line_of_sight((10.0, 50.0), (40.0, 50.0), &wall));
+    }
+}
@@ -0,0 +1,387 @@
+//! Match recording to animated GIF.
+//!
+//! Frames are re-rendered offline from the simulation state with a small software
+//! rasterizer, so recording works the same with the window, the terminal renderer,
+//! or no display at all.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::ecs::{BeamKind, Sprite};
+use crate::simulation::Simulation;
+
+/// Output image size in pixels (half the logical arena size).
+const FRAME_WIDTH: u16 = 200;
+const FRAME_HEIGHT: u16 = 200;
+
+/// Capture one frame every this many simulation ticks.
+const TICKS_PER_FRAME: u64 = 10;
+
+/// Delay between GIF frames, in hundredths of a second.
+const FRAME_DELAY: u16 = 4;
+
+/// Stop recording after this many frames so endless matches still produce a file.
+const MAX_FRAMES: usize = 600;
+
+/// Global palette: black, light gray, red, blue, green, white, gray, yellow.
+const PALETTE: [u8; 24] = [
+    0, 0, 0, 200, 200, 200, 230, 41, 55, 0, 121, 241, 0, 228, 48, 255, 255, 255, 130, 130, 130,
+    253, 249, 0,
+];
+const BACKGROUND: u8 = 0;
+const BORDER: u8 = 1;
+const ROBOT_COLORS: [u8; 2] = [2, 3];
+const DEFAULT_COLOR: u8 = 4;
+const HEADING_COLOR: u8 = 5;
+const OBSTACLE_COLOR: u8 = 6;
+const PROJECTILE_COLOR: u8 = 7;
+const WRECK_COLOR: u8 = 1;
+const HAZARD_COLOR: u8 = 2;
+const MOVER_COLOR: u8 = 5;
+
+/// Writes the simulation as an animated GIF while a match runs.
+pub struct Recorder<W: Write = BufWriter<File>> {
+    encoder: Option<Encoder<W>>,
+    frames: usize,
+}
+
+impl Recorder {
+    /// Create a recorder writing to the GIF file at `path`.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Recorder::new(file)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> io::Result<Self> {
+        let mut encoder =
+            Encoder::new(writer, FRAME_WIDTH, FRAME_HEIGHT, &PALETTE).map_err(to_io_error)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(to_io_error)?;
+        Ok(Recorder {
+            encoder: Some(encoder),
+            frames: 0,
+        })
+    }
+
+    /// Whether the recording has been finalized.
+    pub fn is_finished(&self) -> bool {
+        self.encoder.is_none()
+    }
+
+    /// Record the current simulation state if a frame is due.
+    /// Finalizes the file once the match is over or the frame limit is reached.
+    pub fn capture(&mut self, sim: &Simulation) -> io::Result<()> {
+        let Some(encoder) = self.encoder.as_mut() else {
+            return Ok(());
+        };
+
+        if sim.tick.is_multiple_of(TICKS_PER_FRAME) || sim.is_over() {
+            let mut frame =
+                Frame::from_indexed_pixels(FRAME_WIDTH, FRAME_HEIGHT, rasterize(sim), None);
+            frame.delay = FRAME_DELAY;
+            encoder.write_frame(&frame).map_err(to_io_error)?;
+            self.frames += 1;
+        }
+
+        if sim.is_over() || self.frames >= MAX_FRAMES {
+            self.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Write the GIF trailer and flush the output.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.into_inner()?.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn to_io_error(e: gif::EncodingError) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Draw the arena border, obstacles, robots and entities into an indexed-color pixel buffer.
+fn rasterize(sim: &Simulation) -> Vec<u8> {
+    let (w, h) = (FRAME_WIDTH as usize, FRAME_HEIGHT as usize);
+    let mut pixels = vec![BACKGROUND; w * h];
+
+    for x in 0..w {
+        pixels[x] = BORDER;
+        pixels[(h - 1) * w + x] = BORDER;
+    }
+    for y in 0..h {
+        pixels[y * w] = BORDER;
+        pixels[y * w + w - 1] = BORDER;
+    }
+
+    let config = &sim.config;
+    let scale_x = w as f32 / config.arena_width;
+    let scale_y = h as f32 / config.arena_height;
+    let mut plot = |x: f32, y: f32, color: u8| {
+        if x >= 0.0 && y >= 0.0 && (x as usize) < w && (y as usize) < h {
+            pixels[y as usize * w + x as usize] = color;
+        }
+    };
+
+    // Platforms are dotted and the ramps up to them striped, under everything
+    for (areas, ramp) in [
+        (&sim.elevation.platforms, false),
+        (&sim.elevation.ramps, true),
+    ] {
+        for area in areas {
+            let mut y = (area.y * scale_y).floor();
+            while y < (area.y + area.height) * scale_y {
+                let mut x = (area.x * scale_x).floor();
+                while x < (area.x + area.width) * scale_x {
+                    let (px, py) = (x as i32, y as i32);
+                    let shaded = if ramp {
+                        (px + py) % 4 == 0
+                    } else {
+                        px % 2 == 0 && py % 2 == 0
+                    };
+                    if shaded {
+                        plot(x, y, OBSTACLE_COLOR);
+                    }
+                    x += 1.0;
+                }
+                y += 1.0;
+            }
+        }
+    }
+
+    for obstacle in &sim.obstacles {
+        // Destructible obstacles are checkered, to tell them from solid ones
+        let destructible = obstacle.health.is_some();
+        let mut y = obstacle.y * scale_y;
+        while y < (obstacle.y + obstacle.height) * scale_y {
+            let mut x = obstacle.x * scale_x;
+            while x < (obstacle.x + obstacle.width) * scale_x {
+                let color = if destructible && (x as i32 + y as i32) % 2 == 1 {
+                    WRECK_COLOR
+                } else {
+                    OBSTACLE_COLOR
+                };
+                plot(x, y, color);
+                x += 1.0;
+            }
+            y += 1.0;
+        }
+    }
+
+    // Doors and barriers, in white to tell them from what stands still
+    for piece in sim.world.moving_boxes() {
+        let mut y = piece.y * scale_y;
+        while y < (piece.y + piece.height) * scale_y {
+            let mut x = piece.x * scale_x;
+            while x < (piece.x + piece.width) * scale_x {
+                plot(x, y, MOVER_COLOR);
+                x += 1.0;
+            }
+            y += 1.0;
+        }
+    }
+
+    // Hazard telegraphs: a sparse red dither over the area about to be struck
+    for strike in &sim.strikes {
+        for y in 0..h {
+            for x in 0..w {
+                let at = ((x as f32 + 0.5) / scale_x, (y as f32 + 0.5) / scale_y);
+                if (x + y) % 3 == 0 && strike.area.contains(at, config) {
+                    plot(x as f32, y as f32, HAZARD_COLOR);
+                }
+            }
+        }
+    }
+
+    // Grappling hook cables, under the robots at their ends
+    for (i, robot) in sim.robots.iter().enumerate() {
+        let Some(end) = sim.hook_anchor(i) else {
+            continue;
+        };
+        let (dx, dy) = config.displacement(robot.position, end);
+        let length = (dx * dx + dy * dy).sqrt();
+        for step in 0..=length as usize {
+            let t = step as f32 / length.max(1.0);
+            plot(
+                (robot.position.0 + t * dx) * scale_x,
+                (robot.position.1 + t * dy) * scale_y,
+                DEFAULT_COLOR,
+            );
+        }
+    }
+
+    for (i, robot) in sim.robots.iter().enumerate() {
+        if robot.health <= 0 {
+            continue;
+        }
+        let color = ROBOT_COLORS.get(i).copied().unwrap_or(DEFAULT_COLOR);
+        let (cx, cy) = (robot.position.0 * scale_x, robot.position.1 * scale_y);
+        let (half_w, half_h) = (
+            config.robot_width * scale_x / 2.0,
+            config.robot_height * scale_y / 2.0,
+        );
+
+        // Axis-aligned body; rotation is shown by the heading line below.
+        // Cloaked robots are dithered to look translucent.
+        let mut y = cy - half_h;
+        while y < cy + half_h {
+            let mut x = cx - half_w;
+            while x < cx + half_w {
+                if !robot.cloaked || (x as i32 + y as i32) % 2 == 0 {
+                    plot(x, y, color);
+                }
+                x += 1.0;
+            }
+            y += 1.0;
+        }
+
+        let reach = half_w.max(half_h) * 1.5;
+        let heading = robot.heading.to_radians();
+        for step in 0..=reach as usize {
+            let d = step as f32;
+            plot(
+                cx + d * heading.cos(),
+                cy + d * heading.sin(),
+                HEADING_COLOR,
+            );
+        }
+    }
+
+    for (sprite, transform) in sim.world.renderables() {
+        let (px, py) = (
+            transform.position.0 * scale_x,
+            transform.position.1 * scale_y,
+        );
+        match sprite {
+            Sprite::Projectile => {
+                plot(px, py, PROJECTILE_COLOR);
+                plot(px + 1.0, py, PROJECTILE_COLOR);
+                plot(px, py + 1.0, PROJECTILE_COLOR);
+                plot(px + 1.0, py + 1.0, PROJECTILE_COLOR);
+            }
+            Sprite::Emp => {
+                // A hollow square, told apart from the filled one of a plain shot
+                for (dx, dy) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+                    plot(px + dx, py + dy, PROJECTILE_COLOR);
+                }
+            }
+            Sprite::Ricochet => {
+                // A small cross, told apart from the square of a plain shot
+                for (dx, dy) in [(0.0, 0.0), (-1.0, 0.0), (1.0, 0.0), (0.0, -1.0), (0.0, 1.0)] {
+                    plot(px + dx, py + dy, PROJECTILE_COLOR);
+                }
+            }
+            Sprite::Wreck | Sprite::SalvageWreck => {
+                let (half_w, half_h) = (
+                    config.robot_width * scale_x / 2.0,
+                    config.robot_height * scale_y / 2.0,
+                );
+                let mut y = py - half_h;
+                while y < py + half_h {
+                    let mut x = px - half_w;
+                    while x < px + half_w {
+                        plot(x, y, WRECK_COLOR);
+                        x += 1.0;
+                    }
+                    y += 1.0;
+                }
+                // Salvage still inside shows as a bright dot
+                if sprite == Sprite::SalvageWreck {
+                    plot(px, py, PROJECTILE_COLOR);
+                }
+            }
+            Sprite::Supply => {
+                // A small diamond
+                for (dx, dy) in [(0.0, -2.0), (1.0, -1.0), (2.0, 0.0), (1.0, 1.0)] {
+                    plot(px + dx, py + dy, PROJECTILE_COLOR);
+                    plot(px - dx, py - dy, PROJECTILE_COLOR);
+                }
+            }
+        }
+    }
+
+    // Smoke is dithered: every other pixel, so what is inside stays visible
+    for (center, radius) in sim.world.smoke_clouds() {
+        let (cx, cy) = (center.0 * scale_x, center.1 * scale_y);
+        let (rx, ry) = (radius * scale_x, radius * scale_y);
+        let mut y = (cy - ry).floor();
+        while y <= cy + ry {
+            let mut x = (cx - rx).floor();
+            while x <= cx + rx {
+                let (nx, ny) = ((x - cx) / rx, (y - cy) / ry);
+                if nx * nx + ny * ny <= 1.0 && (x as i32 + y as i32) % 2 == 0 {
+                    plot(x, y, BORDER);
+                }
+                x += 1.0;
+            }
+            y += 1.0;
+        }
+    }
+
+    for (transform, beam, _) in sim.world.visible_beams() {
+        let angle = transform.heading.to_radians();
+        for step in 0..=beam.length as usize {
+            let d = step as f32;
+            plot(
+                (transform.position.0 + d * angle.cos()) * scale_x,
+                (transform.position.1 + d * angle.sin()) * scale_y,
+                match beam.kind {
+                    BeamKind::Laser => PROJECTILE_COLOR,
+                    BeamKind::Repair => DEFAULT_COLOR,
+                    BeamKind::Intercept => PROJECTILE_COLOR,
+                },
+            );
+        }
+    }
+
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Robot;
+    use crate::config::GameConfig;
+    use crate::raycast::Obstacle;
+
+    fn robot_at(id: usize, x: f32, y: f32) -> Robot {
+        Robot::new(id, "test", (x, y), vec![])
+    }
+
+    #[test]
+    fn test_rasterize_draws_border_and_robots() {
+        let robots = vec![robot_at(1, 100.0, 100.0), robot_at(2, 300.0, 300.0)];
+        let obstacles = vec![Obstacle::new(0.0, 380.0, 20.0, 20.0)];
+        let pixels = rasterize(&Simulation::new(GameConfig::default(), robots, obstacles));
+        let w = FRAME_WIDTH as usize;
+        assert_eq!(pixels[0], BORDER);
+        // Robot 1 sits at (50, 50) in frame space; sample just behind its heading line
+        assert_eq!(pixels[50 * w + 48], ROBOT_COLORS[0]);
+        assert_eq!(pixels[150 * w + 148], ROBOT_COLORS[1]);
+        assert_eq!(pixels[50 * w + 52], HEADING_COLOR);
+        assert_eq!(pixels[195 * w + 5], OBSTACLE_COLOR);
+    }
+
+    #[test]
+    fn test_recorder_writes_gif_and_finishes_when_match_over() {
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            vec![robot_at(1, 100.0, 100.0)],
+            vec![],
+        );
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        assert!(sim.is_over());
+        recorder.capture(&sim).unwrap();
+        assert!(recorder.is_finished());
+        assert_eq!(recorder.frames, 1);
+        sim.step();
+        recorder.capture(&sim).unwrap();
+        assert_eq!(recorder.frames, 1);
+    }
+}
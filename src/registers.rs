@@ -0,0 +1,256 @@
+// Register names and storage for the robot VM.
+// Scripts name their registers, but the VM only sees small integer ids: names
+// are interned once, when a program is compiled, and a robot keeps its
+// registers in a vector indexed by id. The names are kept for listings, the
+// debug overlay and tests. Interned names are never forgotten, so the sandbox
+// caps how many scripts may add (`MAX_REGISTER_NAMES`), and reading a register
+// by name only looks the name up.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, PoisonError, RwLock};
+
+/// A register, by its interned id. A name gets the same id in every program
+/// compiled by the process, so commands injected from the console share the
+/// registers of the program they interrupt.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reg(u32);
+
+/// Declare the registers the simulation writes itself, with fixed ids, so the
+/// systems can set them without looking up a name.
+macro_rules! builtin_registers {
+    ($($constant:ident = $name:literal,)*) => {
+        #[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+        enum Builtin {
+            $($constant,)*
+        }
+
+        /// Names of the built-in registers, in id order.
+        const BUILTIN_NAMES: &[&str] = &[$($name,)*];
+
+        impl Reg {
+            $(pub const $constant: Reg = Reg(Builtin::$constant as u32);)*
+        }
+    };
+}
+
+builtin_registers! {
+    // Never set, so a jump on it is always taken
+    ALWAYS = "always",
+    X = "x",
+    Y = "y",
+    HEADING = "heading",
+    HEALTH = "health",
+    ENERGY = "energy",
+    ENEMY_COUNT = "enemy_count",
+    NEAREST_WALL_DIST = "nearest_wall_dist",
+    IN_ZONE = "in_zone",
+    TICK = "tick",
+    PUSHED = "pushed",
+    DETECTED = "detected",
+    CLOAKED = "cloaked",
+    POINT_DEFENSE = "point_defense",
+    HOOKED = "hooked",
+    LAYER = "layer",
+    HAZARD_WARNING = "hazard_warning",
+    HAZARD_BEARING = "hazard_bearing",
+    SCAN = "scan",
+    SCAN_BEARING = "scan_bearing",
+    SCAN_DEBRIS = "scan_debris",
+    SCAN_SALVAGE = "scan_salvage",
+    SCAN_LAYER = "scan_layer",
+    SCAN_COUNT = "scan_count",
+    ENEMIES_ALIVE = "enemies_alive",
+    NEAREST_DIST = "nearest_dist",
+    NEAREST_BEARING = "nearest_bearing",
+    TARGET_VX = "target_vx",
+    TARGET_VY = "target_vy",
+    WAYPOINT = "waypoint",
+}
+
+/// Most register names scripts may intern over the life of the process. This
+/// bounds what a long-running server keeps, and how large a robot's registers
+/// can grow.
+pub const MAX_REGISTER_NAMES: usize = 16_384;
+
+/// Every register name seen so far, both ways round. Names are never
+/// forgotten, so they can be handed out as `&'static str`.
+struct Interner {
+    ids: HashMap<&'static str, Reg>,
+    names: Vec<&'static str>,
+}
+
+static INTERNER: LazyLock<RwLock<Interner>> = LazyLock::new(|| {
+    let names = BUILTIN_NAMES.to_vec();
+    let ids = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (*name, Reg(i as u32)))
+        .collect();
+    RwLock::new(Interner { ids, names })
+});
+
+impl Reg {
+    /// The register called `name`, interning the name if it is new.
+    pub fn named(name: &str) -> Reg {
+        if let Some(reg) = Reg::lookup(name) {
+            return reg;
+        }
+        let mut interner = INTERNER.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(reg) = interner.ids.get(name) {
+            return *reg;
+        }
+        let name: &'static str = Box::leak(name.into());
+        let reg = Reg(interner.names.len() as u32);
+        interner.names.push(name);
+        interner.ids.insert(name, reg);
+        reg
+    }
+
+    /// The register called `name`, if any program has used that name.
+    pub fn lookup(name: &str) -> Option<Reg> {
+        let interner = INTERNER.read().unwrap_or_else(PoisonError::into_inner);
+        interner.ids.get(name).copied()
+    }
+
+    /// How many more names fit under `MAX_REGISTER_NAMES`.
+    pub fn room() -> usize {
+        let interner = INTERNER.read().unwrap_or_else(PoisonError::into_inner);
+        MAX_REGISTER_NAMES.saturating_sub(interner.names.len())
+    }
+
+    /// The name the register was interned under.
+    pub fn name(self) -> &'static str {
+        let interner = INTERNER.read().unwrap_or_else(PoisonError::into_inner);
+        interner.names[self.0 as usize]
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl From<&str> for Reg {
+    fn from(name: &str) -> Reg {
+        Reg::named(name)
+    }
+}
+
+impl std::fmt::Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::fmt::Debug for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Reg({:?})", self.name())
+    }
+}
+
+/// A register to read: a `Reg`, or a name, which is looked up rather than
+/// interned, so reading a name no program uses finds nothing.
+pub trait RegKey {
+    fn reg(self) -> Option<Reg>;
+}
+
+impl RegKey for Reg {
+    fn reg(self) -> Option<Reg> {
+        Some(self)
+    }
+}
+
+impl RegKey for &str {
+    fn reg(self) -> Option<Reg> {
+        Reg::lookup(self)
+    }
+}
+
+/// A robot's registers, indexed by id. A register that was never set has no
+/// value, which scripts read as 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Registers {
+    values: Vec<Option<i32>>,
+}
+
+impl Registers {
+    pub fn get(&self, reg: impl RegKey) -> Option<i32> {
+        self.values.get(reg.reg()?.index()).copied().flatten()
+    }
+
+    pub fn get_mut(&mut self, reg: impl RegKey) -> Option<&mut i32> {
+        self.values
+            .get_mut(reg.reg()?.index())
+            .and_then(Option::as_mut)
+    }
+
+    pub fn contains(&self, reg: impl RegKey) -> bool {
+        self.get(reg).is_some()
+    }
+
+    /// Set a register. The storage only grows the first time a register with
+    /// a higher id is set, so a running program does not allocate.
+    pub fn set(&mut self, reg: impl Into<Reg>, value: i32) {
+        let index = reg.into().index();
+        if index >= self.values.len() {
+            self.values.resize(index + 1, None);
+        }
+        self.values[index] = Some(value);
+    }
+
+    /// The registers that are set, in id order.
+    pub fn iter(&self) -> impl Iterator<Item = (Reg, i32)> + '_ {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(i, value)| value.map(|v| (Reg(i as u32), v)))
+    }
+}
+
+/// The value of register `name`; panics if it is not set.
+impl std::ops::Index<&str> for Registers {
+    type Output = i32;
+
+    fn index(&self, name: &str) -> &i32 {
+        Reg::lookup(name)
+            .and_then(|reg| self.values.get(reg.index()))
+            .and_then(Option::as_ref)
+            .unwrap_or_else(|| panic!("register {} is not set", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{RESULT_REGISTERS, SENSOR_REGISTERS};
+
+    #[test]
+    fn test_names_intern_to_stable_ids() {
+        for name in SENSOR_REGISTERS.iter().chain(RESULT_REGISTERS) {
+            assert_eq!(Reg::named(name).name(), *name);
+        }
+        assert_eq!(Reg::named("scan"), Reg::SCAN);
+        assert_eq!(Reg::WAYPOINT.to_string(), "waypoint");
+        let fresh = Reg::named("registers_test_fresh");
+        assert_eq!(Reg::named("registers_test_fresh"), fresh);
+        assert_eq!(fresh.name(), "registers_test_fresh");
+        assert_eq!(Reg::lookup("registers_test_never_used"), None);
+        assert!(Reg::room() > 0 && Reg::room() < MAX_REGISTER_NAMES);
+    }
+
+    #[test]
+    fn test_unset_registers_have_no_value() {
+        let mut registers = Registers::default();
+        assert_eq!(registers.get(Reg::TICK), None);
+        registers.set("registers_test_a", 3);
+        registers.set(Reg::X, 7);
+        *registers.get_mut(Reg::X).unwrap() -= 1;
+        assert_eq!(registers["x"], 6);
+        assert!(!registers.contains(Reg::Y));
+        // Reading a name no program uses does not intern it
+        assert_eq!(registers.get("registers_test_only_read"), None);
+        assert!(!registers.contains("registers_test_only_read"));
+        assert_eq!(Reg::lookup("registers_test_only_read"), None);
+        let set: Vec<_> = registers.iter().map(|(reg, v)| (reg.name(), v)).collect();
+        assert_eq!(set, vec![("x", 6), ("registers_test_a", 3)]);
+    }
+}
@@ -0,0 +1,528 @@
+// Replays: everything needed to play a match again, plus the events it produced.
+// Matches are deterministic, so re-simulating a replay must reproduce its event
+// stream exactly; `--verify-replay` checks that code changes keep it that way.
+//
+// A replay is either one JSON document (`Replay::write`) or a stream of JSON
+// lines written while the match runs (`ReplayWriter`): a header with the
+// starting state, one line per event, a tick checkpoint every
+// `CHECKPOINT_INTERVAL` ticks and an end line once the match is over. The
+// stream is flushed at every checkpoint, so a match that crashes or is
+// interrupted still leaves a replay up to its last checkpoint.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::elevation::Elevation;
+use crate::error::SimError;
+use crate::events::Event;
+use crate::hazards::Hazard;
+use crate::inject::Scheduled;
+use crate::modes::GameMode;
+use crate::movers::Mover;
+use crate::plugin::Plugins;
+use crate::raycast::Obstacle;
+use crate::setup::{BotScript, compile_robots_with};
+use crate::simulation::Simulation;
+
+/// A recorded match: its initial state and the events it produced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    /// Command-line name of the game mode.
+    pub mode: String,
+    /// Random seed of the match; null in replays from before matches had one,
+    /// which played as seed 0.
+    pub seed: Option<u64>,
+    pub config: GameConfig,
+    pub obstacles: Vec<Obstacle>,
+    /// The arena's doors and barriers; absent when it had none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub movers: Vec<Mover>,
+    /// The arena's platforms and ramps; absent when it was flat.
+    #[serde(default, skip_serializing_if = "Elevation::is_flat")]
+    pub elevation: Elevation,
+    /// The arena's hazards; absent when it had none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hazards: Vec<Hazard>,
+    /// Synthetic events made to happen during the match; absent without any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub injections: Vec<Scheduled>,
+    /// The robots' scripts, in spawn order.
+    pub bots: Vec<BotScript>,
+    /// Ticks the match ran for.
+    pub ticks: u64,
+    pub events: Vec<Event>,
+    /// Whether the match was cut short: a streamed replay without its end line,
+    /// kept up to its last checkpoint.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+}
+
+/// Ticks between checkpoints of a streamed replay.
+pub const CHECKPOINT_INTERVAL: u64 = 50;
+/// Events a streamed match keeps in memory for the event log once written.
+const RETAINED_EVENTS: usize = 64;
+
+/// Starting state of a match, the first line of a streamed replay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    mode: String,
+    seed: Option<u64>,
+    config: GameConfig,
+    obstacles: Vec<Obstacle>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    movers: Vec<Mover>,
+    #[serde(default, skip_serializing_if = "Elevation::is_flat")]
+    elevation: Elevation,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    hazards: Vec<Hazard>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    injections: Vec<Scheduled>,
+    bots: Vec<BotScript>,
+}
+
+/// One line of a streamed replay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Line {
+    Replay(Box<Header>),
+    Event(Event),
+    /// Every event up to this tick has been written.
+    Tick(u64),
+    End {
+        ticks: u64,
+        events: usize,
+    },
+}
+
+/// Writes a replay to disk while the match is played, so memory stays bounded
+/// however long it runs.
+pub struct ReplayWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+    /// Events written so far.
+    written: usize,
+    /// Events at the front of the simulation's log that are written already.
+    retained: usize,
+}
+
+impl ReplayWriter {
+    /// Start a streamed replay at `path` for `sim`, which must not have
+    /// started yet, with the `bots` and `obstacles` it was set up with.
+    pub fn create(
+        path: &Path,
+        sim: &Simulation,
+        bots: Vec<BotScript>,
+        obstacles: Vec<Obstacle>,
+    ) -> Result<Self, SimError> {
+        let error = |source| SimError::WriteResult {
+            path: path.to_path_buf(),
+            source,
+        };
+        let mut writer = ReplayWriter {
+            path: path.to_path_buf(),
+            out: BufWriter::new(File::create(path).map_err(error)?),
+            written: 0,
+            retained: 0,
+        };
+        let header = Header {
+            mode: sim.mode.name().to_string(),
+            seed: Some(sim.seed),
+            config: sim.config.clone(),
+            obstacles,
+            movers: sim.movers.clone(),
+            elevation: sim.elevation.clone(),
+            hazards: sim.hazards.clone(),
+            injections: sim.injections.clone(),
+            bots,
+        };
+        writer
+            .line(&Line::Replay(Box::new(header)))
+            .map_err(error)?;
+        writer.out.flush().map_err(error)?;
+        Ok(writer)
+    }
+
+    /// Write the events `sim` logged since the last call, with a checkpoint if
+    /// one is due. Written events are dropped from `sim`, except the last few
+    /// that the event log shows.
+    pub fn capture(&mut self, sim: &mut Simulation) -> io::Result<()> {
+        for event in &sim.events[self.retained..] {
+            self.line(&Line::Event(event.clone()))?;
+        }
+        self.written += sim.events.len() - self.retained;
+        if sim.tick.is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.line(&Line::Tick(sim.tick))?;
+            self.out.flush()?;
+        }
+        let excess = sim.events.len().saturating_sub(RETAINED_EVENTS);
+        sim.events.drain(..excess);
+        self.retained = sim.events.len();
+        Ok(())
+    }
+
+    /// Write the last events and the end line of a finished match.
+    pub fn finish(mut self, sim: &mut Simulation) -> Result<(), SimError> {
+        let mut end = || {
+            self.capture(sim)?;
+            let end = Line::End {
+                ticks: sim.tick,
+                events: self.written,
+            };
+            self.line(&end)?;
+            self.out.flush()
+        };
+        end().map_err(|source| SimError::WriteResult {
+            path: self.path.clone(),
+            source,
+        })
+    }
+
+    fn line(&mut self, line: &Line) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, line)?;
+        self.out.write_all(b"\n")
+    }
+}
+
+/// The first difference between a replay and a fresh run of it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    /// The event at `index` differs; `None` if that run has no event there.
+    Event {
+        index: usize,
+        recorded: Option<Event>,
+        replayed: Option<Event>,
+    },
+    /// The events match, but the match ended after a different number of ticks.
+    Ticks { recorded: u64, replayed: u64 },
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |event: &Option<Event>| match event {
+            Some(event) => event.to_string(),
+            None => "(no event)".to_string(),
+        };
+        match self {
+            Divergence::Event {
+                index,
+                recorded,
+                replayed,
+            } => write!(
+                f,
+                "event {} differs\n  recorded: {}\n  replayed: {}",
+                index,
+                describe(recorded),
+                describe(replayed)
+            ),
+            Divergence::Ticks { recorded, replayed } => write!(
+                f,
+                "match ended after {} ticks, but the replay recorded {}",
+                replayed, recorded
+            ),
+        }
+    }
+}
+
+impl Replay {
+    /// Record a finished match that started with `bots`, `obstacles` and the
+    /// config, mode, movers, elevation, hazards and injections of `sim`.
+    pub fn record(sim: &Simulation, bots: Vec<BotScript>, obstacles: Vec<Obstacle>) -> Self {
+        Replay {
+            mode: sim.mode.name().to_string(),
+            seed: Some(sim.seed),
+            config: sim.config.clone(),
+            obstacles,
+            movers: sim.movers.clone(),
+            elevation: sim.elevation.clone(),
+            hazards: sim.hazards.clone(),
+            injections: sim.injections.clone(),
+            bots,
+            ticks: sim.tick,
+            events: sim.events.clone(),
+            partial: false,
+        }
+    }
+
+    /// Load a replay written by `write` or streamed by `ReplayWriter`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let first = text.lines().next().unwrap_or_default();
+        let replay = match serde_json::from_str::<Line>(first) {
+            Ok(Line::Replay(header)) => Replay::from_stream(*header, text.lines().skip(1)),
+            _ => serde_json::from_str(&text).map_err(|e| e.to_string()),
+        };
+        replay.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Rebuild a streamed replay from the lines after its header. Without an
+    /// end line, the replay stops at the last checkpoint; a torn last line is
+    /// what an interrupted write leaves, so it is skipped.
+    fn from_stream<'a>(
+        header: Header,
+        lines: impl Iterator<Item = &'a str>,
+    ) -> Result<Replay, String> {
+        let mut replay = Replay {
+            mode: header.mode,
+            seed: header.seed,
+            config: header.config,
+            obstacles: header.obstacles,
+            movers: header.movers,
+            elevation: header.elevation,
+            hazards: header.hazards,
+            injections: header.injections,
+            bots: header.bots,
+            ticks: 0,
+            events: Vec::new(),
+            partial: true,
+        };
+        // Events covered by the last checkpoint
+        let mut checkpointed = 0;
+        let mut lines = lines.enumerate().peekable();
+        while let Some((index, text)) = lines.next() {
+            let line = match serde_json::from_str::<Line>(text) {
+                Ok(line) => line,
+                Err(_) if lines.peek().is_none() => break,
+                Err(e) => return Err(format!("line {}: {}", index + 2, e)),
+            };
+            match line {
+                Line::Event(event) => replay.events.push(event),
+                Line::Tick(tick) => {
+                    replay.ticks = tick;
+                    checkpointed = replay.events.len();
+                }
+                Line::End { ticks, events } => {
+                    if events != replay.events.len() {
+                        return Err(format!(
+                            "the replay ends after {} events, but {} were written",
+                            events,
+                            replay.events.len()
+                        ));
+                    }
+                    replay.ticks = ticks;
+                    replay.partial = false;
+                    return Ok(replay);
+                }
+                Line::Replay(_) => return Err(format!("line {}: a second header", index + 2)),
+            }
+        }
+        replay.events.truncate(checkpointed);
+        Ok(replay)
+    }
+
+    /// Write the replay as JSON.
+    pub fn write(&self, path: &Path) -> Result<(), SimError> {
+        let json = serde_json::to_string(self).map_err(io::Error::other);
+        json.and_then(|json| fs::write(path, json + "\n"))
+            .map_err(|source| SimError::WriteResult {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    /// The match as it was before the first tick.
+    pub fn start(&self) -> Result<Simulation, SimError> {
+        self.start_with(&Plugins::default())
+    }
+
+    /// Like `start`, for a match that used `plugins`.
+    pub fn start_with(&self, plugins: &Plugins) -> Result<Simulation, SimError> {
+        let mode = GameMode::from_name(&self.mode)
+            .ok_or_else(|| SimError::Replay(format!("unknown game mode: {}", self.mode)))?;
+        let robots = compile_robots_with(&self.bots, &self.config, plugins)
+            .map_err(SimError::ScriptsRejected)?;
+        let mut sim = Simulation::new(self.config.clone(), robots, self.obstacles.clone())
+            .with_plugins(plugins.clone())
+            .with_mode(mode)
+            .with_movers(self.movers.clone())
+            .with_elevation(self.elevation.clone())
+            .with_hazards(self.hazards.clone())
+            .with_seed(self.seed.unwrap_or(0));
+        sim.injections = self.injections.clone();
+        Ok(sim)
+    }
+
+    /// Play the match again and compare it with the recording. Returns the
+    /// first divergence, or `None` if the runs are identical.
+    pub fn check(&self) -> Result<Option<Divergence>, SimError> {
+        self.check_with(&Plugins::default())
+    }
+
+    /// Like `check`, for a match that used `plugins`.
+    pub fn check_with(&self, plugins: &Plugins) -> Result<Option<Divergence>, SimError> {
+        let mut sim = self.start_with(plugins)?;
+        while sim.tick < self.ticks && !sim.is_over() {
+            sim.step();
+            // Stop at the first event that differs, rather than running on
+            if let Some(divergence) = self.diverges(&sim.events) {
+                return Ok(Some(divergence));
+            }
+        }
+        if let Some(divergence) = self.diverges(&sim.events) {
+            return Ok(Some(divergence));
+        }
+        if sim.events.len() < self.events.len() {
+            let index = sim.events.len();
+            return Ok(Some(Divergence::Event {
+                index,
+                recorded: Some(self.events[index].clone()),
+                replayed: None,
+            }));
+        }
+        if sim.tick != self.ticks {
+            return Ok(Some(Divergence::Ticks {
+                recorded: self.ticks,
+                replayed: sim.tick,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// The first of `replayed` that does not match the recording.
+    fn diverges(&self, replayed: &[Event]) -> Option<Divergence> {
+        replayed.iter().enumerate().find_map(|(index, event)| {
+            let recorded = self.events.get(index);
+            (recorded != Some(event)).then(|| Divergence::Event {
+                index,
+                recorded: recorded.cloned(),
+                replayed: Some(event.clone()),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventKind;
+    use crate::setup::{compile_robots, default_obstacles};
+
+    fn bot(name: &str, script: &str) -> BotScript {
+        BotScript {
+            entrant: name.to_string(),
+            name: name.to_string(),
+            script: script.to_string(),
+        }
+    }
+
+    fn recorded_match() -> Replay {
+        let bots = vec![
+            bot("gunner", "loop {\n rotate turret 5\n scan\n fire\n}"),
+            bot("bomber", "move forward 30\nselfdestruct\nloop {\n scan\n}"),
+        ];
+        let config = GameConfig::default();
+        let mut sim = Simulation::new(
+            config.clone(),
+            compile_robots(&bots, &config).unwrap(),
+            default_obstacles(),
+        );
+        while sim.tick < 400 && !sim.is_over() {
+            sim.step();
+        }
+        Replay::record(&sim, bots, default_obstacles())
+    }
+
+    #[test]
+    fn test_replay_reproduces_match() {
+        let replay = recorded_match();
+        assert!(!replay.events.is_empty());
+        let json = serde_json::to_string(&replay).unwrap();
+        let loaded: Replay = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, replay);
+        assert_eq!(loaded.check().unwrap(), None);
+    }
+
+    fn streamed_match(path: &Path) -> Replay {
+        let recorded = recorded_match();
+        let mut sim = recorded.start().unwrap();
+        let mut writer = ReplayWriter::create(
+            path,
+            &sim,
+            recorded.bots.clone(),
+            recorded.obstacles.clone(),
+        )
+        .unwrap();
+        while sim.tick < 400 && !sim.is_over() {
+            sim.step();
+            writer.capture(&mut sim).unwrap();
+            assert!(sim.events.len() <= RETAINED_EVENTS);
+        }
+        writer.finish(&mut sim).unwrap();
+        recorded
+    }
+
+    #[test]
+    fn test_streamed_replay_matches_recording() {
+        let path = std::env::temp_dir().join(format!(
+            "robot-battle-streamed-{}.jsonl",
+            std::process::id()
+        ));
+        let recorded = streamed_match(&path);
+        let loaded = Replay::load(&path).unwrap();
+        assert_eq!(loaded, recorded);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_interrupted_stream_loads_up_to_checkpoint() {
+        let path = std::env::temp_dir().join(format!(
+            "robot-battle-interrupted-{}.jsonl",
+            std::process::id()
+        ));
+        let recorded = streamed_match(&path);
+        // Cut the stream after the checkpoint at tick 150 and tear the next line
+        let text = fs::read_to_string(&path).unwrap();
+        let cut = text.find("{\"tick\":150}\n").unwrap() + "{\"tick\":150}\n".len();
+        let torn = &text[cut..cut + 10];
+        fs::write(&path, format!("{}{}", &text[..cut], torn)).unwrap();
+
+        let partial = Replay::load(&path).unwrap();
+        assert!(partial.partial);
+        assert_eq!(partial.ticks, 150);
+        let before: Vec<_> = recorded
+            .events
+            .iter()
+            .take(partial.events.len())
+            .cloned()
+            .collect();
+        assert_eq!(partial.events, before);
+        assert!(partial.events.len() < recorded.events.len());
+        assert_eq!(partial.check().unwrap(), None);
+
+        // Damage anywhere but at the end is an error
+        let broken = text.replacen("{\"tick\":50}", "{\"tick\":", 1);
+        fs::write(&path, broken).unwrap();
+        assert!(Replay::load(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_tampered_replay_diverges() {
+        let mut replay = recorded_match();
+        let first = replay.events[0].clone();
+        replay.events[0].kind = EventKind::WaveStarted { wave: 9, drones: 9 };
+        assert_eq!(
+            replay.check().unwrap(),
+            Some(Divergence::Event {
+                index: 0,
+                recorded: Some(replay.events[0].clone()),
+                replayed: Some(first),
+            })
+        );
+
+        // A replayed event the recording does not have
+        let mut replay = recorded_match();
+        replay.events.clear();
+        assert!(matches!(
+            replay.check().unwrap(),
+            Some(Divergence::Event {
+                index: 0,
+                recorded: None,
+                replayed: Some(_)
+            })
+        ));
+    }
+}
@@ -0,0 +1,438 @@
+// Machine-readable match results, written by `--result-json` for tournament
+// scripts and CI jobs. A match that runs out of ticks with more than one team
+// standing is judged on points here.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::ast::Robot;
+use crate::error::SimError;
+use crate::modes::GameMode;
+use crate::practice::Drill;
+use crate::scenario::Objective;
+use crate::simulation::Simulation;
+
+/// Why a match ended.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndCondition {
+    /// One robot (or team) was left standing.
+    LastStanding,
+    /// A team reached the king-of-the-hill target score.
+    TargetScore,
+    /// Every robot was destroyed.
+    NoSurvivors,
+    /// The player's robot was destroyed in wave survival or practice.
+    PlayerDestroyed,
+    /// Every dummy of a practice drill was destroyed and the checkpoint reached.
+    DrillComplete,
+    /// The goal of a scenario was met.
+    GoalMet,
+    /// The goal of a scenario was missed.
+    GoalFailed,
+    /// The match was stopped at the `--ticks` limit or the rules' `max_ticks`.
+    TickLimit,
+    /// No robot took damage for the rules' `stalemate_ticks`, which they
+    /// call a draw.
+    Stalemate,
+    /// The match was stopped at the `--timeout` wall-clock limit.
+    Timeout,
+}
+
+/// Limits that stop a match early, for unattended runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunLimits {
+    pub max_ticks: Option<u64>,
+    pub timeout: Option<Duration>,
+}
+
+impl RunLimits {
+    /// The limit the match has hit, if any, for a match started at `started`.
+    pub fn reached(&self, sim: &Simulation, started: Instant) -> Option<EndCondition> {
+        if self.max_ticks.is_some_and(|max| sim.tick >= max) {
+            Some(EndCondition::TickLimit)
+        } else if self.timeout.is_some_and(|t| started.elapsed() >= t) {
+            Some(EndCondition::Timeout)
+        } else {
+            None
+        }
+    }
+}
+
+/// Final state of one robot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RobotResult {
+    pub id: usize,
+    pub name: String,
+    pub team: usize,
+    pub alive: bool,
+    pub health: i32,
+    pub kills: u32,
+    pub shots_fired: u32,
+    pub damage_dealt: u32,
+    pub score: u32, // Points of the robot's team, 0 in modes without scoring
+    /// The robot's points, if the match was judged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub judging: Option<Scorecard>,
+}
+
+/// The points a robot scored when a match was judged, by what earned them
+/// (see `JudgingTable`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Scorecard {
+    pub damage: f32,
+    pub kills: f32,
+    pub survival: f32,
+    pub health: f32,
+    pub objective: f32,
+    pub total: f32,
+}
+
+impl Scorecard {
+    /// Score robot `robot` of `sim` by the rules' judging table.
+    pub fn of(sim: &Simulation, robot: &Robot) -> Scorecard {
+        let table = &sim.config.judging;
+        let objective = sim.scores.get(&robot.team).copied().unwrap_or(0);
+        let mut card = Scorecard {
+            damage: table.damage * robot.damage_dealt as f32,
+            kills: table.kills * robot.kills as f32,
+            survival: if robot.health > 0 {
+                table.survival
+            } else {
+                0.0
+            },
+            health: table.health * robot.health.max(0) as f32,
+            objective: table.objective * objective as f32,
+            total: 0.0,
+        };
+        card.total = card.damage + card.kills + card.survival + card.health + card.objective;
+        card
+    }
+}
+
+/// The verdict on a match judged on points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Judgement {
+    /// The team with the most points, unless there was a tie at the top.
+    pub winner: Option<usize>,
+    /// Every robot's scorecard, in the order of `Simulation::robots`.
+    pub scorecards: Vec<Scorecard>,
+}
+
+/// Judge a match that was stopped at its tick limit, if the rules judge it:
+/// a deathmatch or king of the hill with more than one team standing, and
+/// judging enabled. A team's points are the sum of its robots'.
+pub fn judge(sim: &Simulation) -> Option<Judgement> {
+    let judged_mode = matches!(sim.mode, GameMode::Deathmatch | GameMode::KingOfTheHill);
+    if !sim.config.judging.enabled || !judged_mode || sim.winning_team().is_some() {
+        return None;
+    }
+    let scorecards: Vec<Scorecard> = sim.robots.iter().map(|r| Scorecard::of(sim, r)).collect();
+    let mut teams: BTreeMap<usize, f32> = BTreeMap::new();
+    for (robot, card) in sim.robots.iter().zip(&scorecards) {
+        *teams.entry(robot.team).or_insert(0.0) += card.total;
+    }
+    let best = teams.values().copied().fold(f32::MIN, f32::max);
+    let mut leaders = teams.iter().filter(|(_, points)| **points == best);
+    let winner = match (leaders.next(), leaders.next()) {
+        (Some((team, _)), None) => Some(*team),
+        _ => None,
+    };
+    Some(Judgement { winner, scorecards })
+}
+
+/// Outcome of a finished match.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchResult {
+    pub mode: String,
+    pub end_condition: EndCondition,
+    /// Id of the winning robot (the first of the winning team), if any.
+    pub winner: Option<usize>,
+    /// Random seed of the match, to play it again with `--seed`.
+    pub seed: Option<u64>,
+    pub ticks: u64,
+    /// Wall-clock time the match took to run, in seconds.
+    pub duration_secs: f64,
+    /// Last wave reached, in wave survival.
+    pub wave: Option<u32>,
+    /// Tick at which a stalemate turned into sudden death, if one did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sudden_death: Option<u64>,
+    pub robots: Vec<RobotResult>,
+    /// How the drill went, in practice.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drill: Option<Drill>,
+    /// The goal and its outcome, in a scenario.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub objective: Option<Objective>,
+}
+
+impl MatchResult {
+    /// Summarize a match that took `elapsed` to run. `stopped` is the limit that
+    /// ended it early, if any; such a match has no winner. So does one that
+    /// the match rules stopped (see `Simulation::stopped_by_rules`).
+    pub fn from_sim(sim: &Simulation, stopped: Option<EndCondition>, elapsed: Duration) -> Self {
+        let stopped = stopped.or_else(|| sim.stopped_by_rules());
+        let judgement = match stopped {
+            Some(EndCondition::TickLimit) => judge(sim),
+            _ => None,
+        };
+        let winning_team = match (stopped, &judgement) {
+            (None, _) => sim.winning_team(),
+            (Some(_), Some(judgement)) => judgement.winner,
+            (Some(_), None) => None,
+        };
+        let winner = winning_team.and_then(|team| sim.robots.iter().find(|r| r.team == team));
+        let end_condition = match (stopped, sim.mode, winner) {
+            (Some(limit), _, _) => limit,
+            (None, GameMode::WaveSurvival, _) => EndCondition::PlayerDestroyed,
+            (None, GameMode::Practice, _) if sim.drill.as_ref().is_some_and(Drill::is_complete) => {
+                EndCondition::DrillComplete
+            }
+            (None, GameMode::Practice, _) => EndCondition::PlayerDestroyed,
+            (None, GameMode::Scenario, _) => {
+                let passed = sim.objective.as_ref().and_then(|o| o.outcome.as_ref());
+                if passed.is_some_and(|outcome| outcome.passed) {
+                    EndCondition::GoalMet
+                } else {
+                    EndCondition::GoalFailed
+                }
+            }
+            (None, _, None) => EndCondition::NoSurvivors,
+            (None, GameMode::KingOfTheHill, Some(w))
+                if sim.scores.get(&w.team).copied().unwrap_or(0)
+                    >= sim.config.koth_target_score =>
+            {
+                EndCondition::TargetScore
+            }
+            (None, _, Some(_)) => EndCondition::LastStanding,
+        };
+
+        MatchResult {
+            mode: sim.mode.name().to_string(),
+            end_condition,
+            winner: if matches!(
+                sim.mode,
+                GameMode::WaveSurvival | GameMode::Practice | GameMode::Scenario
+            ) {
+                None
+            } else {
+                winner.map(|w| w.id)
+            },
+            seed: Some(sim.seed),
+            ticks: sim.tick,
+            duration_secs: elapsed.as_secs_f64(),
+            wave: (sim.mode == GameMode::WaveSurvival).then_some(sim.wave),
+            sudden_death: sim.sudden_death,
+            robots: sim
+                .robots
+                .iter()
+                .enumerate()
+                .map(|(i, r)| RobotResult {
+                    id: r.id,
+                    name: r.name.clone(),
+                    team: r.team,
+                    alive: r.health > 0,
+                    health: r.health.max(0),
+                    kills: r.kills,
+                    shots_fired: r.shots_fired,
+                    damage_dealt: r.damage_dealt,
+                    score: sim.scores.get(&r.team).copied().unwrap_or(0),
+                    judging: judgement.as_ref().map(|j| j.scorecards[i]),
+                })
+                .collect(),
+            drill: sim.drill.clone(),
+            objective: sim.objective.clone(),
+        }
+    }
+
+    /// Process exit status for the result: 0 for a win (or a completed survival
+    /// run or met scenario goal), 1 for a draw or a missed goal.
+    pub fn exit_code(&self) -> i32 {
+        match self.end_condition {
+            EndCondition::PlayerDestroyed | EndCondition::DrillComplete | EndCondition::GoalMet => {
+                0
+            }
+            _ if self.winner.is_some() => 0,
+            _ => 1,
+        }
+    }
+
+    /// Write the result as pretty-printed JSON.
+    pub fn write(&self, path: &Path) -> Result<(), SimError> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other);
+        json.and_then(|json| fs::write(path, json + "\n"))
+            .map_err(|source| SimError::WriteResult {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GameConfig, StalemateRule};
+    use crate::events::EventKind;
+
+    #[test]
+    fn test_last_standing_result() {
+        let robots = vec![
+            Robot::new(1, "alpha", (50.0, 50.0), vec![]),
+            Robot::new(2, "beta", (150.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.damage(1, 100, Some(1));
+        sim.robots[0].shots_fired = 3;
+        let result = MatchResult::from_sim(&sim, None, Duration::from_millis(1500));
+
+        assert_eq!(result.end_condition, EndCondition::LastStanding);
+        assert_eq!(result.winner, Some(1));
+        assert_eq!(result.exit_code(), 0);
+        assert_eq!(result.duration_secs, 1.5);
+        assert_eq!(result.robots[0].kills, 1);
+        assert_eq!(result.robots[0].shots_fired, 3);
+        assert!(!result.robots[1].alive);
+        assert_eq!(result.robots[1].health, 0);
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["end_condition"], "last_standing");
+        assert_eq!(json["mode"], "deathmatch");
+        assert_eq!(json["seed"], 0);
+    }
+
+    #[test]
+    fn test_no_survivors_result() {
+        let robots = vec![Robot::new(1, "alpha", (50.0, 50.0), vec![])];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[0].health = 0;
+        let result = MatchResult::from_sim(&sim, None, Duration::ZERO);
+        assert_eq!(result.end_condition, EndCondition::NoSurvivors);
+        assert_eq!(result.winner, None);
+        assert_eq!(result.exit_code(), 1);
+    }
+
+    #[test]
+    fn test_tick_limit_is_a_draw() {
+        let robots = vec![
+            Robot::new(1, "alpha", (50.0, 50.0), vec![]),
+            Robot::new(2, "beta", (150.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        let limits = RunLimits {
+            max_ticks: Some(3),
+            timeout: None,
+        };
+        let started = Instant::now();
+        while limits.reached(&sim, started).is_none() {
+            sim.step();
+        }
+        assert_eq!(sim.tick, 3);
+
+        let stopped = limits.reached(&sim, started);
+        let result = MatchResult::from_sim(&sim, stopped, Duration::ZERO);
+        assert_eq!(result.end_condition, EndCondition::TickLimit);
+        assert_eq!(result.winner, None);
+        assert_eq!(result.exit_code(), 1);
+    }
+
+    #[test]
+    fn test_match_rules_stop_quiet_matches() {
+        let robots = || {
+            vec![
+                Robot::new(1, "alpha", (50.0, 50.0), vec![]),
+                Robot::new(2, "beta", (150.0, 50.0), vec![]),
+            ]
+        };
+        let run = |config: GameConfig| {
+            let mut sim = Simulation::new(config, robots(), vec![]);
+            sim.robots[1].health = 3;
+            while !sim.is_over() {
+                sim.step();
+            }
+            let result = MatchResult::from_sim(&sim, None, Duration::ZERO);
+            (sim, result)
+        };
+
+        let (sim, result) = run(GameConfig {
+            max_ticks: 40,
+            ..GameConfig::default()
+        });
+        assert_eq!(sim.tick, 40);
+        assert_eq!(result.end_condition, EndCondition::TickLimit);
+        // Judged on points, alpha has more health left
+        assert_eq!(result.winner, Some(1));
+
+        let (sim, result) = run(GameConfig {
+            stalemate_ticks: 25,
+            stalemate: StalemateRule::Draw,
+            ..GameConfig::default()
+        });
+        assert_eq!(sim.tick, 25);
+        assert_eq!(result.end_condition, EndCondition::Stalemate);
+        assert_eq!(result.exit_code(), 1);
+        assert_eq!(
+            serde_json::to_value(&result).unwrap()["end_condition"],
+            "stalemate"
+        );
+
+        // Sudden death wears both robots down until the weaker one is destroyed
+        let (sim, result) = run(GameConfig {
+            stalemate_ticks: 25,
+            sudden_death_interval: 10,
+            ..GameConfig::default()
+        });
+        assert_eq!(result.end_condition, EndCondition::LastStanding);
+        assert_eq!(result.winner, Some(1));
+        assert_eq!(result.sudden_death, Some(25));
+        assert_eq!(sim.tick, 25 + 2 * 10 + 1);
+        assert_eq!(sim.robots[0].health, sim.robots[0].max_health - 3);
+        assert_eq!(sim.events[0].kind, EventKind::SuddenDeath);
+    }
+
+    #[test]
+    fn test_tick_limit_is_judged_on_points() {
+        let robots = vec![
+            Robot::new(1, "alpha", (50.0, 50.0), vec![]),
+            Robot::new(2, "beta", (150.0, 50.0), vec![]),
+            Robot::new(3, "gamma", (250.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        let full = sim.robots[0].health;
+        // Gamma shoots beta for 4 and alpha for 1, and beta hits back for 2
+        sim.damage(1, 4, Some(3));
+        sim.damage(0, 1, Some(3));
+        sim.damage(2, 2, Some(2));
+        let result = MatchResult::from_sim(&sim, Some(EndCondition::TickLimit), Duration::ZERO);
+        assert_eq!(result.end_condition, EndCondition::TickLimit);
+        assert_eq!(result.winner, Some(3));
+        assert_eq!(result.exit_code(), 0);
+        let gamma = result.robots[2].judging.unwrap();
+        assert_eq!(result.robots[2].damage_dealt, 5);
+        assert_eq!(gamma.damage, 5.0);
+        assert_eq!(gamma.survival, 5.0);
+        assert_eq!(gamma.health, (full - 2) as f32);
+        assert_eq!(gamma.total, 5.0 + 5.0 + (full - 2) as f32);
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["robots"][2]["judging"]["damage"], 5.0);
+
+        // A tie at the top is still a draw, and so is every timeout if judging is off
+        sim.damage(0, 5, Some(2));
+        let result = MatchResult::from_sim(&sim, Some(EndCondition::TickLimit), Duration::ZERO);
+        assert_eq!(result.winner, None);
+        assert!(result.robots[0].judging.is_some());
+        sim.config.judging.enabled = false;
+        let result = MatchResult::from_sim(&sim, Some(EndCondition::TickLimit), Duration::ZERO);
+        assert_eq!(result.winner, None);
+        assert_eq!(result.robots[0].judging, None);
+        // Wall-clock timeouts are not judged
+        sim.config.judging.enabled = true;
+        let result = MatchResult::from_sim(&sim, Some(EndCondition::Timeout), Duration::ZERO);
+        assert_eq!(result.winner, None);
+    }
+}
@@ -0,0 +1,230 @@
+// Limits on what a robot script may use, so one pathological bot cannot bog
+// down a tournament and every build fits the points budget. Scripts are
+// checked when they are compiled.
+//
+// The VM runs exactly one instruction per robot per tick and the DSL has no
+// function calls, so execution time and call depth are bounded already; the
+// limits cover program size, variables and how deeply blocks nest. Register
+// names are interned for the life of the process, so a script is also turned
+// away once it would take the names past `MAX_REGISTER_NAMES`.
+
+use std::collections::BTreeSet;
+
+use crate::ast::{Command, Expr, Instruction, translate_commands_to_instructions};
+use crate::config::GameConfig;
+use crate::error::LimitError;
+use crate::loadout::{Loadout, MAX_LEVEL};
+use crate::registers::{MAX_REGISTER_NAMES, Reg};
+
+/// Compile `commands` into a program, rejecting scripts that exceed the limits
+/// in `config`.
+pub fn compile(commands: &[Command], config: &GameConfig) -> Result<Vec<Instruction>, LimitError> {
+    let loadout = Loadout::of(commands);
+    if let Some((stat, level)) = loadout.stats().into_iter().find(|(_, l)| *l > MAX_LEVEL) {
+        return Err(LimitError::LevelTooHigh {
+            stat,
+            level,
+            max: MAX_LEVEL,
+        });
+    }
+    if loadout.cost() > config.loadout_budget {
+        return Err(LimitError::OverBudget {
+            cost: loadout.cost(),
+            budget: config.loadout_budget,
+        });
+    }
+
+    let depth = nesting_depth(commands);
+    if depth > config.max_nesting_depth {
+        return Err(LimitError::NestingTooDeep {
+            depth,
+            max: config.max_nesting_depth,
+        });
+    }
+
+    let mut variables = BTreeSet::new();
+    collect_variables(commands, &mut variables);
+    if variables.len() > config.max_variables {
+        return Err(LimitError::TooManyVariables {
+            count: variables.len(),
+            max: config.max_variables,
+        });
+    }
+
+    let count = new_register_names(commands);
+    let room = Reg::room();
+    if count > room {
+        return Err(LimitError::OutOfRegisterNames {
+            count,
+            room,
+            max: MAX_REGISTER_NAMES,
+        });
+    }
+
+    let program = translate_commands_to_instructions(commands);
+    if program.len() > config.max_program_size {
+        return Err(LimitError::ProgramTooLarge {
+            size: program.len(),
+            max: config.max_program_size,
+        });
+    }
+    Ok(program)
+}
+
+/// How many blocks deep the most deeply nested command sits; 0 without blocks.
+fn nesting_depth(commands: &[Command]) -> usize {
+    commands
+        .iter()
+        .map(|command| match command {
+            Command::Loop { block } => 1 + nesting_depth(block),
+            Command::If {
+                block, else_block, ..
+            } => 1 + nesting_depth(block).max(else_block.as_deref().map_or(0, nesting_depth)),
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Names of the registers the script assigns with `let` or `persist load`.
+fn collect_variables<'a>(commands: &'a [Command], variables: &mut BTreeSet<&'a str>) {
+    for command in commands {
+        match command {
+            Command::Let { name, .. } | Command::PersistLoad { name } => {
+                variables.insert(name);
+            }
+            Command::Loop { block } => collect_variables(block, variables),
+            Command::If {
+                block, else_block, ..
+            } => {
+                collect_variables(block, variables);
+                if let Some(else_block) = else_block {
+                    collect_variables(else_block, variables);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// How many of the register names the script reads or writes no program has
+/// used yet.
+fn new_register_names(commands: &[Command]) -> usize {
+    let mut registers = BTreeSet::new();
+    collect_registers(commands, &mut registers);
+    registers
+        .into_iter()
+        .filter(|name| Reg::lookup(name).is_none())
+        .count()
+}
+
+/// Names of every register the script reads or writes.
+fn collect_registers<'a>(commands: &'a [Command], registers: &mut BTreeSet<&'a str>) {
+    for command in commands {
+        match command {
+            Command::Let { name, expr } => {
+                registers.insert(name);
+                collect_expr_registers(expr, registers);
+            }
+            Command::PersistStore { name } | Command::PersistLoad { name } => {
+                registers.insert(name);
+            }
+            Command::Plugin { args, .. } => {
+                for arg in args {
+                    collect_expr_registers(arg, registers);
+                }
+            }
+            Command::Loop { block } => collect_registers(block, registers),
+            Command::If {
+                condition,
+                block,
+                else_block,
+            } => {
+                collect_expr_registers(condition, registers);
+                collect_registers(block, registers);
+                if let Some(else_block) = else_block {
+                    collect_registers(else_block, registers);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_expr_registers<'a>(expr: &'a Expr, registers: &mut BTreeSet<&'a str>) {
+    match expr {
+        Expr::Number(_) => {}
+        Expr::Register(name) => {
+            registers.insert(name);
+        }
+        Expr::Op { args, .. } => {
+            for arg in args {
+                collect_expr_registers(arg, registers);
+            }
+        }
+        Expr::History { index, .. } => collect_expr_registers(index, registers),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_script;
+
+    fn check(script: &str, config: &GameConfig) -> Result<Vec<Instruction>, LimitError> {
+        compile(&parse_script(script).unwrap(), config)
+    }
+
+    #[test]
+    fn test_scripts_within_limits_compile() {
+        let program = check("loop {\n scan\n fire\n}", &GameConfig::default()).unwrap();
+        assert!(!program.is_empty());
+    }
+
+    #[test]
+    fn test_limits_are_enforced() {
+        let config = GameConfig {
+            max_program_size: 4,
+            max_variables: 2,
+            max_nesting_depth: 2,
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            check("scan\nfire\nscan\nfire\nscan", &config),
+            Err(LimitError::ProgramTooLarge { size: 5, max: 4 })
+        );
+        // Assigning the same variable twice counts once
+        assert!(check("let a = 1\nlet a = 2\nlet b = a", &config).is_ok());
+        assert_eq!(
+            check("let a = 1\nloop {\n let b = 2\n let c = 3\n}", &config),
+            Err(LimitError::TooManyVariables { count: 3, max: 2 })
+        );
+        assert_eq!(
+            check("build armor 4 speed 4 weapon 1 scanner 0\nscan", &config),
+            Err(LimitError::OverBudget { cost: 9, budget: 8 })
+        );
+        assert_eq!(
+            check("build armor 5 speed 0 weapon 1 scanner 0\nscan", &config),
+            Err(LimitError::LevelTooHigh {
+                stat: "armor",
+                level: 5,
+                max: 4
+            })
+        );
+        assert_eq!(
+            check("loop {\n loop {\n loop {\n scan\n }\n }\n}", &config)
+                .unwrap_err()
+                .to_string(),
+            "blocks are nested 3 deep; the limit is 2"
+        );
+    }
+
+    #[test]
+    fn test_new_register_names_are_counted() {
+        let script = "let sandbox_a = sandbox_b + 1\nif sandbox_c > x {\n let sandbox_a = 2\n}";
+        let commands = parse_script(script).unwrap();
+        assert_eq!(new_register_names(&commands), 3);
+        compile(&commands, &GameConfig::default()).unwrap();
+        assert_eq!(new_register_names(&commands), 0);
+    }
+}
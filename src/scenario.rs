@@ -0,0 +1,497 @@
+// Scenarios: hand-made setups for puzzles and tutorials, read from TOML.
+// A scenario places the player's robot and any opponents, can start them
+// damaged, builds its own obstacles, movers, platforms and hazards, can make
+// things happen on cue (see `inject`) and sets a goal, such as reaching an exit
+// within a number of ticks. The match ends as soon as the goal is met or can no
+// longer be met.
+//
+//     name = "Escape"
+//     description = "Reach the exit in the top-right corner."
+//     within = 500
+//
+//     [player]
+//     position = [40, 360]
+//     health = 4
+//
+//     [[robot]]
+//     bot = "builtin:spinner"
+//     position = [200, 200]
+//
+//     [[obstacle]]
+//     x = 120
+//     y = 0
+//     width = 20
+//     height = 300
+//
+//     [goal]
+//     kind = "reach"
+//     position = [370, 30]
+//     radius = 20
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{Robot, normalize_degrees};
+use crate::config::GameConfig;
+use crate::elevation::{Elevation, Rect};
+use crate::hazards::Hazard;
+use crate::inject::{Injection, Scheduled};
+use crate::modes::{GameMode, Zone};
+use crate::movers::Mover;
+use crate::raycast::Obstacle;
+use crate::setup::Entrant;
+use crate::simulation::Simulation;
+
+/// A scenario file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    /// What to do, shown when the scenario starts.
+    #[serde(default)]
+    pub description: String,
+    /// `key=value` config overrides, applied before those on the command line.
+    #[serde(default)]
+    pub set: Vec<String>,
+    /// Ticks the player has to meet the goal; required for `survive`.
+    pub within: Option<u64>,
+    #[serde(default)]
+    pub player: Placement,
+    /// Opponents, in spawn order after the player.
+    #[serde(default, rename = "robot")]
+    pub robots: Vec<Placement>,
+    /// The arena's obstacles; without any the arena is empty.
+    #[serde(default, rename = "obstacle")]
+    pub obstacles: Vec<Obstacle>,
+    /// Doors and barriers, as in an arena file.
+    #[serde(default, rename = "mover")]
+    pub movers: Vec<Mover>,
+    /// Raised platforms and the ramps up to them, as in an arena file.
+    #[serde(default, rename = "platform")]
+    pub platforms: Vec<Rect>,
+    #[serde(default, rename = "ramp")]
+    pub ramps: Vec<Rect>,
+    /// Hazards that go off during the scenario, as in an arena file.
+    #[serde(default, rename = "hazard")]
+    pub hazards: Vec<Hazard>,
+    /// Synthetic events, such as a hit on the player at a set tick.
+    #[serde(default, rename = "event")]
+    pub events: Vec<Scheduled>,
+    pub goal: Goal,
+    /// Directory of the scenario file, which bot paths are relative to.
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+/// Where a robot starts and how worn it is. Stats left out are the robot's
+/// usual starting stats.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Placement {
+    /// `builtin:<name>` or a script path; the player's bot is given on the
+    /// command line instead.
+    pub bot: Option<String>,
+    pub position: Option<(f32, f32)>,
+    /// Body heading in degrees.
+    pub heading: Option<f32>,
+    pub health: Option<i32>,
+    pub energy: Option<i32>,
+    pub ammo: Option<i32>,
+}
+
+/// What the player has to do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Goal {
+    /// Drive into the circle around `position`.
+    Reach { position: (f32, f32), radius: f32 },
+    /// Drive into the circle of `radius` around each waypoint, in order.
+    Patrol {
+        waypoints: Vec<(f32, f32)>,
+        radius: f32,
+    },
+    /// Land `count` hits on other robots.
+    Hit { count: u32 },
+    /// Destroy every other robot.
+    DestroyAll,
+    /// Stay alive until the time limit.
+    Survive,
+}
+
+impl Goal {
+    fn describe(&self) -> String {
+        match self {
+            Goal::Reach { position, .. } => {
+                format!("reach the exit at ({:.0}, {:.0})", position.0, position.1)
+            }
+            Goal::Patrol { waypoints, .. } => {
+                format!("visit {} waypoints in order", waypoints.len())
+            }
+            Goal::Hit { count: 1 } => "hit a target".to_string(),
+            Goal::Hit { count } => format!("land {} hits", count),
+            Goal::DestroyAll => "destroy every enemy".to_string(),
+            Goal::Survive => "survive".to_string(),
+        }
+    }
+}
+
+/// How a scenario ended.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Outcome {
+    pub passed: bool,
+    pub tick: u64,
+    pub reason: &'static str,
+}
+
+/// The goal of a running scenario and, once decided, its outcome.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Objective {
+    pub scenario: String,
+    /// The scenario's instructions, shown while it runs.
+    #[serde(skip)]
+    pub description: String,
+    pub goal: Goal,
+    pub within: Option<u64>,
+    /// Id of the player's robot.
+    pub player: usize,
+    /// Hits the player has landed on other robots.
+    pub hits: u32,
+    /// Index of the patrol waypoint the player drives to next.
+    pub waypoint: usize,
+    pub outcome: Option<Outcome>,
+}
+
+impl Scenario {
+    /// Read a scenario file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut scenario =
+            Scenario::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if scenario.name.is_empty() {
+            scenario.name = path
+                .file_stem()
+                .map_or("scenario".to_string(), |s| s.to_string_lossy().into_owned());
+        }
+        scenario.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(scenario)
+    }
+
+    /// Parse a scenario written in TOML. Bot paths in it are relative to the
+    /// working directory.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let scenario: Scenario = toml::from_str(text).map_err(|e| e.to_string())?;
+        scenario.check()?;
+        Ok(scenario)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.player.bot.is_some() {
+            return Err("the player's bot is given on the command line".to_string());
+        }
+        if let Some(i) = self.robots.iter().position(|r| r.bot.is_none()) {
+            return Err(format!("robot {} has no bot", i + 1));
+        }
+        if self.goal == Goal::Survive && self.within.is_none() {
+            return Err("a survive goal needs `within`".to_string());
+        }
+        if let Goal::Patrol { waypoints, .. } = &self.goal
+            && waypoints.is_empty()
+        {
+            return Err("a patrol goal needs waypoints".to_string());
+        }
+        // The player is robot 1, and the scenario's robots follow
+        let robots = self.robots.len() + 1;
+        for (i, event) in self.events.iter().enumerate() {
+            let robot = match event.injection {
+                Injection::Hit { robot, .. }
+                | Injection::Push { robot, .. }
+                | Injection::Stun { robot, .. } => robot,
+                Injection::Supply { .. } => continue,
+            };
+            if robot == 0 || robot > robots {
+                return Err(format!(
+                    "event {} is for robot {}, which the scenario does not have",
+                    i + 1,
+                    robot
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The entrants in spawn order: `player`, then the scenario's robots.
+    /// Script paths are relative to the scenario file.
+    pub fn entrants(&self, player: &Entrant) -> Result<Vec<Entrant>, String> {
+        let mut entrants = vec![player.clone()];
+        for bot in self.robots.iter().filter_map(|r| r.bot.as_deref()) {
+            entrants.push(match Entrant::parse(bot)? {
+                Entrant::Script(path) => Entrant::Script(self.dir.join(path)),
+                Entrant::Human => return Err("only the player can be human".to_string()),
+                builtin => builtin,
+            });
+        }
+        Ok(entrants)
+    }
+
+    /// Move the robots compiled from `entrants` into place and wear them down
+    /// as the scenario says.
+    pub fn place(&self, robots: &mut [Robot]) {
+        let placements = std::iter::once(&self.player).chain(&self.robots);
+        for (robot, placement) in robots.iter_mut().zip(placements) {
+            if let Some(position) = placement.position {
+                robot.position = position;
+            }
+            if let Some(heading) = placement.heading {
+                robot.heading = normalize_degrees(heading);
+            }
+            if let Some(health) = placement.health {
+                robot.health = health.min(robot.max_health);
+            }
+            if let Some(energy) = placement.energy {
+                robot.energy = energy;
+            }
+            if let Some(ammo) = placement.ammo {
+                robot.ammo = ammo;
+            }
+        }
+    }
+
+    /// Set up the scenario with the robots compiled from `entrants`.
+    pub fn start(&self, config: GameConfig, mut robots: Vec<Robot>) -> Simulation {
+        self.place(&mut robots);
+        let player = robots.first().map_or(1, |r| r.id);
+        let mut sim = Simulation::new(config, robots, self.obstacles.clone())
+            .with_mode(GameMode::Scenario)
+            .with_movers(self.movers.clone())
+            .with_elevation(Elevation {
+                platforms: self.platforms.clone(),
+                ramps: self.ramps.clone(),
+            })
+            .with_hazards(self.hazards.clone());
+        for event in &self.events {
+            sim.inject_event(event.tick, event.injection.clone());
+        }
+        sim.objective = Some(Objective {
+            scenario: self.name.clone(),
+            description: self.description.clone(),
+            goal: self.goal.clone(),
+            within: self.within,
+            player,
+            hits: 0,
+            waypoint: 0,
+            outcome: None,
+        });
+        sim
+    }
+
+    /// The scenario's name and description, to show before it starts.
+    pub fn briefing(&self) -> String {
+        let mut text = format!("Scenario: {}", self.name);
+        if !self.description.is_empty() {
+            text += &format!("\n{}", self.description.trim_end());
+        }
+        text
+    }
+}
+
+impl Objective {
+    /// The zone the player has to reach next, for a `reach` or `patrol` goal.
+    pub fn zone(&self) -> Option<Zone> {
+        match &self.goal {
+            Goal::Reach { position, radius } => Some(Zone {
+                center: *position,
+                radius: *radius,
+            }),
+            Goal::Patrol { waypoints, radius } => waypoints.get(self.waypoint).map(|center| Zone {
+                center: *center,
+                radius: *radius,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Advance the player along a patrol and decide the outcome once `sim`
+    /// has met or missed the goal.
+    pub fn update(&mut self, sim: &Simulation) {
+        if self.outcome.is_some() {
+            return;
+        }
+        let player = sim.robots.iter().find(|r| r.id == self.player);
+        if let Goal::Patrol { .. } = self.goal
+            && let Some(zone) = self.zone()
+            && player.is_some_and(|p| zone.contains(p.position))
+        {
+            self.waypoint += 1;
+        }
+        self.outcome = self.evaluate(sim);
+    }
+
+    /// The outcome `sim` has reached, if the scenario is decided.
+    pub fn evaluate(&self, sim: &Simulation) -> Option<Outcome> {
+        let tick = sim.tick;
+        let outcome = |passed, reason| {
+            Some(Outcome {
+                passed,
+                tick,
+                reason,
+            })
+        };
+        let player = sim.robots.iter().find(|r| r.id == self.player)?;
+        if player.health <= 0 {
+            return outcome(false, "robot destroyed");
+        }
+        let met = match self.goal {
+            Goal::Reach { .. } => self
+                .zone()
+                .is_some_and(|zone| zone.contains(player.position))
+                .then_some("reached the exit"),
+            Goal::Patrol { ref waypoints, .. } => {
+                (self.waypoint >= waypoints.len()).then_some("completed the patrol")
+            }
+            Goal::Hit { count } => (self.hits >= count).then_some("hit the target"),
+            Goal::DestroyAll => sim
+                .robots
+                .iter()
+                .all(|r| r.team == player.team || r.health <= 0)
+                .then_some("destroyed every enemy"),
+            Goal::Survive => None,
+        };
+        if let Some(reason) = met {
+            return outcome(true, reason);
+        }
+        match self.within {
+            Some(limit) if tick >= limit && self.goal == Goal::Survive => outcome(true, "survived"),
+            Some(limit) if tick >= limit => outcome(false, "out of time"),
+            _ => None,
+        }
+    }
+
+    /// One line of progress for the HUD.
+    pub fn status(&self, tick: u64) -> String {
+        let progress = match (&self.outcome, self.within) {
+            (Some(outcome), _) if outcome.passed => format!("passed: {}", outcome.reason),
+            (Some(outcome), _) => format!("failed: {}", outcome.reason),
+            (None, Some(limit)) => format!("{} ticks left", limit.saturating_sub(tick)),
+            (None, None) => "no time limit".to_string(),
+        };
+        let goal = match &self.goal {
+            Goal::Patrol { waypoints, .. } => format!(
+                "{} ({} of {})",
+                self.goal.describe(),
+                self.waypoint,
+                waypoints.len()
+            ),
+            Goal::Hit { count } if *count > 1 => {
+                format!("{} ({} so far)", self.goal.describe(), self.hits)
+            }
+            _ => self.goal.describe(),
+        };
+        format!("{} | {} | {}", self.scenario, goal, progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ESCAPE: &str = r#"
+        within = 50
+        [player]
+        position = [20, 20]
+        health = 3
+        [[robot]]
+        bot = "builtin:sitting-duck"
+        position = [300, 300]
+        [[obstacle]]
+        x = 100
+        y = 100
+        width = 10
+        height = 10
+        [goal]
+        kind = "reach"
+        position = [40, 20]
+        radius = 5
+    "#;
+
+    #[test]
+    fn test_scenario_places_robots_and_decides_the_goal() {
+        let scenario: Scenario = toml::from_str(ESCAPE).unwrap();
+        scenario.check().unwrap();
+        let entrants = scenario.entrants(&Entrant::Human).unwrap();
+        assert_eq!(entrants[1], Entrant::Builtin("sitting-duck".to_string()));
+
+        let robots = vec![
+            Robot::new(1, "player", (0.0, 0.0), vec![]),
+            Robot::new(2, "duck", (0.0, 0.0), vec![]),
+        ];
+        let mut sim = scenario.start(GameConfig::default(), robots);
+        assert_eq!(sim.robots[0].position, (20.0, 20.0));
+        assert_eq!(sim.robots[0].health, 3);
+        assert_eq!(sim.robots[1].position, (300.0, 300.0));
+        assert_eq!(sim.obstacles.len(), 1);
+        assert_eq!(sim.control_zone().unwrap().center, (40.0, 20.0));
+
+        let objective = sim.objective.clone().unwrap();
+        assert_eq!(objective.evaluate(&sim), None);
+        sim.tick = 50;
+        assert_eq!(objective.evaluate(&sim).unwrap().reason, "out of time");
+        sim.robots[0].position = (40.0, 22.0);
+        assert!(objective.evaluate(&sim).unwrap().passed);
+    }
+
+    #[test]
+    fn test_survive_needs_a_time_limit() {
+        let scenario: Scenario = toml::from_str("[goal]\nkind = \"survive\"\n").unwrap();
+        assert!(scenario.check().is_err());
+        assert!(toml::from_str::<Scenario>("[goal]\nkind = \"escape\"\n").is_err());
+    }
+
+    #[test]
+    fn test_patrol_visits_waypoints_in_order() {
+        let scenario = Scenario::parse(
+            "[goal]\nkind = \"patrol\"\nwaypoints = [[50, 50], [100, 50]]\nradius = 5\n",
+        )
+        .unwrap();
+        let robots = vec![Robot::new(1, "player", (100.0, 50.0), vec![])];
+        let mut sim = scenario.start(GameConfig::default(), robots);
+        let mut objective = sim.objective.clone().unwrap();
+        // The second waypoint does not count before the first
+        objective.update(&sim);
+        assert_eq!(objective.waypoint, 0);
+        sim.robots[0].position = (50.0, 50.0);
+        objective.update(&sim);
+        assert_eq!(objective.waypoint, 1);
+        assert_eq!(objective.zone().unwrap().center, (100.0, 50.0));
+        sim.robots[0].position = (100.0, 50.0);
+        objective.update(&sim);
+        assert_eq!(objective.outcome.unwrap().reason, "completed the patrol");
+    }
+
+    #[test]
+    fn test_events_are_injected() {
+        let scenario = Scenario::parse(
+            "within = 100\n[[event]]\ntick = 10\nkind = \"hit\"\nrobot = 1\ndamage = 4\n\
+             [goal]\nkind = \"survive\"\n",
+        )
+        .unwrap();
+        let robots = vec![Robot::new(1, "player", (100.0, 50.0), vec![])];
+        let mut sim = scenario.start(GameConfig::default(), robots);
+        for _ in 0..11 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].health, 6);
+
+        let err = Scenario::parse(
+            "within = 100\n[[event]]\ntick = 10\nkind = \"stun\"\nrobot = 2\nticks = 5\n\
+             [goal]\nkind = \"survive\"\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "event 1 is for robot 2, which the scenario does not have"
+        );
+    }
+}
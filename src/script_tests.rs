@@ -0,0 +1,337 @@
+// Unit tests written into a bot's script.
+// A script may hold `test "name" { run 100; assert heading == 180 }` blocks
+// among its commands. Matches ignore them; `robot-battle test` runs each one
+// in a simulation of its own, with the bot alone in an empty arena, so authors
+// can check that a behavior still works after changing the script. A test may
+// add a dummy opponent that drives a fixed route and fires on a fixed
+// schedule, so a test of aiming or dodging plays out the same way every time.
+
+use crate::ast::{Command, Expr, Robot, normalize_degrees, translate_commands_to_instructions};
+use crate::config::GameConfig;
+use crate::error::ScriptError;
+use crate::loadout::Loadout;
+use crate::parser::{parse_script, parse_tests};
+use crate::registers::Reg;
+use crate::sandbox;
+use crate::setup::SPAWN_POINTS;
+use crate::simulation::Simulation;
+use crate::systems::sensor_system;
+
+/// A `test` block: a name, the opponent it sets up, if any, and the steps it
+/// takes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptTest {
+    pub name: String,
+    /// Line the block starts on.
+    pub line: usize,
+    pub opponent: Option<Opponent>,
+    pub steps: Vec<TestStep>,
+}
+
+/// A dummy opponent, declared with
+/// `opponent at <x> <y> path { <x> <y> ... } fire { <tick> ... }`. It is in
+/// the arena from the start of the test, whichever step declares it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opponent {
+    pub start: (i32, i32),
+    /// Waypoints it drives to in turn, starting over after the last; without
+    /// any it stands still.
+    pub path: Vec<(i32, i32)>,
+    /// Ticks it fires at the bot on, aiming straight at it.
+    pub fire: Vec<u64>,
+}
+
+/// Names assertions can read besides registers, for the opponent's state.
+const OPPONENT_HEALTH: &str = "opponent_health";
+const OPPONENT_X: &str = "opponent_x";
+const OPPONENT_Y: &str = "opponent_y";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestStep {
+    /// Advance the simulation by this many ticks.
+    Run(u32),
+    /// Fail the test unless the condition is not zero, with the registers as
+    /// they read now.
+    Assert { condition: Expr, line: usize },
+}
+
+/// How a test went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    /// Why the test failed: the first assertion that did not hold.
+    pub failure: Option<String>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Run every test in `script`, a bot called `name`, each in a fresh
+/// simulation. The script must compile within the sandbox limits in `config`.
+pub fn run_tests(
+    name: &str,
+    script: &str,
+    config: &GameConfig,
+) -> Result<Vec<TestResult>, ScriptError> {
+    let tests = parse_tests(script)?;
+    let commands = parse_script(script)?;
+    let program = sandbox::compile(&commands, config)?;
+    let mut robot = Robot::new(1, name, SPAWN_POINTS[0], program);
+    robot.equip(Loadout::of(&commands), config);
+    Ok(tests
+        .iter()
+        .map(|test| TestResult {
+            name: test.name.clone(),
+            failure: run_test(test, &robot, config),
+        })
+        .collect())
+}
+
+/// Run `test` with `robot` alone in the arena, or against the test's
+/// opponent, returning why it failed, if it did.
+fn run_test(test: &ScriptTest, robot: &Robot, config: &GameConfig) -> Option<String> {
+    let mut robots = vec![robot.clone()];
+    if let Some(opponent) = &test.opponent {
+        robots.push(dummy(opponent, config));
+    }
+    let mut sim = Simulation::new(config.clone(), robots, Vec::new());
+    for step in &test.steps {
+        match step {
+            TestStep::Run(ticks) => {
+                for _ in 0..*ticks {
+                    if let Some(opponent) = &test.opponent
+                        && opponent.fire.contains(&sim.tick)
+                    {
+                        fire_at_bot(&mut sim);
+                    }
+                    sim.step();
+                }
+            }
+            TestStep::Assert { condition, line } => {
+                // Sensors are read afresh, on a copy so the test's world is
+                // not disturbed by looking at it
+                let mut now = sim.clone();
+                sensor_system(&mut now);
+                if evaluate(condition, &now) == 0 {
+                    let mut names = Vec::new();
+                    names_in(condition, &mut names);
+                    let values: Vec<String> = names
+                        .iter()
+                        .map(|name| format!("{} = {}", name, value(&now, name)))
+                        .collect();
+                    return Some(if values.is_empty() {
+                        format!("line {}: assertion failed", line)
+                    } else {
+                        format!("line {}: assertion failed ({})", line, values.join(", "))
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The robot playing `opponent`: a program that drives its path in a loop.
+fn dummy(opponent: &Opponent, config: &GameConfig) -> Robot {
+    let route: Vec<Command> = opponent
+        .path
+        .iter()
+        .map(|&(x, y)| Command::GoTo { x, y })
+        .collect();
+    let program = if route.is_empty() {
+        Vec::new()
+    } else {
+        translate_commands_to_instructions(&[Command::Loop { block: route }])
+    };
+    let (x, y) = opponent.start;
+    let mut robot = Robot::new(2, "opponent", (x as f32, y as f32), program);
+    robot.equip(Loadout::default(), config);
+    robot
+}
+
+/// Turn the opponent's turret on the bot and fire one shot, unless the
+/// opponent is destroyed.
+fn fire_at_bot(sim: &mut Simulation) {
+    let target = sim.robots[0].position;
+    let opponent = &sim.robots[1];
+    if opponent.health <= 0 {
+        return;
+    }
+    let (dx, dy) = sim.config.displacement(opponent.position, target);
+    let bearing = sim.config.math.atan2(dy, dx).to_degrees();
+    let heading = opponent.heading;
+    sim.robots[1].turret_heading = normalize_degrees(bearing - heading);
+    sim.fire(1);
+}
+
+/// Value of `expr` in `sim`, as the bot's VM would compute it.
+fn evaluate(expr: &Expr, sim: &Simulation) -> i32 {
+    match expr {
+        Expr::Number(n) => *n,
+        Expr::Register(name) => value(sim, name),
+        Expr::Op { op, args } => {
+            let args: Vec<i32> = args.iter().map(|a| evaluate(a, sim)).collect();
+            op.apply(&args, sim.config.math)
+        }
+        Expr::History { field, index } => usize::try_from(evaluate(index, sim))
+            .ok()
+            .and_then(|i| sim.robots[0].scan_history.get(i))
+            .map_or(0, |record| field.read(record)),
+    }
+}
+
+/// A register of the bot, or a value of the opponent's state; registers that
+/// were never set, and the opponent's values in a test without one, read as 0.
+fn value(sim: &Simulation, name: &str) -> i32 {
+    let opponent = sim.robots.get(1);
+    match name {
+        OPPONENT_HEALTH => opponent.map_or(0, |o| o.health),
+        OPPONENT_X => opponent.map_or(0, |o| o.position.0.round() as i32),
+        OPPONENT_Y => opponent.map_or(0, |o| o.position.1.round() as i32),
+        _ => Reg::lookup(name)
+            .and_then(|reg| sim.robots[0].registers.get(reg))
+            .unwrap_or(0),
+    }
+}
+
+/// Names of the registers and values `expr` reads, in order, each once.
+fn names_in<'a>(expr: &'a Expr, names: &mut Vec<&'a str>) {
+    match expr {
+        Expr::Number(_) => {}
+        Expr::Register(name) if !names.contains(&name.as_str()) => names.push(name),
+        Expr::Register(_) => {}
+        Expr::Op { args, .. } => args.iter().for_each(|a| names_in(a, names)),
+        Expr::History { index, .. } => names_in(index, names),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ParseError;
+
+    const SPINNER: &str = r#"
+        rotate body 180
+        loop { scan }
+
+        test "turns around" {
+            run 800
+            assert heading == 180
+        }
+        test "stays put" { run 50; assert x == 100; assert health > 200 }
+    "#;
+
+    #[test]
+    fn test_tests_are_not_part_of_the_program() {
+        let with_tests = parse_script(SPINNER).unwrap();
+        let without = parse_script("rotate body 180\nloop { scan }").unwrap();
+        assert_eq!(with_tests, without);
+    }
+
+    #[test]
+    fn test_parse_tests() {
+        let tests = parse_tests(SPINNER).unwrap();
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].name, "turns around");
+        assert_eq!(tests[0].line, 5);
+        assert_eq!(tests[0].steps[0], TestStep::Run(800));
+        assert!(matches!(
+            tests[0].steps[1],
+            TestStep::Assert { line: 7, .. }
+        ));
+        assert_eq!(tests[1].steps.len(), 3);
+    }
+
+    #[test]
+    fn test_bad_steps_are_errors() {
+        let err = parse_tests("test \"t\" { fire }").unwrap_err();
+        assert!(matches!(
+            err,
+            ScriptError::Parse {
+                source: ParseError::UnknownTestStep(_),
+                ..
+            }
+        ));
+        assert!(parse_tests("test \"t\" { run -1 }").is_err());
+        assert!(parse_tests("test \"t\" { run 10").is_err());
+        // A test's name must be a string, so `test` alone is not a block
+        assert!(parse_script("test { run 1 }").is_err());
+    }
+
+    #[test]
+    fn test_run_tests() {
+        let config = GameConfig::default();
+        let results = run_tests("spinner", SPINNER, &config).unwrap();
+        assert!(results[0].passed(), "{:?}", results[0].failure);
+        let failure = results[1].failure.as_deref().unwrap();
+        assert!(failure.starts_with("line 9: assertion failed (health = "));
+    }
+
+    #[test]
+    fn test_parse_opponent() {
+        let tests = parse_tests(
+            "test \"t\" { run 10\n opponent at 300 50 path { 300 300 50 300 } fire { 5 40 } }",
+        )
+        .unwrap();
+        assert_eq!(
+            tests[0].opponent,
+            Some(Opponent {
+                start: (300, 50),
+                path: vec![(300, 300), (50, 300)],
+                fire: vec![5, 40],
+            })
+        );
+        let still = parse_tests("test \"t\" { opponent at 1 2 }").unwrap();
+        assert_eq!(still[0].opponent.as_ref().unwrap().path, vec![]);
+        let err = parse_tests("test \"t\" { opponent at 1 2; opponent at 3 4 }").unwrap_err();
+        assert!(matches!(
+            err,
+            ScriptError::Parse {
+                source: ParseError::SecondOpponent,
+                ..
+            }
+        ));
+        assert!(parse_tests("test \"t\" { opponent 1 2 }").is_err());
+        assert!(parse_tests("test \"t\" { opponent at 1 2 path { 3 } }").is_err());
+        assert!(parse_tests("test \"t\" { opponent at 1 2 fire { -3 } }").is_err());
+    }
+
+    #[test]
+    fn test_opponent_drives_and_fires() {
+        let script = r#"
+            loop { scan }
+            test "is shot" {
+                opponent at 100 150 fire { 0 }
+                run 100
+                assert health < 10
+            }
+            test "patrols" {
+                opponent at 300 120 path { 300 100 }
+                run 300
+                assert opponent_x == 300
+                assert opponent_y == 101
+            }
+            test "no shots, no damage" {
+                opponent at 300 50
+                run 100
+                assert opponent_health == 10
+            }
+        "#;
+        let results = run_tests("sitter", script, &GameConfig::default()).unwrap();
+        assert!(results.iter().all(TestResult::passed), "{:?}", results);
+    }
+
+    #[test]
+    fn test_each_test_starts_fresh() {
+        let script = r#"
+            move forward 10
+            test "first" { run 20; assert x > 100 }
+            test "second" { assert x == 100 }
+        "#;
+        let results = run_tests("mover", script, &GameConfig::default()).unwrap();
+        assert!(results.iter().all(TestResult::passed), "{:?}", results);
+    }
+}
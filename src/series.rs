@@ -0,0 +1,292 @@
+// Best-of-N series between the same entrants.
+// Each robot keeps a small key-value memory (`persist store` / `persist load`)
+// from one round to the next, so a bot can learn its opponent's tendencies.
+// The runner saves the memories after every round and restores them into the
+// fresh robots of the next; memories never carry over to a different lineup.
+// The series also builds a model of each robot's play from its `Tendencies`
+// and hands it to the opponents as read-only registers at the start of a round.
+
+use std::collections::BTreeMap;
+
+use crate::ast::{Robot, normalize_degrees};
+use crate::math::MathMode;
+use crate::simulation::Simulation;
+
+/// Values a robot has stored with `persist store`, by register name.
+pub type Memory = BTreeMap<String, i32>;
+
+/// How a robot has played, summed over the ticks it was alive.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tendencies {
+    /// Ticks observed.
+    pub ticks: u64,
+    /// Sum of the distances to the nearest enemy, and the ticks it was measured on.
+    pub distance_sum: f64,
+    pub distance_samples: u64,
+    /// Sum of the robot's movements.
+    pub travel: (f64, f64),
+    /// Shots fired, of any weapon.
+    pub shots: u32,
+    /// Rounds the tendencies were collected over.
+    pub rounds: u32,
+    last_position: Option<(f32, f32)>,
+}
+
+impl Tendencies {
+    /// Record one tick in which the robot was at `position`, with its nearest
+    /// enemy `nearest_enemy` away (`None` without enemies).
+    pub fn observe(&mut self, position: (f32, f32), nearest_enemy: Option<f32>) {
+        self.ticks += 1;
+        if let Some(distance) = nearest_enemy {
+            self.distance_sum += f64::from(distance);
+            self.distance_samples += 1;
+        }
+        if let Some(last) = self.last_position {
+            self.travel.0 += f64::from(position.0 - last.0);
+            self.travel.1 += f64::from(position.1 - last.1);
+        }
+        self.last_position = Some(position);
+    }
+
+    /// Add the tendencies of another round or another robot.
+    pub fn merge(&mut self, other: &Tendencies) {
+        self.ticks += other.ticks;
+        self.distance_sum += other.distance_sum;
+        self.distance_samples += other.distance_samples;
+        self.travel.0 += other.travel.0;
+        self.travel.1 += other.travel.1;
+        self.shots += other.shots;
+        self.rounds = self.rounds.max(other.rounds);
+    }
+
+    /// Average distance kept from the nearest enemy, rounded; 0 if never measured.
+    pub fn average_distance(&self) -> i32 {
+        if self.distance_samples == 0 {
+            return 0;
+        }
+        (self.distance_sum / self.distance_samples as f64).round() as i32
+    }
+
+    /// Shots fired per 1000 ticks.
+    pub fn fire_rate(&self) -> i32 {
+        if self.ticks == 0 {
+            return 0;
+        }
+        (f64::from(self.shots) * 1000.0 / self.ticks as f64).round() as i32
+    }
+
+    /// Bearing in whole degrees of the robot's overall movement; 0 if it stayed put.
+    pub fn direction(&self, math: MathMode) -> i32 {
+        let (dx, dy) = self.travel;
+        if dx.abs() < f64::EPSILON && dy.abs() < f64::EPSILON {
+            return 0;
+        }
+        (normalize_degrees(math.atan2_f64(dy, dx).to_degrees() as f32).round() as i32)
+            .rem_euclid(360)
+    }
+}
+
+/// Memories and round wins of a series in progress.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Series {
+    /// Rounds in the series; the first robot to win a majority takes it.
+    pub rounds: u32,
+    /// Rounds played so far.
+    pub played: u32,
+    /// Robot names in spawn order, identifying who the memories belong to.
+    lineup: Vec<String>,
+    /// Memory of each robot, in spawn order.
+    memories: Vec<Memory>,
+    /// Tendencies of each robot over the rounds so far, in spawn order.
+    models: Vec<Tendencies>,
+    /// Rounds won by each robot, in spawn order.
+    pub wins: Vec<u32>,
+}
+
+impl Series {
+    /// A best-of-`rounds` series with nothing remembered yet.
+    pub fn new(rounds: u32) -> Self {
+        Series {
+            rounds,
+            ..Series::default()
+        }
+    }
+
+    /// Give the robots of a new round what they remembered at the end of the
+    /// last one, and the model of their opponents (see `ast::SERIES_REGISTERS`).
+    /// A different lineup starts the series over, so a bot never sees what it
+    /// learned about another opponent.
+    pub fn start_round(&mut self, robots: &mut [Robot], math: MathMode) {
+        let lineup: Vec<String> = robots.iter().map(|r| r.name.clone()).collect();
+        if lineup != self.lineup {
+            self.memories = vec![Memory::new(); robots.len()];
+            self.models = vec![Tendencies::default(); robots.len()];
+            self.wins = vec![0; robots.len()];
+            self.played = 0;
+            self.lineup = lineup;
+        }
+        let teams: Vec<usize> = robots.iter().map(|r| r.team).collect();
+        for (i, robot) in robots.iter_mut().enumerate() {
+            robot.memory = self.memories[i].clone();
+            let mut opponents = Tendencies::default();
+            for (model, _) in self
+                .models
+                .iter()
+                .zip(&teams)
+                .filter(|(_, t)| **t != teams[i])
+            {
+                opponents.merge(model);
+            }
+            let registers = [
+                ("opp_distance", opponents.average_distance()),
+                ("opp_fire_rate", opponents.fire_rate()),
+                ("opp_direction", opponents.direction(math)),
+                ("opp_rounds", opponents.rounds as i32),
+            ];
+            for (name, value) in registers {
+                robot.registers.set(name, value);
+            }
+        }
+    }
+
+    /// Save the robots' memories and count the winner of a finished round.
+    /// `winner` is the id of the winning robot, if the round had one.
+    pub fn finish_round(&mut self, sim: &Simulation, winner: Option<usize>) {
+        for ((memory, model), robot) in self
+            .memories
+            .iter_mut()
+            .zip(&mut self.models)
+            .zip(&sim.robots)
+        {
+            *memory = robot.memory.clone();
+            model.merge(&Tendencies {
+                shots: robot.shots_fired,
+                rounds: self.played + 1,
+                ..robot.tendencies
+            });
+        }
+        if let Some(index) = winner.and_then(|id| sim.robots.iter().position(|r| r.id == id))
+            && let Some(wins) = self.wins.get_mut(index)
+        {
+            *wins += 1;
+        }
+        self.played += 1;
+    }
+
+    /// Index (in spawn order) of the robot that has won a majority of the rounds.
+    pub fn winner(&self) -> Option<usize> {
+        self.wins.iter().position(|w| *w > self.rounds / 2)
+    }
+
+    /// Whether the series is over: someone has a majority or every round was played.
+    pub fn is_decided(&self) -> bool {
+        self.winner().is_some() || self.played >= self.rounds
+    }
+
+    /// Memory of the robot at `index` in spawn order.
+    pub fn memory(&self, index: usize) -> Option<&Memory> {
+        self.memories.get(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Instruction;
+    use crate::config::GameConfig;
+    use crate::registers::Reg;
+
+    fn round(names: [&str; 2], program: Vec<Instruction>) -> Vec<Robot> {
+        vec![
+            Robot::new(1, names[0], (50.0, 50.0), program),
+            Robot::new(2, names[1], (300.0, 300.0), vec![]),
+        ]
+    }
+
+    #[test]
+    fn test_memory_carries_over_between_rounds() {
+        let program = vec![
+            Instruction::PersistLoad {
+                reg: Reg::named("seen"),
+            },
+            Instruction::Math {
+                op: crate::ast::MathOp::Add,
+                dst: Reg::named("seen"),
+                args: vec![
+                    crate::ast::Operand::Reg(Reg::named("seen")),
+                    crate::ast::Operand::Const(1),
+                ],
+            },
+            Instruction::PersistStore {
+                reg: Reg::named("seen"),
+            },
+        ];
+        let mut series = Series::new(3);
+        for _ in 0..2 {
+            let mut robots = round(["learner", "target"], program.clone());
+            series.start_round(&mut robots, MathMode::Native);
+            let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+            for _ in 0..3 {
+                sim.step();
+            }
+            series.finish_round(&sim, Some(1));
+        }
+        assert_eq!(series.memory(0).unwrap()["seen"], 2);
+        assert_eq!(series.wins, vec![2, 0]);
+        assert_eq!(series.winner(), Some(0));
+        assert!(series.is_decided());
+
+        // A new opponent starts from a clean slate
+        let mut robots = round(["learner", "newcomer"], vec![]);
+        series.start_round(&mut robots, MathMode::Native);
+        assert!(robots[0].memory.is_empty());
+        assert_eq!(series.wins, vec![0, 0]);
+        assert!(!series.is_decided());
+    }
+
+    #[test]
+    fn test_series_without_majority_ends_after_all_rounds() {
+        let mut series = Series::new(2);
+        let mut robots = round(["alpha", "beta"], vec![]);
+        series.start_round(&mut robots, MathMode::Native);
+        let sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        series.finish_round(&sim, Some(1));
+        assert!(!series.is_decided());
+        series.finish_round(&sim, Some(2));
+        assert_eq!(series.winner(), None);
+        assert!(series.is_decided());
+    }
+
+    #[test]
+    fn test_opponent_model_registers() {
+        let program = vec![
+            Instruction::Fire,
+            Instruction::MoveForward,
+            Instruction::MoveForward,
+            Instruction::Fire,
+        ];
+        let mut series = Series::new(3);
+        let mut robots = round(["gunner", "target"], program.clone());
+        series.start_round(&mut robots, MathMode::Native);
+        assert_eq!(robots[1].registers["opp_rounds"], 0);
+        assert_eq!(robots[1].registers["opp_fire_rate"], 0);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        for _ in 0..4 {
+            sim.step();
+        }
+        series.finish_round(&sim, None);
+
+        let mut robots = round(["gunner", "target"], program);
+        series.start_round(&mut robots, MathMode::Native);
+        let target = &robots[1].registers;
+        assert_eq!(target["opp_rounds"], 1);
+        // Two shots in four ticks
+        assert_eq!(target["opp_fire_rate"], 500);
+        // The gunner drove along +x
+        assert_eq!(target["opp_direction"], 0);
+        // About 353.5 apart at the start, a little closer after the moves
+        assert_eq!(target["opp_distance"], 353);
+        // The target never moved or fired
+        assert_eq!(robots[0].registers["opp_fire_rate"], 0);
+    }
+}
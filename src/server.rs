@@ -0,0 +1,534 @@
+// HTTP match server for web front-ends and chat bots.
+// Clients submit bot scripts, start matches between them and poll for the
+// outcome; every match runs headlessly on a thread of its own, up to a limit
+// of matches at once. Bots, results and replays are kept in memory for as long
+// as the server runs.
+//
+//   POST /bots                  {"name": …, "script": …}         -> {"id": …}
+//   POST /matches               {"bots": [id, …], "mode": …,
+//                                "max_ticks": …}                 -> {"id": …, "status": "running"}
+//   GET  /matches/{id}          status and current tick, with the result once finished
+//   GET  /matches/{id}/result   the `MatchResult` of a finished match
+//   GET  /matches/{id}/replay   the `Replay` of a finished match
+
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config::GameConfig;
+use crate::modes::GameMode;
+use crate::replay::Replay;
+use crate::results::{MatchResult, RunLimits};
+use crate::setup::{BotScript, SPAWN_POINTS, compile_robots, default_obstacles};
+use crate::simulation::Simulation;
+
+/// Address the server listens on unless `--addr` says otherwise.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+/// Largest request body the server reads, in bytes; scripts are far smaller.
+pub const MAX_BODY: u64 = 1 << 20;
+/// Matches run at once unless `MatchServer::with_max_running` says otherwise;
+/// more are refused with 503 until one finishes.
+pub const DEFAULT_MAX_RUNNING: usize = 8;
+/// How often, in ticks, a running match reports its progress.
+const PROGRESS_INTERVAL: u64 = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BotSubmission {
+    name: String,
+    script: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MatchRequest {
+    /// Ids of submitted bots, in spawn order.
+    bots: Vec<usize>,
+    /// Command-line name of the game mode; deathmatch if left out.
+    #[serde(default)]
+    mode: Option<String>,
+    /// Stop the match after this many ticks; at most the server's limit.
+    #[serde(default)]
+    max_ticks: Option<u64>,
+}
+
+/// Where a match is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchStatus {
+    Running,
+    Finished,
+    /// The match stopped on an internal error; it has no result.
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+struct MatchEntry {
+    status: MatchStatus,
+    tick: u64,
+    result: Option<MatchResult>,
+    replay: Option<Replay>,
+}
+
+/// An HTTP response: status code and JSON body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, value: impl Serialize) -> Self {
+        Response {
+            status,
+            body: serde_json::to_string(&value).expect("response serializes"),
+        }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Response::json(status, json!({ "error": message.to_string() }))
+    }
+}
+
+/// Submitted bots and matches, shared between request handlers and match threads.
+pub struct MatchServer {
+    config: GameConfig,
+    /// Longest a match may run, in ticks.
+    max_ticks: u64,
+    bots: Mutex<Vec<BotScript>>,
+    matches: Arc<Mutex<Vec<MatchEntry>>>,
+    running: Arc<Slots>,
+}
+
+/// A count of matches running, up to a limit.
+#[derive(Debug)]
+struct Slots {
+    taken: AtomicUsize,
+    limit: usize,
+}
+
+impl Slots {
+    fn new(limit: usize) -> Self {
+        Slots {
+            taken: AtomicUsize::new(0),
+            limit,
+        }
+    }
+
+    /// Take a slot, unless all are taken. The slot is given back when the
+    /// returned guard is dropped.
+    fn take(self: &Arc<Self>) -> Option<Slot> {
+        self.taken
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |taken| {
+                (taken < self.limit).then_some(taken + 1)
+            })
+            .ok()?;
+        Some(Slot(Arc::clone(self)))
+    }
+}
+
+/// A running match's slot, given back on drop, also when the match panics.
+#[derive(Debug)]
+struct Slot(Arc<Slots>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.taken.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl MatchServer {
+    /// A server playing matches with `config`, each stopped after at most
+    /// `max_ticks` ticks.
+    pub fn new(config: GameConfig, max_ticks: u64) -> Self {
+        MatchServer {
+            config,
+            max_ticks,
+            bots: Mutex::new(Vec::new()),
+            matches: Arc::new(Mutex::new(Vec::new())),
+            running: Arc::new(Slots::new(DEFAULT_MAX_RUNNING)),
+        }
+    }
+
+    /// Run at most `max_running` matches at once, at least one.
+    pub fn with_max_running(mut self, max_running: usize) -> Self {
+        self.running = Arc::new(Slots::new(max_running.max(1)));
+        self
+    }
+
+    /// Answer one request. `url` may carry a query string, which is ignored.
+    pub fn handle(&self, method: &str, url: &str, body: &str) -> Response {
+        let path = url.split('?').next().unwrap_or_default();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (method, segments.as_slice()) {
+            ("POST", ["bots"]) => self.submit_bot(body),
+            ("POST", ["matches"]) => self.start_match(body),
+            ("GET", ["matches", id]) => self.with_match(id, |id, entry| {
+                Response::json(
+                    200,
+                    json!({
+                        "id": id,
+                        "status": entry.status,
+                        "tick": entry.tick,
+                        "result": entry.result,
+                    }),
+                )
+            }),
+            ("GET", ["matches", id, "result"]) => {
+                self.with_match(id, |_, entry| match &entry.result {
+                    Some(result) => Response::json(200, result),
+                    None => unfinished(entry),
+                })
+            }
+            ("GET", ["matches", id, "replay"]) => {
+                self.with_match(id, |_, entry| match &entry.replay {
+                    Some(replay) => Response::json(200, replay),
+                    None => unfinished(entry),
+                })
+            }
+            (_, ["bots"] | ["matches"] | ["matches", _] | ["matches", _, "result" | "replay"]) => {
+                Response::error(405, format!("{} is not allowed on {}", method, path))
+            }
+            _ => Response::error(404, format!("no such endpoint: {}", path)),
+        }
+    }
+
+    /// Compile a submitted bot within the sandbox limits and keep it.
+    fn submit_bot(&self, body: &str) -> Response {
+        let submission: BotSubmission = match serde_json::from_str(body) {
+            Ok(submission) => submission,
+            Err(e) => return Response::error(400, e),
+        };
+        let bot = BotScript {
+            entrant: submission.name.clone(),
+            name: submission.name,
+            script: submission.script,
+        };
+        if let Err(errors) = compile_robots(std::slice::from_ref(&bot), &self.config) {
+            let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            return Response::error(400, messages.join("\n"));
+        }
+        let mut bots = self.bots.lock().unwrap_or_else(PoisonError::into_inner);
+        bots.push(bot);
+        Response::json(201, json!({ "id": bots.len() }))
+    }
+
+    /// Start a match between submitted bots on a new thread, unless as many
+    /// matches as allowed are running already.
+    fn start_match(&self, body: &str) -> Response {
+        let request: MatchRequest = match serde_json::from_str(body) {
+            Ok(request) => request,
+            Err(e) => return Response::error(400, e),
+        };
+        let mode = match request.mode.as_deref() {
+            None => GameMode::default(),
+            Some(name) => match GameMode::from_name(name) {
+                Some(mode) => mode,
+                None => return Response::error(400, format!("unknown game mode: {}", name)),
+            },
+        };
+        let max = mode.max_entrants().unwrap_or(SPAWN_POINTS.len());
+        let min = max.min(2);
+        if request.bots.len() < min || request.bots.len() > max {
+            return Response::error(
+                400,
+                format!("{} takes {} to {} bots", mode.name(), min, max),
+            );
+        }
+        let bots = {
+            let submitted = self.bots.lock().unwrap_or_else(PoisonError::into_inner);
+            let picked: Option<Vec<BotScript>> = request
+                .bots
+                .iter()
+                .map(|id| id.checked_sub(1).and_then(|i| submitted.get(i)).cloned())
+                .collect();
+            match picked {
+                Some(bots) => bots,
+                None => return Response::error(400, "unknown bot id"),
+            }
+        };
+        let robots = match compile_robots(&bots, &self.config) {
+            Ok(robots) => robots,
+            Err(errors) => {
+                let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+                return Response::error(400, messages.join("\n"));
+            }
+        };
+
+        let Some(slot) = self.running.take() else {
+            return Response::error(503, "too many matches are running; try again later");
+        };
+        let id = {
+            let mut matches = self.matches.lock().unwrap_or_else(PoisonError::into_inner);
+            matches.push(MatchEntry {
+                status: MatchStatus::Running,
+                tick: 0,
+                result: None,
+                replay: None,
+            });
+            matches.len()
+        };
+        let sim = Simulation::new(self.config.clone(), robots, default_obstacles()).with_mode(mode);
+        let limits = RunLimits {
+            max_ticks: Some(
+                request
+                    .max_ticks
+                    .unwrap_or(self.max_ticks)
+                    .min(self.max_ticks),
+            ),
+            timeout: None,
+        };
+        spawn_match(Arc::clone(&self.matches), id - 1, slot, move |matches| {
+            run_match(sim, bots, limits, matches, id - 1)
+        });
+        Response::json(202, json!({ "id": id, "status": MatchStatus::Running }))
+    }
+
+    /// Look up a match by its id in the URL and answer with `respond`.
+    fn with_match(&self, id: &str, respond: impl Fn(usize, &MatchEntry) -> Response) -> Response {
+        let matches = self.matches.lock().unwrap_or_else(PoisonError::into_inner);
+        let entry = id
+            .parse::<usize>()
+            .ok()
+            .and_then(|id| Some((id, matches.get(id.checked_sub(1)?)?)));
+        match entry {
+            Some((id, entry)) => respond(id, entry),
+            None => Response::error(404, format!("no such match: {}", id)),
+        }
+    }
+}
+
+/// The answer for the result or replay of a match that has none.
+fn unfinished(entry: &MatchEntry) -> Response {
+    match entry.status {
+        MatchStatus::Failed => Response::error(409, "the match failed"),
+        _ => Response::error(409, "the match is still running"),
+    }
+}
+
+/// Run `play` on a thread of its own, holding `slot` until it is done. If it
+/// panics, `matches[index]` is marked failed rather than left running.
+fn spawn_match(
+    matches: Arc<Mutex<Vec<MatchEntry>>>,
+    index: usize,
+    slot: Slot,
+    play: impl FnOnce(&Mutex<Vec<MatchEntry>>) + Send + 'static,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let _slot = slot;
+        if panic::catch_unwind(AssertUnwindSafe(|| play(&matches))).is_err() {
+            let mut matches = matches.lock().unwrap_or_else(PoisonError::into_inner);
+            matches[index].status = MatchStatus::Failed;
+        }
+    })
+}
+
+/// Play a match to the end or its tick limit, reporting progress in
+/// `matches[index]` and storing the result and replay there when done.
+fn run_match(
+    mut sim: Simulation,
+    bots: Vec<BotScript>,
+    limits: RunLimits,
+    matches: &Mutex<Vec<MatchEntry>>,
+    index: usize,
+) {
+    let started = Instant::now();
+    let mut stopped = None;
+    while !sim.is_over() {
+        stopped = limits.reached(&sim, started);
+        if stopped.is_some() {
+            break;
+        }
+        sim.step();
+        if sim.tick.is_multiple_of(PROGRESS_INTERVAL) {
+            matches.lock().unwrap_or_else(PoisonError::into_inner)[index].tick = sim.tick;
+        }
+    }
+    let result = MatchResult::from_sim(&sim, stopped, started.elapsed());
+    let replay = Replay::record(&sim, bots, default_obstacles());
+    let mut matches = matches.lock().unwrap_or_else(PoisonError::into_inner);
+    matches[index] = MatchEntry {
+        status: MatchStatus::Finished,
+        tick: sim.tick,
+        result: Some(result),
+        replay: Some(replay),
+    };
+}
+
+/// Serve `server` over HTTP on `addr` until the process is stopped. Requests
+/// are answered one at a time; matches run on their own threads, so a long
+/// match never holds up a request.
+pub fn serve(addr: &str, server: &MatchServer) -> io::Result<()> {
+    let http = tiny_http::Server::http(addr).map_err(io::Error::other)?;
+    let content_type = tiny_http::Header::from_bytes("Content-Type", "application/json")
+        .expect("static header is valid");
+    for mut request in http.incoming_requests() {
+        let declared = request.body_length();
+        let response = match read_body(request.as_reader(), declared) {
+            Ok(body) => server.handle(request.method().as_str(), request.url(), &body),
+            Err(response) => response,
+        };
+        let reply = tiny_http::Response::from_string(response.body)
+            .with_status_code(response.status)
+            .with_header(content_type.clone());
+        // The client may have hung up; the next request is unaffected
+        let _ = request.respond(reply);
+    }
+    Ok(())
+}
+
+/// Read a request body of at most `MAX_BODY` bytes, answering 413 for a
+/// larger one without reading past the limit. `declared` is the length the
+/// client gave in `Content-Length`, if any.
+fn read_body(reader: impl Read, declared: Option<usize>) -> Result<String, Response> {
+    let too_large = || Response::error(413, format!("bodies are limited to {} bytes", MAX_BODY));
+    if declared.is_some_and(|length| length as u64 > MAX_BODY) {
+        return Err(too_large());
+    }
+    let mut body = String::new();
+    match reader.take(MAX_BODY + 1).read_to_string(&mut body) {
+        Ok(read) if read as u64 > MAX_BODY => Err(too_large()),
+        Ok(_) => Ok(body),
+        Err(e) => Err(Response::error(400, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn submit(server: &MatchServer, name: &str, script: &str) -> Response {
+        let body = json!({ "name": name, "script": script }).to_string();
+        server.handle("POST", "/bots", &body)
+    }
+
+    fn body(response: &Response) -> serde_json::Value {
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn test_match_lifecycle() {
+        let server = MatchServer::new(GameConfig::default(), 1000);
+        assert_eq!(
+            submit(&server, "spinner", "loop {\n rotate body 5\n}").status,
+            201
+        );
+        let second = submit(&server, "scanner", "loop {\n scan\n}");
+        assert_eq!(body(&second)["id"], 2);
+
+        let started = server.handle("POST", "/matches", r#"{"bots": [1, 2], "max_ticks": 50}"#);
+        assert_eq!(started.status, 202);
+        assert_eq!(body(&started)["id"], 1);
+
+        let mut status = server.handle("GET", "/matches/1", "");
+        for _ in 0..500 {
+            if body(&status)["status"] == "finished" {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+            status = server.handle("GET", "/matches/1?poll=1", "");
+        }
+        assert_eq!(body(&status)["status"], "finished");
+        assert_eq!(body(&status)["tick"], 50);
+
+        let result = server.handle("GET", "/matches/1/result", "");
+        assert_eq!(result.status, 200);
+        assert_eq!(body(&result)["end_condition"], "tick_limit");
+        let replay = server.handle("GET", "/matches/1/replay", "");
+        let replay: Replay = serde_json::from_str(&replay.body).unwrap();
+        assert_eq!(replay.bots[0].name, "spinner");
+        assert_eq!(replay.ticks, 50);
+    }
+
+    #[test]
+    fn test_bad_requests() {
+        let server = MatchServer::new(GameConfig::default(), 1000);
+        let rejected = submit(&server, "broken", "rotate sideways 90");
+        assert_eq!(rejected.status, 400);
+        assert!(
+            body(&rejected)["error"]
+                .as_str()
+                .unwrap()
+                .contains("sideways")
+        );
+        assert_eq!(server.handle("POST", "/bots", "not json").status, 400);
+
+        submit(&server, "idle", "scan");
+        let unknown = server.handle("POST", "/matches", r#"{"bots": [1, 7]}"#);
+        assert_eq!(unknown.status, 400);
+        let alone = server.handle("POST", "/matches", r#"{"bots": [1]}"#);
+        assert_eq!(alone.status, 400);
+        let mode = server.handle("POST", "/matches", r#"{"bots": [1, 1], "mode": "golf"}"#);
+        assert_eq!(mode.status, 400);
+
+        assert_eq!(server.handle("GET", "/matches/3", "").status, 404);
+        assert_eq!(server.handle("GET", "/matches/zero/result", "").status, 404);
+        assert_eq!(server.handle("DELETE", "/matches/1", "").status, 405);
+        assert_eq!(server.handle("GET", "/teapot", "").status, 404);
+    }
+
+    #[test]
+    fn test_large_bodies_are_refused() {
+        let limit = MAX_BODY as usize;
+        assert_eq!(read_body("{}".as_bytes(), Some(2)).unwrap(), "{}");
+        // Turned away on the declared length alone
+        let declared = read_body(io::empty(), Some(limit + 1)).unwrap_err();
+        assert_eq!(declared.status, 413);
+        // Or once more than the limit arrives, whatever the client declared
+        let endless = io::repeat(b' ');
+        assert_eq!(read_body(endless, None).unwrap_err().status, 413);
+        let exact = vec![b' '; limit];
+        assert_eq!(
+            read_body(exact.as_slice(), Some(limit)).unwrap().len(),
+            limit
+        );
+    }
+
+    #[test]
+    fn test_running_matches_are_limited() {
+        let slots = Arc::new(Slots::new(2));
+        let first = slots.take().unwrap();
+        let _second = slots.take().unwrap();
+        assert!(slots.take().is_none());
+        drop(first);
+        assert!(slots.take().is_some());
+
+        let server = MatchServer::new(GameConfig::default(), 1000).with_max_running(1);
+        submit(&server, "idle", "scan");
+        let _busy = server.running.take().unwrap();
+        let refused = server.handle("POST", "/matches", r#"{"bots": [1, 1]}"#);
+        assert_eq!(refused.status, 503);
+        // A refused match is not listed
+        assert_eq!(server.handle("GET", "/matches/1", "").status, 404);
+    }
+
+    #[test]
+    fn test_panicking_match_fails() {
+        let server = MatchServer::new(GameConfig::default(), 1000);
+        server.matches.lock().unwrap().push(MatchEntry {
+            status: MatchStatus::Running,
+            tick: 0,
+            result: None,
+            replay: None,
+        });
+        let slot = server.running.take().unwrap();
+        // Panicking while holding the lock poisons it
+        let playing = spawn_match(Arc::clone(&server.matches), 0, slot, |matches| {
+            let _held = matches.lock().unwrap();
+            panic!("the match broke");
+        });
+        assert!(playing.join().is_ok());
+
+        let status = server.handle("GET", "/matches/1", "");
+        assert_eq!(body(&status)["status"], "failed");
+        assert_eq!(server.handle("GET", "/matches/1/result", "").status, 409);
+        assert_eq!(server.running.taken.load(Ordering::Acquire), 0);
+    }
+}
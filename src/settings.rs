@@ -0,0 +1,225 @@
+// Window settings, read from `settings.toml` in the config directory or passed
+// with `--settings`: the palette robots are colored from, the size of the HUD
+// text, a high-contrast mode, the language of the window's text and how the
+// view starts out. Settings left out keep their defaults; the window's
+// settings screen writes the file back.
+//
+//     palette = "okabe_ito"
+//     hud_scale = 1.5
+//     high_contrast = true
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::locale;
+
+/// Smallest and largest HUD text scale.
+pub const MIN_HUD_SCALE: f32 = 0.5;
+pub const MAX_HUD_SCALE: f32 = 3.0;
+/// Simulation speeds the window offers, in ticks per frame.
+pub const SPEEDS: [u32; 5] = [1, 2, 4, 8, 16];
+
+/// A window settings file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Colors robots are drawn in, in entry order.
+    pub palette: Palette,
+    /// Colors as `#rrggbb`, used instead of the palette's when given.
+    pub colors: Vec<String>,
+    /// How much larger the HUD panels and text are drawn.
+    pub hud_scale: f32,
+    /// Outline robots, obstacles and panels in white and draw text brighter.
+    pub high_contrast: bool,
+    /// Language of the window's text, e.g. `de`; from `LANG` when left out.
+    pub locale: Option<String>,
+    /// Ticks per frame the window starts at, one of `SPEEDS`.
+    pub speed: u32,
+    /// Start with the debug overlay shown.
+    pub debug_overlay: bool,
+    /// Start with the observer overlay shown.
+    pub observer_overlay: bool,
+    /// Start with the performance HUD shown.
+    pub performance_overlay: bool,
+}
+
+/// A preset list of robot colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    /// Red, blue, then green for every other robot.
+    Classic,
+    /// The eight colors of Okabe and Ito, told apart with any common form of
+    /// color blindness.
+    OkabeIto,
+    /// Paul Tol's bright scheme, also safe for color-blind viewers.
+    Tol,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            palette: Palette::Classic,
+            colors: Vec::new(),
+            hud_scale: 1.0,
+            high_contrast: false,
+            locale: None,
+            speed: 1,
+            debug_overlay: false,
+            observer_overlay: false,
+            performance_overlay: false,
+        }
+    }
+}
+
+impl Palette {
+    /// Every palette, in the order the settings screen cycles through them.
+    pub const ALL: [Palette; 3] = [Palette::Classic, Palette::OkabeIto, Palette::Tol];
+
+    pub fn colors(self) -> &'static [[u8; 3]] {
+        match self {
+            Palette::Classic => &[[230, 41, 55], [0, 121, 241], [0, 228, 48]],
+            Palette::OkabeIto => &[
+                [230, 159, 0],
+                [86, 180, 233],
+                [0, 158, 115],
+                [240, 228, 66],
+                [0, 114, 178],
+                [213, 94, 0],
+                [204, 121, 167],
+                [255, 255, 255],
+            ],
+            Palette::Tol => &[
+                [68, 119, 170],
+                [238, 102, 119],
+                [34, 136, 51],
+                [204, 187, 68],
+                [102, 204, 238],
+                [170, 51, 119],
+                [187, 187, 187],
+            ],
+        }
+    }
+}
+
+impl Settings {
+    /// Load settings from a TOML file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Settings::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Write the settings file, creating its directory if needed.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parse settings, rejecting colors that are not `#rrggbb`, HUD scales
+    /// out of range, speeds the window does not offer and locales without a
+    /// catalog.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let settings: Settings = toml::from_str(text).map_err(|e| e.to_string())?;
+        if let Some(bad) = settings.colors.iter().find(|c| parse_color(c).is_none()) {
+            return Err(format!("color {:?} is not of the form #rrggbb", bad));
+        }
+        if !(MIN_HUD_SCALE..=MAX_HUD_SCALE).contains(&settings.hud_scale) {
+            return Err(format!(
+                "hud_scale must be from {} to {}",
+                MIN_HUD_SCALE, MAX_HUD_SCALE
+            ));
+        }
+        if !SPEEDS.contains(&settings.speed) {
+            return Err(format!("speed must be one of {:?}", SPEEDS));
+        }
+        if let Some(locale) = &settings.locale
+            && !locale::locales().any(|l| l == locale)
+        {
+            let known: Vec<&str> = locale::locales().collect();
+            return Err(format!(
+                "no messages for locale {:?} (known: {})",
+                locale,
+                known.join(", ")
+            ));
+        }
+        Ok(settings)
+    }
+
+    /// The color of robot number `i`, in entry order. Past the end of the
+    /// palette the colors come round again; the classic palette keeps its
+    /// last color for the rest.
+    pub fn robot_color(&self, i: usize) -> [u8; 3] {
+        if !self.colors.is_empty() {
+            return parse_color(&self.colors[i % self.colors.len()]).unwrap_or([255; 3]);
+        }
+        let colors = self.palette.colors();
+        match self.palette {
+            Palette::Classic => colors[i.min(colors.len() - 1)],
+            _ => colors[i % colors.len()],
+        }
+    }
+}
+
+/// A color written `#rrggbb`.
+fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let hex = text.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |k: usize| u8::from_str_radix(hex.get(k..k + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palettes_color_every_robot() {
+        let classic = Settings::default();
+        assert_eq!(classic.robot_color(1), [0, 121, 241]);
+        assert_eq!(classic.robot_color(5), classic.robot_color(2));
+
+        let settings = Settings::parse("palette = \"okabe_ito\"\nhigh_contrast = true").unwrap();
+        assert_eq!(settings.robot_color(0), [230, 159, 0]);
+        assert_eq!(settings.robot_color(8), settings.robot_color(0));
+        assert_ne!(settings.robot_color(2), settings.robot_color(3));
+        assert!(settings.high_contrast);
+
+        let custom = Settings::parse("colors = [\"#ff8000\", \"#0080FF\"]").unwrap();
+        assert_eq!(custom.robot_color(3), [0, 128, 255]);
+
+        assert!(Settings::parse("colors = [\"orange\"]").is_err());
+        assert!(Settings::parse("hud_scale = 10").is_err());
+        assert!(Settings::parse("palette = \"sepia\"").is_err());
+        assert!(Settings::parse("locale = \"de\"").is_ok());
+        assert!(Settings::parse("locale = \"xx\"").is_err());
+        assert!(Settings::parse("speed = 3").is_err());
+    }
+
+    #[test]
+    fn test_shipped_settings_are_the_defaults() {
+        let settings = Settings::load(Path::new("config/settings.toml")).unwrap();
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn test_written_settings_read_back() {
+        let settings = Settings {
+            palette: Palette::Tol,
+            hud_scale: 1.25,
+            locale: Some("de".to_string()),
+            speed: 4,
+            observer_overlay: true,
+            ..Settings::default()
+        };
+        let path = std::env::temp_dir()
+            .join(format!("robot-battle-settings-{}", std::process::id()))
+            .join("settings.toml");
+        settings.write(&path).unwrap();
+        assert_eq!(Settings::load(&path).unwrap(), settings);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,393 @@
+//! In-game settings screen for the window frontend.
+//!
+//! Opened from the pause menu. It changes the window settings (starting speed,
+//! overlays, palette, HUD scale, high contrast and language) and the first
+//! player's keys, which take effect at once and are written back to the
+//! settings and bindings files when the screen is closed with the menu key
+//! (Esc). Up and Down pick a line, Left and Right change it, and Enter on a
+//! key binding waits for the key to bind it to.
+
+use std::path::PathBuf;
+
+use macroquad::prelude::*;
+
+use crate::bindings::{ACTIONS, Bindings, ViewerKeys, key_label, key_name, pressed};
+use crate::locale;
+use crate::menu::speed_index;
+use crate::settings::{MAX_HUD_SCALE, MIN_HUD_SCALE, Palette, SPEEDS, Settings};
+
+/// How much one step changes the HUD scale.
+const HUD_SCALE_STEP: f32 = 0.25;
+
+/// What the settings screen changes, and the files it saves them to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preferences {
+    pub settings: Settings,
+    pub settings_path: PathBuf,
+    /// The first player's keys, before any are given up to a second player.
+    pub controls: Bindings,
+    pub controls_path: PathBuf,
+    /// The keys that work the window, which players cannot take.
+    pub keys: ViewerKeys,
+}
+
+/// A line of the settings screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Entry {
+    Speed,
+    DebugOverlay,
+    ObserverOverlay,
+    PerformanceOverlay,
+    Palette,
+    HudScale,
+    HighContrast,
+    Language,
+    /// The keys of action number `n` in `ACTIONS`.
+    Key(usize),
+}
+
+/// The lines above the key bindings, in display order.
+const SETTINGS: [Entry; 8] = [
+    Entry::Speed,
+    Entry::DebugOverlay,
+    Entry::ObserverOverlay,
+    Entry::PerformanceOverlay,
+    Entry::Palette,
+    Entry::HudScale,
+    Entry::HighContrast,
+    Entry::Language,
+];
+
+/// Something the window has to act on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingsEvent {
+    Changed(Entry),
+    /// The screen was closed, saving any changes.
+    Closed(Result<(), String>),
+}
+
+/// State of the settings screen.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsScreen {
+    pub open: bool,
+    selected: usize,
+    /// The action waiting for its new key.
+    rebinding: Option<usize>,
+    /// Feedback from the last change, e.g. a key that cannot be bound.
+    status: Vec<String>,
+    settings_changed: bool,
+    controls_changed: bool,
+}
+
+fn entry(i: usize) -> Entry {
+    SETTINGS
+        .get(i)
+        .copied()
+        .unwrap_or(Entry::Key(i - SETTINGS.len()))
+}
+
+fn entry_count() -> usize {
+    SETTINGS.len() + ACTIONS.len()
+}
+
+impl SettingsScreen {
+    pub fn open(&mut self) {
+        self.open = true;
+        self.selected = 0;
+        self.rebinding = None;
+        self.status.clear();
+    }
+
+    /// Move the selection by `delta` lines, wrapping around.
+    pub fn move_selection(&mut self, delta: isize) {
+        let len = entry_count() as isize;
+        self.selected = (self.selected as isize + delta).rem_euclid(len) as usize;
+    }
+
+    pub fn selected(&self) -> Entry {
+        entry(self.selected)
+    }
+
+    /// Step the setting on line `entry` forward or, with a negative `step`,
+    /// back. Returns whether anything changed; key bindings are changed with
+    /// `bind` instead.
+    pub fn change(&mut self, prefs: &mut Preferences, entry: Entry, step: isize) -> bool {
+        let settings = &mut prefs.settings;
+        let cycle = |i: usize, len: usize| (i as isize + step).rem_euclid(len as isize) as usize;
+        match entry {
+            Entry::Speed => {
+                settings.speed = SPEEDS[cycle(speed_index(settings.speed), SPEEDS.len())];
+            }
+            Entry::DebugOverlay => settings.debug_overlay = !settings.debug_overlay,
+            Entry::ObserverOverlay => settings.observer_overlay = !settings.observer_overlay,
+            Entry::PerformanceOverlay => {
+                settings.performance_overlay = !settings.performance_overlay
+            }
+            Entry::Palette => {
+                let i = Palette::ALL.iter().position(|p| *p == settings.palette);
+                settings.palette = Palette::ALL[cycle(i.unwrap_or(0), Palette::ALL.len())];
+            }
+            Entry::HudScale => {
+                let scale = settings.hud_scale + HUD_SCALE_STEP * step as f32;
+                let scale = (scale / HUD_SCALE_STEP).round() * HUD_SCALE_STEP;
+                settings.hud_scale = scale.clamp(MIN_HUD_SCALE, MAX_HUD_SCALE);
+            }
+            Entry::HighContrast => settings.high_contrast = !settings.high_contrast,
+            Entry::Language => {
+                // Following `LANG` comes before the locales themselves
+                let mut choices = vec![None];
+                choices.extend(locale::locales().map(|l| Some(l.to_string())));
+                let i = choices.iter().position(|l| *l == settings.locale);
+                settings.locale = choices[cycle(i.unwrap_or(0), choices.len())].clone();
+            }
+            Entry::Key(_) => return false,
+        }
+        self.settings_changed = true;
+        true
+    }
+
+    /// Bind the action waiting for a key to `key` alone. Returns whether it
+    /// was bound; keys a bindings file has no name for, and keys the window
+    /// reads while the match runs, are refused.
+    pub fn bind(&mut self, prefs: &mut Preferences, key: KeyCode) -> bool {
+        let Some(action) = self.rebinding.take() else {
+            return false;
+        };
+        let name = key_name(key);
+        if let Some(taken) = name
+            .as_deref()
+            .and_then(|n| prefs.keys.used_while_playing(n))
+        {
+            let name = name.as_deref().unwrap_or_default();
+            let text = locale::format("settings.taken", &[("key", &name), ("action", &taken)]);
+            self.status = vec![text];
+            return false;
+        }
+        match name {
+            Some(name) => {
+                prefs.controls.bind(action, vec![name]);
+                self.controls_changed = true;
+                self.status.clear();
+                true
+            }
+            None => {
+                let text = locale::format("settings.unbindable", &[("key", &format!("{:?}", key))]);
+                self.status = vec![text];
+                false
+            }
+        }
+    }
+
+    /// Close the screen, writing the files whose settings changed.
+    pub fn close(&mut self, prefs: &Preferences) -> Result<(), String> {
+        self.open = false;
+        self.rebinding = None;
+        if self.settings_changed {
+            prefs.settings.write(&prefs.settings_path)?;
+            self.settings_changed = false;
+        }
+        if self.controls_changed {
+            prefs.controls.write(&prefs.controls_path)?;
+            self.controls_changed = false;
+        }
+        Ok(())
+    }
+
+    /// Read keyboard input for this frame and return what the window has to
+    /// act on, if anything.
+    pub fn handle_input(&mut self, prefs: &mut Preferences) -> Option<SettingsEvent> {
+        if let Some(action) = self.rebinding {
+            return match get_last_key_pressed() {
+                Some(_) if pressed(&prefs.keys.menu) => {
+                    self.rebinding = None;
+                    None
+                }
+                Some(key) if self.bind(prefs, key) => {
+                    Some(SettingsEvent::Changed(Entry::Key(action)))
+                }
+                _ => None,
+            };
+        }
+        if pressed(&prefs.keys.menu) {
+            return Some(SettingsEvent::Closed(self.close(prefs)));
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.move_selection(-1);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.move_selection(1);
+        }
+        let entry = self.selected();
+        let step = if is_key_pressed(KeyCode::Left) {
+            -1
+        } else if is_key_pressed(KeyCode::Right) || is_key_pressed(KeyCode::Enter) {
+            1
+        } else {
+            return None;
+        };
+        if let Entry::Key(action) = entry {
+            if is_key_pressed(KeyCode::Enter) {
+                self.rebinding = Some(action);
+            }
+            return None;
+        }
+        self.change(prefs, entry, step)
+            .then_some(SettingsEvent::Changed(entry))
+    }
+
+    /// The text of line `entry`, with its current value.
+    fn line(&self, prefs: &Preferences, entry: Entry) -> String {
+        let settings = &prefs.settings;
+        let toggle = |key: &str, on: bool| {
+            let state = if on { "menu.on" } else { "menu.off" };
+            locale::format(state, &[("label", &locale::text(key))])
+        };
+        let value = |key: &str, value: &dyn std::fmt::Display| {
+            locale::format(
+                "settings.value",
+                &[("label", &locale::text(key)), ("value", value)],
+            )
+        };
+        match entry {
+            Entry::Speed => value("settings.speed", &format!("{}x", settings.speed)),
+            Entry::DebugOverlay => toggle("menu.debug", settings.debug_overlay),
+            Entry::ObserverOverlay => toggle("menu.observer", settings.observer_overlay),
+            Entry::PerformanceOverlay => toggle("menu.performance", settings.performance_overlay),
+            Entry::Palette => {
+                let name = match settings.palette {
+                    Palette::Classic => "settings.palette_classic",
+                    Palette::OkabeIto => "settings.palette_okabe_ito",
+                    Palette::Tol => "settings.palette_tol",
+                };
+                value("settings.palette", &locale::text(name))
+            }
+            Entry::HudScale => value("settings.hud_scale", &format!("{:.2}", settings.hud_scale)),
+            Entry::HighContrast => toggle("settings.high_contrast", settings.high_contrast),
+            Entry::Language => match &settings.locale {
+                Some(name) => value("settings.language", name),
+                None => value("settings.language", &locale::text("settings.from_lang")),
+            },
+            Entry::Key(action) => {
+                let label = format!("controls.{}", ACTIONS[action]);
+                let keys = if self.rebinding == Some(action) {
+                    locale::text("settings.press_key").to_string()
+                } else if prefs.controls.keys(action).is_empty() {
+                    locale::text("settings.unbound").to_string()
+                } else {
+                    prefs.controls.keys(action).join(", ")
+                };
+                value(&label, &keys)
+            }
+        }
+    }
+
+    /// Draw the screen centered over the current frame.
+    pub fn draw(&self, prefs: &Preferences) {
+        let (screen_w, screen_h) = (screen_width(), screen_height());
+        draw_rectangle(0.0, 0.0, screen_w, screen_h, Color::new(0.0, 0.0, 0.0, 0.6));
+
+        let panel_w = 460.0;
+        let line_h = 28.0;
+        let lines = entry_count() as f32 + 3.0;
+        let panel_h = line_h * lines + 24.0 * self.status.len() as f32;
+        let x = (screen_w - panel_w) / 2.0;
+        let y = (screen_h - panel_h) / 2.0;
+        draw_rectangle(x, y, panel_w, panel_h, Color::new(0.1, 0.1, 0.1, 0.95));
+        draw_rectangle_lines(x, y, panel_w, panel_h, 2.0, LIGHTGRAY);
+        draw_text(
+            locale::text("settings.title"),
+            x + 20.0,
+            y + 34.0,
+            36.0,
+            WHITE,
+        );
+
+        for i in 0..entry_count() {
+            let color = if i == self.selected {
+                YELLOW
+            } else {
+                LIGHTGRAY
+            };
+            let line_y = y + line_h * (i as f32 + 2.0);
+            let text = self.line(prefs, entry(i));
+            draw_text(&text, x + 20.0, line_y, 24.0, color);
+        }
+
+        let mut status_y = y + line_h * (entry_count() as f32 + 2.5);
+        draw_text(
+            &locale::format("settings.hint", &[("menu", &key_label(&prefs.keys.menu))]),
+            x + 20.0,
+            status_y,
+            18.0,
+            GRAY,
+        );
+        for line in &self.status {
+            status_y += 24.0;
+            draw_text(line, x + 20.0, status_y, 20.0, RED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn preferences(dir: &str) -> Preferences {
+        let dir = std::env::temp_dir().join(format!("robot-battle-{}-{}", dir, std::process::id()));
+        Preferences {
+            settings: Settings::default(),
+            settings_path: dir.join("settings.toml"),
+            controls: Bindings::default(),
+            controls_path: dir.join("controls.toml"),
+            keys: ViewerKeys::default(),
+        }
+    }
+
+    #[test]
+    fn test_settings_step_through_their_values() {
+        let mut prefs = preferences("settings-screen-values");
+        let mut screen = SettingsScreen::default();
+        screen.change(&mut prefs, Entry::Speed, -1);
+        assert_eq!(prefs.settings.speed, 16);
+        screen.change(&mut prefs, Entry::Palette, 1);
+        assert_eq!(prefs.settings.palette, Palette::OkabeIto);
+        for _ in 0..20 {
+            screen.change(&mut prefs, Entry::HudScale, 1);
+        }
+        assert_eq!(prefs.settings.hud_scale, MAX_HUD_SCALE);
+        screen.change(&mut prefs, Entry::Language, 1);
+        assert_eq!(prefs.settings.locale.as_deref(), Some("en"));
+        screen.change(&mut prefs, Entry::Language, -2);
+        assert_eq!(prefs.settings.locale.as_deref(), Some("de"));
+        assert!(!screen.change(&mut prefs, Entry::Key(0), 1));
+    }
+
+    #[test]
+    fn test_rebound_keys_are_saved() {
+        let mut prefs = preferences("settings-screen-keys");
+        let mut screen = SettingsScreen::default();
+        screen.open();
+        screen.move_selection(-1);
+        assert_eq!(screen.selected(), Entry::Key(6));
+
+        screen.rebinding = Some(6);
+        assert!(!screen.bind(&mut prefs, KeyCode::Menu));
+        assert_eq!(prefs.controls.fire, vec!["space"]);
+        screen.rebinding = Some(6);
+        assert!(!screen.bind(&mut prefs, KeyCode::GraveAccent));
+        assert_eq!(screen.status, vec!["grave is already bound to `console`"]);
+        screen.rebinding = Some(0);
+        assert!(screen.bind(&mut prefs, KeyCode::I));
+        assert_eq!(prefs.controls.forward, vec!["i"]);
+
+        // Only the bindings changed, so only they are written
+        screen.close(&prefs).unwrap();
+        assert_eq!(
+            Bindings::load(&prefs.controls_path).unwrap(),
+            prefs.controls
+        );
+        assert!(!prefs.settings_path.exists());
+        fs::remove_dir_all(prefs.controls_path.parent().unwrap()).unwrap();
+    }
+}
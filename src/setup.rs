@@ -0,0 +1,214 @@
+// Match setup shared by the binary and tests: who takes part, where they spawn
+// and the default arena layout.
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{Controls, Robot};
+use crate::builtins;
+use crate::config::GameConfig;
+use crate::error::SimError;
+use crate::loadout::Loadout;
+use crate::parser::parse_script_with;
+use crate::plugin::Plugins;
+use crate::raycast::Obstacle;
+use crate::sandbox;
+
+/// A robot taking part in the match.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entrant {
+    /// Script file on disk.
+    Script(PathBuf),
+    /// Built-in bot by name (`builtin:<name>`).
+    Builtin(String),
+    /// A robot driven by the player at the keyboard (`human`).
+    Human,
+}
+
+/// The `fight` argument for a human robot, also its name.
+pub const HUMAN: &str = "human";
+
+impl Entrant {
+    /// Parse a `fight` argument: `builtin:<name>`, `human` or a script path.
+    pub fn parse(arg: &str) -> Result<Entrant, String> {
+        if arg == HUMAN {
+            return Ok(Entrant::Human);
+        }
+        match arg.strip_prefix("builtin:") {
+            Some(name) if builtins::script(name).is_some() => {
+                Ok(Entrant::Builtin(name.to_string()))
+            }
+            Some(name) => Err(format!(
+                "unknown built-in bot: {} (available: {})",
+                name,
+                builtins::names().join(", ")
+            )),
+            None => Ok(Entrant::Script(PathBuf::from(arg))),
+        }
+    }
+}
+
+impl std::fmt::Display for Entrant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Entrant::Script(path) => write!(f, "{}", path.display()),
+            Entrant::Builtin(name) => write!(f, "builtin:{}", name),
+            Entrant::Human => write!(f, "{}", HUMAN),
+        }
+    }
+}
+
+/// Obstacles placed in the default arena.
+pub fn default_obstacles() -> Vec<Obstacle> {
+    vec![
+        Obstacle::new(140.0, 110.0, 30.0, 30.0),
+        Obstacle::new(260.0, 60.0, 20.0, 80.0),
+        Obstacle::new(60.0, 280.0, 80.0, 20.0),
+    ]
+}
+
+/// Where entrants start, in spawn order.
+pub const SPAWN_POINTS: [(f32, f32); 6] = [
+    (100.0, 50.0),
+    (200.0, 200.0),
+    (300.0, 300.0),
+    (50.0, 350.0),
+    (350.0, 50.0),
+    (300.0, 200.0),
+];
+
+/// The source of one entrant's bot, as read before compiling.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BotScript {
+    /// How the entrant was given, for error messages (`Entrant`'s display form).
+    pub entrant: String,
+    pub name: String,
+    pub script: String,
+}
+
+/// Load and compile the entrants' scripts within the sandbox limits in `config`,
+/// returning the initial robots. Returns every read, parse or limit error found
+/// so they can be reported together.
+pub fn load_robots(entrants: &[Entrant], config: &GameConfig) -> Result<Vec<Robot>, Vec<SimError>> {
+    compile_robots(&read_scripts(entrants)?, config)
+}
+
+/// Read the scripts of the entrants of one match, returning every read error found.
+pub fn read_scripts(entrants: &[Entrant]) -> Result<Vec<BotScript>, Vec<SimError>> {
+    if entrants.len() > SPAWN_POINTS.len() {
+        return Err(vec![SimError::TooManyRobots {
+            max: SPAWN_POINTS.len(),
+        }]);
+    }
+    read_entrants(entrants)
+}
+
+/// Read any number of entrants' scripts, such as a tournament field, returning
+/// every read error found.
+pub fn read_entrants(entrants: &[Entrant]) -> Result<Vec<BotScript>, Vec<SimError>> {
+    let mut scripts = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, entrant) in entrants.iter().enumerate() {
+        let id = i + 1;
+        let (script, name) = match entrant {
+            Entrant::Human => (String::new(), HUMAN.to_string()),
+            Entrant::Builtin(name) => match builtins::script(name) {
+                Some(script) => (script.to_string(), name.clone()),
+                None => {
+                    errors.push(SimError::UnknownBuiltin {
+                        robot: id,
+                        name: name.clone(),
+                    });
+                    continue;
+                }
+            },
+            Entrant::Script(path) => match fs::read_to_string(path) {
+                Ok(script) => {
+                    let name = path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| format!("robot{}", id));
+                    (script, name)
+                }
+                Err(e) => {
+                    errors.push(SimError::ReadScript {
+                        robot: id,
+                        path: path.clone(),
+                        source: e,
+                    });
+                    continue;
+                }
+            },
+        };
+
+        scripts.push(BotScript {
+            entrant: entrant.to_string(),
+            name,
+            script,
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(scripts)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Compile read scripts within the sandbox limits in `config` and place the
+/// robots at the spawn points. A human entrant gets a robot without a program,
+/// waiting for its player's controls. Returns every parse or limit error found.
+pub fn compile_robots(
+    scripts: &[BotScript],
+    config: &GameConfig,
+) -> Result<Vec<Robot>, Vec<SimError>> {
+    compile_robots_with(scripts, config, &Plugins::default())
+}
+
+/// Like `compile_robots`, for scripts that may use the commands of `plugins`.
+pub fn compile_robots_with(
+    scripts: &[BotScript],
+    config: &GameConfig,
+    plugins: &Plugins,
+) -> Result<Vec<Robot>, Vec<SimError>> {
+    if scripts.len() > SPAWN_POINTS.len() {
+        return Err(vec![SimError::TooManyRobots {
+            max: SPAWN_POINTS.len(),
+        }]);
+    }
+
+    let mut robots = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, (bot, position)) in scripts.iter().zip(SPAWN_POINTS).enumerate() {
+        let id = i + 1;
+        let compiled = parse_script_with(&bot.script, plugins).and_then(|ast| {
+            let program = sandbox::compile(&ast, config)?;
+            Ok((program, Loadout::of(&ast)))
+        });
+        match compiled {
+            Ok((program, loadout)) => {
+                let mut robot = Robot::new(id, &bot.name, position, program);
+                robot.equip(loadout, config);
+                if bot.entrant == HUMAN {
+                    robot.controls = Some(Controls::default());
+                }
+                robots.push(robot);
+            }
+            Err(e) => errors.push(SimError::Script {
+                robot: id,
+                entrant: bot.entrant.clone(),
+                source: e,
+            }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(robots)
+    } else {
+        Err(errors)
+    }
+}
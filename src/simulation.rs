@@ -0,0 +1,3202 @@
+// Simulation state and tick logic for the robot-battle world.
+// Frontends (window, terminal) drive a `Simulation` by calling `step` once per tick;
+// the per-tick work itself is done by the systems in `systems.rs`.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::ast::{
+    Controls, Hook, HookAnchor, Instruction, MathOp, Operand, Robot, Section, Speech,
+    is_read_only_register, normalize_degrees,
+};
+use crate::config::{GameConfig, StalemateRule, Topology};
+use crate::drones::DRONE_TEAM;
+use crate::ecs::{
+    Beam, BeamKind, Bounce, Collider, Damage, Debris, Emp, Entity, Lifetime, Salvage, Smoke,
+    Sprite, Transform, Velocity, World,
+};
+use crate::elevation::{Elevation, Level};
+use crate::events::{Event, EventKind};
+use crate::hazards::{Hazard, Strike};
+use crate::inject::Scheduled;
+use crate::modes::{GameMode, Zone};
+use crate::movers::Mover;
+use crate::plugin::Plugins;
+use crate::practice::{self, DUMMY_TEAM, Drill};
+use crate::profile::SystemTimes;
+use crate::raycast::{self, Obstacle};
+use crate::registers::Reg;
+use crate::results::EndCondition;
+use crate::rng::Rng;
+use crate::scenario::Objective;
+use crate::systems::{SYSTEMS, arena_walls};
+use crate::workers::Workers;
+
+/// The shared world all robots live in.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub config: GameConfig,
+    pub robots: Vec<Robot>,
+    pub world: World, // Projectiles and other non-robot entities
+    pub obstacles: Vec<Obstacle>,
+    pub tick: u64,
+    pub seed: u64,          // Seed every robot's random stream is derived from
+    pub events: Vec<Event>, // Log of notable moments, oldest first
+    pub mode: GameMode,
+    pub scores: BTreeMap<usize, u32>, // Points per team, for modes that keep score
+    pub wave: u32,                    // Current wave in wave survival; 0 before the first
+    pub drill: Option<Drill>,         // Progress of a practice drill, once it has started
+    pub objective: Option<Objective>, // Goal of a scenario, and whether it was met
+    pub last_damage_tick: u64,        // Last tick a robot took damage, to spot a stalemate
+    pub sudden_death: Option<u64>,    // Tick a stalemate turned into sudden death, if one did
+    pub hazards: Vec<Hazard>,         // The arena's hazards
+    pub movers: Vec<Mover>,           // The arena's doors and barriers, moving in `world`
+    pub elevation: Elevation,         // The arena's platforms and ramps
+    pub strikes: Vec<Strike>,         // Hazard strikes telegraphed but not yet landed
+    pub injections: Vec<Scheduled>,   // Synthetic events, by the tick they happen on
+    pub system_times: Option<SystemTimes>, // Time spent in each system, when profiling
+    pub threads: usize,               // Threads robots' instructions are executed on
+    pub plugins: Plugins,             // Plugins whose commands the robots run
+    pub(crate) workers: Option<Arc<Workers>>, // Started on the first tick that needs them
+    pub(crate) hazard_rng: Rng,       // Where meteors land
+    pub(crate) buffers: Buffers,
+}
+
+/// Scratch space the systems reuse from tick to tick, so a running match does
+/// not allocate for its working lists. Each buffer is cleared before use.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Buffers {
+    pub blockers: Vec<Obstacle>,       // Obstacles and solid entities
+    pub robot_blockers: Vec<Obstacle>, // The blockers one robot can run into
+    pub planning: Vec<Vec<Obstacle>>,  // `robot_blockers` for each planning thread
+    pub ray_blockers: Vec<Obstacle>,   // The blockers a laser beam is cast against
+    pub surfaces: Vec<Obstacle>,       // What bouncing entities bounce off
+    pub entities: Vec<Entity>,         // Entities to despawn
+    pub hits: Vec<(Entity, usize, Damage, f32)>, // Projectile, robot hit, damage, heading
+    pub positions: Vec<(usize, (f32, f32))>, // Team and position of each living robot
+    pub pairs: Vec<(usize, usize)>,    // Robots that rammed each other
+    pub in_flight: Vec<InFlight>,      // Projectiles checked against each other
+    pub obstacle_hits: Vec<(usize, i32, usize)>, // Obstacle shot, damage, shooter
+    pub actions: Vec<Option<WorldAction>>, // What each robot's instruction asked for
+    pub seen: Vec<Seen>,               // Robots as they were at the start of the tick
+    pub planned: Vec<Option<Planned>>, // Each world action with what it looked at
+}
+
+/// A projectile in flight, as the systems that check projectiles against each
+/// other and against point defense see it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct InFlight {
+    pub entity: Entity,
+    pub owner: usize,
+    pub team: Option<usize>, // The owner's team, if the owner is still in the match
+    pub position: (f32, f32),
+    pub velocity: (f32, f32), // Units per tick
+    pub level: Level,         // The level it was fired from
+}
+
+/// A robot as the others perceive it while the robots act: as it was at the
+/// start of the tick, before any robot moved or any action landed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Seen {
+    pub id: usize,
+    pub team: usize,
+    pub position: (f32, f32),
+    pub health: i32,
+    pub max_health: i32,
+    pub cloaked: bool,
+}
+
+impl Seen {
+    pub fn of(robot: &Robot) -> Seen {
+        Seen {
+            id: robot.id,
+            team: robot.team,
+            position: robot.position,
+            health: robot.health,
+            max_health: robot.max_health,
+            cloaked: robot.cloaked,
+        }
+    }
+}
+
+/// A robot's world action together with what it found in the world, looked
+/// at before any robot's action lands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Planned {
+    Fire,
+    FireRicochet,
+    /// A laser, with what it is fired at.
+    Laser(TurretRay),
+    FireEmp,
+    Scan(Option<ScanContact>),
+    /// Living enemies, and the distance and bearing of the nearest.
+    ScanAll(i32, Option<(i32, i32)>),
+    Say,
+    SelfDestruct,
+    Smoke,
+    Repair,
+    /// The teammate a repair beam goes to, with its distance and bearing.
+    RepairAlly(Option<(usize, f32, f32)>),
+    /// A grappling hook, with what it is thrown at.
+    Hook(TurretRay),
+}
+
+/// What a ray cast along a robot's turret runs into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TurretRay {
+    /// Distance to the first obstacle or wreck, or the range.
+    blocked_at: f32,
+    /// The obstacle that stops the ray, by index, unless something else does.
+    obstacle: Option<usize>,
+    /// The enemy robot hit before that, with the distance to it.
+    target: Option<(usize, f32)>,
+}
+
+/// A robot added with `Simulation::add_robot`, to look it up again later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RobotHandle {
+    pub id: usize,
+}
+
+/// A robot seen by a scan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanContact {
+    /// Id of the robot that was seen.
+    pub id: usize,
+    /// Its position at the time of the scan.
+    pub position: (f32, f32),
+    /// Distance to the contact, rounded up (at least 1).
+    pub distance: i32,
+    /// Absolute bearing to the contact in whole degrees, [0, 360).
+    pub bearing: i32,
+    /// Whether the contact is the wreck of a destroyed robot.
+    pub debris: bool,
+    /// Whether the contact is a wreck that still holds salvage.
+    pub salvage: bool,
+}
+
+/// Effects of an instruction that need access to the rest of the world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorldAction {
+    Fire,
+    FireRicochet,
+    FireLaser,
+    FireEmp,
+    Scan,
+    ScanAll,
+    Say,
+    SelfDestruct,
+    Smoke,
+    Repair,
+    RepairAlly,
+    Hook,
+}
+
+/// Ticks a repair beam stays visible; a robot repairing every tick keeps it lit.
+const REPAIR_BEAM_TICKS: u32 = 2;
+
+/// Ticks a point-defense shot stays visible.
+const INTERCEPT_BEAM_TICKS: u32 = 4;
+
+/// How close to an obstacle a grappling hook's anchor point must be to count
+/// as caught on it.
+const HOOK_GRIP: f32 = 0.5;
+
+/// Id of the random stream hazards draw from, clear of every robot's.
+const HAZARD_STREAM: usize = usize::MAX;
+
+/// An EMP hit sets the register it scrambles to a value from minus this to this.
+const EMP_SCRAMBLE_RANGE: i32 = 100;
+
+impl Simulation {
+    /// A match between `robots`, which keep their ids. Robots sharing a
+    /// display name are told apart by a suffix, as in `add_robot`.
+    pub fn new(config: GameConfig, robots: Vec<Robot>, obstacles: Vec<Obstacle>) -> Self {
+        let mut sim = Simulation {
+            config,
+            robots: Vec::with_capacity(robots.len()),
+            world: World::default(),
+            obstacles,
+            tick: 0,
+            seed: 0,
+            events: Vec::new(),
+            mode: GameMode::default(),
+            scores: BTreeMap::new(),
+            wave: 0,
+            drill: None,
+            objective: None,
+            last_damage_tick: 0,
+            sudden_death: None,
+            hazards: Vec::new(),
+            movers: Vec::new(),
+            elevation: Elevation::default(),
+            strikes: Vec::new(),
+            injections: Vec::new(),
+            system_times: None,
+            threads: 1,
+            plugins: Plugins::default(),
+            workers: None,
+            hazard_rng: Rng::stream(0, HAZARD_STREAM),
+            buffers: Buffers::default(),
+        };
+        for mut robot in robots {
+            robot.name = sim.unique_name(&robot.name);
+            robot.rng = sim.rng_for(robot.id);
+            sim.robots.push(robot);
+        }
+        sim
+    }
+
+    /// Seed the match's random numbers. Each robot gets its own stream,
+    /// derived from `seed` and its id.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        for robot in &mut self.robots {
+            robot.rng = Rng::stream(seed, robot.id);
+        }
+        self.hazard_rng = Rng::stream(seed, HAZARD_STREAM);
+        self
+    }
+
+    /// The random stream of the robot with id `id` in this match.
+    pub fn rng_for(&self, id: usize) -> Rng {
+        Rng::stream(self.seed, id)
+    }
+
+    /// Add `robot` to the match under the next free id, returning a handle to
+    /// it. A robot that is its own team stays its own team under the new id.
+    /// If another robot already has its name, a number is appended, so a
+    /// second `spinner` joins as `spinner (2)`.
+    pub fn add_robot(&mut self, mut robot: Robot) -> RobotHandle {
+        let id = self.next_robot_id();
+        if robot.team == robot.id {
+            robot.team = id;
+        }
+        robot.id = id;
+        robot.name = self.unique_name(&robot.name);
+        robot.rng = self.rng_for(id);
+        self.robots.push(robot);
+        RobotHandle { id }
+    }
+
+    /// The id the next robot added gets: one above every id so far.
+    pub fn next_robot_id(&self) -> usize {
+        self.robots.iter().map(|r| r.id).max().unwrap_or(0) + 1
+    }
+
+    /// The robot `handle` refers to.
+    pub fn robot(&self, handle: RobotHandle) -> Option<&Robot> {
+        self.robots.iter().find(|r| r.id == handle.id)
+    }
+
+    /// The robot `handle` refers to, for changing it.
+    pub fn robot_mut(&mut self, handle: RobotHandle) -> Option<&mut Robot> {
+        self.robots.iter_mut().find(|r| r.id == handle.id)
+    }
+
+    /// `name`, or `name (n)` with the lowest n from 2 up that no robot in
+    /// the match has taken yet.
+    fn unique_name(&self, name: &str) -> String {
+        let taken = |candidate: &str| self.robots.iter().any(|r| r.name == candidate);
+        if !taken(name) {
+            return name.to_string();
+        }
+        let mut n = 2;
+        loop {
+            let candidate = format!("{} ({})", name, n);
+            if !taken(&candidate) {
+                return candidate;
+            }
+            n += 1;
+        }
+    }
+
+    /// Play the match under `mode` instead of the default deathmatch.
+    pub fn with_mode(mut self, mode: GameMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Run the commands and world behaviors of `plugins` in the match; the
+    /// robots' scripts should be parsed with the same plugins.
+    pub fn with_plugins(mut self, plugins: Plugins) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Set off the arena's `hazards` during the match.
+    pub fn with_hazards(mut self, hazards: Vec<Hazard>) -> Self {
+        self.hazards = hazards;
+        self
+    }
+
+    /// Add the arena's sliding doors and rotating barriers to the world.
+    pub fn with_movers(mut self, movers: Vec<Mover>) -> Self {
+        for mover in &movers {
+            mover.spawn(&mut self.world, self.tick, self.config.math);
+        }
+        self.movers.extend(movers);
+        self
+    }
+
+    /// Raise the arena's platforms, reached by its ramps.
+    pub fn with_elevation(mut self, elevation: Elevation) -> Self {
+        self.elevation = elevation;
+        self
+    }
+
+    /// Count the ticks each robot spends on every instruction of its program,
+    /// and after its program ends, in `Robot::ticks_spent`. Robots added later,
+    /// such as drones, are not profiled. Each system's running time is added up
+    /// in `system_times`.
+    pub fn with_profiling(mut self) -> Self {
+        self.system_times = Some(SystemTimes::default());
+        for robot in &mut self.robots {
+            robot.ticks_spent = vec![0; robot.instruction_queue.len() + 1];
+        }
+        self
+    }
+
+    /// Execute the robots' instructions on up to `threads` threads each tick.
+    /// An instruction only changes its own robot, so the match plays out the
+    /// same on any number; threads pay off only for matches of many robots.
+    /// The threads start on the first tick that uses them and stay up until
+    /// the simulation, and every clone of it, is dropped.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// The control zone, in modes that have one.
+    pub fn control_zone(&self) -> Option<Zone> {
+        match self.mode {
+            GameMode::Deathmatch | GameMode::WaveSurvival => None,
+            GameMode::Practice => Some(practice::checkpoint(self)),
+            GameMode::Scenario => self.objective.as_ref().and_then(Objective::zone),
+            GameMode::KingOfTheHill => Some(Zone {
+                center: (
+                    self.config.arena_width / 2.0,
+                    self.config.arena_height / 2.0,
+                ),
+                radius: self.config.koth_zone_radius,
+            }),
+        }
+    }
+
+    /// The team that won, once the match is over: the team that reached the
+    /// target score, or else the team of the last robot standing.
+    pub fn winning_team(&self) -> Option<usize> {
+        if self.mode == GameMode::KingOfTheHill
+            && let Some((&team, _)) = self
+                .scores
+                .iter()
+                .find(|(_, score)| **score >= self.config.koth_target_score)
+        {
+            return Some(team);
+        }
+        match self.alive_count() {
+            1 => self.robots.iter().find(|r| r.health > 0).map(|r| r.team),
+            _ => None,
+        }
+    }
+
+    /// Record an event at the current tick.
+    pub fn log(&mut self, kind: EventKind) {
+        self.events.push(Event {
+            tick: self.tick,
+            kind,
+        });
+    }
+
+    /// Deal `amount` damage, less what the target's armor absorbs, to robot
+    /// `target`. The robot with id `attacker` (if any) is credited with the
+    /// damage, if it is an enemy, and with the kill if that destroys the target.
+    pub fn damage(&mut self, target: usize, amount: i32, attacker: Option<usize>) {
+        if self.robots[target].health <= 0 || amount <= 0 {
+            return;
+        }
+        self.last_damage_tick = self.tick;
+        let robot = &mut self.robots[target];
+        let before = robot.health;
+        robot.health -= robot.loadout.damage_taken(amount, &self.config);
+        let dealt = (before - robot.health.max(0)) as u32;
+        let victim_team = robot.team;
+        if let Some(shooter) = self
+            .robots
+            .iter_mut()
+            .find(|r| Some(r.id) == attacker && r.team != victim_team)
+        {
+            shooter.damage_dealt += dealt;
+        }
+        let robot = &self.robots[target];
+        if let Some(drill) = &mut self.drill
+            && robot.team == DUMMY_TEAM
+            && attacker.is_some()
+        {
+            drill.record_hit(robot.id, self.tick, robot.health <= 0);
+        }
+        if let Some(objective) = &mut self.objective
+            && attacker == Some(objective.player)
+            && robot.id != objective.player
+        {
+            objective.hits += 1;
+        }
+        if robot.health > 0 {
+            return;
+        }
+        self.destroy(target, attacker);
+    }
+
+    /// Count robot `target`, whose health has just run out, as destroyed by
+    /// the robot with id `attacker`, if any: credit the kill, unless the robot
+    /// destroyed itself, log it and leave a wreck.
+    fn destroy(&mut self, target: usize, attacker: Option<usize>) {
+        let (destroyed, victim_team) = (self.robots[target].id, self.robots[target].team);
+        if let Some(shooter) = self
+            .robots
+            .iter_mut()
+            .find(|r| Some(r.id) == attacker && r.id != destroyed)
+        {
+            shooter.kills += 1;
+            if self.mode == GameMode::WaveSurvival
+                && victim_team == DRONE_TEAM
+                && shooter.team != DRONE_TEAM
+            {
+                *self.scores.entry(shooter.team).or_insert(0) += 1;
+            }
+        }
+        self.log(EventKind::Destroyed {
+            robot: destroyed,
+            by: attacker,
+        });
+        self.leave_wreck(target);
+    }
+
+    /// Replace destroyed robot `i` with a wreck that blocks movement and
+    /// projectiles until it decays, holding the salvage the config sets.
+    fn leave_wreck(&mut self, i: usize) {
+        let lifetime = self.config.wreck_lifetime;
+        if lifetime == 0 {
+            return;
+        }
+        let robot = &self.robots[i];
+        let wreck = self.world.spawn();
+        self.world.transforms.insert(
+            wreck,
+            Transform {
+                position: robot.position,
+                heading: robot.heading,
+            },
+        );
+        self.world.colliders.insert(
+            wreck,
+            Collider {
+                half_width: self.config.robot_width / 2.0,
+                half_height: self.config.robot_height / 2.0,
+            },
+        );
+        self.world.debris.insert(wreck, Debris { robot: robot.id });
+        self.world
+            .lifetimes
+            .insert(wreck, Lifetime { ticks: lifetime });
+        let salvage = Salvage {
+            ammo: self.config.salvage_ammo.max(0),
+            energy: self.config.salvage_energy.max(0),
+        };
+        if salvage.ammo > 0 || salvage.energy > 0 {
+            self.world.salvage.insert(wreck, salvage);
+            self.world.sprites.insert(wreck, Sprite::SalvageWreck);
+        } else {
+            self.world.sprites.insert(wreck, Sprite::Wreck);
+        }
+    }
+
+    /// Everything that blocks movement and projectiles: the arena's obstacles
+    /// and solid entities such as wrecks.
+    pub fn blockers(&self) -> Vec<Obstacle> {
+        let mut blockers = Vec::new();
+        self.fill_blockers(&mut blockers);
+        blockers
+    }
+
+    /// Replace the contents of `blockers` with `Simulation::blockers`, reusing
+    /// its memory. The arena's obstacles come first, at their indices in
+    /// `obstacles`.
+    pub fn fill_blockers(&self, blockers: &mut Vec<Obstacle>) {
+        blockers.clear();
+        blockers.extend_from_slice(&self.obstacles);
+        blockers.extend(self.world.solid_boxes());
+    }
+
+    /// Deal `amount` damage to obstacle `k`, if it can be destroyed, logging
+    /// its destruction. Destroyed obstacles stay in `obstacles` until
+    /// `clear_rubble`, so the indices of the others hold until then.
+    pub fn damage_obstacle(&mut self, k: usize, amount: i32, attacker: Option<usize>) {
+        let obstacle = &mut self.obstacles[k];
+        let Some(health) = &mut obstacle.health else {
+            return;
+        };
+        if *health <= 0 || amount <= 0 {
+            return;
+        }
+        *health -= amount;
+        if *health <= 0 {
+            let at = obstacle.center();
+            self.log(EventKind::ObstacleDestroyed { at, by: attacker });
+        }
+    }
+
+    /// Remove destroyed obstacles from the arena, letting go of any grappling
+    /// hook caught on one.
+    pub fn clear_rubble(&mut self) {
+        if !self.obstacles.iter().any(Obstacle::is_destroyed) {
+            return;
+        }
+        for robot in &mut self.robots {
+            if let Some(Hook {
+                anchor: HookAnchor::Point(point),
+                ..
+            }) = robot.hook
+                && self
+                    .obstacles
+                    .iter()
+                    .any(|o| o.is_destroyed() && o.distance_to(point) <= HOOK_GRIP)
+            {
+                robot.hook = None;
+            }
+        }
+        self.obstacles.retain(|o| !o.is_destroyed());
+    }
+
+    /// Arm robot `i`'s self-destruct; arming an already armed or a destroyed
+    /// robot does nothing.
+    pub fn arm_self_destruct(&mut self, i: usize) {
+        let fuse = self.config.self_destruct_fuse;
+        let robot = &mut self.robots[i];
+        if robot.fuse.is_some() || robot.health <= 0 {
+            return;
+        }
+        robot.fuse = Some(fuse);
+        let id = robot.id;
+        self.log(EventKind::SelfDestructArmed { robot: id, fuse });
+    }
+
+    /// Blow up robot `i`, destroying it and damaging every robot in the blast
+    /// radius that is not shielded by an obstacle.
+    pub fn detonate(&mut self, i: usize) {
+        let (id, center) = (self.robots[i].id, self.robots[i].position);
+        self.robots[i].fuse = None;
+        self.robots[i].health = 0;
+
+        let in_blast: Vec<usize> = (0..self.robots.len())
+            .filter(|&j| j != i && self.robots[j].health > 0)
+            .filter(|&j| {
+                let (dx, dy) = self.config.displacement(center, self.robots[j].position);
+                (dx * dx + dy * dy).sqrt() <= self.config.self_destruct_radius
+                    && self.clear_path(center, (center.0 + dx, center.1 + dy))
+            })
+            .collect();
+
+        let hit = in_blast.iter().map(|&j| self.robots[j].id).collect();
+        self.log(EventKind::Detonated { robot: id, hit });
+        self.destroy(i, Some(id));
+        for j in in_blast {
+            self.damage(j, self.config.combat.self_destruct_damage, Some(id));
+            let (dx, dy) = self.config.displacement(center, self.robots[j].position);
+            let dist = (dx * dx + dy * dy).sqrt();
+            if dist > f32::EPSILON {
+                let speed = self.config.self_destruct_knockback / dist;
+                self.push(j, (dx * speed, dy * speed), id);
+            }
+        }
+    }
+
+    /// Knock robot `i` back with `impulse` (a velocity in units per tick),
+    /// caused by the robot with id `by`. Destroyed robots are not moved.
+    pub fn push(&mut self, i: usize, impulse: (f32, f32), by: usize) {
+        let robot = &mut self.robots[i];
+        if robot.health <= 0 || impulse == (0.0, 0.0) {
+            return;
+        }
+        robot.knockback.0 += impulse.0;
+        robot.knockback.1 += impulse.1;
+        let id = robot.id;
+        self.log(EventKind::Pushed { robot: id, by });
+    }
+
+    /// Number of robots that still have health left.
+    pub fn alive_count(&self) -> usize {
+        self.robots.iter().filter(|r| r.health > 0).count()
+    }
+
+    /// The match ends when at most one robot remains alive, or in king of the
+    /// hill when a team reaches the target score. Wave survival ends when no
+    /// robot other than the drones is left. A practice drill ends once it is
+    /// complete or the robot practicing is destroyed. Any match also ends when
+    /// the match rules stop it (see `stopped_by_rules`).
+    pub fn is_over(&self) -> bool {
+        self.decided() || self.stopped_by_rules().is_some()
+    }
+
+    /// Whether the match has come to an end on its own: the mode's goal is
+    /// met or it has no one left to play it.
+    fn decided(&self) -> bool {
+        if self.mode == GameMode::WaveSurvival {
+            return !self
+                .robots
+                .iter()
+                .any(|r| r.health > 0 && r.team != DRONE_TEAM);
+        }
+        if self.mode == GameMode::Practice {
+            let practicing = self
+                .robots
+                .iter()
+                .any(|r| r.health > 0 && r.team != DUMMY_TEAM);
+            return !practicing || self.drill.as_ref().is_some_and(Drill::is_complete);
+        }
+        if self.mode == GameMode::Scenario {
+            return self.objective.as_ref().is_none_or(|o| o.outcome.is_some());
+        }
+        let target_reached = self.mode == GameMode::KingOfTheHill
+            && self
+                .scores
+                .values()
+                .any(|score| *score >= self.config.koth_target_score);
+        self.alive_count() <= 1 || target_reached
+    }
+
+    /// The match rule that stops a match no one has won, if one does: the
+    /// `max_ticks` cap, or a stalemate the rules call a draw.
+    pub fn stopped_by_rules(&self) -> Option<EndCondition> {
+        if self.decided() {
+            None
+        } else if self.config.max_ticks > 0 && self.tick >= self.config.max_ticks {
+            Some(EndCondition::TickLimit)
+        } else if self.is_stalemate() && self.config.stalemate == StalemateRule::Draw {
+            Some(EndCondition::Stalemate)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a deathmatch has gone `stalemate_ticks` without anyone taking
+    /// damage.
+    pub fn is_stalemate(&self) -> bool {
+        let quiet = self.config.stalemate_ticks;
+        self.mode == GameMode::Deathmatch && quiet > 0 && self.tick - self.last_damage_tick >= quiet
+    }
+
+    /// Advance the world by one tick.
+    pub fn step(&mut self) {
+        match self.system_times.take() {
+            None => {
+                for (_, system) in SYSTEMS {
+                    system(self);
+                }
+            }
+            Some(mut times) => {
+                for ((_, system), spent) in SYSTEMS.iter().zip(&mut times.spent) {
+                    let started = Instant::now();
+                    system(self);
+                    *spent += started.elapsed();
+                }
+                times.ticks += 1;
+                self.system_times = Some(times);
+            }
+        }
+        self.tick += 1;
+    }
+
+    /// Launch a projectile from robot `i` along its turret direction.
+    pub fn fire(&mut self, i: usize) {
+        self.launch(i, false);
+    }
+
+    /// Launch a ricochet shot from robot `i` along its turret direction.
+    pub fn fire_ricochet(&mut self, i: usize) {
+        self.launch(i, true);
+    }
+
+    /// Robot `j` as robot actions perceive it: as it was at the start of the
+    /// tick while the robots act, and as it is otherwise.
+    fn seen(&self, j: usize) -> Seen {
+        self.buffers
+            .seen
+            .get(j)
+            .copied()
+            .unwrap_or_else(|| Seen::of(&self.robots[j]))
+    }
+
+    /// Every robot, by index, as robot actions perceive it.
+    fn perceived(&self) -> impl Iterator<Item = (usize, Seen)> + '_ {
+        (0..self.robots.len()).map(|j| (j, self.seen(j)))
+    }
+
+    /// Cast a ray `range` long from robot `i` along its turret direction: how
+    /// far it gets before an obstacle or wreck stops it, and the nearest enemy
+    /// robot it hits before that, with the distance to it. Teammates, and robots
+    /// on a level the robot does not meet, are passed through. In a torus the ray carries on through the edges, so it is cast
+    /// against every copy of the arena it can reach.
+    pub(crate) fn turret_ray(&mut self, i: usize, range: f32) -> TurretRay {
+        let robot = &self.robots[i];
+        let (team, origin) = (robot.team, robot.position);
+        let level = self.elevation.level_at(origin);
+        let angle = robot.turret_direction().to_radians();
+        let offsets = self.config.tile_offsets();
+        let mut blockers = std::mem::take(&mut self.buffers.ray_blockers);
+        self.fill_blockers(&mut blockers);
+        let blocker = offsets
+            .clone()
+            .filter_map(|(ox, oy)| {
+                let from = (origin.0 - ox, origin.1 - oy);
+                raycast::cast_ray_index(from, angle, range, &blockers, self.config.math)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        self.buffers.ray_blockers = blockers;
+        let blocked_at = blocker.map_or(range, |(_, t)| t);
+        let (w, h) = (self.config.robot_width, self.config.robot_height);
+        let (sin, cos) = self.config.math.sin_cos(angle);
+        let target = self
+            .perceived()
+            .filter(|(j, other)| {
+                *j != i
+                    && other.health > 0
+                    && other.team != team
+                    && level.meets(self.elevation.level_at(other.position))
+            })
+            .flat_map(|(j, other)| offsets.clone().map(move |offset| (j, other, offset)))
+            .filter_map(|(j, other, (ox, oy))| {
+                let (x, y) = (other.position.0 + ox, other.position.1 + oy);
+                let hull = Obstacle::new(x - w / 2.0, y - h / 2.0, w, h);
+                hull.ray_hit(origin, (cos, sin))
+                    .filter(|t| *t <= blocked_at)
+                    .map(|t| (j, t))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        TurretRay {
+            blocked_at,
+            obstacle: blocker
+                .map(|(k, _)| k)
+                .filter(|k| *k < self.obstacles.len()),
+            target,
+        }
+    }
+
+    /// Fire robot `i`'s laser along its turret direction. The beam stops at the
+    /// first obstacle, wreck or enemy robot in its path and damages the robot.
+    /// Teammates are passed through, like projectiles pass through them.
+    pub fn fire_laser(&mut self, i: usize) {
+        let ray = self.turret_ray(i, self.config.laser_range);
+        self.fire_laser_along(i, ray);
+    }
+
+    /// Fire robot `i`'s laser at what `ray`, cast from the robot, found.
+    pub(crate) fn fire_laser_along(&mut self, i: usize, ray: TurretRay) {
+        let cost = self.config.laser_energy_cost;
+        let robot = &mut self.robots[i];
+        if robot.energy < cost {
+            return;
+        }
+        robot.energy -= cost;
+        robot.shots_fired += 1;
+        robot.cloaked = false;
+        let (id, origin) = (robot.id, robot.position);
+        let heading = robot.turret_direction();
+        let TurretRay {
+            blocked_at,
+            obstacle,
+            target,
+        } = ray;
+
+        let beam = self.world.spawn();
+        self.world.transforms.insert(
+            beam,
+            Transform {
+                position: origin,
+                heading,
+            },
+        );
+        let duration = self.config.laser_beam_ticks;
+        self.world.beams.insert(
+            beam,
+            Beam {
+                length: target.map_or(blocked_at, |(_, t)| t),
+                duration,
+                kind: BeamKind::Laser,
+            },
+        );
+        self.world
+            .lifetimes
+            .insert(beam, Lifetime { ticks: duration });
+
+        match (target, obstacle) {
+            (Some((j, _)), _) => self.damage(j, self.config.combat.laser_damage, Some(id)),
+            (None, Some(k)) => {
+                self.damage_obstacle(k, self.config.combat.laser_damage, Some(id));
+                self.clear_rubble();
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Throw robot `i`'s grappling hook along its turret direction. It catches
+    /// the first enemy robot, obstacle, wreck or arena wall within
+    /// `hook_range`, replacing any hook the robot already had out; if nothing
+    /// is in reach it falls short and the robot keeps its old hook.
+    pub fn throw_hook(&mut self, i: usize) {
+        let ray = self.turret_ray(i, self.config.hook_range);
+        self.throw_hook_along(i, ray);
+    }
+
+    /// Throw robot `i`'s grappling hook at what `ray`, cast from the robot,
+    /// found.
+    pub(crate) fn throw_hook_along(&mut self, i: usize, ray: TurretRay) {
+        let range = self.config.hook_range;
+        let TurretRay {
+            mut blocked_at,
+            target,
+            ..
+        } = ray;
+        let robot = &self.robots[i];
+        let origin = robot.position;
+        let angle = robot.turret_direction().to_radians();
+        if self.config.topology == Topology::Bounded {
+            let walls = arena_walls(self.config.arena_width, self.config.arena_height);
+            let wall = raycast::cast_ray(origin, angle, range, &walls, self.config.math);
+            blocked_at = wall.map_or(blocked_at, |d| d.min(blocked_at));
+        }
+        let hook = match target {
+            Some((j, length)) => Hook {
+                anchor: HookAnchor::Robot(self.robots[j].id),
+                length,
+            },
+            None if blocked_at < range => {
+                let (sin, cos) = self.config.math.sin_cos(angle);
+                let point = (origin.0 + blocked_at * cos, origin.1 + blocked_at * sin);
+                Hook {
+                    anchor: HookAnchor::Point(self.config.wrap(point)),
+                    length: blocked_at,
+                }
+            }
+            None => return,
+        };
+        self.robots[i].hook = Some(hook);
+    }
+
+    /// Where the far end of robot `i`'s grappling hook cable is, if the hook is
+    /// out: the point it caught, or the robot it caught while that is alive.
+    pub fn hook_anchor(&self, i: usize) -> Option<(f32, f32)> {
+        match self.robots[i].hook?.anchor {
+            HookAnchor::Point(point) => Some(point),
+            HookAnchor::Robot(id) => self
+                .robots
+                .iter()
+                .find(|r| r.id == id && r.health > 0)
+                .map(|r| r.position),
+        }
+    }
+
+    /// Convert robot `i`'s energy into health. Only works while the robot stands
+    /// still, so not while it is sliding from a knockback, and never brings back
+    /// a destroyed robot.
+    pub fn repair(&mut self, i: usize) {
+        let (rate, cost) = (self.config.repair_rate, self.config.repair_energy_cost);
+        let robot = &mut self.robots[i];
+        if robot.health <= 0
+            || robot.energy < cost
+            || robot.knockback != (0.0, 0.0)
+            || robot.health >= robot.max_health
+        {
+            return;
+        }
+        robot.energy -= cost;
+        robot.health = (robot.health + rate).min(robot.max_health);
+    }
+
+    /// Spend robot `i`'s energy to repair the nearest damaged teammate within
+    /// `repair_ally_range`, drawing a repair beam to it. Like `repair`, the
+    /// robot must stand still.
+    pub fn repair_ally(&mut self, i: usize) {
+        let ally = self.ally_in_reach(i);
+        self.repair_ally_at(i, ally);
+    }
+
+    /// The nearest damaged teammate of robot `i` within `repair_ally_range`,
+    /// with its distance and bearing.
+    pub(crate) fn ally_in_reach(&self, i: usize) -> Option<(usize, f32, f32)> {
+        let robot = &self.robots[i];
+        let origin = robot.position;
+        self.perceived()
+            .filter(|(j, other)| {
+                *j != i
+                    && other.team == robot.team
+                    && other.health > 0
+                    && other.health < other.max_health
+            })
+            .map(|(j, other)| {
+                let (dx, dy) = self.config.displacement(origin, other.position);
+                (
+                    j,
+                    (dx * dx + dy * dy).sqrt(),
+                    self.config.math.atan2(dy, dx).to_degrees(),
+                )
+            })
+            .filter(|(_, dist, _)| *dist <= self.config.repair_ally_range)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Repair `ally`, found by `ally_in_reach`, with robot `i`'s energy.
+    pub(crate) fn repair_ally_at(&mut self, i: usize, ally: Option<(usize, f32, f32)>) {
+        let (rate, cost) = (self.config.repair_rate, self.config.repair_energy_cost);
+        let robot = &self.robots[i];
+        if robot.energy < cost || robot.knockback != (0.0, 0.0) {
+            return;
+        }
+        let origin = robot.position;
+        // A robot destroyed this tick is not brought back
+        let Some((j, dist, bearing)) = ally.filter(|(j, _, _)| self.robots[*j].health > 0) else {
+            return;
+        };
+
+        self.robots[i].energy -= cost;
+        let ally = &mut self.robots[j];
+        ally.health = (ally.health + rate).min(ally.max_health);
+
+        let beam = self.world.spawn();
+        self.world.transforms.insert(
+            beam,
+            Transform {
+                position: origin,
+                heading: bearing,
+            },
+        );
+        self.world.beams.insert(
+            beam,
+            Beam {
+                length: dist,
+                duration: REPAIR_BEAM_TICKS,
+                kind: BeamKind::Repair,
+            },
+        );
+        self.world.lifetimes.insert(
+            beam,
+            Lifetime {
+                ticks: REPAIR_BEAM_TICKS,
+            },
+        );
+    }
+
+    /// Shoot down projectile `entity` with robot `i`'s point defense, if the
+    /// robot has the energy.
+    pub fn shoot_down(&mut self, i: usize, entity: Entity) {
+        let cost = self.config.point_defense_energy_cost;
+        let (Some(target), Some(damage)) = (
+            self.world.transforms.get(entity).map(|t| t.position),
+            self.world.damages.get(entity).copied(),
+        ) else {
+            return;
+        };
+        let robot = &mut self.robots[i];
+        if robot.energy < cost {
+            return;
+        }
+        robot.energy -= cost;
+        let (id, origin) = (robot.id, robot.position);
+        self.world.despawn(entity);
+
+        let (dx, dy) = self.config.displacement(origin, target);
+        let beam = self.world.spawn();
+        self.world.transforms.insert(
+            beam,
+            Transform {
+                position: origin,
+                heading: self.config.math.atan2(dy, dx).to_degrees(),
+            },
+        );
+        self.world.beams.insert(
+            beam,
+            Beam {
+                length: (dx * dx + dy * dy).sqrt(),
+                duration: INTERCEPT_BEAM_TICKS,
+                kind: BeamKind::Intercept,
+            },
+        );
+        self.world.lifetimes.insert(
+            beam,
+            Lifetime {
+                ticks: INTERCEPT_BEAM_TICKS,
+            },
+        );
+        self.log(EventKind::Intercepted {
+            robot: id,
+            owner: damage.owner,
+        });
+    }
+
+    /// Drop a smoke cloud where robot `i` stands, if it has the energy.
+    pub fn drop_smoke(&mut self, i: usize) {
+        let cost = self.config.smoke_energy_cost;
+        let robot = &mut self.robots[i];
+        if robot.energy < cost {
+            return;
+        }
+        robot.energy -= cost;
+        let position = robot.position;
+
+        let cloud = self.world.spawn();
+        self.world.transforms.insert(
+            cloud,
+            Transform {
+                position,
+                heading: 0.0,
+            },
+        );
+        self.world.smoke.insert(
+            cloud,
+            Smoke {
+                radius: self.config.smoke_radius,
+            },
+        );
+        self.world.lifetimes.insert(
+            cloud,
+            Lifetime {
+                ticks: self.config.smoke_ticks,
+            },
+        );
+    }
+
+    /// Whether robot `i` can see `point`, for drawing its view of the arena:
+    /// the point is within its scan range and its scanner can see it. A
+    /// destroyed robot sees everything.
+    pub fn in_sight(&self, i: usize, point: (f32, f32)) -> bool {
+        let robot = &self.robots[i];
+        if robot.health <= 0 {
+            return true;
+        }
+        let (dx, dy) = self.config.displacement(robot.position, point);
+        let range = robot.loadout.scan_range(&self.config);
+        dx * dx + dy * dy <= range * range
+            && self.scanner_can_see(
+                robot.position,
+                (robot.position.0 + dx, robot.position.1 + dy),
+            )
+    }
+
+    /// Whether a scanner at `from` can see `to`: no obstacle or smoke cloud is
+    /// in between. In a torus `to` may lie outside the arena, as `from` plus
+    /// the displacement to the target.
+    pub fn scanner_can_see(&self, from: (f32, f32), to: (f32, f32)) -> bool {
+        self.clear_path(from, to)
+            && self.config.tile_offsets().all(|(ox, oy)| {
+                let (from, to) = ((from.0 - ox, from.1 - oy), (to.0 - ox, to.1 - oy));
+                !self
+                    .world
+                    .smoke_clouds()
+                    .any(|(center, radius)| raycast::segment_hits_circle(from, to, center, radius))
+            })
+    }
+
+    /// Whether no obstacle or arena mover lies on the segment from `from` to
+    /// `to`. In a torus the obstacles of every copy of the arena around it count.
+    fn clear_path(&self, from: (f32, f32), to: (f32, f32)) -> bool {
+        self.config.tile_offsets().all(|(ox, oy)| {
+            let (from, to) = ((from.0 - ox, from.1 - oy), (to.0 - ox, to.1 - oy));
+            raycast::line_of_sight(from, to, &self.obstacles)
+                && self
+                    .world
+                    .moving_boxes()
+                    .all(|b| raycast::line_of_sight(from, to, std::slice::from_ref(&b)))
+        })
+    }
+
+    fn launch(&mut self, i: usize, ricochet: bool) {
+        let robot = &mut self.robots[i];
+        if robot.ammo <= 0 {
+            return;
+        }
+        robot.ammo -= 1;
+        let amount = robot.loadout.projectile_damage(&self.config);
+        let projectile = self.spawn_projectile(i, amount);
+        if ricochet {
+            let remaining = self.config.ricochet_bounces;
+            self.world.bounces.insert(projectile, Bounce { remaining });
+            self.world.sprites.insert(projectile, Sprite::Ricochet);
+        } else {
+            self.world.sprites.insert(projectile, Sprite::Projectile);
+        }
+    }
+
+    /// Fire an EMP charge from robot `i` along its turret direction, if it has
+    /// the energy and its last charge has cooled down.
+    pub fn fire_emp(&mut self, i: usize) {
+        let cost = self.config.emp_energy_cost;
+        let robot = &mut self.robots[i];
+        if robot.energy < cost || self.tick < robot.emp_ready_at {
+            return;
+        }
+        robot.energy -= cost;
+        robot.emp_ready_at = self.tick + u64::from(self.config.emp_cooldown);
+        let projectile = self.spawn_projectile(i, 0);
+        let stun_ticks = self.config.emp_stun_ticks;
+        self.world.emps.insert(projectile, Emp { stun_ticks });
+        self.world.sprites.insert(projectile, Sprite::Emp);
+    }
+
+    /// Launch a projectile dealing `amount` damage from robot `i` along its
+    /// turret direction; the caller adds its sprite and any other components.
+    fn spawn_projectile(&mut self, i: usize, amount: i32) -> Entity {
+        let level = self.elevation.level_at(self.robots[i].position);
+        let robot = &mut self.robots[i];
+        robot.shots_fired += 1;
+        // Firing gives the robot away
+        robot.cloaked = false;
+
+        let projectile = self.world.spawn();
+        self.world.transforms.insert(
+            projectile,
+            Transform {
+                position: robot.position,
+                heading: robot.turret_direction(),
+            },
+        );
+        self.world.velocities.insert(
+            projectile,
+            Velocity {
+                speed: self.config.projectile_speed,
+            },
+        );
+        self.world.damages.insert(
+            projectile,
+            Damage {
+                owner: robot.id,
+                amount,
+            },
+        );
+        self.world.levels.insert(projectile, level);
+        self.world.lifetimes.insert(
+            projectile,
+            Lifetime {
+                ticks: self.config.projectile_lifetime,
+            },
+        );
+        projectile
+    }
+
+    /// Stop robot `i`'s program for `ticks` ticks after an EMP hit from robot
+    /// `by`, and set one of its script's registers to a random value.
+    pub fn stun(&mut self, i: usize, ticks: u32, by: usize) {
+        let robot = &mut self.robots[i];
+        robot.stunned = robot.stunned.max(ticks);
+        let writable = |(reg, _): &(Reg, i32)| !is_read_only_register(reg.name());
+        let count = robot.registers.iter().filter(writable).count();
+        let scrambled = if count > 0 {
+            let k = robot.rng.range(0, count as i32 - 1) as usize;
+            let (reg, _) = robot.registers.iter().filter(writable).nth(k).unwrap();
+            let value = robot.rng.range(-EMP_SCRAMBLE_RANGE, EMP_SCRAMBLE_RANGE);
+            robot.registers.set(reg, value);
+            Some(reg.name().to_string())
+        } else {
+            None
+        };
+        let robot = robot.id;
+        self.log(EventKind::Stunned {
+            robot,
+            by,
+            scrambled,
+        });
+    }
+
+    /// The nearest robot or wreck inside robot `i`'s scanner cone that is not
+    /// hidden behind an obstacle or smoke, if any. Wrecks are low enough to scan over.
+    pub fn scan(&self, i: usize) -> Option<ScanContact> {
+        let team = self.robots[i].team;
+        let robots = self
+            .perceived()
+            .filter(|(j, other)| *j != i && other.health > 0)
+            // Cloaked enemies are invisible; teammates still see each other
+            .filter(|(_, other)| !(other.cloaked && other.team != team))
+            .map(|(_, other)| (other.id, other.position, None));
+        let wrecks = self.world.debris.iter().filter_map(|(e, debris)| {
+            let transform = self.world.transforms.get(e)?;
+            Some((debris.robot, transform.position, Some(e)))
+        });
+
+        robots
+            .chain(wrecks)
+            .filter_map(|(id, position, wreck)| {
+                let (dist, bearing) = self.scan_covers(i, position)?;
+                Some((id, position, wreck, dist, bearing))
+            })
+            .min_by(|a, b| a.3.total_cmp(&b.3))
+            .map(|(id, position, wreck, dist, bearing)| ScanContact {
+                id,
+                position,
+                distance: (dist.ceil() as i32).max(1),
+                bearing: (normalize_degrees(bearing).round() as i32).rem_euclid(360),
+                debris: wreck.is_some(),
+                salvage: wreck.is_some_and(|e| self.world.salvage.get(e).is_some()),
+            })
+    }
+
+    /// What `scan_all` senses for robot `i`: the number of living enemies, and
+    /// the distance and bearing of the nearest one that is not cloaked, rounded
+    /// to `scan_all_distance_step` and `scan_all_bearing_step`. It sees in every
+    /// direction and through obstacles, and ignores wrecks.
+    pub fn scan_all(&self, i: usize) -> (i32, Option<(i32, i32)>) {
+        let scanner = &self.robots[i];
+        let enemies = || {
+            self.perceived()
+                .map(|(_, other)| other)
+                .filter(|other| other.team != scanner.team && other.health > 0)
+        };
+        let nearest = enemies()
+            .filter(|other| !other.cloaked)
+            .map(|other| {
+                let (dx, dy) = self.config.displacement(scanner.position, other.position);
+                ((dx * dx + dy * dy).sqrt(), self.config.math.atan2(dy, dx))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(dist, angle)| {
+                let step = self.config.scan_all_distance_step.max(1.0);
+                let distance = ((dist / step).ceil().max(1.0) * step) as i32;
+                let step = self.config.scan_all_bearing_step.max(1.0);
+                let bearing = (normalize_degrees(angle.to_degrees()) / step).round() * step;
+                (distance, (bearing as i32).rem_euclid(360))
+            });
+        (enemies().count() as i32, nearest)
+    }
+
+    /// Distance and bearing (degrees) from robot `i` to `position`, if its
+    /// scanner cone covers the point and nothing blocks the view.
+    fn scan_covers(&self, i: usize, position: (f32, f32)) -> Option<(f32, f32)> {
+        let scanner = &self.robots[i];
+        let (dx, dy) = self.config.displacement(scanner.position, position);
+        let dist = (dx * dx + dy * dy).sqrt();
+        let bearing = self.config.math.atan2(dy, dx).to_degrees();
+        let off_axis = angle_difference(bearing, scanner.scanner_direction()).abs();
+        let visible = dist <= scanner.loadout.scan_range(&self.config)
+            && off_axis <= self.config.scan_half_angle
+            && self.scanner_can_see(
+                scanner.position,
+                (scanner.position.0 + dx, scanner.position.1 + dy),
+            );
+        visible.then_some((dist, bearing))
+    }
+
+    /// Warn every living enemy that robot `i`'s scan covers, not only the
+    /// nearest one the scan reports; they read the scanner's id from their
+    /// `detected` register next tick.
+    pub fn warn_scanned(&mut self, i: usize) {
+        let (scanner_id, team) = (self.robots[i].id, self.robots[i].team);
+        for j in 0..self.robots.len() {
+            let other = self.seen(j);
+            let covered = j != i
+                && other.health > 0
+                && other.team != team
+                && self.scan_covers(i, other.position).is_some();
+            if covered {
+                self.robots[j].detected_by = Some(scanner_id);
+            }
+        }
+    }
+}
+
+/// Value of an operand; unset registers read as 0.
+fn read_operand(robot: &Robot, operand: &Operand) -> i32 {
+    match operand {
+        Operand::Const(n) => *n,
+        Operand::Reg(reg) => robot.registers.get(*reg).unwrap_or(0),
+    }
+}
+
+/// Heading error (degrees) within which a robot or turret counts as facing its
+/// target; `goto` drives instead of turning and `turn_to` finishes.
+const ALIGN_TOLERANCE: f32 = 0.5;
+
+/// Whether a robot centered at `position` would stick out of the arena.
+/// Robots stop at the edges rather than drive off into space where nothing
+/// could reach them and the match could never end; projectiles leaving the
+/// arena are removed by the bounds system instead. A torus has no outside.
+pub(crate) fn outside_arena(position: (f32, f32), config: &GameConfig) -> bool {
+    let half = config.robot_width.max(config.robot_height) / 2.0;
+    config.topology == Topology::Bounded
+        && (position.0 - half < 0.0
+            || position.0 + half > config.arena_width
+            || position.1 - half < 0.0
+            || position.1 + half > config.arena_height)
+}
+
+/// Whether a robot centered at `position` would overlap an obstacle or stick
+/// out of the arena. On a torus a robot may straddle an edge, and overlaps
+/// obstacles on the far side of it too.
+pub(crate) fn movement_blocked(
+    position: (f32, f32),
+    obstacles: &[Obstacle],
+    config: &GameConfig,
+) -> bool {
+    let half = config.robot_width.max(config.robot_height) / 2.0;
+    outside_arena(position, config)
+        || config.tile_offsets().any(|(ox, oy)| {
+            let ghost = (position.0 + ox, position.1 + oy);
+            obstacles.iter().any(|o| o.overlaps_square(ghost, half))
+        })
+}
+
+/// Spend one tick turning toward or driving to `target`.
+/// Returns true once the robot has arrived, or is blocked by an obstacle and
+/// gives up on the target rather than stall forever.
+fn drive_toward(
+    robot: &mut Robot,
+    target: (f32, f32),
+    obstacles: &[Obstacle],
+    config: &GameConfig,
+) -> bool {
+    let (dx, dy) = config.displacement(robot.position, target);
+    let dist = (dx * dx + dy * dy).sqrt();
+    if dist <= config.arrival_tolerance {
+        return true;
+    }
+
+    let off_course = angle_difference(config.math.atan2(dy, dx).to_degrees(), robot.heading);
+    if off_course.abs() > ALIGN_TOLERANCE {
+        let turn_rate = config.turn_rate;
+        robot.turn(Section::Body, off_course.clamp(-turn_rate, turn_rate));
+        return false;
+    }
+
+    let step = robot.move_speed(config).min(dist);
+    let (sin, cos) = config.math.sin_cos(robot.heading.to_radians());
+    let next = config.wrap((robot.position.0 + step * cos, robot.position.1 + step * sin));
+    if movement_blocked(next, obstacles, config) {
+        return true;
+    }
+    robot.position = next;
+    false
+}
+
+/// Bearing in degrees at which a projectile fired now meets the latest scan
+/// contact, assuming it keeps its estimated velocity (or stands still without an
+/// estimate). The time since the scan is taken from the `tick` sensor register.
+fn lead_bearing(robot: &Robot, config: &GameConfig) -> Option<f32> {
+    let projectile_speed = config.projectile_speed;
+    let contact = robot.scan_history.front()?;
+    let (vx, vy) = robot.target_velocity().unwrap_or((0.0, 0.0));
+    let now = robot.registers.get(Reg::TICK).unwrap_or(0) as f32;
+    let since_scan = (now - contact.tick as f32).max(0.0);
+
+    // Refine the flight time a few times; converges quickly for targets slower
+    // than the projectile
+    let mut flight = 0.0;
+    let mut aim = contact.position;
+    for _ in 0..4 {
+        let t = since_scan + flight;
+        aim = (contact.position.0 + vx * t, contact.position.1 + vy * t);
+        let (dx, dy) = config.displacement(robot.position, aim);
+        flight = (dx * dx + dy * dy).sqrt() / projectile_speed.max(f32::EPSILON);
+    }
+    let (dx, dy) = config.displacement(robot.position, aim);
+    Some(normalize_degrees(config.math.atan2(dy, dx).to_degrees()))
+}
+
+/// Signed difference `a - b` between two angles in degrees, wrapped to [-180, 180].
+fn angle_difference(a: f32, b: f32) -> f32 {
+    (a - b + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Drive a human robot by its player's `controls` for one tick, like the
+/// instructions a script would run: it moves, turns and fires at the same
+/// rates. A requested shot is cleared once taken.
+pub fn execute_controls(
+    robot: &mut Robot,
+    controls: Controls,
+    obstacles: &[Obstacle],
+    config: &GameConfig,
+) -> Option<WorldAction> {
+    let drive = controls.drive.clamp(-1.0, 1.0);
+    if drive != 0.0 {
+        let (sin, cos) = config.math.sin_cos(robot.heading.to_radians());
+        let speed = robot.move_speed(config) * drive;
+        let next = config.wrap((
+            robot.position.0 + speed * cos,
+            robot.position.1 + speed * sin,
+        ));
+        if !movement_blocked(next, obstacles, config) {
+            robot.position = next;
+        }
+    }
+    let turn_rate = config.turn_rate;
+    if controls.turn != 0.0 {
+        robot.turn(Section::Body, controls.turn.clamp(-1.0, 1.0) * turn_rate);
+    }
+    if controls.turret != 0.0 {
+        robot.turn(
+            Section::Turret,
+            controls.turret.clamp(-1.0, 1.0) * turn_rate,
+        );
+    }
+    robot.controls = Some(Controls {
+        fire: false,
+        ..controls
+    });
+    controls.fire.then_some(WorldAction::Fire)
+}
+
+/// Execute the instruction at the current instruction pointer for a robot.
+/// Advances the instruction pointer and updates robot state as needed.
+/// Returns the action to resolve against the world, if the instruction has one.
+/// Plugin commands do nothing; see `execute_robot_instruction_with`.
+pub fn execute_robot_instruction(
+    robot: &mut Robot,
+    obstacles: &[Obstacle],
+    config: &GameConfig,
+) -> Option<WorldAction> {
+    execute_robot_instruction_with(robot, obstacles, config, &Plugins::default())
+}
+
+/// Like `execute_robot_instruction`, running plugin commands with `plugins`.
+pub fn execute_robot_instruction_with(
+    robot: &mut Robot,
+    obstacles: &[Obstacle],
+    config: &GameConfig,
+    plugins: &Plugins,
+) -> Option<WorldAction> {
+    robot.resume();
+    if robot.ip >= robot.instruction_queue.len() {
+        return None;
+    }
+    let mut action = None;
+    let turn_rate = config.turn_rate;
+    let instr = &robot.instruction_queue[robot.ip];
+    match instr {
+        Instruction::MoveForward => {
+            let (sin, cos) = config.math.sin_cos(robot.heading.to_radians());
+            let speed = robot.move_speed(config);
+            let next = config.wrap((
+                robot.position.0 + speed * cos,
+                robot.position.1 + speed * sin,
+            ));
+            // Obstacles and the arena walls block movement; the robot stays put
+            if !movement_blocked(next, obstacles, config) {
+                robot.position = next;
+            }
+            robot.ip += 1;
+        }
+        Instruction::GoTo { x, y } => {
+            // The instruction stays current until the robot arrives
+            let target = (*x as f32, *y as f32);
+            if drive_toward(robot, target, obstacles, config) {
+                robot.ip += 1;
+            }
+        }
+        Instruction::AimLead => {
+            let error = lead_bearing(robot, config)
+                .map(|bearing| angle_difference(bearing, robot.turret_direction()));
+            match error {
+                Some(error) if error.abs() > ALIGN_TOLERANCE => {
+                    robot.turn(Section::Turret, error.clamp(-turn_rate, turn_rate));
+                }
+                _ => robot.ip += 1,
+            }
+        }
+        Instruction::TurnTo { section, target } => {
+            // Without a target (e.g. nothing scanned yet) there is nothing to do
+            let target = match target {
+                Operand::Reg(reg) => robot.registers.get(*reg),
+                Operand::Const(n) => Some(*n),
+            };
+            let current = match section {
+                Section::Body => robot.heading,
+                Section::Turret => robot.turret_direction(),
+                Section::Scanner => robot.scanner_direction(),
+            };
+            match target.map(|t| angle_difference(t as f32, current)) {
+                Some(error) if error.abs() > ALIGN_TOLERANCE => {
+                    robot.turn(section.clone(), error.clamp(-turn_rate, turn_rate));
+                }
+                _ => robot.ip += 1,
+            }
+        }
+        Instruction::SetPath { waypoints } => {
+            robot.path = waypoints.clone();
+            robot.set_waypoint(0);
+            robot.ip += 1;
+        }
+        Instruction::FollowPath => match robot.path.get(robot.waypoint) {
+            Some(&(x, y)) => {
+                if drive_toward(robot, (x as f32, y as f32), obstacles, config) {
+                    let next = (robot.waypoint + 1) % robot.path.len();
+                    robot.set_waypoint(next);
+                    robot.ip += 1;
+                }
+            }
+            None => robot.ip += 1,
+        },
+        Instruction::TurnLeft => {
+            robot.turn(Section::Body, -turn_rate);
+            robot.ip += 1;
+        }
+        Instruction::TurnRight => {
+            robot.turn(Section::Body, turn_rate);
+            robot.ip += 1;
+        }
+        Instruction::TurnTurretLeft => {
+            robot.turn(Section::Turret, -turn_rate);
+            robot.ip += 1;
+        }
+        Instruction::TurnTurretRight => {
+            robot.turn(Section::Turret, turn_rate);
+            robot.ip += 1;
+        }
+        Instruction::TurnScannerLeft => {
+            robot.turn(Section::Scanner, -turn_rate);
+            robot.ip += 1;
+        }
+        Instruction::TurnScannerRight => {
+            robot.turn(Section::Scanner, turn_rate);
+            robot.ip += 1;
+        }
+        Instruction::Fire => {
+            action = Some(WorldAction::Fire);
+            robot.ip += 1;
+        }
+        Instruction::FireRicochet => {
+            action = Some(WorldAction::FireRicochet);
+            robot.ip += 1;
+        }
+        Instruction::FireLaser => {
+            action = Some(WorldAction::FireLaser);
+            robot.ip += 1;
+        }
+        Instruction::FireEmp => {
+            action = Some(WorldAction::FireEmp);
+            robot.ip += 1;
+        }
+        Instruction::Smoke => {
+            action = Some(WorldAction::Smoke);
+            robot.ip += 1;
+        }
+        Instruction::Repair => {
+            action = Some(WorldAction::Repair);
+            robot.ip += 1;
+        }
+        Instruction::RepairAlly => {
+            action = Some(WorldAction::RepairAlly);
+            robot.ip += 1;
+        }
+        Instruction::Cloak => {
+            robot.cloaked = robot.energy > 0;
+            robot.ip += 1;
+        }
+        Instruction::Uncloak => {
+            robot.cloaked = false;
+            robot.ip += 1;
+        }
+        Instruction::PointDefense { on } => {
+            robot.point_defense = *on;
+            robot.ip += 1;
+        }
+        Instruction::Hook => {
+            action = Some(WorldAction::Hook);
+            robot.ip += 1;
+        }
+        Instruction::Unhook => {
+            robot.hook = None;
+            robot.ip += 1;
+        }
+        Instruction::Say { text } => {
+            let now = robot.registers.get(Reg::TICK).unwrap_or(0) as u64;
+            robot.speech = Some(Speech {
+                text: text.clone(),
+                tick: now,
+            });
+            action = Some(WorldAction::Say);
+            robot.ip += 1;
+        }
+        Instruction::PersistStore { reg } => {
+            let value = robot.registers.get(*reg).unwrap_or(0);
+            if let Some(stored) = robot.memory.get_mut(reg.name()) {
+                *stored = value;
+            } else if robot.memory.len() < config.max_memory_entries {
+                robot.memory.insert(reg.name().to_string(), value);
+            }
+            robot.ip += 1;
+        }
+        Instruction::PersistLoad { reg } => {
+            if let Some(value) = robot.memory.get(reg.name()) {
+                robot.registers.set(*reg, *value);
+            }
+            robot.ip += 1;
+        }
+        Instruction::Scan => {
+            action = Some(WorldAction::Scan);
+            robot.ip += 1;
+        }
+        Instruction::ScanAll => {
+            action = Some(WorldAction::ScanAll);
+            robot.ip += 1;
+        }
+        Instruction::SelfDestruct => {
+            action = Some(WorldAction::SelfDestruct);
+            robot.ip += 1;
+        }
+        Instruction::LoadCounter { reg, value } => {
+            robot.registers.set(*reg, *value);
+            robot.ip += 1;
+        }
+        Instruction::Dec { reg } => {
+            if let Some(val) = robot.registers.get_mut(*reg) {
+                *val -= 1;
+            }
+            robot.ip += 1;
+        }
+        Instruction::Jnz { reg, label } => {
+            let jump = match robot.registers.get(*reg) {
+                Some(val) => val != 0,
+                None => *reg == Reg::ALWAYS,
+            };
+            if jump {
+                if let Some(target) = robot.instruction_queue.iter().position(|i| match i {
+                    Instruction::Label(l) => l == label,
+                    _ => false,
+                }) {
+                    robot.ip = target;
+                } else {
+                    robot.ip += 1;
+                }
+            } else {
+                robot.ip += 1;
+            }
+        }
+        Instruction::Label(_) => {
+            robot.ip += 1;
+        }
+        Instruction::Set { dst, src } => {
+            let value = read_operand(robot, src);
+            robot.registers.set(*dst, value);
+            robot.ip += 1;
+        }
+        Instruction::Math { op, dst, args } => {
+            // Operations take at most two arguments
+            let mut values = [0; 2];
+            for (value, arg) in values.iter_mut().zip(args) {
+                *value = read_operand(robot, arg);
+            }
+            let value = match op {
+                MathOp::Random => robot.rng.range(values[0], values[1]),
+                _ => op.apply(&values, config.math),
+            };
+            robot.registers.set(*dst, value);
+            robot.ip += 1;
+        }
+        Instruction::Plugin { name, args } => {
+            let values: Vec<i32> = args.iter().map(|a| read_operand(robot, a)).collect();
+            let name = name.clone();
+            // A command whose plugin is not registered does nothing
+            let done = plugins
+                .provider(&name)
+                .is_none_or(|p| p.execute(&name, robot, &values));
+            if done {
+                robot.ip += 1;
+            }
+        }
+        Instruction::ReadHistory { field, dst, index } => {
+            let value = usize::try_from(read_operand(robot, index))
+                .ok()
+                .and_then(|i| robot.scan_history.get(i))
+                .map_or(0, |record| field.read(record));
+            robot.registers.set(*dst, value);
+            robot.ip += 1;
+        }
+    }
+    action
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{
+        Command, Expr, HistoryField, SPEECH_FADE_TICKS, SPEECH_TICKS,
+        translate_commands_to_instructions,
+    };
+    use crate::elevation::Rect;
+
+    fn robot(id: usize, position: (f32, f32), program: Vec<Instruction>) -> Robot {
+        Robot::new(id, "test", position, program)
+    }
+
+    #[test]
+    fn test_scan_sees_robot_in_cone() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::Scan]),
+            robot(2, (150.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.step();
+        assert_eq!(sim.robots[0].registers.get("scan"), Some(100));
+        assert_eq!(sim.robots[0].registers.get("scan_bearing"), Some(0));
+    }
+
+    #[test]
+    fn test_scans_see_the_start_of_the_tick() {
+        // The target moves away in the same tick, before or after the scanner
+        let scanner = robot(1, (50.0, 50.0), vec![Instruction::Scan]);
+        let target = robot(2, (150.0, 50.0), vec![Instruction::MoveForward]);
+        for robots in [
+            vec![scanner.clone(), target.clone()],
+            vec![target.clone(), scanner.clone()],
+        ] {
+            let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+            sim.step();
+            let target = sim.robots.iter().find(|r| r.id == 2).unwrap();
+            assert!(target.position.0 > 150.0);
+            let scanner = sim.robots.iter().find(|r| r.id == 1).unwrap();
+            assert_eq!(scanner.registers.get("scan"), Some(100));
+        }
+    }
+
+    #[test]
+    fn test_robots_act_as_if_at_once() {
+        // Robots 1 and 2 shoot each other, and robot 3 shoots robot 2 too
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::FireLaser]),
+            robot(2, (200.0, 50.0), vec![Instruction::FireLaser]),
+            robot(3, (200.0, 200.0), vec![Instruction::FireLaser]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[1].heading = 180.0;
+        sim.robots[2].heading = 270.0;
+        let lethal = sim.config.combat.laser_damage;
+        sim.robots[0].health = lethal;
+        sim.robots[1].health = lethal;
+        sim.step();
+
+        // Robot 2 fires although robot 1 destroyed it first, and robot 1, which
+        // comes before robot 3, gets the kill they both made
+        assert!(sim.robots[0].health <= 0 && sim.robots[1].health <= 0);
+        let destroyed: Vec<&EventKind> = sim
+            .events
+            .iter()
+            .map(|e| &e.kind)
+            .filter(|k| matches!(k, EventKind::Destroyed { .. }))
+            .collect();
+        assert_eq!(
+            destroyed,
+            vec![
+                &EventKind::Destroyed {
+                    robot: 2,
+                    by: Some(1)
+                },
+                &EventKind::Destroyed {
+                    robot: 1,
+                    by: Some(2)
+                },
+            ]
+        );
+        assert_eq!(sim.robots[2].damage_dealt, 0);
+    }
+
+    #[test]
+    fn test_destroyed_robot_cannot_self_repair() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::FireLaser]),
+            robot(2, (200.0, 50.0), vec![Instruction::Repair]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[1].health = sim.config.combat.laser_damage;
+        sim.step();
+        assert!(sim.robots[1].health <= 0);
+        assert_eq!(sim.alive_count(), 1);
+    }
+
+    #[test]
+    fn test_destroyed_robot_cannot_arm() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::FireLaser]),
+            robot(2, (200.0, 50.0), vec![Instruction::SelfDestruct]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[1].health = sim.config.combat.laser_damage;
+        sim.step();
+        assert!(sim.robots[1].health <= 0);
+        assert_eq!(sim.robots[1].fuse, None);
+        assert!(
+            !sim.events
+                .iter()
+                .any(|e| matches!(e.kind, EventKind::SelfDestructArmed { .. }))
+        );
+    }
+
+    #[test]
+    fn test_scan_blocked_by_obstacle() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::Scan]),
+            robot(2, (150.0, 50.0), vec![]),
+        ];
+        let wall = Obstacle::new(90.0, 0.0, 10.0, 100.0);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        sim.step();
+        assert_eq!(sim.robots[0].registers.get("scan"), Some(0));
+    }
+
+    #[test]
+    fn test_scan_all_senses_every_direction_coarsely() {
+        let robots = vec![
+            robot(
+                1,
+                (50.0, 50.0),
+                vec![Instruction::ScanAll, Instruction::ScanAll],
+            ),
+            // Behind the scanner and a wall: sensed anyway
+            robot(2, (47.0, 170.0), vec![]),
+            // Nearer, but cloaked: counted, not located
+            robot(3, (100.0, 50.0), vec![]),
+            robot(4, (60.0, 50.0), vec![]),
+        ];
+        let wall = Obstacle::new(0.0, 100.0, 200.0, 10.0);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        sim.robots[2].cloaked = true;
+        sim.robots[3].team = 1; // Teammates are not enemies
+        sim.step();
+        let registers = &sim.robots[0].registers;
+        assert_eq!(registers["enemies_alive"], 2);
+        assert_eq!(registers["nearest_dist"], 150);
+        assert_eq!(registers["nearest_bearing"], 90);
+
+        sim.robots[1].cloaked = true;
+        sim.step();
+        let registers = &sim.robots[0].registers;
+        assert_eq!(registers["enemies_alive"], 2);
+        assert_eq!(registers["nearest_dist"], 0);
+        assert_eq!(registers["nearest_bearing"], 90);
+    }
+
+    #[test]
+    fn test_sweep_scans_each_step_of_the_arc() {
+        let program = translate_commands_to_instructions(&[Command::Sweep { arc: -30, step: 10 }]);
+        let target = 25.0f32.to_radians();
+        let robots = vec![
+            robot(1, (50.0, 50.0), program),
+            robot(
+                2,
+                (50.0 + 100.0 * target.cos(), 50.0 + 100.0 * target.sin()),
+                vec![],
+            ),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        for _ in 0..200 {
+            sim.step();
+        }
+        let scanner = &sim.robots[0];
+        assert_eq!(scanner.scanner_direction(), 30.0);
+        // Seen from 20 and 30 degrees, not from 0 or 10
+        assert_eq!(scanner.registers["scan_count"], 2);
+        assert_eq!(scanner.registers["scan_bearing"], 25);
+    }
+
+    #[test]
+    fn test_random_streams_are_per_robot() {
+        let script = "let a = random(1, 1000)\nlet b = random(1, 1000)";
+        let program =
+            translate_commands_to_instructions(&crate::parser::parse_script(script).unwrap());
+        let draws = |robots: usize, seed: u64| {
+            let robots = (1..=robots)
+                .map(|id| robot(id, (50.0 * id as f32, 50.0), program.clone()))
+                .collect();
+            let mut sim = Simulation::new(GameConfig::default(), robots, vec![]).with_seed(seed);
+            for _ in 0..20 {
+                sim.step();
+            }
+            let registers = &sim.robots[1].registers;
+            (registers["a"], registers["b"])
+        };
+        // A third bot joining leaves the second one's numbers alone
+        assert_eq!(draws(2, 7), draws(3, 7));
+        assert_ne!(draws(2, 7), draws(2, 8));
+        let (a, b) = draws(2, 7);
+        assert!((1..=1000).contains(&a) && (1..=1000).contains(&b));
+    }
+
+    #[test]
+    fn test_torus_arena_wraps_movement_scans_and_shots() {
+        let config = GameConfig {
+            topology: Topology::Torus,
+            ..GameConfig::default()
+        };
+        let mut runner = robot(1, (399.9, 200.0), vec![Instruction::MoveForward]);
+        runner.heading = 0.0;
+        // Across the right edge from the scanner, 30 units away the short way
+        let scanner = robot(
+            2,
+            (380.0, 100.0),
+            vec![Instruction::Scan, Instruction::Fire],
+        );
+        let target = robot(3, (10.0, 100.0), vec![]);
+        let mut sim = Simulation::new(config, vec![runner, scanner, target], vec![]);
+        sim.step();
+        let runner = &sim.robots[0];
+        assert!(runner.position.0 < 1.0, "wrapped to {:?}", runner.position);
+        assert_eq!(sim.robots[1].registers["scan"], 30);
+        assert_eq!(sim.robots[1].registers["scan_bearing"], 0);
+
+        let health = sim.robots[2].health;
+        for _ in 0..10 {
+            sim.step();
+        }
+        assert!(sim.robots[2].health < health, "the shot wraps around too");
+
+        // Obstacles on the far side of an edge block the view across it
+        let wall = Obstacle::new(0.0, 90.0, 5.0, 20.0);
+        let robots = vec![
+            robot(1, (380.0, 100.0), vec![Instruction::Scan]),
+            robot(2, (10.0, 100.0), vec![]),
+        ];
+        let mut sim = Simulation::new(sim.config.clone(), robots, vec![wall]);
+        sim.step();
+        assert_eq!(sim.robots[0].registers["scan"], 0);
+    }
+
+    #[test]
+    fn test_added_robots_get_fresh_ids_and_unique_names() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![]),
+            robot(4, (90.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        // Robots passed to `new` keep their ids, but not a taken name
+        assert_eq!(sim.robots[1].id, 4);
+        assert_eq!(sim.robots[1].name, "test (2)");
+
+        let first = sim.add_robot(robot(1, (150.0, 50.0), vec![]));
+        let mut drone = robot(1, (250.0, 50.0), vec![]);
+        drone.name = "drone".to_string();
+        drone.team = 99;
+        let second = sim.add_robot(drone);
+        assert_eq!(first, RobotHandle { id: 5 });
+        assert_eq!(second, RobotHandle { id: 6 });
+
+        let added = sim.robot(first).unwrap();
+        assert_eq!(added.name, "test (3)");
+        assert_eq!(added.team, 5);
+        assert_eq!(added.position, (150.0, 50.0));
+        assert_eq!(sim.robot(second).unwrap().name, "drone");
+        assert_eq!(sim.robot(second).unwrap().team, 99);
+
+        sim.robot_mut(second).unwrap().health = 0;
+        assert_eq!(sim.robots[3].health, 0);
+        assert!(sim.robot(RobotHandle { id: 2 }).is_none());
+    }
+
+    #[test]
+    fn test_human_robot_follows_its_controls() {
+        let mut human = robot(1, (100.0, 100.0), vec![]);
+        human.controls = Some(Controls {
+            drive: 1.0,
+            turret: -1.0,
+            fire: true,
+            ..Controls::default()
+        });
+        let robots = vec![human, robot(2, (300.0, 300.0), vec![])];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        let speed = sim.robots[0].move_speed(&sim.config);
+        sim.step();
+        sim.step();
+        let human = &sim.robots[0];
+        assert!((human.position.0 - (100.0 + 2.0 * speed)).abs() < 1e-4);
+        assert_eq!(human.turret_heading, 360.0 - 2.0 * sim.config.turn_rate);
+        // One press is one shot, however long the controls stay as they are
+        assert_eq!(human.shots_fired, 1);
+        assert_eq!(human.controls.map(|c| c.fire), Some(false));
+
+        sim.robots[0].controls = Some(Controls {
+            drive: -1.0,
+            turn: 0.5,
+            ..Controls::default()
+        });
+        sim.step();
+        let human = &sim.robots[0];
+        assert_eq!(human.heading, sim.config.turn_rate / 2.0);
+        assert!(human.position.0 < 100.0 + 2.0 * speed);
+    }
+
+    #[test]
+    fn test_say_is_logged_and_shown_for_a_while() {
+        let say = Instruction::Say {
+            text: "reloading".to_string(),
+        };
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::Scan, say]),
+            robot(2, (150.0, 150.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.step();
+        sim.step();
+        assert!(sim.events.iter().any(|e| e.kind
+            == EventKind::Said {
+                robot: 1,
+                text: "reloading".to_string()
+            }));
+        let speaker = &sim.robots[0];
+        let said_at = speaker.speech.as_ref().unwrap().tick;
+        assert_eq!(speaker.speech_at(said_at), Some("reloading"));
+        assert_eq!(
+            speaker.speech_at(said_at + SPEECH_TICKS - 1),
+            Some("reloading")
+        );
+        assert_eq!(speaker.speech_at(said_at + SPEECH_TICKS), None);
+        // Fully visible until it starts fading out near the end
+        let speech = speaker.speech.as_ref().unwrap();
+        let fade_start = said_at + SPEECH_TICKS - SPEECH_FADE_TICKS;
+        assert_eq!(speech.opacity(said_at), 1.0);
+        assert_eq!(speech.opacity(fade_start), 1.0);
+        assert_eq!(speech.opacity(fade_start + SPEECH_FADE_TICKS / 2), 0.5);
+        assert_eq!(speech.opacity(said_at + SPEECH_TICKS), 0.0);
+    }
+
+    #[test]
+    fn test_scanned_robots_are_warned() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::Scan]),
+            robot(2, (150.0, 50.0), vec![]),
+            // Behind robot 2 but still in the cone: warned too
+            robot(3, (250.0, 52.0), vec![]),
+            robot(4, (50.0, 250.0), vec![]),
+            robot(5, (120.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[4].team = 1; // A teammate is not warned
+        sim.step();
+        assert_eq!(sim.robots[0].registers["scan"], 70);
+        sim.step();
+        let detected: Vec<i32> = sim.robots.iter().map(|r| r.registers["detected"]).collect();
+        assert_eq!(detected, vec![0, 1, 1, 0, 0]);
+        // The program has ended, so there are no more scans and the warning clears
+        sim.step();
+        assert_eq!(sim.robots[1].registers["detected"], 0);
+    }
+
+    #[test]
+    fn test_projectile_hits_robot_and_credits_kill() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::Fire]),
+            robot(2, (70.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[1].health = sim.config.combat.projectile_damage;
+        for _ in 0..10 {
+            sim.step();
+        }
+        // The projectile is consumed; only the wreck is left
+        assert_eq!(sim.world.entity_count(), 1);
+        assert_eq!(sim.world.debris.iter().count(), 1);
+        assert_eq!(sim.robots[1].health, 0);
+        assert_eq!(sim.robots[0].kills, 1);
+        assert_eq!(sim.robots[0].ammo, crate::ast::STARTING_AMMO - 1);
+    }
+
+    #[test]
+    fn test_projectile_stopped_by_obstacle() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::Fire]),
+            robot(2, (150.0, 50.0), vec![]),
+        ];
+        let wall = Obstacle::new(90.0, 0.0, 10.0, 100.0);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        for _ in 0..40 {
+            sim.step();
+        }
+        assert_eq!(sim.world.entity_count(), 0);
+        assert_eq!(sim.robots[1].health, sim.robots[1].max_health);
+    }
+
+    #[test]
+    fn test_ricochet_bounces_back_with_less_damage() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::FireRicochet]),
+            robot(2, (20.0, 50.0), vec![]),
+        ];
+        let wall = Obstacle::new(90.0, 0.0, 10.0, 100.0);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        for _ in 0..40 {
+            sim.step();
+        }
+        // Off the wall, past the shooter and into the robot behind it
+        assert_eq!(sim.world.entity_count(), 0);
+        let expected = (sim.config.combat.projectile_damage as f32
+            * sim.config.combat.ricochet_damage_factor)
+            .round() as i32;
+        assert_eq!(sim.robots[1].health, sim.robots[1].max_health - expected);
+        assert_eq!(sim.robots[0].health, sim.robots[0].max_health);
+    }
+
+    #[test]
+    fn test_ricochet_breaks_after_last_bounce() {
+        let robots = vec![robot(1, (200.0, 200.0), vec![Instruction::FireRicochet])];
+        let config = GameConfig {
+            ricochet_bounces: 2,
+            projectile_lifetime: 1000,
+            ..GameConfig::default()
+        };
+        let mut sim = Simulation::new(config, robots, vec![]);
+        sim.step();
+        let shot = sim.world.bounces.iter().next().map(|(e, _)| e).unwrap();
+        // Bounces between the left and right arena edges, staying inside
+        for _ in 0..240 {
+            sim.step();
+            let t = sim.world.transforms.get(shot).unwrap();
+            assert!((0.0..=400.0).contains(&t.position.0));
+        }
+        assert_eq!(sim.world.bounces.get(shot).unwrap().remaining, 0);
+        // The third wall hit breaks it
+        for _ in 0..20 {
+            sim.step();
+        }
+        assert!(!sim.world.is_alive(shot));
+    }
+
+    #[test]
+    fn test_laser_hits_instantly_unless_blocked() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::FireLaser]),
+            robot(2, (200.0, 50.0), vec![]),
+            robot(3, (50.0, 200.0), vec![Instruction::FireLaser]),
+            robot(4, (200.0, 200.0), vec![]),
+        ];
+        let wall = Obstacle::new(120.0, 150.0, 10.0, 100.0);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        sim.step();
+
+        let config = &sim.config;
+        assert_eq!(
+            sim.robots[1].health,
+            sim.robots[1].max_health - config.combat.laser_damage
+        );
+        assert_eq!(sim.robots[3].health, sim.robots[3].max_health);
+        assert_eq!(
+            sim.robots[0].energy,
+            crate::ast::STARTING_ENERGY - config.laser_energy_cost
+        );
+
+        // The beams end at the target's hull and at the wall
+        let mut lengths: Vec<f32> = sim.world.beams.iter().map(|(_, b)| b.length).collect();
+        lengths.sort_by(f32::total_cmp);
+        assert!((lengths[0] - 70.0).abs() < 1e-3);
+        assert!((lengths[1] - 145.0).abs() < 1e-3);
+
+        let (_, _, brightness) = sim.world.visible_beams().next().unwrap();
+        assert!(brightness < 1.0 && brightness > 0.0);
+        for _ in 0..sim.config.laser_beam_ticks {
+            sim.step();
+        }
+        assert_eq!(sim.world.visible_beams().count(), 0);
+    }
+
+    #[test]
+    fn test_laser_needs_energy() {
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            vec![
+                robot(1, (50.0, 50.0), vec![Instruction::FireLaser]),
+                robot(2, (100.0, 50.0), vec![]),
+            ],
+            vec![],
+        );
+        sim.robots[0].energy = sim.config.laser_energy_cost - 1;
+        sim.step();
+        assert_eq!(sim.robots[1].health, sim.robots[1].max_health);
+        assert_eq!(sim.world.entity_count(), 0);
+    }
+
+    #[test]
+    fn test_smoke_hides_from_scanners_until_it_clears() {
+        let program = vec![
+            Instruction::Label("look".to_string()),
+            Instruction::Scan,
+            Instruction::Jnz {
+                reg: Reg::named("always"),
+                label: "look".to_string(),
+            },
+        ];
+        let robots = vec![
+            robot(1, (50.0, 50.0), program),
+            robot(2, (150.0, 50.0), vec![Instruction::Smoke]),
+        ];
+        let config = GameConfig {
+            smoke_ticks: 20,
+            ..GameConfig::default()
+        };
+        let mut sim = Simulation::new(config, robots, vec![]);
+        sim.step();
+        sim.step();
+        assert_eq!(sim.robots[0].registers.get("scan"), Some(0));
+        assert_eq!(
+            sim.robots[1].energy,
+            crate::ast::STARTING_ENERGY - sim.config.smoke_energy_cost
+        );
+        assert!(!sim.scanner_can_see((50.0, 50.0), (150.0, 50.0)));
+        // Smoke does not block movement or shots
+        assert!(sim.blockers().is_empty());
+
+        for _ in 0..20 {
+            sim.step();
+        }
+        assert_eq!(sim.world.smoke_clouds().count(), 0);
+        // One more pass through the scan loop
+        for _ in 0..3 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].registers.get("scan"), Some(100));
+    }
+
+    #[test]
+    fn test_cloak_hides_from_enemy_scans_until_firing() {
+        let scan_loop = vec![
+            Instruction::Label("look".to_string()),
+            Instruction::Scan,
+            Instruction::Jnz {
+                reg: Reg::named("always"),
+                label: "look".to_string(),
+            },
+        ];
+        let cloaker = vec![
+            Instruction::Cloak,
+            Instruction::LoadCounter {
+                reg: Reg::named("n"),
+                value: 10,
+            },
+            Instruction::Label("wait".to_string()),
+            Instruction::Dec {
+                reg: Reg::named("n"),
+            },
+            Instruction::Jnz {
+                reg: Reg::named("n"),
+                label: "wait".to_string(),
+            },
+            Instruction::Fire,
+        ];
+        let robots = vec![
+            robot(1, (50.0, 50.0), scan_loop.clone()),
+            robot(2, (150.0, 50.0), cloaker),
+            robot(3, (150.0, 150.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[2].team = 2; // Robot 3 is the cloaker's teammate
+        sim.robots[2].scanner_heading = 270.0;
+        sim.robots[2].instruction_queue = scan_loop;
+        for _ in 0..3 {
+            sim.step();
+        }
+        assert!(sim.robots[1].cloaked);
+        assert_eq!(sim.robots[0].registers["scan"], 0);
+        assert_eq!(sim.robots[2].registers["scan"], 100);
+        assert_eq!(sim.robots[1].registers["cloaked"], 1);
+        assert_eq!(sim.robots[1].energy, crate::ast::STARTING_ENERGY - 3);
+
+        // Firing drops the cloak
+        while sim.robots[1].shots_fired == 0 {
+            sim.step();
+        }
+        assert!(!sim.robots[1].cloaked);
+        for _ in 0..3 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].registers["scan"], 100);
+    }
+
+    #[test]
+    fn test_build_changes_robot_stats() {
+        let config = GameConfig::default();
+        let bots = [(
+            "scout",
+            "build armor 0 speed 4 weapon 2 scanner 2\nloop {\n move forward 1\n}",
+        )]
+        .map(|(name, script)| crate::setup::BotScript {
+            entrant: name.to_string(),
+            name: name.to_string(),
+            script: script.to_string(),
+        });
+        let mut robots = crate::setup::compile_robots(&bots, &config).unwrap();
+        robots.push(robot(2, (300.0, 300.0), vec![]));
+        let start = robots[0].position;
+        let mut sim = Simulation::new(config, robots, vec![]);
+        assert_eq!(sim.robots[0].health, crate::ast::STARTING_HEALTH - 6);
+        assert_eq!(sim.robots[0].max_health, crate::ast::STARTING_HEALTH - 6);
+        // Loop label, load the counter, move label, then move
+        for _ in 0..4 {
+            sim.step();
+        }
+        let (dx, dy) = (
+            sim.robots[0].position.0 - start.0,
+            sim.robots[0].position.1 - start.1,
+        );
+        let moved = (dx * dx + dy * dy).sqrt();
+        assert!(
+            (moved - sim.config.move_speed * 1.5).abs() < 1e-5,
+            "moved {moved}"
+        );
+    }
+
+    #[test]
+    fn test_repair_converts_energy_while_standing_still() {
+        let program = translate_commands_to_instructions(&[Command::Repair {
+            ally: false,
+            ticks: 5,
+        }]);
+        let robots = vec![
+            robot(1, (50.0, 50.0), program),
+            robot(2, (300.0, 300.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[0].health = 5;
+        // Load the counter, then five rounds of label, repair, dec and jump
+        for _ in 0..21 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].health, 10);
+        assert_eq!(
+            sim.robots[0].energy,
+            crate::ast::STARTING_ENERGY - 5 * sim.config.repair_energy_cost
+        );
+
+        // No repairs while sliding, or at full health
+        sim.robots[0].health = 5;
+        sim.robots[0].knockback = (1.0, 0.0);
+        sim.repair(0);
+        assert_eq!(sim.robots[0].health, 5);
+        sim.robots[0].knockback = (0.0, 0.0);
+        sim.robots[0].health = 10;
+        let energy = sim.robots[0].energy;
+        sim.repair(0);
+        assert_eq!(sim.robots[0].energy, energy);
+    }
+
+    #[test]
+    fn test_repair_ally_heals_nearest_damaged_teammate() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::RepairAlly]),
+            robot(2, (80.0, 50.0), vec![]),
+            robot(3, (60.0, 50.0), vec![]),
+            // Too far away
+            robot(4, (50.0, 150.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        for robot in &mut sim.robots {
+            robot.team = 1;
+            robot.health = 4;
+        }
+        sim.robots[2].team = 3; // Not a teammate
+        sim.step();
+        let health: Vec<i32> = sim.robots.iter().map(|r| r.health).collect();
+        assert_eq!(health, vec![4, 5, 4, 4]);
+        let (transform, beam, _) = sim.world.visible_beams().next().unwrap();
+        assert_eq!(beam.kind, BeamKind::Repair);
+        assert_eq!(beam.length, 30.0);
+        assert_eq!(transform.heading, 0.0);
+    }
+
+    #[test]
+    fn test_persist_store_is_capped() {
+        let store = |reg: &str| Instruction::PersistStore {
+            reg: Reg::named(reg),
+        };
+        let program = vec![store("a"), store("b"), store("c"), store("a")];
+        let robots = vec![
+            robot(1, (50.0, 50.0), program),
+            robot(2, (300.0, 300.0), vec![]),
+        ];
+        let config = GameConfig {
+            max_memory_entries: 2,
+            ..GameConfig::default()
+        };
+        let mut sim = Simulation::new(config, robots, vec![]);
+        sim.robots[0].registers.set("a", 7);
+        for _ in 0..3 {
+            sim.step();
+        }
+        // The memory is full, so `c` is dropped but `a` can still be updated
+        assert_eq!(sim.robots[0].memory.len(), 2);
+        assert!(!sim.robots[0].memory.contains_key("c"));
+        sim.robots[0].registers.set("a", 9);
+        sim.step();
+        assert_eq!(sim.robots[0].memory["a"], 9);
+    }
+
+    #[test]
+    fn test_cloak_drops_without_energy() {
+        let robots = vec![robot(1, (50.0, 50.0), vec![Instruction::Cloak])];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[0].energy = 3;
+        sim.step();
+        assert!(sim.robots[0].cloaked);
+        sim.step();
+        sim.step();
+        assert_eq!(sim.robots[0].energy, 0);
+        assert!(!sim.robots[0].cloaked);
+
+        // Without energy the cloak does not come on
+        sim.robots[0].ip = 0;
+        sim.step();
+        assert!(!sim.robots[0].cloaked);
+    }
+
+    #[test]
+    fn test_heading_stays_normalized_while_turning() {
+        let program = vec![
+            Instruction::Label("spin".to_string()),
+            Instruction::TurnLeft,
+            Instruction::Jnz {
+                reg: Reg::named("always"),
+                label: "spin".to_string(),
+            },
+        ];
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            vec![robot(1, (50.0, 50.0), program)],
+            vec![],
+        );
+        for _ in 0..3 * 400 {
+            sim.step();
+        }
+        // 400 turns of one degree to the left
+        assert!((sim.robots[0].heading - 320.0).abs() < 1e-2);
+        assert_eq!(sim.robots[0].registers.get("heading"), Some(320));
+    }
+
+    #[test]
+    fn test_goto_turns_and_arrives() {
+        let program = vec![Instruction::GoTo { x: 50, y: 80 }, Instruction::Fire];
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            vec![robot(1, (50.0, 50.0), program)],
+            vec![],
+        );
+        for _ in 0..300 {
+            sim.step();
+        }
+        let robot = &sim.robots[0];
+        assert_eq!(robot.registers.get("heading"), Some(90));
+        assert!((robot.position.1 - 80.0).abs() <= sim.config.arrival_tolerance);
+        // Moved on past the goto and fired
+        assert_eq!(robot.ammo, crate::ast::STARTING_AMMO - 1);
+    }
+
+    #[test]
+    fn test_goto_gives_up_when_blocked() {
+        let program = vec![Instruction::GoTo { x: 150, y: 50 }];
+        let wall = Obstacle::new(90.0, 0.0, 10.0, 100.0);
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            vec![robot(1, (50.0, 50.0), program)],
+            vec![wall],
+        );
+        for _ in 0..300 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].ip, 1);
+        assert!(sim.robots[0].position.0 < 90.0);
+    }
+
+    #[test]
+    fn test_follow_path_cycles_waypoints() {
+        let program = vec![
+            Instruction::SetPath {
+                waypoints: vec![(50, 60), (60, 60)],
+            },
+            Instruction::Label("patrol".to_string()),
+            Instruction::FollowPath,
+            Instruction::Jnz {
+                reg: Reg::named("always"),
+                label: "patrol".to_string(),
+            },
+        ];
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            vec![robot(1, (50.0, 50.0), program)],
+            vec![],
+        );
+        sim.step();
+        assert_eq!(sim.robots[0].registers.get("waypoint"), Some(0));
+
+        let mut visited = Vec::new();
+        for _ in 0..1000 {
+            sim.step();
+            let waypoint = sim.robots[0].waypoint;
+            if visited.last() != Some(&waypoint) {
+                visited.push(waypoint);
+            }
+        }
+        assert_eq!(visited[..4], [0, 1, 0, 1]);
+        assert_eq!(
+            sim.robots[0].registers.get("waypoint"),
+            Some(sim.robots[0].waypoint as i32)
+        );
+    }
+
+    #[test]
+    fn test_math_instructions_write_registers() {
+        let program = vec![
+            Instruction::Set {
+                dst: Reg::named("dx"),
+                src: Operand::Const(-30),
+            },
+            Instruction::Math {
+                op: crate::ast::MathOp::Atan2,
+                dst: Reg::named("bearing"),
+                args: vec![
+                    Operand::Reg(Reg::named("unset")),
+                    Operand::Reg(Reg::named("dx")),
+                ],
+            },
+        ];
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            vec![robot(1, (50.0, 50.0), program)],
+            vec![],
+        );
+        sim.step();
+        sim.step();
+        assert_eq!(sim.robots[0].registers.get("bearing"), Some(180));
+    }
+
+    #[test]
+    fn test_scans_fill_history() {
+        let program = translate_commands_to_instructions(&[
+            Command::Scan,
+            Command::Scan,
+            Command::Let {
+                name: "old_x".to_string(),
+                expr: Expr::History {
+                    field: HistoryField::X,
+                    index: Box::new(Expr::Number(1)),
+                },
+            },
+            Command::Let {
+                name: "missing".to_string(),
+                expr: Expr::History {
+                    field: HistoryField::Id,
+                    index: Box::new(Expr::Number(5)),
+                },
+            },
+        ]);
+        let robots = vec![
+            robot(1, (50.0, 50.0), program),
+            robot(2, (150.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.step();
+        sim.robots[1].position = (160.0, 51.0);
+        for _ in 0..3 {
+            sim.step();
+        }
+
+        let scanner = &sim.robots[0];
+        assert_eq!(scanner.registers["scan_count"], 2);
+        let latest = scanner.scan_history[0];
+        assert_eq!((latest.id, latest.position), (2, (160.0, 51.0)));
+        assert_eq!(latest.tick, scanner.scan_history[1].tick + 1);
+        assert_eq!(scanner.registers["old_x"], 150);
+        assert_eq!(scanner.registers["missing"], 0);
+    }
+
+    #[test]
+    fn test_fire_lead_hits_moving_target() {
+        let mut program = vec![Instruction::Scan, Instruction::Scan];
+        program.extend(translate_commands_to_instructions(&[Command::FireLead]));
+        let mut target = robot(2, (150.0, 40.0), vec![Instruction::MoveForward; 200]);
+        target.turn(Section::Body, 90.0);
+        let robots = vec![robot(1, (50.0, 50.0), program), target];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        for _ in 0..60 {
+            sim.step();
+        }
+        let shooter = &sim.robots[0];
+        assert_eq!(shooter.registers["target_vy"], 200);
+        // Aimed ahead of the last contact, which was almost straight ahead
+        assert!(shooter.turret_direction() > 10.0);
+        assert!(sim.robots[1].health < sim.robots[1].max_health);
+    }
+
+    #[test]
+    fn test_aim_at_scanned_turns_turret_to_contact() {
+        let mut program = vec![Instruction::Scan];
+        program.extend(translate_commands_to_instructions(&[Command::AimAtScanned]));
+        program.push(Instruction::Fire);
+        let robots = vec![
+            robot(1, (50.0, 50.0), program),
+            robot(2, (100.0, 53.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        for _ in 0..10 {
+            sim.step();
+        }
+        // Contact at ~3.4 degrees: the turret turns in 1 degree steps, then fires
+        let shooter = &sim.robots[0];
+        assert_eq!(shooter.registers.get("scan_bearing"), Some(3));
+        assert!((shooter.turret_direction() - 3.0).abs() < 1e-3);
+        assert_eq!(shooter.heading, 0.0);
+        assert_eq!(shooter.ammo, crate::ast::STARTING_AMMO - 1);
+    }
+
+    #[test]
+    fn test_aim_without_contact_is_skipped() {
+        let program = translate_commands_to_instructions(&[Command::AimAtScanned]);
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            vec![robot(1, (50.0, 50.0), program)],
+            vec![],
+        );
+        sim.step();
+        assert_eq!(sim.robots[0].ip, 1);
+        assert_eq!(sim.robots[0].turret_heading, 0.0);
+    }
+
+    #[test]
+    fn test_self_destruct_damages_robots_in_blast() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::SelfDestruct]),
+            robot(2, (70.0, 50.0), vec![]),
+            robot(3, (300.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[1].health = sim.config.combat.self_destruct_damage;
+        let fuse = sim.config.self_destruct_fuse;
+
+        // The fuse starts burning on the tick it is armed
+        sim.step();
+        assert_eq!(sim.robots[0].fuse, Some(fuse - 1));
+        for _ in 1..fuse {
+            assert!(sim.robots[0].health > 0);
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].health, 0);
+        assert_eq!(sim.robots[1].health, 0);
+        assert_eq!(sim.robots[2].health, sim.robots[2].max_health);
+        assert_eq!(sim.robots[0].kills, 1);
+
+        let kinds: Vec<_> = sim.events.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EventKind::SelfDestructArmed { robot: 1, fuse },
+                EventKind::Detonated {
+                    robot: 1,
+                    hit: vec![2]
+                },
+                // The bomber is destroyed by its own blast, without a kill for it
+                EventKind::Destroyed {
+                    robot: 1,
+                    by: Some(1)
+                },
+                EventKind::Destroyed {
+                    robot: 2,
+                    by: Some(1)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_king_of_the_hill_scores_uncontested_zone() {
+        let robots = vec![
+            robot(1, (200.0, 200.0), vec![]),
+            robot(2, (210.0, 200.0), vec![]),
+            robot(3, (20.0, 20.0), vec![]),
+        ];
+        let config = GameConfig {
+            koth_target_score: 5,
+            ..GameConfig::default()
+        };
+        let mut sim = Simulation::new(config, robots, vec![]).with_mode(GameMode::KingOfTheHill);
+
+        // Two teams in the zone: contested, nobody scores
+        sim.step();
+        assert!(sim.scores.is_empty());
+        assert_eq!(sim.robots[0].registers.get("in_zone"), Some(1));
+        assert_eq!(sim.robots[2].registers.get("in_zone"), Some(0));
+
+        sim.robots[1].team = 1;
+        for _ in 0..5 {
+            sim.step();
+        }
+        assert_eq!(sim.scores.get(&1), Some(&5));
+        assert!(sim.is_over());
+        assert_eq!(sim.winning_team(), Some(1));
+    }
+
+    #[test]
+    fn test_arena_walls_block_movement() {
+        let robots = vec![robot(1, (394.9, 50.0), vec![Instruction::MoveForward])];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.step();
+        assert_eq!(sim.robots[0].position, (394.9, 50.0));
+    }
+
+    #[test]
+    fn test_outside_arena() {
+        let config = GameConfig::default();
+        let half = config.robot_width.max(config.robot_height) / 2.0;
+        assert!(!outside_arena((half, half), &config));
+        assert!(!outside_arena((200.0, 200.0), &config));
+        assert!(outside_arena((half - 0.1, 200.0), &config));
+        assert!(outside_arena(
+            (200.0, config.arena_height - half + 0.1),
+            &config
+        ));
+        let torus = GameConfig {
+            topology: Topology::Torus,
+            ..GameConfig::default()
+        };
+        assert!(!outside_arena((0.0, 0.0), &torus));
+    }
+
+    #[test]
+    fn test_goto_gives_up_at_arena_walls() {
+        let program = vec![Instruction::GoTo { x: 500, y: 50 }];
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            vec![robot(1, (350.0, 50.0), program)],
+            vec![],
+        );
+        for _ in 0..300 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].ip, 1);
+        assert!(!outside_arena(sim.robots[0].position, &sim.config));
+    }
+
+    #[test]
+    fn test_obstacle_blocks_movement() {
+        let robots = vec![robot(1, (84.9, 50.0), vec![Instruction::MoveForward])];
+        let wall = Obstacle::new(90.0, 0.0, 10.0, 100.0);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        sim.step();
+        assert_eq!(sim.robots[0].position, (84.9, 50.0));
+    }
+
+    #[test]
+    fn test_wreck_is_scanned_as_debris_and_stops_projectiles() {
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::Scan, Instruction::Fire]),
+            robot(2, (100.0, 50.0), vec![]),
+            robot(3, (150.0, 50.0), vec![]),
+        ];
+        let config = GameConfig {
+            wreck_lifetime: 60,
+            ..GameConfig::default()
+        };
+        let mut sim = Simulation::new(config, robots, vec![]);
+        sim.damage(1, 100, None);
+        assert_eq!(sim.world.debris.iter().count(), 1);
+
+        sim.step();
+        let registers = &sim.robots[0].registers;
+        assert_eq!(registers.get("scan"), Some(50));
+        assert_eq!(registers.get("scan_debris"), Some(1));
+        assert!(sim.robots[0].scan_history.is_empty());
+
+        for _ in 0..30 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[2].health, sim.robots[2].max_health);
+        assert_eq!(sim.world.entity_count(), 1);
+
+        // The wreck decays and no longer blocks anything
+        for _ in 0..30 {
+            sim.step();
+        }
+        assert_eq!(sim.world.entity_count(), 0);
+    }
+
+    #[test]
+    fn test_emp_stuns_and_scrambles_the_target() {
+        let compile = |script: &str| {
+            translate_commands_to_instructions(&crate::parser::parse_script(script).unwrap())
+        };
+        let robots = vec![
+            robot(1, (50.0, 50.0), compile("loop { fire emp }")),
+            robot(
+                2,
+                (150.0, 50.0),
+                compile("let n = 7\nloop { rotate body 10 }"),
+            ),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        let (health, energy) = (sim.robots[1].health, sim.robots[0].energy);
+        while sim.robots[1].stunned == 0 && sim.tick < 60 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[1].stunned, sim.config.emp_stun_ticks);
+        assert_eq!(sim.robots[1].health, health);
+        let Some(EventKind::Stunned {
+            robot: 2,
+            by: 1,
+            scrambled: Some(reg),
+        }) = sim.events.last().map(|e| &e.kind)
+        else {
+            panic!("no stun logged: {:?}", sim.events);
+        };
+        assert!(!crate::ast::is_read_only_register(reg));
+
+        // The program stands still until the stun wears off
+        let heading = sim.robots[1].heading;
+        for _ in 0..sim.config.emp_stun_ticks {
+            sim.step();
+        }
+        assert_eq!(sim.robots[1].heading, heading);
+        assert_eq!(sim.robots[1].stunned, 0);
+        for _ in 0..10 {
+            sim.step();
+        }
+        assert_ne!(sim.robots[1].heading, heading);
+
+        // Still cooling down: only the first charge was fired
+        assert_eq!(sim.robots[0].energy, energy - sim.config.emp_energy_cost);
+        assert_eq!(sim.world.emps.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_sustained_fire_destroys_an_obstacle() {
+        let compile = |script: &str| {
+            translate_commands_to_instructions(&crate::parser::parse_script(script).unwrap())
+        };
+        let robots = vec![
+            robot(1, (50.0, 100.0), compile("loop { fire }")),
+            robot(2, (200.0, 100.0), vec![]),
+        ];
+        let wall = Obstacle::new(100.0, 80.0, 20.0, 40.0).with_health(5);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        let health = sim.robots[1].health;
+        while !sim.obstacles.is_empty() && sim.tick < 100 {
+            sim.step();
+        }
+        assert!(sim.obstacles.is_empty());
+        assert_eq!(sim.robots[1].health, health);
+        assert_eq!(
+            sim.events.last().map(|e| &e.kind),
+            Some(&EventKind::ObstacleDestroyed {
+                at: (110.0, 100.0),
+                by: Some(1),
+            })
+        );
+        // The lane is open: the next shots reach the robot behind it
+        for _ in 0..100 {
+            sim.step();
+        }
+        assert!(sim.robots[1].health < health);
+    }
+
+    #[test]
+    fn test_door_blocks_shots_until_it_slides_open() {
+        let compile = |script: &str| {
+            translate_commands_to_instructions(&crate::parser::parse_script(script).unwrap())
+        };
+        let robots = vec![
+            robot(1, (50.0, 100.0), compile("loop { fire }")),
+            robot(2, (200.0, 100.0), vec![]),
+            robot(3, (110.0, 150.0), vec![]),
+        ];
+        let door = Mover::Door {
+            x: 100.0,
+            y: 60.0,
+            width: 20.0,
+            height: 80.0,
+            open: (100.0, 200.0),
+            period: 100,
+            speed: 2.0,
+        };
+        let mut sim =
+            Simulation::new(GameConfig::default(), robots, vec![]).with_movers(vec![door]);
+        let health = sim.robots[1].health;
+        for _ in 0..100 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[1].health, health);
+        assert_eq!(sim.robots[2].position, (110.0, 150.0));
+        // Sliding open, the door shoves the robot below it along
+        for _ in 0..100 {
+            sim.step();
+        }
+        assert!(sim.robots[1].health < health);
+        assert!(sim.robots[2].position.1 > 280.0);
+    }
+
+    #[test]
+    fn test_shots_stay_on_their_level() {
+        let compile = |script: &str| {
+            translate_commands_to_instructions(&crate::parser::parse_script(script).unwrap())
+        };
+        let robots = vec![
+            robot(1, (50.0, 100.0), compile("loop { scan; fire }")),
+            robot(2, (150.0, 100.0), vec![]),
+            robot(3, (196.0, 130.0), compile("loop { move forward 10 }")),
+        ];
+        let elevation = Elevation {
+            platforms: vec![Rect::new(100.0, 50.0, 100.0, 100.0)],
+            ramps: vec![Rect::new(80.0, 90.0, 20.0, 20.0)],
+        };
+        let mut sim =
+            Simulation::new(GameConfig::default(), robots, vec![]).with_elevation(elevation);
+        let health = sim.robots[1].health;
+        for _ in 0..100 {
+            sim.step();
+        }
+        // Seen from the ground, but out of reach of its shots
+        assert_eq!(sim.robots[1].health, health);
+        assert_eq!(sim.robots[0].registers.get(Reg::SCAN_LAYER), Some(2));
+        assert_eq!(sim.robots[2].registers.get(Reg::LAYER), Some(2));
+        // The far edge of the platform is a cliff
+        assert!((199.0..=200.0).contains(&sim.robots[2].position.0));
+        // On the ramp, the robot can be hit from below
+        sim.robots[1].position = (90.0, 100.0);
+        for _ in 0..30 {
+            sim.step();
+        }
+        assert!(sim.robots[1].health < health);
+    }
+
+    #[test]
+    fn test_robots_only_see_what_their_scanner_reaches() {
+        let robots = vec![robot(1, (50.0, 100.0), vec![])];
+        let mut sim = Simulation::new(
+            GameConfig::default(),
+            robots,
+            vec![Obstacle::new(100.0, 50.0, 20.0, 100.0)],
+        );
+        assert!(sim.in_sight(0, (90.0, 100.0)));
+        assert!(!sim.in_sight(0, (150.0, 100.0)));
+        assert!(sim.in_sight(0, (50.0, 300.0)));
+        assert!(!sim.in_sight(0, (250.0, 390.0)));
+        // Once destroyed, the fog lifts
+        sim.robots[0].health = 0;
+        assert!(sim.in_sight(0, (150.0, 100.0)));
+    }
+
+    #[test]
+    fn test_laser_wears_down_an_obstacle() {
+        let robots = vec![robot(1, (50.0, 100.0), vec![Instruction::FireLaser; 3])];
+        let obstacles = vec![
+            Obstacle::new(100.0, 80.0, 20.0, 40.0).with_health(3),
+            Obstacle::new(150.0, 80.0, 20.0, 40.0),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, obstacles);
+        sim.step();
+        assert_eq!(
+            sim.obstacles[0].health,
+            Some(3 - sim.config.combat.laser_damage)
+        );
+        sim.step();
+        sim.step();
+        // Only the destructible obstacle is gone
+        assert_eq!(sim.obstacles, vec![Obstacle::new(150.0, 80.0, 20.0, 40.0)]);
+    }
+
+    #[test]
+    fn test_hazard_warns_then_strikes_robots_that_stay() {
+        use crate::hazards::{Axis, Hazard};
+        let grid = Hazard::LaserGrid {
+            every: 20,
+            warning: 10,
+            damage: 3,
+            axis: Axis::Vertical,
+            spacing: 400.0,
+            width: 20.0,
+            drift: 100.0,
+        };
+        let robots = vec![
+            robot(1, (105.0, 50.0), vec![]),
+            robot(2, (300.0, 50.0), vec![]),
+        ];
+        let mut sim =
+            Simulation::new(GameConfig::default(), robots, vec![]).with_hazards(vec![grid]);
+        let health = sim.robots[0].health;
+        for _ in 0..22 {
+            sim.step();
+        }
+        // Telegraphed at tick 20, landing at tick 30, on the line at x = 100
+        let read = |sim: &Simulation, i: usize, reg| sim.robots[i].registers.get(reg);
+        assert_eq!(read(&sim, 0, Reg::HAZARD_WARNING), Some(9));
+        assert_eq!(read(&sim, 0, Reg::HAZARD_BEARING), Some(0));
+        assert_eq!(read(&sim, 1, Reg::HAZARD_WARNING), Some(0));
+        assert!(sim.events.is_empty());
+
+        for _ in 0..10 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].health, health - 3);
+        assert_eq!(sim.robots[1].health, health);
+        assert!(sim.strikes.is_empty());
+        assert_eq!(
+            sim.events.last().map(|e| &e.kind),
+            Some(&EventKind::HazardStruck {
+                hazard: "laser grid".to_string(),
+                hit: vec![1],
+            })
+        );
+    }
+
+    #[test]
+    fn test_hook_reels_robot_to_a_wall() {
+        let wall = Obstacle::new(150.0, 100.0, 20.0, 200.0);
+        let robots = vec![
+            robot(1, (50.0, 200.0), vec![Instruction::Hook]),
+            robot(2, (300.0, 50.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        sim.step();
+        assert_eq!(
+            sim.robots[0].hook.map(|h| h.anchor),
+            Some(HookAnchor::Point((150.0, 200.0)))
+        );
+        for _ in 0..150 {
+            sim.step();
+        }
+        // Reeled in until the cable is a robot long
+        assert!((sim.robots[0].position.0 - 140.0).abs() < 0.01);
+        assert_eq!(sim.robots[0].position.1, 200.0);
+        assert_eq!(sim.robots[0].registers["hooked"], 1);
+
+        // Nothing in reach: the hook falls short
+        let robots = vec![robot(1, (200.0, 200.0), vec![Instruction::Hook])];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.step();
+        assert_eq!(sim.robots[0].hook, None);
+    }
+
+    #[test]
+    fn test_hook_tows_an_enemy_until_it_is_destroyed() {
+        let robots = vec![
+            robot(1, (50.0, 200.0), vec![Instruction::Hook]),
+            robot(2, (150.0, 200.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.step();
+        assert_eq!(
+            sim.robots[0].hook.map(|h| h.anchor),
+            Some(HookAnchor::Robot(2))
+        );
+        for _ in 0..100 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].position, (50.0, 200.0));
+        assert!((sim.robots[1].position.0 - 60.0).abs() < 0.01);
+
+        sim.damage(1, 100, None);
+        sim.step();
+        assert_eq!(sim.robots[0].hook, None);
+    }
+
+    #[test]
+    fn test_point_defense_shoots_down_enemy_shots() {
+        let defender = vec![Instruction::PointDefense { on: true }];
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::Fire]),
+            robot(2, (150.0, 50.0), defender.clone()),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        let (health, energy) = (sim.robots[1].health, sim.robots[1].energy);
+        for _ in 0..30 {
+            sim.step();
+        }
+        assert!(sim.robots[1].point_defense);
+        assert_eq!(sim.robots[1].registers["point_defense"], 1);
+        assert_eq!(sim.robots[1].health, health);
+        assert_eq!(
+            sim.robots[1].energy,
+            energy - sim.config.point_defense_energy_cost
+        );
+        assert_eq!(sim.world.damages.iter().count(), 0);
+        assert!(
+            sim.events
+                .iter()
+                .any(|e| e.kind == EventKind::Intercepted { robot: 2, owner: 1 })
+        );
+
+        // Without the energy for it, the shot gets through
+        let robots = vec![
+            robot(1, (50.0, 50.0), vec![Instruction::Fire]),
+            robot(2, (150.0, 50.0), defender),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[1].energy = sim.config.point_defense_energy_cost - 1;
+        for _ in 0..30 {
+            sim.step();
+        }
+        assert!(sim.robots[1].health < health);
+    }
+
+    #[test]
+    fn test_driving_up_to_a_wreck_collects_its_salvage() {
+        let program = vec![
+            Instruction::Scan,
+            Instruction::Label("drive".to_string()),
+            Instruction::MoveForward,
+            Instruction::Jnz {
+                reg: Reg::ALWAYS,
+                label: "drive".to_string(),
+            },
+        ];
+        let mut collector = robot(1, (50.0, 50.0), program);
+        collector.heading = 0.0;
+        collector.scanner_heading = 0.0;
+        let robots = vec![
+            collector,
+            robot(2, (62.0, 50.0), vec![]),
+            robot(3, (300.0, 300.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        let (ammo, energy) = (sim.robots[0].ammo, sim.robots[0].energy);
+        sim.damage(1, 100, None);
+        let wreck = sim.world.debris.iter().next().unwrap().0;
+        assert_eq!(sim.world.sprites.get(wreck), Some(&Sprite::SalvageWreck));
+
+        sim.step();
+        assert_eq!(sim.robots[0].registers.get(Reg::SCAN_SALVAGE), Some(1));
+        // Already within reach, so the salvage is picked up on the first tick
+        assert_eq!(sim.robots[0].ammo, ammo + sim.config.salvage_ammo);
+        assert_eq!(sim.robots[0].energy, energy + sim.config.salvage_energy);
+        assert_eq!(sim.world.salvage.get(wreck), None);
+        assert_eq!(sim.world.sprites.get(wreck), Some(&Sprite::Wreck));
+        assert!(sim.events.iter().any(|e| e.kind
+            == EventKind::Salvaged {
+                robot: 1,
+                wreck: 2,
+                ammo: 10,
+                energy: 30,
+            }));
+
+        // Each wreck gives salvage once
+        for _ in 0..10 {
+            sim.step();
+        }
+        assert_eq!(sim.robots[0].ammo, ammo + sim.config.salvage_ammo);
+        let contact = sim.scan(0).unwrap();
+        assert!(contact.debris && !contact.salvage);
+    }
+
+    #[test]
+    fn test_wreck_blocks_movement_but_does_not_trap() {
+        let program = vec![
+            Instruction::Label("drive".to_string()),
+            Instruction::MoveForward,
+            Instruction::Jnz {
+                reg: Reg::named("always"),
+                label: "drive".to_string(),
+            },
+        ];
+        let robots = vec![
+            robot(1, (84.9, 50.0), program.clone()),
+            robot(2, (95.0, 50.0), vec![]),
+            robot(3, (300.0, 300.0), vec![]),
+            robot(4, (305.0, 300.0), program),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.damage(1, 100, None);
+        sim.damage(2, 100, None);
+        // Past the label, then one move
+        sim.step();
+        sim.step();
+        assert_eq!(sim.robots[0].position, (84.9, 50.0));
+        // Robot 4 sits on robot 3's wreck and can still drive away
+        assert!(sim.robots[3].position.0 > 305.0);
+    }
+}
@@ -0,0 +1,144 @@
+// Snapshot ring buffer for time-travel debugging in the window frontend.
+// Keeps a copy of the simulation every few ticks; stepping back restores the
+// nearest earlier snapshot and replays forward to the wanted tick. Matches are
+// deterministic, so the replayed state is the one the match was in.
+
+use std::collections::VecDeque;
+
+use crate::simulation::Simulation;
+
+/// Ticks between snapshots.
+pub const SNAPSHOT_INTERVAL: u64 = 30;
+/// Snapshots kept; older ones are dropped, limiting how far back one can step.
+pub const SNAPSHOT_CAPACITY: usize = 200;
+
+/// The most recent snapshots of a match, oldest first.
+#[derive(Debug, Clone)]
+pub struct SnapshotRing {
+    interval: u64,
+    capacity: usize,
+    snapshots: VecDeque<Simulation>,
+}
+
+impl Default for SnapshotRing {
+    fn default() -> Self {
+        SnapshotRing::new(SNAPSHOT_INTERVAL, SNAPSHOT_CAPACITY)
+    }
+}
+
+impl SnapshotRing {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        SnapshotRing {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Take a snapshot if `sim` is on a snapshot tick that has not been kept yet.
+    /// Call after every step.
+    pub fn record(&mut self, sim: &Simulation) {
+        let newer = self
+            .snapshots
+            .back()
+            .is_none_or(|last| sim.tick > last.tick);
+        if sim.tick.is_multiple_of(self.interval) && newer {
+            if self.snapshots.len() == self.capacity {
+                self.snapshots.pop_front();
+            }
+            self.snapshots.push_back(sim.clone());
+        }
+    }
+
+    /// Forget every snapshot, e.g. after a restart.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    /// Earliest tick that can still be restored.
+    pub fn oldest_tick(&self) -> Option<u64> {
+        self.snapshots.front().map(|sim| sim.tick)
+    }
+
+    /// The match as it was at `tick`, rebuilt from the nearest earlier snapshot.
+    /// `None` if `tick` is older than every snapshot kept.
+    pub fn restore(&self, tick: u64) -> Option<Simulation> {
+        let snapshot = self.snapshots.iter().rev().find(|sim| sim.tick <= tick)?;
+        let mut sim = snapshot.clone();
+        while sim.tick < tick {
+            sim.step();
+        }
+        Some(sim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Robot;
+    use crate::config::GameConfig;
+    use crate::parser::parse_script;
+    use crate::translate_commands_to_instructions;
+
+    fn robot(id: usize, position: (f32, f32), script: &str) -> Robot {
+        let program = translate_commands_to_instructions(&parse_script(script).unwrap());
+        Robot::new(id, "bot", position, program)
+    }
+
+    fn new_match() -> Simulation {
+        let robots = vec![
+            robot(
+                1,
+                (50.0, 50.0),
+                "loop {\n move forward 3\n rotate body 7\n fire\n}",
+            ),
+            robot(
+                2,
+                (200.0, 80.0),
+                "loop {\n rotate turret 5\n scan\n fire\n}",
+            ),
+        ];
+        Simulation::new(GameConfig::default(), robots, vec![])
+    }
+
+    #[test]
+    fn test_restore_matches_original_run() {
+        let mut sim = new_match();
+        let mut ring = SnapshotRing::new(10, 100);
+        ring.record(&sim);
+        let mut states = Vec::new();
+        for _ in 0..100 {
+            sim.step();
+            ring.record(&sim);
+            states.push(format!("{:?}", sim.robots));
+        }
+
+        for tick in [1, 37, 60, 99] {
+            let restored = ring.restore(tick).unwrap();
+            assert_eq!(restored.tick, tick);
+            assert_eq!(format!("{:?}", restored.robots), states[tick as usize - 1]);
+        }
+    }
+
+    #[test]
+    fn test_ring_drops_oldest_snapshots() {
+        let mut sim = new_match();
+        let mut ring = SnapshotRing::new(10, 3);
+        ring.record(&sim);
+        for _ in 0..50 {
+            sim.step();
+            ring.record(&sim);
+        }
+        assert_eq!(ring.oldest_tick(), Some(30));
+        assert!(ring.restore(29).is_none());
+        assert_eq!(ring.restore(45).unwrap().tick, 45);
+
+        // Stepping back and forward again does not duplicate snapshots
+        let mut again = ring.restore(30).unwrap();
+        for _ in 0..20 {
+            again.step();
+            ring.record(&again);
+        }
+        assert_eq!(ring.oldest_tick(), Some(30));
+    }
+}
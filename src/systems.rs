@@ -0,0 +1,1305 @@
+// Systems run by `Simulation::step`, in order, once per tick.
+// Each system reads and updates the components it is interested in; adding a new
+// kind of world object means adding components and, if needed, a system here.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::ast::{HookAnchor, Instruction, Robot, ScanRecord};
+use crate::config::{GameConfig, StalemateRule, Topology};
+use crate::drones::{self, DRONE_TEAM};
+use crate::ecs::Sprite;
+use crate::elevation::{Elevation, Level};
+use crate::events::EventKind;
+use crate::inject::injection_system;
+use crate::modes::GameMode;
+use crate::plugin::Plugins;
+use crate::practice::{self, DUMMY_TEAM};
+use crate::raycast::{self, Obstacle};
+use crate::registers::Reg;
+use crate::simulation::{
+    InFlight, Planned, Seen, Simulation, WorldAction, execute_controls,
+    execute_robot_instruction_with, movement_blocked,
+};
+use crate::workers::Workers;
+
+/// A system advances one aspect of the simulation by a tick.
+pub type System = fn(&mut Simulation);
+
+/// Systems in the order they run each tick, with the names profiles list
+/// them by.
+pub const SYSTEMS: &[(&str, System)] = &[
+    ("injection", injection_system),
+    ("sensor", sensor_system),
+    ("robot", robot_system),
+    ("plugin", plugin_system),
+    ("cloak", cloak_system),
+    ("self_destruct", self_destruct_system),
+    ("zone", zone_system),
+    ("wave", wave_system),
+    ("practice", practice_system),
+    ("scenario", scenario_system),
+    ("kinematic", kinematic_system),
+    ("movement", movement_system),
+    ("point_defense", point_defense_system),
+    ("projectile_collision", projectile_collision_system),
+    ("salvage", salvage_system),
+    ("contact_damage", contact_damage_system),
+    ("knockback", knockback_system),
+    ("hook", hook_system),
+    ("ram", ram_system),
+    ("sudden_death", sudden_death_system),
+    ("hazard", hazard_system),
+    ("lifetime", lifetime_system),
+    ("bounds", bounds_system),
+    ("tendency", tendency_system),
+];
+
+/// Refresh every robot's sensor registers (see `ast::SENSOR_REGISTERS`) from
+/// the current world state.
+pub fn sensor_system(sim: &mut Simulation) {
+    let alive = sim.alive_count();
+    let (width, height) = (sim.config.arena_width, sim.config.arena_height);
+    let zone = sim.control_zone();
+    for robot in &mut sim.robots {
+        let (x, y) = robot.position;
+        let enemies = alive - usize::from(robot.health > 0);
+        // Nearest arena edge or obstacle; a torus has no edges, so there it
+        // is the nearest obstacle, or the arena size without any
+        let edge_dist = match sim.config.topology {
+            Topology::Bounded => x.min(y).min(width - x).min(height - y),
+            Topology::Torus => width.max(height),
+        };
+        let wall_dist = sim
+            .obstacles
+            .iter()
+            .map(|o| o.distance_to(robot.position))
+            .fold(edge_dist, f32::min)
+            .max(0.0);
+        // The strike over the robot that lands soonest, if any
+        let strike = sim
+            .strikes
+            .iter()
+            .filter(|s| s.area.contains(robot.position, &sim.config))
+            .min_by_key(|s| s.lands_at);
+
+        let sensors = [
+            (Reg::X, x.round() as i32),
+            (Reg::Y, y.round() as i32),
+            (Reg::HEADING, (robot.heading.round() as i32).rem_euclid(360)),
+            (Reg::HEALTH, robot.health),
+            (Reg::ENERGY, robot.energy),
+            (Reg::ENEMY_COUNT, enemies as i32),
+            (Reg::NEAREST_WALL_DIST, wall_dist as i32),
+            (
+                Reg::IN_ZONE,
+                i32::from(zone.is_some_and(|z| z.contains(robot.position))),
+            ),
+            (Reg::TICK, sim.tick as i32),
+            (Reg::PUSHED, i32::from(robot.knockback != (0.0, 0.0))),
+            // Scans during the last tick; the warning is cleared once read
+            (
+                Reg::DETECTED,
+                robot.detected_by.take().map_or(0, |id| id as i32),
+            ),
+            (Reg::CLOAKED, i32::from(robot.cloaked)),
+            (Reg::POINT_DEFENSE, i32::from(robot.point_defense)),
+            (Reg::HOOKED, i32::from(robot.hook.is_some())),
+            (Reg::LAYER, sim.elevation.level_at(robot.position).value()),
+            (
+                Reg::HAZARD_WARNING,
+                strike.map_or(0, |s| s.lands_at.saturating_sub(sim.tick).max(1) as i32),
+            ),
+            (
+                Reg::HAZARD_BEARING,
+                strike.map_or(0, |s| s.area.escape_bearing(robot.position, &sim.config)),
+            ),
+        ];
+        for (reg, value) in sensors {
+            robot.registers.set(reg, value);
+        }
+    }
+}
+
+/// Record where every living robot is and how far its nearest enemy is, for
+/// the opponent model of a series.
+pub fn tendency_system(sim: &mut Simulation) {
+    let config = &sim.config;
+    let living = &mut sim.buffers.positions;
+    living.clear();
+    living.extend(
+        sim.robots
+            .iter()
+            .filter(|r| r.health > 0)
+            .map(|r| (r.team, r.position)),
+    );
+    for robot in sim.robots.iter_mut().filter(|r| r.health > 0) {
+        let nearest = living
+            .iter()
+            .filter(|(team, _)| *team != robot.team)
+            .map(|(_, enemy)| {
+                let (dx, dy) = config.displacement(robot.position, *enemy);
+                (dx * dx + dy * dy).sqrt()
+            })
+            .min_by(f32::total_cmp);
+        robot.tendencies.observe(robot.position, nearest);
+    }
+}
+
+/// Drain energy from cloaked robots; the cloak drops when the energy runs out
+/// or the robot is destroyed.
+pub fn cloak_system(sim: &mut Simulation) {
+    let drain = sim.config.cloak_energy_drain;
+    for robot in sim.robots.iter_mut().filter(|r| r.cloaked) {
+        robot.energy = (robot.energy - drain).max(0);
+        if robot.energy == 0 || robot.health <= 0 {
+            robot.cloaked = false;
+        }
+    }
+}
+
+/// Let every plugin of the match advance its world behaviors.
+pub fn plugin_system(sim: &mut Simulation) {
+    let plugins = sim.plugins.clone();
+    for plugin in plugins.iter() {
+        plugin.tick(sim);
+    }
+}
+
+/// Execute one instruction for every living robot, then resolve the world
+/// actions they asked for. An instruction only changes its own robot, so the
+/// robots are planned together, on up to `Simulation::threads` threads that
+/// stay up for the whole match, and the match plays out the same on any
+/// number of threads.
+///
+/// The actions resolve as if at once: every robot that was alive at the start
+/// of the tick gets its shots, scans and hooks out, and every action looks at
+/// the world before any lands, seeing the other robots as they were before
+/// anyone moved. The effects land in robot order, which breaks ties; of two
+/// robots whose lasers destroy the same robot, the first gets the kill. A robot
+/// destroyed earlier in the tick can no longer repair itself or arm.
+pub fn robot_system(sim: &mut Simulation) {
+    let mut blockers = std::mem::take(&mut sim.buffers.blockers);
+    let mut obstacles = std::mem::take(&mut sim.buffers.robot_blockers);
+    let mut actions = std::mem::take(&mut sim.buffers.actions);
+    let mut seen = std::mem::take(&mut sim.buffers.seen);
+    sim.fill_blockers(&mut blockers);
+    seen.clear();
+    seen.extend(sim.robots.iter().map(Seen::of));
+    actions.clear();
+    actions.resize(sim.robots.len(), None);
+
+    let (config, elevation, plugins) = (&sim.config, &sim.elevation, &sim.plugins);
+    let per_thread = sim
+        .robots
+        .len()
+        .div_ceil(sim.threads.max(1))
+        .max(MIN_ROBOTS_PER_THREAD);
+    if per_thread >= sim.robots.len() {
+        plan(
+            &mut sim.robots,
+            &mut actions,
+            &blockers,
+            &mut obstacles,
+            config,
+            elevation,
+            plugins,
+        );
+    } else {
+        let threads = sim.threads.max(1);
+        let workers = match &sim.workers {
+            Some(workers) if workers.len() == threads => workers,
+            _ => sim.workers.insert(Arc::new(Workers::new(threads - 1))),
+        };
+        let mut planning = std::mem::take(&mut sim.buffers.planning);
+        planning.resize_with(threads, Vec::new);
+        // Each thread takes the next chunk of robots until none are left
+        let chunks = Mutex::new(
+            sim.robots
+                .chunks_mut(per_thread)
+                .zip(actions.chunks_mut(per_thread))
+                .zip(planning.iter_mut()),
+        );
+        let blockers = &blockers;
+        workers.run(&|| {
+            loop {
+                let chunk = chunks.lock().unwrap_or_else(PoisonError::into_inner).next();
+                let Some(((robots, actions), obstacles)) = chunk else {
+                    break;
+                };
+                plan(
+                    robots, actions, blockers, obstacles, config, elevation, plugins,
+                );
+            }
+        });
+        sim.buffers.planning = planning;
+    }
+
+    // Every action looks at the world before any of them lands
+    sim.buffers.seen = seen;
+    let mut planned = std::mem::take(&mut sim.buffers.planned);
+    planned.clear();
+    for (i, action) in actions.iter().enumerate() {
+        planned.push(action.map(|action| sight(sim, i, action)));
+    }
+    for (i, action) in planned.iter().enumerate() {
+        if let Some(action) = *action {
+            apply_action(sim, i, action);
+        }
+    }
+    // Outside this system, robots are seen as they are
+    sim.buffers.seen.clear();
+    sim.buffers.planned = planned;
+    sim.buffers.blockers = blockers;
+    sim.buffers.robot_blockers = obstacles;
+    sim.buffers.actions = actions;
+}
+
+/// Fewest robots worth a thread of their own when planning.
+const MIN_ROBOTS_PER_THREAD: usize = 16;
+
+/// Execute one instruction for each living robot of `robots`, putting the
+/// world action it asks for in the same place in `actions`. `obstacles` is
+/// scratch space.
+fn plan(
+    robots: &mut [Robot],
+    actions: &mut [Option<WorldAction>],
+    blockers: &[Obstacle],
+    obstacles: &mut Vec<Obstacle>,
+    config: &GameConfig,
+    elevation: &Elevation,
+    plugins: &Plugins,
+) {
+    for (robot, action) in robots.iter_mut().zip(actions) {
+        if robot.health <= 0 {
+            continue;
+        }
+        // An EMP hit stops the program, and a player's controls, for a while
+        if robot.stunned > 0 {
+            robot.stunned -= 1;
+            continue;
+        }
+        blockers_around(config, robot.position, blockers, obstacles);
+        let (from, ip) = (robot.position, robot.ip);
+        *action = match robot.controls {
+            Some(controls) => execute_controls(robot, controls, obstacles, config),
+            None => {
+                let slot = robot.ip.min(robot.instruction_queue.len());
+                if let Some(ticks) = robot.ticks_spent.get_mut(slot) {
+                    *ticks += 1;
+                }
+                execute_robot_instruction_with(robot, obstacles, config, plugins)
+            }
+        };
+        // Cliffs stop a robot like obstacles do, and a `goto` gives up on them
+        if elevation.blocks(from, robot.position) {
+            robot.position = from;
+            if robot.ip == ip
+                && matches!(
+                    robot.instruction_queue.get(ip),
+                    Some(Instruction::GoTo { .. })
+                )
+            {
+                robot.ip += 1;
+            }
+        }
+    }
+}
+
+/// Look at the world for the world action robot `i` asked for: what it
+/// scans, shoots or repairs. A scan also warns the robots it covers.
+fn sight(sim: &mut Simulation, i: usize, action: WorldAction) -> Planned {
+    match action {
+        WorldAction::Fire => Planned::Fire,
+        WorldAction::FireRicochet => Planned::FireRicochet,
+        WorldAction::FireLaser => Planned::Laser(sim.turret_ray(i, sim.config.laser_range)),
+        WorldAction::FireEmp => Planned::FireEmp,
+        WorldAction::Scan => {
+            sim.warn_scanned(i);
+            Planned::Scan(sim.scan(i))
+        }
+        WorldAction::ScanAll => {
+            let (enemies, nearest) = sim.scan_all(i);
+            Planned::ScanAll(enemies, nearest)
+        }
+        WorldAction::Say => Planned::Say,
+        WorldAction::SelfDestruct => Planned::SelfDestruct,
+        WorldAction::Smoke => Planned::Smoke,
+        WorldAction::Repair => Planned::Repair,
+        WorldAction::RepairAlly => Planned::RepairAlly(sim.ally_in_reach(i)),
+        WorldAction::Hook => Planned::Hook(sim.turret_ray(i, sim.config.hook_range)),
+    }
+}
+
+/// Carry out the world action robot `i` asked for, on what it saw.
+fn apply_action(sim: &mut Simulation, i: usize, action: Planned) {
+    match action {
+        Planned::Fire => sim.fire(i),
+        Planned::FireRicochet => sim.fire_ricochet(i),
+        Planned::Laser(ray) => sim.fire_laser_along(i, ray),
+        Planned::FireEmp => sim.fire_emp(i),
+        Planned::Smoke => sim.drop_smoke(i),
+        Planned::Repair => sim.repair(i),
+        Planned::RepairAlly(ally) => sim.repair_ally_at(i, ally),
+        Planned::Hook(ray) => sim.throw_hook_along(i, ray),
+        Planned::SelfDestruct => sim.arm_self_destruct(i),
+        Planned::Say => {
+            let robot = &sim.robots[i];
+            let text = robot.speech.as_ref().map(|s| s.text.clone());
+            let id = robot.id;
+            sim.log(EventKind::Said {
+                robot: id,
+                text: text.unwrap_or_default(),
+            });
+        }
+        Planned::ScanAll(enemies, nearest) => {
+            let robot = &mut sim.robots[i];
+            robot.registers.set(Reg::ENEMIES_ALIVE, enemies);
+            robot
+                .registers
+                .set(Reg::NEAREST_DIST, nearest.map_or(0, |(dist, _)| dist));
+            // Like `scan_bearing`, the bearing is kept when nothing is sensed
+            if let Some((_, bearing)) = nearest {
+                robot.registers.set(Reg::NEAREST_BEARING, bearing);
+            }
+        }
+        Planned::Scan(contact) => {
+            let robot = &mut sim.robots[i];
+            robot
+                .registers
+                .set(Reg::SCAN, contact.map_or(0, |c| c.distance));
+            robot
+                .registers
+                .set(Reg::SCAN_DEBRIS, contact.map_or(0, |c| i32::from(c.debris)));
+            robot.registers.set(
+                Reg::SCAN_SALVAGE,
+                contact.map_or(0, |c| i32::from(c.salvage)),
+            );
+            robot.registers.set(
+                Reg::SCAN_LAYER,
+                contact.map_or(0, |c| sim.elevation.level_at(c.position).value()),
+            );
+            // The bearing and history of past contacts are kept when a scan finds nothing
+            if let Some(contact) = contact {
+                robot.registers.set(Reg::SCAN_BEARING, contact.bearing);
+            }
+            // Wrecks do not move, so only robots go into the contact history
+            if let Some(contact) = contact
+                && !contact.debris
+            {
+                robot.record_scan(ScanRecord {
+                    tick: sim.tick,
+                    id: contact.id,
+                    position: contact.position,
+                });
+            }
+        }
+    }
+}
+
+/// Count down armed self-destruct fuses and detonate robots whose fuse runs out.
+/// A robot destroyed before its fuse runs out does not detonate.
+pub fn self_destruct_system(sim: &mut Simulation) {
+    for i in 0..sim.robots.len() {
+        let robot = &mut sim.robots[i];
+        let Some(fuse) = robot.fuse.as_mut() else {
+            continue;
+        };
+        if robot.health <= 0 {
+            robot.fuse = None;
+            continue;
+        }
+        *fuse = fuse.saturating_sub(1);
+        if *fuse == 0 {
+            sim.detonate(i);
+        }
+    }
+}
+
+/// Award a point to the team holding the control zone: the only team with
+/// living robots inside it.
+pub fn zone_system(sim: &mut Simulation) {
+    if sim.mode != GameMode::KingOfTheHill {
+        return;
+    }
+    let Some(zone) = sim.control_zone() else {
+        return;
+    };
+    let mut teams = sim
+        .robots
+        .iter()
+        .filter(|r| r.health > 0 && zone.contains(r.position))
+        .map(|r| r.team);
+    if let Some(team) = teams.next()
+        && teams.all(|t| t == team)
+    {
+        *sim.scores.entry(team).or_insert(0) += 1;
+    }
+}
+
+/// In wave survival, start the next wave once every drone is destroyed.
+/// Wrecks of the cleared wave are removed to make room.
+pub fn wave_system(sim: &mut Simulation) {
+    if sim.mode != GameMode::WaveSurvival || sim.is_over() {
+        return;
+    }
+    let drones_alive = sim
+        .robots
+        .iter()
+        .any(|r| r.team == DRONE_TEAM && r.health > 0);
+    if drones_alive {
+        return;
+    }
+    sim.robots.retain(|r| r.team != DRONE_TEAM);
+    sim.wave += 1;
+    drones::spawn_wave(sim, sim.wave);
+    let count = drones::wave_size(sim, sim.wave);
+    sim.log(EventKind::WaveStarted {
+        wave: sim.wave,
+        drones: count,
+    });
+}
+
+/// In practice, start the drill on the first tick and note when the robot
+/// practicing first enters the checkpoint.
+pub fn practice_system(sim: &mut Simulation) {
+    if sim.mode != GameMode::Practice {
+        return;
+    }
+    if sim.drill.is_none() {
+        practice::start_drill(sim);
+    }
+    let checkpoint = practice::checkpoint(sim);
+    let arrived = sim
+        .robots
+        .iter()
+        .any(|r| r.health > 0 && r.team != DUMMY_TEAM && checkpoint.contains(r.position));
+    let tick = sim.tick;
+    if let Some(drill) = &mut sim.drill
+        && arrived
+    {
+        drill.checkpoint_reached.get_or_insert(tick);
+    }
+}
+
+/// In a scenario, follow the player's progress and decide the outcome once
+/// the goal is met or out of reach.
+pub fn scenario_system(sim: &mut Simulation) {
+    let Some(mut objective) = sim.objective.take() else {
+        return;
+    };
+    objective.update(sim);
+    sim.objective = Some(objective);
+}
+
+/// Move the pieces of arena movers to where their course puts them this tick.
+/// A piece that moves into a robot pushes it along, unless that would shove
+/// the robot into an obstacle, out of the arena or over a cliff.
+pub fn kinematic_system(sim: &mut Simulation) {
+    let (tick, math) = (sim.tick, sim.config.math);
+    let half = sim.config.robot_width.max(sim.config.robot_height) / 2.0;
+    for (entity, kinematic) in sim.world.kinematics.iter() {
+        let (Some(transform), Some(collider)) = (
+            sim.world.transforms.get_mut(entity),
+            sim.world.colliders.get(entity),
+        ) else {
+            continue;
+        };
+        let next = kinematic.course.position(tick, math);
+        let (dx, dy) = (next.0 - transform.position.0, next.1 - transform.position.1);
+        transform.position = next;
+        if (dx, dy) == (0.0, 0.0) {
+            continue;
+        }
+        let piece = Obstacle::new(
+            next.0 - collider.half_width,
+            next.1 - collider.half_height,
+            collider.half_width * 2.0,
+            collider.half_height * 2.0,
+        );
+        for robot in sim.robots.iter_mut().filter(|r| r.health > 0) {
+            if !piece.overlaps_square(robot.position, half) {
+                continue;
+            }
+            let pushed = sim
+                .config
+                .wrap((robot.position.0 + dx, robot.position.1 + dy));
+            if !movement_blocked(pushed, &sim.obstacles, &sim.config)
+                && !sim.elevation.blocks(robot.position, pushed)
+            {
+                robot.position = pushed;
+            }
+        }
+    }
+}
+
+/// How far a bouncing entity is set back from the surface it bounced off, so
+/// its next ray does not start on that surface.
+const BOUNCE_OFFSET: f32 = 0.01;
+
+/// Move entities along their heading. Entities that would run into an obstacle
+/// or a wreck are destroyed, unless they can bounce: those reflect off obstacles,
+/// wrecks and the arena edges, keeping `combat.ricochet_damage_factor` of their damage
+/// each time. A projectile destroyed on a destructible obstacle damages it.
+pub fn movement_system(sim: &mut Simulation) {
+    let mut blockers = std::mem::take(&mut sim.buffers.blockers);
+    sim.fill_blockers(&mut blockers);
+    let surfaces = &mut sim.buffers.surfaces;
+    surfaces.clone_from(&blockers);
+    if sim.config.topology == Topology::Bounded {
+        surfaces.extend(arena_walls(sim.config.arena_width, sim.config.arena_height));
+    }
+    let damage_factor = sim.config.combat.ricochet_damage_factor;
+    let math = sim.config.math;
+    let destroyed = &mut sim.buffers.entities;
+    destroyed.clear();
+    let obstacle_hits = &mut sim.buffers.obstacle_hits;
+    obstacle_hits.clear();
+    for (entity, velocity) in sim.world.velocities.iter() {
+        let Some(transform) = sim.world.transforms.get_mut(entity) else {
+            continue;
+        };
+        if let Some(bounce) = sim.world.bounces.get_mut(entity) {
+            let mut travel = velocity.speed;
+            loop {
+                let heading = transform.heading.to_radians();
+                let (sin, cos) = math.sin_cos(heading);
+                let Some(hit) =
+                    raycast::cast_ray_hit(transform.position, heading, travel, surfaces, math)
+                else {
+                    transform.position.0 += travel * cos;
+                    transform.position.1 += travel * sin;
+                    break;
+                };
+                // Out of bounces, or launched from inside something
+                if bounce.remaining == 0 || hit.normal == (0.0, 0.0) {
+                    destroyed.push(entity);
+                    break;
+                }
+                transform.position.0 += hit.distance * cos + hit.normal.0 * BOUNCE_OFFSET;
+                transform.position.1 += hit.distance * sin + hit.normal.1 * BOUNCE_OFFSET;
+                transform.heading = raycast::reflect_heading(transform.heading, hit.normal, math);
+                travel -= hit.distance;
+                bounce.remaining -= 1;
+                if let Some(damage) = sim.world.damages.get_mut(entity) {
+                    damage.amount = ((damage.amount as f32 * damage_factor).round() as i32).max(1);
+                }
+            }
+            continue;
+        }
+        let heading = transform.heading.to_radians();
+        let blocker =
+            raycast::cast_ray_index(transform.position, heading, velocity.speed, &blockers, math);
+        if let Some((k, _)) = blocker {
+            destroyed.push(entity);
+            if let Some(damage) = sim.world.damages.get(entity)
+                && k < sim.obstacles.len()
+            {
+                obstacle_hits.push((k, damage.amount, damage.owner));
+            }
+            continue;
+        }
+        let (sin, cos) = math.sin_cos(heading);
+        transform.position.0 += velocity.speed * cos;
+        transform.position.1 += velocity.speed * sin;
+    }
+    sim.buffers.blockers = blockers;
+    despawn_buffered(sim);
+    for k in 0..sim.buffers.obstacle_hits.len() {
+        let (obstacle, amount, owner) = sim.buffers.obstacle_hits[k];
+        sim.damage_obstacle(obstacle, amount, Some(owner));
+    }
+    sim.clear_rubble();
+}
+
+/// List the projectiles in flight: moving entities that deal damage.
+fn fill_in_flight(sim: &Simulation, in_flight: &mut Vec<InFlight>) {
+    in_flight.clear();
+    for (entity, damage) in sim.world.damages.iter() {
+        let (Some(transform), Some(velocity)) = (
+            sim.world.transforms.get(entity),
+            sim.world.velocities.get(entity),
+        ) else {
+            continue;
+        };
+        let (sin, cos) = sim.config.math.sin_cos(transform.heading.to_radians());
+        in_flight.push(InFlight {
+            entity,
+            owner: damage.owner,
+            team: sim
+                .robots
+                .iter()
+                .find(|r| r.id == damage.owner)
+                .map(|r| r.team),
+            position: transform.position,
+            velocity: (velocity.speed * cos, velocity.speed * sin),
+            level: sim
+                .world
+                .levels
+                .get(entity)
+                .copied()
+                .unwrap_or(Level::Ground),
+        });
+    }
+}
+
+/// Let each robot with point defense on shoot down the nearest enemy
+/// projectile within `point_defense_radius` that could hit it, one per tick,
+/// while it has the energy for it.
+pub fn point_defense_system(sim: &mut Simulation) {
+    let radius = sim.config.point_defense_radius;
+    let cost = sim.config.point_defense_energy_cost;
+    let mut in_flight = std::mem::take(&mut sim.buffers.in_flight);
+    fill_in_flight(sim, &mut in_flight);
+    for i in 0..sim.robots.len() {
+        let robot = &sim.robots[i];
+        if !robot.point_defense || robot.health <= 0 || robot.energy < cost {
+            continue;
+        }
+        let level = sim.elevation.level_at(robot.position);
+        let nearest = in_flight
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| {
+                p.owner != robot.id && p.team != Some(robot.team) && p.level.meets(level)
+            })
+            .map(|(k, p)| {
+                let (dx, dy) = sim.config.displacement(robot.position, p.position);
+                (k, dx * dx + dy * dy)
+            })
+            .filter(|(_, dist_sq)| *dist_sq <= radius * radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((k, _)) = nearest {
+            let target = in_flight.swap_remove(k);
+            sim.shoot_down(i, target.entity);
+        }
+    }
+    sim.buffers.in_flight = in_flight;
+}
+
+/// Destroy projectiles of different teams that came within
+/// `projectile_collision_radius` of each other during the tick, so shots can
+/// meet in the air. Shots on levels that do not meet pass each other.
+pub fn projectile_collision_system(sim: &mut Simulation) {
+    let radius = sim.config.projectile_collision_radius;
+    if radius <= 0.0 {
+        return;
+    }
+    let mut in_flight = std::mem::take(&mut sim.buffers.in_flight);
+    fill_in_flight(sim, &mut in_flight);
+    let destroyed = &mut sim.buffers.entities;
+    destroyed.clear();
+    for (k, a) in in_flight.iter().enumerate() {
+        for b in in_flight[k + 1..]
+            .iter()
+            .filter(|b| b.team != a.team && b.level.meets(a.level))
+        {
+            // Both moved in a straight line this tick, so the gap between
+            // them did too; find its closest approach along the way
+            let (x1, y1) = sim.config.displacement(b.position, a.position);
+            let (vx, vy) = (a.velocity.0 - b.velocity.0, a.velocity.1 - b.velocity.1);
+            let (x0, y0) = (x1 - vx, y1 - vy);
+            let speed_sq = vx * vx + vy * vy;
+            let t = if speed_sq > 0.0 {
+                (-(x0 * vx + y0 * vy) / speed_sq).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+            let (dx, dy) = (x0 + t * vx, y0 + t * vy);
+            if dx * dx + dy * dy <= radius * radius {
+                destroyed.push(a.entity);
+                destroyed.push(b.entity);
+            }
+        }
+    }
+    sim.buffers.in_flight = in_flight;
+    despawn_buffered(sim);
+}
+
+/// Apply damage from entities touching a robot that is not on their owner's team,
+/// consuming them. A projectile passes robots on a level it does not meet.
+pub fn contact_damage_system(sim: &mut Simulation) {
+    let half_w = sim.config.robot_width / 2.0;
+    let half_h = sim.config.robot_height / 2.0;
+    let config = &sim.config;
+    let consumed = &mut sim.buffers.hits;
+    consumed.clear();
+
+    for (entity, damage) in sim.world.damages.iter() {
+        let Some(transform) = sim.world.transforms.get(entity) else {
+            continue;
+        };
+        // No friendly fire: projectiles pass through the owner's teammates
+        let owner_team = sim
+            .robots
+            .iter()
+            .find(|r| r.id == damage.owner)
+            .map(|r| r.team);
+        let level = sim.world.levels.get(entity).copied();
+        let hit = sim.robots.iter().position(|r| {
+            let (dx, dy) = config.displacement(transform.position, r.position);
+            r.id != damage.owner
+                && Some(r.team) != owner_team
+                && r.health > 0
+                && dx.abs() <= half_w
+                && dy.abs() <= half_h
+                && level.is_none_or(|l| l.meets(sim.elevation.level_at(r.position)))
+        });
+        if let Some(target) = hit {
+            consumed.push((entity, target, *damage, transform.heading));
+        }
+    }
+    for k in 0..sim.buffers.hits.len() {
+        let (entity, target, damage, heading) = sim.buffers.hits[k];
+        if let Some(emp) = sim.world.emps.get(entity).copied() {
+            sim.stun(target, emp.stun_ticks, damage.owner);
+            sim.world.despawn(entity);
+            continue;
+        }
+        sim.damage(target, damage.amount, Some(damage.owner));
+        // The robot is knocked along the projectile's path
+        let speed = sim.config.projectile_knockback;
+        let (sin, cos) = sim.config.math.sin_cos(heading.to_radians());
+        sim.push(target, (speed * cos, speed * sin), damage.owner);
+        sim.world.despawn(entity);
+    }
+}
+
+/// Slide speed below which a knocked-back robot comes to rest.
+const MIN_SLIDE_SPEED: f32 = 0.01;
+
+/// Slide knocked-back robots and slow them down. A robot that slides into a
+/// wall, obstacle, wreck or cliff stops there and takes `combat.wall_damage`.
+pub fn knockback_system(sim: &mut Simulation) {
+    let mut blockers = std::mem::take(&mut sim.buffers.blockers);
+    let mut obstacles = std::mem::take(&mut sim.buffers.robot_blockers);
+    sim.fill_blockers(&mut blockers);
+    let friction = sim.config.knockback_friction.clamp(0.0, 1.0);
+    for i in 0..sim.robots.len() {
+        let (position, (vx, vy)) = (sim.robots[i].position, sim.robots[i].knockback);
+        if sim.robots[i].health <= 0 || (vx, vy) == (0.0, 0.0) {
+            continue;
+        }
+        blockers_for(sim, &blockers, i, &mut obstacles);
+        let next = sim.config.wrap((position.0 + vx, position.1 + vy));
+        let robot = &mut sim.robots[i];
+        if movement_blocked(next, &obstacles, &sim.config) || sim.elevation.blocks(position, next) {
+            robot.knockback = (0.0, 0.0);
+            sim.damage(i, sim.config.combat.wall_damage, None);
+            continue;
+        }
+        robot.position = next;
+        robot.knockback = (vx * (1.0 - friction), vy * (1.0 - friction));
+        if robot.knockback.0 * robot.knockback.0 + robot.knockback.1 * robot.knockback.1
+            < MIN_SLIDE_SPEED * MIN_SLIDE_SPEED
+        {
+            robot.knockback = (0.0, 0.0);
+        }
+    }
+    sim.buffers.blockers = blockers;
+    sim.buffers.robot_blockers = obstacles;
+}
+
+/// Deal `combat.ram_damage` to both robots of every enemy pair whose hulls
+/// overlap, each credited to the other.
+pub fn ram_system(sim: &mut Simulation) {
+    let amount = sim.config.combat.ram_damage;
+    if amount <= 0 {
+        return;
+    }
+    let (width, height) = (sim.config.robot_width, sim.config.robot_height);
+    let rams = &mut sim.buffers.pairs;
+    rams.clear();
+    for (i, a) in sim.robots.iter().enumerate() {
+        for (j, b) in sim.robots.iter().enumerate().skip(i + 1) {
+            let (dx, dy) = sim.config.displacement(a.position, b.position);
+            if a.health > 0
+                && b.health > 0
+                && a.team != b.team
+                && dx.abs() < width
+                && dy.abs() < height
+            {
+                rams.push((i, j));
+            }
+        }
+    }
+    for k in 0..sim.buffers.pairs.len() {
+        let (i, j) = sim.buffers.pairs[k];
+        let (a, b) = (sim.robots[i].id, sim.robots[j].id);
+        sim.damage(i, amount, Some(b));
+        sim.damage(j, amount, Some(a));
+    }
+}
+
+/// Reel in every grappling hook cable by `hook_reel_speed`, down to a robot's
+/// length, and pull in whatever is on the far end of a cable it has gone
+/// slack on: a robot hooked to a point is drawn toward it, and a hooked enemy
+/// is towed toward the robot holding the cable. A pull into a wall, obstacle
+/// or wreck, or over a cliff, does not happen. A hook lets go when either robot is destroyed.
+pub fn hook_system(sim: &mut Simulation) {
+    let mut blockers = std::mem::take(&mut sim.buffers.blockers);
+    let mut obstacles = std::mem::take(&mut sim.buffers.robot_blockers);
+    sim.fill_blockers(&mut blockers);
+    let shortest = sim.config.robot_width.max(sim.config.robot_height);
+    for i in 0..sim.robots.len() {
+        let Some(mut hook) = sim.robots[i].hook else {
+            continue;
+        };
+        let end = sim.hook_anchor(i);
+        let Some(end) = end.filter(|_| sim.robots[i].health > 0) else {
+            sim.robots[i].hook = None;
+            continue;
+        };
+        hook.length = (hook.length - sim.config.hook_reel_speed).max(shortest);
+        sim.robots[i].hook = Some(hook);
+
+        // The robot that is pulled, and the end of the cable it is pulled toward
+        let (mover, toward) = match hook.anchor {
+            HookAnchor::Point(_) => (i, end),
+            HookAnchor::Robot(id) => match sim.robots.iter().position(|r| r.id == id) {
+                Some(j) => (j, sim.robots[i].position),
+                None => continue,
+            },
+        };
+        let (dx, dy) = sim.config.displacement(toward, sim.robots[mover].position);
+        let dist = (dx * dx + dy * dy).sqrt();
+        if dist <= hook.length {
+            continue;
+        }
+        let scale = hook.length / dist;
+        let next = sim
+            .config
+            .wrap((toward.0 + dx * scale, toward.1 + dy * scale));
+        blockers_for(sim, &blockers, mover, &mut obstacles);
+        if !movement_blocked(next, &obstacles, &sim.config)
+            && !sim.elevation.blocks(sim.robots[mover].position, next)
+        {
+            sim.robots[mover].position = next;
+        }
+    }
+    sim.buffers.blockers = blockers;
+    sim.buffers.robot_blockers = obstacles;
+}
+
+/// Fill `out` with the blockers that stop robot `i` from moving. A wreck left
+/// on top of a robot does not trap it; it can drive out.
+fn blockers_for(sim: &Simulation, blockers: &[Obstacle], i: usize, out: &mut Vec<Obstacle>) {
+    blockers_around(&sim.config, sim.robots[i].position, blockers, out);
+}
+
+/// The blockers a robot at `position` can run into, as `blockers_for`.
+fn blockers_around(
+    config: &GameConfig,
+    position: (f32, f32),
+    blockers: &[Obstacle],
+    out: &mut Vec<Obstacle>,
+) {
+    let half = config.robot_width.max(config.robot_height) / 2.0;
+    out.clear();
+    out.extend(
+        blockers
+            .iter()
+            .filter(|o| !o.overlaps_square(position, half))
+            .cloned(),
+    );
+}
+
+/// Start sudden death once a stalemate is called, if the rules ask for it, and
+/// from then on take a point of health from every robot each
+/// `sudden_death_interval` ticks, until one is left.
+pub fn sudden_death_system(sim: &mut Simulation) {
+    let started = match sim.sudden_death {
+        Some(started) => started,
+        None if sim.is_stalemate() && sim.config.stalemate == StalemateRule::SuddenDeath => {
+            sim.sudden_death = Some(sim.tick);
+            sim.log(EventKind::SuddenDeath);
+            sim.tick
+        }
+        None => return,
+    };
+    let interval = u64::from(sim.config.sudden_death_interval.max(1));
+    if !(sim.tick - started).is_multiple_of(interval) {
+        return;
+    }
+    for i in 0..sim.robots.len() {
+        sim.damage(i, 1, None);
+    }
+}
+
+/// Telegraph the strikes of arena hazards that are due, and land those whose
+/// warning has run out on every living robot still in the struck area.
+pub fn hazard_system(sim: &mut Simulation) {
+    for (index, hazard) in sim.hazards.iter().enumerate() {
+        if let Some(strike) = hazard.telegraph(index, sim.tick, &mut sim.hazard_rng, &sim.config) {
+            sim.strikes.push(strike);
+        }
+    }
+    let tick = sim.tick;
+    let (landed, pending) = std::mem::take(&mut sim.strikes)
+        .into_iter()
+        .partition(|strike| strike.lands_at <= tick);
+    sim.strikes = pending;
+    for strike in landed {
+        let hit: Vec<usize> = (0..sim.robots.len())
+            .filter(|&i| {
+                let robot = &sim.robots[i];
+                robot.health > 0 && strike.area.contains(robot.position, &sim.config)
+            })
+            .collect();
+        let hazard = &sim.hazards[strike.hazard];
+        let (name, damage) = (hazard.name(), hazard.damage());
+        for &i in &hit {
+            sim.damage(i, damage, None);
+        }
+        sim.log(EventKind::HazardStruck {
+            hazard: name.to_string(),
+            hit: hit.iter().map(|&i| sim.robots[i].id).collect(),
+        });
+    }
+}
+
+/// Count down lifetimes and remove entities whose time is up.
+pub fn lifetime_system(sim: &mut Simulation) {
+    let expired = &mut sim.buffers.entities;
+    expired.clear();
+    for (entity, lifetime) in sim.world.lifetimes.iter_mut() {
+        lifetime.ticks = lifetime.ticks.saturating_sub(1);
+        if lifetime.ticks == 0 {
+            expired.push(entity);
+        }
+    }
+    despawn_buffered(sim);
+}
+
+/// Remove moving entities that have left the arena, or in a torus, bring them
+/// back in at the opposite edge.
+pub fn bounds_system(sim: &mut Simulation) {
+    if sim.config.topology == Topology::Torus {
+        for (entity, _) in sim.world.velocities.iter() {
+            if let Some(transform) = sim.world.transforms.get_mut(entity) {
+                transform.position = sim.config.wrap(transform.position);
+            }
+        }
+        return;
+    }
+    let (width, height) = (sim.config.arena_width, sim.config.arena_height);
+    let outside = &mut sim.buffers.entities;
+    outside.clear();
+    outside.extend(
+        sim.world
+            .velocities
+            .iter()
+            .filter_map(|(entity, _)| sim.world.transforms.get(entity).map(|t| (entity, t)))
+            .filter(|(_, t)| {
+                !(0.0..=width).contains(&t.position.0) || !(0.0..=height).contains(&t.position.1)
+            })
+            .map(|(entity, _)| entity),
+    );
+    despawn_buffered(sim);
+}
+
+/// Thick walls just outside the arena edges, for entities that bounce off them
+/// and grappling hooks that catch on them.
+pub(crate) fn arena_walls(width: f32, height: f32) -> [Obstacle; 4] {
+    const THICKNESS: f32 = 10.0;
+    [
+        Obstacle::new(-THICKNESS, -THICKNESS, THICKNESS, height + 2.0 * THICKNESS),
+        Obstacle::new(width, -THICKNESS, THICKNESS, height + 2.0 * THICKNESS),
+        Obstacle::new(-THICKNESS, -THICKNESS, width + 2.0 * THICKNESS, THICKNESS),
+        Obstacle::new(-THICKNESS, height, width + 2.0 * THICKNESS, THICKNESS),
+    ]
+}
+
+/// Hand the salvage in each wreck, or injected supply, to the first living
+/// robot, in id order, whose center is within `salvage_reach` of it. A wreck
+/// stays; a supply is gone once collected.
+pub fn salvage_system(sim: &mut Simulation) {
+    let reach = sim.config.salvage_reach;
+    let mut piles = std::mem::take(&mut sim.buffers.entities);
+    piles.clear();
+    piles.extend(sim.world.salvage.iter().map(|(entity, _)| entity));
+    for &pile in &piles {
+        let Some(wreck) = sim.world.transforms.get(pile).map(|t| t.position) else {
+            continue;
+        };
+        let collector = sim.robots.iter().position(|r| {
+            let (dx, dy) = sim.config.displacement(r.position, wreck);
+            r.health > 0 && dx * dx + dy * dy <= reach * reach
+        });
+        let (Some(i), Some(salvage)) = (collector, sim.world.salvage.remove(pile)) else {
+            continue;
+        };
+        let robot = &mut sim.robots[i];
+        robot.ammo += salvage.ammo;
+        robot.energy += salvage.energy;
+        let id = robot.id;
+        let Some(wreck) = sim.world.debris.get(pile).map(|d| d.robot) else {
+            sim.world.despawn(pile);
+            sim.log(EventKind::Supplied {
+                robot: id,
+                ammo: salvage.ammo,
+                energy: salvage.energy,
+            });
+            continue;
+        };
+        sim.world.sprites.insert(pile, Sprite::Wreck);
+        sim.log(EventKind::Salvaged {
+            robot: id,
+            wreck,
+            ammo: salvage.ammo,
+            energy: salvage.energy,
+        });
+    }
+    sim.buffers.entities = piles;
+}
+
+/// Despawn the entities a system collected in `sim.buffers.entities`.
+fn despawn_buffered(sim: &mut Simulation) {
+    for &entity in &sim.buffers.entities {
+        sim.world.despawn(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Robot;
+    use crate::config::GameConfig;
+    use crate::ecs::{Damage, Entity, Lifetime, Transform, Velocity};
+    use crate::raycast::Obstacle;
+
+    fn moving_entity(sim: &mut Simulation, position: (f32, f32), speed: f32) -> Entity {
+        let entity = sim.world.spawn();
+        sim.world.transforms.insert(
+            entity,
+            Transform {
+                position,
+                heading: 0.0,
+            },
+        );
+        sim.world.velocities.insert(entity, Velocity { speed });
+        entity
+    }
+
+    #[test]
+    fn test_threads_do_not_change_the_match() {
+        let names = ["tracker", "spinner", "circler", "wall-follower"];
+        let robots: Vec<Robot> = (0..40)
+            .map(|i| {
+                let name = names[i % names.len()];
+                let script = crate::parser::parse_script(crate::builtins::script(name).unwrap());
+                let program = crate::ast::translate_commands_to_instructions(&script.unwrap());
+                let position = (40.0 + 60.0 * (i % 8) as f32, 40.0 + 90.0 * (i / 8) as f32);
+                Robot::new(i + 1, name, position, program)
+            })
+            .collect();
+        let sequential = Simulation::new(GameConfig::default(), robots, vec![]);
+        let threaded = sequential.clone().with_threads(4);
+        let [sequential, threaded] = [sequential, threaded].map(|mut sim| {
+            for _ in 0..600 {
+                sim.step();
+            }
+            sim
+        });
+        assert!(threaded.events.len() > 40, "the robots should have fought");
+        assert_eq!(threaded.events, sequential.events);
+        for (a, b) in threaded.robots.iter().zip(&sequential.robots) {
+            assert_eq!(
+                (a.position, a.heading, a.health, a.ip),
+                (b.position, b.heading, b.health, b.ip)
+            );
+            assert_eq!(a.registers, b.registers);
+        }
+    }
+
+    #[test]
+    fn test_sensor_system_fills_registers() {
+        let robots = vec![
+            Robot::new(1, "a", (30.0, 50.0), vec![]),
+            Robot::new(2, "b", (200.0, 200.0), vec![]),
+            Robot::new(3, "c", (300.0, 300.0), vec![]),
+        ];
+        let wall = Obstacle::new(40.0, 0.0, 10.0, 100.0);
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![wall]);
+        sim.robots[1].health = 0;
+        sensor_system(&mut sim);
+
+        let registers = &sim.robots[0].registers;
+        for name in crate::ast::SENSOR_REGISTERS {
+            assert!(registers.contains(*name), "missing {}", name);
+        }
+        assert_eq!(registers["x"], 30);
+        assert_eq!(registers["y"], 50);
+        assert_eq!(registers["health"], sim.robots[0].max_health);
+        assert_eq!(registers["enemy_count"], 1);
+        // The obstacle is closer than the left arena edge
+        assert_eq!(registers["nearest_wall_dist"], 10);
+    }
+
+    #[test]
+    fn test_wave_system_spawns_next_wave_when_cleared() {
+        let player = Robot::new(1, "player", (200.0, 200.0), vec![]);
+        let mut sim = Simulation::new(GameConfig::default(), vec![player], vec![])
+            .with_mode(GameMode::WaveSurvival);
+        wave_system(&mut sim);
+        assert_eq!(sim.wave, 1);
+        let first_wave = sim.robots.len() - 1;
+        assert_eq!(first_wave as u32, sim.config.wave_base_drones);
+
+        // Still drones alive: nothing happens
+        wave_system(&mut sim);
+        assert_eq!(sim.wave, 1);
+
+        for robot in sim.robots.iter_mut().filter(|r| r.team == DRONE_TEAM) {
+            robot.health = 0;
+        }
+        wave_system(&mut sim);
+        assert_eq!(sim.wave, 2);
+        assert_eq!(sim.robots.len() - 1, first_wave + 1);
+        assert!(!sim.is_over());
+    }
+
+    #[test]
+    fn test_knockback_slides_and_stops_at_walls() {
+        let robots = vec![
+            Robot::new(1, "a", (100.0, 100.0), vec![]),
+            Robot::new(2, "b", (390.0, 200.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.push(0, (2.0, 0.0), 2);
+        assert_eq!(
+            sim.events.last().map(|e| &e.kind),
+            Some(&EventKind::Pushed { robot: 1, by: 2 })
+        );
+        sensor_system(&mut sim);
+        assert_eq!(sim.robots[0].registers["pushed"], 1);
+
+        knockback_system(&mut sim);
+        assert_eq!(sim.robots[0].position, (102.0, 100.0));
+        assert!((sim.robots[0].knockback.0 - 1.6).abs() < 1e-5);
+        for _ in 0..100 {
+            knockback_system(&mut sim);
+        }
+        // Slides about 2 / 0.2 units in total, then comes to rest
+        assert!((sim.robots[0].position.0 - 110.0).abs() < 0.1);
+        assert_eq!(sim.robots[0].knockback, (0.0, 0.0));
+
+        // Pushed into the arena wall: stops instead of leaving the arena
+        sim.push(1, (10.0, 0.0), 1);
+        knockback_system(&mut sim);
+        assert_eq!(sim.robots[1].position, (390.0, 200.0));
+        assert_eq!(sim.robots[1].knockback, (0.0, 0.0));
+        // Walls deal no damage under the default combat table
+        assert_eq!(sim.robots[1].health, sim.robots[1].max_health);
+    }
+
+    #[test]
+    fn test_ram_and_wall_damage() {
+        let mut config = GameConfig::default();
+        config.combat.ram_damage = 1;
+        config.combat.wall_damage = 2;
+        let robots = vec![
+            Robot::new(1, "a", (100.0, 100.0), vec![]),
+            Robot::new(2, "b", (108.0, 104.0), vec![]),
+            Robot::new(3, "c", (390.0, 200.0), vec![]),
+        ];
+        let mut sim = Simulation::new(config, robots, vec![]);
+        let full = sim.robots[0].max_health;
+        ram_system(&mut sim);
+        assert_eq!(sim.robots[0].health, full - 1);
+        assert_eq!(sim.robots[1].health, full - 1);
+        assert_eq!(sim.robots[2].health, full);
+
+        // Teammates can touch without harm
+        sim.robots[1].team = sim.robots[0].team;
+        ram_system(&mut sim);
+        assert_eq!(sim.robots[0].health, full - 1);
+
+        sim.push(2, (10.0, 0.0), 1);
+        knockback_system(&mut sim);
+        assert_eq!(sim.robots[2].health, full - 2);
+    }
+
+    #[test]
+    fn test_movement_system_moves_along_heading() {
+        let mut sim = Simulation::new(GameConfig::default(), vec![], vec![]);
+        let entity = moving_entity(&mut sim, (10.0, 10.0), 2.5);
+        movement_system(&mut sim);
+        assert_eq!(
+            sim.world.transforms.get(entity).unwrap().position,
+            (12.5, 10.0)
+        );
+    }
+
+    #[test]
+    fn test_movement_system_destroys_on_obstacle() {
+        let wall = Obstacle::new(11.0, 0.0, 5.0, 20.0);
+        let mut sim = Simulation::new(GameConfig::default(), vec![], vec![wall]);
+        let entity = moving_entity(&mut sim, (10.0, 10.0), 2.5);
+        movement_system(&mut sim);
+        assert!(!sim.world.is_alive(entity));
+    }
+
+    #[test]
+    fn test_projectiles_of_different_teams_destroy_each_other() {
+        let robots = vec![
+            Robot::new(1, "a", (10.0, 10.0), vec![]),
+            Robot::new(2, "b", (390.0, 10.0), vec![]),
+        ];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        let shot = |sim: &mut Simulation, position, heading, owner| {
+            let entity = moving_entity(sim, position, 4.0);
+            sim.world.transforms.get_mut(entity).unwrap().heading = heading;
+            sim.world
+                .damages
+                .insert(entity, Damage { owner, amount: 2 });
+            entity
+        };
+        // Crossed head-on during the tick, though they have moved apart again
+        let a = shot(&mut sim, (105.0, 50.0), 0.0, 1);
+        let b = shot(&mut sim, (103.0, 50.0), 180.0, 2);
+        // The same crossing between teammates' shots, and enemy shots far apart
+        let c = shot(&mut sim, (105.0, 150.0), 0.0, 1);
+        let d = shot(&mut sim, (103.0, 150.0), 180.0, 1);
+        let e = shot(&mut sim, (200.0, 250.0), 0.0, 2);
+        projectile_collision_system(&mut sim);
+        assert!(!sim.world.is_alive(a) && !sim.world.is_alive(b));
+        assert!([c, d, e].iter().all(|&x| sim.world.is_alive(x)));
+    }
+
+    #[test]
+    fn test_lifetime_system_expires_entities() {
+        let mut sim = Simulation::new(GameConfig::default(), vec![], vec![]);
+        let entity = sim.world.spawn();
+        sim.world.lifetimes.insert(entity, Lifetime { ticks: 2 });
+        lifetime_system(&mut sim);
+        assert!(sim.world.is_alive(entity));
+        lifetime_system(&mut sim);
+        assert!(!sim.world.is_alive(entity));
+    }
+
+    #[test]
+    fn test_bounds_system_removes_escaped_entities() {
+        let mut sim = Simulation::new(GameConfig::default(), vec![], vec![]);
+        let inside = moving_entity(&mut sim, (10.0, 10.0), 1.0);
+        let outside = moving_entity(&mut sim, (-1.0, 10.0), 1.0);
+        bounds_system(&mut sim);
+        assert!(sim.world.is_alive(inside));
+        assert!(!sim.world.is_alive(outside));
+    }
+
+    #[test]
+    fn test_bounds_system_wraps_entities_in_a_torus() {
+        let config = GameConfig {
+            topology: Topology::Torus,
+            ..GameConfig::default()
+        };
+        let mut sim = Simulation::new(config, vec![], vec![]);
+        let escaped = moving_entity(&mut sim, (-1.0, 410.0), 1.0);
+        bounds_system(&mut sim);
+        assert!(sim.world.is_alive(escaped));
+        assert_eq!(
+            sim.world.transforms.get(escaped).unwrap().position,
+            (399.0, 10.0)
+        );
+    }
+}
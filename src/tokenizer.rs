@@ -1,12 +1,12 @@
 //! Tokenizer for the robot-battle DSL.
 //!
-//! This module provides functionality to tokenize lines and scripts written in the robot DSL.
+//! This module provides a character-level lexer for lines and scripts written in the robot DSL.
 //! The DSL supports commands such as `rotate`, `move`, `scan`, `fire`, and control flow like `if`, `else`, `while`.
 //!
 //! # Example
 //!
 //! ```
-//! use tokenizer::{tokenize_line, Token};
+//! use robot_battle::tokenizer::{tokenize_line, Token};
 //! let tokens = tokenize_line("rotate treads 90");
 //! assert_eq!(
 //!     tokens,
@@ -18,61 +18,281 @@
 //! );
 //! ```
 
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::commands;
+use crate::error::{ScriptError, Span, TokenizeError};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Keyword(String),
     Identifier(String),
     Number(i32),
     Symbol(char),
+    /// A string literal, without its quotes.
+    Str(String),
 }
 
-/// Tokenizes a single line of robot DSL code.
-pub fn tokenize_line(line: &str) -> Vec<Token> {
-    // Remove comments: split at '#' and take the part before it
-    let code = match line.find('#') {
-        Some(idx) => &line[..idx],
-        None => line,
-    };
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Keyword(word) | Token::Identifier(word) => write!(f, "`{}`", word),
+            Token::Number(n) => write!(f, "`{}`", n),
+            Token::Symbol(c) => write!(f, "`{}`", c),
+            Token::Str(text) => write!(f, "`\"{}\"`", text),
+        }
+    }
+}
 
-    let mut words = code.split_whitespace();
-    let mut tokens = Vec::new();
-    if let Some(first) = words.next() {
-        let keywords = [
-            "rotate", "move", "scan", "fire", "if", "else", "while", "loop", "body", "turret",
-            "scanner",
-        ];
-        if keywords.contains(&first) {
-            tokens.push(Token::Keyword(first.to_string()));
-        } else if let Ok(num) = first.parse::<i32>() {
-            tokens.push(Token::Number(num));
-        } else if first.len() == 1 && "{}()".contains(first) {
-            tokens.push(Token::Symbol(first.chars().next().unwrap()));
+/// Comment that makes keywords case-insensitive, before a file's first command.
+const ANY_CASE_PRAGMA: &str = "# keywords: any-case";
+
+/// How keywords are recognised. Keywords are found wherever they appear in a
+/// line; this only decides whether `FIRE` or `Fire` is one too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeywordCase {
+    /// Keywords are lowercase; `Fire` is an identifier.
+    #[default]
+    Exact,
+    /// Keywords may be written in any case, and are lowercased. Registers
+    /// and other identifiers keep their case.
+    Any,
+}
+
+impl KeywordCase {
+    /// The case `source` asks for: `Any` if `ANY_CASE_PRAGMA` comes before
+    /// any code.
+    pub fn of(source: &str) -> KeywordCase {
+        if has_pragma(source, ANY_CASE_PRAGMA) {
+            KeywordCase::Any
         } else {
-            tokens.push(Token::Identifier(first.to_string()));
+            KeywordCase::Exact
         }
-        for word in words {
-            if let Ok(num) = word.parse::<i32>() {
-                tokens.push(Token::Number(num));
-            } else if word.len() == 1 && "{}()".contains(word) {
-                tokens.push(Token::Symbol(word.chars().next().unwrap()));
-            } else if keywords.contains(&word) {
-                tokens.push(Token::Keyword(word.to_string()));
+    }
+}
+
+/// Whether `pragma` is one of the comment lines before a script's first command.
+pub(crate) fn has_pragma(source: &str, pragma: &str) -> bool {
+    source
+        .lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with('#'))
+        .any(|line| line == pragma)
+}
+
+/// Punctuation that forms a token on its own.
+const SYMBOLS: &str = "{}(),;";
+
+/// Operators, longest first so `<=` is not read as `<` then `=`. They are
+/// tokens of their own whether or not spaces surround them.
+const OPERATORS: [&str; 12] = [
+    "<=", ">=", "==", "!=", "<", ">", "=", "+", "-", "*", "/", "%",
+];
+
+/// A token, where it starts, and what is wrong with it, if anything. The
+/// lenient `tokenize_*` functions keep the token and ignore the problem.
+struct Lexeme {
+    token: Token,
+    span: Span,
+    error: Option<TokenizeError>,
+}
+
+/// Reads a script one character at a time. Comments run from a `#` outside a
+/// string to the end of the line, and a string literal may not span lines.
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+    case: KeywordCase,
+    /// Whether the last token was the end of an operand with nothing after
+    /// it yet, so a `-` here subtracts rather than starting a negative number.
+    after_operand: bool,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(script: &'a str, case: KeywordCase) -> Self {
+        Lexer {
+            chars: script.chars().peekable(),
+            line: 1,
+            column: 1,
+            case,
+            after_operand: false,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Skip whitespace and comments, stopping at the start of the next token.
+    fn skip_blanks(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c == '#' {
+                while self.chars.peek().is_some_and(|&c| c != '\n') {
+                    self.bump();
+                }
+            } else if c.is_whitespace() {
+                self.bump();
             } else {
-                tokens.push(Token::Identifier(word.to_string()));
+                return;
             }
+            self.after_operand = false;
         }
     }
-    tokens
+
+    /// Append the rest of a word (letters, digits and underscores) to `text`.
+    fn word(&mut self, mut text: String) -> String {
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_alphanumeric() || c == '_') {
+                break;
+            }
+            text.push(c);
+            self.bump();
+        }
+        text
+    }
+
+    /// A string literal whose opening quote has been read.
+    fn string(&mut self) -> (Token, Option<TokenizeError>) {
+        let mut text = String::new();
+        loop {
+            match self.chars.peek() {
+                Some('"') => {
+                    self.bump();
+                    return (Token::Str(text), None);
+                }
+                Some('\n') | None => {
+                    return (Token::Str(text), Some(TokenizeError::UnterminatedString));
+                }
+                Some(&c) => {
+                    text.push(c);
+                    self.bump();
+                }
+            }
+        }
+    }
+
+    /// The operator starting with `first`, which has been read.
+    fn operator(&mut self, first: char) -> Option<&'static str> {
+        let second = self.chars.peek().copied();
+        let op = OPERATORS.iter().find(|op| {
+            let mut chars = op.chars();
+            chars.next() == Some(first) && chars.next().is_none_or(|c| Some(c) == second)
+        })?;
+        if op.len() == 2 {
+            self.bump();
+        }
+        Some(op)
+    }
+}
+
+/// A word: a number if it is all digits (after an optional minus sign), else
+/// a keyword or identifier. Numbers too large for a register are identifiers,
+/// and an error.
+fn classify(word: String, case: KeywordCase) -> (Token, Option<TokenizeError>) {
+    let digits = word.strip_prefix('-').unwrap_or(&word);
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        return match word.parse() {
+            Ok(n) => (Token::Number(n), None),
+            Err(_) => {
+                let error = TokenizeError::NumberOutOfRange(word.clone());
+                (Token::Identifier(word), Some(error))
+            }
+        };
+    }
+    if commands::is_keyword(&word) {
+        (Token::Keyword(word), None)
+    } else if case == KeywordCase::Any && commands::is_keyword(&word.to_lowercase()) {
+        (Token::Keyword(word.to_lowercase()), None)
+    } else {
+        (Token::Identifier(word), None)
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Lexeme;
+
+    fn next(&mut self) -> Option<Lexeme> {
+        self.skip_blanks();
+        let span = Span {
+            line: self.line,
+            column: self.column,
+        };
+        let c = self.bump()?;
+        let next_is_digit = self.chars.peek().is_some_and(char::is_ascii_digit);
+        let (token, error) = if c == '"' {
+            self.string()
+        } else if SYMBOLS.contains(c) {
+            (Token::Symbol(c), None)
+        } else if c.is_alphanumeric()
+            || c == '_'
+            || (c == '-' && next_is_digit && !self.after_operand)
+        {
+            // `-` right before a digit is a sign, unless it follows an operand
+            // directly: `x -5` is `x` then -5, but `x-5` and `x - 5` subtract.
+            classify(self.word(c.to_string()), self.case)
+        } else if let Some(op) = self.operator(c) {
+            (Token::Identifier(op.to_string()), None)
+        } else {
+            let error = TokenizeError::UnexpectedCharacter(c);
+            (Token::Identifier(c.to_string()), Some(error))
+        };
+        self.after_operand = match &token {
+            Token::Number(_) | Token::Keyword(_) | Token::Symbol(')') => true,
+            Token::Identifier(word) => word.starts_with(|c: char| c.is_alphanumeric() || c == '_'),
+            _ => false,
+        };
+        Some(Lexeme { token, span, error })
+    }
+}
+
+/// Tokenizes a single line of robot DSL code.
+pub fn tokenize_line(line: &str) -> Vec<Token> {
+    Lexer::new(line, KeywordCase::Exact)
+        .map(|lexeme| lexeme.token)
+        .collect()
 }
 
 /// Tokenizes a multi-line robot DSL script.
 pub fn tokenize_script(script: &str) -> Vec<Token> {
-    script
-        .lines()
-        .flat_map(|line| tokenize_line(line))
+    Lexer::new(script, KeywordCase::of(script))
+        .map(|lexeme| lexeme.token)
         .collect()
 }
 
+/// Tokenizes a script, returning the position of every token alongside it.
+/// Unlike `tokenize_script`, text that is not a valid token, such as a number
+/// too large for a register or a stray `@`, is an error.
+pub fn tokenize_with_spans(script: &str) -> Result<(Vec<Token>, Vec<Span>), ScriptError> {
+    tokenize_with_case(script, KeywordCase::of(script))
+}
+
+/// Like `tokenize_with_spans`, recognising keywords as `case` says whatever
+/// the script's pragmas ask for.
+pub fn tokenize_with_case(
+    script: &str,
+    case: KeywordCase,
+) -> Result<(Vec<Token>, Vec<Span>), ScriptError> {
+    let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    for Lexeme { token, span, error } in Lexer::new(script, case) {
+        if let Some(source) = error {
+            return Err(ScriptError::Tokenize { span, source });
+        }
+        tokens.push(token);
+        spans.push(span);
+    }
+    Ok((tokens, spans))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,6 +327,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tokenize_splits_attached_punctuation() {
+        let tokens = tokenize_line("let b = atan2(dy, -5)");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword("let".to_string()),
+                Token::Identifier("b".to_string()),
+                Token::Identifier("=".to_string()),
+                Token::Identifier("atan2".to_string()),
+                Token::Symbol('('),
+                Token::Identifier("dy".to_string()),
+                Token::Symbol(','),
+                Token::Number(-5),
+                Token::Symbol(')'),
+            ]
+        );
+    }
+
     #[test]
     fn test_tokenize_script_multiline() {
         let script = r#"
@@ -138,4 +377,141 @@ if scan > 0 {
         ];
         assert_eq!(tokens, expected);
     }
+
+    #[test]
+    fn test_tokenize_with_spans() {
+        let (tokens, spans) = tokenize_with_spans("scan\n  fire # shoot\nif x>(1){").unwrap();
+        assert_eq!(tokens, tokenize_script("scan\n  fire # shoot\nif x>(1){"));
+        let positions: Vec<_> = spans.iter().map(|s| (s.line, s.column)).collect();
+        assert_eq!(
+            positions,
+            vec![
+                (1, 1),
+                (2, 3),
+                (3, 1),
+                (3, 4),
+                (3, 5),
+                (3, 6),
+                (3, 7),
+                (3, 8),
+                (3, 9)
+            ]
+        );
+
+        let err = tokenize_with_spans("move forward\n  move forward 99999999999").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 16: number `99999999999` does not fit in a register"
+        );
+    }
+
+    #[test]
+    fn test_tokenize_strings() {
+        let tokens = tokenize_script("say \"reloading # now\" # comment\nfire");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword("say".to_string()),
+                Token::Str("reloading # now".to_string()),
+                Token::Keyword("fire".to_string()),
+            ]
+        );
+
+        let err = tokenize_with_spans("scan\nsay \"oops").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 5: string is missing its closing quote"
+        );
+    }
+
+    #[test]
+    fn test_lexer_needs_no_spaces() {
+        let id = |w: &str| Token::Identifier(w.to_string());
+        let kw = |w: &str| Token::Keyword(w.to_string());
+        assert_eq!(
+            tokenize_line("move forward 10;x=3"),
+            vec![
+                kw("move"),
+                id("forward"),
+                Token::Number(10),
+                Token::Symbol(';'),
+                id("x"),
+                id("="),
+                Token::Number(3),
+            ]
+        );
+        assert_eq!(
+            tokenize_line("if x>=3{fire}"),
+            vec![
+                kw("if"),
+                id("x"),
+                id(">="),
+                Token::Number(3),
+                Token::Symbol('{'),
+                kw("fire"),
+                Token::Symbol('}'),
+            ]
+        );
+        // A minus sign attached to an operand subtracts; after a space it is a sign
+        assert_eq!(
+            tokenize_line("x-5"),
+            vec![id("x"), id("-"), Token::Number(5)]
+        );
+        assert_eq!(tokenize_line("x -5"), vec![id("x"), Token::Number(-5)]);
+        assert_eq!(tokenize_line("(-5)*y"), tokenize_line("( -5 ) * y"));
+        assert_eq!(
+            tokenize_line("a!=b<c"),
+            vec![id("a"), id("!="), id("b"), id("<"), id("c")]
+        );
+    }
+
+    #[test]
+    fn test_keywords_anywhere_in_a_line() {
+        let kw = |w: &str| Token::Keyword(w.to_string());
+        let tokens = tokenize_line("if x > 0 { fire } else { scan; move forward 5 }");
+        let keywords: Vec<_> = tokens
+            .iter()
+            .filter(|t| matches!(t, Token::Keyword(_)))
+            .cloned()
+            .collect();
+        assert_eq!(
+            keywords,
+            vec![kw("if"), kw("fire"), kw("else"), kw("scan"), kw("move")]
+        );
+        assert_eq!(tokenize_line("loop 3 {fire}")[3], kw("fire"));
+    }
+
+    #[test]
+    fn test_keyword_case() {
+        let kw = |w: &str| Token::Keyword(w.to_string());
+        let id = |w: &str| Token::Identifier(w.to_string());
+        assert_eq!(tokenize_line("Fire"), vec![id("Fire")]);
+        let (tokens, _) = tokenize_with_case("IF Ready { Fire }", KeywordCase::Any).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                kw("if"),
+                id("Ready"),
+                Token::Symbol('{'),
+                kw("fire"),
+                Token::Symbol('}')
+            ]
+        );
+        let script = "# keywords: any-case\nSCAN";
+        assert_eq!(KeywordCase::of(script), KeywordCase::Any);
+        assert_eq!(tokenize_script(script), vec![kw("scan")]);
+        assert_eq!(
+            KeywordCase::of("scan\n# keywords: any-case"),
+            KeywordCase::Exact
+        );
+    }
+
+    #[test]
+    fn test_unexpected_character() {
+        let err = tokenize_with_spans("fire\nlet x = 3 @ 4").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 11: unexpected character `@`"
+        );
+    }
 }
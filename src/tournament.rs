@@ -0,0 +1,874 @@
+// Tournaments between many bots: round robin, or single or double elimination
+// brackets. Entrants are seeded by their rating on a ladder (Elo ratings kept
+// in a TOML file), every match is played headlessly, and the ladder is updated
+// with each result. The bracket is built round by round as matches finish and
+// can be written as JSON or rendered as text. Replays of the matches can be
+// saved to a directory along the way, every match recorded in the history, and
+// each bot's script coverage over all its matches written at the end.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::coverage::Coverage;
+use crate::error::SimError;
+use crate::history::History;
+use crate::raycast::Obstacle;
+use crate::replay::Replay;
+use crate::results::{self, EndCondition, MatchResult};
+use crate::setup::{BotScript, compile_robots, default_obstacles};
+use crate::simulation::Simulation;
+
+/// Rating of a bot that is not on the ladder yet.
+pub const DEFAULT_RATING: f64 = 1500.0;
+/// How far one match moves a rating.
+const ELO_K: f64 = 32.0;
+
+/// How the field is played off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// Everyone plays everyone once; most points wins.
+    RoundRobin,
+    /// Knockout: one loss and a bot is out.
+    SingleElimination,
+    /// A bot is out after its second loss; first-time losers drop into a
+    /// losers bracket whose winner meets the winners bracket's in the final.
+    DoubleElimination,
+}
+
+impl Format {
+    /// Parse a `--format` value.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "round-robin" => Some(Format::RoundRobin),
+            "single" => Some(Format::SingleElimination),
+            "double" => Some(Format::DoubleElimination),
+            _ => None,
+        }
+    }
+}
+
+/// Ratings of bots by name, as stored in a ladder file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Ladder {
+    pub ratings: BTreeMap<String, f64>,
+}
+
+impl Ladder {
+    /// Load a ladder from a TOML file of `name = rating` lines.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Write the ladder back as TOML.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Rating of a bot; `DEFAULT_RATING` if it has none.
+    pub fn rating(&self, name: &str) -> f64 {
+        self.ratings.get(name).copied().unwrap_or(DEFAULT_RATING)
+    }
+
+    /// Update both ratings after a match; `score` is 1 if `a` won, 0.5 for a
+    /// draw and 0 if `b` won.
+    pub fn record(&mut self, a: &str, b: &str, score: f64) {
+        let (ra, rb) = (self.rating(a), self.rating(b));
+        let expected = 1.0 / (1.0 + 10f64.powf((rb - ra) / 400.0));
+        let change = ELO_K * (score - expected);
+        // Ratings are kept to one decimal so the file stays readable
+        let round = |r: f64| (r * 10.0).round() / 10.0;
+        self.ratings.insert(a.to_string(), round(ra + change));
+        self.ratings.insert(b.to_string(), round(rb - change));
+    }
+}
+
+/// A bot's place in the seeding.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Seed {
+    /// 1 for the top seed.
+    pub seed: usize,
+    pub name: String,
+    /// Rating when the tournament started.
+    pub rating: f64,
+}
+
+/// Which part of the tournament a round belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    RoundRobin,
+    Winners,
+    Losers,
+    Final,
+}
+
+/// One pairing of a round.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pairing {
+    pub a: String,
+    /// The opponent; `None` if `a` has a bye.
+    pub b: Option<String>,
+    /// Who went through; `None` for a drawn round robin match.
+    pub winner: Option<String>,
+    pub ticks: u64,
+    /// Whether the match ended without a winner and was decided on remaining
+    /// health (then seeding).
+    pub tiebreak: bool,
+    /// Where the replay of the match was saved, if replays were kept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replay: Option<PathBuf>,
+}
+
+impl Pairing {
+    /// The pairing as one line of text.
+    pub fn describe(&self) -> String {
+        match (&self.b, &self.winner) {
+            (None, _) => format!("{} advances with a bye", self.a),
+            (Some(b), None) => format!("{} drew with {}", self.a, b),
+            (Some(b), Some(winner)) => {
+                let loser = if winner == &self.a { b } else { &self.a };
+                let how = if self.tiebreak { ", on tiebreak" } else { "" };
+                format!("{} beat {} ({} ticks{})", winner, loser, self.ticks, how)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Round {
+    pub stage: Stage,
+    /// 1-based number of the round within its stage.
+    pub number: usize,
+    pub matches: Vec<Pairing>,
+}
+
+impl Round {
+    /// The round as text, one line per pairing.
+    pub fn render(&self) -> String {
+        let mut text = self.title() + "\n";
+        for pairing in &self.matches {
+            text += &format!("  {}\n", pairing.describe());
+        }
+        text
+    }
+
+    /// Title of the round, such as "Winners round 2".
+    pub fn title(&self) -> String {
+        match self.stage {
+            Stage::RoundRobin => format!("Round {}", self.number),
+            Stage::Winners => format!("Winners round {}", self.number),
+            Stage::Losers => format!("Losers round {}", self.number),
+            Stage::Final if self.number == 1 => "Grand final".to_string(),
+            Stage::Final => "Grand final (reset)".to_string(),
+        }
+    }
+}
+
+/// Round robin record of one bot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Standing {
+    pub name: String,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// 3 per win and 1 per draw.
+    pub points: u32,
+}
+
+/// The whole tournament, as written by `--bracket`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bracket {
+    pub format: Format,
+    pub seeds: Vec<Seed>,
+    pub rounds: Vec<Round>,
+    /// Final table of a round robin, best first; empty for brackets.
+    pub standings: Vec<Standing>,
+    pub champion: Option<String>,
+}
+
+impl Bracket {
+    /// Write the bracket as pretty-printed JSON.
+    pub fn write(&self, path: &Path) -> Result<(), SimError> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other);
+        json.and_then(|json| fs::write(path, json + "\n"))
+            .map_err(|source| SimError::WriteResult {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+}
+
+/// Result of one match between seeds `a` and `b`.
+#[derive(Debug, Clone, PartialEq)]
+struct Outcome {
+    /// Seed index of the winner; `None` for a draw.
+    winner: Option<usize>,
+    /// Seed index of the bot that goes through, with draws decided by tiebreak.
+    advancing: usize,
+    ticks: u64,
+    /// Where the replay was saved, if replays are kept.
+    replay: Option<PathBuf>,
+}
+
+/// A tournament being played.
+pub struct Tournament {
+    pub format: Format,
+    /// Bots in seeding order, best first.
+    bots: Vec<BotScript>,
+    config: GameConfig,
+    /// Arena layout of every match.
+    obstacles: Vec<Obstacle>,
+    /// Matches still going after this many ticks are stopped and count as draws.
+    max_ticks: u64,
+    /// Directory the replay of every match is saved in, if any.
+    replays: Option<PathBuf>,
+    /// Matches played so far, numbering the replay files.
+    played: usize,
+    /// Database every match is recorded in, if any.
+    history: Option<History>,
+    /// Directory each bot's coverage listing is written to, if any.
+    coverage_dir: Option<PathBuf>,
+    /// Coverage of every bot so far, by name.
+    coverage: BTreeMap<String, Coverage>,
+    bracket: Bracket,
+}
+
+impl Tournament {
+    /// Seed `bots` by their ladder rating (entry order breaks ties) and check that
+    /// every one of them compiles. Bots need distinct names, since the ladder and
+    /// the bracket tell them apart by name.
+    pub fn new(
+        format: Format,
+        mut bots: Vec<BotScript>,
+        ladder: &Ladder,
+        config: GameConfig,
+        max_ticks: u64,
+    ) -> Result<Self, SimError> {
+        if bots.len() < 2 {
+            return Err(SimError::Config(
+                "a tournament needs at least two bots".to_string(),
+            ));
+        }
+        let mut names: Vec<&str> = bots.iter().map(|b| b.name.as_str()).collect();
+        names.sort_unstable();
+        if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(SimError::Config(format!(
+                "two bots are named `{}`; tournament bots need distinct names",
+                pair[0]
+            )));
+        }
+        let errors: Vec<SimError> = bots
+            .iter()
+            .filter_map(|bot| compile_robots(std::slice::from_ref(bot), &config).err())
+            .flatten()
+            .collect();
+        if !errors.is_empty() {
+            return Err(SimError::ScriptsRejected(errors));
+        }
+
+        // Stable, so bots with equal ratings keep their entry order
+        bots.sort_by(|a, b| ladder.rating(&b.name).total_cmp(&ladder.rating(&a.name)));
+        let seeds = bots
+            .iter()
+            .enumerate()
+            .map(|(i, bot)| Seed {
+                seed: i + 1,
+                name: bot.name.clone(),
+                rating: ladder.rating(&bot.name),
+            })
+            .collect();
+        Ok(Tournament {
+            format,
+            bots,
+            config,
+            obstacles: default_obstacles(),
+            max_ticks,
+            replays: None,
+            played: 0,
+            history: None,
+            coverage_dir: None,
+            coverage: BTreeMap::new(),
+            bracket: Bracket {
+                format,
+                seeds,
+                rounds: Vec::new(),
+                standings: Vec::new(),
+                champion: None,
+            },
+        })
+    }
+
+    /// Play every match in an arena with these obstacles instead of the default layout.
+    pub fn with_obstacles(mut self, obstacles: Vec<Obstacle>) -> Self {
+        self.obstacles = obstacles;
+        self
+    }
+
+    /// Save the replay of every match in `dir`, which is created if needed.
+    /// Files are named after the match number and the two bots, such as
+    /// `003-tracker-vs-hunter.json`.
+    pub fn with_replays(mut self, dir: &Path) -> Self {
+        self.replays = Some(dir.to_path_buf());
+        self
+    }
+
+    /// Record every match in `history`.
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Track which commands of each bot ran over all its matches, and write a
+    /// coverage listing per bot to `dir`, such as `tracker.txt`, once the
+    /// tournament is over. The directory is created if needed.
+    pub fn with_coverage(mut self, dir: &Path) -> Self {
+        self.coverage_dir = Some(dir.to_path_buf());
+        self.coverage = self
+            .bots
+            .iter()
+            .filter_map(|bot| Some((bot.name.clone(), Coverage::new(bot)?)))
+            .collect();
+        self
+    }
+
+    /// Play the whole tournament, updating `ladder` after every match and
+    /// calling `on_round` after every round so progress can be shown.
+    pub fn run(
+        mut self,
+        ladder: &mut Ladder,
+        mut on_round: impl FnMut(&Round),
+    ) -> Result<Bracket, SimError> {
+        let mut report = |round: &Round| on_round(round);
+        let champion = match self.format {
+            Format::RoundRobin => self.round_robin(ladder, &mut report)?,
+            Format::SingleElimination => self.single_elimination(ladder, &mut report)?,
+            Format::DoubleElimination => self.double_elimination(ladder, &mut report)?,
+        };
+        self.bracket.champion = Some(self.bots[champion].name.clone());
+        if let Some(dir) = &self.coverage_dir {
+            fs::create_dir_all(dir).map_err(|source| SimError::WriteCoverage {
+                path: dir.clone(),
+                source,
+            })?;
+            for (name, coverage) in &self.coverage {
+                let path = dir.join(format!("{}.txt", name));
+                Coverage::write(std::slice::from_ref(coverage), &path)?;
+            }
+        }
+        Ok(self.bracket)
+    }
+
+    fn round_robin(
+        &mut self,
+        ladder: &mut Ladder,
+        report: &mut dyn FnMut(&Round),
+    ) -> Result<usize, SimError> {
+        let n = self.bots.len();
+        let mut table: Vec<(u32, u32, u32)> = vec![(0, 0, 0); n];
+        // Circle method: fix the first slot and rotate the others, with a bye
+        // slot when the field is odd
+        let mut slots: Vec<Option<usize>> = (0..n).map(Some).collect();
+        if n % 2 == 1 {
+            slots.push(None);
+        }
+        for number in 1..slots.len() {
+            let mut matches = Vec::new();
+            for i in 0..slots.len() / 2 {
+                // The bot drawn against the bye slot sits the round out
+                let (Some(x), Some(y)) = (slots[i], slots[slots.len() - 1 - i]) else {
+                    continue;
+                };
+                let (a, b) = (x.min(y), x.max(y));
+                let outcome = self.play(a, b, ladder)?;
+                match outcome.winner {
+                    Some(w) => {
+                        table[w].0 += 1;
+                        table[if w == a { b } else { a }].2 += 1;
+                    }
+                    None => {
+                        table[a].1 += 1;
+                        table[b].1 += 1;
+                    }
+                }
+                matches.push(self.pairing(a, Some(b), &outcome));
+            }
+            self.push_round(Stage::RoundRobin, number, matches, report);
+            slots[1..].rotate_right(1);
+        }
+
+        let mut order: Vec<usize> = (0..n).collect();
+        let points = |(w, d, _): (u32, u32, u32)| 3 * w + d;
+        // Stable, so seeding breaks ties
+        order.sort_by_key(|&i| std::cmp::Reverse(points(table[i])));
+        self.bracket.standings = order
+            .iter()
+            .map(|&i| Standing {
+                name: self.bots[i].name.clone(),
+                wins: table[i].0,
+                draws: table[i].1,
+                losses: table[i].2,
+                points: points(table[i]),
+            })
+            .collect();
+        Ok(order[0])
+    }
+
+    fn single_elimination(
+        &mut self,
+        ladder: &mut Ladder,
+        report: &mut dyn FnMut(&Round),
+    ) -> Result<usize, SimError> {
+        let mut alive = self.first_round_slots();
+        let mut number = 1;
+        while alive.len() > 1 {
+            let (next, _, matches) = self.play_slots(&alive, ladder)?;
+            self.push_round(Stage::Winners, number, matches, report);
+            alive = next;
+            number += 1;
+        }
+        Ok(alive[0].expect("the final has a winner"))
+    }
+
+    fn double_elimination(
+        &mut self,
+        ladder: &mut Ladder,
+        report: &mut dyn FnMut(&Round),
+    ) -> Result<usize, SimError> {
+        let mut winners = self.first_round_slots();
+        let mut losers: Vec<usize> = Vec::new();
+        let (mut winners_round, mut losers_round) = (1, 1);
+        while winners.len() > 1 || losers.len() > 1 {
+            if winners.len() > 1 {
+                let (next, dropped, matches) = self.play_slots(&winners, ladder)?;
+                self.push_round(Stage::Winners, winners_round, matches, report);
+                winners = next;
+                winners_round += 1;
+                losers.extend(dropped);
+            }
+            if losers.len() > 1 {
+                // Best remaining seed meets the worst; the odd one out gets a bye
+                losers.sort_unstable();
+                let mut next = Vec::new();
+                let mut matches = Vec::new();
+                if losers.len() % 2 == 1 {
+                    let bye = losers.remove(0);
+                    matches.push(self.bye(bye));
+                    next.push(bye);
+                }
+                for i in 0..losers.len() / 2 {
+                    let (a, b) = (losers[i], losers[losers.len() - 1 - i]);
+                    let outcome = self.play(a, b, ladder)?;
+                    matches.push(self.pairing(a, Some(b), &outcome));
+                    next.push(outcome.advancing);
+                }
+                self.push_round(Stage::Losers, losers_round, matches, report);
+                losers = next;
+                losers_round += 1;
+            }
+        }
+
+        let top = winners[0].expect("the winners bracket has a winner");
+        let Some(&challenger) = losers.first() else {
+            return Ok(top);
+        };
+        let outcome = self.play(top, challenger, ladder)?;
+        let matches = vec![self.pairing(top, Some(challenger), &outcome)];
+        self.push_round(Stage::Final, 1, matches, report);
+        if outcome.advancing == top {
+            return Ok(top);
+        }
+        // The winners bracket champion has only lost once: play it again
+        let outcome = self.play(top, challenger, ladder)?;
+        let matches = vec![self.pairing(top, Some(challenger), &outcome)];
+        self.push_round(Stage::Final, 2, matches, report);
+        Ok(outcome.advancing)
+    }
+
+    /// Slots of the first knockout round in bracket order, padded with byes to a
+    /// power of two so the top seeds get the byes and meet as late as possible.
+    fn first_round_slots(&self) -> Vec<Option<usize>> {
+        let n = self.bots.len();
+        bracket_order(n.next_power_of_two())
+            .into_iter()
+            .map(|seed| (seed < n).then_some(seed))
+            .collect()
+    }
+
+    /// Play a knockout round over adjacent pairs of slots. Returns the slots of
+    /// the next round, the bots that lost and the pairings.
+    #[allow(clippy::type_complexity)]
+    fn play_slots(
+        &mut self,
+        slots: &[Option<usize>],
+        ladder: &mut Ladder,
+    ) -> Result<(Vec<Option<usize>>, Vec<usize>, Vec<Pairing>), SimError> {
+        let mut next = Vec::new();
+        let mut lost = Vec::new();
+        let mut matches = Vec::new();
+        for pair in slots.chunks(2) {
+            match (pair[0], pair.get(1).copied().flatten()) {
+                (Some(a), Some(b)) => {
+                    let outcome = self.play(a, b, ladder)?;
+                    matches.push(self.pairing(a, Some(b), &outcome));
+                    lost.push(if outcome.advancing == a { b } else { a });
+                    next.push(Some(outcome.advancing));
+                }
+                (Some(a), None) | (None, Some(a)) => {
+                    matches.push(self.bye(a));
+                    next.push(Some(a));
+                }
+                (None, None) => next.push(None),
+            }
+        }
+        Ok((next, lost, matches))
+    }
+
+    /// Play seeds `a` and `b` against each other and record the result on the
+    /// ladder. A match that runs out of ticks is judged on points (see
+    /// `results::judge`); one still without a winner goes to the bot with more
+    /// health left, then to the better seed.
+    fn play(&mut self, a: usize, b: usize, ladder: &mut Ladder) -> Result<Outcome, SimError> {
+        let bots = [self.bots[a].clone(), self.bots[b].clone()];
+        let robots = compile_robots(&bots, &self.config).map_err(SimError::ScriptsRejected)?;
+        let mut sim = Simulation::new(self.config.clone(), robots, self.obstacles.clone());
+        if self.coverage_dir.is_some() {
+            sim = sim.with_profiling();
+        }
+        let started = Instant::now();
+        while !sim.is_over() && sim.tick < self.max_ticks {
+            sim.step();
+        }
+        for (bot, robot) in bots.iter().zip(&sim.robots) {
+            if let Some(coverage) = self.coverage.get_mut(&bot.name) {
+                coverage.add(robot);
+            }
+        }
+
+        // A match that ran out of ticks is judged on points, if the rules say so
+        let winning_team = match sim.stopped_by_rules() {
+            None if sim.is_over() => sim.winning_team(),
+            None | Some(EndCondition::TickLimit) => results::judge(&sim).and_then(|j| j.winner),
+            Some(_) => None,
+        };
+        let winner = winning_team.and_then(|team| sim.robots.iter().position(|r| r.team == team));
+        let winner = winner.map(|i| [a, b][i]);
+        let (ha, hb) = (sim.robots[0].health, sim.robots[1].health);
+        let advancing = winner.unwrap_or(if hb > ha { b } else { a.min(b) });
+        let score = match winner {
+            Some(w) if w == a => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+        ladder.record(&self.bots[a].name, &self.bots[b].name, score);
+
+        self.played += 1;
+        let replay = match &self.replays {
+            Some(dir) => {
+                let file = format!(
+                    "{:03}-{}-vs-{}.json",
+                    self.played, bots[0].name, bots[1].name
+                );
+                let path = dir.join(file);
+                fs::create_dir_all(dir).map_err(|source| SimError::WriteResult {
+                    path: dir.clone(),
+                    source,
+                })?;
+                Replay::record(&sim, bots.to_vec(), self.obstacles.clone()).write(&path)?;
+                Some(path)
+            }
+            None => None,
+        };
+        if let Some(history) = &self.history {
+            let stopped = (!sim.is_over()).then_some(EndCondition::TickLimit);
+            let result = MatchResult::from_sim(&sim, stopped, started.elapsed());
+            history.record(&result, replay.as_deref())?;
+        }
+        Ok(Outcome {
+            winner,
+            advancing,
+            ticks: sim.tick,
+            replay,
+        })
+    }
+
+    fn pairing(&self, a: usize, b: Option<usize>, outcome: &Outcome) -> Pairing {
+        let knockout = self.format != Format::RoundRobin;
+        let winner = if knockout {
+            Some(outcome.advancing)
+        } else {
+            outcome.winner
+        };
+        Pairing {
+            a: self.bots[a].name.clone(),
+            b: b.map(|b| self.bots[b].name.clone()),
+            winner: winner.map(|w| self.bots[w].name.clone()),
+            ticks: outcome.ticks,
+            tiebreak: knockout && outcome.winner.is_none(),
+            replay: outcome.replay.clone(),
+        }
+    }
+
+    fn bye(&self, a: usize) -> Pairing {
+        Pairing {
+            a: self.bots[a].name.clone(),
+            b: None,
+            winner: Some(self.bots[a].name.clone()),
+            ticks: 0,
+            tiebreak: false,
+            replay: None,
+        }
+    }
+
+    fn push_round(
+        &mut self,
+        stage: Stage,
+        number: usize,
+        matches: Vec<Pairing>,
+        report: &mut dyn FnMut(&Round),
+    ) {
+        let round = Round {
+            stage,
+            number,
+            matches,
+        };
+        report(&round);
+        self.bracket.rounds.push(round);
+    }
+}
+
+/// Seed indices in bracket order for a bracket of `size` slots (a power of
+/// two): adjacent slots meet in the first round, and seeds 0 and 1 can only
+/// meet in the final.
+fn bracket_order(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let width = order.len() * 2;
+        order = order
+            .iter()
+            .flat_map(|&seed| [seed, width - 1 - seed])
+            .collect();
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bot(name: &str, script: &str) -> BotScript {
+        BotScript {
+            entrant: name.to_string(),
+            name: name.to_string(),
+            script: script.to_string(),
+        }
+    }
+
+    /// A bot that turns its turret onto the spawn point of its opponent and
+    /// fires, so it beats an idle bot quickly from either spawn point.
+    fn shooter(name: &str) -> BotScript {
+        bot(
+            name,
+            "loop {\n scan\n aim at scanned\n fire\n rotate scanner 10\n}",
+        )
+    }
+
+    fn idle(name: &str) -> BotScript {
+        bot(name, "loop {\n rotate body 1\n}")
+    }
+
+    #[test]
+    fn test_bracket_order_keeps_top_seeds_apart() {
+        assert_eq!(bracket_order(4), vec![0, 3, 1, 2]);
+        assert_eq!(bracket_order(8), vec![0, 7, 3, 4, 1, 6, 2, 5]);
+    }
+
+    #[test]
+    fn test_ladder_seeds_and_elo() {
+        let mut ladder = Ladder::default();
+        ladder.ratings.insert("strong".to_string(), 1700.0);
+        let bots = vec![idle("a"), idle("b"), idle("strong")];
+        let tournament = Tournament::new(
+            Format::SingleElimination,
+            bots,
+            &ladder,
+            GameConfig::default(),
+            10,
+        )
+        .unwrap();
+        let names: Vec<&str> = tournament
+            .bracket
+            .seeds
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["strong", "a", "b"]);
+
+        ladder.record("a", "b", 1.0);
+        assert_eq!(ladder.rating("a"), 1516.0);
+        assert_eq!(ladder.rating("b"), 1484.0);
+    }
+
+    #[test]
+    fn test_duplicate_names_rejected() {
+        let bots = vec![idle("twin"), idle("twin")];
+        let result = Tournament::new(
+            Format::RoundRobin,
+            bots,
+            &Ladder::default(),
+            GameConfig::default(),
+            10,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_single_elimination_gives_top_seed_a_bye() {
+        let mut ladder = Ladder::default();
+        ladder.ratings.insert("ace".to_string(), 1600.0);
+        let bots = vec![idle("b"), idle("c"), shooter("ace")];
+        let tournament = Tournament::new(
+            Format::SingleElimination,
+            bots,
+            &ladder,
+            GameConfig::default(),
+            3000,
+        )
+        .unwrap()
+        .with_obstacles(Vec::new());
+        let mut rendered = String::new();
+        let bracket = tournament
+            .run(&mut ladder, |round| rendered += &round.render())
+            .unwrap();
+        assert_eq!(bracket.rounds.len(), 2);
+        assert_eq!(bracket.rounds[0].matches[0].b, None);
+        assert!(rendered.contains("ace advances with a bye"));
+        // Two idle bots can only be split by the tiebreak
+        assert!(bracket.rounds[0].matches[1].tiebreak);
+        assert_eq!(bracket.champion.as_deref(), Some("ace"));
+        assert!(ladder.rating("ace") > 1600.0);
+    }
+
+    #[test]
+    fn test_replays_and_history_saved_per_match() {
+        let dir = std::env::temp_dir().join(format!(
+            "robot-battle-tournament-replays-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let mut ladder = Ladder::default();
+        let tournament = Tournament::new(
+            Format::RoundRobin,
+            vec![shooter("ace"), idle("b")],
+            &ladder,
+            GameConfig::default(),
+            3000,
+        )
+        .unwrap()
+        .with_obstacles(Vec::new())
+        .with_replays(&dir)
+        .with_history(History::open(&dir.with_extension("db")).unwrap());
+        let bracket = tournament.run(&mut ladder, |_| {}).unwrap();
+        let path = bracket.rounds[0].matches[0].replay.clone().unwrap();
+        assert_eq!(path, dir.join("001-ace-vs-b.json"));
+        let replay = Replay::load(&path).unwrap();
+        assert_eq!(replay.check().unwrap(), None);
+
+        let history = History::open(&dir.with_extension("db")).unwrap();
+        let matches = history.matches(Some("ace"), Some("b"), 10).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].replay, Some(path.to_string_lossy().into_owned()));
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_file(dir.with_extension("db"));
+    }
+
+    #[test]
+    fn test_coverage_written_per_bot() {
+        let dir = std::env::temp_dir().join(format!(
+            "robot-battle-tournament-coverage-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let mut ladder = Ladder::default();
+        let lazy = bot("lazy", "loop {\n scan\n}\nfire");
+        Tournament::new(
+            Format::RoundRobin,
+            vec![shooter("ace"), lazy, idle("c")],
+            &ladder,
+            GameConfig::default(),
+            200,
+        )
+        .unwrap()
+        .with_obstacles(Vec::new())
+        .with_coverage(&dir)
+        .run(&mut ladder, |_| {})
+        .unwrap();
+        let listing = fs::read_to_string(dir.join("lazy.txt")).unwrap();
+        assert!(listing.starts_with("lazy: 2 of 3 commands ran in 2 match(es)"));
+        assert!(listing.ends_with("##### |    4 | fire\n"));
+        assert!(dir.join("ace.txt").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_round_robin_standings() {
+        let bots = vec![shooter("ace"), idle("b"), idle("c")];
+        let mut ladder = Ladder::default();
+        let tournament = Tournament::new(
+            Format::RoundRobin,
+            bots,
+            &ladder,
+            GameConfig::default(),
+            3000,
+        )
+        .unwrap()
+        .with_obstacles(Vec::new());
+        let bracket = tournament.run(&mut ladder, |_| {}).unwrap();
+        // Three bots: three rounds, with one bot sitting out each
+        assert_eq!(bracket.rounds.len(), 3);
+        assert!(bracket.rounds.iter().all(|r| r.matches.len() == 1));
+        let ace = &bracket.standings[0];
+        assert_eq!((ace.name.as_str(), ace.wins, ace.points), ("ace", 2, 6));
+        assert_eq!(bracket.standings[1].draws, 1);
+        assert_eq!(bracket.champion.as_deref(), Some("ace"));
+    }
+
+    #[test]
+    fn test_double_elimination_needs_two_losses() {
+        let bots = vec![shooter("ace"), idle("b"), idle("c"), idle("d")];
+        let mut ladder = Ladder::default();
+        let tournament = Tournament::new(
+            Format::DoubleElimination,
+            bots,
+            &ladder,
+            GameConfig::default(),
+            3000,
+        )
+        .unwrap()
+        .with_obstacles(Vec::new());
+        let bracket = tournament.run(&mut ladder, |_| {}).unwrap();
+        let stages: Vec<Stage> = bracket.rounds.iter().map(|r| r.stage).collect();
+        assert!(stages.contains(&Stage::Losers));
+        assert_eq!(stages.last(), Some(&Stage::Final));
+        assert_eq!(bracket.champion.as_deref(), Some("ace"));
+        // Everyone but the unbeaten champion is out after two losses
+        let mut losses: BTreeMap<&str, u32> = BTreeMap::new();
+        for pairing in bracket.rounds.iter().flat_map(|r| &r.matches) {
+            if let (Some(b), Some(winner)) = (&pairing.b, &pairing.winner) {
+                let loser = if winner == &pairing.a { b } else { &pairing.a };
+                *losses.entry(loser).or_default() += 1;
+            }
+        }
+        assert_eq!(losses.get("ace"), None);
+        assert_eq!(losses.len(), 3);
+        assert!(losses.values().all(|l| *l == 2));
+    }
+}
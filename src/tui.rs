@@ -0,0 +1,395 @@
+//! Text-mode renderer for the robot-battle arena.
+//!
+//! Draws the arena as a grid of characters and the HUD as plain text using ANSI
+//! escape codes, so battles can be watched over SSH. When stdout is not a terminal
+//! (e.g. CI logs) frames are printed periodically without cursor control codes.
+
+use std::io::{self, IsTerminal, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ast::Robot;
+use crate::config::GameConfig;
+use crate::ecs::{BeamKind, Sprite};
+use crate::elevation::Level;
+use crate::modes::GameMode;
+use crate::record::Recorder;
+use crate::replay::ReplayWriter;
+use crate::results::{EndCondition, RunLimits};
+use crate::simulation::Simulation;
+
+/// Size of the character grid used for the arena.
+const GRID_COLS: usize = 60;
+const GRID_ROWS: usize = 30;
+
+/// Delay between rendered frames when attached to a terminal.
+const FRAME_DELAY: Duration = Duration::from_millis(33);
+
+/// Simulation ticks advanced per rendered frame.
+const TICKS_PER_FRAME: u64 = 2;
+
+/// Ticks between frames when output is not a terminal.
+const LOG_FRAME_INTERVAL: u64 = 500;
+
+/// Number of recent events listed under the HUD.
+const EVENT_LOG_LINES: usize = 3;
+
+/// ANSI foreground colors for robots, matching the window renderer.
+const ROBOT_COLORS: [&str; 2] = ["\x1b[31m", "\x1b[34m"];
+const DEFAULT_COLOR: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+/// Faint text for cloaked robots.
+const DIM: &str = "\x1b[2m";
+
+/// Map a logical arena position to a grid cell, or `None` if it lies outside the arena.
+fn to_grid_coords(x: f32, y: f32, config: &GameConfig) -> Option<(usize, usize)> {
+    if !(0.0..config.arena_width).contains(&x) || !(0.0..config.arena_height).contains(&y) {
+        return None;
+    }
+    let col = (x / config.arena_width * GRID_COLS as f32) as usize;
+    let row = (y / config.arena_height * GRID_ROWS as f32) as usize;
+    Some((col.min(GRID_COLS - 1), row.min(GRID_ROWS - 1)))
+}
+
+/// Character used to mark a robot in the grid.
+fn robot_glyph(robot: &Robot) -> char {
+    std::char::from_digit((robot.id % 36) as u32, 36).unwrap_or('?')
+}
+
+/// Contents of one grid cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    Empty,
+    Obstacle,
+    Destructible,
+    Mover,
+    Platform,
+    Ramp,
+    Zone,
+    Smoke,
+    Hazard,
+    Entity(Sprite),
+    Beam(BeamKind),
+    Robot(usize),
+}
+
+/// Render one frame of the arena and HUD into a string.
+pub fn render_frame(sim: &Simulation, color: bool) -> String {
+    let robots = &sim.robots;
+    let mut grid = vec![vec![Cell::Empty; GRID_COLS]; GRID_ROWS];
+
+    // Mark every cell whose center lies inside an obstacle, a door or barrier,
+    // smoke, a hazard about to strike, the control zone or up off the ground
+    let zone = sim.control_zone();
+    let movers: Vec<_> = sim.world.moving_boxes().collect();
+    for (row, cells) in grid.iter_mut().enumerate() {
+        for (col, cell) in cells.iter_mut().enumerate() {
+            let x = (col as f32 + 0.5) / GRID_COLS as f32 * sim.config.arena_width;
+            let y = (row as f32 + 0.5) / GRID_ROWS as f32 * sim.config.arena_height;
+            let in_smoke =
+                |(center, radius): ((f32, f32), f32)| (x - center.0).hypot(y - center.1) <= radius;
+            if let Some(obstacle) = sim.obstacles.iter().find(|o| o.contains((x, y))) {
+                *cell = match obstacle.health {
+                    Some(_) => Cell::Destructible,
+                    None => Cell::Obstacle,
+                };
+            } else if movers.iter().any(|m| m.contains((x, y))) {
+                *cell = Cell::Mover;
+            } else if sim.world.smoke_clouds().any(in_smoke) {
+                *cell = Cell::Smoke;
+            } else if sim
+                .strikes
+                .iter()
+                .any(|s| s.area.contains((x, y), &sim.config))
+            {
+                *cell = Cell::Hazard;
+            } else if zone.is_some_and(|z| z.contains((x, y))) {
+                *cell = Cell::Zone;
+            } else {
+                *cell = match sim.elevation.level_at((x, y)) {
+                    Level::Ground => Cell::Empty,
+                    Level::Ramp => Cell::Ramp,
+                    Level::Platform => Cell::Platform,
+                };
+            }
+        }
+    }
+    for (sprite, transform) in sim.world.renderables() {
+        if let Some((col, row)) =
+            to_grid_coords(transform.position.0, transform.position.1, &sim.config)
+        {
+            grid[row][col] = Cell::Entity(sprite);
+        }
+    }
+    for (transform, beam, _) in sim.world.visible_beams() {
+        let angle = transform.heading.to_radians();
+        // One sample per unit of beam is plenty at this resolution
+        for step in 0..=beam.length as usize {
+            let (x, y) = (
+                transform.position.0 + step as f32 * angle.cos(),
+                transform.position.1 + step as f32 * angle.sin(),
+            );
+            if let Some((col, row)) = to_grid_coords(x, y, &sim.config)
+                && matches!(
+                    grid[row][col],
+                    Cell::Empty
+                        | Cell::Zone
+                        | Cell::Smoke
+                        | Cell::Hazard
+                        | Cell::Platform
+                        | Cell::Ramp
+                )
+            {
+                grid[row][col] = Cell::Beam(beam.kind);
+            }
+        }
+    }
+    // Destroyed robots are shown by their wrecks while these last
+    for (i, robot) in robots.iter().enumerate().filter(|(_, r)| r.health > 0) {
+        if let Some((col, row)) = to_grid_coords(robot.position.0, robot.position.1, &sim.config) {
+            grid[row][col] = Cell::Robot(i);
+        }
+    }
+
+    let color_for = |i: usize| ROBOT_COLORS.get(i).copied().unwrap_or(DEFAULT_COLOR);
+
+    let mut out = String::new();
+    let border = format!("+{}+\n", "-".repeat(GRID_COLS));
+    out.push_str(&border);
+    for row in &grid {
+        out.push('|');
+        for cell in row {
+            match cell {
+                Cell::Robot(i) if color => {
+                    if robots[*i].cloaked {
+                        out.push_str(DIM);
+                    }
+                    out.push_str(color_for(*i));
+                    out.push(robot_glyph(&robots[*i]));
+                    out.push_str(RESET);
+                }
+                Cell::Robot(i) => out.push(robot_glyph(&robots[*i])),
+                Cell::Obstacle => out.push('#'),
+                Cell::Destructible => out.push('='),
+                Cell::Mover => out.push('&'),
+                Cell::Platform => out.push('^'),
+                Cell::Ramp => out.push('/'),
+                Cell::Zone => out.push('.'),
+                Cell::Smoke => out.push(':'),
+                Cell::Hazard => out.push('!'),
+                Cell::Entity(Sprite::Projectile) => out.push('*'),
+                Cell::Entity(Sprite::Ricochet) => out.push('o'),
+                Cell::Entity(Sprite::Emp) => out.push('%'),
+                Cell::Entity(Sprite::Wreck) => out.push('x'),
+                Cell::Entity(Sprite::SalvageWreck) => out.push('$'),
+                Cell::Entity(Sprite::Supply) => out.push('s'),
+                Cell::Beam(BeamKind::Laser) => out.push('~'),
+                Cell::Beam(BeamKind::Repair) => out.push('+'),
+                Cell::Beam(BeamKind::Intercept) => out.push('-'),
+                Cell::Empty => out.push(' '),
+            }
+        }
+        out.push_str("|\n");
+    }
+    out.push_str(&border);
+
+    // HUD for each robot
+    for (i, robot) in robots.iter().enumerate() {
+        let mut hud_text = format!(
+            "Robot {:>2} | Pos: ({:>6.1}, {:>6.1}) | Heading: {:>5.1}°",
+            robot.id, robot.position.0, robot.position.1, robot.heading
+        );
+        if let Some(fuse) = robot.fuse {
+            hud_text.push_str(&format!(" | SELF-DESTRUCT in {}", fuse));
+        }
+        if robot.cloaked {
+            hud_text.push_str(" | CLOAKED");
+        }
+        if robot.point_defense {
+            hud_text.push_str(" | DEFENSE");
+        }
+        if robot.hook.is_some() {
+            hud_text.push_str(" | HOOKED");
+        }
+        if robot.stunned > 0 {
+            hud_text.push_str(&format!(" | STUNNED for {}", robot.stunned));
+        }
+        if let Some(text) = robot.speech_at(sim.tick) {
+            hud_text.push_str(&format!(" | says \"{}\"", text));
+        }
+        if color {
+            out.push_str(color_for(i));
+            out.push_str(&hud_text);
+            out.push_str(RESET);
+        } else {
+            out.push_str(&hud_text);
+        }
+        out.push('\n');
+    }
+
+    if sim.mode == GameMode::WaveSurvival {
+        let score = sim.scores.values().sum::<u32>();
+        out.push_str(&format!("Wave {} | Score {}\n", sim.wave, score));
+    }
+    if let Some(drill) = &sim.drill {
+        out.push_str(&drill.status());
+        out.push('\n');
+    }
+    if let Some(objective) = &sim.objective {
+        out.push_str(&objective.status(sim.tick));
+        out.push('\n');
+        for line in objective.description.lines().filter(|l| !l.is_empty()) {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    if sim.mode == GameMode::KingOfTheHill {
+        let scores: Vec<String> = sim
+            .scores
+            .iter()
+            .map(|(team, points)| format!("Team {}: {}", team, points))
+            .collect();
+        out.push_str(&format!(
+            "King of the hill (first to {}) | {}\n",
+            sim.config.koth_target_score,
+            scores.join(" | ")
+        ));
+    }
+
+    // Most recent events, newest last
+    let recent = &sim.events[sim.events.len().saturating_sub(EVENT_LOG_LINES)..];
+    for event in recent {
+        out.push_str(&event.to_string());
+        out.push('\n');
+    }
+    out
+}
+
+/// Run the simulation, drawing it to the terminal until the match ends or hits
+/// one of `limits`, and streaming it to `replay` if given. Returns the final
+/// simulation and the limit it hit, if any.
+pub fn run(
+    mut sim: Simulation,
+    mut recorder: Option<Recorder>,
+    mut replay: Option<&mut ReplayWriter>,
+    limits: RunLimits,
+) -> io::Result<(Simulation, Option<EndCondition>)> {
+    let started = Instant::now();
+    let stdout = io::stdout();
+    let interactive = stdout.is_terminal();
+    let mut out = stdout.lock();
+
+    if interactive {
+        // Clear the screen and hide the cursor
+        write!(out, "\x1b[2J\x1b[?25l")?;
+    }
+
+    let mut stopped = None;
+    loop {
+        stopped = stopped.or_else(|| limits.reached(&sim, started));
+        let over = sim.is_over() || stopped.is_some();
+        if interactive {
+            write!(out, "\x1b[H{}", render_frame(&sim, true))?;
+            out.flush()?;
+        } else if sim.tick.is_multiple_of(LOG_FRAME_INTERVAL) || over {
+            writeln!(out, "tick {}", sim.tick)?;
+            write!(out, "{}", render_frame(&sim, false))?;
+        }
+
+        if over {
+            break;
+        }
+
+        for _ in 0..TICKS_PER_FRAME {
+            if sim.is_over() || limits.reached(&sim, started).is_some() {
+                break;
+            }
+            sim.step();
+            if let Some(rec) = recorder.as_mut() {
+                rec.capture(&sim)?;
+            }
+            if let Some(writer) = replay.as_deref_mut() {
+                writer.capture(&mut sim)?;
+            }
+        }
+        if interactive {
+            thread::sleep(FRAME_DELAY);
+        }
+    }
+
+    if let Some(rec) = recorder.as_mut() {
+        rec.finish()?;
+    }
+
+    if interactive {
+        // Show the cursor again
+        write!(out, "\x1b[?25h")?;
+        out.flush()?;
+    }
+    Ok((sim, stopped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Command, Emote, SPEECH_TICKS, translate_commands_to_instructions};
+    use crate::raycast::Obstacle;
+
+    fn robot_at(id: usize, x: f32, y: f32) -> Robot {
+        Robot::new(id, "test", (x, y), vec![])
+    }
+
+    #[test]
+    fn test_grid_coords_clamp_to_arena() {
+        let config = GameConfig::default();
+        assert_eq!(to_grid_coords(0.0, 0.0, &config), Some((0, 0)));
+        assert_eq!(
+            to_grid_coords(200.0, 200.0, &config),
+            Some((GRID_COLS / 2, GRID_ROWS / 2))
+        );
+        assert_eq!(to_grid_coords(-1.0, 10.0, &config), None);
+        assert_eq!(to_grid_coords(10.0, config.arena_height, &config), None);
+    }
+
+    #[test]
+    fn test_render_frame_places_robots() {
+        let robots = vec![robot_at(1, 0.0, 0.0), robot_at(2, 200.0, 200.0)];
+        let sim = Simulation::new(GameConfig::default(), robots.clone(), vec![]);
+        let frame = render_frame(&sim, false);
+        let lines: Vec<&str> = frame.lines().collect();
+        // Border + rows + border + one HUD line per robot
+        assert_eq!(lines.len(), GRID_ROWS + 2 + robots.len());
+        assert_eq!(lines[1].chars().nth(1), Some('1'));
+        assert_eq!(
+            lines[1 + GRID_ROWS / 2].chars().nth(1 + GRID_COLS / 2),
+            Some('2')
+        );
+        assert!(lines[GRID_ROWS + 2].starts_with("Robot  1"));
+    }
+
+    #[test]
+    fn test_render_frame_shows_speech() {
+        let emote = translate_commands_to_instructions(&[Command::Emote(Emote::Happy)]);
+        let robots = vec![robot_at(1, 10.0, 10.0), robot_at(2, 200.0, 200.0)];
+        let mut sim = Simulation::new(GameConfig::default(), robots, vec![]);
+        sim.robots[0].instruction_queue = emote;
+        sim.step();
+        let frame = render_frame(&sim, false);
+        assert!(frame.contains("Robot  1 | Pos: (  10.0,   10.0) | Heading:   0.0° | says \":)\""));
+        for _ in 0..SPEECH_TICKS {
+            sim.step();
+        }
+        assert!(!render_frame(&sim, false).contains("says"));
+    }
+
+    #[test]
+    fn test_render_frame_draws_obstacles() {
+        let config = GameConfig::default();
+        let obstacle = Obstacle::new(0.0, 0.0, config.arena_width, 20.0);
+        let sim = Simulation::new(config, vec![], vec![obstacle]);
+        let frame = render_frame(&sim, false);
+        let lines: Vec<&str> = frame.lines().collect();
+        assert_eq!(lines[1], format!("|{}|", "#".repeat(GRID_COLS)));
+        assert_eq!(lines[3], format!("|{}|", " ".repeat(GRID_COLS)));
+    }
+}
@@ -0,0 +1,198 @@
+// The tutorial: built-in scenarios that teach the DSL one step at a time,
+// from driving to a point to beating a first opponent. Each lesson explains
+// its goal on screen and passes or fails like any scenario; lessons passed are
+// remembered in the data directory so `tutorial` can point to the next one.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scenario::Scenario;
+
+/// A tutorial lesson: a short name to pick it by and its scenario.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lesson {
+    pub name: &'static str,
+    scenario: &'static str,
+}
+
+/// The lessons, in the order they are meant to be played.
+pub const LESSONS: &[Lesson] = &[
+    Lesson {
+        name: "waypoint",
+        scenario: r#"
+            name = "First steps"
+            description = """
+Drive your robot into the circle on the right.
+`goto x y` turns toward a point and drives there:
+    goto 300 200"""
+            within = 3000
+
+            [player]
+            position = [60, 200]
+
+            [goal]
+            kind = "reach"
+            position = [300, 200]
+            radius = 15
+        "#,
+    },
+    Lesson {
+        name: "patrol",
+        scenario: r#"
+            name = "Patrol"
+            description = """
+Visit the four corners in order; the circle shows the next one.
+Declare the route in a `path` block and drive it with `follow path`:
+    path { 80 80  320 80  320 320  80 320 }
+    loop { follow path }"""
+            within = 8000
+
+            [player]
+            position = [200, 80]
+
+            [goal]
+            kind = "patrol"
+            waypoints = [[80, 80], [320, 80], [320, 320], [80, 320]]
+            radius = 15
+        "#,
+    },
+    Lesson {
+        name: "target",
+        scenario: r#"
+            name = "Target practice"
+            description = """
+Hit the robot on the right. It will not move or shoot back.
+`scan` looks along the scanner, `aim at scanned` turns the turret to what it found:
+    loop { scan; aim at scanned; fire }"""
+            within = 2000
+
+            [player]
+            position = [100, 200]
+
+            [[robot]]
+            bot = "builtin:sitting-duck"
+            position = [300, 200]
+
+            [goal]
+            kind = "hit"
+            count = 1
+        "#,
+    },
+    Lesson {
+        name: "duck",
+        scenario: r#"
+            name = "Sitting duck"
+            description = """
+Destroy the sitting duck hiding behind the wall.
+Drive to where the wall does not block your view, then search for it:
+`if` fires when the scan found something and turns the scanner if not.
+    goto 300 120
+    loop { scan; if scan > 0 { aim at scanned; fire } else rotate scanner 10 }"""
+            within = 10000
+
+            [player]
+            position = [60, 60]
+
+            [[robot]]
+            bot = "builtin:sitting-duck"
+            position = [200, 300]
+
+            [[obstacle]]
+            x = 40
+            y = 180
+            width = 200
+            height = 20
+
+            [goal]
+            kind = "destroy_all"
+        "#,
+    },
+];
+
+impl Lesson {
+    /// The lesson's scenario.
+    pub fn scenario(&self) -> Scenario {
+        Scenario::parse(self.scenario).expect("built-in lesson")
+    }
+}
+
+/// A lesson by name or by its number, counting from 1.
+pub fn lesson(name: &str) -> Option<Lesson> {
+    let by_number = name
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| LESSONS.get(i));
+    by_number
+        .or_else(|| LESSONS.iter().find(|l| l.name == name))
+        .copied()
+}
+
+/// The lessons passed so far.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Progress {
+    pub passed: BTreeSet<String>,
+}
+
+impl Progress {
+    /// Read the progress file; without one, nothing has been passed yet.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Progress::default());
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Write the progress file, creating its directory if needed.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The first lesson not passed yet, if any.
+    pub fn next(&self) -> Option<(usize, Lesson)> {
+        LESSONS
+            .iter()
+            .copied()
+            .enumerate()
+            .find(|(_, lesson)| !self.passed.contains(lesson.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lessons_parse_and_are_found_by_name_or_number() {
+        for lesson in LESSONS {
+            assert!(!lesson.scenario().description.is_empty());
+        }
+        assert_eq!(lesson("2"), lesson("patrol"));
+        assert_eq!(lesson("0"), None);
+        assert_eq!(lesson("boss"), None);
+    }
+
+    #[test]
+    fn test_progress_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("robot-battle-tutorial-{}", std::process::id()))
+            .join("tutorial.toml");
+        let mut progress = Progress::load(&path).unwrap();
+        assert_eq!(progress.next().unwrap().1.name, "waypoint");
+        progress.passed.insert("waypoint".to_string());
+        progress.write(&path).unwrap();
+
+        let progress = Progress::load(&path).unwrap();
+        assert_eq!(progress.next().unwrap(), (1, LESSONS[1]));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+// Tournament checks for submitted bots.
+// `verify` compiles a bot within the sandbox limits and points budget, enforces the tournament's
+// feature bans and describes the bot in a manifest that submission pipelines
+// can store alongside the script.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::ast::Command;
+use crate::config::GameConfig;
+use crate::error::VerifyError;
+use crate::loadout::Loadout;
+use crate::parser::parse_script;
+use crate::sandbox;
+
+/// Script features a tournament can ban, by the name used in rules files.
+pub const FEATURES: &[&str] = &[
+    "goto",
+    "path",
+    "fire_lead",
+    "ricochet",
+    "laser",
+    "emp",
+    "self_destruct",
+    "smoke",
+    "cloak",
+    "point_defense",
+    "hook",
+    "repair",
+    "persist",
+    "plugins",
+];
+
+/// Rules a tournament imposes on submitted bots, on top of the sandbox limits
+/// in the game config.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TournamentRules {
+    /// Features from `FEATURES` that bots may not use.
+    pub banned: Vec<String>,
+}
+
+impl TournamentRules {
+    /// Load rules from a TOML file, rejecting features that do not exist.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let rules: TournamentRules =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(unknown) = rules
+            .banned
+            .iter()
+            .find(|f| !FEATURES.contains(&f.as_str()))
+        {
+            return Err(format!(
+                "{}: unknown feature `{}` (expected one of {})",
+                path.display(),
+                unknown,
+                FEATURES.join(", ")
+            ));
+        }
+        Ok(rules)
+    }
+}
+
+/// Description of a bot that passed verification.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub author: Option<String>,
+    /// SHA-256 of the script file, in hex.
+    pub sha256: String,
+    /// Length of the compiled program.
+    pub instructions: usize,
+    /// Stat levels from the script's `build` header.
+    pub build: Loadout,
+    /// Features from `FEATURES` the script uses.
+    pub features: Vec<String>,
+}
+
+/// Verify the bot at `path` against the sandbox limits in `config` and the
+/// tournament `rules`. The bot is named after the file unless `name` is given.
+pub fn verify(
+    path: &Path,
+    name: Option<&str>,
+    author: Option<&str>,
+    config: &GameConfig,
+    rules: &TournamentRules,
+) -> Result<Manifest, VerifyError> {
+    let bytes = fs::read(path).map_err(|source| VerifyError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let script_error = |source| VerifyError::Script {
+        path: path.to_path_buf(),
+        source,
+    };
+    let script = String::from_utf8_lossy(&bytes);
+    let commands = parse_script(&script).map_err(script_error)?;
+    let program = sandbox::compile(&commands, config).map_err(|e| script_error(e.into()))?;
+
+    let features = features_used(&commands);
+    let banned: Vec<&'static str> = features
+        .iter()
+        .copied()
+        .filter(|f| rules.banned.iter().any(|b| b == f))
+        .collect();
+    if !banned.is_empty() {
+        return Err(VerifyError::Banned {
+            path: path.to_path_buf(),
+            features: banned,
+        });
+    }
+
+    Ok(Manifest {
+        name: name.map(str::to_string).unwrap_or_else(|| bot_name(path)),
+        author: author.map(str::to_string),
+        sha256: sha256_hex(&bytes),
+        instructions: program.len(),
+        build: Loadout::of(&commands),
+        features: features.iter().map(|f| f.to_string()).collect(),
+    })
+}
+
+/// SHA-256 of `bytes`, in lowercase hex.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The file name of a bot without its extension.
+fn bot_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| PathBuf::from(path).display().to_string())
+}
+
+/// Features the script uses, in `FEATURES` order.
+fn features_used(commands: &[Command]) -> Vec<&'static str> {
+    let mut used = Vec::new();
+    collect_features(commands, &mut used);
+    FEATURES
+        .iter()
+        .copied()
+        .filter(|f| used.contains(f))
+        .collect()
+}
+
+fn collect_features(commands: &[Command], used: &mut Vec<&'static str>) {
+    for command in commands {
+        let feature = match command {
+            Command::GoTo { .. } => "goto",
+            Command::Path { .. } | Command::FollowPath => "path",
+            Command::FireLead => "fire_lead",
+            Command::FireRicochet => "ricochet",
+            Command::FireLaser => "laser",
+            Command::FireEmp => "emp",
+            Command::SelfDestruct => "self_destruct",
+            Command::Smoke => "smoke",
+            Command::Cloak => "cloak",
+            Command::PointDefense { .. } => "point_defense",
+            Command::Hook => "hook",
+            Command::Repair { .. } => "repair",
+            Command::PersistStore { .. } | Command::PersistLoad { .. } => "persist",
+            Command::Plugin { .. } => "plugins",
+            Command::Loop { block } => {
+                collect_features(block, used);
+                continue;
+            }
+            Command::If {
+                block, else_block, ..
+            } => {
+                collect_features(block, used);
+                collect_features(else_block.as_deref().unwrap_or_default(), used);
+                continue;
+            }
+            _ => continue,
+        };
+        used.push(feature);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_bot(name: &str, script: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("robot-battle-verify-{}", name));
+        fs::write(&path, script).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_manifest_describes_bot() {
+        let path = write_bot("ok.robo", "loop {\n scan\n fire laser\n smoke\n}");
+        let manifest = verify(
+            &path,
+            None,
+            Some("ada"),
+            &GameConfig::default(),
+            &TournamentRules::default(),
+        )
+        .unwrap();
+        assert_eq!(manifest.name, "robot-battle-verify-ok");
+        assert_eq!(manifest.author.as_deref(), Some("ada"));
+        assert_eq!(manifest.features, vec!["laser", "smoke"]);
+        assert_eq!(manifest.sha256.len(), 64);
+        // The hash only depends on the file contents
+        let copy = write_bot("copy.robo", "loop {\n scan\n fire laser\n smoke\n}");
+        let other = verify(
+            &copy,
+            Some("bot"),
+            None,
+            &GameConfig::default(),
+            &TournamentRules::default(),
+        )
+        .unwrap();
+        assert_eq!(other.sha256, manifest.sha256);
+        assert_eq!(other.name, "bot");
+    }
+
+    #[test]
+    fn test_banned_features_and_limits_reject_bot() {
+        let path = write_bot("banned.robo", "loop {\n smoke\n selfdestruct\n fire\n}");
+        let rules: TournamentRules = toml::from_str("banned = [\"smoke\", \"laser\"]").unwrap();
+        let err = verify(&path, None, None, &GameConfig::default(), &rules).unwrap_err();
+        assert!(matches!(&err, VerifyError::Banned { features, .. } if features == &["smoke"]));
+
+        let config = GameConfig {
+            max_program_size: 2,
+            ..GameConfig::default()
+        };
+        let err = verify(&path, None, None, &config, &TournamentRules::default()).unwrap_err();
+        assert!(matches!(err, VerifyError::Script { .. }));
+    }
+
+    #[test]
+    fn test_rules_reject_unknown_features() {
+        let path = write_bot("rules.toml", "banned = [\"mines\"]");
+        let err = TournamentRules::load(&path).unwrap_err();
+        assert!(err.contains("unknown feature `mines`"));
+    }
+}
@@ -1,33 +1,162 @@
 use crate::ast::Robot;
+use crate::bindings::{Bindings, Gamepads, ViewerKeys, combine, key_label, pressed};
+use crate::config::{GameConfig, Topology};
+use crate::console::Console;
+use crate::ecs::{Beam, BeamKind, Sprite, Transform};
+use crate::elevation::Elevation;
+use crate::error::SimError;
+use crate::events::Event;
+use crate::hazards::{Area, Axis, Strike};
+use crate::locale;
+use crate::menu::{MenuAction, PauseMenu, ViewSettings, speed_index};
+use crate::modes::{GameMode, Zone};
+use crate::observer;
+use crate::perf::{FrameStats, alloc_stats, count_allocations};
+use crate::raycast::{self, Obstacle};
+use crate::record::Recorder;
+use crate::scenario::Objective;
+use crate::settings::Settings;
+use crate::settings_screen::{Entry, Preferences, SettingsEvent, SettingsScreen};
+use crate::simulation::Simulation;
+use crate::snapshots::SnapshotRing;
 use macroquad::math::Vec2;
 use macroquad::prelude::*;
 use macroquad::shapes::{DrawRectangleParams, draw_rectangle_ex};
+use std::time::Instant;
 
-/// Size of the arena (in logical units)
-const ARENA_WIDTH: f32 = 400.0;
-const ARENA_HEIGHT: f32 = 400.0;
+/// Color of arena doors and barriers
+const MOVER_COLOR: Color = Color::new(0.45, 0.55, 0.7, 1.0);
+/// Shade of raised platforms, and of the stripes on the ramps up to them
+const PLATFORM_COLOR: Color = Color::new(0.3, 0.26, 0.18, 1.0);
+const RAMP_COLOR: Color = Color::new(0.3, 0.26, 0.18, 0.6);
 
-/// Size of each robot (in logical units)
-const ROBOT_WIDTH: f32 = 10.0;
-const ROBOT_HEIGHT: f32 = 10.0;
+/// Layout of the per-robot HUD panels (in screen pixels)
+const PANEL_MAX_WIDTH: f32 = 260.0;
+const PANEL_MAX_HEIGHT: f32 = 84.0;
+const PANEL_MARGIN: f32 = 6.0;
+const PANEL_PADDING: f32 = 6.0;
+const PANEL_ACCENT_WIDTH: f32 = 4.0;
+const PANEL_LINES: f32 = 4.4;
+const PANEL_BACKGROUND: Color = Color::new(0.1, 0.1, 0.1, 0.8);
 
-/// Colors for robots
-const ROBOT_COLORS: [Color; 2] = [RED, BLUE];
+/// Color of robot number `i` in the palette of `style`.
+fn robot_color(style: &Settings, i: usize) -> Color {
+    let [r, g, b] = style.robot_color(i);
+    Color::from_rgba(r, g, b, 255)
+}
+
+/// Color of secondary text, brighter in high contrast.
+fn dim_text(style: &Settings) -> Color {
+    if style.high_contrast {
+        WHITE
+    } else {
+        LIGHTGRAY
+    }
+}
+
+/// Number of recent events listed in the event log
+const EVENT_LOG_LINES: usize = 5;
+
+/// Most matches shown at once by `run_grid`, in a 3x3 grid.
+pub const GRID_MAX_MATCHES: usize = 9;
+/// Height of the mini HUD above each arena of the grid.
+const GRID_HUD_HEIGHT: f32 = 34.0;
+const GRID_GAP: f32 = 8.0;
+/// Frames the grid stays on a finished batch before moving on.
+const GRID_HOLD_FRAMES: u32 = 120;
+
+/// Arena units shown top to bottom by each half of the split screen.
+const FOLLOW_SPAN: f32 = 250.0;
+/// Size of the squares of fog over what a split-screen robot cannot see.
+const FOG_CELL: f32 = 10.0;
+const FOG_COLOR: Color = Color::new(0.0, 0.0, 0.0, 0.6);
+
+/// Rays each scanner cone of the observer overlay is traced with.
+const OBSERVER_CONE_RAYS: usize = 12;
+
+/// Mapping from logical arena units to screen pixels
+struct Viewport<'a> {
+    config: &'a GameConfig,
+    /// Screen position of the shown part's top-left corner.
+    left: f32,
+    top: f32,
+    screen_w: f32,
+    screen_h: f32,
+    /// The part of the arena shown: its top-left corner and size.
+    origin: (f32, f32),
+    span: (f32, f32),
+}
+
+impl<'a> Viewport<'a> {
+    /// A view of the whole arena, stretched over the given screen rectangle.
+    fn whole(config: &'a GameConfig, left: f32, top: f32, screen_w: f32, screen_h: f32) -> Self {
+        Viewport {
+            config,
+            left,
+            top,
+            screen_w,
+            screen_h,
+            origin: (0.0, 0.0),
+            span: (config.arena_width, config.arena_height),
+        }
+    }
+
+    /// A view of the arena around `focus`, `FOLLOW_SPAN` units high, kept in
+    /// the arena where it can be.
+    fn following(config: &'a GameConfig, focus: (f32, f32), screen_w: f32, screen_h: f32) -> Self {
+        let span_h = FOLLOW_SPAN.min(config.arena_height);
+        let span = (span_h * screen_w / screen_h.max(1.0), span_h);
+        let corner = |focus: f32, span: f32, extent: f32| {
+            if span >= extent {
+                (extent - span) / 2.0
+            } else {
+                (focus - span / 2.0).clamp(0.0, extent - span)
+            }
+        };
+        Viewport {
+            config,
+            left: 0.0,
+            top: 0.0,
+            screen_w,
+            screen_h,
+            origin: (
+                corner(focus.0, span.0, config.arena_width),
+                corner(focus.1, span.1, config.arena_height),
+            ),
+            span,
+        }
+    }
 
-/// Convert logical arena coordinates to screen coordinates
-fn to_screen_coords(x: f32, y: f32, screen_w: f32, screen_h: f32) -> (f32, f32) {
-    let sx = x / ARENA_WIDTH * screen_w;
-    let sy = y / ARENA_HEIGHT * screen_h;
-    (sx, sy)
+    /// Convert logical arena coordinates to screen coordinates
+    fn to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.left + self.scale_x(x - self.origin.0),
+            self.top + self.scale_y(y - self.origin.1),
+        )
+    }
+
+    /// Convert a horizontal logical length to pixels
+    fn scale_x(&self, len: f32) -> f32 {
+        len / self.span.0 * self.screen_w
+    }
+
+    /// Convert a vertical logical length to pixels
+    fn scale_y(&self, len: f32) -> f32 {
+        len / self.span.1 * self.screen_h
+    }
 }
 
-/// Draw a single robot as a rectangle, rotated according to its heading
-fn draw_robot(robot: &Robot, color: Color, screen_w: f32, screen_h: f32) {
-    let (x, y) = (robot.position.0 as f32, robot.position.1 as f32);
-    let (sx, sy) = to_screen_coords(x, y, screen_w, screen_h);
+/// Draw a single robot as a rectangle at `at`, rotated according to its
+/// heading, outlined in white if `outline`. Cloaked robots are drawn
+/// translucent.
+fn draw_robot(robot: &Robot, at: (f32, f32), mut color: Color, outline: bool, view: &Viewport) {
+    if robot.cloaked {
+        color.a = 0.3;
+    }
+    let (sx, sy) = view.to_screen(at.0, at.1);
 
-    let rw = ROBOT_WIDTH / ARENA_WIDTH * screen_w;
-    let rh = ROBOT_HEIGHT / ARENA_HEIGHT * screen_h;
+    let rw = view.scale_x(view.config.robot_width);
+    let rh = view.scale_y(view.config.robot_height);
 
     // Use draw_rectangle_ex to rotate around the center using offset
     draw_rectangle_ex(
@@ -36,45 +165,1213 @@ fn draw_robot(robot: &Robot, color: Color, screen_w: f32, screen_h: f32) {
         rw,
         rh,
         DrawRectangleParams {
-            rotation: robot.heading,
+            rotation: robot.heading.to_radians(),
             offset: Vec2::new(0.5, 0.5),
             color,
         },
     );
+    if outline {
+        let (sin, cos) = robot.heading.to_radians().sin_cos();
+        let corner = |u: f32, v: f32| {
+            let (x, y) = (u * rw / 2.0, v * rh / 2.0);
+            (sx + x * cos - y * sin, sy + x * sin + y * cos)
+        };
+        let corners = [
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, 1.0),
+        ];
+        for k in 0..4 {
+            let (a, b) = (corners[k], corners[(k + 1) % 4]);
+            draw_line(a.0, a.1, b.0, b.1, 2.0, WHITE);
+        }
+    }
+
+    // Sparks flicker around a robot an EMP has stunned
+    if robot.stunned > 0 && robot.stunned % 4 < 2 {
+        draw_circle_lines(sx, sy, rw.max(rh), 2.0, SKYBLUE);
+    }
 
     // Draw robot ID (not rotated)
     let id_text = format!("{}", robot.id);
     draw_text(&id_text, sx - 8.0, sy - 8.0, 24.0, WHITE);
 }
 
-/// Visualize the robots in the arena.
-/// This function runs a macroquad window and draws the robots in their positions.
-pub async fn visualize_robots(robots: &[Robot]) {
+/// Where else a robot at `position` shows in a torus arena: as it crosses an
+/// edge, the part sticking out shows at the opposite edge.
+fn edge_ghosts(position: (f32, f32), config: &GameConfig) -> Vec<(f32, f32)> {
+    // Half the diagonal, so a rotated robot is covered too
+    let reach = config.robot_width.hypot(config.robot_height) / 2.0;
+    config
+        .tile_offsets()
+        .skip(1)
+        .map(|(ox, oy)| (position.0 + ox, position.1 + oy))
+        .filter(|(x, y)| {
+            *x > -reach
+                && *x < config.arena_width + reach
+                && *y > -reach
+                && *y < config.arena_height + reach
+        })
+        .collect()
+}
+
+/// Draw what a robot said in a speech bubble above it, `opacity` faded.
+fn draw_speech(robot: &Robot, text: &str, opacity: f32, view: &Viewport) {
+    let background = Color {
+        a: opacity,
+        ..WHITE
+    };
+    let foreground = Color {
+        a: opacity,
+        ..BLACK
+    };
+    let (sx, sy) = view.to_screen(robot.position.0, robot.position.1);
+    let font_size = 20.0;
+    let size = measure_text(text, None, font_size as u16, 1.0);
+    let (w, h) = (size.width + 12.0, size.height + 10.0);
+    let x = sx - w / 2.0;
+    let y = sy - view.scale_y(view.config.robot_height) - h - 8.0;
+    draw_rectangle(x, y, w, h, background);
+    draw_triangle(
+        Vec2::new(sx - 5.0, y + h),
+        Vec2::new(sx + 5.0, y + h),
+        Vec2::new(sx, y + h + 6.0),
+        background,
+    );
+    draw_text(
+        text,
+        x + 6.0,
+        y + 5.0 + size.offset_y,
+        font_size,
+        foreground,
+    );
+}
+
+/// Draw a horizontal bar filled to `fraction` of its width.
+fn draw_bar(x: f32, y: f32, w: f32, h: f32, fraction: f32, color: Color) {
+    draw_rectangle(x, y, w, h, DARKGRAY);
+    draw_rectangle(x, y, w * fraction.clamp(0.0, 1.0), h, color);
+}
+
+/// Draw a status panel for one robot with its top-left corner at (x, y).
+/// In high contrast the panel is opaque and outlined.
+fn draw_status_panel(
+    robot: &Robot,
+    color: Color,
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    style: &Settings,
+) {
+    if style.high_contrast {
+        draw_rectangle(x, y, w, h, BLACK);
+        draw_rectangle_lines(x, y, w, h, 2.0, WHITE);
+    } else {
+        draw_rectangle(x, y, w, h, PANEL_BACKGROUND);
+    }
+    draw_rectangle(x, y, PANEL_ACCENT_WIDTH, h, color);
+
+    let line_h = h / PANEL_LINES;
+    let font_size = line_h * 1.1;
+    let text_x = x + PANEL_ACCENT_WIDTH + PANEL_PADDING;
+    let inner_w = w - PANEL_ACCENT_WIDTH - 2.0 * PANEL_PADDING;
+    let text_color = if robot.health > 0 { WHITE } else { GRAY };
+
+    // Line 1: name, id, kills, cloak, point defense and stun
+    let mut title = locale::format(
+        "hud.title",
+        &[
+            ("name", &robot.name),
+            ("id", &robot.id),
+            ("kills", &robot.kills),
+        ],
+    );
+    let flags = [
+        (robot.cloaked, "hud.cloaked"),
+        (robot.point_defense, "hud.point_defense"),
+        (robot.stunned > 0, "hud.stunned"),
+    ];
+    for (_, key) in flags.iter().filter(|(on, _)| *on) {
+        title.push_str("  ");
+        title.push_str(locale::text(key));
+    }
+    draw_text(&title, text_x, y + line_h * 0.9, font_size, text_color);
+
+    // Line 2: health bar
+    let health_fraction = robot.health as f32 / robot.max_health.max(1) as f32;
+    let health_color = if health_fraction > 0.3 { GREEN } else { RED };
+    draw_bar(
+        text_x,
+        y + line_h * 1.25,
+        inner_w,
+        line_h * 0.5,
+        health_fraction,
+        health_color,
+    );
+
+    // Line 3: energy, ammo, heat
+    let stats = locale::format(
+        "hud.stats",
+        &[
+            ("health", &format!("{:>3}", robot.health.max(0))),
+            ("energy", &format!("{:>3}", robot.energy)),
+            ("ammo", &format!("{:>3}", robot.ammo)),
+            ("heat", &format!("{:>3}", robot.heat)),
+        ],
+    );
+    draw_text(&stats, text_x, y + line_h * 2.9, font_size, text_color);
+
+    // Line 4: current instruction
+    let instr = match robot.current_instruction() {
+        _ if robot.health <= 0 => locale::text("hud.destroyed").to_string(),
+        _ if robot.controls.is_some() => locale::text("hud.player_controlled").to_string(),
+        Some(instr) => format!("> {}", instr),
+        None => locale::text("hud.halted").to_string(),
+    };
+    draw_text(&instr, text_x, y + line_h * 3.9, font_size, dim_text(style));
+}
+
+/// Draw per-robot status panels stacked along the left edge of the screen,
+/// as large as `style` scales them. Panels shrink to fit when there are many
+/// robots.
+fn draw_hud(robots: &[Robot], screen_w: f32, screen_h: f32, style: &Settings) {
+    if robots.is_empty() {
+        return;
+    }
+    let scale = style.hud_scale;
+    let panel_w = (screen_w * 0.3 * scale).min(PANEL_MAX_WIDTH * scale);
+    let available_h = screen_h - PANEL_MARGIN;
+    let slot_h = (available_h / robots.len() as f32).min(PANEL_MAX_HEIGHT * scale + PANEL_MARGIN);
+    let panel_h = slot_h - PANEL_MARGIN;
+
+    for (i, robot) in robots.iter().enumerate() {
+        let color = robot_color(style, i);
+        let y = PANEL_MARGIN + i as f32 * slot_h;
+        draw_status_panel(robot, color, PANEL_MARGIN, y, panel_w, panel_h, style);
+    }
+}
+
+/// Draw an armed self-destruct: the blast radius and the ticks left on the fuse.
+fn draw_fuse(robot: &Robot, fuse: u32, view: &Viewport) {
+    let (sx, sy) = view.to_screen(robot.position.0, robot.position.1);
+    let radius = view.scale_x(view.config.self_destruct_radius);
+    // Blink faster as the fuse runs down
+    let blink = if fuse < 10 { 2 } else { 8 };
+    if (fuse / blink).is_multiple_of(2) {
+        draw_circle_lines(sx, sy, radius, 2.0, ORANGE);
+    }
+    draw_text(&fuse.to_string(), sx + 8.0, sy - 12.0, 28.0, ORANGE);
+}
+
+/// List the most recent events in the bottom-right corner, newest last.
+fn draw_event_log(events: &[Event], screen_w: f32, screen_h: f32, style: &Settings) {
+    let recent = &events[events.len().saturating_sub(EVENT_LOG_LINES)..];
+    let line_h = 18.0 * style.hud_scale;
+    let mut y = screen_h - 36.0 - line_h * (recent.len() as f32 - 1.0);
+    for event in recent {
+        let x = screen_w - 420.0 * style.hud_scale;
+        let line = event.describe(locale::current());
+        draw_text(&line, x, y, line_h, dim_text(style));
+        y += line_h;
+    }
+}
+
+/// Draw the control zone as a translucent disc.
+fn draw_zone(zone: &Zone, view: &Viewport) {
+    let (sx, sy) = view.to_screen(zone.center.0, zone.center.1);
+    let radius = view.scale_x(zone.radius);
+    draw_circle(sx, sy, radius, Color::new(1.0, 0.85, 0.2, 0.15));
+    draw_circle_lines(sx, sy, radius, 2.0, GOLD);
+}
+
+/// Draw a telegraphed hazard strike, flashing faster as it is about to land.
+fn draw_strike(strike: &Strike, tick: u64, config: &GameConfig, view: &Viewport) {
+    let remaining = strike.lands_at.saturating_sub(tick);
+    let flash = if remaining < 20 { 4 } else { 12 };
+    let alpha = if (tick / flash).is_multiple_of(2) {
+        0.35
+    } else {
+        0.15
+    };
+    let fill = Color::new(1.0, 0.2, 0.1, alpha);
+    match strike.area {
+        Area::Circle { center, radius } => {
+            let (sx, sy) = view.to_screen(center.0, center.1);
+            draw_circle(sx, sy, view.scale_x(radius), fill);
+            draw_circle_lines(sx, sy, view.scale_x(radius), 2.0, RED);
+        }
+        Area::Lines { axis, width, .. } => {
+            for at in strike.area.line_offsets(config) {
+                let (sx, sy, w, h) = match axis {
+                    Axis::Horizontal => {
+                        let (sx, sy) = view.to_screen(0.0, at - width / 2.0);
+                        (
+                            sx,
+                            sy,
+                            view.scale_x(config.arena_width),
+                            view.scale_y(width),
+                        )
+                    }
+                    Axis::Vertical => {
+                        let (sx, sy) = view.to_screen(at - width / 2.0, 0.0);
+                        (
+                            sx,
+                            sy,
+                            view.scale_x(width),
+                            view.scale_y(config.arena_height),
+                        )
+                    }
+                };
+                // Clip the stripe to the arena
+                let (left, top) = view.to_screen(0.0, 0.0);
+                let (right, bottom) = view.to_screen(config.arena_width, config.arena_height);
+                let (x0, y0) = (sx.max(left), sy.max(top));
+                let x1 = (sx + w).min(right);
+                let y1 = (sy + h).min(bottom);
+                if x1 > x0 && y1 > y0 {
+                    draw_rectangle(x0, y0, x1 - x0, y1 - y0, fill);
+                }
+            }
+        }
+    }
+}
+
+/// A scenario's status and instructions along the top-right edge, and its
+/// outcome across the middle of the screen once it is decided.
+fn draw_objective(
+    objective: &Objective,
+    tick: u64,
+    screen_w: f32,
+    screen_h: f32,
+    style: &Settings,
+) {
+    let scale = style.hud_scale;
+    let x = screen_w - 520.0 * scale;
+    draw_text(&objective.status(tick), x, 24.0 * scale, 22.0 * scale, GOLD);
+    for (i, line) in objective.description.lines().enumerate() {
+        let y = (46.0 + 18.0 * i as f32) * scale;
+        draw_text(line, x, y, 18.0 * scale, dim_text(style));
+    }
+    if let Some(outcome) = &objective.outcome {
+        let (text, color) = if outcome.passed {
+            let text = locale::format("hud.passed", &[("reason", &outcome.reason)]);
+            (text, GREEN)
+        } else {
+            let text = locale::format("hud.failed", &[("reason", &outcome.reason)]);
+            (text, RED)
+        };
+        let size = measure_text(&text, None, 40, 1.0);
+        draw_text(
+            &text,
+            (screen_w - size.width) / 2.0,
+            screen_h / 2.0,
+            40.0,
+            color,
+        );
+    }
+}
+
+/// Team scores and the target, along the top-right edge.
+fn draw_scores(sim: &Simulation, screen_w: f32, scale: f32) {
+    let scores: Vec<String> = sim
+        .scores
+        .iter()
+        .map(|(team, points)| {
+            locale::format("hud.team_score", &[("team", team), ("points", points)])
+        })
+        .collect();
+    let text = locale::format(
+        "hud.king_of_the_hill",
+        &[
+            ("target", &sim.config.koth_target_score),
+            ("scores", &scores.join("  ")),
+        ],
+    );
+    draw_text(
+        &text,
+        screen_w - 520.0 * scale,
+        24.0 * scale,
+        22.0 * scale,
+        GOLD,
+    );
+}
+
+/// Shade the arena's platforms, outlined where their edges are cliffs, and
+/// stripe the ramps up to them
+fn draw_elevation(elevation: &Elevation, view: &Viewport) {
+    for platform in &elevation.platforms {
+        let (sx, sy) = view.to_screen(platform.x, platform.y);
+        let (w, h) = (view.scale_x(platform.width), view.scale_y(platform.height));
+        draw_rectangle(sx, sy, w, h, PLATFORM_COLOR);
+        draw_rectangle_lines(sx, sy, w, h, 2.0, BEIGE);
+    }
+    for ramp in &elevation.ramps {
+        let (sx, sy) = view.to_screen(ramp.x, ramp.y);
+        let (w, h) = (view.scale_x(ramp.width), view.scale_y(ramp.height));
+        draw_rectangle(sx, sy, w, h, BLACK);
+        let mut stripe = 0.0;
+        while stripe < w {
+            draw_rectangle(sx + stripe, sy, 3.0_f32.min(w - stripe), h, RAMP_COLOR);
+            stripe += 6.0;
+        }
+    }
+}
+
+/// Draw an obstacle as a filled rectangle, outlined in white if `outline`; a
+/// destructible one is brown, with its hit points left written on it
+fn draw_obstacle(obstacle: &Obstacle, outline: bool, view: &Viewport) {
+    let (sx, sy) = view.to_screen(obstacle.x, obstacle.y);
+    let w = view.scale_x(obstacle.width);
+    let h = view.scale_y(obstacle.height);
+    if outline {
+        draw_rectangle_lines(sx - 1.0, sy - 1.0, w + 2.0, h + 2.0, 2.0, WHITE);
+    }
+    let Some(health) = obstacle.health else {
+        draw_rectangle(sx, sy, w, h, GRAY);
+        return;
+    };
+    draw_rectangle(sx, sy, w, h, BROWN);
+    let text = health.to_string();
+    let size = measure_text(&text, None, 16, 1.0);
+    draw_text(
+        &text,
+        sx + (w - size.width) / 2.0,
+        sy + (h + size.height) / 2.0,
+        16.0,
+        WHITE,
+    );
+}
+
+/// Draw a non-robot entity according to its sprite
+fn draw_entity(sprite: Sprite, transform: &Transform, view: &Viewport) {
+    let (sx, sy) = view.to_screen(transform.position.0, transform.position.1);
+    match sprite {
+        Sprite::Projectile => draw_circle(sx, sy, 3.0, YELLOW),
+        Sprite::Ricochet => {
+            draw_circle(sx, sy, 3.0, ORANGE);
+            draw_circle_lines(sx, sy, 5.0, 1.0, ORANGE);
+        }
+        Sprite::Emp => {
+            draw_circle(sx, sy, 2.0, SKYBLUE);
+            draw_circle_lines(sx, sy, 6.0, 1.5, SKYBLUE);
+        }
+        Sprite::Wreck | Sprite::SalvageWreck => {
+            let (w, h) = (
+                view.scale_x(view.config.robot_width),
+                view.scale_y(view.config.robot_height),
+            );
+            draw_rectangle_ex(
+                sx - w / 2.0,
+                sy - h / 2.0,
+                w,
+                h,
+                DrawRectangleParams {
+                    rotation: transform.heading.to_radians(),
+                    offset: Vec2::new(0.5, 0.5),
+                    color: DARKGRAY,
+                },
+            );
+            if sprite == Sprite::SalvageWreck {
+                draw_circle(sx, sy, 2.5, GOLD);
+            }
+        }
+        Sprite::Supply => {
+            draw_circle(sx, sy, 3.0, GOLD);
+            draw_circle_lines(sx, sy, 5.0, 1.0, GOLD);
+        }
+    }
+}
+
+/// Draw a laser (red) or repair (green) beam, fading out as `brightness` drops to 0
+fn draw_beam(transform: &Transform, beam: &Beam, brightness: f32, view: &Viewport) {
+    let (x, y) = transform.position;
+    let angle = transform.heading.to_radians();
+    let length = beam.length;
+    let (sx, sy) = view.to_screen(x, y);
+    let (ex, ey) = view.to_screen(x + length * angle.cos(), y + length * angle.sin());
+    let color = match beam.kind {
+        BeamKind::Laser => Color::new(1.0, 0.2, 0.2, brightness),
+        BeamKind::Repair => Color::new(0.2, 1.0, 0.4, brightness),
+        BeamKind::Intercept => Color::new(1.0, 0.85, 0.2, brightness),
+    };
+    draw_line(sx, sy, ex, ey, 1.0 + 2.0 * brightness, color);
+}
+
+/// Draw the heading and scanner lines, instruction pointer and registers next to a robot.
+fn draw_debug_overlay(robot: &Robot, obstacles: &[Obstacle], view: &Viewport) {
+    let (sx, sy) = view.to_screen(robot.position.0, robot.position.1);
+    let reach = view.scale_x(view.config.robot_width) * 2.0;
+    let heading = robot.heading.to_radians();
+    draw_line(
+        sx,
+        sy,
+        sx + reach * heading.cos(),
+        sy + reach * heading.sin(),
+        2.0,
+        YELLOW,
+    );
+
+    // Scanner ray, cut short where an obstacle blocks it
+    let direction = robot.scanner_direction().to_radians();
+    let range = robot.loadout.scan_range(view.config);
+    let scan_len = raycast::cast_ray(
+        robot.position,
+        direction,
+        range,
+        obstacles,
+        view.config.math,
+    )
+    .unwrap_or(range);
+    let (ex, ey) = view.to_screen(
+        robot.position.0 + scan_len * direction.cos(),
+        robot.position.1 + scan_len * direction.sin(),
+    );
+    draw_line(sx, sy, ex, ey, 1.0, SKYBLUE);
+
+    let mut lines = vec![format!("ip {}", robot.ip)];
+    if let Some(instr) = robot.current_instruction() {
+        lines.push(instr.to_string());
+    }
+    let mut registers: Vec<_> = robot
+        .registers
+        .iter()
+        .map(|(reg, val)| (reg.name(), val))
+        .collect();
+    registers.sort();
+    lines.extend(
+        registers
+            .iter()
+            .map(|(reg, val)| format!("{} = {}", reg, val)),
+    );
+
+    let mut text_y = sy + 20.0;
+    for line in &lines {
+        draw_text(line, sx + 12.0, text_y, 18.0, YELLOW);
+        text_y += 16.0;
+    }
+}
+
+/// The two robots the halves of a split screen follow: the human players
+/// first, then the rest in entry order. None without two robots to follow.
+fn split_followed(sim: &Simulation) -> Option<[usize; 2]> {
+    let humans = (0..sim.robots.len()).filter(|&i| sim.robots[i].controls.is_some());
+    let others = (0..sim.robots.len()).filter(|&i| sim.robots[i].controls.is_none());
+    let mut followed = humans.chain(others);
+    Some([followed.next()?, followed.next()?])
+}
+
+/// Draw the arena twice, side by side, each half following one of
+/// `followed` with only what it can see and its status panel on top.
+fn draw_split(sim: &Simulation, followed: [usize; 2], debug_overlay: bool, style: &Settings) {
+    let half_w = (screen_width() / 2.0).floor();
+    let screen_h = screen_height();
+    for (side, &i) in followed.iter().enumerate() {
+        let left = side as f32 * half_w;
+        // Each half draws at its own origin, clipped to its part of the window
+        set_camera(&Camera2D {
+            target: vec2(half_w / 2.0, screen_h / 2.0),
+            zoom: vec2(2.0 / half_w, 2.0 / screen_h),
+            viewport: Some((left as i32, 0, half_w as i32, screen_h as i32)),
+            ..Default::default()
+        });
+        let robot = &sim.robots[i];
+        let view = Viewport::following(&sim.config, robot.position, half_w, screen_h);
+        draw_arena(sim, debug_overlay, &view, Some(i), style);
+        let color = robot_color(style, i);
+        let panel_w = (half_w * 0.6).min(PANEL_MAX_WIDTH * style.hud_scale);
+        draw_status_panel(
+            robot,
+            color,
+            PANEL_MARGIN,
+            PANEL_MARGIN,
+            panel_w,
+            PANEL_MAX_HEIGHT * style.hud_scale,
+            style,
+        );
+    }
+    set_default_camera();
+    draw_line(half_w, 0.0, half_w, screen_h, 3.0, LIGHTGRAY);
+}
+
+/// Draw the arena, robots and HUD for one frame in `style`, split between
+/// two robots if the settings say so.
+fn draw_world(sim: &Simulation, settings: &ViewSettings, style: &Settings) {
     clear_background(BLACK);
 
     let screen_w = screen_width();
     let screen_h = screen_height();
+    match split_followed(sim).filter(|_| settings.split_screen) {
+        Some(followed) => draw_split(sim, followed, settings.debug_overlay, style),
+        None => {
+            let view = Viewport::whole(&sim.config, 0.0, 0.0, screen_w, screen_h);
+            draw_arena(sim, settings.debug_overlay, &view, None, style);
+            // Spectators only: it would show a player what its scanner cannot
+            if settings.observer_overlay && sim.robots.iter().all(|r| r.controls.is_none()) {
+                draw_observer_overlay(sim, &view);
+            }
+            draw_hud(&sim.robots, screen_w, screen_h, style);
+        }
+    }
+    draw_event_log(&sim.events, screen_w, screen_h, style);
+    let scale = style.hud_scale;
+    match sim.mode {
+        GameMode::KingOfTheHill => draw_scores(sim, screen_w, scale),
+        GameMode::WaveSurvival => {
+            let score = sim.scores.values().sum::<u32>();
+            let text = locale::format("hud.wave", &[("wave", &sim.wave), ("score", &score)]);
+            draw_text(
+                &text,
+                screen_w - 220.0 * scale,
+                24.0 * scale,
+                24.0 * scale,
+                GOLD,
+            );
+        }
+        GameMode::Practice => {
+            if let Some(drill) = &sim.drill {
+                let x = screen_w - 520.0 * scale;
+                draw_text(&drill.status(), x, 24.0 * scale, 22.0 * scale, GOLD);
+            }
+        }
+        GameMode::Scenario => {
+            if let Some(objective) = &sim.objective {
+                draw_objective(objective, sim.tick, screen_w, screen_h, style);
+            }
+        }
+        GameMode::Deathmatch => {}
+    }
+
+    if settings.debug_overlay {
+        let status = locale::format(
+            "hud.debug_status",
+            &[("tick", &sim.tick), ("entities", &sim.world.entity_count())],
+        );
+        draw_text(&status, screen_w - 220.0, screen_h - 12.0, 20.0, YELLOW);
+    }
+}
+
+/// Draw the performance HUD at the top of the window: frame and tick times,
+/// ticks per frame, what the match holds and how much each frame allocates.
+fn draw_performance(sim: &Simulation, stats: &FrameStats, style: &Settings) {
+    let alive = sim.robots.iter().filter(|r| r.health > 0).count();
+    let memory = alloc_stats().bytes_in_use / 1024;
+    let lines = [
+        locale::format(
+            "hud.perf_frame",
+            &[
+                ("ms", &format!("{:.1}", stats.ms_per_frame())),
+                ("worst", &format!("{:.1}", stats.worst_ms())),
+                ("ticks", &format!("{:.1}", stats.ticks_per_frame())),
+                ("tick_ms", &format!("{:.3}", stats.ms_per_tick())),
+            ],
+        ),
+        locale::format(
+            "hud.perf_entities",
+            &[
+                ("alive", &alive),
+                ("robots", &sim.robots.len()),
+                ("entities", &sim.world.entity_count()),
+                ("shots", &sim.world.damages.iter().count()),
+            ],
+        ),
+        locale::format(
+            "hud.perf_allocations",
+            &[
+                (
+                    "allocations",
+                    &format!("{:.0}", stats.allocations_per_frame()),
+                ),
+                ("memory", &memory),
+            ],
+        ),
+    ];
+    let scale = style.hud_scale;
+    let line_h = 18.0 * scale;
+    let w = 440.0 * scale;
+    let x = (screen_width() - w) / 2.0;
+    draw_rectangle(
+        x,
+        4.0,
+        w,
+        line_h * lines.len() as f32 + 8.0,
+        PANEL_BACKGROUND,
+    );
+    for (i, line) in lines.iter().enumerate() {
+        let y = 4.0 + line_h * (i as f32 + 1.0);
+        draw_text(line, x + 8.0, y, line_h, dim_text(style));
+    }
+}
+
+/// Draw the observer overlay: every scanner's cone, cut short by obstacles,
+/// where each robot's current instruction steers it, and the projected path
+/// of every shot in flight, ringing the robot it will hit.
+fn draw_observer_overlay(sim: &Simulation, view: &Viewport) {
+    let blockers = sim.blockers();
+    let math = sim.config.math;
+    for (i, robot) in sim.robots.iter().enumerate().filter(|(_, r)| r.health > 0) {
+        let (sx, sy) = view.to_screen(robot.position.0, robot.position.1);
+        let range = robot.loadout.scan_range(&sim.config);
+        let half = sim.config.scan_half_angle;
+        let edges: Vec<(f32, f32)> = (0..=OBSERVER_CONE_RAYS)
+            .map(|k| {
+                let bearing = robot.scanner_direction() - half
+                    + 2.0 * half * k as f32 / OBSERVER_CONE_RAYS as f32;
+                let angle = bearing.to_radians();
+                let reach = raycast::cast_ray(robot.position, angle, range, &blockers, math)
+                    .unwrap_or(range);
+                view.to_screen(
+                    robot.position.0 + reach * angle.cos(),
+                    robot.position.1 + reach * angle.sin(),
+                )
+            })
+            .collect();
+        let cone = Color::new(0.4, 0.8, 1.0, 0.5);
+        for pair in edges.windows(2) {
+            draw_line(pair[0].0, pair[0].1, pair[1].0, pair[1].1, 1.0, cone);
+        }
+        for &(ex, ey) in [edges.first(), edges.last()].into_iter().flatten() {
+            draw_line(sx, sy, ex, ey, 1.0, cone);
+        }
+
+        if let Some(heading) = observer::intended_heading(sim, i) {
+            let reach = view.scale_x(sim.config.robot_width) * 2.5;
+            let angle = heading.to_radians();
+            let tip = vec2(sx + reach * angle.cos(), sy + reach * angle.sin());
+            draw_line(sx, sy, tip.x, tip.y, 2.0, LIME);
+            let back = |turn: f32| {
+                let side = angle + std::f32::consts::PI + turn;
+                tip + vec2(side.cos(), side.sin()) * 8.0
+            };
+            draw_triangle(tip, back(0.5), back(-0.5), LIME);
+        }
+    }
+
+    for path in observer::trajectories(sim) {
+        let points: Vec<(f32, f32)> = path
+            .points
+            .iter()
+            .map(|&(x, y)| view.to_screen(x, y))
+            .collect();
+        for pair in points.windows(2) {
+            draw_line(pair[0].0, pair[0].1, pair[1].0, pair[1].1, 1.0, ORANGE);
+        }
+        for &(bx, by) in &points[1..points.len().saturating_sub(1)] {
+            draw_circle_lines(bx, by, 3.0, 1.0, ORANGE);
+        }
+        if let Some(target) = path.hits {
+            let position = sim.robots[target].position;
+            let (tx, ty) = view.to_screen(position.0, position.1);
+            let radius = view.scale_x(sim.config.robot_width);
+            draw_circle_lines(tx, ty, radius, 2.0, ORANGE);
+        }
+    }
+}
+
+/// Draw the arena with everything in it into `view`, in `style`. Seen
+/// through the eyes of robot `pov`, enemies and shots it cannot see are left
+/// out and fog covers the rest of what it cannot see.
+fn draw_arena(
+    sim: &Simulation,
+    debug_overlay: bool,
+    view: &Viewport,
+    pov: Option<usize>,
+    style: &Settings,
+) {
+    let seen = |point: (f32, f32)| pov.is_none_or(|i| sim.in_sight(i, point));
+    let shown = |j: usize| {
+        let robot = &sim.robots[j];
+        match pov {
+            None => true,
+            Some(i) if robot.team == sim.robots[i].team => true,
+            Some(_) => !robot.cloaked && seen(robot.position),
+        }
+    };
 
     // Draw arena border
-    draw_rectangle_lines(0.0, 0.0, screen_w, screen_h, 4.0, LIGHTGRAY);
+    // A torus has no walls, so its edges are drawn faintly
+    let border = match (sim.config.topology, style.high_contrast) {
+        (Topology::Bounded, true) => WHITE,
+        (Topology::Bounded, false) => LIGHTGRAY,
+        (Topology::Torus, true) => GRAY,
+        (Topology::Torus, false) => DARKGRAY,
+    };
+    let (left, top) = view.to_screen(0.0, 0.0);
+    draw_rectangle_lines(
+        left,
+        top,
+        view.scale_x(sim.config.arena_width),
+        view.scale_y(sim.config.arena_height),
+        4.0,
+        border,
+    );
 
-    // Draw all robots
-    for (i, robot) in robots.iter().enumerate() {
-        let color = ROBOT_COLORS.get(i).copied().unwrap_or(GREEN);
-        draw_robot(robot, color, screen_w, screen_h);
+    draw_elevation(&sim.elevation, view);
+    if let Some(zone) = sim.control_zone() {
+        draw_zone(&zone, view);
     }
 
-    // Draw HUD for each robot
-    let mut hud_y = 20.0;
-    for (i, robot) in robots.iter().enumerate() {
-        let color = ROBOT_COLORS.get(i).copied().unwrap_or(GREEN);
-        let hud_text = format!(
-            "Robot {:>2} | Pos: ({:>6.1}, {:>6.1}) | Heading: {:>7.2}",
-            robot.id, robot.position.0, robot.position.1, robot.heading
+    for obstacle in &sim.obstacles {
+        draw_obstacle(obstacle, style.high_contrast, view);
+    }
+    // Doors and barriers, in steel blue to tell them from what stands still
+    for piece in sim.world.moving_boxes() {
+        let (sx, sy) = view.to_screen(piece.x, piece.y);
+        let (w, h) = (view.scale_x(piece.width), view.scale_y(piece.height));
+        draw_rectangle(sx, sy, w, h, MOVER_COLOR);
+    }
+
+    // Hazard telegraphs go on the floor, under the robots that should move
+    for strike in &sim.strikes {
+        draw_strike(strike, sim.tick, &sim.config, view);
+    }
+
+    // Grappling hook cables, under the robots at their ends
+    for (i, robot) in sim.robots.iter().enumerate().filter(|(i, _)| shown(*i)) {
+        if let Some(end) = sim.hook_anchor(i) {
+            let (dx, dy) = sim.config.displacement(robot.position, end);
+            let (sx, sy) = view.to_screen(robot.position.0, robot.position.1);
+            let (ex, ey) = view.to_screen(robot.position.0 + dx, robot.position.1 + dy);
+            draw_line(sx, sy, ex, ey, 1.5, GRAY);
+        }
+    }
+
+    // Draw all robots; destroyed ones are shown by their wrecks
+    let alive = |(i, robot): &(usize, &Robot)| robot.health > 0 && shown(*i);
+    for (i, robot) in sim.robots.iter().enumerate().filter(alive) {
+        let color = robot_color(style, i);
+        draw_robot(robot, robot.position, color, style.high_contrast, view);
+        for ghost in edge_ghosts(robot.position, &sim.config) {
+            draw_robot(robot, ghost, color, style.high_contrast, view);
+        }
+        if let Some(fuse) = robot.fuse {
+            draw_fuse(robot, fuse, view);
+        }
+        if let Some(speech) = &robot.speech
+            && speech.opacity(sim.tick) > 0.0
+        {
+            draw_speech(robot, &speech.text, speech.opacity(sim.tick), view);
+        }
+        if debug_overlay {
+            draw_debug_overlay(robot, &sim.obstacles, view);
+        }
+    }
+
+    for (sprite, transform) in sim.world.renderables() {
+        if seen(transform.position) {
+            draw_entity(sprite, transform, view);
+        }
+    }
+    for (transform, beam, brightness) in sim.world.visible_beams() {
+        draw_beam(transform, beam, brightness, view);
+    }
+    // Smoke goes on top, half hiding whatever is inside it
+    for (center, radius) in sim.world.smoke_clouds() {
+        let (sx, sy) = view.to_screen(center.0, center.1);
+        draw_circle(
+            sx,
+            sy,
+            view.scale_x(radius),
+            Color::new(0.7, 0.7, 0.7, 0.45),
         );
-        draw_text(&hud_text, 20.0, hud_y, 28.0, color);
-        hud_y += 32.0;
+    }
+    if let Some(i) = pov {
+        draw_fog(sim, i, view);
+    }
+}
+
+/// Darken the squares of the shown part of the arena robot `i` cannot see.
+fn draw_fog(sim: &Simulation, i: usize, view: &Viewport) {
+    if sim.robots[i].health <= 0 {
+        return;
+    }
+    let (w, h) = (view.scale_x(FOG_CELL), view.scale_y(FOG_CELL));
+    let first = |origin: f32| (origin.max(0.0) / FOG_CELL).floor() * FOG_CELL;
+    let mut y = first(view.origin.1);
+    while y < (view.origin.1 + view.span.1).min(sim.config.arena_height) {
+        let mut x = first(view.origin.0);
+        while x < (view.origin.0 + view.span.0).min(sim.config.arena_width) {
+            let center = (x + FOG_CELL / 2.0, y + FOG_CELL / 2.0);
+            if !sim.in_sight(i, center) {
+                let (sx, sy) = view.to_screen(x, y);
+                draw_rectangle(sx, sy, w, h, FOG_COLOR);
+            }
+            x += FOG_CELL;
+        }
+        y += FOG_CELL;
+    }
+}
+
+/// Reloads robot scripts from disk for the pause menu.
+pub type ScriptLoader = Box<dyn Fn() -> Result<Vec<Robot>, Vec<SimError>>>;
+
+/// Called with the simulation when the match is over, once per run from
+/// the start.
+pub type MatchOver = Box<dyn FnMut(&Simulation)>;
+
+/// Run the simulation in a macroquad window.
+/// Esc opens the pause menu; `reload` is used by its "Reload scripts" entry.
+/// While paused, Left and Right step the match back and forward one tick.
+/// ~ opens the script console, which injects commands into a robot.
+/// Human robots are driven, in entry order, with the keys and gamepads in
+/// `players`.
+/// `split_screen` starts the window split between two robots, and `prefs`
+/// sets its colors, HUD size, contrast and starting view; the menu's settings
+/// screen changes them and the first player's keys, and saves them.
+/// `on_over` is told when the match ends, as the window keeps running.
+pub async fn run(
+    mut initial: Simulation,
+    reload: ScriptLoader,
+    mut recorder: Option<Recorder>,
+    mut players: Vec<Bindings>,
+    split_screen: bool,
+    mut prefs: Preferences,
+    mut on_over: Option<MatchOver>,
+) {
+    let mut sim = initial.clone();
+    let mut over_reported = false;
+    let mut settings = ViewSettings::starting(&prefs.settings, split_screen);
+    let mut menu = PauseMenu::default();
+    let mut settings_screen = SettingsScreen::default();
+    let mut console = Console::default();
+    let mut snapshots = SnapshotRing::default();
+    snapshots.record(&sim);
+    let mut perf = FrameStats::default();
+    // Only matches with human robots look for gamepads
+    let mut gamepads = (!players.is_empty()).then(Gamepads::new);
+    count_allocations();
+    let mut allocations = alloc_stats().allocations;
+
+    loop {
+        let mut ticks = 0;
+        let mut stepping = 0.0;
+        if let Some(gamepads) = &mut gamepads {
+            gamepads.update();
+        }
+        // Keys that closed the menu this frame are not also the player's input
+        let was_open = menu.open || console.open;
+        if console.open {
+            console.handle_input(&mut sim, &prefs.keys);
+        } else if settings_screen.open {
+            match settings_screen.handle_input(&mut prefs) {
+                Some(SettingsEvent::Changed(entry)) => {
+                    apply_setting(entry, &prefs, &mut settings, &mut players)
+                }
+                Some(SettingsEvent::Closed(Err(e))) => menu.status = vec![e],
+                Some(SettingsEvent::Closed(Ok(()))) | None => {}
+            }
+        } else if !menu.open && pressed(&prefs.keys.console) {
+            // Drop what was typed before, such as the player's driving
+            while get_char_pressed().is_some() {}
+            console.toggle();
+        } else if pressed(&prefs.keys.menu) {
+            if menu.open {
+                menu.close();
+            } else {
+                menu.open();
+            }
+        } else if menu.open {
+            if pressed(&prefs.keys.step_back) {
+                match sim.tick.checked_sub(1).and_then(|t| snapshots.restore(t)) {
+                    Some(earlier) => sim = earlier,
+                    None => {
+                        menu.status = vec![locale::text("menu.no_earlier_snapshot").to_string()]
+                    }
+                }
+            } else if pressed(&prefs.keys.step_forward) {
+                sim.step();
+                ticks += 1;
+                snapshots.record(&sim);
+            }
+            match menu.handle_input(&prefs.keys) {
+                Some(MenuAction::Resume) => menu.close(),
+                Some(MenuAction::Restart) => {
+                    sim = initial.clone();
+                    over_reported = false;
+                    snapshots.clear();
+                    snapshots.record(&sim);
+                    menu.close();
+                }
+                Some(MenuAction::ReloadScripts) => match reload() {
+                    Ok(mut robots) => {
+                        if let Some(max) = initial.mode.max_entrants() {
+                            robots.truncate(max);
+                        }
+                        let objective = initial.objective.take();
+                        initial = Simulation::new(
+                            initial.config.clone(),
+                            robots,
+                            initial.obstacles.clone(),
+                        )
+                        .with_mode(initial.mode)
+                        .with_seed(initial.seed)
+                        .with_threads(initial.threads);
+                        initial.objective = objective;
+                        sim = initial.clone();
+                        over_reported = false;
+                        snapshots.clear();
+                        snapshots.record(&sim);
+                        menu.close();
+                    }
+                    Err(errors) => menu.status = errors.iter().map(ToString::to_string).collect(),
+                },
+                Some(MenuAction::CycleSpeed) => settings.cycle_speed(),
+                Some(MenuAction::ToggleDebug) => settings.debug_overlay = !settings.debug_overlay,
+                Some(MenuAction::ToggleSplit) => settings.split_screen = !settings.split_screen,
+                Some(MenuAction::ToggleObserver) => {
+                    settings.observer_overlay = !settings.observer_overlay
+                }
+                Some(MenuAction::TogglePerformance) => {
+                    settings.performance_overlay = !settings.performance_overlay
+                }
+                Some(MenuAction::OpenSettings) => settings_screen.open(),
+                Some(MenuAction::Quit) => break,
+                None => {}
+            }
+        }
+
+        if !menu.open {
+            if !was_open && !console.open {
+                let humans = sim.robots.iter_mut().filter(|r| r.controls.is_some());
+                for (robot, bindings) in humans.zip(&players) {
+                    let keys = bindings.controls(is_key_down, is_key_pressed);
+                    let pad = gamepads.as_ref().and_then(|pads| pads.controls(bindings));
+                    robot.controls = Some(pad.map_or(keys, |pad| combine(keys, pad)));
+                }
+            }
+            for _ in 0..settings.ticks_per_frame() {
+                let started = Instant::now();
+                sim.step();
+                stepping += started.elapsed().as_secs_f32();
+                ticks += 1;
+                snapshots.record(&sim);
+                if let Some(rec) = recorder.as_mut() {
+                    match rec.capture(&sim) {
+                        Ok(()) if rec.is_finished() => recorder = None,
+                        Ok(()) => {}
+                        Err(e) => {
+                            eprintln!("Recording stopped: {}", e);
+                            recorder = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        if !over_reported
+            && sim.is_over()
+            && let Some(on_over) = on_over.as_mut()
+        {
+            on_over(&sim);
+            over_reported = true;
+        }
+
+        let now = alloc_stats().allocations;
+        perf.record(get_frame_time(), stepping, ticks, now - allocations);
+        allocations = now;
+
+        draw_world(&sim, &settings, &prefs.settings);
+        if settings.performance_overlay {
+            draw_performance(&sim, &perf, &prefs.settings);
+        }
+        if settings_screen.open {
+            settings_screen.draw(&prefs);
+        } else if menu.open {
+            menu.draw(&settings, sim.tick, &prefs.keys);
+        }
+        if console.open {
+            console.draw(&sim, &prefs.keys);
+        }
+        next_frame().await;
     }
 
-    next_frame().await;
+    if let Some(rec) = recorder.as_mut()
+        && let Err(e) = rec.finish()
+    {
+        eprintln!("Failed to finish recording: {}", e);
+    }
+}
+
+/// Bring a change made on the settings screen into the running window.
+fn apply_setting(
+    entry: Entry,
+    prefs: &Preferences,
+    view: &mut ViewSettings,
+    players: &mut [Bindings],
+) {
+    match entry {
+        Entry::Speed => view.speed = speed_index(prefs.settings.speed),
+        Entry::DebugOverlay => view.debug_overlay = prefs.settings.debug_overlay,
+        Entry::ObserverOverlay => view.observer_overlay = prefs.settings.observer_overlay,
+        Entry::PerformanceOverlay => view.performance_overlay = prefs.settings.performance_overlay,
+        Entry::Language => locale::select(prefs.settings.locale.as_deref()),
+        // The first player keeps giving up the second player's keys
+        Entry::Key(_) => match players {
+            [first, second] => *first = prefs.controls.without(second),
+            [first] => *first = prefs.controls.clone(),
+            _ => {}
+        },
+        Entry::Palette | Entry::HudScale | Entry::HighContrast => {}
+    }
+}
+
+/// Watch batches of matches, such as the rounds of a tournament, each batch
+/// in a grid of arenas with a mini HUD above each. Every match runs until it
+/// is over or reaches `max_ticks`; a little after the last one ends, the next
+/// batch starts. The `pause`, `speed` and `next` keys of `keys` (Space, S and
+/// N) pause, cycle the speed and skip to the next batch.
+pub async fn run_grid(
+    batches: Vec<(String, Vec<Simulation>)>,
+    max_ticks: u64,
+    style: Settings,
+    keys: ViewerKeys,
+) {
+    let mut settings = ViewSettings::starting(&style, false);
+    let mut paused = false;
+    for (title, mut sims) in batches {
+        let mut hold = 0;
+        while hold < GRID_HOLD_FRAMES {
+            if pressed(&keys.pause) {
+                paused = !paused;
+            }
+            if pressed(&keys.speed) {
+                settings.cycle_speed();
+            }
+            if pressed(&keys.next) {
+                break;
+            }
+            let running = |sim: &Simulation| !sim.is_over() && sim.tick < max_ticks;
+            if !paused {
+                for sim in sims.iter_mut() {
+                    for _ in 0..settings.ticks_per_frame() {
+                        if running(sim) {
+                            sim.step();
+                        }
+                    }
+                }
+                if !sims.iter().any(running) {
+                    hold += 1;
+                }
+            }
+
+            clear_background(BLACK);
+            draw_grid(&sims, max_ticks, &style);
+            let status = locale::format(
+                "grid.status",
+                &[
+                    ("title", &title),
+                    ("speed", &settings.ticks_per_frame()),
+                    ("pause", &key_label(&keys.pause)),
+                    ("speed_key", &key_label(&keys.speed)),
+                    ("next", &key_label(&keys.next)),
+                ],
+            );
+            draw_text(&status, GRID_GAP, screen_height() - 8.0, 20.0, LIGHTGRAY);
+            if paused {
+                draw_text(
+                    locale::text("grid.paused"),
+                    screen_width() - 100.0,
+                    screen_height() - 8.0,
+                    20.0,
+                    YELLOW,
+                );
+            }
+            next_frame().await;
+        }
+    }
+
+    loop {
+        clear_background(BLACK);
+        let over = locale::text("grid.tournament_over");
+        draw_text(over, 20.0, 40.0, 36.0, GOLD);
+        let hint = locale::text("grid.close_to_exit");
+        draw_text(hint, 20.0, 76.0, 24.0, LIGHTGRAY);
+        next_frame().await;
+    }
+}
+
+/// Draw `sims` side by side in as square a grid as fits them, leaving a
+/// line at the bottom of the screen for the status.
+fn draw_grid(sims: &[Simulation], max_ticks: u64, style: &Settings) {
+    if sims.is_empty() {
+        return;
+    }
+    let cols = (sims.len() as f32).sqrt().ceil() as usize;
+    let rows = sims.len().div_ceil(cols);
+    let cell_w = (screen_width() - GRID_GAP) / cols as f32 - GRID_GAP;
+    let cell_h = (screen_height() - 24.0 - GRID_GAP) / rows as f32 - GRID_GAP;
+
+    for (i, sim) in sims.iter().enumerate() {
+        let left = GRID_GAP + (i % cols) as f32 * (cell_w + GRID_GAP);
+        let top = GRID_GAP + (i / cols) as f32 * (cell_h + GRID_GAP);
+        draw_mini_hud(sim, max_ticks, left, top, cell_w, style);
+        let view = Viewport::whole(
+            &sim.config,
+            left,
+            top + GRID_HUD_HEIGHT,
+            cell_w,
+            cell_h - GRID_HUD_HEIGHT,
+        );
+        draw_arena(sim, false, &view, None, style);
+    }
+}
+
+/// One line per robot with its name and health, and the state of the match
+/// on the right.
+fn draw_mini_hud(sim: &Simulation, max_ticks: u64, x: f32, y: f32, w: f32, style: &Settings) {
+    draw_rectangle(x, y, w, GRID_HUD_HEIGHT - 2.0, PANEL_BACKGROUND);
+    let line_h = (GRID_HUD_HEIGHT - 2.0) / sim.robots.len().max(2) as f32;
+    let name_w = (w * 0.35).min(140.0);
+    let bar_w = (w * 0.3).min(120.0);
+    for (i, robot) in sim.robots.iter().enumerate() {
+        let color = robot_color(style, i);
+        let line_y = y + i as f32 * line_h;
+        draw_text(&robot.name, x + 4.0, line_y + line_h * 0.8, line_h, color);
+        let health = robot.health as f32 / robot.max_health.max(1) as f32;
+        let bar_color = if health > 0.3 { GREEN } else { RED };
+        draw_bar(
+            x + 8.0 + name_w,
+            line_y + line_h * 0.25,
+            bar_w,
+            line_h * 0.5,
+            health,
+            bar_color,
+        );
+    }
+
+    let state = if sim.is_over() {
+        match sim.winning_team() {
+            Some(team) => match sim.robots.iter().find(|r| r.team == team) {
+                Some(winner) => locale::format("grid.wins", &[("name", &winner.name)]),
+                None => locale::text("grid.over").to_string(),
+            },
+            None => locale::text("grid.draw").to_string(),
+        }
+    } else if sim.tick >= max_ticks {
+        locale::text("grid.time").to_string()
+    } else {
+        locale::format("grid.tick", &[("tick", &sim.tick)])
+    };
+    let state_w = measure_text(&state, None, 18, 1.0).width;
+    draw_text(&state, x + w - state_w - 4.0, y + 20.0, 18.0, GOLD);
+}
+
+/// Show script errors instead of starting the match.
+/// Stays on screen until the window is closed.
+pub async fn show_errors(errors: Vec<SimError>) {
+    loop {
+        clear_background(BLACK);
+        draw_rectangle_lines(0.0, 0.0, screen_width(), screen_height(), 4.0, RED);
+
+        draw_text(locale::text("errors.title"), 20.0, 40.0, 36.0, RED);
+        let mut y = 80.0;
+        for error in &errors {
+            draw_text(&error.to_string(), 20.0, y, 24.0, WHITE);
+            y += 28.0;
+        }
+        draw_text(locale::text("errors.hint"), 20.0, y + 16.0, 24.0, LIGHTGRAY);
+
+        next_frame().await;
+    }
 }
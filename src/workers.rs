@@ -0,0 +1,206 @@
+// Threads that stay up for the whole match.
+// The robot system spreads planning over threads every tick; starting threads
+// each tick would cost more than the planning on all but the largest matches,
+// and allocate. `Workers` starts its threads once and hands each tick's job to
+// them. A job may borrow from the caller's stack because `run` does not return
+// until every worker is done with it.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+/// A job, with its lifetime erased; only dereferenced while `run` waits.
+#[derive(Clone, Copy)]
+struct Job(*const (dyn Fn() + Sync));
+
+// SAFETY: the job is `Sync`, and `run` keeps it alive while workers call it
+unsafe impl Send for Job {}
+
+struct State {
+    job: Option<Job>,
+    /// Counts the jobs handed out, so a worker takes each job once.
+    generation: u64,
+    /// Workers still running the current job.
+    running: usize,
+    panicked: bool,
+    stop: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signals a new job, or that the workers should stop.
+    work: Condvar,
+    /// Signals that the last worker finished the current job.
+    done: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A pool of threads that run one job at a time, all of them together with
+/// the caller.
+pub(crate) struct Workers {
+    shared: Arc<Shared>,
+    threads: Vec<JoinHandle<()>>,
+    /// Held while a job runs, so clones of a match sharing the pool take
+    /// turns.
+    running: Mutex<()>,
+}
+
+impl Workers {
+    /// A pool of `threads` threads besides the caller's.
+    pub fn new(threads: usize) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                job: None,
+                generation: 0,
+                running: 0,
+                panicked: false,
+                stop: false,
+            }),
+            work: Condvar::new(),
+            done: Condvar::new(),
+        });
+        let threads = (0..threads)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || work(&shared))
+            })
+            .collect();
+        Workers {
+            shared,
+            threads,
+            running: Mutex::new(()),
+        }
+    }
+
+    /// Threads the pool runs a job on, the caller's included.
+    pub fn len(&self) -> usize {
+        self.threads.len() + 1
+    }
+
+    /// Run `job` on every worker and on this thread at once, returning when
+    /// all are done. A panic in any of them is raised here afterwards.
+    pub fn run(&self, job: &(dyn Fn() + Sync)) {
+        let _turn = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        // SAFETY: only the lifetime is erased. The workers call the job before
+        // `running` drops to 0 and forget it then, and this function does not
+        // return, nor unwind, before that.
+        let erased: *const (dyn Fn() + Sync + 'static) = unsafe { std::mem::transmute(job) };
+        {
+            let mut state = self.shared.lock();
+            state.job = Some(Job(erased));
+            state.generation += 1;
+            state.running = self.threads.len();
+            state.panicked = false;
+        }
+        self.shared.work.notify_all();
+
+        let here = panic::catch_unwind(AssertUnwindSafe(job));
+        let mut state = self.shared.lock();
+        while state.running > 0 {
+            state = self
+                .shared
+                .done
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        state.job = None;
+        let panicked = state.panicked;
+        drop(state);
+        if let Err(payload) = here {
+            panic::resume_unwind(payload);
+        }
+        assert!(!panicked, "a worker thread panicked");
+    }
+}
+
+/// A worker's loop: wait for each new job, run it and report back.
+fn work(shared: &Shared) {
+    let mut generation = 0;
+    loop {
+        let job = {
+            let mut state = shared.lock();
+            while !state.stop && state.generation == generation {
+                state = shared
+                    .work
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            if state.stop {
+                return;
+            }
+            generation = state.generation;
+            state.job.expect("a new job is set")
+        };
+        // SAFETY: `run` keeps the job alive until this worker reports back
+        let ran = panic::catch_unwind(AssertUnwindSafe(|| unsafe { (*job.0)() }));
+        let mut state = shared.lock();
+        state.panicked |= ran.is_err();
+        state.running -= 1;
+        if state.running == 0 {
+            shared.done.notify_one();
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        self.shared.lock().stop = true;
+        self.shared.work.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl fmt::Debug for Workers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Workers")
+            .field("threads", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_jobs_run_on_every_thread() {
+        let workers = Workers::new(3);
+        assert_eq!(workers.len(), 4);
+        for round in 1..=5 {
+            let ran = AtomicUsize::new(0);
+            workers.run(&|| {
+                ran.fetch_add(1, Ordering::Relaxed);
+            });
+            assert_eq!(ran.load(Ordering::Relaxed), 4, "round {}", round);
+        }
+    }
+
+    #[test]
+    fn test_worker_panics_reach_the_caller() {
+        let workers = Workers::new(2);
+        let caller = thread::current().id();
+        let failed = panic::catch_unwind(AssertUnwindSafe(|| {
+            workers.run(&|| {
+                if thread::current().id() != caller {
+                    panic!("worker failed");
+                }
+            })
+        }));
+        assert!(failed.is_err());
+        // The pool is still usable afterwards
+        let ran = AtomicUsize::new(0);
+        workers.run(&|| {
+            ran.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(ran.load(Ordering::Relaxed), 3);
+    }
+}
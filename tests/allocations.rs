@@ -0,0 +1,106 @@
+// Allocation budget for the simulation's hot loop.
+// A counting global allocator measures how often a match allocates once it has
+// warmed up: projectiles, scan results, blockers and events are meant to reuse
+// their buffers, and planning threads stay up, so a tick should almost never
+// allocate. Allocations are counted on every thread, so the tests take turns.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use robot_battle::ast::{Robot, translate_commands_to_instructions};
+use robot_battle::builtins;
+use robot_battle::config::GameConfig;
+use robot_battle::parser::parse_script;
+use robot_battle::setup::default_obstacles;
+use robot_battle::simulation::Simulation;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Held by the test measuring, so the other's allocations do not count.
+static MEASURING: Mutex<()> = Mutex::new(());
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations made on any thread so far.
+fn allocations() -> u64 {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+#[test]
+fn sixteen_robot_match_barely_allocates_per_tick() {
+    assert_match_barely_allocates(4, 1);
+}
+
+#[test]
+fn threaded_match_barely_allocates_per_tick() {
+    // Enough robots for the planning to be spread over the threads
+    assert_match_barely_allocates(6, 4);
+}
+
+/// Run a match of `side` by `side` robots on `threads` threads and check that
+/// its ticks barely allocate once it has warmed up.
+fn assert_match_barely_allocates(side: usize, threads: usize) {
+    const WARM_UP: u64 = 500;
+    const MEASURED: u64 = 2000;
+    let _turn = MEASURING.lock().unwrap_or_else(|e| e.into_inner());
+
+    // More robots than there are spawn points, so they are placed on a grid
+    let names = ["tracker", "spinner", "circler", "wall-follower"];
+    let (margin, spacing) = (240.0 / side as f32, 360.0 / side as f32);
+    let robots: Vec<Robot> = (0..side * side)
+        .map(|i| {
+            let name = names[i % names.len()];
+            let script = parse_script(builtins::script(name).unwrap()).unwrap();
+            let position = (
+                margin + spacing * (i % side) as f32,
+                margin + spacing * (i / side) as f32,
+            );
+            Robot::new(
+                i + 1,
+                name,
+                position,
+                translate_commands_to_instructions(&script),
+            )
+        })
+        .collect();
+    let config = GameConfig::default();
+    let mut sim = Simulation::new(config, robots, default_obstacles()).with_threads(threads);
+
+    // The first ticks fill registers, buffers and the entity pool
+    for _ in 0..WARM_UP {
+        sim.step();
+    }
+    let before = allocations();
+    for _ in 0..MEASURED {
+        sim.step();
+    }
+    let per_tick = (allocations() - before) as f64 / MEASURED as f64;
+    assert!(
+        per_tick < 0.05,
+        "{:.3} allocations per tick over {} ticks on {} threads",
+        per_tick,
+        MEASURED,
+        threads
+    );
+    assert!(!sim.is_over(), "the match should still be running");
+}
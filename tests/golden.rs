@@ -0,0 +1,170 @@
+// Golden-file tests for full battles.
+// Each scenario runs a match headlessly and compares its result, event log and
+// final robot state against `tests/golden/<scenario>.json`. Matches are
+// deterministic, so any difference means a change to battle outcomes.
+//
+// After an intended change, regenerate the files with
+// `UPDATE_GOLDEN=1 cargo test --test golden` and review the diff.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use robot_battle::config::GameConfig;
+use robot_battle::math::MathMode;
+use robot_battle::modes::GameMode;
+use robot_battle::results::{MatchResult, RunLimits};
+use robot_battle::setup::{Entrant, default_obstacles, load_robots};
+use robot_battle::simulation::Simulation;
+use serde_json::{Value, json};
+
+/// Run a match to completion (or `max_ticks`) and describe its outcome.
+fn run_match(entrants: &[&str], mode: GameMode, max_ticks: Option<u64>) -> Value {
+    run_match_with(GameConfig::default(), entrants, mode, max_ticks)
+}
+
+/// Like `run_match`, under the given config.
+fn run_match_with(
+    config: GameConfig,
+    entrants: &[&str],
+    mode: GameMode,
+    max_ticks: Option<u64>,
+) -> Value {
+    let entrants: Vec<Entrant> = entrants
+        .iter()
+        .map(|arg| match arg.strip_prefix("builtin:") {
+            Some(_) => Entrant::parse(arg).unwrap(),
+            None => Entrant::Script(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(arg)),
+        })
+        .collect();
+    let mut robots = load_robots(&entrants, &config).unwrap();
+    if let Some(max) = mode.max_entrants() {
+        robots.truncate(max);
+    }
+    let mut sim = Simulation::new(config, robots, default_obstacles()).with_mode(mode);
+
+    let limits = RunLimits {
+        max_ticks,
+        timeout: None,
+    };
+    let started = Instant::now();
+    let mut stopped = None;
+    while !sim.is_over() {
+        stopped = limits.reached(&sim, started);
+        if stopped.is_some() {
+            break;
+        }
+        sim.step();
+    }
+
+    let mut result =
+        serde_json::to_value(MatchResult::from_sim(&sim, stopped, Duration::ZERO)).unwrap();
+    // Wall-clock time differs between runs
+    result.as_object_mut().unwrap().remove("duration_secs");
+    // Round floats so the files stay readable
+    let round = |v: f32| (v as f64 * 100.0).round() / 100.0;
+    let robots: Vec<Value> = sim
+        .robots
+        .iter()
+        .map(|r| {
+            json!({
+                "id": r.id,
+                "position": [round(r.position.0), round(r.position.1)],
+                "heading": round(r.heading),
+                "turret_heading": round(r.turret_heading),
+                "health": r.health,
+                "ammo": r.ammo,
+                "ip": r.ip,
+            })
+        })
+        .collect();
+    json!({
+        "result": result,
+        "events": sim.events,
+        "robots": robots,
+    })
+}
+
+/// Compare `actual` with the golden file for `scenario`, or rewrite the file
+/// when `UPDATE_GOLDEN` is set.
+fn check_golden(scenario: &str, actual: Value) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.json", scenario));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let text = serde_json::to_string_pretty(&actual).unwrap();
+        fs::write(&path, text + "\n").unwrap();
+        return;
+    }
+    let text = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {} (run with UPDATE_GOLDEN=1 to create it)",
+            path.display(),
+            e
+        )
+    });
+    let expected: Value = serde_json::from_str(&text).unwrap();
+    assert!(
+        actual == expected,
+        "{} changed (run with UPDATE_GOLDEN=1 to accept):\n{}",
+        scenario,
+        serde_json::to_string_pretty(&actual).unwrap()
+    );
+}
+
+#[test]
+fn golden_deathmatch_wall_follower_vs_circler() {
+    let outcome = run_match(
+        &["builtin:wall-follower", "builtin:circler"],
+        GameMode::Deathmatch,
+        None,
+    );
+    check_golden("deathmatch_wall_follower_vs_circler", outcome);
+}
+
+#[test]
+fn golden_self_destruct() {
+    let outcome = run_match(
+        &["tests/scripts/kamikaze.robo", "builtin:sitting-duck"],
+        GameMode::Deathmatch,
+        Some(5_000),
+    );
+    check_golden("self_destruct", outcome);
+}
+
+#[test]
+fn golden_king_of_the_hill() {
+    let outcome = run_match(
+        &[
+            "builtin:tracker",
+            "builtin:spinner",
+            "builtin:wall-follower",
+        ],
+        GameMode::KingOfTheHill,
+        Some(20_000),
+    );
+    check_golden("king_of_the_hill", outcome);
+}
+
+#[test]
+fn golden_wave_survival() {
+    let outcome = run_match(&["builtin:spinner"], GameMode::WaveSurvival, Some(20_000));
+    check_golden("wave_survival", outcome);
+}
+
+#[test]
+fn golden_portable_math() {
+    // Portable trigonometry gives the same bits on every platform, so this file
+    // must match on Linux, Windows and WASM alike
+    let config = GameConfig {
+        math: MathMode::Portable,
+        ..GameConfig::default()
+    };
+    let outcome = run_match_with(
+        config,
+        &["builtin:wall-follower", "builtin:circler"],
+        GameMode::Deathmatch,
+        None,
+    );
+    check_golden("portable_math", outcome);
+}
@@ -0,0 +1,675 @@
+// Conformance tests for the robot VM: the spec for what each `Instruction` does.
+// Every case runs one instruction against a fresh robot with the given registers
+// and checks the instruction pointer, registers, world action and robot state
+// afterwards. Programs compiled from scripts are checked at the end.
+
+use robot_battle::ast::{
+    Command, HistoryField, Hook, HookAnchor, Instruction, MathOp, Operand, Robot, ScanRecord,
+    Section, translate_commands_to_instructions,
+};
+use robot_battle::config::GameConfig;
+use robot_battle::parser::parse_script;
+use robot_battle::raycast::Obstacle;
+use robot_battle::registers::Reg;
+use robot_battle::simulation::{WorldAction, execute_robot_instruction};
+
+/// One instruction executed once in a controlled setup.
+struct Case {
+    name: &'static str,
+    /// The instruction under test is at `ip`; the rest of the program surrounds it.
+    program: Vec<Instruction>,
+    ip: usize,
+    registers: &'static [(&'static str, i32)],
+    expect_ip: usize,
+    /// Registers after the step; `None` means the register must not exist.
+    expect_registers: &'static [(&'static str, Option<i32>)],
+    expect_action: Option<WorldAction>,
+    /// Extra setup and checks for cases that involve more than registers.
+    setup: fn(&mut Robot),
+    check: fn(&Robot),
+}
+
+impl Default for Case {
+    fn default() -> Self {
+        Case {
+            name: "",
+            program: Vec::new(),
+            ip: 0,
+            registers: &[],
+            expect_ip: 1,
+            expect_registers: &[],
+            expect_action: None,
+            setup: |_| {},
+            check: |_| {},
+        }
+    }
+}
+
+const START: (f32, f32) = (100.0, 100.0);
+
+fn reg(name: &str) -> Operand {
+    Operand::Reg(Reg::named(name))
+}
+
+fn label(name: &str) -> Instruction {
+    Instruction::Label(name.to_string())
+}
+
+fn jnz(reg: &str, label: &str) -> Instruction {
+    Instruction::Jnz {
+        reg: Reg::named(reg),
+        label: label.to_string(),
+    }
+}
+
+fn run_case(case: Case) {
+    let config = GameConfig::default();
+    let obstacles = [Obstacle::new(0.0, 0.0, 40.0, 40.0)];
+    let mut robot = Robot::new(1, "vm", START, case.program);
+    robot.ip = case.ip;
+    for (name, value) in case.registers {
+        robot.registers.set(*name, *value);
+    }
+    (case.setup)(&mut robot);
+
+    let action = execute_robot_instruction(&mut robot, &obstacles, &config);
+
+    assert_eq!(action, case.expect_action, "{}: action", case.name);
+    assert_eq!(robot.ip, case.expect_ip, "{}: ip", case.name);
+    for (name, expected) in case.expect_registers {
+        assert_eq!(
+            robot.registers.get(*name),
+            *expected,
+            "{}: register {}",
+            case.name,
+            name
+        );
+    }
+    (case.check)(&robot);
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        // Turning: one `turn_rate` step, positive is clockwise
+        Case {
+            name: "turn_left",
+            program: vec![Instruction::TurnLeft],
+            expect_registers: &[("heading", Some(359))],
+            check: |r| assert_eq!(r.heading, 359.0),
+            ..Case::default()
+        },
+        Case {
+            name: "turn_right",
+            program: vec![Instruction::TurnRight],
+            expect_registers: &[("heading", Some(1))],
+            check: |r| assert_eq!(r.heading, 1.0),
+            ..Case::default()
+        },
+        Case {
+            name: "turret_turns_independently",
+            program: vec![Instruction::TurnTurretRight],
+            check: |r| {
+                assert_eq!(r.turret_heading, 1.0);
+                assert_eq!(r.heading, 0.0);
+            },
+            ..Case::default()
+        },
+        Case {
+            name: "turret_left",
+            program: vec![Instruction::TurnTurretLeft],
+            check: |r| assert_eq!(r.turret_heading, 359.0),
+            ..Case::default()
+        },
+        Case {
+            name: "scanner_right",
+            program: vec![Instruction::TurnScannerRight],
+            check: |r| assert_eq!(r.scanner_heading, 1.0),
+            ..Case::default()
+        },
+        Case {
+            name: "scanner_left",
+            program: vec![Instruction::TurnScannerLeft],
+            check: |r| assert_eq!(r.scanner_heading, 359.0),
+            ..Case::default()
+        },
+        // Movement
+        Case {
+            name: "move_forward",
+            program: vec![Instruction::MoveForward],
+            check: |r| assert_eq!(r.position, (100.2, 100.0)),
+            ..Case::default()
+        },
+        Case {
+            name: "move_blocked_by_obstacle_stays_put",
+            program: vec![Instruction::MoveForward],
+            setup: |r| {
+                r.position = (45.0, 20.0);
+                r.turn(Section::Body, 180.0);
+            },
+            check: |r| assert_eq!(r.position, (45.0, 20.0)),
+            ..Case::default()
+        },
+        Case {
+            name: "move_blocked_by_arena_wall",
+            program: vec![Instruction::MoveForward],
+            setup: |r| {
+                r.position = (100.0, 5.0);
+                r.turn(Section::Body, -90.0);
+            },
+            check: |r| assert_eq!(r.position, (100.0, 5.0)),
+            ..Case::default()
+        },
+        Case {
+            name: "goto_turns_before_driving",
+            program: vec![Instruction::GoTo { x: 100, y: 200 }],
+            expect_ip: 0,
+            check: |r| {
+                assert_eq!(r.heading, 1.0);
+                assert_eq!(r.position, START);
+            },
+            ..Case::default()
+        },
+        Case {
+            name: "goto_drives_when_facing_target",
+            program: vec![Instruction::GoTo { x: 200, y: 100 }],
+            expect_ip: 0,
+            check: |r| assert_eq!(r.position, (100.2, 100.0)),
+            ..Case::default()
+        },
+        Case {
+            name: "goto_finishes_on_arrival",
+            program: vec![Instruction::GoTo { x: 100, y: 100 }],
+            ..Case::default()
+        },
+        Case {
+            name: "set_path_resets_waypoint",
+            program: vec![Instruction::SetPath {
+                waypoints: vec![(10, 20), (30, 40)],
+            }],
+            registers: &[("waypoint", 1)],
+            expect_registers: &[("waypoint", Some(0))],
+            check: |r| assert_eq!(r.path, vec![(10, 20), (30, 40)]),
+            ..Case::default()
+        },
+        Case {
+            name: "follow_path_advances_and_wraps_on_arrival",
+            program: vec![Instruction::FollowPath],
+            setup: |r| {
+                r.path = vec![(0, 0), (100, 100)];
+                r.set_waypoint(1);
+            },
+            expect_registers: &[("waypoint", Some(0))],
+            ..Case::default()
+        },
+        Case {
+            name: "follow_empty_path_is_skipped",
+            program: vec![Instruction::FollowPath],
+            ..Case::default()
+        },
+        // Aiming
+        Case {
+            name: "turn_to_steps_toward_target",
+            program: vec![Instruction::TurnTo {
+                section: Section::Turret,
+                target: Operand::Const(90),
+            }],
+            expect_ip: 0,
+            check: |r| assert_eq!(r.turret_heading, 1.0),
+            ..Case::default()
+        },
+        Case {
+            name: "turn_to_takes_shortest_way",
+            program: vec![Instruction::TurnTo {
+                section: Section::Scanner,
+                target: Operand::Const(270),
+            }],
+            expect_ip: 0,
+            check: |r| assert_eq!(r.scanner_heading, 359.0),
+            ..Case::default()
+        },
+        Case {
+            name: "turn_to_finishes_when_aligned",
+            program: vec![Instruction::TurnTo {
+                section: Section::Body,
+                target: reg("bearing"),
+            }],
+            registers: &[("bearing", 360)],
+            ..Case::default()
+        },
+        Case {
+            name: "turn_to_unset_register_is_skipped",
+            program: vec![Instruction::TurnTo {
+                section: Section::Turret,
+                target: reg("scan_bearing"),
+            }],
+            check: |r| assert_eq!(r.turret_heading, 0.0),
+            ..Case::default()
+        },
+        Case {
+            name: "aim_lead_without_contact_is_skipped",
+            program: vec![Instruction::AimLead],
+            ..Case::default()
+        },
+        Case {
+            name: "aim_lead_turns_toward_contact",
+            program: vec![Instruction::AimLead],
+            setup: |r| {
+                r.record_scan(ScanRecord {
+                    tick: 0,
+                    id: 2,
+                    position: (100.0, 200.0),
+                });
+            },
+            expect_ip: 0,
+            check: |r| assert_eq!(r.turret_heading, 1.0),
+            ..Case::default()
+        },
+        // World actions
+        Case {
+            name: "fire",
+            program: vec![Instruction::Fire],
+            expect_action: Some(WorldAction::Fire),
+            ..Case::default()
+        },
+        Case {
+            name: "fire_ricochet",
+            program: vec![Instruction::FireRicochet],
+            expect_action: Some(WorldAction::FireRicochet),
+            ..Case::default()
+        },
+        Case {
+            name: "fire_laser",
+            program: vec![Instruction::FireLaser],
+            expect_action: Some(WorldAction::FireLaser),
+            ..Case::default()
+        },
+        Case {
+            name: "scan",
+            program: vec![Instruction::Scan],
+            expect_action: Some(WorldAction::Scan),
+            ..Case::default()
+        },
+        Case {
+            name: "scan_all",
+            program: vec![Instruction::ScanAll],
+            expect_action: Some(WorldAction::ScanAll),
+            ..Case::default()
+        },
+        Case {
+            name: "say",
+            program: vec![Instruction::Say {
+                text: "reloading".to_string(),
+            }],
+            registers: &[("tick", 12)],
+            expect_action: Some(WorldAction::Say),
+            check: |robot| assert_eq!(robot.speech_at(12), Some("reloading")),
+            ..Case::default()
+        },
+        Case {
+            name: "smoke",
+            program: vec![Instruction::Smoke],
+            expect_action: Some(WorldAction::Smoke),
+            ..Case::default()
+        },
+        Case {
+            name: "repair",
+            program: vec![Instruction::Repair],
+            expect_action: Some(WorldAction::Repair),
+            ..Case::default()
+        },
+        Case {
+            name: "repair_ally",
+            program: vec![Instruction::RepairAlly],
+            expect_action: Some(WorldAction::RepairAlly),
+            ..Case::default()
+        },
+        Case {
+            name: "cloak",
+            program: vec![Instruction::Cloak],
+            check: |robot| assert!(robot.cloaked),
+            ..Case::default()
+        },
+        Case {
+            name: "cloak without energy",
+            program: vec![Instruction::Cloak],
+            setup: |robot| robot.energy = 0,
+            check: |robot| assert!(!robot.cloaked),
+            ..Case::default()
+        },
+        Case {
+            name: "uncloak",
+            program: vec![Instruction::Uncloak],
+            setup: |robot| robot.cloaked = true,
+            check: |robot| assert!(!robot.cloaked),
+            ..Case::default()
+        },
+        Case {
+            name: "fire emp",
+            program: vec![Instruction::FireEmp],
+            expect_action: Some(WorldAction::FireEmp),
+            ..Case::default()
+        },
+        Case {
+            name: "defense on",
+            program: vec![Instruction::PointDefense { on: true }],
+            check: |robot| assert!(robot.point_defense),
+            ..Case::default()
+        },
+        Case {
+            name: "defense off",
+            program: vec![Instruction::PointDefense { on: false }],
+            setup: |robot| robot.point_defense = true,
+            check: |robot| assert!(!robot.point_defense),
+            ..Case::default()
+        },
+        Case {
+            name: "hook",
+            program: vec![Instruction::Hook],
+            expect_action: Some(WorldAction::Hook),
+            ..Case::default()
+        },
+        Case {
+            name: "unhook",
+            program: vec![Instruction::Unhook],
+            setup: |robot| {
+                robot.hook = Some(Hook {
+                    anchor: HookAnchor::Point((0.0, 0.0)),
+                    length: 50.0,
+                })
+            },
+            check: |robot| assert_eq!(robot.hook, None),
+            ..Case::default()
+        },
+        Case {
+            name: "selfdestruct",
+            program: vec![Instruction::SelfDestruct],
+            expect_action: Some(WorldAction::SelfDestruct),
+            ..Case::default()
+        },
+        // Counters and jumps
+        Case {
+            name: "load_counter",
+            program: vec![Instruction::LoadCounter {
+                reg: Reg::named("c"),
+                value: 7,
+            }],
+            registers: &[("c", 2)],
+            expect_registers: &[("c", Some(7))],
+            ..Case::default()
+        },
+        Case {
+            name: "dec",
+            program: vec![Instruction::Dec {
+                reg: Reg::named("c"),
+            }],
+            registers: &[("c", 2)],
+            expect_registers: &[("c", Some(1))],
+            ..Case::default()
+        },
+        Case {
+            name: "dec_missing_register_is_a_no_op",
+            program: vec![Instruction::Dec {
+                reg: Reg::named("c"),
+            }],
+            expect_registers: &[("c", None)],
+            ..Case::default()
+        },
+        Case {
+            name: "jnz_taken_jumps_to_label",
+            program: vec![label("top"), Instruction::Fire, jnz("c", "top")],
+            ip: 2,
+            registers: &[("c", 3)],
+            expect_ip: 0,
+            ..Case::default()
+        },
+        Case {
+            name: "jnz_zero_falls_through",
+            program: vec![label("top"), Instruction::Fire, jnz("c", "top")],
+            ip: 2,
+            registers: &[("c", 0)],
+            expect_ip: 3,
+            ..Case::default()
+        },
+        Case {
+            name: "jnz_negative_is_taken",
+            program: vec![label("top"), jnz("c", "top")],
+            ip: 1,
+            registers: &[("c", -1)],
+            expect_ip: 0,
+            ..Case::default()
+        },
+        Case {
+            name: "jnz_missing_label_falls_through",
+            program: vec![Instruction::Fire, jnz("c", "nowhere")],
+            ip: 1,
+            registers: &[("c", 1)],
+            expect_ip: 2,
+            ..Case::default()
+        },
+        Case {
+            name: "jnz_missing_register_falls_through",
+            program: vec![label("top"), jnz("c", "top")],
+            ip: 1,
+            expect_ip: 2,
+            ..Case::default()
+        },
+        Case {
+            name: "jnz_always_jumps_unless_set",
+            program: vec![label("top"), jnz("always", "top")],
+            ip: 1,
+            expect_ip: 0,
+            ..Case::default()
+        },
+        Case {
+            name: "jnz_goes_to_first_matching_label",
+            program: vec![label("l"), label("l"), jnz("always", "l")],
+            ip: 2,
+            expect_ip: 0,
+            ..Case::default()
+        },
+        Case {
+            name: "label_is_a_no_op",
+            program: vec![label("top")],
+            ..Case::default()
+        },
+        // Registers and math
+        Case {
+            name: "set_constant",
+            program: vec![Instruction::Set {
+                dst: Reg::named("a"),
+                src: Operand::Const(-4),
+            }],
+            expect_registers: &[("a", Some(-4))],
+            ..Case::default()
+        },
+        Case {
+            name: "set_from_unset_register_reads_zero",
+            program: vec![Instruction::Set {
+                dst: Reg::named("a"),
+                src: reg("nothing"),
+            }],
+            registers: &[("a", 9)],
+            expect_registers: &[("a", Some(0))],
+            ..Case::default()
+        },
+        Case {
+            name: "math_reads_registers",
+            program: vec![Instruction::Math {
+                op: MathOp::Sub,
+                dst: Reg::named("d"),
+                args: vec![reg("a"), Operand::Const(5)],
+            }],
+            registers: &[("a", 12)],
+            expect_registers: &[("d", Some(7)), ("a", Some(12))],
+            ..Case::default()
+        },
+        Case {
+            name: "math_divide_by_zero_gives_zero",
+            program: vec![Instruction::Math {
+                op: MathOp::Div,
+                dst: Reg::named("d"),
+                args: vec![Operand::Const(5), Operand::Const(0)],
+            }],
+            expect_registers: &[("d", Some(0))],
+            ..Case::default()
+        },
+        Case {
+            name: "math_overflow_wraps",
+            program: vec![Instruction::Math {
+                op: MathOp::Add,
+                dst: Reg::named("d"),
+                args: vec![Operand::Const(i32::MAX), Operand::Const(1)],
+            }],
+            expect_registers: &[("d", Some(i32::MIN))],
+            ..Case::default()
+        },
+        Case {
+            name: "math_comparison_gives_one_or_zero",
+            program: vec![Instruction::Math {
+                op: MathOp::Ge,
+                dst: Reg::named("t"),
+                args: vec![reg("a"), Operand::Const(12)],
+            }],
+            registers: &[("a", 12)],
+            expect_registers: &[("t", Some(1))],
+            ..Case::default()
+        },
+        Case {
+            name: "read_history",
+            program: vec![Instruction::ReadHistory {
+                field: HistoryField::Y,
+                dst: Reg::named("y0"),
+                index: Operand::Const(0),
+            }],
+            setup: |r| {
+                r.record_scan(ScanRecord {
+                    tick: 3,
+                    id: 2,
+                    position: (10.4, 20.6),
+                });
+            },
+            expect_registers: &[("y0", Some(21))],
+            ..Case::default()
+        },
+        Case {
+            name: "read_history_out_of_range_reads_zero",
+            program: vec![Instruction::ReadHistory {
+                field: HistoryField::Id,
+                dst: Reg::named("id"),
+                index: Operand::Const(-1),
+            }],
+            registers: &[("id", 5)],
+            expect_registers: &[("id", Some(0))],
+            ..Case::default()
+        },
+        // Past the end of the program
+        Case {
+            name: "finished_program_does_nothing",
+            program: vec![Instruction::Fire],
+            ip: 1,
+            expect_ip: 1,
+            ..Case::default()
+        },
+    ]
+}
+
+#[test]
+fn instruction_conformance() {
+    for case in cases() {
+        run_case(case);
+    }
+}
+
+/// Run a compiled program until it finishes or `max_steps` have passed.
+fn run_program(commands: &[Command], max_steps: usize) -> (Robot, usize) {
+    let config = GameConfig::default();
+    let mut robot = Robot::new(1, "vm", START, translate_commands_to_instructions(commands));
+    let mut steps = 0;
+    while robot.ip < robot.instruction_queue.len() && steps < max_steps {
+        execute_robot_instruction(&mut robot, &[], &config);
+        steps += 1;
+    }
+    (robot, steps)
+}
+
+#[test]
+fn counted_loops_run_exactly_n_times() {
+    let (robot, _) = run_program(
+        &[
+            Command::Rotate {
+                section: Section::Body,
+                angle: -3,
+            },
+            Command::Move {
+                direction: "forward".to_string(),
+                distance: 5,
+            },
+        ],
+        1_000,
+    );
+    assert_eq!(robot.heading, 3.0);
+    let expected = 100.0 + 5.0 * 0.2 * 3.0_f32.to_radians().cos();
+    assert!((robot.position.0 - expected).abs() < 1e-3);
+}
+
+#[test]
+fn zero_counts_are_no_ops() {
+    let (robot, steps) = run_program(
+        &[
+            Command::Rotate {
+                section: Section::Turret,
+                angle: 0,
+            },
+            Command::Move {
+                direction: "forward".to_string(),
+                distance: 0,
+            },
+            Command::Fire,
+        ],
+        1_000,
+    );
+    assert_eq!(steps, 1);
+    assert_eq!(robot.turret_heading, 0.0);
+    assert_eq!(robot.position, START);
+}
+
+#[test]
+fn nested_loops_jump_to_their_own_labels() {
+    // loop { loop { rotate body -1; fire } }: the inner loop must never exit to
+    // the outer one, so the program keeps turning right
+    let script = [Command::Loop {
+        block: vec![Command::Loop {
+            block: vec![
+                Command::Rotate {
+                    section: Section::Body,
+                    angle: -1,
+                },
+                Command::Fire,
+            ],
+        }],
+    }];
+    let program = translate_commands_to_instructions(&script);
+    let labels: Vec<&Instruction> = program
+        .iter()
+        .filter(|i| matches!(i, Instruction::Label(_)))
+        .collect();
+    for (i, a) in labels.iter().enumerate() {
+        assert!(!labels[i + 1..].contains(a), "duplicate label {}", a);
+    }
+
+    let (robot, _) = run_program(&script, 60);
+    assert!(robot.heading > 5.0);
+}
+
+#[test]
+fn if_runs_one_branch() {
+    let script = parse_script(
+        "let a = 3\n\
+         if a > 2 rotate body -1 else rotate body -2\n\
+         if a == 0 { rotate body -4 }\n\
+         if a != 0 if a < 0 rotate body -8 else rotate body -16",
+    )
+    .unwrap();
+    let (robot, _) = run_program(&script, 1_000);
+    assert_eq!(robot.heading, 17.0);
+}